@@ -0,0 +1,293 @@
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use image_policy::{SignatureError, SignaturePolicy, Verifier};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::external_api::generic::problem::Problem;
+
+/// Kind of the resource submitted to the admission webhooks.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Workload,
+    Instance,
+}
+
+/// Mutation submitted to the admission webhooks.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Create,
+    Update,
+    Delete,
+}
+
+/// What to do with a mutation when a webhook can't be reached or answers garbage.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Reject the mutation
+    Fail,
+    /// Accept the mutation as if the webhook allowed it
+    Ignore,
+}
+
+fn default_failure_policy() -> FailurePolicy {
+    FailurePolicy::Fail
+}
+
+fn default_timeout_seconds() -> u64 {
+    5
+}
+
+/// A validating webhook registered by an operator in the controller configuration.
+///
+/// Properties:
+///
+/// * `name`: Name of the webhook, used in the error messages.
+/// * `url`: HTTP endpoint receiving an `AdmissionRequest` as JSON (POST).
+/// * `kinds`: Kinds of resources reviewed by this webhook, every kind if empty.
+/// * `failure_policy`: What to do when the webhook is unavailable.
+/// * `timeout_seconds`: Maximum duration of a review.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct AdmissionWebhook {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub kinds: Vec<ResourceKind>,
+    #[serde(default = "default_failure_policy")]
+    pub failure_policy: FailurePolicy,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl AdmissionWebhook {
+    pub fn applies_to(&self, kind: ResourceKind) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&kind)
+    }
+}
+
+/// Body sent to the webhooks.
+#[derive(Serialize, Debug)]
+pub struct AdmissionRequest<'a> {
+    pub kind: ResourceKind,
+    pub operation: Operation,
+    pub namespace: &'a str,
+    pub name: &'a str,
+    /// The submitted object, `null` for deletions
+    pub object: Value,
+    /// If true, the mutation is only validated and won't be stored, the webhooks with side
+    /// effects skip them
+    pub dry_run: bool,
+}
+
+/// Body expected from the webhooks.
+#[derive(Deserialize, Debug)]
+pub struct AdmissionResponse {
+    pub allowed: bool,
+    #[serde(default)]
+    pub reason: String,
+}
+
+pub enum AdmissionError {
+    Denied(String, String),
+    Unavailable(String, String),
+    ImageSignature(String, SignatureError),
+}
+
+impl AdmissionError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            AdmissionError::Denied(webhook, reason) => Problem::new(
+                StatusCode::FORBIDDEN,
+                "admission_denied",
+                format!("Denied by admission webhook {}: {}", webhook, reason),
+            ),
+            AdmissionError::Unavailable(webhook, err) => Problem::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "admission_unavailable",
+                format!("Admission webhook {} unavailable: {}", webhook, err),
+            ),
+            AdmissionError::ImageSignature(image, err) => match err {
+                SignatureError::InvalidReference(_)
+                | SignatureError::Unsigned(_)
+                | SignatureError::InvalidSignature(_) => Problem::new(
+                    StatusCode::FORBIDDEN,
+                    "image_signature_invalid",
+                    format!("Image {} rejected by the signature policy: {}", image, err),
+                ),
+                _ => Problem::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "image_signature_unavailable",
+                    format!("Unable to verify the signature of image {}: {}", image, err),
+                ),
+            },
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// `AdmissionService` submits the mutations of resources to the webhooks registered in the
+/// configuration. A mutation is accepted only if every webhook reviewing it allows it.
+pub struct AdmissionService {
+    webhooks: Vec<AdmissionWebhook>,
+    image_signature: Option<SignaturePolicy>,
+    dry_run: bool,
+    client: reqwest::Client,
+}
+
+impl AdmissionService {
+    pub fn new(webhooks: &[AdmissionWebhook]) -> Self {
+        AdmissionService {
+            webhooks: webhooks.to_vec(),
+            image_signature: None,
+            dry_run: false,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Requires the images of the workloads to be signed according to `policy`, if any.
+    pub fn with_image_signature(mut self, policy: Option<&SignaturePolicy>) -> Self {
+        self.image_signature = policy.cloned();
+        self
+    }
+
+    /// Tells the webhooks that the mutations reviewed are dry runs, validated but not stored.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// It verifies the signature of the image of a workload, when a signature policy is
+    /// configured. In the `Warn` mode, an image failing the verification is accepted.
+    pub async fn verify_image(&self, image: &str) -> Result<(), AdmissionError> {
+        let policy = match &self.image_signature {
+            Some(policy) => policy,
+            None => return Ok(()),
+        };
+
+        Verifier::new(policy)
+            .map_err(|err| AdmissionError::ImageSignature(image.to_string(), err))?
+            .check(image)
+            .await
+            .map(|_| ())
+            .map_err(|err| AdmissionError::ImageSignature(image.to_string(), err))
+    }
+
+    /// It sends the mutation to every webhook reviewing this kind of resource, in the order of
+    /// the configuration, and stops at the first denial.
+    pub async fn review(
+        &self,
+        kind: ResourceKind,
+        operation: Operation,
+        namespace: &str,
+        name: &str,
+        object: Value,
+    ) -> Result<(), AdmissionError> {
+        let request = AdmissionRequest {
+            kind,
+            operation,
+            namespace,
+            name,
+            object,
+            dry_run: self.dry_run,
+        };
+
+        for webhook in self.webhooks.iter().filter(|w| w.applies_to(kind)) {
+            match self.send(webhook, &request).await {
+                Ok(response) if response.allowed => {}
+                Ok(response) => {
+                    info!(
+                        "Admission webhook {} denied {:?} of {:?} {}/{}",
+                        webhook.name, operation, kind, namespace, name
+                    );
+                    return Err(AdmissionError::Denied(
+                        webhook.name.clone(),
+                        response.reason,
+                    ));
+                }
+                Err(err) => match webhook.failure_policy {
+                    FailurePolicy::Fail => {
+                        return Err(AdmissionError::Unavailable(
+                            webhook.name.clone(),
+                            err.to_string(),
+                        ))
+                    }
+                    FailurePolicy::Ignore => {
+                        warn!(
+                            "Ignoring unavailable admission webhook {}: {}",
+                            webhook.name, err
+                        )
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        webhook: &AdmissionWebhook,
+        request: &AdmissionRequest<'_>,
+    ) -> Result<AdmissionResponse, reqwest::Error> {
+        self.client
+            .post(&webhook.url)
+            .timeout(Duration::from_secs(webhook.timeout_seconds))
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<AdmissionResponse>()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_default_values() {
+        let webhook: AdmissionWebhook =
+            serde_json::from_str(r#"{"name": "policy", "url": "http://localhost/review"}"#)
+                .unwrap();
+
+        assert_eq!(webhook.failure_policy, FailurePolicy::Fail);
+        assert_eq!(webhook.timeout_seconds, 5);
+        assert!(webhook.applies_to(ResourceKind::Workload));
+        assert!(webhook.applies_to(ResourceKind::Instance));
+    }
+
+    #[test]
+    fn test_request_dry_run() {
+        let request = AdmissionRequest {
+            kind: ResourceKind::Workload,
+            operation: Operation::Create,
+            namespace: "default",
+            name: "web",
+            object: Value::Null,
+            dry_run: true,
+        };
+
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["operation"], "create");
+        assert_eq!(json["dry_run"], true);
+    }
+
+    #[test]
+    fn test_webhook_kinds_filter() {
+        let webhook: AdmissionWebhook = serde_json::from_str(
+            r#"{"name": "policy", "url": "http://localhost/review", "kinds": ["workload"]}"#,
+        )
+        .unwrap();
+
+        assert!(webhook.applies_to(ResourceKind::Workload));
+        assert!(!webhook.applies_to(ResourceKind::Instance));
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use proto::scheduler::{ClusterSnapshot, ResourceSummary};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::shard::service::ShardService;
+use crate::tasks::BackgroundTasks;
+
+/// Timeout of the requests to the alert webhook, in seconds
+const WEBHOOK_TIMEOUT_SECONDS: u64 = 5;
+
+/// `AlertingConfig` is the configuration of the alert evaluator.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two evaluations, the evaluator is disabled if 0 or
+///   without rules.
+/// * `webhook_url`: HTTP endpoint receiving the `AlertNotification`s as JSON (POST), the alerts
+///   are only logged if unset.
+/// * `rules`: The conditions raising an alert.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AlertingConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+}
+
+fn default_interval_seconds() -> u64 {
+    30
+}
+
+fn default_window_seconds() -> u64 {
+    600
+}
+
+impl Default for AlertingConfig {
+    fn default() -> Self {
+        AlertingConfig {
+            interval_seconds: default_interval_seconds(),
+            webhook_url: None,
+            rules: vec![],
+        }
+    }
+}
+
+/// A rule defined by an operator, e.g. `{ name = "memory", metric = "node_memory_percent",
+/// above = 90 }`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+}
+
+/// The condition of a rule, on the last numbers reported by the nodes or on the instances stored
+/// in etcd.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(tag = "metric", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// The CPU used on a node, in percent of its CPU
+    NodeCpuPercent { above: u64 },
+    /// The memory used on a node, in percent of its memory
+    NodeMemoryPercent { above: u64 },
+    /// The disk used on a node, in percent of its disk
+    NodeDiskPercent { above: u64 },
+    /// The times an instance ran again after it crashed or failed, over the last `window_seconds`
+    InstanceRestarts {
+        above: usize,
+        #[serde(default = "default_window_seconds")]
+        window_seconds: u64,
+    },
+}
+
+/// A rule matching a node or an instance.
+///
+/// Properties:
+///
+/// * `rule`: The name of the rule.
+/// * `subject`: The node or the instance matching the rule, e.g. `node/a` or `instance/<id>`.
+/// * `message`: What was measured.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub rule: String,
+    pub subject: String,
+    pub message: String,
+}
+
+/// Whether an alert starts or stops.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// Body sent to the alert webhook.
+#[derive(Debug, Serialize)]
+pub struct AlertNotification<'a> {
+    pub state: AlertState,
+    pub alert: &'a Alert,
+}
+
+/// Returns the share of a resource used, in percent, `None` if the resource is unknown.
+fn percent(usage: u64, limit: u64) -> Option<u64> {
+    (limit > 0).then(|| usage * 100 / limit)
+}
+
+/// Returns the alerts raised by the rules on the nodes of a snapshot of the cluster. The nodes
+/// which didn't report their resources are skipped.
+pub fn evaluate_nodes(rules: &[AlertRule], snapshot: &ClusterSnapshot) -> Vec<Alert> {
+    let mut alerts = vec![];
+    for node in &snapshot.nodes {
+        let Some(resource) = node
+            .status
+            .as_ref()
+            .and_then(|status| status.resource.as_ref())
+        else {
+            continue;
+        };
+        let (Some(limit), Some(usage)) = (&resource.limit, &resource.usage) else {
+            continue;
+        };
+
+        for rule in rules {
+            let measure = |name: &str, select: fn(&ResourceSummary) -> u64, above: u64| {
+                percent(select(usage), select(limit))
+                    .filter(|percent| *percent > above)
+                    .map(|percent| Alert {
+                        rule: rule.name.clone(),
+                        subject: format!("node/{}", node.id),
+                        message: format!("{} at {}% (above {}%)", name, percent, above),
+                    })
+            };
+            let alert = match rule.condition {
+                AlertCondition::NodeCpuPercent { above } => measure("cpu", |r| r.cpu, above),
+                AlertCondition::NodeMemoryPercent { above } => {
+                    measure("memory", |r| r.memory, above)
+                }
+                AlertCondition::NodeDiskPercent { above } => measure("disk", |r| r.disk, above),
+                AlertCondition::InstanceRestarts { .. } => None,
+            };
+            alerts.extend(alert);
+        }
+    }
+    alerts
+}
+
+/// `RestartTracker` counts the restarts of the instances across evaluations: an instance seen
+/// crashed or failed, then seen running again, restarted once. The restarts happening between
+/// two evaluations are missed.
+#[derive(Debug, Default)]
+pub struct RestartTracker {
+    states: HashMap<String, InstanceState>,
+    restarts: HashMap<String, Vec<u64>>,
+}
+
+impl RestartTracker {
+    /// Records the states of the instances observed at `now`, the deleted instances are
+    /// forgotten.
+    pub fn observe(&mut self, instances: &[Instance], now: u64) {
+        let mut states = HashMap::new();
+        for instance in instances {
+            let state = instance.status.state.clone();
+            let crashed = matches!(
+                self.states.get(&instance.id),
+                Some(InstanceState::Crashed | InstanceState::Failed)
+            );
+            let restarted = crashed
+                && !matches!(
+                    state,
+                    InstanceState::Crashed | InstanceState::Failed | InstanceState::Terminated
+                );
+            if restarted {
+                self.restarts
+                    .entry(instance.id.clone())
+                    .or_default()
+                    .push(now);
+            }
+            states.insert(instance.id.clone(), state);
+        }
+        self.restarts.retain(|id, _| states.contains_key(id));
+        self.states = states;
+    }
+
+    /// Returns the number of restarts of an instance since `since`, in seconds since the unix
+    /// epoch.
+    pub fn restarts(&self, instance_id: &str, since: u64) -> usize {
+        self.restarts.get(instance_id).map_or(0, |restarts| {
+            restarts.iter().filter(|at| **at >= since).count()
+        })
+    }
+
+    /// Forgets the restarts older than `since`.
+    fn forget_before(&mut self, since: u64) {
+        for restarts in self.restarts.values_mut() {
+            restarts.retain(|at| *at >= since);
+        }
+        self.restarts.retain(|_, restarts| !restarts.is_empty());
+    }
+}
+
+/// Returns the alerts raised by the rules on the restarts of the instances.
+pub fn evaluate_instances(
+    rules: &[AlertRule],
+    instances: &[Instance],
+    tracker: &RestartTracker,
+    now: u64,
+) -> Vec<Alert> {
+    let mut alerts = vec![];
+    for rule in rules {
+        let AlertCondition::InstanceRestarts {
+            above,
+            window_seconds,
+        } = rule.condition
+        else {
+            continue;
+        };
+        for instance in instances {
+            let restarts = tracker.restarts(&instance.id, now.saturating_sub(window_seconds));
+            if restarts > above {
+                alerts.push(Alert {
+                    rule: rule.name.clone(),
+                    subject: format!("instance/{}", instance.id),
+                    message: format!(
+                        "{} restarted {} times in {}s (above {})",
+                        instance.name, restarts, window_seconds, above
+                    ),
+                });
+            }
+        }
+    }
+    alerts
+}
+
+/// `ActiveAlerts` keeps the alerts firing, so an alert is notified once when it starts and once
+/// when it stops.
+#[derive(Debug, Default)]
+pub struct ActiveAlerts {
+    alerts: HashMap<(String, String), Alert>,
+}
+
+impl ActiveAlerts {
+    /// Replaces the alerts firing by the ones raised by an evaluation, returns the alerts which
+    /// started and the ones which stopped.
+    pub fn update(&mut self, raised: Vec<Alert>) -> (Vec<Alert>, Vec<Alert>) {
+        let mut previous = std::mem::take(&mut self.alerts);
+        let mut fired = vec![];
+        for alert in raised {
+            let key = (alert.rule.clone(), alert.subject.clone());
+            if previous.remove(&key).is_none() {
+                fired.push(alert.clone());
+            }
+            self.alerts.insert(key, alert);
+        }
+        let mut resolved: Vec<Alert> = previous.into_values().collect();
+        resolved.sort_by(|a, b| (&a.rule, &a.subject).cmp(&(&b.rule, &b.subject)));
+        (fired, resolved)
+    }
+}
+
+/// `AlertEvaluator` periodically evaluates the alert rules of the configuration on the numbers
+/// reported to the schedulers and on the instances stored in etcd. The alerts are logged when
+/// they start and stop, and sent to the webhook of the configuration if any.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks the evaluator runs in.
+/// * `tracker`: The restarts of the instances seen so far.
+/// * `active`: The alerts firing.
+/// * `client`: The client of the webhook.
+pub struct AlertEvaluator {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+    tracker: RestartTracker,
+    active: ActiveAlerts,
+    client: reqwest::Client,
+}
+
+impl AlertEvaluator {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        AlertEvaluator {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+            tracker: RestartTracker::default(),
+            active: ActiveAlerts::default(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Spawns the evaluator in `background_tasks`, it stops when the controller shuts down.
+    pub fn start(mut self, config: &AlertingConfig) {
+        if config.interval_seconds == 0 || config.rules.is_empty() {
+            info!("Alert evaluator disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+        let config = config.clone();
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.evaluate(&config).await {
+                                warn!("Alert evaluation failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Alert evaluator stopped");
+            },
+        );
+    }
+
+    /// Runs a single evaluation of every rule, and notifies the alerts which started or stopped.
+    async fn evaluate(&mut self, config: &AlertingConfig) -> Result<(), String> {
+        let snapshot = ShardService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .cluster_snapshot()
+            .await?;
+        let instances = InstanceService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .get_instances_of_all_namespaces()
+            .await;
+
+        let now = unix_time();
+        self.tracker.observe(&instances, now);
+        let longest_window = config
+            .rules
+            .iter()
+            .filter_map(|rule| match rule.condition {
+                AlertCondition::InstanceRestarts { window_seconds, .. } => Some(window_seconds),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default();
+        self.tracker
+            .forget_before(now.saturating_sub(longest_window));
+
+        let mut raised = evaluate_nodes(&config.rules, &snapshot);
+        raised.extend(evaluate_instances(
+            &config.rules,
+            &instances,
+            &self.tracker,
+            now,
+        ));
+        debug!("{} alert(s) raised", raised.len());
+
+        let (fired, resolved) = self.active.update(raised);
+        for alert in &fired {
+            warn!(
+                "Alert {} firing on {}: {}",
+                alert.rule, alert.subject, alert.message
+            );
+            self.notify(config, AlertState::Firing, alert).await;
+        }
+        for alert in &resolved {
+            info!("Alert {} resolved on {}", alert.rule, alert.subject);
+            self.notify(config, AlertState::Resolved, alert).await;
+        }
+        Ok(())
+    }
+
+    /// Sends an alert to the webhook of the configuration, a failure is only logged.
+    async fn notify(&self, config: &AlertingConfig, state: AlertState, alert: &Alert) {
+        let Some(url) = &config.webhook_url else {
+            return;
+        };
+        let result = self
+            .client
+            .post(url)
+            .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECONDS))
+            .json(&AlertNotification { state, alert })
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = result {
+            warn!(
+                "Failed to send alert {} on {} to {}: {}",
+                alert.rule, alert.subject, url, err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{NodeSnapshot, NodeStatus, Resource};
+
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+
+    fn rule(name: &str, condition: AlertCondition) -> AlertRule {
+        AlertRule {
+            name: name.to_string(),
+            condition,
+        }
+    }
+
+    fn node(id: &str, memory: Option<u64>) -> NodeSnapshot {
+        NodeSnapshot {
+            id: id.to_string(),
+            connected: true,
+            status: memory.map(|memory| NodeStatus {
+                resource: Some(Resource {
+                    limit: Some(ResourceSummary {
+                        cpu: 4000,
+                        memory: 1000,
+                        disk: 0,
+                    }),
+                    usage: Some(ResourceSummary {
+                        cpu: 100,
+                        memory,
+                        disk: 0,
+                    }),
+                }),
+                ..Default::default()
+            }),
+            capabilities: None,
+            cordoned: false,
+            suspect: false,
+            features: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let config: AlertingConfig = serde_json::from_str(
+            r#"{"rules": [
+                {"name": "memory", "metric": "node_memory_percent", "above": 90},
+                {"name": "flapping", "metric": "instance_restarts", "above": 5}
+            ]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.interval_seconds, 30);
+        assert_eq!(
+            config.rules,
+            vec![
+                rule("memory", AlertCondition::NodeMemoryPercent { above: 90 }),
+                rule(
+                    "flapping",
+                    AlertCondition::InstanceRestarts {
+                        above: 5,
+                        window_seconds: 600
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_nodes() {
+        let rules = [
+            rule("memory", AlertCondition::NodeMemoryPercent { above: 90 }),
+            rule("cpu", AlertCondition::NodeCpuPercent { above: 50 }),
+            // nodes don't report their disk yet
+            rule("disk", AlertCondition::NodeDiskPercent { above: 0 }),
+        ];
+        let snapshot = ClusterSnapshot {
+            nodes: vec![node("a", Some(950)), node("b", Some(900)), node("c", None)],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            evaluate_nodes(&rules, &snapshot),
+            vec![Alert {
+                rule: "memory".to_string(),
+                subject: "node/a".to_string(),
+                message: "memory at 95% (above 90%)".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_instance_restarts() {
+        let rules = [rule(
+            "flapping",
+            AlertCondition::InstanceRestarts {
+                above: 1,
+                window_seconds: 600,
+            },
+        )];
+        let mut tracker = RestartTracker::default();
+        let states = [
+            InstanceState::Running,
+            InstanceState::Crashed,
+            InstanceState::Starting,
+            InstanceState::Crashed,
+            InstanceState::Running,
+        ];
+        for (at, state) in states.into_iter().enumerate() {
+            tracker.observe(&[test_instance("1", state)], at as u64 * 60);
+        }
+        assert_eq!(tracker.restarts("1", 0), 2);
+
+        let instances = [test_instance("1", InstanceState::Running)];
+        let alerts = evaluate_instances(&rules, &instances, &tracker, 240);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].subject, "instance/1");
+        // the first restart left the window
+        assert!(evaluate_instances(&rules, &instances, &tracker, 120 + 601).is_empty());
+
+        // a deleted instance is forgotten
+        tracker.observe(&[], 300);
+        assert_eq!(tracker.restarts("1", 0), 0);
+    }
+
+    #[test]
+    fn test_active_alerts() {
+        let alert = |subject: &str| Alert {
+            rule: "memory".to_string(),
+            subject: subject.to_string(),
+            message: String::new(),
+        };
+        let mut active = ActiveAlerts::default();
+
+        let (fired, resolved) = active.update(vec![alert("node/a")]);
+        assert_eq!((fired.len(), resolved.len()), (1, 0));
+        // still firing, not notified again
+        let (fired, resolved) = active.update(vec![alert("node/a"), alert("node/b")]);
+        assert_eq!(fired, vec![alert("node/b")]);
+        assert!(resolved.is_empty());
+        let (fired, resolved) = active.update(vec![]);
+        assert!(fired.is_empty());
+        assert_eq!(resolved, vec![alert("node/a"), alert("node/b")]);
+    }
+}
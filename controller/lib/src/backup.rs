@@ -0,0 +1,144 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::backup::service::BackupService;
+use crate::tasks::BackgroundTasks;
+
+/// The etcd tool restoring the snapshots, looked up in the `PATH`.
+const ETCDUTL: &str = "etcdutl";
+
+/// `BackupConfig` is the configuration of the backups of etcd.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two scheduled backups, they are disabled if 0. A
+///   backup can still be taken through the API.
+/// * `dir`: The directory the snapshots are stored in, on the host of the controller.
+/// * `retention`: The number of backups kept, the oldest ones are deleted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupConfig {
+    #[serde(default)]
+    pub interval_seconds: u64,
+    #[serde(default = "default_dir")]
+    pub dir: PathBuf,
+    #[serde(default = "default_retention")]
+    pub retention: usize,
+}
+
+fn default_dir() -> PathBuf {
+    PathBuf::from("/var/lib/kudo/backups")
+}
+
+fn default_retention() -> usize {
+    7
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        BackupConfig {
+            interval_seconds: 0,
+            dir: default_dir(),
+            retention: default_retention(),
+        }
+    }
+}
+
+/// `BackupScheduler` periodically takes a snapshot of etcd, on the elected controller only.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `background_tasks`: The tasks the scheduler is spawned in.
+pub struct BackupScheduler {
+    etcd_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl BackupScheduler {
+    pub fn new(etcd_address: SocketAddr, background_tasks: &BackgroundTasks) -> Self {
+        BackupScheduler {
+            etcd_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the scheduler in `background_tasks`, it stops when the controller shuts down.
+    pub fn start(self, config: &BackupConfig) {
+        if config.interval_seconds == 0 {
+            info!("Scheduled backups disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+        let config = config.clone();
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                // the first tick completes right away, the controller just started
+                ticker.tick().await;
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.backup(&config).await {
+                                warn!("Scheduled backup failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Backup scheduler stopped");
+            },
+        );
+    }
+
+    async fn backup(&self, config: &BackupConfig) -> Result<(), String> {
+        BackupService::new(&self.etcd_address, config)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .take_backup()
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        Ok(())
+    }
+}
+
+/// Restores a backup in `data_dir`, a new data directory etcd is then started on. The snapshot
+/// is checked and restored by `etcdutl snapshot restore`, which must be installed on the host.
+pub fn restore(snapshot: &Path, data_dir: &Path) -> Result<(), String> {
+    if !snapshot.is_file() {
+        return Err(format!("The backup {} doesn't exist", snapshot.display()));
+    }
+    if data_dir.exists() {
+        return Err(format!(
+            "The data directory {} already exists, etcd is restored in a new one",
+            data_dir.display()
+        ));
+    }
+
+    info!(
+        "Restoring the backup {} in {}",
+        snapshot.display(),
+        data_dir.display()
+    );
+    let status = Command::new(ETCDUTL)
+        .arg("snapshot")
+        .arg("restore")
+        .arg(snapshot)
+        .arg("--data-dir")
+        .arg(data_dir)
+        .status()
+        .map_err(|err| format!("Failed to run {}: {}", ETCDUTL, err))?;
+    if !status.success() {
+        return Err(format!("{} failed: {}", ETCDUTL, status));
+    }
+    Ok(())
+}
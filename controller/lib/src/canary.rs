@@ -0,0 +1,403 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::disruption;
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::workload::model::{
+    CanaryStatus, DisruptionBudget, VersionStatus, Workload,
+};
+use crate::external_api::workload::service::WorkloadService;
+use crate::tasks::BackgroundTasks;
+
+/// `CanaryConfig` is the configuration of the canary controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CanaryConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        CanaryConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// What the canary controller does for a workload with a canary, a single instance is moved
+/// per pass.
+#[derive(Debug)]
+pub enum CanaryAction {
+    /// Nothing until the next pass
+    Wait,
+    /// Re-create the instance from the canary
+    ToCanary(Instance),
+    /// Re-create the instance from the definition of the workload
+    ToStable(Instance),
+}
+
+/// Returns the number of instances running the canary out of `total`, rounded up so that a
+/// canary runs as soon as the workload has an instance.
+pub fn canary_target(percentage: u8, total: usize) -> usize {
+    (total * percentage.min(100) as usize).div_ceil(100)
+}
+
+/// Decides which instance of a workload moves to the other version, once all the instances run
+/// and if its disruption budget allows to stop one of them.
+///
+/// # Arguments:
+///
+/// * `percentage`: The share of the instances running the canary.
+/// * `budget`: The disruption budget of the workload.
+/// * `instances`: The instances of the workload stored in etcd.
+pub fn plan_canary(
+    percentage: u8,
+    budget: Option<&DisruptionBudget>,
+    instances: &[Instance],
+) -> CanaryAction {
+    let mut active: Vec<&Instance> = instances
+        .iter()
+        .filter(|instance| !instance.status.state.is_finished())
+        .collect();
+    if active
+        .iter()
+        .any(|instance| instance.status.state != InstanceState::Running)
+    {
+        return CanaryAction::Wait;
+    }
+    active.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let target = canary_target(percentage, active.len());
+    let canaries = active.iter().filter(|instance| instance.canary).count();
+    let moved = if canaries < target {
+        active.iter().find(|instance| !instance.canary)
+    } else if canaries > target {
+        active.iter().find(|instance| instance.canary)
+    } else {
+        None
+    };
+    match moved {
+        Some(instance) if disruption::allows_disruption(budget, instance, instances) => {
+            if instance.canary {
+                CanaryAction::ToStable((*instance).clone())
+            } else {
+                CanaryAction::ToCanary((*instance).clone())
+            }
+        }
+        _ => CanaryAction::Wait,
+    }
+}
+
+/// Returns the progress of the canary of a workload, `None` if it has no canary.
+///
+/// # Arguments:
+///
+/// * `workload`: The workload with a canary.
+/// * `instances`: The instances of the workload.
+pub fn canary_status(workload: &Workload, instances: &[Instance]) -> Option<CanaryStatus> {
+    let canary = workload.canary.as_ref()?;
+    let count = |uri: &str, is_canary: bool| {
+        let mut status = VersionStatus {
+            uri: uri.to_string(),
+            ..Default::default()
+        };
+        for instance in instances.iter().filter(|i| i.canary == is_canary) {
+            status.instances += 1;
+            if instance.status.state == InstanceState::Running {
+                status.running += 1;
+            } else if instance.status.state.is_finished() {
+                status.finished += 1;
+            }
+        }
+        status
+    };
+
+    Some(CanaryStatus {
+        percentage: canary.percentage,
+        stable: count(&workload.uri, false),
+        canary: count(&canary.version.uri, true),
+    })
+}
+
+/// `CanaryController` periodically moves the instances of the workloads with a canary between
+/// their two versions, so that the canary runs on its percentage of the instances. The canary is
+/// left running until it is promoted or rolled back through the API.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the created instances.
+pub struct CanaryController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl CanaryController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        CanaryController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the canary controller in `background_tasks`, it stops when the controller shuts
+    /// down.
+    pub fn start(self, config: &CanaryConfig) {
+        if config.interval_seconds == 0 {
+            info!("Canary controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Canary synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Canary controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every workload with a canary.
+    async fn sync(&self) -> Result<(), String> {
+        let workloads = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .get_canary_workloads()
+            .await;
+        if workloads.is_empty() {
+            return Ok(());
+        }
+
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        debug!("Synchronizing {} canary(ies)", workloads.len());
+
+        for workload in workloads {
+            let Some(canary) = &workload.canary else {
+                continue;
+            };
+            let instances = instance_service.get_instances_of_workload(&workload).await;
+            match plan_canary(
+                canary.percentage,
+                workload.disruption_budget.as_ref(),
+                &instances,
+            ) {
+                CanaryAction::Wait => {}
+                CanaryAction::ToCanary(instance) => {
+                    info!(
+                        "Canary of workload {} replaces instance {}",
+                        workload.id, instance.id
+                    );
+                    let result = match instance_service
+                        .delete_instance(&instance.id, &instance.namespace)
+                        .await
+                    {
+                        Ok(()) => {
+                            instance_service
+                                .create_canary_instance(&workload.name, &workload.namespace)
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    };
+                    if let Err(err) = result {
+                        error!(
+                            "Failed to move instance {} to the canary of workload {}: {}",
+                            instance.id,
+                            workload.id,
+                            err.to_problem().detail
+                        );
+                    }
+                }
+                CanaryAction::ToStable(instance) => {
+                    info!(
+                        "Workload {} has too many canary instances, re-creating {}",
+                        workload.id, instance.id
+                    );
+                    if let Err(err) = instance_service
+                        .patch_instance(&instance.id, &instance.namespace)
+                        .await
+                    {
+                        error!(
+                            "Failed to move instance {} out of the canary of workload {}: {}",
+                            instance.id,
+                            workload.id,
+                            err.to_problem().detail
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::Canary;
+
+    fn instance(id: &str, state: InstanceState, canary: bool) -> Instance {
+        Instance {
+            canary,
+            ..test_instance(id, state)
+        }
+    }
+
+    fn step(percentage: u8, instances: &[Instance]) -> String {
+        step_with_budget(percentage, None, instances)
+    }
+
+    fn step_with_budget(
+        percentage: u8,
+        budget: Option<&DisruptionBudget>,
+        instances: &[Instance],
+    ) -> String {
+        match plan_canary(percentage, budget, instances) {
+            CanaryAction::Wait => "wait".to_string(),
+            CanaryAction::ToCanary(instance) => format!("canary {}", instance.id),
+            CanaryAction::ToStable(instance) => format!("stable {}", instance.id),
+        }
+    }
+
+    #[test]
+    fn test_canary_target() {
+        assert_eq!(canary_target(20, 0), 0);
+        assert_eq!(canary_target(20, 1), 1);
+        assert_eq!(canary_target(20, 5), 1);
+        assert_eq!(canary_target(20, 6), 2);
+        assert_eq!(canary_target(100, 3), 3);
+    }
+
+    #[test]
+    fn test_plan_canary() {
+        let running = |id, canary| instance(id, InstanceState::Running, canary);
+
+        let instances = [
+            running("a", false),
+            running("b", false),
+            running("c", false),
+        ];
+        assert_eq!(step(50, &instances), "canary a");
+
+        let instances = [running("a", true), running("b", false), running("c", false)];
+        assert_eq!(step(50, &instances), "canary b");
+
+        let instances = [running("a", true), running("b", true), running("c", false)];
+        assert_eq!(step(50, &instances), "wait");
+        // an instance was deleted, the canary runs on too many instances
+        assert_eq!(step(50, &instances[..2]), "stable a");
+        assert_eq!(step(10, &instances), "stable a");
+
+        // the moves wait for the instances to run
+        let instances = [
+            instance("a", InstanceState::Starting, true),
+            running("b", false),
+        ];
+        assert_eq!(step(100, &instances), "wait");
+
+        // the finished instances are not counted
+        let instances = [
+            instance("a", InstanceState::Crashed, true),
+            running("b", false),
+        ];
+        assert_eq!(step(50, &instances), "canary b");
+    }
+
+    #[test]
+    fn test_plan_canary_respects_disruption_budget() {
+        let running = |id, canary| instance(id, InstanceState::Running, canary);
+        let budget = DisruptionBudget { min_available: 2 };
+
+        let instances = [running("a", false), running("b", false)];
+        assert_eq!(step_with_budget(50, Some(&budget), &instances), "wait");
+
+        let instances = [
+            running("a", false),
+            running("b", false),
+            running("c", false),
+        ];
+        assert_eq!(step_with_budget(50, Some(&budget), &instances), "canary a");
+    }
+
+    #[test]
+    fn test_canary_status() {
+        let mut workload = Workload {
+            id: "default.web".to_string(),
+            name: "web".to_string(),
+            uri: "nginx:1.23".to_string(),
+            namespace: "default".to_string(),
+            ..Default::default()
+        };
+        let instances = [
+            instance("a", InstanceState::Running, false),
+            instance("b", InstanceState::Starting, false),
+            instance("c", InstanceState::Crashed, true),
+        ];
+        assert_eq!(canary_status(&workload, &instances), None);
+
+        let mut version = workload.clone();
+        version.uri = "nginx:1.24".to_string();
+        workload.canary = Some(Canary {
+            percentage: 25,
+            version: Box::new(version),
+        });
+
+        let status = canary_status(&workload, &instances).unwrap();
+        assert_eq!(status.percentage, 25);
+        assert_eq!(
+            status.stable,
+            VersionStatus {
+                uri: "nginx:1.23".to_string(),
+                instances: 2,
+                running: 1,
+                finished: 0,
+            }
+        );
+        assert_eq!(
+            status.canary,
+            VersionStatus {
+                uri: "nginx:1.24".to_string(),
+                instances: 1,
+                running: 0,
+                finished: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::cronjob::model::{ConcurrencyPolicy, CronJob, CRONJOB_LABEL};
+use crate::external_api::cronjob::schedule::CronSchedule;
+use crate::external_api::cronjob::service::CronJobService;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::instance::model::Instance;
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::workload::model::{JobState, Workload, WorkloadError, WorkloadKind};
+use crate::external_api::workload::service::WorkloadService;
+use crate::tasks::BackgroundTasks;
+
+/// The number of scheduled times walked through to find the latest run missed.
+const MAX_MISSED_RUNS: usize = 10_000;
+
+/// `CronConfig` is the configuration of the cron job controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CronConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+impl Default for CronConfig {
+    fn default() -> Self {
+        CronConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// What the cron job controller does for a run which is due.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CronAction {
+    /// The run is skipped, because the cron job is suspended or a job is still active
+    Skip,
+    /// A job is created
+    Create,
+    /// The active jobs, by name, are deleted and a job is created
+    Replace(Vec<String>),
+}
+
+/// Returns the latest time scheduled after `since` and until `now`, or `None` if no run is due.
+pub fn latest_due(schedule: &CronSchedule, since: u64, now: u64) -> Option<u64> {
+    let mut due = None;
+    let mut time = since;
+    for _ in 0..MAX_MISSED_RUNS {
+        match schedule.next_after(time) {
+            Some(next) if next <= now => {
+                due = Some(next);
+                time = next;
+            }
+            _ => break,
+        }
+    }
+    due
+}
+
+/// Returns `true` if a job created by a cron job still runs.
+fn is_active(job: &Workload) -> bool {
+    job.job_status
+        .as_ref()
+        .is_none_or(|status| status.state == JobState::Active)
+}
+
+/// Returns the scheduled time of a job created by a cron job, read from its name.
+fn scheduled_time(cronjob: &CronJob, job: &Workload) -> u64 {
+    job.name
+        .strip_prefix(&format!("{}-", cronjob.name))
+        .and_then(|time| time.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Decides what to do for a run of a cron job which is due.
+///
+/// # Arguments:
+///
+/// * `cronjob`: The cron job.
+/// * `jobs`: The jobs created by the cron job.
+///
+/// # Returns:
+///
+/// What to do for the run.
+pub fn plan_run(cronjob: &CronJob, jobs: &[Workload]) -> CronAction {
+    if cronjob.suspend {
+        return CronAction::Skip;
+    }
+
+    let active: Vec<String> = jobs
+        .iter()
+        .filter(|job| is_active(job))
+        .map(|job| job.name.clone())
+        .collect();
+    match cronjob.concurrency_policy {
+        ConcurrencyPolicy::Allow => CronAction::Create,
+        ConcurrencyPolicy::Forbid if active.is_empty() => CronAction::Create,
+        ConcurrencyPolicy::Forbid => CronAction::Skip,
+        ConcurrencyPolicy::Replace if active.is_empty() => CronAction::Create,
+        ConcurrencyPolicy::Replace => CronAction::Replace(active),
+    }
+}
+
+/// Returns the names of the finished jobs beyond the history limits of a cron job, the oldest
+/// ones are deleted first.
+pub fn expired_jobs(cronjob: &CronJob, jobs: &[Workload]) -> Vec<String> {
+    let mut expired = vec![];
+    for (state, limit) in [
+        (JobState::Complete, cronjob.successful_jobs_history_limit),
+        (JobState::Failed, cronjob.failed_jobs_history_limit),
+    ] {
+        let mut finished: Vec<&Workload> = jobs
+            .iter()
+            .filter(|job| job.job_status.as_ref().map(|status| status.state) == Some(state))
+            .collect();
+        finished.sort_by_key(|job| std::cmp::Reverse(scheduled_time(cronjob, job)));
+        expired.extend(finished.into_iter().skip(limit).map(|job| job.name.clone()));
+    }
+    expired
+}
+
+/// `CronJobController` periodically creates the `Job` workloads of the cron jobs whose schedule
+/// fired, applies their concurrency policy and deletes the finished jobs beyond their history
+/// limits.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks the controller is spawned in.
+pub struct CronJobController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl CronJobController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        CronJobController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the cron job controller in `background_tasks`, it stops when the controller shuts
+    /// down.
+    pub fn start(self, config: &CronConfig) {
+        if config.interval_seconds == 0 {
+            info!("Cron job controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Cron job synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Cron job controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every cron job.
+    async fn sync(&self) -> Result<(), String> {
+        let mut cronjob_service = CronJobService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let cronjobs = cronjob_service
+            .get_all_cronjobs(&Pagination::default(), None)
+            .await
+            .cronjobs;
+        if cronjobs.is_empty() {
+            return Ok(());
+        }
+
+        let mut workload_service = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?;
+        let jobs = workload_service
+            .get_workloads_of_kind(WorkloadKind::Job)
+            .await;
+        let instances = instance_service.get_instances_of_all_namespaces().await;
+        debug!("Synchronizing {} cron job(s)", cronjobs.len());
+
+        for cronjob in cronjobs {
+            let owned: Vec<Workload> = jobs
+                .iter()
+                .filter(|job| {
+                    job.namespace == cronjob.namespace
+                        && job.labels.get(CRONJOB_LABEL) == Some(&cronjob.name)
+                })
+                .cloned()
+                .collect();
+            self.sync_one(
+                cronjob,
+                &owned,
+                &instances,
+                &mut cronjob_service,
+                &mut workload_service,
+                &mut instance_service,
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    /// Runs a cron job if it is due and deletes its expired jobs.
+    async fn sync_one(
+        &self,
+        mut cronjob: CronJob,
+        jobs: &[Workload],
+        instances: &[Instance],
+        cronjob_service: &mut CronJobService,
+        workload_service: &mut WorkloadService,
+        instance_service: &mut InstanceService,
+    ) {
+        let schedule = match CronSchedule::parse(&cronjob.schedule) {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                warn!("Cron job {} has an invalid schedule: {}", cronjob.id, err);
+                return;
+            }
+        };
+
+        let since = cronjob.last_schedule_time.unwrap_or(cronjob.created_at);
+        if let Some(due) = latest_due(&schedule, since, unix_time()) {
+            let action = plan_run(&cronjob, jobs);
+            if let CronAction::Replace(active) = &action {
+                for name in active {
+                    self.delete_job(
+                        name,
+                        &cronjob.namespace,
+                        instances,
+                        workload_service,
+                        instance_service,
+                    )
+                    .await;
+                }
+            }
+
+            if action != CronAction::Skip {
+                let name = cronjob.job_name(due);
+                let job = cronjob
+                    .job_template
+                    .to_workload_dto(name.clone(), &cronjob.name);
+                match workload_service
+                    .create_workload(job, &cronjob.namespace)
+                    .await
+                {
+                    // the job was created by a pass which failed to record it
+                    Ok(_) | Err(WorkloadError::NameAlreadyExists(_)) => {
+                        info!("Cron job {} created job {}", cronjob.id, name)
+                    }
+                    Err(err) => {
+                        error!(
+                            "Failed to create job {} of cron job {}: {}",
+                            name,
+                            cronjob.id,
+                            err.to_problem().detail
+                        );
+                        return;
+                    }
+                }
+            } else {
+                debug!("Cron job {} skipped the run of {}", cronjob.id, due);
+            }
+
+            cronjob.last_schedule_time = Some(due);
+            if let Err(err) = cronjob_service.put_cronjob(&cronjob).await {
+                error!(
+                    "Failed to update cron job {}: {}",
+                    cronjob.id,
+                    err.to_problem().detail
+                );
+                return;
+            }
+        }
+
+        for name in expired_jobs(&cronjob, jobs) {
+            self.delete_job(
+                &name,
+                &cronjob.namespace,
+                instances,
+                workload_service,
+                instance_service,
+            )
+            .await;
+        }
+    }
+
+    /// Deletes a job and its instances.
+    async fn delete_job(
+        &self,
+        name: &str,
+        namespace: &str,
+        instances: &[Instance],
+        workload_service: &mut WorkloadService,
+        instance_service: &mut InstanceService,
+    ) {
+        let id = workload_service.id(name, namespace);
+        for instance in instances
+            .iter()
+            .filter(|instance| instance.workload_id == id)
+        {
+            if let Err(err) = instance_service
+                .delete_instance(&instance.id, namespace)
+                .await
+            {
+                // the garbage collector deletes it once the job is gone
+                warn!(
+                    "Failed to delete instance {} of job {}: {}",
+                    instance.id,
+                    id,
+                    err.to_problem().detail
+                );
+            }
+        }
+        workload_service.delete_workload(name, namespace).await;
+        info!("Deleted job {}", id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::cronjob::model::JobTemplate;
+    use crate::external_api::workload::model::{JobStatus, Ressources, Type, WorkloadKind};
+
+    fn cronjob(concurrency_policy: ConcurrencyPolicy) -> CronJob {
+        CronJob {
+            id: "cronjob.default.backup".to_string(),
+            name: "backup".to_string(),
+            namespace: "default".to_string(),
+            schedule: "0 * * * *".to_string(),
+            concurrency_policy,
+            successful_jobs_history_limit: 2,
+            failed_jobs_history_limit: 1,
+            suspend: false,
+            job_template: JobTemplate {
+                uri: "alpine".to_string(),
+                environment: vec![],
+                ports: vec![],
+                resources: Default::default(),
+                labels: Default::default(),
+                ttl_seconds_after_finished: None,
+                security_context: Default::default(),
+                job: None,
+            },
+            created_at: 0,
+            last_schedule_time: None,
+        }
+    }
+
+    fn job(scheduled_time: u64, state: Option<JobState>) -> Workload {
+        let name = format!("backup-{}", scheduled_time);
+        Workload {
+            id: format!("default.{}", name),
+            name,
+            workload_type: Type::Container,
+            uri: "alpine".to_string(),
+            environment: vec![],
+            resources: Ressources {
+                cpu: 0,
+                memory: 0,
+                disk: 0,
+            },
+            ports: vec![],
+            namespace: "default".to_string(),
+            labels: [(CRONJOB_LABEL.to_string(), "backup".to_string())].into(),
+            ttl_seconds_after_finished: None,
+            security_context: Default::default(),
+            kind: WorkloadKind::Job,
+            job: None,
+            job_status: state.map(|state| JobStatus {
+                state,
+                ..Default::default()
+            }),
+            stateful: None,
+            stateful_status: None,
+            canary: None,
+            depends_on: vec![],
+            sidecars: vec![],
+            spread: None,
+            constraint: None,
+            pool: None,
+            disruption_budget: None,
+            devices: Default::default(),
+            cpu_policy: Default::default(),
+            huge_pages: Default::default(),
+            platforms: vec![],
+            revision: 0,
+        }
+    }
+
+    #[test]
+    fn test_latest_due() {
+        let schedule = CronSchedule::parse("0 * * * *").unwrap();
+
+        assert_eq!(latest_due(&schedule, 3600, 7199), None);
+        assert_eq!(latest_due(&schedule, 3600, 7200), Some(7200));
+        // only the latest run missed is due
+        assert_eq!(latest_due(&schedule, 3600, 4 * 3600 + 10), Some(4 * 3600));
+    }
+
+    #[test]
+    fn test_plan_run() {
+        let jobs = [job(3600, Some(JobState::Complete)), job(7200, None)];
+
+        assert_eq!(
+            plan_run(&cronjob(ConcurrencyPolicy::Allow), &jobs),
+            CronAction::Create
+        );
+        assert_eq!(
+            plan_run(&cronjob(ConcurrencyPolicy::Forbid), &jobs),
+            CronAction::Skip
+        );
+        assert_eq!(
+            plan_run(&cronjob(ConcurrencyPolicy::Forbid), &jobs[..1]),
+            CronAction::Create
+        );
+        assert_eq!(
+            plan_run(&cronjob(ConcurrencyPolicy::Replace), &jobs),
+            CronAction::Replace(vec!["backup-7200".to_string()])
+        );
+
+        let mut suspended = cronjob(ConcurrencyPolicy::Allow);
+        suspended.suspend = true;
+        assert_eq!(plan_run(&suspended, &jobs), CronAction::Skip);
+    }
+
+    #[test]
+    fn test_expired_jobs() {
+        let jobs = [
+            job(3600, Some(JobState::Complete)),
+            job(7200, Some(JobState::Failed)),
+            job(10800, Some(JobState::Complete)),
+            job(14400, Some(JobState::Failed)),
+            job(18000, Some(JobState::Complete)),
+            job(21600, Some(JobState::Active)),
+        ];
+
+        assert_eq!(
+            expired_jobs(&cronjob(ConcurrencyPolicy::Allow), &jobs),
+            vec!["backup-3600".to_string(), "backup-7200".to_string()]
+        );
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use proto::scheduler::{ClusterSnapshot, NodeCapabilities};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::Instance;
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::shard::service::ShardService;
+use crate::external_api::workload::model::{Workload, WorkloadKind};
+use crate::external_api::workload::service::WorkloadService;
+use crate::grpc_client::interface::SchedulerClientInterface;
+use crate::tasks::BackgroundTasks;
+
+/// `DaemonConfig` is the configuration of the daemon set controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DaemonConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// What the daemon set controller does for a `DaemonSet` workload.
+///
+/// Properties:
+///
+/// * `create_on`: The nodes an instance is created on.
+/// * `delete`: The instances deleted, their node left or can't run them, or they are duplicates.
+#[derive(Debug, Default)]
+pub struct DaemonPlan {
+    pub create_on: Vec<String>,
+    pub delete: Vec<Instance>,
+}
+
+/// Returns `true` if a node can run the instances of a workload, the same requirements as the
+/// ones checked by the scheduler when placing an instance.
+///
+/// # Arguments:
+///
+/// * `workload`: The `DaemonSet` workload.
+/// * `capabilities`: The capabilities of the node, `None` if it can run any instance of the
+///   default pool.
+pub fn can_run(workload: &Workload, capabilities: Option<&NodeCapabilities>) -> bool {
+    let pool = capabilities.map_or("", |capabilities| capabilities.pool.as_str());
+    let other_pool = workload
+        .pool
+        .as_deref()
+        .is_some_and(|wanted| wanted != pool);
+    if other_pool {
+        return false;
+    }
+    let Some(capabilities) = capabilities else {
+        return true;
+    };
+    let privileged_port = workload
+        .ports
+        .iter()
+        .any(|port| (port.source as u32) < capabilities.unprivileged_port_start);
+    let limited =
+        workload.resources.cpu > 0 || workload.resources.memory > 0 || workload.resources.disk > 0;
+
+    !privileged_port && (!limited || capabilities.cgroup_limits)
+}
+
+/// Compares the instances of a `DaemonSet` workload with the nodes known by the scheduler.
+///
+/// # Arguments:
+///
+/// * `workload`: The `DaemonSet` workload.
+/// * `snapshot`: The nodes known by the scheduler.
+/// * `instances`: The instances stored in etcd, in every namespace.
+///
+/// # Returns:
+///
+/// The nodes missing an instance and the instances to delete.
+pub fn plan_daemon(
+    workload: &Workload,
+    snapshot: &ClusterSnapshot,
+    instances: &[Instance],
+) -> DaemonPlan {
+    let mut plan = DaemonPlan::default();
+    let mut covered = HashSet::new();
+
+    let mut owned: Vec<&Instance> = instances
+        .iter()
+        .filter(|instance| {
+            instance.workload_id == workload.id && !instance.status.state.is_finished()
+        })
+        .collect();
+    owned.sort_by(|a, b| a.id.cmp(&b.id));
+
+    for instance in owned {
+        // an instance created before the workload became a `DaemonSet` is left alone
+        if instance.node_id.is_empty() {
+            continue;
+        }
+        let keep = snapshot
+            .nodes
+            .iter()
+            .find(|node| node.id == instance.node_id)
+            .is_some_and(|node| can_run(workload, node.capabilities.as_ref()));
+        if keep && covered.insert(instance.node_id.as_str()) {
+            continue;
+        }
+        plan.delete.push(instance.clone());
+    }
+
+    plan.create_on = snapshot
+        .nodes
+        .iter()
+        .filter(|node| {
+            node.connected
+                && !covered.contains(node.id.as_str())
+                && can_run(workload, node.capabilities.as_ref())
+        })
+        .map(|node| node.id.clone())
+        .collect();
+    plan
+}
+
+/// `DaemonSetController` periodically keeps an instance of each `DaemonSet` workload on every
+/// node able to run it: the nodes registered since the previous pass get an instance and the
+/// instances of the nodes which left are deleted.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the created instances.
+pub struct DaemonSetController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl DaemonSetController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        DaemonSetController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the daemon set controller in `background_tasks`, it stops when the controller
+    /// shuts down.
+    pub fn start(self, config: &DaemonConfig) {
+        if config.interval_seconds == 0 {
+            info!("Daemon set controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Daemon set synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Daemon set controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every `DaemonSet` workload.
+    async fn sync(&self) -> Result<(), String> {
+        let mut workload_service = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let workloads = workload_service
+            .get_workloads_of_kind(WorkloadKind::DaemonSet)
+            .await;
+        if workloads.is_empty() {
+            return Ok(());
+        }
+
+        let mut shard_service = ShardService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        let instances = instance_service.get_instances_of_all_namespaces().await;
+        debug!("Synchronizing {} daemon set(s)", workloads.len());
+
+        // the instances of a namespace run on the nodes of its scheduler
+        let mut snapshots: HashMap<SocketAddr, ClusterSnapshot> = HashMap::new();
+        for workload in workloads {
+            let scheduler = shard_service
+                .scheduler_for(&workload.namespace)
+                .await
+                .map_err(|err| err.to_problem().detail)?;
+            if let Entry::Vacant(entry) = snapshots.entry(scheduler) {
+                let snapshot = SchedulerClientInterface::new(format!("http://{}", scheduler))
+                    .await
+                    .map_err(|err| format!("{:?}", err))?
+                    .cluster_snapshot()
+                    .await
+                    .map_err(|err| format!("{:?}", err))?
+                    .into_inner();
+                entry.insert(snapshot);
+            }
+            let plan = plan_daemon(&workload, &snapshots[&scheduler], &instances);
+            self.apply(&workload, plan, &mut instance_service).await;
+        }
+        Ok(())
+    }
+
+    /// Deletes the instances and creates the ones planned for a `DaemonSet` workload.
+    async fn apply(
+        &self,
+        workload: &Workload,
+        plan: DaemonPlan,
+        instance_service: &mut InstanceService,
+    ) {
+        for instance in plan.delete {
+            info!(
+                "Daemon set {} deletes instance {} of node {}",
+                workload.id, instance.id, instance.node_id
+            );
+            if let Err(err) = instance_service
+                .delete_instance(&instance.id, &workload.namespace)
+                .await
+            {
+                // the node left, the scheduler doesn't know the instance anymore
+                debug!(
+                    "Scheduler failed to destroy instance {}: {}",
+                    instance.id,
+                    err.to_problem().detail
+                );
+                if let Err(err) = instance_service.remove_instance(&instance).await {
+                    error!(
+                        "Failed to delete instance {} of daemon set {}: {}",
+                        instance.id,
+                        workload.id,
+                        err.to_problem().detail
+                    );
+                }
+            }
+        }
+
+        for node_id in plan.create_on {
+            match instance_service
+                .create_instance_on_node(&workload.name, &workload.namespace, &node_id)
+                .await
+            {
+                Ok(instance) => info!(
+                    "Daemon set {} started instance {} on node {}",
+                    workload.id, instance.id, node_id
+                ),
+                Err(err) => error!(
+                    "Failed to start an instance of daemon set {} on node {}: {}",
+                    workload.id,
+                    node_id,
+                    err.to_problem().detail
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::NodeSnapshot;
+
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::instance::model::InstanceState;
+    use crate::external_api::workload::model::Ports;
+
+    fn workload(ports: Vec<Ports>) -> Workload {
+        Workload {
+            id: "default.logs".to_string(),
+            name: "logs".to_string(),
+            uri: "fluent-bit".to_string(),
+            ports,
+            namespace: "default".to_string(),
+            kind: WorkloadKind::DaemonSet,
+            ..Default::default()
+        }
+    }
+
+    fn instance(id: &str, node_id: &str, state: InstanceState) -> Instance {
+        Instance {
+            name: format!("logs-{}", id),
+            workload_id: "default.logs".to_string(),
+            uri: "fluent-bit".to_string(),
+            node_id: node_id.to_string(),
+            kind: WorkloadKind::DaemonSet,
+            ..test_instance(id, state)
+        }
+    }
+
+    fn node(id: &str, connected: bool, capabilities: Option<NodeCapabilities>) -> NodeSnapshot {
+        NodeSnapshot {
+            id: id.to_string(),
+            connected,
+            status: None,
+            capabilities,
+            cordoned: false,
+            suspect: false,
+            features: vec![],
+        }
+    }
+
+    fn ids(instances: &[Instance]) -> Vec<&str> {
+        instances
+            .iter()
+            .map(|instance| instance.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_daemon() {
+        let snapshot = ClusterSnapshot {
+            nodes: vec![
+                node("a", true, None),
+                node("b", true, None),
+                node("c", true, None),
+                node("d", false, None),
+            ],
+            ..Default::default()
+        };
+        let instances = [
+            instance("1", "a", InstanceState::Running),
+            // a duplicate on the same node
+            instance("2", "a", InstanceState::Running),
+            // a crashed instance is replaced
+            instance("3", "b", InstanceState::Crashed),
+            // the node is disconnected, its instance is kept until it leaves
+            instance("4", "d", InstanceState::Running),
+            // the node left
+            instance("5", "e", InstanceState::Running),
+        ];
+
+        let plan = plan_daemon(&workload(vec![]), &snapshot, &instances);
+        assert_eq!(plan.create_on, vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(ids(&plan.delete), vec!["2", "5"]);
+    }
+
+    #[test]
+    fn test_plan_daemon_skips_incapable_nodes() {
+        let rootless = NodeCapabilities {
+            rootless: true,
+            unprivileged_port_start: 1024,
+            ..Default::default()
+        };
+        let snapshot = ClusterSnapshot {
+            nodes: vec![node("a", true, None), node("b", true, Some(rootless))],
+            ..Default::default()
+        };
+        let web = workload(vec![Ports {
+            source: 80,
+            destination: 80,
+        }]);
+
+        let plan = plan_daemon(
+            &web,
+            &snapshot,
+            &[instance("1", "b", InstanceState::Running)],
+        );
+        assert_eq!(plan.create_on, vec!["a".to_string()]);
+        assert_eq!(ids(&plan.delete), vec!["1"]);
+
+        let plan = plan_daemon(&workload(vec![]), &snapshot, &[]);
+        assert_eq!(plan.create_on.len(), 2);
+    }
+
+    #[test]
+    fn test_plan_daemon_stays_in_pool() {
+        let gpu = NodeCapabilities {
+            pool: "gpu".to_string(),
+            ..Default::default()
+        };
+        let snapshot = ClusterSnapshot {
+            nodes: vec![node("a", true, None), node("b", true, Some(gpu))],
+            ..Default::default()
+        };
+        let mut pooled = workload(vec![]);
+        pooled.pool = Some("gpu".to_string());
+
+        let plan = plan_daemon(
+            &pooled,
+            &snapshot,
+            &[instance("1", "a", InstanceState::Running)],
+        );
+        assert_eq!(plan.create_on, vec!["b".to_string()]);
+        assert_eq!(ids(&plan.delete), vec!["1"]);
+    }
+}
@@ -0,0 +1,218 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::workload::model::{JobState, Workload, WorkloadKind};
+use crate::tasks::BackgroundTasks;
+
+/// `DependencyConfig` is the configuration of the dependency controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DependencyConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    5
+}
+
+impl Default for DependencyConfig {
+    fn default() -> Self {
+        DependencyConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// Returns `true` if the workloads depending on `workload` can be started: a `Job` once it
+/// completed, the other workloads once one of their instances runs.
+///
+/// # Arguments:
+///
+/// * `workload`: The workload depended on.
+/// * `instances`: The instances of the workload stored in etcd.
+pub fn is_ready(workload: &Workload, instances: &[Instance]) -> bool {
+    match workload.kind {
+        WorkloadKind::Job => workload
+            .job_status
+            .as_ref()
+            .is_some_and(|status| status.state == JobState::Complete),
+        _ => instances
+            .iter()
+            .any(|instance| instance.status.state == InstanceState::Running),
+    }
+}
+
+/// Returns the status description of an instance waiting for the `pending` workloads.
+pub fn blocked_description(pending: &[String]) -> String {
+    format!("Waiting for {}", pending.join(", "))
+}
+
+/// `DependencyController` periodically starts the instances blocked by the dependencies of their
+/// workload: the instances created while a dependency isn't ready are stored as `Blocked`, and
+/// sent to the scheduler once all of them are.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the started instances.
+pub struct DependencyController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl DependencyController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        DependencyController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the dependency controller in `background_tasks`, it stops when the controller shuts
+    /// down.
+    pub fn start(self, config: &DependencyConfig) {
+        if config.interval_seconds == 0 {
+            info!("Dependency controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Dependency synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Dependency controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every blocked instance.
+    async fn sync(&self) -> Result<(), String> {
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        let blocked: Vec<Instance> = instance_service
+            .get_instances_of_all_namespaces()
+            .await
+            .into_iter()
+            .filter(|instance| instance.status.state == InstanceState::Blocked)
+            .collect();
+        if blocked.is_empty() {
+            return Ok(());
+        }
+        debug!("Checking the dependencies of {} instance(s)", blocked.len());
+
+        for mut instance in blocked {
+            match instance_service.unblock_instance(&mut instance).await {
+                Ok(true) => info!(
+                    "Instance {} started, its dependencies are ready",
+                    instance.id
+                ),
+                Ok(false) => debug!(
+                    "Instance {} still blocked: {}",
+                    instance.id, instance.status.status_description
+                ),
+                Err(err) => error!(
+                    "Failed to start blocked instance {}: {}",
+                    instance.id,
+                    err.to_problem().detail
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::JobStatus;
+
+    fn workload(kind: WorkloadKind) -> Workload {
+        Workload {
+            id: "default.db".to_string(),
+            name: "db".to_string(),
+            uri: "postgres".to_string(),
+            namespace: "default".to_string(),
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn instance(state: InstanceState) -> Instance {
+        Instance {
+            workload_id: "default.db".to_string(),
+            ..test_instance("1", state)
+        }
+    }
+
+    #[test]
+    fn test_is_ready() {
+        let service = workload(WorkloadKind::Service);
+        assert!(!is_ready(&service, &[]));
+        assert!(!is_ready(
+            &service,
+            &[
+                instance(InstanceState::Starting),
+                instance(InstanceState::Blocked)
+            ]
+        ));
+        assert!(is_ready(
+            &service,
+            &[
+                instance(InstanceState::Crashed),
+                instance(InstanceState::Running)
+            ]
+        ));
+
+        // a job is ready once complete, its instances are finished by then
+        let mut job = workload(WorkloadKind::Job);
+        let running = [instance(InstanceState::Running)];
+        assert!(!is_ready(&job, &running));
+        job.job_status = Some(JobStatus {
+            state: JobState::Complete,
+            ..Default::default()
+        });
+        assert!(is_ready(&job, &[instance(InstanceState::Terminated)]));
+    }
+
+    #[test]
+    fn test_blocked_description() {
+        assert_eq!(
+            blocked_description(&["db".to_string(), "cache".to_string()]),
+            "Waiting for db, cache"
+        );
+    }
+}
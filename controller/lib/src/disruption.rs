@@ -0,0 +1,71 @@
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::workload::model::DisruptionBudget;
+
+/// Returns `true` if an instance may be stopped by a voluntary disruption: its workload has no
+/// disruption budget, the instance doesn't run, or enough of the other instances of its workload
+/// keep running without it.
+///
+/// # Arguments:
+///
+/// * `budget`: The disruption budget of the workload of the instance.
+/// * `instance`: The instance to disrupt.
+/// * `instances`: The instances of the workload stored in etcd.
+pub fn allows_disruption(
+    budget: Option<&DisruptionBudget>,
+    instance: &Instance,
+    instances: &[Instance],
+) -> bool {
+    let Some(budget) = budget else {
+        return true;
+    };
+    if instance.status.state != InstanceState::Running {
+        return true;
+    }
+    let others = instances
+        .iter()
+        .filter(|other| {
+            other.workload_id == instance.workload_id
+                && other.id != instance.id
+                && other.status.state == InstanceState::Running
+        })
+        .count();
+    others >= budget.min_available as usize
+}
+
+/// Returns the status description of an instance whose disruption is delayed by its budget.
+pub fn delayed_description(budget: &DisruptionBudget) -> String {
+    format!(
+        "The workload must keep {} running instance(s)",
+        budget.min_available
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+
+    #[test]
+    fn test_allows_disruption() {
+        let budget = DisruptionBudget { min_available: 2 };
+        let mut instances = vec![
+            test_instance("a", InstanceState::Running),
+            test_instance("b", InstanceState::Running),
+            test_instance("c", InstanceState::Starting),
+        ];
+
+        // without a budget, any instance may be disrupted
+        assert!(allows_disruption(None, &instances[0], &instances));
+        // a single other instance would keep running
+        assert!(!allows_disruption(Some(&budget), &instances[0], &instances));
+        // the instances not running don't count toward the budget
+        assert!(allows_disruption(Some(&budget), &instances[2], &instances));
+
+        instances[2].status.state = InstanceState::Running;
+        assert!(allows_disruption(Some(&budget), &instances[0], &instances));
+
+        // the instances of the other workloads don't count either
+        instances[2].workload_id = "default.api".to_string();
+        assert!(!allows_disruption(Some(&budget), &instances[0], &instances));
+    }
+}
@@ -1,6 +1,77 @@
-use etcd_client::{Client, DeleteResponse, Error, GetOptions, PutResponse};
+use etcd_client::{
+    Client, Compare, CompareOp, DeleteOptions, DeleteResponse, Error, GetOptions,
+    LeaseKeepAliveStream, LeaseKeeper, PutOptions, PutResponse, Txn, TxnOp, TxnOpResponse,
+    WatchOptions, WatchStream, Watcher,
+};
+use futures_util::{pin_mut, stream, Stream, TryStreamExt};
 use log::info;
+use serde::de::DeserializeOwned;
+use std::io::Write;
 
+/// The number of keys read by each range request of a scan.
+const SCAN_PAGE_SIZE: i64 = 500;
+
+/// Returns the end of the range of the keys starting with `prefix`.
+fn prefix_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // every key starts with the prefix, "\0" ends the range at the end of the keyspace
+    vec![0]
+}
+
+/// Returns the first key of a scan of `prefix`, resuming after the key `start_after` if any.
+fn scan_start(prefix: &str, start_after: Option<&str>) -> Vec<u8> {
+    match start_after {
+        Some(key) if key >= prefix => {
+            let mut start = key.as_bytes().to_vec();
+            start.push(0);
+            start
+        }
+        _ => prefix.as_bytes().to_vec(),
+    }
+}
+
+/// A page of the keys read by a range request.
+///
+/// Properties:
+///
+/// * `kvs`: The keys and their values, in key order.
+/// * `last_key`: The last key read, where the next page starts.
+/// * `more`: If true, keys are left after this page.
+pub struct Page {
+    pub kvs: Vec<(String, String)>,
+    pub last_key: Option<String>,
+    pub more: bool,
+}
+
+/// The items of a listing and the token to resume it.
+///
+/// Properties:
+///
+/// * `items`: The items listed.
+/// * `continue_token`: The key of the last item, set if the listing stopped at its limit.
+pub struct Listing<T> {
+    pub items: Vec<T>,
+    pub continue_token: Option<String>,
+}
+
+/// The outcome of `EtcdClient::rename`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rename {
+    /// The value was moved, at this revision
+    Renamed(i64),
+    /// The new key already exists, nothing was written
+    Taken,
+    /// The previous key changed since its version or was deleted, nothing was written
+    Changed,
+}
+
+#[derive(Clone)]
 pub struct EtcdClient {
     inner: Client,
 }
@@ -20,6 +91,75 @@ impl EtcdClient {
             Err(_) => None,
         }
     }
+    /// Returns the value of `key` and the revision of etcd it was last modified at, its
+    /// resource version.
+    pub async fn get_versioned(&mut self, key: &str) -> Option<(String, i64)> {
+        let response = self.inner.get(key, None).await.ok()?;
+        let kv = response.kvs().first()?;
+        Some((kv.value_str().ok()?.to_string(), kv.mod_revision()))
+    }
+
+    /// Stores `value` under `key`, if `version` is set only if the key wasn't modified since
+    /// this revision, atomically.
+    ///
+    /// Returns the revision the key is modified at, `None` if the key changed or was deleted.
+    pub async fn put_if_version(
+        &mut self,
+        key: &str,
+        value: &str,
+        version: Option<i64>,
+    ) -> Result<Option<i64>, Error> {
+        let txn = match version {
+            Some(version) => Txn::new()
+                .when([Compare::mod_revision(key, CompareOp::Equal, version)])
+                .and_then([TxnOp::put(key, value, None)]),
+            None => Txn::new().and_then([TxnOp::put(key, value, None)]),
+        };
+        let response = self.inner.txn(txn).await?;
+        Ok(response
+            .succeeded()
+            .then(|| response.header().map_or(0, |header| header.revision())))
+    }
+
+    /// Moves a value from the key `from` to the key `to` atomically, e.g. for a renamed
+    /// resource: `to` is only created if it doesn't exist, and `from` is deleted. If `version`
+    /// is set, only if `from` wasn't modified since this revision.
+    pub async fn rename(
+        &mut self,
+        from: &str,
+        to: &str,
+        value: &str,
+        version: Option<i64>,
+    ) -> Result<Rename, Error> {
+        let guard = match version {
+            Some(version) => Compare::mod_revision(from, CompareOp::Equal, version),
+            None => Compare::create_revision(from, CompareOp::Greater, 0),
+        };
+        let txn = Txn::new()
+            .when([Compare::create_revision(to, CompareOp::Equal, 0), guard])
+            .and_then([TxnOp::put(to, value, None), TxnOp::delete(from, None)])
+            .or_else([TxnOp::get(to, None)]);
+        let response = self.inner.txn(txn).await?;
+        if response.succeeded() {
+            return Ok(Rename::Renamed(
+                response.header().map_or(0, |header| header.revision()),
+            ));
+        }
+
+        let taken = response
+            .op_responses()
+            .into_iter()
+            .any(|response| match response {
+                TxnOpResponse::Get(get) => !get.kvs().is_empty(),
+                _ => false,
+            });
+        Ok(if taken {
+            Rename::Taken
+        } else {
+            Rename::Changed
+        })
+    }
+
     pub async fn put(&mut self, key: &str, value: &str) -> Result<PutResponse, Error> {
         info!(
             "Inserting value in ETCD : Key \"{}\" associated with value \"{}\"",
@@ -27,16 +167,90 @@ impl EtcdClient {
         );
         self.inner.put(key, value, None).await
     }
+    /// Stores `value` under `key` only if the key doesn't exist, atomically. The key is deleted
+    /// after `ttl_seconds` if given, the lease granted for it is revoked if the key isn't stored.
+    ///
+    /// Returns the value already stored under the key, `None` if `value` was stored.
+    pub async fn put_if_absent(
+        &mut self,
+        key: &str,
+        value: &str,
+        ttl_seconds: Option<i64>,
+    ) -> Result<Option<String>, Error> {
+        let lease = match ttl_seconds {
+            Some(ttl) => Some(self.inner.lease_grant(ttl, None).await?.id()),
+            None => None,
+        };
+        let options = lease.map(|id| PutOptions::new().with_lease(id));
+
+        let txn = Txn::new()
+            .when([Compare::create_revision(key, CompareOp::Equal, 0)])
+            .and_then([TxnOp::put(key, value, options)])
+            .or_else([TxnOp::get(key, None)]);
+        // on an error the key may have been stored, the lease is left to expire with it
+        let response = self.inner.txn(txn).await?;
+        if response.succeeded() {
+            return Ok(None);
+        }
+        if let Some(id) = lease {
+            // the key wasn't stored, nothing is attached to the lease
+            _ = self.inner.lease_revoke(id).await;
+        }
+
+        Ok(response
+            .op_responses()
+            .into_iter()
+            .find_map(|response| match response {
+                TxnOpResponse::Get(get) => get
+                    .kvs()
+                    .first()
+                    .and_then(|kv| kv.value_str().ok().map(String::from)),
+                _ => None,
+            }))
+    }
+
     pub async fn delete(&mut self, key: &str) -> Option<DeleteResponse> {
         match self.get(key).await {
-            Some(_) => match self.inner.delete(key, None).await {
-                Ok(response) => Some(response),
-                Err(_) => None,
-            },
+            Some(_) => self.inner.delete(key, None).await.ok(),
             None => None,
         }
     }
 
+    /// Deletes `key`, the errors of etcd are returned unlike `delete`.
+    ///
+    /// Returns `true` if the key existed.
+    pub async fn delete_checked(&mut self, key: &str) -> Result<bool, Error> {
+        let response = self.inner.delete(key, None).await?;
+        Ok(response.deleted() > 0)
+    }
+
+    /// Deletes `key` only if the key `guard` wasn't modified since the revision `version`,
+    /// atomically.
+    ///
+    /// Returns `true` if the guard was unchanged, whether `key` existed or not.
+    pub async fn delete_if_unchanged(
+        &mut self,
+        key: &str,
+        guard: &str,
+        version: i64,
+    ) -> Result<bool, Error> {
+        let txn = Txn::new()
+            .when([Compare::mod_revision(guard, CompareOp::Equal, version)])
+            .and_then([TxnOp::delete(key, None)]);
+        Ok(self.inner.txn(txn).await?.succeeded())
+    }
+
+    /// Deletes every key starting with `prefix`.
+    ///
+    /// Returns the number of keys deleted.
+    pub async fn delete_prefix(&mut self, prefix: &str) -> Result<i64, Error> {
+        let response = self
+            .inner
+            .delete(prefix, Some(DeleteOptions::new().with_prefix()))
+            .await?;
+        Ok(response.deleted())
+    }
+
     pub async fn get_all(&mut self) -> Option<Vec<String>> {
         info!("Retrieving all keys in ETCD");
         let resp = self
@@ -55,7 +269,305 @@ impl EtcdClient {
             values
         })
     }
+
+    /// Returns the values of the keys starting with `prefix`. The keys are read page by page,
+    /// prefer `scan_prefix` or `list_prefix` when not every value is needed.
+    pub async fn get_all_with_prefix(&mut self, prefix: &str) -> Option<Vec<String>> {
+        info!("Retrieving all keys with prefix \"{}\" in ETCD", prefix);
+        self.scan_prefix(prefix, None)
+            .map_ok(|(_, value)| value)
+            .try_collect()
+            .await
+            .ok()
+    }
+
+    /// Reads at most `limit` keys starting with `prefix`, after the key `start_after` if any.
+    pub async fn get_page(
+        &mut self,
+        prefix: &str,
+        start_after: Option<&str>,
+        limit: i64,
+    ) -> Result<Page, Error> {
+        let response = self
+            .inner
+            .get(
+                scan_start(prefix, start_after),
+                Some(
+                    GetOptions::new()
+                        .with_range(prefix_end(prefix))
+                        .with_limit(limit),
+                ),
+            )
+            .await?;
+
+        Ok(Page {
+            kvs: response
+                .kvs()
+                .iter()
+                .filter_map(|kv| {
+                    Some((
+                        kv.key_str().ok()?.to_string(),
+                        kv.value_str().ok()?.to_string(),
+                    ))
+                })
+                .collect(),
+            last_key: response
+                .kvs()
+                .last()
+                .map(|kv| String::from_utf8_lossy(kv.key()).to_string()),
+            more: response.more(),
+        })
+    }
+
+    /// Streams the keys starting with `prefix` and their values, in key order, after the key
+    /// `start_after` if any. The keys are read one page at a time, as the stream is consumed.
+    pub fn scan_prefix(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+    ) -> impl Stream<Item = Result<(String, String), Error>> {
+        let state = (
+            self.clone(),
+            prefix.to_string(),
+            start_after.map(String::from),
+            true,
+        );
+
+        stream::try_unfold(
+            state,
+            |(mut client, prefix, start_after, more)| async move {
+                if !more {
+                    return Ok::<_, Error>(None);
+                }
+                let page = client
+                    .get_page(&prefix, start_after.as_deref(), SCAN_PAGE_SIZE)
+                    .await?;
+
+                let more = page.more && page.last_key.is_some();
+                let next = page.last_key.or(start_after);
+                let kvs = stream::iter(page.kvs.into_iter().map(Ok::<_, Error>));
+                Ok(Some((kvs, (client, prefix, next, more))))
+            },
+        )
+        .try_flatten()
+    }
+
+    /// Lists the values of the keys starting with `prefix` matching `matches`, skipping the
+    /// `offset` first ones and stopping after `limit` ones (no limit if 0). The keys are only
+    /// read up to the last item listed.
+    ///
+    /// # Arguments:
+    ///
+    /// * `prefix`: The prefix of the keys.
+    /// * `start_after`: The continue token of a previous listing, the listing resumes after it.
+    /// * `offset`: The number of matching items to skip.
+    /// * `limit`: The maximum number of items to list.
+    /// * `matches`: The filter of the items, the values which can't be deserialized are skipped.
+    pub async fn list_prefix<T: DeserializeOwned>(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        offset: u32,
+        limit: u32,
+        matches: impl Fn(&T) -> bool,
+    ) -> Result<Listing<T>, Error> {
+        let scan = self.scan_prefix(prefix, start_after);
+        pin_mut!(scan);
+
+        let mut items = vec![];
+        let mut skipped = 0;
+        while let Some((key, value)) = scan.try_next().await? {
+            let Ok(item) = serde_json::from_str::<T>(&value) else {
+                continue;
+            };
+            if !matches(&item) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+
+            items.push(item);
+            if limit > 0 && items.len() == limit as usize {
+                return Ok(Listing {
+                    items,
+                    continue_token: Some(key),
+                });
+            }
+        }
+
+        Ok(Listing {
+            items,
+            continue_token: None,
+        })
+    }
+
+    /// Watches the keys starting with `prefix`, the events of the deletions carry the deleted value.
+    /// The watch is cancelled when the `Watcher` is dropped.
+    pub async fn watch_prefix(&mut self, prefix: &str) -> Result<(Watcher, WatchStream), Error> {
+        info!("Watching keys with prefix \"{}\" in ETCD", prefix);
+        self.inner
+            .watch(
+                prefix,
+                Some(WatchOptions::new().with_prefix().with_prev_key()),
+            )
+            .await
+    }
+
+    /// Watches the keys starting with `prefix` as `watch_prefix` does, the modifications made
+    /// from `revision` are received first. See `is_compacted` to know if they still can be.
+    pub async fn watch_prefix_since(
+        &mut self,
+        prefix: &str,
+        revision: i64,
+    ) -> Result<(Watcher, WatchStream), Error> {
+        info!(
+            "Watching keys with prefix \"{}\" since revision {} in ETCD",
+            prefix, revision
+        );
+        self.inner
+            .watch(
+                prefix,
+                Some(
+                    WatchOptions::new()
+                        .with_prefix()
+                        .with_prev_key()
+                        .with_start_revision(revision),
+                ),
+            )
+            .await
+    }
+
+    /// Returns `true` if the revision `revision` was compacted by etcd, the modifications made
+    /// since can't be watched anymore.
+    pub async fn is_compacted(&mut self, revision: i64) -> Result<bool, Error> {
+        let options = GetOptions::new()
+            .with_revision(revision)
+            .with_keys_only()
+            .with_limit(1);
+        match self.inner.get("", Some(options)).await {
+            Ok(_) => Ok(false),
+            Err(Error::GRpcStatus(status)) if status.message().contains("compacted") => Ok(true),
+            // a revision not reached yet is watched until it is
+            Err(Error::GRpcStatus(status)) if status.message().contains("future revision") => {
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads every key starting with `prefix` in a single request, returns the revision of etcd
+    /// they were read at and the keys with their values, in key order.
+    pub async fn snapshot_prefix(
+        &mut self,
+        prefix: &str,
+    ) -> Result<(i64, Vec<(String, String)>), Error> {
+        let response = self
+            .inner
+            .get(
+                prefix,
+                Some(GetOptions::new().with_range(prefix_end(prefix))),
+            )
+            .await?;
+        let revision = response.header().map_or(0, |header| header.revision());
+        let kvs = response
+            .kvs()
+            .iter()
+            .filter_map(|kv| {
+                Some((
+                    kv.key_str().ok()?.to_string(),
+                    kv.value_str().ok()?.to_string(),
+                ))
+            })
+            .collect();
+        Ok((revision, kvs))
+    }
+
+    /// Watches the keys starting with `prefix` from `revision`, the modifications made since a
+    /// `snapshot_prefix` at `revision - 1` are all received.
+    pub async fn watch_prefix_from(
+        &mut self,
+        prefix: &str,
+        revision: i64,
+    ) -> Result<(Watcher, WatchStream), Error> {
+        info!(
+            "Watching keys with prefix \"{}\" from revision {} in ETCD",
+            prefix, revision
+        );
+        self.inner
+            .watch(
+                prefix,
+                Some(
+                    WatchOptions::new()
+                        .with_prefix()
+                        .with_start_revision(revision),
+                ),
+            )
+            .await
+    }
+
+    /// Grants a lease expiring after `ttl_seconds` unless kept alive, returns its ID.
+    pub async fn grant_lease(&mut self, ttl_seconds: i64) -> Result<i64, Error> {
+        Ok(self.inner.lease_grant(ttl_seconds, None).await?.id())
+    }
+
+    /// Opens the stream keeping the lease `id` alive, a keep alive is sent with the `LeaseKeeper`
+    /// and answered on the `LeaseKeepAliveStream`.
+    pub async fn keep_lease_alive(
+        &mut self,
+        id: i64,
+    ) -> Result<(LeaseKeeper, LeaseKeepAliveStream), Error> {
+        self.inner.lease_keep_alive(id).await
+    }
+
+    /// Revokes the lease `id`, deleting the keys attached to it.
+    pub async fn revoke_lease(&mut self, id: i64) -> Result<(), Error> {
+        self.inner.lease_revoke(id).await.map(|_| ())
+    }
+
+    /// Streams a snapshot of the whole keyspace to `writer`, in the format of
+    /// `etcdctl snapshot save`. Returns the size of the snapshot, in bytes.
+    pub async fn snapshot(&mut self, writer: &mut impl Write) -> Result<u64, Error> {
+        info!("Taking a snapshot of ETCD");
+        let mut stream = self.inner.snapshot().await?;
+        let mut size = 0;
+        while let Some(response) = stream.message().await? {
+            writer.write_all(response.blob()).map_err(Error::IoError)?;
+            size += response.blob().len() as u64;
+        }
+        writer.flush().map_err(Error::IoError)?;
+        Ok(size)
+    }
+
+    /// Campaigns in the election `name` with `value` as long as the lease `id` is alive, returns
+    /// once elected.
+    pub async fn campaign(&mut self, name: &str, value: &str, id: i64) -> Result<(), Error> {
+        self.inner.campaign(name, value, id).await.map(|_| ())
+    }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(prefix_end("instance."), b"instance/".to_vec());
+        assert_eq!(prefix_end(""), vec![0]);
+    }
+
+    #[test]
+    fn test_scan_start() {
+        assert_eq!(scan_start("instance.", None), b"instance.".to_vec());
+        assert_eq!(
+            scan_start("instance.", Some("instance.default.42")),
+            b"instance.default.42\0".to_vec()
+        );
+        // a token before the prefix can't skip keys of the prefix
+        assert_eq!(scan_start("instance.", Some("a")), b"instance.".to_vec());
+    }
+}
+
 /*
 
 #[cfg(test)]
@@ -0,0 +1,99 @@
+use crate::admission::{AdmissionError, AdmissionService, Operation, ResourceKind};
+use crate::external_api::interface::ActixAppState;
+
+use super::model::{Archive, ExportQuery, ImportQuery};
+use super::service::ArchiveService;
+use crate::external_api::generic::problem::Problem;
+use actix_web::{web, Responder, Scope};
+pub struct ArchiveController {}
+impl ArchiveController {
+    pub fn services(&self) -> Scope {
+        web::scope("/archive")
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(ARCHIVE_SIZE_LIMIT)
+                    .error_handler(Problem::json_error_handler),
+            )
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(web::resource("/export").route(web::get().to(ArchiveController::export)))
+            .service(web::resource("/import").route(web::post().to(ArchiveController::import)))
+    }
+
+    /// `export` is an async function that handle **/archive/export** route (GET)
+    /// # Description:
+    /// * Export the workloads, services, ingresses, network policies and cron jobs of the cluster
+    /// # Arguments:
+    ///
+    /// * `query`: web::Query<ExportQuery> - The comma-separated namespaces to export, every namespace if unset.
+    pub async fn export(
+        query: web::Query<ExportQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut archive_service =
+            match ArchiveService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        archive_service
+            .export(query.namespaces())
+            .await
+            .map_or_else(|e| e.to_http(), |a| a.to_http())
+    }
+
+    /// `import` is an async function that handle **/archive/import** route (POST)
+    /// # Description:
+    /// * Import an archive exported by a cluster, its workloads pass the admission like the ones created one by one
+    /// # Arguments:
+    ///
+    /// * `query`: web::Query<ImportQuery> - What to do with the resources which already exist: `fail`, `skip` or `overwrite`.
+    /// * `body`: web::Json<Archive> - The archive to import.
+    pub async fn import(
+        query: web::Query<ImportQuery>,
+        body: web::Json<Archive>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut archive_service =
+            match ArchiveService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_node_port_range(data.node_port_range)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+        let archive = body.into_inner();
+
+        if let Err(e) = ArchiveController::admit(&data, &archive).await {
+            return e.to_http();
+        }
+
+        archive_service
+            .import(archive, query.strategy)
+            .await
+            .map_or_else(|e| e.to_http(), |r| r.to_http())
+    }
+
+    /// Sends each workload of an archive to the admission webhooks and checks the signature of
+    /// its image, before anything of the archive is written.
+    async fn admit(data: &ActixAppState, archive: &Archive) -> Result<(), AdmissionError> {
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref());
+        for namespace in &archive.namespaces {
+            for workload_dto in &namespace.workloads {
+                admission
+                    .review(
+                        ResourceKind::Workload,
+                        Operation::Create,
+                        &namespace.name,
+                        &workload_dto.name,
+                        serde_json::to_value(workload_dto).unwrap_or_default(),
+                    )
+                    .await?;
+                admission.verify_image(&workload_dto.uri).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The largest archive imported, in bytes, above the default limit of the JSON bodies.
+const ARCHIVE_SIZE_LIMIT: usize = 64 * 1024 * 1024;
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::cronjob::model::{CronJob, CronJobDTO, CronJobError};
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::ingress::model::{Ingress, IngressDTO, IngressError};
+use crate::external_api::network_policy::model::{
+    NetworkPolicy, NetworkPolicyDTO, NetworkPolicyError,
+};
+use crate::external_api::service::model::{Service, ServiceDTO, ServiceError};
+use crate::external_api::workload::model::{Workload, WorkloadDTO, WorkloadError};
+
+/// The version of the archives exported, the archives of a later version are refused.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+pub enum ArchiveError {
+    Etcd(String),
+    UnsupportedVersion(u32),
+    Conflict(String, String, String),
+    Workload(WorkloadError),
+    Service(ServiceError),
+    Ingress(IngressError),
+    NetworkPolicy(NetworkPolicyError),
+    CronJob(CronJobError),
+    ArchiveToJson(String),
+}
+
+impl ArchiveError {
+    pub fn to_http(&self) -> HttpResponse {
+        let problem = match self {
+            ArchiveError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            ArchiveError::UnsupportedVersion(version) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "unsupported_archive_version",
+                format!(
+                    "Archive version {} is not supported, the latest is {}",
+                    version, ARCHIVE_VERSION
+                ),
+            ),
+            ArchiveError::Conflict(kind, namespace, name) => Problem::new(
+                StatusCode::CONFLICT,
+                "resource_conflict",
+                format!(
+                    "The {} {}/{} already exists, import with the skip or overwrite strategy",
+                    kind, namespace, name
+                ),
+            ),
+            ArchiveError::Workload(err) => return err.to_http(),
+            ArchiveError::Service(err) => return err.to_http(),
+            ArchiveError::Ingress(err) => return err.to_http(),
+            ArchiveError::NetworkPolicy(err) => return err.to_http(),
+            ArchiveError::CronJob(err) => return err.to_http(),
+            ArchiveError::ArchiveToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "archive_serialization_failed",
+                format!("Error while converting the archive to JSON: {}", err),
+            ),
+        };
+        problem.to_http()
+    }
+}
+
+/// What an import does with the resources of the archive which already exist in the cluster.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictStrategy {
+    /// Nothing is imported if one of the resources exists
+    #[default]
+    Fail,
+    /// The existing resources are kept
+    Skip,
+    /// The existing resources are replaced by the ones of the archive
+    Overwrite,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// Comma-separated namespaces to export, every namespace if unset
+    pub namespaces: Option<String>,
+}
+
+impl ExportQuery {
+    pub fn namespaces(&self) -> Option<Vec<String>> {
+        self.namespaces.as_ref().map(|namespaces| {
+            namespaces
+                .split(',')
+                .map(str::trim)
+                .filter(|namespace| !namespace.is_empty())
+                .map(String::from)
+                .collect()
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    pub strategy: ConflictStrategy,
+}
+
+/// The resources of a namespace in an archive, as they would be submitted to create them.
+#[derive(Deserialize, Serialize, Default)]
+pub struct NamespaceArchive {
+    pub name: String,
+    #[serde(default)]
+    pub workloads: Vec<WorkloadDTO>,
+    #[serde(default)]
+    pub services: Vec<ServiceDTO>,
+    #[serde(default)]
+    pub ingresses: Vec<IngressDTO>,
+    #[serde(default)]
+    pub network_policies: Vec<NetworkPolicyDTO>,
+    #[serde(default)]
+    pub cronjobs: Vec<CronJobDTO>,
+}
+
+impl NamespaceArchive {
+    /// Returns the resources of the namespace, in the order they are imported: the services
+    /// after the workloads they route to.
+    pub fn resources(&self) -> Vec<ImportedResource> {
+        let kinds = [
+            (
+                "workload",
+                self.workloads.iter().map(|w| &w.name).collect::<Vec<_>>(),
+            ),
+            ("service", self.services.iter().map(|s| &s.name).collect()),
+            ("ingress", self.ingresses.iter().map(|i| &i.name).collect()),
+            (
+                "networkpolicy",
+                self.network_policies.iter().map(|p| &p.name).collect(),
+            ),
+            ("cronjob", self.cronjobs.iter().map(|c| &c.name).collect()),
+        ];
+        kinds
+            .into_iter()
+            .flat_map(|(kind, names)| {
+                names
+                    .into_iter()
+                    .map(move |name| ImportedResource::new(kind, &self.name, name))
+            })
+            .collect()
+    }
+}
+
+/// The declared state of a cluster, exported to be imported in another one.
+///
+/// Properties:
+///
+/// * `version`: The version of the format of the archive.
+/// * `exported_at`: When the archive was exported, in seconds since the unix epoch.
+/// * `namespaces`: The resources of each namespace, sorted by namespace.
+#[derive(Deserialize, Serialize)]
+pub struct Archive {
+    pub version: u32,
+    pub exported_at: u64,
+    pub namespaces: Vec<NamespaceArchive>,
+}
+
+impl Archive {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => ArchiveError::ArchiveToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// A resource stored in etcd which is part of the archives.
+pub enum ArchivedResource {
+    Workload(Box<Workload>),
+    Service(Service),
+    Ingress(Ingress),
+    NetworkPolicy(NetworkPolicy),
+    CronJob(Box<CronJob>),
+}
+
+impl ArchivedResource {
+    /// Returns the resource stored under `key`, `None` if the key holds no resource of the
+    /// archives, e.g. an instance or an index. The workloads are the only resources stored
+    /// without a prefix, under `<namespace>.<name>`.
+    pub fn parse(key: &str, value: &str) -> Option<ArchivedResource> {
+        let kind = key.split('.').next().unwrap_or_default();
+        match kind {
+            "service" => serde_json::from_str(value)
+                .ok()
+                .map(ArchivedResource::Service),
+            "ingress" => serde_json::from_str(value)
+                .ok()
+                .map(ArchivedResource::Ingress),
+            "networkpolicy" => serde_json::from_str(value)
+                .ok()
+                .map(ArchivedResource::NetworkPolicy),
+            "cronjob" => serde_json::from_str::<CronJob>(value)
+                .ok()
+                .map(|cronjob| ArchivedResource::CronJob(Box::new(cronjob))),
+            _ => serde_json::from_str::<Workload>(value)
+                .ok()
+                .filter(|workload| workload.id == key)
+                .map(|workload| ArchivedResource::Workload(Box::new(workload))),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        match self {
+            ArchivedResource::Workload(workload) => &workload.namespace,
+            ArchivedResource::Service(service) => &service.namespace,
+            ArchivedResource::Ingress(ingress) => &ingress.namespace,
+            ArchivedResource::NetworkPolicy(policy) => &policy.namespace,
+            ArchivedResource::CronJob(cronjob) => &cronjob.namespace,
+        }
+    }
+}
+
+/// Groups resources by namespace, sorted by namespace.
+pub fn group_by_namespace(resources: Vec<ArchivedResource>) -> Vec<NamespaceArchive> {
+    let mut namespaces: BTreeMap<String, NamespaceArchive> = BTreeMap::new();
+    for resource in resources {
+        let namespace = namespaces
+            .entry(resource.namespace().to_string())
+            .or_insert_with_key(|name| NamespaceArchive {
+                name: name.clone(),
+                ..Default::default()
+            });
+        match resource {
+            ArchivedResource::Workload(workload) => namespace.workloads.push((*workload).into()),
+            ArchivedResource::Service(service) => namespace.services.push(service.into()),
+            ArchivedResource::Ingress(ingress) => namespace.ingresses.push(ingress.into()),
+            ArchivedResource::NetworkPolicy(policy) => {
+                namespace.network_policies.push(policy.into())
+            }
+            ArchivedResource::CronJob(cronjob) => namespace.cronjobs.push((*cronjob).into()),
+        }
+    }
+    namespaces.into_values().collect()
+}
+
+/// A resource of an archive and what the import did with it.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ImportedResource {
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+}
+
+impl ImportedResource {
+    pub fn new(kind: &str, namespace: &str, name: &str) -> ImportedResource {
+        ImportedResource {
+            kind: kind.to_string(),
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        }
+    }
+}
+
+/// Report of an import, listing the resources in the order they were imported.
+#[derive(Deserialize, Serialize, Default, Debug)]
+pub struct ImportReport {
+    pub created: Vec<ImportedResource>,
+    pub updated: Vec<ImportedResource>,
+    pub skipped: Vec<ImportedResource>,
+}
+
+impl ImportReport {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => ArchiveError::ArchiveToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ingress =
+            r#"{"id": "ingress.default.web", "name": "web", "namespace": "default", "rules": []}"#;
+        assert!(matches!(
+            ArchivedResource::parse("ingress.default.web", ingress),
+            Some(ArchivedResource::Ingress(_))
+        ));
+        // a value which isn't a workload stored under its id is not archived
+        assert!(ArchivedResource::parse("default.web", ingress).is_none());
+        assert!(ArchivedResource::parse("index.workload.default.web", "default.web").is_none());
+    }
+
+    #[test]
+    fn test_export_query() {
+        let query = ExportQuery {
+            namespaces: Some("default, shop,".to_string()),
+        };
+        assert_eq!(query.namespaces().unwrap(), vec!["default", "shop"]);
+        assert!(ExportQuery { namespaces: None }.namespaces().is_none());
+    }
+}
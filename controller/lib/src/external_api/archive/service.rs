@@ -0,0 +1,295 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use futures_util::TryStreamExt;
+use log::info;
+
+use super::model::{
+    group_by_namespace, Archive, ArchiveError, ArchivedResource, ConflictStrategy, ImportReport,
+    ImportedResource, NamespaceArchive, ARCHIVE_VERSION,
+};
+use crate::etcd::EtcdClient;
+use crate::external_api::cronjob::model::CronJobError;
+use crate::external_api::cronjob::service::CronJobService;
+use crate::external_api::ingress::model::IngressError;
+use crate::external_api::ingress::service::IngressService;
+use crate::external_api::instance::service::unix_time;
+use crate::external_api::network_policy::model::NetworkPolicyError;
+use crate::external_api::network_policy::service::NetworkPolicyService;
+use crate::external_api::service::model::{NodePortRange, ServiceError};
+use crate::external_api::service::service::ServiceService;
+use crate::external_api::workload::cache::WorkloadCache;
+use crate::external_api::workload::model::WorkloadError;
+use crate::external_api::workload::service::WorkloadService;
+
+/// `ArchiveService` exports the declared state of the cluster, the workloads, services,
+/// ingresses, network policies and cron jobs of its namespaces, and imports it in another
+/// cluster. The instances are not archived, the controllers of the imported workloads create
+/// theirs and the other ones are created again through the instance routes.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to read the resources from etcd.
+/// * `workload_service`, `service_service`, `ingress_service`, `network_policy_service`,
+///   `cronjob_service`: The services used to write the imported resources, so that they are
+///   validated and allocated as if they were created one by one.
+pub struct ArchiveService {
+    etcd_service: EtcdClient,
+    workload_service: WorkloadService,
+    service_service: ServiceService,
+    ingress_service: IngressService,
+    network_policy_service: NetworkPolicyService,
+    cronjob_service: CronJobService,
+}
+
+impl ArchiveService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<ArchiveService, ArchiveError> {
+        Ok(ArchiveService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| ArchiveError::Etcd(err.to_string()))?,
+            workload_service: WorkloadService::new(etcd_address)
+                .await
+                .map_err(ArchiveError::Workload)?,
+            service_service: ServiceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(ArchiveError::Service)?,
+            ingress_service: IngressService::new(etcd_address)
+                .await
+                .map_err(ArchiveError::Ingress)?,
+            network_policy_service: NetworkPolicyService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(ArchiveError::NetworkPolicy)?,
+            cronjob_service: CronJobService::new(etcd_address)
+                .await
+                .map_err(ArchiveError::CronJob)?,
+        })
+    }
+
+    pub fn with_node_port_range(mut self, node_port_range: NodePortRange) -> Self {
+        self.service_service = self.service_service.with_node_port_range(node_port_range);
+        self
+    }
+
+    pub fn with_workload_cache(mut self, cache: &WorkloadCache) -> Self {
+        self.workload_service = self.workload_service.with_cache(cache);
+        self
+    }
+
+    /// It exports the resources of `namespaces`, or of every namespace if `None`.
+    pub async fn export(
+        &mut self,
+        namespaces: Option<Vec<String>>,
+    ) -> Result<Archive, ArchiveError> {
+        let resources: Vec<ArchivedResource> = self
+            .etcd_service
+            .scan_prefix("", None)
+            .try_filter_map(|(key, value)| async move {
+                // the other values, e.g. the instances or the indexes, are not archived
+                Ok(ArchivedResource::parse(&key, &value))
+            })
+            .try_collect()
+            .await
+            .map_err(|err| ArchiveError::Etcd(err.to_string()))?;
+
+        let resources = resources
+            .into_iter()
+            .filter(|resource| match &namespaces {
+                Some(namespaces) => namespaces.iter().any(|n| n == resource.namespace()),
+                None => true,
+            })
+            .collect();
+        Ok(Archive {
+            version: ARCHIVE_VERSION,
+            exported_at: unix_time(),
+            namespaces: group_by_namespace(resources),
+        })
+    }
+
+    /// It imports an archive, namespace by namespace: the workloads first, then the services,
+    /// the ingresses, the network policies and the cron jobs. The resources which already exist
+    /// are handled according to `strategy`, with `Fail` nothing is imported if one exists.
+    /// An import stopped by an error can be resumed with the `Skip` strategy.
+    pub async fn import(
+        &mut self,
+        archive: Archive,
+        strategy: ConflictStrategy,
+    ) -> Result<ImportReport, ArchiveError> {
+        if archive.version > ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(archive.version));
+        }
+
+        let mut existing = HashSet::new();
+        for resource in archive
+            .namespaces
+            .iter()
+            .flat_map(NamespaceArchive::resources)
+        {
+            if self.exists(&resource).await? {
+                if strategy == ConflictStrategy::Fail {
+                    return Err(ArchiveError::Conflict(
+                        resource.kind,
+                        resource.namespace,
+                        resource.name,
+                    ));
+                }
+                existing.insert(resource);
+            }
+        }
+
+        let mut report = ImportReport::default();
+        for namespace in archive.namespaces {
+            self.import_namespace(namespace, strategy, &existing, &mut report)
+                .await?;
+        }
+        info!(
+            "Archive imported: {} resource(s) created, {} updated, {} skipped",
+            report.created.len(),
+            report.updated.len(),
+            report.skipped.len()
+        );
+        Ok(report)
+    }
+
+    async fn import_namespace(
+        &mut self,
+        namespace: NamespaceArchive,
+        strategy: ConflictStrategy,
+        existing: &HashSet<ImportedResource>,
+        report: &mut ImportReport,
+    ) -> Result<(), ArchiveError> {
+        let ns = namespace.name;
+        // the resources which exist are overwritten or skipped, `Fail` already returned
+        let overwrite = strategy == ConflictStrategy::Overwrite;
+
+        for dto in namespace.workloads {
+            let resource = ImportedResource::new("workload", &ns, &dto.name);
+            if !existing.contains(&resource) {
+                self.workload_service
+                    .create_workload(dto, &ns)
+                    .await
+                    .map_err(ArchiveError::Workload)?;
+                report.created.push(resource);
+            } else if overwrite {
+                self.workload_service
+                    .update_workload(dto, &resource.name, &ns)
+                    .await
+                    .map_err(ArchiveError::Workload)?;
+                report.updated.push(resource);
+            } else {
+                report.skipped.push(resource);
+            }
+        }
+        for dto in namespace.services {
+            let resource = ImportedResource::new("service", &ns, &dto.name);
+            if !existing.contains(&resource) {
+                self.service_service
+                    .create_service(dto, &ns)
+                    .await
+                    .map_err(ArchiveError::Service)?;
+                report.created.push(resource);
+            } else if overwrite {
+                self.service_service
+                    .update_service(dto, &resource.name, &ns)
+                    .await
+                    .map_err(ArchiveError::Service)?;
+                report.updated.push(resource);
+            } else {
+                report.skipped.push(resource);
+            }
+        }
+        for dto in namespace.ingresses {
+            let resource = ImportedResource::new("ingress", &ns, &dto.name);
+            if !existing.contains(&resource) {
+                self.ingress_service
+                    .create_ingress(dto, &ns)
+                    .await
+                    .map_err(ArchiveError::Ingress)?;
+                report.created.push(resource);
+            } else if overwrite {
+                self.ingress_service
+                    .update_ingress(dto, &resource.name, &ns)
+                    .await
+                    .map_err(ArchiveError::Ingress)?;
+                report.updated.push(resource);
+            } else {
+                report.skipped.push(resource);
+            }
+        }
+        for dto in namespace.network_policies {
+            let resource = ImportedResource::new("networkpolicy", &ns, &dto.name);
+            if !existing.contains(&resource) {
+                self.network_policy_service
+                    .create_network_policy(dto, &ns)
+                    .await
+                    .map_err(ArchiveError::NetworkPolicy)?;
+                report.created.push(resource);
+            } else if overwrite {
+                self.network_policy_service
+                    .update_network_policy(dto, &resource.name, &ns)
+                    .await
+                    .map_err(ArchiveError::NetworkPolicy)?;
+                report.updated.push(resource);
+            } else {
+                report.skipped.push(resource);
+            }
+        }
+        for dto in namespace.cronjobs {
+            let resource = ImportedResource::new("cronjob", &ns, &dto.name);
+            if !existing.contains(&resource) {
+                self.cronjob_service
+                    .create_cronjob(dto, &ns)
+                    .await
+                    .map_err(ArchiveError::CronJob)?;
+                report.created.push(resource);
+            } else if overwrite {
+                self.cronjob_service
+                    .update_cronjob(dto, &resource.name, &ns)
+                    .await
+                    .map_err(ArchiveError::CronJob)?;
+                report.updated.push(resource);
+            } else {
+                report.skipped.push(resource);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if a resource of the archive already exists in the cluster.
+    async fn exists(&mut self, resource: &ImportedResource) -> Result<bool, ArchiveError> {
+        let (name, ns) = (resource.name.as_str(), resource.namespace.as_str());
+        match resource.kind.as_str() {
+            "workload" => match self.workload_service.get_workload(name, ns).await {
+                Ok(_) => Ok(true),
+                Err(WorkloadError::WorkloadNotFound) => Ok(false),
+                Err(err) => Err(ArchiveError::Workload(err)),
+            },
+            "service" => match self.service_service.get_service(name, ns).await {
+                Ok(_) => Ok(true),
+                Err(ServiceError::ServiceNotFound) => Ok(false),
+                Err(err) => Err(ArchiveError::Service(err)),
+            },
+            "ingress" => match self.ingress_service.get_ingress(name, ns).await {
+                Ok(_) => Ok(true),
+                Err(IngressError::IngressNotFound) => Ok(false),
+                Err(err) => Err(ArchiveError::Ingress(err)),
+            },
+            "networkpolicy" => match self
+                .network_policy_service
+                .get_network_policy(name, ns)
+                .await
+            {
+                Ok(_) => Ok(true),
+                Err(NetworkPolicyError::NetworkPolicyNotFound) => Ok(false),
+                Err(err) => Err(ArchiveError::NetworkPolicy(err)),
+            },
+            _ => match self.cronjob_service.get_cronjob(name, ns).await {
+                Ok(_) => Ok(true),
+                Err(CronJobError::CronJobNotFound) => Ok(false),
+                Err(err) => Err(ArchiveError::CronJob(err)),
+            },
+        }
+    }
+}
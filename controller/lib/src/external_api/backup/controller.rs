@@ -0,0 +1,78 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::BackupError;
+use super::service::BackupService;
+use crate::external_api::generic::problem::Problem;
+use actix_web::{web, HttpResponse, Responder, Scope};
+pub struct BackupController {}
+impl BackupController {
+    pub fn services(&self) -> Scope {
+        web::scope("/backup")
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(web::resource("/{name}").route(web::get().to(BackupController::download)))
+            .service(
+                web::resource("")
+                    .route(web::post().to(BackupController::take_backup))
+                    .route(web::get().to(BackupController::get_all_backups)),
+            )
+    }
+
+    /// `take_backup` is an async function that handle **/backup** route (POST)
+    /// # Description:
+    /// * Take a snapshot of etcd and store it in the backup directory
+    pub async fn take_backup(data: web::Data<ActixAppState>) -> impl Responder {
+        let mut backup_service = match BackupService::new(&data.etcd_address, &data.backup).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        backup_service
+            .take_backup()
+            .await
+            .map_or_else(|e| e.to_http(), |b| b.to_http())
+    }
+
+    /// `get_all_backups` is an async function that handle **/backup** route (GET)
+    /// # Description:
+    /// * Get the backups of the backup directory, the most recent first
+    pub async fn get_all_backups(data: web::Data<ActixAppState>) -> impl Responder {
+        let backup_service = match BackupService::new(&data.etcd_address, &data.backup).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        backup_service
+            .get_backups()
+            .map_or_else(|e| e.to_http(), |b| b.to_http())
+    }
+
+    /// `download` is an async function that handle **/backup/\<name>** route (GET)
+    /// # Description:
+    /// * Download a backup, e.g. to copy it to another host or to an object storage
+    /// # Arguments:
+    ///
+    /// * `name`: web::Path<String> - The name of the backup.
+    pub async fn download(
+        name: web::Path<String>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let backup_service = match BackupService::new(&data.etcd_address, &data.backup).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        let snapshot = backup_service
+            .get_backup_path(&name)
+            .and_then(|path| std::fs::read(path).map_err(|err| BackupError::Io(err.to_string())));
+        match snapshot {
+            Ok(snapshot) => HttpResponse::Ok()
+                .content_type("application/octet-stream")
+                .insert_header((
+                    "Content-Disposition",
+                    format!("attachment; filename=\"{}\"", name),
+                ))
+                .body(snapshot),
+            Err(e) => e.to_http(),
+        }
+    }
+}
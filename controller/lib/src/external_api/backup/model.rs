@@ -0,0 +1,133 @@
+use std::cmp::Reverse;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+
+/// Returns the name of the file of a backup taken at `created_at`, in seconds since the unix
+/// epoch.
+pub fn backup_name(created_at: u64) -> String {
+    format!("etcd-{}.db", created_at)
+}
+
+/// Returns when a backup was taken from the name of its file, `None` if the file isn't a backup.
+pub fn parse_backup_name(name: &str) -> Option<u64> {
+    name.strip_prefix("etcd-")?
+        .strip_suffix(".db")?
+        .parse()
+        .ok()
+}
+
+pub enum BackupError {
+    BackupNotFound,
+    Etcd(String),
+    Io(String),
+    BackupToJson(String),
+}
+
+impl BackupError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            BackupError::BackupNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "backup_not_found",
+                "Backup not found",
+            ),
+            BackupError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            BackupError::Io(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "backup_storage_error",
+                format!("Error while accessing the backups: {}", err),
+            ),
+            BackupError::BackupToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "backup_serialization_failed",
+                format!("Error while converting the backup to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// A snapshot of etcd stored in the backup directory.
+///
+/// Properties:
+///
+/// * `name`: The name of the file of the snapshot.
+/// * `size`: The size of the snapshot, in bytes.
+/// * `created_at`: When the snapshot was taken, in seconds since the unix epoch.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Backup {
+    pub name: String,
+    pub size: u64,
+    pub created_at: u64,
+}
+
+impl Backup {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => BackupError::BackupToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Returns the backups beyond the `retention` most recent ones, deleted after each backup.
+pub fn expired_backups(backups: &[Backup], retention: usize) -> Vec<&Backup> {
+    let mut backups: Vec<&Backup> = backups.iter().collect();
+    backups.sort_by_key(|backup| Reverse(backup.created_at));
+    backups.into_iter().skip(retention).collect()
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BackupVector {
+    pub backups: Vec<Backup>,
+}
+
+impl BackupVector {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => BackupError::BackupToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_name() {
+        assert_eq!(backup_name(1700000000), "etcd-1700000000.db");
+        assert_eq!(parse_backup_name("etcd-1700000000.db"), Some(1700000000));
+        assert_eq!(parse_backup_name("etcd-1700000000.db.part"), None);
+        assert_eq!(parse_backup_name("../etcd-1.db"), None);
+    }
+
+    #[test]
+    fn test_expired_backups() {
+        let backups: Vec<Backup> = [30, 10, 20]
+            .into_iter()
+            .map(|created_at| Backup {
+                name: backup_name(created_at),
+                size: 0,
+                created_at,
+            })
+            .collect();
+
+        let expired = expired_backups(&backups, 2);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].created_at, 10);
+        assert!(expired_backups(&backups, 3).is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use std::cmp::Reverse;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use log::{info, warn};
+
+use super::model::{
+    backup_name, expired_backups, parse_backup_name, Backup, BackupError, BackupVector,
+};
+use crate::backup::BackupConfig;
+use crate::etcd::EtcdClient;
+use crate::external_api::instance::service::unix_time;
+
+/// `BackupService` takes the snapshots of etcd and stores them as files of the backup
+/// directory, the most recent ones being kept.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to take the snapshots.
+/// * `dir`: The directory of the backups.
+/// * `retention`: The number of backups kept, the oldest ones are deleted.
+pub struct BackupService {
+    etcd_service: EtcdClient,
+    dir: PathBuf,
+    retention: usize,
+}
+
+impl BackupService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        config: &BackupConfig,
+    ) -> Result<BackupService, BackupError> {
+        Ok(BackupService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| BackupError::Etcd(err.to_string()))?,
+            dir: config.dir.clone(),
+            retention: config.retention,
+        })
+    }
+
+    /// It takes a snapshot of etcd then deletes the backups beyond the retention. The snapshot
+    /// is written to a temporary file, renamed once complete so that a backup is never partial.
+    pub async fn take_backup(&mut self) -> Result<Backup, BackupError> {
+        fs::create_dir_all(&self.dir).map_err(|err| BackupError::Io(err.to_string()))?;
+        let created_at = unix_time();
+        let name = backup_name(created_at);
+        let path = self.dir.join(&name);
+        let partial = self.dir.join(format!("{}.part", name));
+
+        let file = File::create(&partial).map_err(|err| BackupError::Io(err.to_string()))?;
+        let result = self.etcd_service.snapshot(&mut BufWriter::new(file)).await;
+        let size = match result {
+            Ok(size) => size,
+            Err(err) => {
+                _ = fs::remove_file(&partial);
+                return Err(BackupError::Etcd(err.to_string()));
+            }
+        };
+        fs::rename(&partial, &path).map_err(|err| BackupError::Io(err.to_string()))?;
+        info!("Backup {} taken, {} bytes", name, size);
+
+        self.prune()?;
+        Ok(Backup {
+            name,
+            size,
+            created_at,
+        })
+    }
+
+    /// It returns the backups of the backup directory, the most recent first.
+    pub fn get_backups(&self) -> Result<BackupVector, BackupError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(BackupVector { backups: vec![] })
+            }
+            Err(err) => return Err(BackupError::Io(err.to_string())),
+        };
+
+        let mut backups: Vec<Backup> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let created_at = parse_backup_name(&name)?;
+                let size = entry.metadata().ok()?.len();
+                Some(Backup {
+                    name,
+                    size,
+                    created_at,
+                })
+            })
+            .collect();
+        backups.sort_by_key(|backup| Reverse(backup.created_at));
+        Ok(BackupVector { backups })
+    }
+
+    /// It returns the path of a backup, to download it.
+    pub fn get_backup_path(&self, name: &str) -> Result<PathBuf, BackupError> {
+        // only the names of backups are accepted, they can't leave the backup directory
+        parse_backup_name(name).ok_or(BackupError::BackupNotFound)?;
+        let path = self.dir.join(name);
+        match path.is_file() {
+            true => Ok(path),
+            false => Err(BackupError::BackupNotFound),
+        }
+    }
+
+    /// It deletes the backups beyond the retention.
+    fn prune(&self) -> Result<(), BackupError> {
+        let backups = self.get_backups()?.backups;
+        for backup in expired_backups(&backups, self.retention) {
+            if let Err(err) = fs::remove_file(self.dir.join(&backup.name)) {
+                warn!("Failed to delete the backup {}: {}", backup.name, err);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,193 @@
+use crate::admission::{AdmissionError, AdmissionService, Operation, ResourceKind};
+use crate::external_api::interface::ActixAppState;
+
+use super::model::BundleDTO;
+use super::service::BundleService;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::generic::problem::Problem;
+use actix_web::{web, Responder, Scope};
+pub struct BundleController {}
+impl BundleController {
+    pub fn services(&self) -> Scope {
+        web::scope("/bundle")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(
+                web::resource("/{namespace}/{bundle_name}")
+                    .route(web::delete().to(BundleController::delete_bundle))
+                    .route(web::get().to(BundleController::bundle))
+                    .route(web::patch().to(BundleController::patch_bundle)),
+            )
+            .service(
+                web::resource("/{namespace}")
+                    .route(web::put().to(BundleController::put_bundle))
+                    .route(web::get().to(BundleController::get_all_bundles)),
+            )
+    }
+
+    /// `bundle` is an async function that handle **/bundle/\<namespace>/<bundle_name>** route (GET)
+    /// # Description:
+    /// * Get a bundle, its version and the resources it owns
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the bundle name.
+    pub async fn bundle(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, bundle_name) = params.into_inner();
+
+        let mut bundle_service =
+            match BundleService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        bundle_service
+            .get_bundle(&bundle_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |b| b.to_http())
+    }
+
+    /// `put_bundle` is an async function that handle **/bundle/\<namespace>** route (PUT)
+    /// # Description:
+    /// * Install a bundle, its workloads pass the admission like the ones created one by one
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - This is the namespace that the bundle will be installed in.
+    /// * `body`: web::Json<BundleDTO> - Contain the version, the workloads and the services of the bundle.
+    pub async fn put_bundle(
+        namespace: web::Path<String>,
+        body: web::Json<BundleDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut bundle_service =
+            match BundleService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_node_port_range(data.node_port_range)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+        let bundle_dto = body.into_inner();
+
+        if let Err(e) =
+            BundleController::admit(&data, &bundle_dto, &namespace, Operation::Create).await
+        {
+            return e.to_http();
+        }
+
+        bundle_service
+            .install(bundle_dto, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |b| b.to_http())
+    }
+
+    /// `get_all_bundles` is an async function that handle **/bundle/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get all bundles installed in the namespace
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the bundles you want to retrieve.
+    /// * `pagination`: Option<web::Query<Pagination>>
+    pub async fn get_all_bundles(
+        namespace: web::Path<String>,
+        pagination: Option<web::Query<Pagination>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut bundle_service =
+            match BundleService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        bundle_service
+            .get_all_bundles(&pagination, &namespace)
+            .await
+            .to_http()
+    }
+
+    /// `patch_bundle` is an async function that handle **/bundle/\<namespace>/<bundle_name>** route (PATCH)
+    /// # Description:
+    /// * Upgrade a bundle to a new version, the resources it no longer lists are deleted
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the bundle name.
+    /// * `body`: web::Json<BundleDTO> - Contain the new version of the bundle.
+    pub async fn patch_bundle(
+        params: web::Path<(String, String)>,
+        body: web::Json<BundleDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, bundle_name) = params.into_inner();
+
+        let mut bundle_service =
+            match BundleService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_node_port_range(data.node_port_range)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+        let bundle_dto = body.into_inner();
+
+        if let Err(e) =
+            BundleController::admit(&data, &bundle_dto, &namespace, Operation::Update).await
+        {
+            return e.to_http();
+        }
+
+        bundle_service
+            .upgrade(bundle_dto, &bundle_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |b| b.to_http())
+    }
+
+    /// `delete_bundle` is an async function that handle **/bundle/\<namespace>/<bundle_name>** route (DELETE)
+    /// # Description:
+    /// * Uninstall a bundle, its services, workloads and their instances are deleted
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the bundle name.
+    pub async fn delete_bundle(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, bundle_name) = params.into_inner();
+
+        let mut bundle_service =
+            match BundleService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service.with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        bundle_service
+            .uninstall(&bundle_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |b| b.to_http())
+    }
+
+    /// Sends each workload of a bundle to the admission webhooks and checks the signature of its
+    /// image, before anything of the bundle is written.
+    async fn admit(
+        data: &ActixAppState,
+        bundle_dto: &BundleDTO,
+        namespace: &str,
+        operation: Operation,
+    ) -> Result<(), AdmissionError> {
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref());
+        for workload_dto in &bundle_dto.workloads {
+            admission
+                .review(
+                    ResourceKind::Workload,
+                    operation,
+                    namespace,
+                    &workload_dto.name,
+                    serde_json::to_value(workload_dto).unwrap_or_default(),
+                )
+                .await?;
+            admission.verify_image(&workload_dto.uri).await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::instance::model::InstanceError;
+use crate::external_api::service::model::{ServiceDTO, ServiceError};
+use crate::external_api::workload::model::{WorkloadDTO, WorkloadError};
+
+/// The label set on the workloads of a bundle, its value is the name of the bundle.
+pub const BUNDLE_LABEL: &str = "bundle";
+
+pub enum BundleError {
+    BundleNotFound,
+    Etcd(String),
+    NameAlreadyExists(String),
+    InvalidBundle(String),
+    NotOwned(String, String),
+    Workload(WorkloadError),
+    Service(ServiceError),
+    Instance(InstanceError),
+    JsonToBundle(String),
+    BundleToJson(String),
+}
+
+impl BundleError {
+    pub fn to_http(&self) -> HttpResponse {
+        let problem = match self {
+            BundleError::BundleNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "bundle_not_found",
+                "Bundle not found",
+            ),
+            BundleError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            BundleError::NameAlreadyExists(name) => Problem::new(
+                StatusCode::CONFLICT,
+                "bundle_already_exists",
+                format!("Bundle with name {} already exists", name),
+            ),
+            BundleError::InvalidBundle(err) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_bundle",
+                format!("Invalid bundle: {}", err),
+            ),
+            BundleError::NotOwned(kind, name) => Problem::new(
+                StatusCode::CONFLICT,
+                "resource_not_owned",
+                format!("The {} {} already exists outside of the bundle", kind, name),
+            ),
+            BundleError::Workload(err) => return err.to_http(),
+            BundleError::Service(err) => return err.to_http(),
+            BundleError::Instance(err) => return err.to_http(),
+            BundleError::JsonToBundle(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_bundle",
+                format!("Error while converting JSON string to bundle : {}", err),
+            ),
+            BundleError::BundleToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "bundle_serialization_failed",
+                format!("Error while converting the bundle to JSON: {}", err),
+            ),
+        };
+        problem.to_http()
+    }
+}
+
+/// A bundle submitted to be installed or upgraded: the workloads and the services of an
+/// application, deployed together under a version.
+#[derive(Deserialize, Serialize)]
+pub struct BundleDTO {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub workloads: Vec<WorkloadDTO>,
+    #[serde(default)]
+    pub services: Vec<ServiceDTO>,
+}
+
+impl BundleDTO {
+    /// Returns an error if the bundle has no name or version, or if it lists a resource twice.
+    pub fn validate(&self) -> Result<(), BundleError> {
+        if self.name.is_empty() || self.version.is_empty() {
+            return Err(BundleError::InvalidBundle(
+                "a bundle must have a name and a version".to_string(),
+            ));
+        }
+        let names = [
+            ("workload", self.workload_names()),
+            ("service", self.service_names()),
+        ];
+        for (kind, names) in names {
+            let mut seen = HashSet::new();
+            if let Some(name) = names.iter().find(|name| !seen.insert(*name)) {
+                return Err(BundleError::InvalidBundle(format!(
+                    "duplicate {} {}",
+                    kind, name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn workload_names(&self) -> Vec<String> {
+        self.workloads
+            .iter()
+            .map(|workload| workload.name.clone())
+            .collect()
+    }
+
+    pub fn service_names(&self) -> Vec<String> {
+        self.services
+            .iter()
+            .map(|service| service.name.clone())
+            .collect()
+    }
+}
+
+/// An installed bundle and the resources it owns, deleted with it.
+///
+/// Properties:
+///
+/// * `version`: The version of the bundle last installed or upgraded.
+/// * `workloads`: The names of the workloads of the bundle, labelled with `bundle`.
+/// * `services`: The names of the services of the bundle.
+/// * `updated_at`: When the version was installed, in seconds since the unix epoch.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Bundle {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+    pub version: String,
+    pub workloads: Vec<String>,
+    pub services: Vec<String>,
+    pub updated_at: u64,
+}
+
+impl Bundle {
+    /// Returns `true` if the bundle owns the resource of `kind` named `name`.
+    pub fn owns(&self, kind: &str, name: &str) -> bool {
+        let owned = match kind {
+            "workload" => &self.workloads,
+            "service" => &self.services,
+            _ => return false,
+        };
+        owned.iter().any(|owned| owned == name)
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => BundleError::BundleToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Returns the names of `owned` missing from `kept`, the resources dropped by an upgrade.
+pub fn removed(owned: &[String], kept: &[String]) -> Vec<String> {
+    owned
+        .iter()
+        .filter(|name| !kept.contains(name))
+        .cloned()
+        .collect()
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BundleVector {
+    pub bundles: Vec<Bundle>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl BundleVector {
+    pub fn new(bundles: Vec<Bundle>) -> BundleVector {
+        BundleVector {
+            bundles,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => BundleError::BundleToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(workloads: &str) -> BundleDTO {
+        serde_json::from_str(&format!(
+            r#"{{"name": "shop", "version": "1.0.0", "workloads": {}}}"#,
+            workloads
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        let web = r#"{"name": "web", "environment": [], "ports": [], "uri": "nginx"}"#;
+        assert!(bundle(&format!("[{}]", web)).validate().is_ok());
+        assert!(matches!(
+            bundle(&format!("[{}, {}]", web, web)).validate(),
+            Err(BundleError::InvalidBundle(_))
+        ));
+
+        let mut unversioned = bundle("[]");
+        unversioned.version = String::new();
+        assert!(unversioned.validate().is_err());
+    }
+
+    #[test]
+    fn test_removed() {
+        let owned = vec!["web".to_string(), "db".to_string()];
+        assert_eq!(removed(&owned, &["web".to_string()]), vec!["db"]);
+        assert!(removed(&owned, &owned).is_empty());
+    }
+}
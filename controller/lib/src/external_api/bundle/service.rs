@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use log::{info, warn};
+
+use super::model::{removed, Bundle, BundleDTO, BundleError, BundleVector, BUNDLE_LABEL};
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::service::model::{NodePortRange, ServiceError};
+use crate::external_api::service::service::ServiceService;
+use crate::external_api::workload::cache::WorkloadCache;
+use crate::external_api::workload::model::{WorkloadDTO, WorkloadError};
+use crate::external_api::workload::service::WorkloadService;
+
+/// `BundleService` installs, upgrades and uninstalls the bundles, the applications made of
+/// workloads and services deployed as a single versioned unit. A bundle owns the resources it
+/// created: they are updated by its upgrades and deleted with it, and it never takes over a
+/// resource created outside of it.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `workload_service`: This is the service used to write the workloads of the bundles.
+/// * `service_service`: This is the service used to write the services of the bundles.
+/// * `instance_service`: This is the service used to destroy the instances of the workloads
+///   removed from a bundle.
+pub struct BundleService {
+    etcd_service: EtcdClient,
+    workload_service: WorkloadService,
+    service_service: ServiceService,
+    instance_service: InstanceService,
+}
+
+impl BundleService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<BundleService, BundleError> {
+        Ok(BundleService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| BundleError::Etcd(err.to_string()))?,
+            workload_service: WorkloadService::new(etcd_address)
+                .await
+                .map_err(BundleError::Workload)?,
+            service_service: ServiceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(BundleError::Service)?,
+            instance_service: InstanceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(BundleError::Instance)?,
+        })
+    }
+
+    pub fn with_node_port_range(mut self, node_port_range: NodePortRange) -> Self {
+        self.service_service = self.service_service.with_node_port_range(node_port_range);
+        self
+    }
+
+    pub fn with_workload_cache(mut self, cache: &WorkloadCache) -> Self {
+        self.workload_service = self.workload_service.with_cache(cache);
+        self
+    }
+
+    pub async fn get_bundle(
+        &mut self,
+        bundle_name: &str,
+        namespace: &str,
+    ) -> Result<Bundle, BundleError> {
+        match self
+            .etcd_service
+            .get(&self.id(bundle_name, namespace))
+            .await
+        {
+            Some(bundle) => serde_json::from_str(&bundle)
+                .map_err(|err| BundleError::JsonToBundle(err.to_string())),
+            None => Err(BundleError::BundleNotFound),
+        }
+    }
+
+    /// This function gets the bundles of a namespace, paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    pub async fn get_all_bundles(
+        &mut self,
+        pagination: &Pagination,
+        namespace: &str,
+    ) -> BundleVector {
+        match self
+            .etcd_service
+            .list_prefix(
+                &self.id("", namespace),
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |bundle: &Bundle| bundle.namespace == namespace,
+            )
+            .await
+        {
+            Ok(listing) => {
+                BundleVector::new(listing.items).with_continue_token(listing.continue_token)
+            }
+            Err(_) => BundleVector::new(vec![]),
+        }
+    }
+
+    /// It installs a bundle: its workloads are created, then its services. Nothing is written if
+    /// one of its resources already exists, and the resources already created are deleted if the
+    /// creation of another one fails.
+    pub async fn install(
+        &mut self,
+        bundle_dto: BundleDTO,
+        namespace: &str,
+    ) -> Result<Bundle, BundleError> {
+        bundle_dto.validate()?;
+        match self.get_bundle(&bundle_dto.name, namespace).await {
+            Ok(bundle) => return Err(BundleError::NameAlreadyExists(bundle.name)),
+            Err(BundleError::BundleNotFound) => {}
+            Err(err) => return Err(err),
+        }
+
+        let mut bundle = Bundle {
+            id: self.id(&bundle_dto.name, namespace),
+            name: bundle_dto.name.clone(),
+            namespace: namespace.to_string(),
+            version: bundle_dto.version.clone(),
+            workloads: vec![],
+            services: vec![],
+            updated_at: unix_time(),
+        };
+        let existing = self.existing_resources(&bundle_dto, namespace).await?;
+        self.check_ownership(&bundle, &existing)?;
+
+        if let Err(err) = self.apply(&mut bundle, bundle_dto, &existing).await {
+            // the bundle is installed entirely or not at all
+            let (workloads, services) = (bundle.workloads.clone(), bundle.services.clone());
+            self.remove_resources(&bundle, &workloads, &services).await;
+            return Err(err);
+        }
+        self.put_bundle(&bundle).await?;
+        info!(
+            "Bundle {} {} installed in namespace {}",
+            bundle.name, bundle.version, namespace
+        );
+        Ok(bundle)
+    }
+
+    /// It upgrades a bundle to a new version: the resources it owns are updated, the new ones
+    /// created and the ones it no longer lists deleted, with the instances of the deleted
+    /// workloads. Nothing is written if a new resource already exists outside of the bundle.
+    pub async fn upgrade(
+        &mut self,
+        bundle_dto: BundleDTO,
+        bundle_name: &str,
+        namespace: &str,
+    ) -> Result<Bundle, BundleError> {
+        bundle_dto.validate()?;
+        let mut bundle = self.get_bundle(bundle_name, namespace).await?;
+        if bundle_dto.name != bundle.name {
+            return Err(BundleError::InvalidBundle(
+                "the name of a bundle can't change".to_string(),
+            ));
+        }
+        let existing = self.existing_resources(&bundle_dto, namespace).await?;
+        self.check_ownership(&bundle, &existing)?;
+
+        let dropped_workloads = removed(&bundle.workloads, &bundle_dto.workload_names());
+        let dropped_services = removed(&bundle.services, &bundle_dto.service_names());
+        let version = bundle_dto.version.clone();
+        let result = self.apply(&mut bundle, bundle_dto, &existing).await;
+        if result.is_ok() {
+            self.remove_resources(&bundle, &dropped_workloads, &dropped_services)
+                .await;
+            bundle
+                .workloads
+                .retain(|name| !dropped_workloads.contains(name));
+            bundle
+                .services
+                .retain(|name| !dropped_services.contains(name));
+            bundle.version = version;
+            bundle.updated_at = unix_time();
+        }
+        // the resources created before a failure are owned, an upgrade or an uninstall finds them
+        self.put_bundle(&bundle).await?;
+        result?;
+
+        info!(
+            "Bundle {} upgraded to {} in namespace {}",
+            bundle.name, bundle.version, namespace
+        );
+        Ok(bundle)
+    }
+
+    /// It uninstalls a bundle: its services are deleted, then the instances of its workloads and
+    /// its workloads. Uninstalling it again resumes an interrupted uninstall.
+    pub async fn uninstall(
+        &mut self,
+        bundle_name: &str,
+        namespace: &str,
+    ) -> Result<Bundle, BundleError> {
+        let bundle = self.get_bundle(bundle_name, namespace).await?;
+        let (workloads, services) = (bundle.workloads.clone(), bundle.services.clone());
+        self.remove_resources(&bundle, &workloads, &services).await;
+        _ = self.etcd_service.delete(&bundle.id).await;
+        info!(
+            "Bundle {} {} uninstalled from namespace {}",
+            bundle.name, bundle.version, namespace
+        );
+        Ok(bundle)
+    }
+
+    /// It deletes the record of a bundle, leaving the resources it owns. Used when they are
+    /// deleted on their own, e.g. with their namespace.
+    pub async fn delete_bundle(
+        &mut self,
+        bundle_name: &str,
+        namespace: &str,
+    ) -> Result<(), BundleError> {
+        let id = self.id(bundle_name, namespace);
+        self.etcd_service
+            .delete_checked(&id)
+            .await
+            .map(|_| ())
+            .map_err(|err| BundleError::Etcd(err.to_string()))
+    }
+
+    /// Returns the resources of a bundle which already exist, as `(kind, name)`.
+    async fn existing_resources(
+        &mut self,
+        bundle_dto: &BundleDTO,
+        namespace: &str,
+    ) -> Result<HashSet<(&'static str, String)>, BundleError> {
+        let mut existing = HashSet::new();
+        for name in bundle_dto.workload_names() {
+            match self.workload_service.get_workload(&name, namespace).await {
+                Ok(_) => _ = existing.insert(("workload", name)),
+                Err(WorkloadError::WorkloadNotFound) => {}
+                Err(err) => return Err(BundleError::Workload(err)),
+            }
+        }
+        for name in bundle_dto.service_names() {
+            match self.service_service.get_service(&name, namespace).await {
+                Ok(_) => _ = existing.insert(("service", name)),
+                Err(ServiceError::ServiceNotFound) => {}
+                Err(err) => return Err(BundleError::Service(err)),
+            }
+        }
+        Ok(existing)
+    }
+
+    /// Returns an error if a resource which already exists isn't owned by the bundle.
+    fn check_ownership(
+        &self,
+        bundle: &Bundle,
+        existing: &HashSet<(&'static str, String)>,
+    ) -> Result<(), BundleError> {
+        match existing
+            .iter()
+            .find(|(kind, name)| !bundle.owns(kind, name))
+        {
+            Some((kind, name)) => Err(BundleError::NotOwned(kind.to_string(), name.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates or updates the workloads then the services of a bundle, each resource created
+    /// being added to the ones the bundle owns.
+    async fn apply(
+        &mut self,
+        bundle: &mut Bundle,
+        bundle_dto: BundleDTO,
+        existing: &HashSet<(&'static str, String)>,
+    ) -> Result<(), BundleError> {
+        let namespace = bundle.namespace.clone();
+        for mut workload_dto in bundle_dto.workloads {
+            workload_dto
+                .labels
+                .insert(BUNDLE_LABEL.to_string(), bundle.name.clone());
+            let name = workload_dto.name.clone();
+            if existing.contains(&("workload", name.clone())) {
+                self.update_workload(workload_dto, &namespace).await?;
+            } else {
+                self.workload_service
+                    .create_workload(workload_dto, &namespace)
+                    .await
+                    .map_err(BundleError::Workload)?;
+                bundle.workloads.push(name);
+            }
+        }
+        for service_dto in bundle_dto.services {
+            let name = service_dto.name.clone();
+            if existing.contains(&("service", name.clone())) {
+                self.service_service
+                    .update_service(service_dto, &name, &namespace)
+                    .await
+                    .map_err(BundleError::Service)?;
+            } else {
+                self.service_service
+                    .create_service(service_dto, &namespace)
+                    .await
+                    .map_err(BundleError::Service)?;
+                bundle.services.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_workload(
+        &mut self,
+        workload_dto: WorkloadDTO,
+        namespace: &str,
+    ) -> Result<(), BundleError> {
+        let name = workload_dto.name.clone();
+        self.workload_service
+            .update_workload(workload_dto, &name, namespace)
+            .await
+            .map_err(BundleError::Workload)?;
+        Ok(())
+    }
+
+    /// Deletes resources of a bundle, the services first then the instances of the workloads and
+    /// the workloads, so that no service is left pointing to a deleted workload. The failures are
+    /// logged, the resources left behind are still owned by the bundle.
+    async fn remove_resources(
+        &mut self,
+        bundle: &Bundle,
+        workloads: &[String],
+        services: &[String],
+    ) {
+        let namespace = &bundle.namespace;
+        for name in services {
+            if self
+                .service_service
+                .delete_service(name, namespace)
+                .await
+                .is_err()
+            {
+                warn!("Bundle {}: failed to delete service {}", bundle.name, name);
+            }
+        }
+        for name in workloads {
+            let Ok(workload) = self.workload_service.get_workload(name, namespace).await else {
+                continue;
+            };
+            for instance in self
+                .instance_service
+                .get_instances_of_workload(&workload)
+                .await
+            {
+                if self
+                    .instance_service
+                    .delete_instance(&instance.id, namespace)
+                    .await
+                    .is_err()
+                {
+                    warn!(
+                        "Bundle {}: failed to delete instance {}",
+                        bundle.name, instance.id
+                    );
+                }
+            }
+            self.workload_service.delete_workload(name, namespace).await;
+        }
+    }
+
+    /// It stores a bundle in etcd.
+    async fn put_bundle(&mut self, bundle: &Bundle) -> Result<(), BundleError> {
+        let json = serde_json::to_string(bundle)
+            .map_err(|err| BundleError::BundleToJson(err.to_string()))?;
+        self.etcd_service
+            .put(&bundle.id, &json)
+            .await
+            .map_err(|err| BundleError::Etcd(err.to_string()))?;
+        Ok(())
+    }
+
+    pub fn id(&self, name: &str, namespace: &str) -> String {
+        format!("bundle.{}.{}", namespace, name)
+    }
+}
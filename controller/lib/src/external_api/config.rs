@@ -0,0 +1,88 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use image_policy::SignaturePolicy;
+use serde::{Deserialize, Serialize};
+
+use super::middleware::cors::CorsConfig;
+use super::middleware::rate_limit::RateLimitConfig;
+use super::service::model::NodePortRange;
+use crate::admission::AdmissionWebhook;
+use crate::backup::BackupConfig;
+
+/// `ExternalAPIConfig` is the configuration of the HTTP API of the controller and of the
+/// components it calls.
+///
+/// Properties:
+///
+/// * `http_server_addr`: The address the HTTP server is bound to.
+/// * `http_server_num_workers`: The number of HTTP workers.
+/// * `etcd_address`: The address of etcd, where the resources are stored.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `admission_webhooks`: The webhooks reviewing the mutations of the resources.
+/// * `image_signature`: The keys the images of the workloads must be signed with, any image is
+///   accepted if empty.
+/// * `rate_limit`: The limit of requests per client, no limit if empty.
+/// * `cors`: The cross-origin policy, cross-origin requests are rejected if empty.
+/// * `node_port_range`: The range in which the node ports of the `NodePort` services are
+///   allocated.
+/// * `request_timeout_seconds`: How long a request is handled before the client is answered a
+///   `504 Gateway Timeout` and the work it started is canceled, no deadline if 0.
+/// * `shutdown_timeout_seconds`: How long the in-flight requests, then the background tasks, are
+///   awaited on shutdown.
+/// * `backup`: Where the snapshots of etcd are stored and how often they are taken.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExternalAPIConfig {
+    pub http_server_addr: SocketAddr,
+    pub http_server_num_workers: usize,
+    pub etcd_address: SocketAddr,
+    pub scheduler_address: SocketAddr,
+    #[serde(default)]
+    pub admission_webhooks: Vec<AdmissionWebhook>,
+    #[serde(default)]
+    pub image_signature: Option<SignaturePolicy>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    pub cors: Option<CorsConfig>,
+    #[serde(default)]
+    pub node_port_range: NodePortRange,
+    #[serde(default = "default_request_timeout_seconds")]
+    pub request_timeout_seconds: u64,
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub shutdown_timeout_seconds: u64,
+    #[serde(default)]
+    pub backup: BackupConfig,
+}
+
+fn default_request_timeout_seconds() -> u64 {
+    60
+}
+
+fn default_shutdown_timeout_seconds() -> u64 {
+    30
+}
+
+impl Default for ExternalAPIConfig {
+    fn default() -> Self {
+        ExternalAPIConfig {
+            http_server_addr: SocketAddr::new(
+                std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                3000,
+            ),
+            http_server_num_workers: 1,
+            etcd_address: SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 2379),
+            scheduler_address: SocketAddr::new(
+                std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                50052,
+            ),
+            admission_webhooks: vec![],
+            image_signature: None,
+            rate_limit: None,
+            cors: None,
+            node_port_range: NodePortRange::default(),
+            request_timeout_seconds: default_request_timeout_seconds(),
+            shutdown_timeout_seconds: default_shutdown_timeout_seconds(),
+            backup: BackupConfig::default(),
+        }
+    }
+}
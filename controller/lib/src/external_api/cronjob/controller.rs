@@ -0,0 +1,182 @@
+use crate::admission::{AdmissionError, AdmissionService, Operation, ResourceKind};
+use crate::external_api::interface::ActixAppState;
+
+use super::model::CronJobDTO;
+use super::service::CronJobService;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::generic::problem::Problem;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, Scope};
+/// Submits the job template of a cron job to the admission webhooks and checks its image, as
+/// the workloads created from it won't be.
+async fn admit_job_template(
+    admission: &AdmissionService,
+    namespace: &str,
+    cronjob_dto: &CronJobDTO,
+) -> Result<(), AdmissionError> {
+    let job = cronjob_dto
+        .job_template
+        .to_workload_dto(cronjob_dto.name.clone(), &cronjob_dto.name);
+    admission
+        .review(
+            ResourceKind::Workload,
+            Operation::Create,
+            namespace,
+            &cronjob_dto.name,
+            serde_json::to_value(&job).unwrap_or_default(),
+        )
+        .await?;
+    admission.verify_image(&job.uri).await
+}
+
+pub struct CronJobController {}
+impl CronJobController {
+    pub fn services(&self) -> Scope {
+        web::scope("/cronjob")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(
+                web::resource("/{namespace}/{cronjob_name}")
+                    .route(web::delete().to(CronJobController::delete_cronjob))
+                    .route(web::get().to(CronJobController::cronjob))
+                    .route(web::patch().to(CronJobController::patch_cronjob)),
+            )
+            .service(
+                web::resource("/{namespace}")
+                    .route(web::put().to(CronJobController::put_cronjob))
+                    .route(web::get().to(CronJobController::get_all_cronjobs)),
+            )
+    }
+
+    /// `cronjob` is an async function that handle **/cronjob/\<namespace>/<cronjob_name>** route (GET)
+    /// # Description:
+    /// * Get a cron job
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the cron job name.
+    pub async fn cronjob(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, cronjob_name) = params.into_inner();
+
+        let mut cronjob_service = match CronJobService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        cronjob_service
+            .get_versioned_cronjob(&cronjob_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |c| c.to_http())
+    }
+
+    /// `put_cronjob` is an async function that handle **/cronjob/\<namespace>** route (PUT)
+    /// # Description:
+    /// * Create a new cron job, its job template must pass the admission webhooks and the signature policy
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - This is the namespace that the cron job will be created in.
+    /// * `body`: web::Json<CronJobDTO> - Contain the schedule and the job template of the cron job.
+    pub async fn put_cronjob(
+        namespace: web::Path<String>,
+        body: web::Json<CronJobDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut cronjob_service = match CronJobService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+        let cronjob_dto = body.into_inner();
+
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref());
+        if let Err(e) = admit_job_template(&admission, &namespace, &cronjob_dto).await {
+            return e.to_http();
+        }
+
+        cronjob_service
+            .create_cronjob(cronjob_dto, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |c| c.to_http())
+    }
+
+    /// `get_all_cronjobs` is an async function that handle **/cronjob/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get all cron jobs in the namespace
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the cron jobs you want to retrieve.
+    /// * `pagination`: Option<web::Query<Pagination>>
+    pub async fn get_all_cronjobs(
+        namespace: web::Path<String>,
+        pagination: Option<web::Query<Pagination>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut cronjob_service = match CronJobService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        cronjob_service
+            .get_all_cronjobs(&pagination, Some(&namespace))
+            .await
+            .to_http()
+    }
+
+    /// `patch_cronjob` is an async function that handle **/cronjob/\<namespace>/<cronjob_name>** route (PATCH)
+    /// # Description:
+    /// * Replace the schedule, the policies and the job template of a cron job
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the cron job name.
+    /// * `body`: web::Json<CronJobDTO> - Contain the new specification of the cron job.
+    pub async fn patch_cronjob(
+        params: web::Path<(String, String)>,
+        body: web::Json<CronJobDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, cronjob_name) = params.into_inner();
+
+        let mut cronjob_service = match CronJobService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+        let cronjob_dto = body.into_inner();
+
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref());
+        if let Err(e) = admit_job_template(&admission, &namespace, &cronjob_dto).await {
+            return e.to_http();
+        }
+
+        cronjob_service
+            .update_cronjob(cronjob_dto, &cronjob_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |c| c.to_http())
+    }
+
+    /// `delete_cronjob` is an async function that handle **/cronjob/\<namespace>/<cronjob_name>** route (DELETE)
+    /// # Description:
+    /// * Delete a cron job, the jobs it created are kept
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the cron job name.
+    pub async fn delete_cronjob(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, cronjob_name) = params.into_inner();
+
+        let mut cronjob_service = match CronJobService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        cronjob_service
+            .delete_cronjob(&cronjob_name, &namespace)
+            .await;
+        HttpResponse::build(StatusCode::NO_CONTENT).body("Remove successfully")
+    }
+}
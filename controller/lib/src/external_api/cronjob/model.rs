@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::model::version_conflict;
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::workload::model::{
+    JobSpec, Ports, Ressources, SecurityContext, WorkloadDTO, WorkloadKind,
+};
+
+/// The label set on the jobs created by a cron job, its value is the name of the cron job.
+pub const CRONJOB_LABEL: &str = "cronjob";
+
+pub enum CronJobError {
+    CronJobNotFound,
+    Etcd(String),
+    NameAlreadyExists(String),
+    InvalidSchedule(String),
+    VersionConflict(String, i64),
+    JsonToCronJob(String),
+    CronJobToJson(String),
+}
+
+impl CronJobError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            CronJobError::CronJobNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "cronjob_not_found",
+                "Cron job not found",
+            ),
+            CronJobError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            CronJobError::NameAlreadyExists(name) => Problem::new(
+                StatusCode::CONFLICT,
+                "cronjob_already_exists",
+                format!("Cron job with name {} already exists", name),
+            ),
+            CronJobError::InvalidSchedule(err) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_schedule",
+                format!("Invalid schedule: {}", err),
+            ),
+            CronJobError::VersionConflict(name, version) => {
+                version_conflict("cron job", name, *version)
+            }
+            CronJobError::JsonToCronJob(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_cronjob",
+                format!("Error while converting JSON string to cron job : {}", err),
+            ),
+            CronJobError::CronJobToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "cronjob_serialization_failed",
+                format!("Error while converting the cron job to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// What the cron job controller does when a run is due while a previous job is still active.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConcurrencyPolicy {
+    /// The jobs run concurrently
+    #[default]
+    Allow,
+    /// The run is skipped
+    Forbid,
+    /// The active jobs are deleted before the new one is created
+    Replace,
+}
+
+/// The workload created for each run of a cron job, always a `Job`.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct JobTemplate {
+    pub uri: String,
+    #[serde(default)]
+    pub environment: Vec<String>,
+    #[serde(default)]
+    pub ports: Vec<Ports>,
+    #[serde(default)]
+    pub resources: Ressources,
+    /// Labels of the jobs, the `cronjob` label is added by the controller
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub ttl_seconds_after_finished: Option<u64>,
+    #[serde(default)]
+    pub security_context: SecurityContext,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobSpec>,
+}
+
+impl JobTemplate {
+    /// Returns the job named `name` created by the cron job `cronjob_name`.
+    pub fn to_workload_dto(&self, name: String, cronjob_name: &str) -> WorkloadDTO {
+        let mut labels = self.labels.clone();
+        labels.insert(CRONJOB_LABEL.to_string(), cronjob_name.to_string());
+
+        WorkloadDTO {
+            name,
+            environment: self.environment.clone(),
+            ports: self.ports.clone(),
+            uri: self.uri.clone(),
+            workload_type: Default::default(),
+            resources: self.resources.clone(),
+            labels,
+            ttl_seconds_after_finished: self.ttl_seconds_after_finished,
+            security_context: self.security_context.clone(),
+            kind: WorkloadKind::Job,
+            job: self.job.clone(),
+            stateful: None,
+            canary_percentage: None,
+            depends_on: vec![],
+            sidecars: vec![],
+            spread: None,
+            constraint: None,
+            pool: None,
+            disruption_budget: None,
+            devices: HashMap::new(),
+            cpu_policy: Default::default(),
+            huge_pages: HashMap::new(),
+            platforms: vec![],
+            resource_version: None,
+        }
+    }
+}
+
+fn default_successful_jobs_history_limit() -> usize {
+    3
+}
+
+fn default_failed_jobs_history_limit() -> usize {
+    1
+}
+
+/// A `CronJob` creates a `Job` workload from its template each time its schedule fires. The
+/// runs missed while the controller was down are not caught up, only the latest one is run.
+///
+/// Properties:
+///
+/// * `schedule`: The cron expression, evaluated in UTC.
+/// * `concurrency_policy`: What to do when a run is due while a previous job is active.
+/// * `successful_jobs_history_limit`: The number of completed jobs kept.
+/// * `failed_jobs_history_limit`: The number of failed jobs kept.
+/// * `suspend`: No job is created while set, the runs missed are skipped.
+/// * `job_template`: The job created for each run.
+/// * `created_at`: When the cron job was created, in seconds since the unix epoch.
+/// * `last_schedule_time`: The scheduled time of the latest run, in seconds since the unix epoch.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CronJob {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+    pub schedule: String,
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    #[serde(default = "default_successful_jobs_history_limit")]
+    pub successful_jobs_history_limit: usize,
+    #[serde(default = "default_failed_jobs_history_limit")]
+    pub failed_jobs_history_limit: usize,
+    #[serde(default)]
+    pub suspend: bool,
+    pub job_template: JobTemplate,
+    pub created_at: u64,
+    #[serde(default)]
+    pub last_schedule_time: Option<u64>,
+}
+
+impl CronJob {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => CronJobError::CronJobToJson(err.to_string()).to_http(),
+        }
+    }
+
+    /// Returns the name of the job created for the run scheduled at `scheduled_time`.
+    pub fn job_name(&self, scheduled_time: u64) -> String {
+        format!("{}-{}", self.name, scheduled_time)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CronJobDTO {
+    pub name: String,
+    pub schedule: String,
+    #[serde(default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    #[serde(default = "default_successful_jobs_history_limit")]
+    pub successful_jobs_history_limit: usize,
+    #[serde(default = "default_failed_jobs_history_limit")]
+    pub failed_jobs_history_limit: usize,
+    #[serde(default)]
+    pub suspend: bool,
+    pub job_template: JobTemplate,
+    /// If set, the update fails if the cron job was modified since this version
+    #[serde(default, skip_serializing)]
+    pub resource_version: Option<i64>,
+}
+
+impl From<CronJob> for CronJobDTO {
+    fn from(cronjob: CronJob) -> Self {
+        CronJobDTO {
+            name: cronjob.name,
+            schedule: cronjob.schedule,
+            concurrency_policy: cronjob.concurrency_policy,
+            successful_jobs_history_limit: cronjob.successful_jobs_history_limit,
+            failed_jobs_history_limit: cronjob.failed_jobs_history_limit,
+            suspend: cronjob.suspend,
+            job_template: cronjob.job_template,
+            resource_version: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CronJobVector {
+    pub cronjobs: Vec<CronJob>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl CronJobVector {
+    pub fn new(cronjobs: Vec<CronJob>) -> CronJobVector {
+        CronJobVector {
+            cronjobs,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => CronJobError::CronJobToJson(err.to_string()).to_http(),
+        }
+    }
+}
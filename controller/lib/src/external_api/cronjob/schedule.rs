@@ -0,0 +1,206 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+/// How far `next_after` looks for a matching minute, a schedule like `0 0 30 2 *` never fires.
+const SEARCH_DAYS: i64 = 5 * 366;
+
+/// A parsed cron expression, evaluated in UTC.
+///
+/// The expression has five fields: minute (0-59), hour (0-23), day of month (1-31), month
+/// (1-12) and day of week (0-7, 0 and 7 are Sunday). Each field is a comma separated list of
+/// `*`, values, ranges `a-b` and steps `*/n`, `a-b/n` or `a/n`. The macros `@yearly`,
+/// `@annually`, `@monthly`, `@weekly`, `@daily`, `@midnight` and `@hourly` are also accepted.
+/// When both the day of month and the day of week are restricted, a day matching either of them
+/// matches, as in the standard cron.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+
+impl CronSchedule {
+    /// Parses a cron expression, the error describes the invalid field.
+    pub fn parse(expression: &str) -> Result<CronSchedule, String> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "expected 5 fields in the cron expression, got {}",
+                fields.len()
+            ));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7, "day of week")?;
+        // 7 is another name of Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week | 1) & !(1 << 7);
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59, "minute")?,
+            hours: parse_field(fields[1], 0, 23, "hour")?,
+            days_of_month: parse_field(fields[2], 1, 31, "day of month")?,
+            months: parse_field(fields[3], 1, 12, "month")?,
+            days_of_week,
+            day_of_month_restricted: !fields[2].starts_with('*'),
+            day_of_week_restricted: !fields[4].starts_with('*'),
+        })
+    }
+
+    /// Returns the first time matching the schedule strictly after `after`, in seconds since the
+    /// unix epoch, or `None` if the schedule doesn't fire in the next years.
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let after = i64::try_from(after).ok()?;
+        // the schedule has a minute precision, the search starts at the next minute
+        let mut time = NaiveDateTime::from_timestamp_opt(after - after % 60 + 60, 0)?;
+        let limit = time + Duration::days(SEARCH_DAYS);
+
+        while time < limit {
+            if !contains(self.months, time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.matches_day(time.date()) {
+                time = time.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !contains(self.hours, time.hour()) {
+                time = time.date().and_hms_opt(time.hour(), 0, 0)? + Duration::hours(1);
+                continue;
+            }
+            if !contains(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+                continue;
+            }
+            return u64::try_from(time.timestamp()).ok();
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day_of_month = contains(self.days_of_month, date.day());
+        let day_of_week = contains(self.days_of_week, date.weekday().num_days_from_sunday());
+        if self.day_of_month_restricted && self.day_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+}
+
+fn contains(set: u64, value: u32) -> bool {
+    set & (1 << value) != 0
+}
+
+/// Parses a field of a cron expression to the set of its values, as a bit mask.
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid {} field: {}", name, field);
+    let parse = |value: &str| -> Result<u32, String> {
+        let value = value.parse::<u32>().map_err(|_| invalid())?;
+        if value < min || value > max {
+            return Err(format!(
+                "{} {} is out of the range {}-{}",
+                name, value, min, max
+            ));
+        }
+        Ok(value)
+    };
+
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().map_err(|_| invalid())?)),
+            None => (part, None),
+        };
+        if step == Some(0) {
+            return Err(invalid());
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse(start)?, parse(end)?)
+        } else {
+            let value = parse(range)?;
+            // `a/n` runs from `a` to the end of the range
+            (value, if step.is_some() { max } else { value })
+        };
+        if start > end {
+            return Err(invalid());
+        }
+
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the unix time of a UTC date.
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> u64 {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    fn next(expression: &str, after: u64) -> Option<u64> {
+        CronSchedule::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* * 0 * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("5-1 * * * *").is_err());
+        assert!(CronSchedule::parse("@often").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        let start = at(2022, 10, 14, 10, 7);
+
+        assert_eq!(next("* * * * *", start), Some(at(2022, 10, 14, 10, 8)));
+        assert_eq!(next("*/15 * * * *", start), Some(at(2022, 10, 14, 10, 15)));
+        assert_eq!(next("0 9-17/4 * * *", start), Some(at(2022, 10, 14, 13, 0)));
+        assert_eq!(next("30 2 1,15 * *", start), Some(at(2022, 10, 15, 2, 30)));
+        assert_eq!(next("@monthly", start), Some(at(2022, 11, 1, 0, 0)));
+        assert_eq!(next("@yearly", start), Some(at(2023, 1, 1, 0, 0)));
+        // 2022-10-14 is a Friday, 7 is Sunday
+        assert_eq!(next("0 0 * * 7", start), Some(at(2022, 10, 16, 0, 0)));
+        // the first of the month or a Monday
+        assert_eq!(next("0 0 1 * 1", start), Some(at(2022, 10, 17, 0, 0)));
+        // February 29th
+        assert_eq!(next("0 0 29 2 *", start), Some(at(2024, 2, 29, 0, 0)));
+        assert_eq!(next("0 0 30 2 *", start), None);
+    }
+
+    #[test]
+    fn test_next_after_is_strict() {
+        let start = at(2022, 10, 14, 10, 0);
+        assert_eq!(next("0 * * * *", start), Some(at(2022, 10, 14, 11, 0)));
+        assert_eq!(next("0 * * * *", start + 30), Some(at(2022, 10, 14, 11, 0)));
+    }
+}
@@ -0,0 +1,168 @@
+use std::net::SocketAddr;
+
+use super::model::{CronJob, CronJobDTO, CronJobError, CronJobVector};
+use super::schedule::CronSchedule;
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::{Pagination, Versioned};
+use crate::external_api::instance::service::unix_time;
+
+/// `CronJobService` is the service used by the `CronJobController` and the cron job controller
+/// loop to store cron jobs in etcd.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+pub struct CronJobService {
+    etcd_service: EtcdClient,
+}
+
+impl CronJobService {
+    pub async fn new(etcd_address: &SocketAddr) -> Result<CronJobService, CronJobError> {
+        Ok(CronJobService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| CronJobError::Etcd(err.to_string()))?,
+        })
+    }
+
+    pub async fn get_cronjob(
+        &mut self,
+        cronjob_name: &str,
+        namespace: &str,
+    ) -> Result<CronJob, CronJobError> {
+        let id = self.id(cronjob_name, namespace);
+        match self.etcd_service.get(&id).await {
+            Some(cronjob) => serde_json::from_str(&cronjob)
+                .map_err(|err| CronJobError::JsonToCronJob(err.to_string())),
+            None => Err(CronJobError::CronJobNotFound),
+        }
+    }
+
+    /// Reads a cron job and its version.
+    pub async fn get_versioned_cronjob(
+        &mut self,
+        cronjob_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<CronJob>, CronJobError> {
+        let id = self.id(cronjob_name, namespace);
+        match self.etcd_service.get_versioned(&id).await {
+            Some((cronjob, version)) => serde_json::from_str(&cronjob)
+                .map(|cronjob| Versioned::new(cronjob, version))
+                .map_err(|err| CronJobError::JsonToCronJob(err.to_string())),
+            None => Err(CronJobError::CronJobNotFound),
+        }
+    }
+
+    /// This function gets the cron jobs of a namespace, or of every namespace if `namespace` is
+    /// `None`, paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    pub async fn get_all_cronjobs(
+        &mut self,
+        pagination: &Pagination,
+        namespace: Option<&str>,
+    ) -> CronJobVector {
+        let prefix = match namespace {
+            Some(namespace) => self.id("", namespace),
+            None => "cronjob.".to_string(),
+        };
+        match self
+            .etcd_service
+            .list_prefix(
+                &prefix,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |_: &CronJob| true,
+            )
+            .await
+        {
+            Ok(listing) => {
+                CronJobVector::new(listing.items).with_continue_token(listing.continue_token)
+            }
+            Err(_) => CronJobVector::new(vec![]),
+        }
+    }
+
+    pub async fn create_cronjob(
+        &mut self,
+        cronjob_dto: CronJobDTO,
+        namespace: &str,
+    ) -> Result<CronJob, CronJobError> {
+        CronSchedule::parse(&cronjob_dto.schedule).map_err(CronJobError::InvalidSchedule)?;
+        match self.get_cronjob(&cronjob_dto.name, namespace).await {
+            Ok(cronjob) => return Err(CronJobError::NameAlreadyExists(cronjob.name)),
+            Err(CronJobError::CronJobNotFound) => {}
+            Err(err) => return Err(err),
+        }
+
+        let cronjob = CronJob {
+            id: self.id(&cronjob_dto.name, namespace),
+            name: cronjob_dto.name,
+            namespace: namespace.to_string(),
+            schedule: cronjob_dto.schedule,
+            concurrency_policy: cronjob_dto.concurrency_policy,
+            successful_jobs_history_limit: cronjob_dto.successful_jobs_history_limit,
+            failed_jobs_history_limit: cronjob_dto.failed_jobs_history_limit,
+            suspend: cronjob_dto.suspend,
+            job_template: cronjob_dto.job_template,
+            created_at: unix_time(),
+            last_schedule_time: None,
+        };
+        self.put_cronjob(&cronjob).await?;
+        Ok(cronjob)
+    }
+
+    /// It replaces the specification of a cron job, the name and the time of the latest run are
+    /// kept. If `resource_version` is set, only if the cron job wasn't modified since, its runs
+    /// included.
+    pub async fn update_cronjob(
+        &mut self,
+        cronjob_dto: CronJobDTO,
+        cronjob_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<CronJob>, CronJobError> {
+        CronSchedule::parse(&cronjob_dto.schedule).map_err(CronJobError::InvalidSchedule)?;
+        let mut cronjob = self.get_cronjob(cronjob_name, namespace).await?;
+        cronjob.schedule = cronjob_dto.schedule;
+        cronjob.concurrency_policy = cronjob_dto.concurrency_policy;
+        cronjob.successful_jobs_history_limit = cronjob_dto.successful_jobs_history_limit;
+        cronjob.failed_jobs_history_limit = cronjob_dto.failed_jobs_history_limit;
+        cronjob.suspend = cronjob_dto.suspend;
+        cronjob.job_template = cronjob_dto.job_template;
+        let version = self
+            .put_cronjob_if(&cronjob, cronjob_dto.resource_version)
+            .await?;
+        Ok(Versioned::new(cronjob, version))
+    }
+
+    /// It deletes a cron job, the jobs it created are kept.
+    pub async fn delete_cronjob(&mut self, cronjob_name: &str, namespace: &str) {
+        let id = self.id(cronjob_name, namespace);
+        _ = self.etcd_service.delete(&id).await;
+    }
+
+    pub async fn put_cronjob(&mut self, cronjob: &CronJob) -> Result<(), CronJobError> {
+        self.put_cronjob_if(cronjob, None).await.map(|_| ())
+    }
+
+    /// Stores a cron job, only if it is still at the version `expected` if set. Returns the
+    /// version of the cron job stored.
+    async fn put_cronjob_if(
+        &mut self,
+        cronjob: &CronJob,
+        expected: Option<i64>,
+    ) -> Result<i64, CronJobError> {
+        let json = serde_json::to_string(cronjob)
+            .map_err(|err| CronJobError::CronJobToJson(err.to_string()))?;
+        self.etcd_service
+            .put_if_version(&cronjob.id, &json, expected)
+            .await
+            .map_err(|err| CronJobError::Etcd(err.to_string()))?
+            .ok_or_else(|| {
+                CronJobError::VersionConflict(cronjob.name.clone(), expected.unwrap_or_default())
+            })
+    }
+
+    pub fn id(&self, name: &str, namespace: &str) -> String {
+        format!("cronjob.{}.{}", namespace, name)
+    }
+}
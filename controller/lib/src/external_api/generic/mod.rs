@@ -1,2 +1,4 @@
-pub mod filter;
 pub mod model;
+pub mod patch;
+pub mod problem;
+pub mod read_cache;
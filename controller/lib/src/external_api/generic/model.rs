@@ -1,10 +1,113 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
 use serde::{Deserialize, Serialize};
-pub enum FilterError {
-    OutOfRange,
-}
 
-#[derive(Deserialize, Serialize)]
+use super::problem::Problem;
+
+/// Pagination of the listings, `?limit=<n>&offset=<n>&continue=<token>`, every parameter is
+/// optional. A listing stopped at its limit returns a `continue` token, passed back to get the
+/// next items.
+#[derive(Deserialize, Serialize, Default)]
 pub struct Pagination {
+    #[serde(default)]
     pub limit: u32,
+    #[serde(default)]
     pub offset: u32,
+    #[serde(default, rename = "continue")]
+    pub continue_token: Option<String>,
+}
+
+/// The consistency of a read, `?consistency=eventual` to read from the cache of the controller.
+#[derive(Deserialize, Serialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Consistency {
+    /// The resources are read from etcd, as last written.
+    #[default]
+    Strong,
+    /// The resources are read from the cache kept up to date by a watch of etcd, they may lag
+    /// behind the last writes. The reads fall back to etcd while the cache is out of sync.
+    Eventual,
+}
+
+/// `?dry_run=true` validates a mutation, admission included, and answers with its result
+/// without writing anything.
+#[derive(Deserialize, Serialize, Default)]
+pub struct DryRunQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Options of the reads, `?consistency=<strong|eventual>`.
+#[derive(Deserialize, Serialize, Default)]
+pub struct ReadOptions {
+    #[serde(default)]
+    pub consistency: Consistency,
+}
+
+/// A resource read with its version, the revision of etcd it was last modified at. An update
+/// setting `resource_version` to it fails if the resource changed since.
+#[derive(Serialize)]
+pub struct Versioned<T> {
+    #[serde(flatten)]
+    pub resource: T,
+    pub resource_version: i64,
+}
+
+impl<T: Serialize> Versioned<T> {
+    pub fn new(resource: T, resource_version: i64) -> Self {
+        Versioned {
+            resource,
+            resource_version,
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "resource_serialization_failed",
+                format!("Error while converting the resource to JSON: {}", err),
+            )
+            .to_http(),
+        }
+    }
+}
+
+/// Returns the problem of an update made against `expected`, a resource version which isn't
+/// the current one anymore.
+pub fn version_conflict(kind: &str, name: &str, expected: i64) -> Problem {
+    Problem::new(
+        StatusCode::CONFLICT,
+        "resource_version_conflict",
+        format!(
+            "The {} {} was modified since its version {}, read it again before updating it",
+            kind, name, expected
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_versioned() {
+        #[derive(Serialize)]
+        struct Resource {
+            name: String,
+        }
+
+        let versioned = Versioned::new(
+            Resource {
+                name: "web".to_string(),
+            },
+            42,
+        );
+        assert_eq!(
+            serde_json::to_value(&versioned).unwrap(),
+            json!({"name": "web", "resource_version": 42})
+        );
+    }
 }
@@ -0,0 +1,349 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::problem::Problem;
+
+/// The media type of a JSON merge patch (RFC 7386).
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+/// The media type of a JSON patch (RFC 6902).
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+#[derive(Debug)]
+pub enum PatchError {
+    UnsupportedMediaType(String),
+    InvalidPatch(String),
+    PatchFailed(String),
+    TestFailed(String),
+}
+
+impl PatchError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            PatchError::UnsupportedMediaType(content_type) => Problem::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "unsupported_media_type",
+                format!(
+                    "Unsupported content type {}, expected application/json, {} or {}",
+                    content_type, MERGE_PATCH_CONTENT_TYPE, JSON_PATCH_CONTENT_TYPE
+                ),
+            ),
+            PatchError::InvalidPatch(err) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_patch",
+                format!("Invalid patch: {}", err),
+            ),
+            PatchError::PatchFailed(err) => Problem::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "patch_failed",
+                format!("The patch can't be applied: {}", err),
+            ),
+            PatchError::TestFailed(path) => Problem::new(
+                StatusCode::CONFLICT,
+                "patch_test_failed",
+                format!("The value at {} isn't the one tested by the patch", path),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// An operation of a JSON patch, its paths being JSON pointers (RFC 6901).
+#[derive(Deserialize, Clone, Debug)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOperation {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: Value },
+}
+
+/// A partial update of a resource, applied to its JSON document.
+pub enum Patch {
+    /// The fields of the document to replace, a `null` removing the field (RFC 7386).
+    Merge(Value),
+    /// The operations applied in order, none of them is if one fails (RFC 6902).
+    Json(Vec<PatchOperation>),
+}
+
+impl Patch {
+    /// Parses the body of a request according to its `content_type`.
+    ///
+    /// # Returns:
+    ///
+    /// `None` if the body is a whole document, sent as `application/json`.
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<Option<Patch>, PatchError> {
+        match content_type {
+            "" | "application/json" => Ok(None),
+            MERGE_PATCH_CONTENT_TYPE => serde_json::from_slice(body)
+                .map(|patch| Some(Patch::Merge(patch)))
+                .map_err(|err| PatchError::InvalidPatch(err.to_string())),
+            JSON_PATCH_CONTENT_TYPE => serde_json::from_slice(body)
+                .map(|operations| Some(Patch::Json(operations)))
+                .map_err(|err| PatchError::InvalidPatch(err.to_string())),
+            _ => Err(PatchError::UnsupportedMediaType(content_type.to_string())),
+        }
+    }
+
+    /// Applies the patch to `document`, which is left unchanged if it fails.
+    pub fn apply(&self, document: &mut Value) -> Result<(), PatchError> {
+        match self {
+            Patch::Merge(patch) => {
+                merge(document, patch);
+                Ok(())
+            }
+            Patch::Json(operations) => {
+                let mut patched = document.clone();
+                for operation in operations {
+                    apply_operation(&mut patched, operation)?;
+                }
+                *document = patched;
+                Ok(())
+            }
+        }
+    }
+}
+
+fn merge(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+fn apply_operation(document: &mut Value, operation: &PatchOperation) -> Result<(), PatchError> {
+    match operation {
+        PatchOperation::Add { path, value } => add(document, path, value.clone()),
+        PatchOperation::Remove { path } => remove(document, path).map(|_| ()),
+        PatchOperation::Replace { path, value } => {
+            *get_mut(document, path)? = value.clone();
+            Ok(())
+        }
+        PatchOperation::Move { from, path } => {
+            if path.starts_with(&format!("{}/", from)) {
+                return Err(PatchError::PatchFailed(format!(
+                    "{} can't be moved into one of its children",
+                    from
+                )));
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        PatchOperation::Copy { from, path } => {
+            let value = get_mut(document, from)?.clone();
+            add(document, path, value)
+        }
+        PatchOperation::Test { path, value } => match *get_mut(document, path)? == *value {
+            true => Ok(()),
+            false => Err(PatchError::TestFailed(path.clone())),
+        },
+    }
+}
+
+/// Splits a JSON pointer into the keys it is made of.
+fn tokens(path: &str) -> Result<Vec<String>, PatchError> {
+    if path.is_empty() {
+        return Ok(vec![]);
+    }
+    match path.strip_prefix('/') {
+        Some(path) => Ok(path
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect()),
+        None => Err(PatchError::InvalidPatch(format!(
+            "{} isn't a JSON pointer",
+            path
+        ))),
+    }
+}
+
+fn index(token: &str, len: usize, path: &str) -> Result<usize, PatchError> {
+    match token.parse::<usize>() {
+        Ok(index) if index < len && (token == "0" || !token.starts_with('0')) => Ok(index),
+        _ => Err(PatchError::PatchFailed(format!("{} doesn't exist", path))),
+    }
+}
+
+fn get_mut<'a>(document: &'a mut Value, path: &str) -> Result<&'a mut Value, PatchError> {
+    let mut value = document;
+    for token in tokens(path)? {
+        value = match value {
+            Value::Object(map) => map.get_mut(&token),
+            Value::Array(items) => {
+                let index = index(&token, items.len(), path)?;
+                items.get_mut(index)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| PatchError::PatchFailed(format!("{} doesn't exist", path)))?;
+    }
+    Ok(value)
+}
+
+/// Returns the parent of the value at `path` and the last key of `path`, `None` for the root.
+fn parent_mut<'a>(
+    document: &'a mut Value,
+    path: &str,
+) -> Result<Option<(&'a mut Value, String)>, PatchError> {
+    let mut tokens = tokens(path)?;
+    let last = match tokens.pop() {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+    let parent = tokens
+        .iter()
+        .map(|token| format!("/{}", token.replace('~', "~0").replace('/', "~1")))
+        .collect::<String>();
+    Ok(Some((get_mut(document, &parent)?, last)))
+}
+
+fn add(document: &mut Value, path: &str, value: Value) -> Result<(), PatchError> {
+    let (parent, key) = match parent_mut(document, path)? {
+        Some(parent) => parent,
+        None => {
+            *document = value;
+            return Ok(());
+        }
+    };
+    match parent {
+        Value::Object(map) => {
+            map.insert(key, value);
+            Ok(())
+        }
+        Value::Array(items) if key == "-" => {
+            items.push(value);
+            Ok(())
+        }
+        Value::Array(items) => {
+            // an item can be added at the end of the list
+            let index = index(&key, items.len() + 1, path)?;
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err(PatchError::PatchFailed(format!(
+            "the parent of {} isn't an object nor a list",
+            path
+        ))),
+    }
+}
+
+fn remove(document: &mut Value, path: &str) -> Result<Value, PatchError> {
+    let removed = match parent_mut(document, path)? {
+        Some((Value::Object(map), key)) => map.remove(&key),
+        Some((Value::Array(items), key)) => {
+            let index = index(&key, items.len(), path)?;
+            Some(items.remove(index))
+        }
+        Some(_) => None,
+        None => {
+            return Err(PatchError::PatchFailed(
+                "the whole document can't be removed".to_string(),
+            ))
+        }
+    };
+    removed.ok_or_else(|| PatchError::PatchFailed(format!("{} doesn't exist", path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn json_patch(operations: Value) -> Patch {
+        Patch::Json(serde_json::from_value(operations).unwrap())
+    }
+
+    #[test]
+    fn test_merge_patch() {
+        let mut document = json!({"uri": "nginx:1.22", "labels": {"app": "web", "tier": "front"}});
+        Patch::Merge(json!({"uri": "nginx:1.23", "labels": {"tier": null, "team": "core"}}))
+            .apply(&mut document)
+            .unwrap();
+        assert_eq!(
+            document,
+            json!({"uri": "nginx:1.23", "labels": {"app": "web", "team": "core"}})
+        );
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let mut document = json!({"uri": "nginx:1.22", "environment": ["A=1"], "a/b": 1});
+        json_patch(json!([
+            {"op": "test", "path": "/uri", "value": "nginx:1.22"},
+            {"op": "replace", "path": "/uri", "value": "nginx:1.23"},
+            {"op": "add", "path": "/environment/-", "value": "B=2"},
+            {"op": "add", "path": "/environment/0", "value": "C=3"},
+            {"op": "copy", "from": "/uri", "path": "/image"},
+            {"op": "move", "from": "/a~1b", "path": "/c"},
+            {"op": "remove", "path": "/image"},
+        ]))
+        .apply(&mut document)
+        .unwrap();
+        assert_eq!(
+            document,
+            json!({"uri": "nginx:1.23", "environment": ["C=3", "A=1", "B=2"], "c": 1})
+        );
+    }
+
+    #[test]
+    fn test_json_patch_is_atomic() {
+        let original = json!({"uri": "nginx:1.22", "environment": []});
+        let mut document = original.clone();
+
+        let result = json_patch(json!([
+            {"op": "replace", "path": "/uri", "value": "nginx:1.23"},
+            {"op": "test", "path": "/uri", "value": "nginx:1.22"},
+        ]))
+        .apply(&mut document);
+        assert!(matches!(result, Err(PatchError::TestFailed(_))));
+
+        let result = json_patch(json!([
+            {"op": "replace", "path": "/uri", "value": "nginx:1.23"},
+            {"op": "remove", "path": "/environment/0"},
+        ]))
+        .apply(&mut document);
+        assert!(matches!(result, Err(PatchError::PatchFailed(_))));
+        assert_eq!(document, original);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(Patch::parse("application/json", b"{}"), Ok(None)));
+        assert!(matches!(
+            Patch::parse(MERGE_PATCH_CONTENT_TYPE, b"{}"),
+            Ok(Some(Patch::Merge(_)))
+        ));
+        assert!(matches!(
+            Patch::parse(
+                JSON_PATCH_CONTENT_TYPE,
+                br#"[{"op": "drop", "path": "/uri"}]"#
+            ),
+            Err(PatchError::InvalidPatch(_))
+        ));
+        assert!(matches!(
+            Patch::parse("text/plain", b""),
+            Err(PatchError::UnsupportedMediaType(_))
+        ));
+    }
+}
@@ -0,0 +1,94 @@
+use actix_web::error::{InternalError, JsonPayloadError, QueryPayloadError};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+/// Content type of the error responses, as defined by RFC 7807.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// `Problem` is the body of every error response of the API (RFC 7807 "problem details").
+///
+/// Properties:
+///
+/// * `type`: URI identifying the kind of problem, `urn:kudo:problem:<code>`.
+/// * `title`: Short summary of the kind of problem, the reason phrase of the status.
+/// * `status`: HTTP status code of the response.
+/// * `detail`: Explanation specific to this occurrence of the problem.
+/// * `code`: Machine-readable error code, stable across releases (e.g. `instance_not_found`).
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    pub code: String,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &str, detail: impl Into<String>) -> Self {
+        Problem {
+            r#type: format!("urn:kudo:problem:{}", code),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            code: code.to_string(),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        HttpResponse::build(status)
+            .content_type(PROBLEM_CONTENT_TYPE)
+            .json(self)
+    }
+
+    /// Error handler of the JSON bodies, answering a problem instead of actix's plain text.
+    pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+        let problem = Problem::new(StatusCode::BAD_REQUEST, "invalid_body", err.to_string());
+        InternalError::from_response(err, problem.to_http()).into()
+    }
+
+    /// Error handler of the query strings, answering a problem instead of actix's plain text.
+    pub fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+        let problem = Problem::new(StatusCode::BAD_REQUEST, "invalid_query", err.to_string());
+        InternalError::from_response(err, problem.to_http()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_serialization() {
+        let problem = Problem::new(
+            StatusCode::NOT_FOUND,
+            "instance_not_found",
+            "Instance not found",
+        );
+
+        assert_eq!(
+            serde_json::to_value(&problem).unwrap(),
+            serde_json::json!({
+                "type": "urn:kudo:problem:instance_not_found",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Instance not found",
+                "code": "instance_not_found",
+            })
+        );
+    }
+
+    #[test]
+    fn test_problem_response() {
+        let response = Problem::new(StatusCode::CONFLICT, "name_already_exists", "").to_http();
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            PROBLEM_CONTENT_TYPE
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use etcd_client::EventType;
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use tokio::sync::watch;
+
+use crate::etcd::{EtcdClient, Listing};
+use crate::tasks::BackgroundTasks;
+
+/// The delay before reading etcd again once the watch is interrupted.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+struct ReadCacheState {
+    synced: bool,
+    revision: i64,
+    entries: BTreeMap<String, String>,
+}
+
+/// `ReadCache` mirrors the keys of etcd in memory, read once then kept up to date by a watch, so
+/// that the reads tolerating stale resources (`?consistency=eventual`) don't reach etcd. It lags
+/// behind etcd by the delay of the watch, and is unused until the keys are read and while the
+/// watch is interrupted.
+///
+/// It is shared by the HTTP workers, the clones share the same keys.
+#[derive(Clone, Default)]
+pub struct ReadCache {
+    state: Arc<RwLock<ReadCacheState>>,
+}
+
+impl ReadCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns the mirroring of etcd in `background_tasks`, it stops when the controller shuts
+    /// down.
+    pub fn start(&self, etcd_address: SocketAddr, background_tasks: &BackgroundTasks) {
+        let cache = self.clone();
+        background_tasks.spawn(move |mut shutdown: watch::Receiver<bool>| async move {
+            loop {
+                tokio::select! {
+                    result = cache.mirror(&etcd_address) => {
+                        cache.unsync();
+                        if let Err(err) = result {
+                            warn!("Read cache watch interrupted: {}", err);
+                        }
+                    }
+                    Ok(()) = shutdown.changed() => break,
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => {}
+                    Ok(()) = shutdown.changed() => break,
+                }
+            }
+            cache.unsync();
+            info!("Read cache stopped");
+        });
+    }
+
+    /// Reads every key of etcd, then applies their modifications until the watch ends.
+    async fn mirror(&self, etcd_address: &SocketAddr) -> Result<(), String> {
+        let mut etcd = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| err.to_string())?;
+        // the workloads are stored without a prefix, every key is mirrored
+        let (revision, kvs) = etcd
+            .snapshot_prefix("")
+            .await
+            .map_err(|err| err.to_string())?;
+        let (_watcher, mut stream) = etcd
+            .watch_prefix_from("", revision + 1)
+            .await
+            .map_err(|err| err.to_string())?;
+        debug!(
+            "Read cache synced with {} key(s) at revision {}",
+            kvs.len(),
+            revision
+        );
+        self.load(revision, kvs);
+
+        while let Some(response) = stream.message().await.map_err(|err| err.to_string())? {
+            if response.canceled() {
+                return Err(format!(
+                    "the watch was canceled, compacted at revision {}",
+                    response.compact_revision()
+                ));
+            }
+            for event in response.events() {
+                let Some(kv) = event.kv() else {
+                    continue;
+                };
+                let Ok(key) = kv.key_str() else {
+                    continue;
+                };
+                match event.event_type() {
+                    EventType::Put => self.apply(kv.mod_revision(), key, kv.value_str().ok()),
+                    EventType::Delete => self.apply(kv.mod_revision(), key, None),
+                }
+            }
+        }
+        Err("the watch was closed by etcd".to_string())
+    }
+
+    /// Replaces the keys by the ones read from etcd at `revision`, the cache is then used.
+    fn load(&self, revision: i64, kvs: Vec<(String, String)>) {
+        let mut state = self.state.write().unwrap();
+        state.entries = kvs.into_iter().collect();
+        state.revision = revision;
+        state.synced = true;
+    }
+
+    /// Applies the modification of `key` made at `revision`, `value` is `None` if it was deleted.
+    fn apply(&self, revision: i64, key: &str, value: Option<&str>) {
+        let mut state = self.state.write().unwrap();
+        match value {
+            Some(value) => {
+                state.entries.insert(key.to_string(), value.to_string());
+            }
+            None => {
+                state.entries.remove(key);
+            }
+        }
+        state.revision = state.revision.max(revision);
+    }
+
+    /// Empties the cache, it is unused until the keys are read again.
+    fn unsync(&self) {
+        let mut state = self.state.write().unwrap();
+        state.synced = false;
+        state.revision = 0;
+        state.entries.clear();
+    }
+
+    /// Returns the revision of etcd the cache is synced with, `None` while it is unused.
+    pub fn revision(&self) -> Option<i64> {
+        let state = self.state.read().unwrap();
+        state.synced.then_some(state.revision)
+    }
+
+    /// Returns the value of `key`, `None` while the cache is unused and `Some(None)` if the key
+    /// doesn't exist.
+    pub fn get(&self, key: &str) -> Option<Option<String>> {
+        let state = self.state.read().unwrap();
+        state.synced.then(|| state.entries.get(key).cloned())
+    }
+
+    /// Lists the values of the keys starting with `prefix` as `EtcdClient::list_prefix` does,
+    /// `None` while the cache is unused.
+    ///
+    /// # Arguments:
+    ///
+    /// * `prefix`: The prefix of the keys.
+    /// * `start_after`: The continue token of a previous listing, the listing resumes after it.
+    /// * `offset`: The number of matching items to skip.
+    /// * `limit`: The maximum number of items to list.
+    /// * `matches`: The filter of the items, the values which can't be deserialized are skipped.
+    pub fn list_prefix<T: DeserializeOwned>(
+        &self,
+        prefix: &str,
+        start_after: Option<&str>,
+        offset: u32,
+        limit: u32,
+        matches: impl Fn(&T) -> bool,
+    ) -> Option<Listing<T>> {
+        let state = self.state.read().unwrap();
+        if !state.synced {
+            return None;
+        }
+
+        let start = match start_after {
+            Some(key) if key >= prefix => Bound::Excluded(key.to_string()),
+            _ => Bound::Included(prefix.to_string()),
+        };
+        let mut items = vec![];
+        let mut skipped = 0;
+        for (key, value) in state
+            .entries
+            .range((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+        {
+            let Ok(item) = serde_json::from_str::<T>(value) else {
+                continue;
+            };
+            if !matches(&item) {
+                continue;
+            }
+            if skipped < offset {
+                skipped += 1;
+                continue;
+            }
+
+            items.push(item);
+            if limit > 0 && items.len() == limit as usize {
+                return Some(Listing {
+                    items,
+                    continue_token: Some(key.clone()),
+                });
+            }
+        }
+        Some(Listing {
+            items,
+            continue_token: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn synced_cache() -> ReadCache {
+        let cache = ReadCache::new();
+        cache.load(
+            10,
+            vec![
+                ("default.a".to_string(), "1".to_string()),
+                ("default.b".to_string(), "2".to_string()),
+                ("default.c".to_string(), "3".to_string()),
+                ("defaults.d".to_string(), "4".to_string()),
+                ("other.a".to_string(), "5".to_string()),
+            ],
+        );
+        cache
+    }
+
+    #[test]
+    fn test_mirror() {
+        let cache = ReadCache::new();
+        assert_eq!(cache.get("default.a"), None);
+        assert_eq!(cache.revision(), None);
+
+        let cache = synced_cache();
+        assert_eq!(cache.get("default.a"), Some(Some("1".to_string())));
+        assert_eq!(cache.get("default.z"), Some(None));
+        assert_eq!(cache.revision(), Some(10));
+
+        cache.apply(11, "default.a", Some("6"));
+        cache.apply(12, "default.b", None);
+        assert_eq!(cache.get("default.a"), Some(Some("6".to_string())));
+        assert_eq!(cache.get("default.b"), Some(None));
+        assert_eq!(cache.revision(), Some(12));
+
+        // the cache is unused until etcd is read again
+        cache.unsync();
+        assert_eq!(cache.get("other.a"), None);
+        assert!(cache
+            .list_prefix("default.", None, 0, 0, |_: &u32| true)
+            .is_none());
+    }
+
+    #[test]
+    fn test_list_prefix() {
+        let cache = synced_cache();
+
+        let all = cache
+            .list_prefix("default.", None, 0, 0, |_: &u32| true)
+            .unwrap();
+        assert_eq!(all.items, vec![1, 2, 3]);
+        assert_eq!(all.continue_token, None);
+
+        let page = cache
+            .list_prefix("default.", None, 0, 2, |_: &u32| true)
+            .unwrap();
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.continue_token, Some("default.b".to_string()));
+        let next = cache
+            .list_prefix("default.", Some("default.b"), 0, 2, |_: &u32| true)
+            .unwrap();
+        assert_eq!(next.items, vec![3]);
+        assert_eq!(next.continue_token, None);
+
+        let odd = cache
+            .list_prefix("default.", None, 1, 0, |item: &u32| item % 2 == 1)
+            .unwrap();
+        assert_eq!(odd.items, vec![3]);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::ImagePullDTO;
+use super::service::ImageService;
+use crate::external_api::generic::problem::Problem;
+use actix_web::{web, Responder, Scope};
+pub struct ImageController {}
+impl ImageController {
+    pub fn services(&self) -> Scope {
+        web::scope("/image")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .service(web::resource("/pull").route(web::put().to(ImageController::put_pull)))
+            .service(web::resource("/pull/{pull_id}").route(web::get().to(ImageController::pull)))
+    }
+
+    /// `pull` is an async function that handle **/image/pull/\<pull_id>** route (GET)
+    /// # Description:
+    /// * Get the progress of the pull of an image on each node
+    /// # Arguments:
+    ///
+    /// * `pull_id`: web::Path<String> - The id of the pull.
+    pub async fn pull(
+        pull_id: web::Path<String>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut image_service =
+            match ImageService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        image_service
+            .get_pull(&pull_id)
+            .await
+            .map_or_else(|e| e.to_http(), |p| p.to_http())
+    }
+
+    /// `put_pull` is an async function that handle **/image/pull** route (PUT)
+    /// # Description:
+    /// * Pull an image on the nodes ahead of a deployment, the progress is followed with the returned id
+    /// # Arguments:
+    ///
+    /// * `body`: web::Json<ImagePullDTO> - The image and the nodes pulling it, every connected node if empty.
+    pub async fn put_pull(
+        body: web::Json<ImagePullDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut image_service =
+            match ImageService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service.with_background_tasks(&data.background_tasks),
+                Err(e) => return e.to_http(),
+            };
+
+        image_service
+            .pull(body.into_inner())
+            .await
+            .map_or_else(|e| e.to_http(), |p| p.to_http())
+    }
+}
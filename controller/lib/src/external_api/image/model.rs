@@ -0,0 +1,321 @@
+use std::net::SocketAddr;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use proto::agent;
+use proto::scheduler::{ClusterSnapshot, NodeImagePullStatus};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+
+/// The prefix of the keys of the image pulls in etcd.
+pub const IMAGE_PULL_PREFIX: &str = "image_pull.";
+
+/// Returns the key of an image pull.
+pub fn pull_key(id: &str) -> String {
+    format!("{}{}", IMAGE_PULL_PREFIX, id)
+}
+
+pub enum ImageError {
+    PullNotFound,
+    InvalidPull(String),
+    NoNode,
+    Etcd(String),
+    Scheduler(String),
+    JsonToPull(String),
+    PullToJson(String),
+}
+
+impl ImageError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            ImageError::PullNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "image_pull_not_found",
+                "The image pull does not exist",
+            ),
+            ImageError::InvalidPull(err) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_image_pull",
+                format!("Invalid image pull: {}", err),
+            ),
+            ImageError::NoNode => Problem::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no_node_connected",
+                "No node is connected to the schedulers",
+            ),
+            ImageError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            ImageError::Scheduler(err) => Problem::new(
+                StatusCode::BAD_GATEWAY,
+                "scheduler_error",
+                format!("Failed to pull the image on the scheduler {}", err),
+            ),
+            ImageError::JsonToPull(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_image_pull",
+                format!("Error while converting JSON string to image pull: {}", err),
+            ),
+            ImageError::PullToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "image_pull_serialization_failed",
+                format!("Error while converting the image pull to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// The body of `PUT /image/pull`, the image is pulled on every connected node if `nodes` is
+/// empty.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ImagePullDTO {
+    pub uri: String,
+    #[serde(default)]
+    pub nodes: Vec<String>,
+}
+
+impl ImagePullDTO {
+    /// Checks that the pull names an image.
+    pub fn validate(&self) -> Result<(), ImageError> {
+        if self.uri.trim().is_empty() {
+            return Err(ImageError::InvalidPull(
+                "the uri of the image is empty".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The progress of the pull of an image on a node.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImagePullState {
+    /// The node is pulling the image
+    #[default]
+    Pulling,
+    /// The image is on the node, the instances using it start without pulling it
+    Pulled,
+    /// The node couldn't pull the image, the instances using it will pull it again
+    Failed,
+}
+
+impl ImagePullState {
+    pub fn is_finished(&self) -> bool {
+        *self != ImagePullState::Pulling
+    }
+}
+
+impl From<agent::ImagePullState> for ImagePullState {
+    fn from(state: agent::ImagePullState) -> Self {
+        match state {
+            agent::ImagePullState::ImagePulling => ImagePullState::Pulling,
+            agent::ImagePullState::ImagePulled => ImagePullState::Pulled,
+            agent::ImagePullState::ImagePullFailed => ImagePullState::Failed,
+        }
+    }
+}
+
+/// The pull of an image on a node.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeImagePull {
+    pub node_id: String,
+    pub state: ImagePullState,
+    /// The progress of the pull, or why it failed
+    #[serde(default)]
+    pub description: String,
+}
+
+/// An `ImagePull` is an image pulled on the nodes ahead of a deployment, so that its instances
+/// start without waiting for the image. It is updated as the nodes report their progress.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ImagePull {
+    pub id: String,
+    pub uri: String,
+    pub nodes: Vec<NodeImagePull>,
+}
+
+impl ImagePull {
+    /// Creates a pull in progress on `pulling`, the nodes of `missing` being connected to no
+    /// scheduler.
+    pub fn new(id: String, uri: String, pulling: &[String], missing: &[String]) -> Self {
+        let mut nodes: Vec<NodeImagePull> = pulling
+            .iter()
+            .map(|node_id| NodeImagePull {
+                node_id: node_id.clone(),
+                state: ImagePullState::Pulling,
+                description: String::new(),
+            })
+            .collect();
+        nodes.extend(missing.iter().map(|node_id| NodeImagePull {
+            node_id: node_id.clone(),
+            state: ImagePullState::Failed,
+            description: "The node is not connected".to_string(),
+        }));
+        nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        ImagePull { id, uri, nodes }
+    }
+
+    /// Returns `true` once every node pulled the image or failed to.
+    pub fn is_finished(&self) -> bool {
+        self.nodes.iter().all(|node| node.state.is_finished())
+    }
+
+    /// Keeps the progress of the pull sent by a node.
+    pub fn update(&mut self, status: NodeImagePullStatus) {
+        let Some(pull) = status.status else {
+            return;
+        };
+        let state = pull.state().into();
+        match self
+            .nodes
+            .iter_mut()
+            .find(|node| node.node_id == status.node_id)
+        {
+            Some(node) => {
+                node.state = state;
+                node.description = pull.description;
+            }
+            None => self.nodes.push(NodeImagePull {
+                node_id: status.node_id,
+                state,
+                description: pull.description,
+            }),
+        }
+    }
+
+    /// Marks the nodes of `node_ids` still pulling as failed, e.g. when their scheduler is lost.
+    pub fn fail(&mut self, node_ids: &[String], description: &str) {
+        for node in self.nodes.iter_mut() {
+            if !node.state.is_finished() && node_ids.contains(&node.node_id) {
+                node.state = ImagePullState::Failed;
+                node.description = description.to_string();
+            }
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => ImageError::PullToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Returns the nodes each scheduler pulls the image on and the requested nodes connected to no
+/// scheduler. A node connected to several schedulers pulls the image once.
+///
+/// # Arguments:
+///
+/// * `nodes`: The nodes pulling the image, every connected node if empty.
+/// * `snapshots`: The snapshot of every scheduler of the cluster.
+pub fn plan_pull(
+    nodes: &[String],
+    snapshots: &[(SocketAddr, ClusterSnapshot)],
+) -> (Vec<(SocketAddr, Vec<String>)>, Vec<String>) {
+    let mut planned: Vec<String> = vec![];
+    let mut targets = vec![];
+    for (scheduler, snapshot) in snapshots {
+        let node_ids: Vec<String> = snapshot
+            .nodes
+            .iter()
+            .filter(|node| {
+                node.connected
+                    && (nodes.is_empty() || nodes.contains(&node.id))
+                    && !planned.contains(&node.id)
+            })
+            .map(|node| node.id.clone())
+            .collect();
+        if !node_ids.is_empty() {
+            planned.extend(node_ids.iter().cloned());
+            targets.push((*scheduler, node_ids));
+        }
+    }
+
+    let mut missing: Vec<String> = nodes
+        .iter()
+        .filter(|node_id| !planned.contains(node_id))
+        .cloned()
+        .collect();
+    missing.sort();
+    missing.dedup();
+    (targets, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::NodeSnapshot;
+
+    use super::*;
+
+    fn snapshot(nodes: &[(&str, bool)]) -> ClusterSnapshot {
+        ClusterSnapshot {
+            nodes: nodes
+                .iter()
+                .map(|(id, connected)| NodeSnapshot {
+                    id: id.to_string(),
+                    connected: *connected,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(node_ids: &[&str]) -> Vec<String> {
+        node_ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn test_plan_pull() {
+        let first: SocketAddr = "10.0.0.1:50051".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:50051".parse().unwrap();
+        let snapshots = vec![
+            (first, snapshot(&[("a", true), ("b", false)])),
+            (second, snapshot(&[("a", true), ("c", true)])),
+        ];
+
+        // every connected node pulls the image once
+        let (targets, missing) = plan_pull(&[], &snapshots);
+        assert_eq!(targets, vec![(first, ids(&["a"])), (second, ids(&["c"]))]);
+        assert!(missing.is_empty());
+
+        let (targets, missing) = plan_pull(&ids(&["b", "c", "d"]), &snapshots);
+        assert_eq!(targets, vec![(second, ids(&["c"]))]);
+        assert_eq!(missing, ids(&["b", "d"]));
+    }
+
+    #[test]
+    fn test_update() {
+        let mut pull = ImagePull::new(
+            "1".to_string(),
+            "nginx".to_string(),
+            &ids(&["b", "a"]),
+            &ids(&["c"]),
+        );
+        assert_eq!(pull.nodes[0].node_id, "a");
+        assert_eq!(pull.nodes[2].state, ImagePullState::Failed);
+        assert!(!pull.is_finished());
+
+        pull.update(NodeImagePullStatus {
+            node_id: "a".to_string(),
+            status: Some(agent::ImagePullStatus {
+                uri: "nginx".to_string(),
+                state: agent::ImagePullState::ImagePulled.into(),
+                description: "Pulled nginx".to_string(),
+            }),
+        });
+        assert_eq!(pull.nodes[0].state, ImagePullState::Pulled);
+        assert_eq!(pull.nodes[0].description, "Pulled nginx");
+
+        pull.fail(&ids(&["a", "b"]), "The scheduler is gone");
+        assert_eq!(pull.nodes[0].state, ImagePullState::Pulled);
+        assert_eq!(pull.nodes[1].state, ImagePullState::Failed);
+        assert!(pull.is_finished());
+    }
+}
@@ -0,0 +1,150 @@
+use std::net::SocketAddr;
+
+use futures_util::stream::{self, StreamExt};
+use log::{error, info, warn};
+use uuid::Uuid;
+
+use super::model::{plan_pull, pull_key, ImageError, ImagePull, ImagePullDTO};
+use crate::etcd::EtcdClient;
+use crate::external_api::shard::service::ShardService;
+use crate::grpc_client::interface::SchedulerClientInterface;
+use crate::tasks::BackgroundTasks;
+
+/// `ImageService` pulls the images on the nodes ahead of the deployments, through the
+/// schedulers, and stores the progress of the pulls in etcd.
+///
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `shard_service`: This is the service finding the schedulers of the cluster.
+/// * `background_tasks`: The tasks writing the progress of the pulls, awaited on shutdown.
+pub struct ImageService {
+    etcd_service: EtcdClient,
+    shard_service: ShardService,
+    background_tasks: BackgroundTasks,
+}
+
+impl ImageService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<ImageService, ImageError> {
+        let etcd_service = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| ImageError::Etcd(err.to_string()))?;
+        Ok(ImageService {
+            shard_service: ShardService::with_client(etcd_service.clone(), scheduler_address),
+            etcd_service,
+            background_tasks: BackgroundTasks::new(),
+        })
+    }
+
+    /// Registers the tasks spawned by the service in `background_tasks`, so that the controller
+    /// waits for them before exiting.
+    pub fn with_background_tasks(mut self, background_tasks: &BackgroundTasks) -> Self {
+        self.background_tasks = background_tasks.clone();
+        self
+    }
+
+    pub async fn get_pull(&mut self, id: &str) -> Result<ImagePull, ImageError> {
+        match self.etcd_service.get(&pull_key(id)).await {
+            Some(pull) => {
+                serde_json::from_str(&pull).map_err(|err| ImageError::JsonToPull(err.to_string()))
+            }
+            None => Err(ImageError::PullNotFound),
+        }
+    }
+
+    /// It pulls an image on the given nodes, or on every connected node, each node being asked
+    /// to the first scheduler it is connected to. The pull is stored in etcd and returned right
+    /// away, its progress is written as the nodes report it.
+    ///
+    /// # Arguments:
+    ///
+    /// * `pull_dto`: The image and the nodes pulling it.
+    pub async fn pull(&mut self, pull_dto: ImagePullDTO) -> Result<ImagePull, ImageError> {
+        pull_dto.validate()?;
+
+        let snapshots = self
+            .shard_service
+            .scheduler_snapshots()
+            .await
+            .map_err(ImageError::Scheduler)?;
+        let (targets, missing) = plan_pull(&pull_dto.nodes, &snapshots);
+        if targets.is_empty() && missing.is_empty() {
+            return Err(ImageError::NoNode);
+        }
+
+        let pulling: Vec<String> = targets
+            .iter()
+            .flat_map(|(_, node_ids)| node_ids.iter().cloned())
+            .collect();
+        let mut record =
+            ImagePull::new(Uuid::new_v4().to_string(), pull_dto.uri, &pulling, &missing);
+
+        let mut streams = vec![];
+        for (scheduler, node_ids) in targets {
+            let client = SchedulerClientInterface::new(format!("http://{}", scheduler)).await;
+            let result = match client {
+                Ok(mut client) => client.pull_image(&record.uri, node_ids.clone()).await,
+                Err(err) => Err(err),
+            };
+            match result {
+                // the statuses are tagged with the nodes of their scheduler, which fail with it
+                Ok(response) => streams.push(
+                    response
+                        .into_inner()
+                        .map(move |status| (node_ids.clone(), status)),
+                ),
+                Err(err) => record.fail(&node_ids, &format!("{}: {:?}", scheduler, err)),
+            }
+        }
+        self.save_pull(&record).await?;
+        info!("Pulling image {} on {} node(s)", record.uri, pulling.len());
+
+        let mut etcd_service = self.etcd_service.clone();
+        let result = record.clone();
+        self.background_tasks.spawn(move |mut shutdown| async move {
+            let mut statuses = stream::select_all(streams);
+            loop {
+                let (node_ids, status) = tokio::select! {
+                    message = statuses.next() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                    Ok(()) = shutdown.changed() => break,
+                };
+
+                match status {
+                    Ok(status) => record.update(status),
+                    Err(err) => {
+                        warn!("Pull of image {} failed: {}", record.uri, err.message());
+                        record.fail(&node_ids, err.message());
+                    }
+                }
+                match serde_json::to_string(&record) {
+                    Ok(json) => {
+                        if let Err(err) = etcd_service.put(&pull_key(&record.id), &json).await {
+                            error!("Failed to update image pull {}: {}", record.id, err);
+                        }
+                    }
+                    Err(err) => error!("Failed to serialize image pull {}: {}", record.id, err),
+                }
+            }
+            info!("Pull of image {} ended", record.uri);
+        });
+
+        Ok(result)
+    }
+
+    /// It stores an image pull in etcd.
+    pub async fn save_pull(&mut self, pull: &ImagePull) -> Result<(), ImageError> {
+        let json =
+            serde_json::to_string(pull).map_err(|err| ImageError::PullToJson(err.to_string()))?;
+        self.etcd_service
+            .put(&pull_key(&pull.id), &json)
+            .await
+            .map_err(|err| ImageError::Etcd(err.to_string()))?;
+        Ok(())
+    }
+}
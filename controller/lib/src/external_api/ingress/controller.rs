@@ -0,0 +1,160 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::IngressDTO;
+use super::service::IngressService;
+use crate::external_api::generic::model::Pagination;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, Scope};
+pub struct IngressController {}
+impl IngressController {
+    pub fn services(&self) -> Scope {
+        web::scope("/ingress")
+            .service(
+                web::resource("/{namespace}/{ingress_name}")
+                    .route(web::delete().to(IngressController::delete_ingress))
+                    .route(web::get().to(IngressController::ingress))
+                    .route(web::patch().to(IngressController::patch_ingress)),
+            )
+            .service(
+                web::resource("/{namespace}")
+                    .route(web::put().to(IngressController::put_ingress))
+                    .route(web::get().to(IngressController::get_all_ingresses)),
+            )
+            .service(
+                web::resource("").route(web::get().to(IngressController::get_cluster_ingresses)),
+            )
+    }
+
+    /// `ingress` is an async function that handle **/ingress/\<namespace>/<ingress_name>** route (GET)
+    /// # Description:
+    /// * Get an ingress
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the ingress name.
+    pub async fn ingress(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, ingress_name) = params.into_inner();
+
+        let mut ingress_service = match IngressService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        ingress_service
+            .get_versioned_ingress(&ingress_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `put_ingress` is an async function that handle **/ingress/\<namespace>** route (PUT)
+    /// # Description:
+    /// * Create a new ingress
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - This is the namespace that the ingress will be created in.
+    /// * `body`: web::Json<IngressDTO> - Contain the routing rules of the ingress.
+    pub async fn put_ingress(
+        namespace: web::Path<String>,
+        body: web::Json<IngressDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut ingress_service = match IngressService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        ingress_service
+            .create_ingress(body.into_inner(), &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `get_all_ingresses` is an async function that handle **/ingress/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get all ingresses in the namespace
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the ingresses you want to retrieve.
+    /// * `pagination`: Option<web::Query<Pagination>>
+    pub async fn get_all_ingresses(
+        namespace: web::Path<String>,
+        pagination: Option<web::Query<Pagination>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut ingress_service = match IngressService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        ingress_service
+            .get_all_ingresses(&pagination, Some(&namespace))
+            .await
+            .to_http()
+    }
+
+    /// `get_cluster_ingresses` is an async function that handle **/ingress** route (GET)
+    /// # Description:
+    /// * Get the ingresses of every namespace, used by the ingress proxy to build its routes
+    pub async fn get_cluster_ingresses(data: web::Data<ActixAppState>) -> impl Responder {
+        let mut ingress_service = match IngressService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        ingress_service
+            .get_all_ingresses(&Pagination::default(), None)
+            .await
+            .to_http()
+    }
+
+    /// `patch_ingress` is an async function that handle **/ingress/\<namespace>/<ingress_name>** route (PATCH)
+    /// # Description:
+    /// * Replace the routing rules of an ingress
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the ingress name.
+    /// * `body`: web::Json<IngressDTO> - Contain the new routing rules of the ingress.
+    pub async fn patch_ingress(
+        params: web::Path<(String, String)>,
+        body: web::Json<IngressDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, ingress_name) = params.into_inner();
+
+        let mut ingress_service = match IngressService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        ingress_service
+            .update_ingress(body.into_inner(), &ingress_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `delete_ingress` is an async function that handle **/ingress/\<namespace>/<ingress_name>** route (DELETE)
+    /// # Description:
+    /// * Delete an ingress
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the ingress name.
+    pub async fn delete_ingress(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, ingress_name) = params.into_inner();
+
+        let mut ingress_service = match IngressService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        ingress_service
+            .delete_ingress(&ingress_name, &namespace)
+            .await;
+        HttpResponse::build(StatusCode::NO_CONTENT).body("Remove successfully")
+    }
+}
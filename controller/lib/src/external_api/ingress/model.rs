@@ -0,0 +1,167 @@
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::model::version_conflict;
+
+pub enum IngressError {
+    IngressNotFound,
+    Etcd(String),
+    NameAlreadyExists(String),
+    InvalidRule(String),
+    VersionConflict(String, i64),
+    JsonToIngress(String),
+    IngressToJson(String),
+}
+
+impl IngressError {
+    pub fn to_http(&self) -> HttpResponse {
+        match self {
+            IngressError::IngressNotFound => HttpResponse::NotFound().body("Ingress not found"),
+            IngressError::Etcd(err) => {
+                HttpResponse::InternalServerError().body(format!("Etcd error: {} ", err))
+            }
+            IngressError::NameAlreadyExists(name) => {
+                HttpResponse::Conflict().body(format!("Ingress with name {} already exists", name))
+            }
+            IngressError::InvalidRule(err) => {
+                HttpResponse::BadRequest().body(format!("Invalid ingress rule: {}", err))
+            }
+            IngressError::VersionConflict(name, version) => {
+                version_conflict("ingress", name, *version).to_http()
+            }
+            IngressError::JsonToIngress(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting JSON string to ingress : {}",
+                err
+            )),
+            IngressError::IngressToJson(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the ingress to JSON: {}",
+                err
+            )),
+        }
+    }
+}
+
+/// Routes the HTTP requests matching a host and a path prefix to a service.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct IngressRule {
+    /// Host header to match, an empty host matches every request
+    #[serde(default)]
+    pub host: String,
+    /// Path prefix to match
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// Name of the service to route to, in the namespace of the ingress
+    pub service: String,
+    /// Port of the service to route to
+    pub port: i32,
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+/// Checks that the rules of an ingress route to a valid port.
+pub fn validate_rules(rules: &[IngressRule]) -> Result<(), IngressError> {
+    match rules
+        .iter()
+        .find(|rule| u16::try_from(rule.port).map_or(true, |port| port == 0))
+    {
+        Some(rule) => Err(IngressError::InvalidRule(format!(
+            "port {} of service {} is not between 1 and 65535",
+            rule.port, rule.service
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Ingress {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+    pub rules: Vec<IngressRule>,
+}
+
+impl Ingress {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the ingress to json: {}",
+                err
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct IngressDTO {
+    pub name: String,
+    pub rules: Vec<IngressRule>,
+    /// If set, the update fails if the ingress was modified since this version
+    #[serde(default, skip_serializing)]
+    pub resource_version: Option<i64>,
+}
+
+impl From<Ingress> for IngressDTO {
+    fn from(ingress: Ingress) -> Self {
+        IngressDTO {
+            name: ingress.name,
+            rules: ingress.rules,
+            resource_version: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct IngressVector {
+    pub ingresses: Vec<Ingress>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl IngressVector {
+    pub fn new(ingresses: Vec<Ingress>) -> IngressVector {
+        IngressVector {
+            ingresses,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the ingresses to json: {}",
+                err
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(port: i32) -> IngressRule {
+        IngressRule {
+            host: String::new(),
+            path: default_path(),
+            service: "web".to_string(),
+            port,
+        }
+    }
+
+    #[test]
+    fn test_validate_rules() {
+        assert!(validate_rules(&[rule(1), rule(8080), rule(65535)]).is_ok());
+        assert!(validate_rules(&[rule(80), rule(0)]).is_err());
+        assert!(validate_rules(&[rule(-1)]).is_err());
+        assert!(validate_rules(&[rule(65536)]).is_err());
+    }
+}
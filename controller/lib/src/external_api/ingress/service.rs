@@ -0,0 +1,147 @@
+use std::net::SocketAddr;
+
+use super::model::{validate_rules, Ingress, IngressDTO, IngressError, IngressVector};
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::{Pagination, Versioned};
+
+/// `IngressService` is the service used by the `IngressController` to store ingresses in etcd.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+pub struct IngressService {
+    etcd_service: EtcdClient,
+}
+
+impl IngressService {
+    pub async fn new(etcd_address: &SocketAddr) -> Result<IngressService, IngressError> {
+        Ok(IngressService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| IngressError::Etcd(err.to_string()))?,
+        })
+    }
+
+    pub async fn get_ingress(
+        &mut self,
+        ingress_name: &str,
+        namespace: &str,
+    ) -> Result<Ingress, IngressError> {
+        let id = self.id(ingress_name, namespace);
+        match self.etcd_service.get(&id).await {
+            Some(ingress) => serde_json::from_str(&ingress)
+                .map_err(|err| IngressError::JsonToIngress(err.to_string())),
+            None => Err(IngressError::IngressNotFound),
+        }
+    }
+
+    /// Reads an ingress and its version.
+    pub async fn get_versioned_ingress(
+        &mut self,
+        ingress_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<Ingress>, IngressError> {
+        let id = self.id(ingress_name, namespace);
+        match self.etcd_service.get_versioned(&id).await {
+            Some((ingress, version)) => serde_json::from_str(&ingress)
+                .map(|ingress| Versioned::new(ingress, version))
+                .map_err(|err| IngressError::JsonToIngress(err.to_string())),
+            None => Err(IngressError::IngressNotFound),
+        }
+    }
+
+    /// This function gets the ingresses of a namespace, or of every namespace if `namespace` is
+    /// `None`, paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    pub async fn get_all_ingresses(
+        &mut self,
+        pagination: &Pagination,
+        namespace: Option<&str>,
+    ) -> IngressVector {
+        let prefix = match namespace {
+            Some(namespace) => self.id("", namespace),
+            None => "ingress.".to_string(),
+        };
+        match self
+            .etcd_service
+            .list_prefix(
+                &prefix,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |_: &Ingress| true,
+            )
+            .await
+        {
+            Ok(listing) => {
+                IngressVector::new(listing.items).with_continue_token(listing.continue_token)
+            }
+            Err(_) => IngressVector::new(vec![]),
+        }
+    }
+
+    pub async fn create_ingress(
+        &mut self,
+        ingress_dto: IngressDTO,
+        namespace: &str,
+    ) -> Result<Ingress, IngressError> {
+        match self.get_ingress(&ingress_dto.name, namespace).await {
+            Ok(ingress) => return Err(IngressError::NameAlreadyExists(ingress.name)),
+            Err(IngressError::IngressNotFound) => {}
+            Err(err) => return Err(err),
+        }
+        validate_rules(&ingress_dto.rules)?;
+
+        let ingress = Ingress {
+            id: self.id(&ingress_dto.name, namespace),
+            name: ingress_dto.name,
+            namespace: namespace.to_string(),
+            rules: ingress_dto.rules,
+        };
+        self.put_ingress(&ingress, None).await?;
+        Ok(ingress)
+    }
+
+    /// It replaces the rules of an ingress, if `resource_version` is set only if the ingress
+    /// wasn't modified since.
+    pub async fn update_ingress(
+        &mut self,
+        ingress_dto: IngressDTO,
+        ingress_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<Ingress>, IngressError> {
+        validate_rules(&ingress_dto.rules)?;
+        let mut ingress = self.get_ingress(ingress_name, namespace).await?;
+        ingress.rules = ingress_dto.rules;
+        let version = self
+            .put_ingress(&ingress, ingress_dto.resource_version)
+            .await?;
+        Ok(Versioned::new(ingress, version))
+    }
+
+    pub async fn delete_ingress(&mut self, ingress_name: &str, namespace: &str) {
+        let id = self.id(ingress_name, namespace);
+        _ = self.etcd_service.delete(&id).await;
+    }
+
+    /// Stores an ingress, only if it is still at the version `expected` if set. Returns the
+    /// version of the ingress stored.
+    async fn put_ingress(
+        &mut self,
+        ingress: &Ingress,
+        expected: Option<i64>,
+    ) -> Result<i64, IngressError> {
+        let json = serde_json::to_string(ingress)
+            .map_err(|err| IngressError::IngressToJson(err.to_string()))?;
+        self.etcd_service
+            .put_if_version(&ingress.id, &json, expected)
+            .await
+            .map_err(|err| IngressError::Etcd(err.to_string()))?
+            .ok_or_else(|| {
+                IngressError::VersionConflict(ingress.name.clone(), expected.unwrap_or_default())
+            })
+    }
+
+    pub fn id(&self, name: &str, namespace: &str) -> String {
+        format!("ingress.{}.{}", namespace, name)
+    }
+}
@@ -0,0 +1,512 @@
+use crate::admission::{AdmissionService, Operation, ResourceKind};
+use crate::external_api::interface::ActixAppState;
+
+use super::exec;
+use super::model::{
+    Eviction, InstanceDTO, InstanceFilter, InstanceMigrationDTO, LogsQuery, WatchQuery,
+};
+use super::service::InstanceService;
+use crate::external_api::generic::model::{DryRunQuery, Pagination, ReadOptions};
+use crate::external_api::generic::problem::Problem;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, Responder, Scope};
+use futures_util::StreamExt;
+/// Header carrying the key deduplicating the retries of an instance creation.
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Sent by the clients reconnecting to a stream of server-sent events, the id of the last event
+/// they received.
+const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+
+pub struct InstanceController {}
+impl InstanceController {
+    pub fn services(&self) -> Scope {
+        web::scope("/instance")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(
+                web::resource("/{namespace}/{instance_id}")
+                    .route(web::delete().to(InstanceController::delete_instance))
+                    .route(web::get().to(InstanceController::instance))
+                    .route(web::patch().to(InstanceController::patch_instance)),
+            )
+            .service(
+                web::resource("/{namespace}/{instance_id}/restart")
+                    .route(web::post().to(InstanceController::restart_instance)),
+            )
+            .service(
+                web::resource("/{namespace}/{instance_id}/evict")
+                    .route(web::post().to(InstanceController::evict_instance)),
+            )
+            .service(
+                web::resource("/{namespace}/{instance_id}/migrate")
+                    .route(web::post().to(InstanceController::migrate_instance)),
+            )
+            .service(
+                web::resource("/{namespace}/{instance_id}/logs")
+                    .route(web::get().to(InstanceController::instance_logs)),
+            )
+            .service(
+                web::resource("/{namespace}/{instance_id}/exec")
+                    .route(web::get().to(InstanceController::instance_exec)),
+            )
+            .service(
+                web::resource("/{namespace}")
+                    .route(web::put().to(InstanceController::put_instance))
+                    .route(web::get().to(InstanceController::get_all_instances)),
+            )
+    }
+
+    /// `instance` is an async function that handle **/instance/\<namespace>/<instance_id>** route (GET)
+    /// # Description:
+    /// * Get an instance
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    /// * `read`: web::Query<ReadOptions> - `?consistency=eventual` to read the instance from the cache of the controller.
+    pub async fn instance(
+        params: web::Path<(String, String)>,
+        read: web::Query<ReadOptions>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache)
+                    .with_consistency(read.consistency, &data.read_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        instance_service
+            .get_instance(&instance_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `put_instance` is an async function that handle **/instance/\<namespace>** route (PUT)
+    /// # Description:
+    /// * Create a new instance of a workload
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - This is the namespace of the workload to instantiate.
+    /// * `body`: web::Json<InstanceDTO> - Contain the name of the workload to instantiate.
+    /// * `dry_run`: web::Query<DryRunQuery> - `?dry_run=true` to validate the instance without creating nor scheduling it.
+    /// * `req`: HttpRequest - Its `Idempotency-Key` header, if any, deduplicates the retries of the request.
+    pub async fn put_instance(
+        namespace: web::Path<String>,
+        body: web::Json<InstanceDTO>,
+        dry_run: web::Query<DryRunQuery>,
+        data: web::Data<ActixAppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let idempotency_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+            Some(value) => match value.to_str() {
+                Ok(key) if !key.is_empty() && key.len() <= 255 => Some(key.to_string()),
+                _ => {
+                    return Problem::new(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_idempotency_key",
+                        "The Idempotency-Key header must be a visible ASCII string of 1 to 255 characters",
+                    )
+                    .to_http()
+                }
+            },
+            None => None,
+        };
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache)
+                    .with_dry_run(dry_run.dry_run),
+                Err(e) => return e.to_http(),
+            };
+
+        let instance_dto = body.into_inner();
+
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .with_dry_run(dry_run.dry_run)
+            .review(
+                ResourceKind::Instance,
+                Operation::Create,
+                &namespace,
+                &instance_dto.workload_name,
+                serde_json::to_value(&instance_dto).unwrap_or_default(),
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
+        instance_service
+            .create_instance(instance_dto, &namespace, idempotency_key.as_deref())
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `get_all_instances` is an async function that handle **/instance/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get all instances in the namespace
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the instances you want to retrieve.
+    /// * `pagination`: Option<web::Query<Pagination>>
+    /// * `filter`: web::Query<InstanceFilter> - `?state=Running&node=<id>` to select the instances by state or node.
+    /// * `watch`: web::Query<WatchQuery> - `?watch=true` to stream the changes of the instances as server-sent events instead, `&from_version=<n>` to resume the stream after an event.
+    /// * `read`: web::Query<ReadOptions> - `?consistency=eventual` to read the instances from the cache of the controller.
+    /// * `req`: HttpRequest - Its `Last-Event-ID` header resumes the stream as `from_version` does.
+    pub async fn get_all_instances(
+        namespace: web::Path<String>,
+        pagination: Option<web::Query<Pagination>>,
+        filter: web::Query<InstanceFilter>,
+        watch: web::Query<WatchQuery>,
+        read: web::Query<ReadOptions>,
+        data: web::Data<ActixAppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache)
+                    .with_consistency(read.consistency, &data.read_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        if watch.watch {
+            let from_version = watch.from_version.or_else(|| {
+                req.headers()
+                    .get(LAST_EVENT_ID_HEADER)
+                    .and_then(|value| value.to_str().ok()?.parse().ok())
+            });
+            return match instance_service
+                .watch_instances(&namespace, filter.into_inner(), from_version)
+                .await
+            {
+                Ok(events) => HttpResponse::Ok()
+                    .content_type("text/event-stream")
+                    .streaming(events.map(|event| {
+                        event.to_sse().map(web::Bytes::from).map_err(|err| {
+                            actix_web::error::ErrorInternalServerError(err.to_problem().detail)
+                        })
+                    })),
+                Err(e) => e.to_http(),
+            };
+        }
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        instance_service
+            .get_all_instances(&pagination, &namespace, &filter)
+            .await
+            .to_http()
+    }
+
+    /// `patch_instance` is an async function that handle **/instance/\<namespace>/<instance_id>** route (PATCH)
+    /// # Description:
+    /// * Re-create an instance from the current definition of its workload
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    /// * `dry_run`: web::Query<DryRunQuery> - `?dry_run=true` to return the replacement without re-creating the instance.
+    pub async fn patch_instance(
+        params: web::Path<(String, String)>,
+        dry_run: web::Query<DryRunQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache)
+                    .with_dry_run(dry_run.dry_run),
+                Err(e) => return e.to_http(),
+            };
+
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .with_dry_run(dry_run.dry_run)
+            .review(
+                ResourceKind::Instance,
+                Operation::Update,
+                &namespace,
+                &instance_id,
+                serde_json::Value::Null,
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
+        instance_service
+            .patch_instance(&instance_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `restart_instance` is an async function that handle **/instance/\<namespace>/<instance_id>/restart** route (POST)
+    /// # Description:
+    /// * Restart an instance in place, keeping its id and its IP
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    pub async fn restart_instance(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .review(
+                ResourceKind::Instance,
+                Operation::Update,
+                &namespace,
+                &instance_id,
+                serde_json::Value::Null,
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
+        instance_service
+            .restart_instance(&instance_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `evict_instance` is an async function that handle **/instance/\<namespace>/<instance_id>/evict** route (POST)
+    /// # Description:
+    /// * Stop an instance for a reason other than its deletion, kept on the instance
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    /// * `body`: web::Json<Eviction> - The reason of the eviction (`node_drain`, `quota` or `preemption`) and its details.
+    pub async fn evict_instance(
+        params: web::Path<(String, String)>,
+        body: web::Json<Eviction>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        let eviction = body.into_inner();
+
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .review(
+                ResourceKind::Instance,
+                Operation::Update,
+                &namespace,
+                &instance_id,
+                serde_json::to_value(&eviction).unwrap_or_default(),
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
+        instance_service
+            .evict_instance(&instance_id, &namespace, eviction)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `migrate_instance` is an async function that handle **/instance/\<namespace>/<instance_id>/migrate** route (POST)
+    /// # Description:
+    /// * Move a running instance to another node without restarting it, keeping its id and its IP (experimental)
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    /// * `body`: web::Json<InstanceMigrationDTO> - The node the instance is moved to.
+    pub async fn migrate_instance(
+        params: web::Path<(String, String)>,
+        body: web::Json<InstanceMigrationDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        let migration = body.into_inner();
+
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .review(
+                ResourceKind::Instance,
+                Operation::Update,
+                &namespace,
+                &instance_id,
+                serde_json::to_value(&migration).unwrap_or_default(),
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
+        instance_service
+            .migrate_instance(&instance_id, &namespace, &migration.node_id)
+            .await
+            .map_or_else(|e| e.to_http(), |i| i.to_http())
+    }
+
+    /// `instance_logs` is an async function that handle **/instance/\<namespace>/<instance_id>/logs** route (GET)
+    /// # Description:
+    /// * Stream the output of an instance from its node, as plain text
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    /// * `query`: web::Query<LogsQuery> - `?follow=true` to keep streaming the new output, `&tail=<n>` to start from the last lines.
+    pub async fn instance_logs(
+        params: web::Path<(String, String)>,
+        query: web::Query<LogsQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        match instance_service
+            .instance_logs(&instance_id, &namespace, query.follow, query.tail)
+            .await
+        {
+            Ok(chunks) => HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .streaming(chunks.map(|chunk| {
+                    chunk
+                        .map(web::Bytes::from)
+                        .map_err(|err| actix_web::error::ErrorBadGateway(err.to_problem().detail))
+                })),
+            Err(e) => e.to_http(),
+        }
+    }
+
+    /// `instance_exec` is an async function that handle **/instance/\<namespace>/<instance_id>/exec** route (GET)
+    /// # Description:
+    /// * Run a command in an instance over a websocket, its input and output being binary messages
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    /// * `query`: web::Query<Vec<(String, String)>> - `?command=<arg>` for each argument of the command, `&tty=true` to run it in a terminal.
+    pub async fn instance_exec(
+        req: HttpRequest,
+        body: web::Payload,
+        params: web::Path<(String, String)>,
+        query: web::Query<Vec<(String, String)>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+        let command: Vec<String> = query
+            .iter()
+            .filter(|(key, _)| key == "command")
+            .map(|(_, value)| value.clone())
+            .collect();
+        let tty = query
+            .iter()
+            .any(|(key, value)| key == "tty" && value == "true");
+        if command.is_empty() {
+            return Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_query",
+                "The command to run is missing",
+            )
+            .to_http();
+        }
+
+        // the handshake is checked before the command runs, the errors of the session are still
+        // answered as problems until the response is sent
+        let (response, session, messages) = match actix_ws::handle(&req, body) {
+            Ok(handshake) => handshake,
+            Err(err) => return HttpResponse::from_error(err),
+        };
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        match instance_service
+            .instance_exec(&instance_id, &namespace, command, tty)
+            .await
+        {
+            Ok((inputs, outputs)) => {
+                actix_web::rt::spawn(exec::relay(session, messages, inputs, outputs));
+                response
+            }
+            Err(e) => e.to_http(),
+        }
+    }
+
+    /// `delete_instance` is an async function that handle **/instance/\<namespace>/<instance_id>** route (DELETE)
+    /// # Description:
+    /// * Destroy an instance
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the instance id.
+    pub async fn delete_instance(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, instance_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .review(
+                ResourceKind::Instance,
+                Operation::Delete,
+                &namespace,
+                &instance_id,
+                serde_json::Value::Null,
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
+        match instance_service
+            .delete_instance(&instance_id, &namespace)
+            .await
+        {
+            Ok(()) => HttpResponse::build(StatusCode::NO_CONTENT).body("Remove successfully"),
+            Err(e) => e.to_http(),
+        }
+    }
+}
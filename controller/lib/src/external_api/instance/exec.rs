@@ -0,0 +1,118 @@
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use log::debug;
+use proto::agent::{exec_input::Input, exec_output::Output, ExecInput, ExecOutput, TerminalSize};
+use tokio::sync::mpsc;
+use tonic::Streaming;
+
+use super::model::ExecMessage;
+
+/// The longest reason a close frame carries, its payload being limited to 125 bytes.
+const MAX_CLOSE_REASON: usize = 123;
+
+/// Relays an exec session between the websocket of the client and the scheduler. The binary
+/// messages of the client are written to the stdin of the command and its resizes applied, the
+/// stdin being closed once the client closes its side. The output of the command is sent back
+/// as binary messages, then its exit code before the socket is closed, or the error of the
+/// session as the reason of the close.
+///
+/// Arguments:
+///
+/// * `session`: The sending side of the websocket.
+/// * `messages`: The messages of the client.
+/// * `inputs`: The inputs of the exec session.
+/// * `outputs`: The output of the exec session.
+pub async fn relay(
+    mut session: Session,
+    mut messages: MessageStream,
+    inputs: mpsc::Sender<ExecInput>,
+    mut outputs: Streaming<ExecOutput>,
+) {
+    let mut inputs = Some(inputs);
+    let error = loop {
+        tokio::select! {
+            message = messages.recv(), if inputs.is_some() => {
+                let input = match message {
+                    Some(Ok(Message::Binary(data))) => Input::Stdin(data.to_vec()),
+                    Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                        Ok(ExecMessage::Resize { cols, rows }) => Input::Resize(TerminalSize {
+                            cols: cols.into(),
+                            rows: rows.into(),
+                        }),
+                        _ => {
+                            debug!("Ignoring exec message {}", text);
+                            continue;
+                        }
+                    },
+                    Some(Ok(Message::Ping(bytes))) => {
+                        _ = session.pong(&bytes).await;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                        inputs = None;
+                        continue;
+                    }
+                    Some(Ok(_)) => continue,
+                };
+                let input = ExecInput {
+                    input: Some(input),
+                    ..Default::default()
+                };
+                if let Some(sender) = &inputs {
+                    if sender.send(input).await.is_err() {
+                        inputs = None;
+                    }
+                }
+            }
+            output = outputs.message() => match output.map(|output| output.map(|output| output.output)) {
+                Ok(Some(Some(Output::Stdout(data)))) => {
+                    if session.binary(data).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(Some(Some(Output::ExitCode(code)))) => {
+                    if let Ok(exit) = serde_json::to_string(&ExecMessage::Exit { code }) {
+                        _ = session.text(exit).await;
+                    }
+                    break None;
+                }
+                Ok(Some(Some(Output::Error(error)))) => break Some(error),
+                Ok(Some(None)) => {}
+                Ok(None) => break None,
+                Err(status) => break Some(status.message().to_string()),
+            }
+        }
+    };
+
+    _ = session.close(error.map(close_reason)).await;
+}
+
+/// Returns the reason of a session closed on an error, shortened to fit in the close frame.
+fn close_reason(mut error: String) -> CloseReason {
+    let mut end = error.len().min(MAX_CLOSE_REASON);
+    while !error.is_char_boundary(end) {
+        end -= 1;
+    }
+    error.truncate(end);
+
+    CloseReason {
+        code: CloseCode::Error,
+        description: Some(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_close_reason() {
+        let reason = close_reason("node 1 disconnected".to_string());
+        assert_eq!(reason.code, CloseCode::Error);
+        assert_eq!(reason.description.as_deref(), Some("node 1 disconnected"));
+
+        let reason = close_reason("é".repeat(100));
+        let description = reason.description.unwrap();
+        assert_eq!(description.len(), 122);
+        assert!(description.chars().all(|c| c == 'é'));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+use etcd_client::Error;
+use futures_util::TryStreamExt;
+
+use super::model::{Instance, InstanceFilter, InstanceState};
+use crate::etcd::EtcdClient;
+
+/// Secondary indexes of the instances. Every instance has a key per indexed property, holding
+/// its id, so that the instances in a given state or on a given node are found with a prefix scan
+/// instead of reading every instance of the namespace.
+///
+/// * `index.instance.<namespace>.state.<state>.<id>`
+/// * `index.instance.<namespace>.node.<node_id>.<id>`, once the instance is placed on a node
+///
+/// The controller rebuilds them on start, see `InstanceService::rebuild_indexes`, so the instances
+/// stored before them are indexed as well.
+fn prefix(namespace: &str) -> String {
+    format!("index.instance.{}.", namespace)
+}
+
+fn state_prefix(namespace: &str, state: &InstanceState) -> String {
+    format!("{}state.{:?}.", prefix(namespace), state)
+}
+
+fn node_prefix(namespace: &str, node_id: &str) -> String {
+    format!("{}node.{}.", prefix(namespace), node_id)
+}
+
+/// Returns the index keys of an instance.
+pub fn index_keys(instance: &Instance) -> Vec<String> {
+    let mut keys = vec![format!(
+        "{}{}",
+        state_prefix(&instance.namespace, &instance.status.state),
+        instance.id
+    )];
+    if !instance.node_id.is_empty() {
+        keys.push(format!(
+            "{}{}",
+            node_prefix(&instance.namespace, &instance.node_id),
+            instance.id
+        ));
+    }
+    keys
+}
+
+/// It replaces the index keys of the `previous` version of an instance by the keys of its
+/// `current` version. `None` stands for an instance which doesn't exist (yet or anymore).
+pub async fn update_index(
+    etcd_service: &mut EtcdClient,
+    previous: Option<&Instance>,
+    current: Option<&Instance>,
+) -> Result<(), Error> {
+    let previous_keys = previous.map(index_keys).unwrap_or_default();
+    let current_keys = current.map(index_keys).unwrap_or_default();
+
+    for key in previous_keys.iter().filter(|k| !current_keys.contains(k)) {
+        etcd_service.delete(key).await;
+    }
+    if let Some(instance) = current {
+        for key in current_keys.iter().filter(|k| !previous_keys.contains(k)) {
+            etcd_service.put(key, &instance.id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the index keys to delete, held by none of `instances`, and the missing ones to write
+/// with the id of their instance, given the `existing` keys of every namespace.
+pub fn index_diff(
+    existing: &HashSet<String>,
+    instances: &[Instance],
+) -> (Vec<String>, Vec<(String, String)>) {
+    let expected: HashMap<String, &str> = instances
+        .iter()
+        .flat_map(|instance| {
+            index_keys(instance)
+                .into_iter()
+                .map(|key| (key, instance.id.as_str()))
+        })
+        .collect();
+
+    let mut stale: Vec<String> = existing
+        .iter()
+        .filter(|key| !expected.contains_key(*key))
+        .cloned()
+        .collect();
+    stale.sort();
+    let mut missing: Vec<(String, String)> = expected
+        .into_iter()
+        .filter(|(key, _)| !existing.contains(key))
+        .map(|(key, id)| (key, id.to_string()))
+        .collect();
+    missing.sort();
+    (stale, missing)
+}
+
+/// It brings the index keys of every namespace back to the instances stored: the keys of the
+/// instances stored before the indexes existed, or whose index write failed, are written, and
+/// the keys left by a state or a node the instance no longer has are deleted. The keys are read
+/// before the instances, a key written meanwhile for a newer version of an instance is kept.
+///
+/// Returns the number of instances indexed.
+pub async fn rebuild(etcd_service: &mut EtcdClient) -> Result<usize, Error> {
+    let existing: HashSet<String> = etcd_service
+        .scan_prefix("index.instance.", None)
+        .map_ok(|(key, _)| key)
+        .try_collect()
+        .await?;
+    // an instance which can't be read would lose its keys, the rebuild fails instead
+    let instances: Vec<Instance> = etcd_service
+        .scan_prefix("instance.", None)
+        .try_filter_map(|(_, value)| async move { Ok(serde_json::from_str(&value).ok()) })
+        .try_collect()
+        .await?;
+
+    let (stale, missing) = index_diff(&existing, &instances);
+    for key in stale {
+        etcd_service.delete_checked(&key).await?;
+    }
+    for (key, id) in missing {
+        etcd_service.put(&key, &id).await?;
+    }
+    Ok(instances.len())
+}
+
+/// Returns the ids of the instances of a namespace matching every criteria of the filter, or
+/// `None` if the filter has no criteria.
+pub async fn find_ids(
+    etcd_service: &mut EtcdClient,
+    namespace: &str,
+    filter: &InstanceFilter,
+) -> Option<HashSet<String>> {
+    let mut prefixes = vec![];
+    if let Some(state) = &filter.state {
+        prefixes.push(state_prefix(namespace, state));
+    }
+    if let Some(node) = &filter.node {
+        prefixes.push(node_prefix(namespace, node));
+    }
+
+    let mut ids: Option<HashSet<String>> = None;
+    for prefix in prefixes {
+        let matching: HashSet<String> = etcd_service
+            .get_all_with_prefix(&prefix)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        ids = Some(match ids {
+            Some(ids) => ids.intersection(&matching).cloned().collect(),
+            None => matching,
+        });
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+
+    fn instance(state: InstanceState, node_id: &str) -> Instance {
+        Instance {
+            node_id: node_id.to_string(),
+            ..test_instance("42", state)
+        }
+    }
+
+    #[test]
+    fn test_index_keys_unplaced_instance() {
+        assert_eq!(
+            index_keys(&instance(InstanceState::Scheduling, "")),
+            vec!["index.instance.default.state.Scheduling.42"]
+        );
+    }
+
+    #[test]
+    fn test_index_diff() {
+        let existing: HashSet<String> = [
+            "index.instance.default.state.Scheduling.42",
+            "index.instance.default.state.Running.42",
+            "index.instance.default.node.node-2.42",
+            "index.instance.default.state.Running.deleted",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        let (stale, missing) = index_diff(&existing, &[instance(InstanceState::Running, "node-1")]);
+        assert_eq!(
+            stale,
+            vec![
+                "index.instance.default.node.node-2.42",
+                "index.instance.default.state.Running.deleted",
+                "index.instance.default.state.Scheduling.42",
+            ]
+        );
+        assert_eq!(
+            missing,
+            vec![(
+                "index.instance.default.node.node-1.42".to_string(),
+                "42".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_index_keys_placed_instance() {
+        assert_eq!(
+            index_keys(&instance(InstanceState::Running, "node-1")),
+            vec![
+                "index.instance.default.state.Running.42",
+                "index.instance.default.node.node-1.42"
+            ]
+        );
+    }
+}
@@ -0,0 +1,5 @@
+pub mod controller;
+pub mod exec;
+pub mod index;
+pub mod model;
+pub mod service;
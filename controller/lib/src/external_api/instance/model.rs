@@ -0,0 +1,635 @@
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+use crate::ipam::IpamError;
+
+use crate::external_api::workload::model::{
+    parse_platform, Container, CpuPolicy, DisruptionBudget, Ports, Ressources, SecurityContext,
+    Spread, Type, Workload, WorkloadError, WorkloadKind,
+};
+
+pub enum InstanceError {
+    InstanceNotFound,
+    IdempotencyConflict(String),
+    DisruptionBudget(String),
+    NotMigratable(String),
+    VersionCompacted(i64),
+    Workload(WorkloadError),
+    Ipam(IpamError),
+    Etcd(String),
+    Grpc(String),
+    JsonToInstance(String),
+    InstanceToJson(String),
+}
+
+impl InstanceError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            InstanceError::InstanceNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "instance_not_found",
+                "Instance not found",
+            ),
+            InstanceError::IdempotencyConflict(err) => Problem::new(
+                StatusCode::CONFLICT,
+                "idempotency_conflict",
+                err.to_string(),
+            ),
+            InstanceError::DisruptionBudget(err) => Problem::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "disruption_budget_exceeded",
+                format!("The instance can't be disrupted yet: {}", err),
+            ),
+            InstanceError::NotMigratable(err) => Problem::new(
+                StatusCode::CONFLICT,
+                "instance_not_migratable",
+                format!("The instance can't be migrated: {}", err),
+            ),
+            InstanceError::Workload(err) => err.to_problem(),
+            InstanceError::Ipam(err) => err.to_problem(),
+            InstanceError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            InstanceError::VersionCompacted(version) => Problem::new(
+                StatusCode::GONE,
+                "resource_version_compacted",
+                format!(
+                    "The changes since version {} were compacted, list the instances again",
+                    version
+                ),
+            ),
+            InstanceError::Grpc(err) => Problem::new(
+                StatusCode::BAD_GATEWAY,
+                "scheduler_error",
+                format!("Scheduler error: {}", err),
+            ),
+            InstanceError::JsonToInstance(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_instance",
+                format!("Error while converting JSON string to instance : {}", err),
+            ),
+            InstanceError::InstanceToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "instance_serialization_failed",
+                format!("Error while converting the instance to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// Liveness state of an instance, as reported by the scheduler. A new instance is `Scheduling`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub enum InstanceState {
+    Running,
+    Starting,
+    Stopped,
+    Stopping,
+    Destroying,
+    Terminated,
+    Crashed,
+    Failed,
+    #[default]
+    Scheduling,
+    Scheduled,
+    /// The image of the instance is being pulled, the progress is in the status description
+    Pulling,
+    /// The instance waits for the dependencies of its workload, it isn't sent to the scheduler
+    /// until they are ready
+    Blocked,
+}
+
+impl InstanceState {
+    /// Returns `true` if the instance ran to completion or failed, it won't run again unless
+    /// restarted.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            InstanceState::Terminated | InstanceState::Failed | InstanceState::Crashed
+        )
+    }
+}
+
+impl From<proto::scheduler::Status> for InstanceState {
+    fn from(status: proto::scheduler::Status) -> Self {
+        match status {
+            proto::scheduler::Status::Running => InstanceState::Running,
+            proto::scheduler::Status::Starting => InstanceState::Starting,
+            proto::scheduler::Status::Stopped => InstanceState::Stopped,
+            proto::scheduler::Status::Stopping => InstanceState::Stopping,
+            proto::scheduler::Status::Destroying => InstanceState::Destroying,
+            proto::scheduler::Status::Terminated => InstanceState::Terminated,
+            proto::scheduler::Status::Failed => InstanceState::Failed,
+            proto::scheduler::Status::Scheduling => InstanceState::Scheduling,
+            proto::scheduler::Status::Scheduled => InstanceState::Scheduled,
+            proto::scheduler::Status::Pulling => InstanceState::Pulling,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct InstanceStatus {
+    pub state: InstanceState,
+    pub status_description: String,
+}
+
+/// Why an instance was stopped by the cluster rather than deleted by a user.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+    /// Its node is drained, e.g. before its maintenance
+    NodeDrain,
+    /// Its namespace exceeds its quota
+    Quota,
+    /// Its resources are taken by an instance of higher priority
+    Preemption,
+}
+
+/// The eviction of an instance, sent to `POST /instance/<namespace>/<id>/evict` and kept on the
+/// instance.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Eviction {
+    pub reason: EvictionReason,
+    /// Details of the eviction, e.g. the node being drained
+    #[serde(default)]
+    pub message: String,
+}
+
+/// The body of `POST /instance/<namespace>/<id>/migrate`, the node the instance is moved to.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct InstanceMigrationDTO {
+    pub node_id: String,
+}
+
+impl From<Eviction> for proto::scheduler::Eviction {
+    fn from(eviction: Eviction) -> Self {
+        let reason = match eviction.reason {
+            EvictionReason::NodeDrain => proto::scheduler::EvictionReason::NodeDrain,
+            EvictionReason::Quota => proto::scheduler::EvictionReason::Quota,
+            EvictionReason::Preemption => proto::scheduler::EvictionReason::Preemption,
+        };
+        proto::scheduler::Eviction {
+            reason: reason.into(),
+            message: eviction.message,
+        }
+    }
+}
+
+impl From<proto::scheduler::Eviction> for Eviction {
+    fn from(eviction: proto::scheduler::Eviction) -> Self {
+        let reason = match eviction.reason() {
+            proto::scheduler::EvictionReason::NodeDrain => EvictionReason::NodeDrain,
+            proto::scheduler::EvictionReason::Quota => EvictionReason::Quota,
+            proto::scheduler::EvictionReason::Preemption => EvictionReason::Preemption,
+        };
+        Eviction {
+            reason,
+            message: eviction.message,
+        }
+    }
+}
+
+/// Criteria of the instances listed by `GET /instance/<namespace>`, every criteria is optional.
+#[derive(Deserialize, Default)]
+pub struct InstanceFilter {
+    pub state: Option<InstanceState>,
+    pub node: Option<String>,
+}
+
+impl InstanceFilter {
+    pub fn matches(&self, instance: &Instance) -> bool {
+        self.state
+            .as_ref()
+            .is_none_or(|state| *state == instance.status.state)
+            && self
+                .node
+                .as_ref()
+                .is_none_or(|node| *node == instance.node_id)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Instance {
+    pub id: String,
+    pub name: String,
+    pub workload_id: String,
+    pub r#type: Type,
+    pub uri: String,
+    pub environment: Vec<String>,
+    pub resources: Ressources,
+    pub ports: Vec<Ports>,
+    pub ip: String,
+    pub namespace: String,
+    /// Node the instance runs on, empty until the scheduler places it
+    #[serde(default)]
+    pub node_id: String,
+    pub status: InstanceStatus,
+    /// labels of the workload, to select its instances
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Seconds after which the instance is deleted once finished, copied from the workload
+    #[serde(default)]
+    pub ttl_seconds_after_finished: Option<u64>,
+    /// When the instance finished, in seconds since the unix epoch
+    #[serde(default)]
+    pub finished_at: Option<u64>,
+    /// Security settings of the container, copied from the workload
+    #[serde(default)]
+    pub security_context: SecurityContext,
+    /// Whether the instance runs to completion, copied from the workload
+    #[serde(default)]
+    pub kind: WorkloadKind,
+    /// Volumes of the node mounted in the container, only the instances of a `StatefulSet` have
+    /// one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<Volume>,
+    /// Whether the instance runs the canary of its workload instead of its definition
+    #[serde(default)]
+    pub canary: bool,
+    /// Containers started next to the main one, sharing its IP address, ports and volumes,
+    /// copied from the workload
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<Container>,
+    /// How the instances of the workload are spread over the nodes, copied from the workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<Spread>,
+    /// Placement constraint on the attributes of the nodes, copied from the workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    /// Pool of the nodes the instance is placed on, copied from the workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+    /// Running instances of the workload kept when the instance is moved, copied from the workload
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disruption_budget: Option<DisruptionBudget>,
+    /// Number of devices of each kind mounted into the container, copied from the workload
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub devices: HashMap<String, u32>,
+    /// Whether the container gets exclusive CPUs, copied from the workload
+    #[serde(default)]
+    pub cpu_policy: CpuPolicy,
+    /// Number of huge pages of each size mounted into the container, copied from the workload
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub huge_pages: HashMap<String, u64>,
+    /// Platforms the images are built for, copied from the workload
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// Why the cluster stopped the instance, unset unless it was evicted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub eviction: Option<Eviction>,
+    /// Revision of the definition of the workload the instance was created from
+    #[serde(default)]
+    pub revision: u64,
+}
+
+/// A volume of a node mounted in the container of an instance, it outlives the instance.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Volume {
+    pub name: String,
+    /// Mount point in the container
+    pub path: String,
+}
+
+impl From<Volume> for proto::agent::Volume {
+    fn from(volume: Volume) -> Self {
+        proto::agent::Volume {
+            name: volume.name,
+            path: volume.path,
+        }
+    }
+}
+
+impl Instance {
+    /// Creates a new instance record from a workload. The instance starts in the `Scheduling`
+    /// state and has no IP address until the IPAM allocates it one.
+    pub fn from_workload(id: String, workload: Workload) -> Self {
+        Instance {
+            name: format!("{}-{}", workload.name, id),
+            id,
+            workload_id: workload.id,
+            r#type: workload.workload_type,
+            uri: workload.uri,
+            environment: workload.environment,
+            resources: workload.resources,
+            ports: workload.ports,
+            ip: String::new(),
+            namespace: workload.namespace,
+            node_id: String::new(),
+            status: InstanceStatus {
+                state: InstanceState::Scheduling,
+                status_description: String::new(),
+            },
+            labels: workload.labels,
+            ttl_seconds_after_finished: workload.ttl_seconds_after_finished,
+            finished_at: None,
+            security_context: workload.security_context,
+            kind: workload.kind,
+            volumes: vec![],
+            canary: false,
+            sidecars: workload.sidecars,
+            spread: workload.spread,
+            constraint: workload.constraint,
+            pool: workload.pool,
+            disruption_budget: workload.disruption_budget,
+            devices: workload.devices,
+            cpu_policy: workload.cpu_policy,
+            huge_pages: workload.huge_pages,
+            platforms: workload.platforms,
+            eviction: None,
+            revision: workload.revision,
+        }
+    }
+
+    /// Returns `true` if the instance must run on the node it was placed on: the instances of a
+    /// `DaemonSet`, and the ones of a `StatefulSet` with a volume.
+    pub fn is_pinned(&self) -> bool {
+        match self.kind {
+            WorkloadKind::DaemonSet => true,
+            WorkloadKind::StatefulSet => !self.volumes.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Returns the ordinal of an instance of a `StatefulSet`, `None` if its name isn't one of the
+    /// stable names `<workload>-<ordinal>`.
+    pub fn ordinal(&self) -> Option<u32> {
+        let workload_name = self
+            .workload_id
+            .strip_prefix(&format!("{}.", self.namespace))?;
+        parse_ordinal(workload_name, &self.name)
+    }
+
+    /// Records when the instance finished, the timestamp is cleared if the instance runs again.
+    pub fn update_finished_at(&mut self, now: u64) {
+        if !self.status.state.is_finished() {
+            self.finished_at = None;
+        } else if self.finished_at.is_none() {
+            self.finished_at = Some(now);
+        }
+    }
+
+    /// Returns `true` if the instance finished more than its TTL ago.
+    pub fn is_expired(&self, now: u64) -> bool {
+        match (self.ttl_seconds_after_finished, self.finished_at) {
+            (Some(ttl), Some(finished_at)) => now >= finished_at.saturating_add(ttl),
+            _ => false,
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => InstanceError::InstanceToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Returns an instance of the workload `default.web` in the given state, not placed on a node.
+/// The tests set the other fields they need with the struct update syntax.
+#[cfg(test)]
+pub(crate) fn test_instance(id: &str, state: InstanceState) -> Instance {
+    Instance {
+        id: id.to_string(),
+        name: format!("web-{}", id),
+        workload_id: "default.web".to_string(),
+        uri: "nginx".to_string(),
+        namespace: "default".to_string(),
+        status: InstanceStatus {
+            state,
+            status_description: String::new(),
+        },
+        ..Default::default()
+    }
+}
+
+/// Returns the stable name of the instance of a `StatefulSet` with the given ordinal.
+pub fn stateful_name(workload_name: &str, ordinal: u32) -> String {
+    format!("{}-{}", workload_name, ordinal)
+}
+
+/// Returns the ordinal of a stable name `<workload>-<ordinal>`, the ordinals are written
+/// without leading zeros.
+pub fn parse_ordinal(workload_name: &str, name: &str) -> Option<u32> {
+    let ordinal = name.strip_prefix(workload_name)?.strip_prefix('-')?;
+    ordinal
+        .parse::<u32>()
+        .ok()
+        .filter(|parsed| parsed.to_string() == ordinal)
+}
+
+impl From<Instance> for proto::scheduler::Instance {
+    fn from(instance: Instance) -> Self {
+        let pinned = instance.is_pinned();
+        proto::scheduler::Instance {
+            id: instance.id,
+            name: instance.name,
+            r#type: proto::scheduler::Type::from(instance.r#type).into(),
+            status: proto::scheduler::Status::Scheduling.into(),
+            uri: instance.uri,
+            environnement: instance.environment,
+            resource: Some(proto::scheduler::Resource {
+                limit: Some(proto::scheduler::ResourceSummary {
+                    cpu: instance.resources.cpu,
+                    memory: instance.resources.memory,
+                    disk: instance.resources.disk,
+                }),
+                usage: None,
+            }),
+            ports: instance
+                .ports
+                .into_iter()
+                .map(|port| proto::scheduler::Port {
+                    source: port.source,
+                    destination: port.destination,
+                })
+                .collect(),
+            ip: instance.ip,
+            security_context: Some(instance.security_context.into()),
+            kind: proto::agent::WorkloadKind::from(instance.kind).into(),
+            node_id: if pinned {
+                instance.node_id
+            } else {
+                String::new()
+            },
+            volumes: instance.volumes.into_iter().map(Into::into).collect(),
+            sidecars: instance.sidecars.into_iter().map(Into::into).collect(),
+            workload_id: instance.workload_id,
+            spread: instance
+                .spread
+                .map_or(proto::scheduler::Spread::Any, Into::into)
+                .into(),
+            constraint: instance.constraint.unwrap_or_default(),
+            pool: instance.pool.unwrap_or_default(),
+            namespace: instance.namespace,
+            min_available: instance
+                .disruption_budget
+                .map_or(0, |budget| budget.min_available),
+            restore: None,
+            devices: instance.devices,
+            exclusive_cpus: instance.cpu_policy.exclusive_cpus(instance.resources.cpu),
+            huge_pages: instance.huge_pages,
+            platforms: instance
+                .platforms
+                .iter()
+                .filter_map(|platform| parse_platform(platform))
+                .collect(),
+        }
+    }
+}
+
+/// Query of the output of an instance, `?follow=true&tail=<n>`. With `follow`, the new output is
+/// streamed until the client goes away, `tail` is the number of lines sent from the end of the
+/// output, every line if not set.
+#[derive(Deserialize, Default)]
+pub struct LogsQuery {
+    #[serde(default)]
+    pub follow: bool,
+    #[serde(default)]
+    pub tail: Option<u32>,
+}
+
+/// Control message of an exec session, sent as a websocket text message. The input and the
+/// output of the command are sent as binary messages.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExecMessage {
+    /// Size of the terminal of the client, sent when the session starts and on every resize
+    Resize { cols: u16, rows: u16 },
+    /// Exit code of the command, sent by the controller before it closes the session
+    Exit { code: i32 },
+}
+
+/// Query of a watch, `?watch=true&from_version=<n>`. With `from_version`, the changes made
+/// after this version, the `resource_version` of the last event received, are replayed first.
+#[derive(Deserialize, Default)]
+pub struct WatchQuery {
+    #[serde(default)]
+    pub watch: bool,
+    #[serde(default)]
+    pub from_version: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstanceEventType {
+    Added,
+    Modified,
+    /// The instance was stopped by the cluster, its `eviction` tells why
+    Evicted,
+    Deleted,
+}
+
+impl InstanceEventType {
+    /// Returns the type of the change of an instance written to etcd.
+    ///
+    /// Arguments:
+    ///
+    /// * `created`: The key of the instance was created by the write.
+    /// * `previous`: The instance before the write, if known.
+    /// * `instance`: The instance written.
+    pub fn of_put(created: bool, previous: Option<&Instance>, instance: &Instance) -> Self {
+        let evicted = instance.eviction.is_some()
+            && previous.is_some_and(|previous| previous.eviction.is_none());
+        if created {
+            InstanceEventType::Added
+        } else if evicted {
+            InstanceEventType::Evicted
+        } else {
+            InstanceEventType::Modified
+        }
+    }
+}
+
+/// `InstanceEvent` is a change of an instance, sent to the clients watching the instances.
+/// `resource_version` is the revision of etcd the change was made at, a watch resumed from it
+/// receives the changes which follow.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct InstanceEvent {
+    pub r#type: InstanceEventType,
+    pub instance: Instance,
+    pub resource_version: i64,
+}
+
+impl InstanceEvent {
+    /// Returns the change described by an etcd event on an instance key, `None` if the stored
+    /// value is not an instance.
+    pub fn from_etcd(event: &etcd_client::Event) -> Option<Self> {
+        match event.event_type() {
+            etcd_client::EventType::Put => {
+                let kv = event.kv()?;
+                let instance = serde_json::from_slice(kv.value()).ok()?;
+                let previous: Option<Instance> = event
+                    .prev_kv()
+                    .and_then(|prev_kv| serde_json::from_slice(prev_kv.value()).ok());
+                let r#type = InstanceEventType::of_put(
+                    kv.create_revision() == kv.mod_revision(),
+                    previous.as_ref(),
+                    &instance,
+                );
+                Some(InstanceEvent {
+                    r#type,
+                    instance,
+                    resource_version: kv.mod_revision(),
+                })
+            }
+            etcd_client::EventType::Delete => {
+                let instance = serde_json::from_slice(event.prev_kv()?.value()).ok()?;
+                Some(InstanceEvent {
+                    r#type: InstanceEventType::Deleted,
+                    instance,
+                    // the key of a deletion carries the revision it was deleted at
+                    resource_version: event.kv()?.mod_revision(),
+                })
+            }
+        }
+    }
+
+    /// Formats the event as a server-sent event, its id being its version so that the clients
+    /// reconnecting with `Last-Event-ID` resume from it.
+    pub fn to_sse(&self) -> Result<String, InstanceError> {
+        serde_json::to_string(self)
+            .map(|json| format!("id: {}\ndata: {}\n\n", self.resource_version, json))
+            .map_err(|err| InstanceError::InstanceToJson(err.to_string()))
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct InstanceDTO {
+    pub workload_name: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct InstanceVector {
+    pub instances: Vec<Instance>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl InstanceVector {
+    pub fn new(instances: Vec<Instance>) -> InstanceVector {
+        InstanceVector {
+            instances,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => InstanceError::InstanceToJson(err.to_string()).to_http(),
+        }
+    }
+}
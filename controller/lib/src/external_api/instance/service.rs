@@ -0,0 +1,1114 @@
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures_util::{future, stream, Stream, StreamExt};
+use log::{error, info};
+use proto::agent::{exec_input::Input, ExecInput, ExecOutput, ExecStart};
+use proto::scheduler::InstanceIdentifier;
+use tokio::sync::mpsc;
+use tonic::{Request, Streaming};
+use uuid::Uuid;
+
+use super::index;
+use super::model::{
+    stateful_name, Eviction, Instance, InstanceDTO, InstanceError, InstanceEvent, InstanceFilter,
+    InstanceState, InstanceStatus, InstanceVector, Volume,
+};
+use crate::dependency;
+use crate::disruption;
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::{Consistency, Pagination};
+use crate::external_api::generic::read_cache::ReadCache;
+use crate::external_api::shard::service::ShardService;
+use crate::external_api::workload::cache::WorkloadCache;
+use crate::external_api::workload::model::{Workload, WorkloadError, WorkloadKind};
+use crate::external_api::workload::service::WorkloadService;
+use crate::grpc_client::interface::SchedulerClientInterface;
+use crate::ipam::IpamService;
+use crate::tasks::BackgroundTasks;
+
+/// How long an idempotency key is remembered, a retried request is expected well before.
+const IDEMPOTENCY_KEY_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// How many inputs of an exec session can be queued, the client is read from again once they are
+/// sent.
+const EXEC_BUFFER: usize = 16;
+
+/// Returns the name of the workload of an instance, its id without the namespace.
+fn workload_name(instance: &Instance) -> &str {
+    instance
+        .workload_id
+        .strip_prefix(&format!("{}.", instance.namespace))
+        .unwrap_or(&instance.workload_id)
+}
+
+/// Applies a status sent by the scheduler to an instance.
+fn apply_status(instance: &mut Instance, status: &proto::scheduler::InstanceStatus) {
+    instance.status = InstanceStatus {
+        state: status.status().into(),
+        status_description: status.status_description.clone(),
+    };
+    if !status.node_id.is_empty() {
+        instance.node_id = status.node_id.clone();
+    }
+    if let Some(eviction) = status.eviction.clone() {
+        instance.eviction = Some(eviction.into());
+    }
+    instance.update_finished_at(unix_time());
+}
+
+/// Returns the current time, in seconds since the unix epoch.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `InstanceService` is the service used by the `InstanceController`. Instances are stored in etcd
+/// and their lifecycle is delegated to the scheduler.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `workload_service`: This is the service used to retrieve the workload of an instance.
+/// * `ipam_service`: This is the service allocating the IP addresses of the instances.
+/// * `shard_service`: This is the service finding the scheduler of each namespace.
+/// * `background_tasks`: The tasks writing the status of the instances, awaited on shutdown.
+/// * `read_cache`: The mirror of etcd the instances are read from if set, for the eventually
+///   consistent reads.
+/// * `dry_run`: If true, the instances created and re-created are validated but neither stored
+///   nor scheduled.
+pub struct InstanceService {
+    etcd_service: EtcdClient,
+    workload_service: WorkloadService,
+    ipam_service: IpamService,
+    shard_service: ShardService,
+    background_tasks: BackgroundTasks,
+    read_cache: Option<ReadCache>,
+    dry_run: bool,
+}
+
+impl InstanceService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<InstanceService, InstanceError> {
+        let etcd_service = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+
+        Ok(InstanceService {
+            ipam_service: IpamService::new(etcd_service.clone()),
+            shard_service: ShardService::with_client(etcd_service.clone(), scheduler_address),
+            etcd_service,
+            workload_service: WorkloadService::new(etcd_address)
+                .await
+                .map_err(InstanceError::Workload)?,
+            background_tasks: BackgroundTasks::new(),
+            read_cache: None,
+            dry_run: false,
+        })
+    }
+
+    /// Registers the tasks spawned by the service in `background_tasks`, so that the controller
+    /// waits for them before exiting.
+    pub fn with_background_tasks(mut self, background_tasks: &BackgroundTasks) -> Self {
+        self.background_tasks = background_tasks.clone();
+        self
+    }
+
+    /// Reads the workloads of the instances through `cache`.
+    pub fn with_workload_cache(mut self, cache: &WorkloadCache) -> Self {
+        self.workload_service = self.workload_service.with_cache(cache);
+        self
+    }
+
+    /// Validates the instances created and re-created without storing nor scheduling them if
+    /// `dry_run` is true.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Reads the instances from `read_cache` if `consistency` is eventual, instead of etcd.
+    pub fn with_consistency(mut self, consistency: Consistency, read_cache: &ReadCache) -> Self {
+        self.read_cache = (consistency == Consistency::Eventual).then(|| read_cache.clone());
+        self
+    }
+
+    pub async fn get_instance(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+    ) -> Result<Instance, InstanceError> {
+        let key = self.id(instance_id, namespace);
+        let cached = self.read_cache.as_ref().and_then(|cache| cache.get(&key));
+        let value = match cached {
+            Some(value) => value,
+            None => self.etcd_service.get(&key).await,
+        };
+        match value {
+            Some(instance) => serde_json::from_str(&instance)
+                .map_err(|err| InstanceError::JsonToInstance(err.to_string())),
+            None => Err(InstanceError::InstanceNotFound),
+        }
+    }
+
+    /// This function gets the instances of a namespace from etcd, paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    /// # Arguments:
+    ///
+    /// * `pagination`: The limit, the offset and the continue token of the listing.
+    /// * `namespace`: The namespace to filter by.
+    /// * `filter`: The state and the node of the instances, looked up in the secondary indexes.
+    ///
+    /// # Returns:
+    ///
+    /// A vector of instances
+    pub async fn get_all_instances(
+        &mut self,
+        pagination: &Pagination,
+        namespace: &str,
+        filter: &InstanceFilter,
+    ) -> InstanceVector {
+        // the cache holds every instance, the indexes aren't needed
+        let cached = self.read_cache.as_ref().and_then(|cache| {
+            cache.list_prefix(
+                &self.id("", namespace),
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |instance: &Instance| filter.matches(instance),
+            )
+        });
+        if let Some(listing) = cached {
+            return InstanceVector::new(listing.items).with_continue_token(listing.continue_token);
+        }
+
+        let ids = match index::find_ids(&mut self.etcd_service, namespace, filter).await {
+            Some(ids) => ids,
+            None => {
+                // if instance deserialize failed, we don't want to throw error, so we just don't add it to the vector
+                return match self
+                    .etcd_service
+                    .list_prefix(
+                        &self.id("", namespace),
+                        pagination.continue_token.as_deref(),
+                        pagination.offset,
+                        pagination.limit,
+                        |_: &Instance| true,
+                    )
+                    .await
+                {
+                    Ok(listing) => InstanceVector::new(listing.items)
+                        .with_continue_token(listing.continue_token),
+                    Err(_) => InstanceVector::new(vec![]),
+                };
+            }
+        };
+
+        // the ids found in the indexes are listed in the order of their keys, as the others
+        let mut keys: Vec<String> = ids
+            .iter()
+            .map(|id| self.id(id, namespace))
+            .filter(|key| {
+                pagination
+                    .continue_token
+                    .as_ref()
+                    .is_none_or(|token| key > token)
+            })
+            .collect();
+        keys.sort();
+
+        let mut instances = vec![];
+        let mut skipped = 0;
+        for key in keys {
+            // the index may lag behind the instance, so the instance is checked again
+            let Some(instance) = self
+                .etcd_service
+                .get(&key)
+                .await
+                .and_then(|value| serde_json::from_str::<Instance>(&value).ok())
+            else {
+                continue;
+            };
+            if !filter.matches(&instance) {
+                continue;
+            }
+            if skipped < pagination.offset {
+                skipped += 1;
+                continue;
+            }
+
+            instances.push(instance);
+            if pagination.limit > 0 && instances.len() == pagination.limit as usize {
+                return InstanceVector::new(instances).with_continue_token(Some(key));
+            }
+        }
+        InstanceVector::new(instances)
+    }
+
+    /// Returns the instances of every namespace, the instances which can't be deserialized are
+    /// skipped.
+    pub async fn get_instances_of_all_namespaces(&mut self) -> Vec<Instance> {
+        self.etcd_service
+            .get_all_with_prefix("instance.")
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|value| serde_json::from_str(value).ok())
+            .collect()
+    }
+
+    /// Brings the indexes of the instances of every namespace back to the instances stored, see
+    /// `index::rebuild`. It can run on every start.
+    ///
+    /// # Returns:
+    ///
+    /// The number of instances indexed.
+    pub async fn rebuild_indexes(&mut self) -> Result<usize, InstanceError> {
+        index::rebuild(&mut self.etcd_service)
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_string()))
+    }
+
+    /// It creates a new instance of a workload, stores it in etcd and asks the scheduler to
+    /// run it. Status updates streamed back by the scheduler are written to etcd in the
+    /// background.
+    ///
+    /// # Arguments:
+    ///
+    /// * `instance_dto`: InstanceDTO containing the name of the workload to instantiate
+    /// * `namespace`: The namespace of the workload
+    /// * `idempotency_key`: A key chosen by the client, the retries of a creation with the same
+    ///   key return the instance created by the first attempt.
+    ///
+    /// # Returns:
+    ///
+    /// The newly created instance.
+    pub async fn create_instance(
+        &mut self,
+        instance_dto: InstanceDTO,
+        namespace: &str,
+        idempotency_key: Option<&str>,
+    ) -> Result<Instance, InstanceError> {
+        let workload = self
+            .workload_service
+            .get_workload(&instance_dto.workload_name, namespace)
+            .await
+            .map_err(InstanceError::Workload)?;
+        let pending = self.pending_dependencies(&workload).await?;
+
+        let mut instance = Instance::from_workload(Uuid::new_v4().to_string(), workload);
+        // the idempotency key isn't claimed by a dry run
+        if self.dry_run {
+            return Ok(instance);
+        }
+
+        let idempotency_record = idempotency_key.map(|key| self.idempotency_id(key, namespace));
+        if let Some(record) = &idempotency_record {
+            let claimed = self
+                .etcd_service
+                .put_if_absent(record, &instance.id, Some(IDEMPOTENCY_KEY_TTL_SECONDS))
+                .await
+                .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+
+            if let Some(instance_id) = claimed {
+                return self
+                    .replay_creation(&instance_id, &instance.workload_id, namespace)
+                    .await;
+            }
+        }
+
+        let result = self.start_instance(&mut instance, &pending).await;
+
+        // the key is released so that the creation can be retried
+        if let (Err(_), Some(record)) = (&result, &idempotency_record) {
+            _ = self.etcd_service.delete(record).await;
+        }
+        result.map(|_| instance)
+    }
+
+    /// It creates a new instance of a `DaemonSet` workload pinned to a node, the scheduler
+    /// places it on this node only.
+    pub async fn create_instance_on_node(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+        node_id: &str,
+    ) -> Result<Instance, InstanceError> {
+        let workload = self
+            .workload_service
+            .get_workload(workload_name, namespace)
+            .await
+            .map_err(InstanceError::Workload)?;
+        let pending = self.pending_dependencies(&workload).await?;
+
+        let mut instance = Instance::from_workload(Uuid::new_v4().to_string(), workload);
+        instance.node_id = node_id.to_string();
+        self.start_instance(&mut instance, &pending).await?;
+        Ok(instance)
+    }
+
+    /// It creates the instance of a `StatefulSet` workload with the given ordinal. The instance
+    /// is named `<workload>-<ordinal>` and gets the address kept for this name, its volume is
+    /// named after it.
+    ///
+    /// # Arguments:
+    ///
+    /// * `workload_name`: The name of the `StatefulSet` workload.
+    /// * `namespace`: The namespace of the workload.
+    /// * `ordinal`: The ordinal of the instance.
+    /// * `node_id`: The node holding the volume of the instance, if it already has one.
+    pub async fn create_stateful_instance(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+        ordinal: u32,
+        node_id: Option<&str>,
+    ) -> Result<Instance, InstanceError> {
+        let workload = self
+            .workload_service
+            .get_workload(workload_name, namespace)
+            .await
+            .map_err(InstanceError::Workload)?;
+        let volume_path = workload
+            .stateful
+            .as_ref()
+            .and_then(|stateful| stateful.volume_path.clone());
+        let pending = self.pending_dependencies(&workload).await?;
+
+        let mut instance = Instance::from_workload(Uuid::new_v4().to_string(), workload);
+        instance.name = stateful_name(workload_name, ordinal);
+        if let Some(path) = volume_path {
+            instance.volumes = vec![Volume {
+                name: format!("kudo-{}-{}", namespace, instance.name),
+                path,
+            }];
+            instance.node_id = node_id.unwrap_or_default().to_string();
+        }
+        self.start_instance(&mut instance, &pending).await?;
+        Ok(instance)
+    }
+
+    /// It creates a new instance running the canary of a workload.
+    pub async fn create_canary_instance(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Instance, InstanceError> {
+        let canary = self
+            .workload_service
+            .get_workload(workload_name, namespace)
+            .await
+            .map_err(InstanceError::Workload)?
+            .canary
+            .ok_or(InstanceError::Workload(WorkloadError::CanaryNotFound))?;
+        let pending = self.pending_dependencies(&canary.version).await?;
+
+        let mut instance = Instance::from_workload(Uuid::new_v4().to_string(), *canary.version);
+        instance.canary = true;
+        self.start_instance(&mut instance, &pending).await?;
+        Ok(instance)
+    }
+
+    /// It promotes the canary of a workload: the instances running the canary are kept and the
+    /// other ones are re-created from the new definition.
+    pub async fn promote_canary(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Workload, InstanceError> {
+        let workload = self
+            .workload_service
+            .promote_canary(workload_name, namespace)
+            .await
+            .map_err(InstanceError::Workload)?;
+
+        for mut instance in self.get_instances_of_workload(&workload).await {
+            if instance.canary {
+                instance.canary = false;
+                instance.revision = workload.revision;
+                self.put_instance(&instance).await?;
+            } else if !instance.status.state.is_finished() {
+                self.patch_instance(&instance.id, namespace).await?;
+            }
+        }
+        Ok(workload)
+    }
+
+    /// It rolls back the canary of a workload: the instances running the canary are re-created
+    /// from the unchanged definition.
+    pub async fn rollback_canary(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Workload, InstanceError> {
+        let workload = self
+            .workload_service
+            .rollback_canary(workload_name, namespace)
+            .await
+            .map_err(InstanceError::Workload)?;
+
+        for mut instance in self.get_instances_of_workload(&workload).await {
+            if !instance.canary {
+                continue;
+            }
+            if instance.status.state.is_finished() {
+                instance.canary = false;
+                self.put_instance(&instance).await?;
+            } else {
+                self.patch_instance(&instance.id, namespace).await?;
+            }
+        }
+        Ok(workload)
+    }
+
+    /// Returns the instances of a workload, in its namespace.
+    pub async fn get_instances_of_workload(&mut self, workload: &Workload) -> Vec<Instance> {
+        self.get_all_instances(
+            &Pagination::default(),
+            &workload.namespace,
+            &InstanceFilter::default(),
+        )
+        .await
+        .instances
+        .into_iter()
+        .filter(|instance| instance.workload_id == workload.id)
+        .collect()
+    }
+
+    /// Returns the names of the dependencies of a workload which aren't ready yet, a missing
+    /// dependency isn't ready.
+    pub async fn pending_dependencies(
+        &mut self,
+        workload: &Workload,
+    ) -> Result<Vec<String>, InstanceError> {
+        let mut pending = vec![];
+        for name in &workload.depends_on {
+            let ready = match self
+                .workload_service
+                .get_workload(name, &workload.namespace)
+                .await
+            {
+                Ok(dependency) => {
+                    let instances = self.get_instances_of_workload(&dependency).await;
+                    dependency::is_ready(&dependency, &instances)
+                }
+                Err(WorkloadError::WorkloadNotFound) => false,
+                Err(err) => return Err(InstanceError::Workload(err)),
+            };
+            if !ready {
+                pending.push(name.clone());
+            }
+        }
+        Ok(pending)
+    }
+
+    /// It sends a `Blocked` instance to the scheduler once the dependencies of its workload are
+    /// ready, its description lists the ones still pending otherwise.
+    ///
+    /// # Returns:
+    ///
+    /// `true` if the instance was sent to the scheduler.
+    pub async fn unblock_instance(
+        &mut self,
+        instance: &mut Instance,
+    ) -> Result<bool, InstanceError> {
+        let mut workload = self
+            .workload_service
+            .get_workload(workload_name(instance), &instance.namespace)
+            .await
+            .map_err(InstanceError::Workload)?;
+        if let (true, Some(canary)) = (instance.canary, workload.canary.take()) {
+            workload = *canary.version;
+        }
+
+        let pending = self.pending_dependencies(&workload).await?;
+        if !pending.is_empty() {
+            let description = dependency::blocked_description(&pending);
+            if instance.status.status_description != description {
+                instance.status.status_description = description;
+                self.put_instance(instance).await?;
+            }
+            return Ok(false);
+        }
+
+        instance.status = InstanceStatus {
+            state: InstanceState::Scheduling,
+            status_description: String::new(),
+        };
+        self.put_instance(instance).await?;
+        self.schedule_instance(instance).await?;
+        Ok(true)
+    }
+
+    /// Allocates the address of a new instance, stores it and asks the scheduler to run it. The
+    /// instances of a `StatefulSet` get the address kept for their name. The instance is stored
+    /// as `Blocked` if some dependencies of its workload are `pending`, the dependency
+    /// controller sends it to the scheduler once they are ready. Nothing is done in a dry run.
+    async fn start_instance(
+        &mut self,
+        instance: &mut Instance,
+        pending: &[String],
+    ) -> Result<(), InstanceError> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let stateful = instance.kind == WorkloadKind::StatefulSet;
+        let ip = if stateful {
+            self.ipam_service
+                .allocate_sticky(&instance.namespace, &instance.name)
+                .await
+        } else {
+            self.ipam_service
+                .allocate(&instance.namespace, &instance.id)
+                .await
+        };
+        instance.ip = ip.map_err(InstanceError::Ipam)?.to_string();
+        if !pending.is_empty() {
+            instance.status = InstanceStatus {
+                state: InstanceState::Blocked,
+                status_description: dependency::blocked_description(pending),
+            };
+        }
+
+        if let Err(err) = self.put_instance(instance).await {
+            if !stateful {
+                self.ipam_service
+                    .release(&instance.namespace, &instance.ip)
+                    .await;
+            }
+            return Err(err);
+        }
+        if !pending.is_empty() {
+            info!(
+                "Instance {} blocked: {}",
+                instance.id, instance.status.status_description
+            );
+            return Ok(());
+        }
+        self.schedule_instance(instance).await
+    }
+
+    /// Returns the instance created by a previous request with the same idempotency key.
+    async fn replay_creation(
+        &mut self,
+        instance_id: &str,
+        workload_id: &str,
+        namespace: &str,
+    ) -> Result<Instance, InstanceError> {
+        match self.get_instance(instance_id, namespace).await {
+            Ok(instance) if instance.workload_id == workload_id => {
+                info!("Instance {} already created for this request", instance.id);
+                Ok(instance)
+            }
+            Ok(_) => Err(InstanceError::IdempotencyConflict(
+                "Idempotency key already used to create an instance of another workload"
+                    .to_string(),
+            )),
+            Err(InstanceError::InstanceNotFound) => Err(InstanceError::IdempotencyConflict(
+                "A request with the same idempotency key is in progress".to_string(),
+            )),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// It asks the scheduler to run an instance already stored in etcd. Status updates streamed
+    /// back by the scheduler are written to etcd in the background.
+    pub async fn schedule_instance(&mut self, instance: &Instance) -> Result<(), InstanceError> {
+        let mut scheduler_client = self.scheduler_client(&instance.namespace).await?;
+
+        let mut stream = scheduler_client
+            .create_instance(Request::new(instance.clone().into()))
+            .await
+            .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?
+            .into_inner();
+
+        let mut etcd_service = self.etcd_service.clone();
+        let key = self.id(&instance.id, &instance.namespace);
+        let id = instance.id.clone();
+
+        self.background_tasks.spawn(move |mut shutdown| async move {
+            'statuses: loop {
+                // a status being written is always written before shutting down
+                let status = tokio::select! {
+                    message = stream.message() => match message {
+                        Ok(Some(status)) => status,
+                        _ => break,
+                    },
+                    Ok(()) = shutdown.changed() => break,
+                };
+
+                // the status is written over the stored instance only if it wasn't modified
+                // meanwhile, a deleted instance is never written again
+                loop {
+                    let Some((json, revision)) = etcd_service.get_versioned(&key).await else {
+                        info!("Instance {} deleted, its status is no longer written", id);
+                        break 'statuses;
+                    };
+                    let previous: Instance = match serde_json::from_str(&json) {
+                        Ok(previous) => previous,
+                        Err(err) => {
+                            error!("Failed to deserialize instance {}: {}", id, err);
+                            break;
+                        }
+                    };
+                    let mut record = previous.clone();
+                    apply_status(&mut record, &status);
+
+                    let json = match serde_json::to_string(&record) {
+                        Ok(json) => json,
+                        Err(err) => {
+                            error!("Failed to serialize instance {}: {}", id, err);
+                            break;
+                        }
+                    };
+                    match etcd_service
+                        .put_if_version(&key, &json, Some(revision))
+                        .await
+                    {
+                        Ok(Some(_)) => {
+                            if let Err(err) = index::update_index(
+                                &mut etcd_service,
+                                Some(&previous),
+                                Some(&record),
+                            )
+                            .await
+                            {
+                                error!("Failed to update instance {} indexes: {}", id, err);
+                            }
+                            break;
+                        }
+                        // modified or deleted since it was read, read again
+                        Ok(None) => continue,
+                        Err(err) => {
+                            error!("Failed to update instance {} status: {}", id, err);
+                            break;
+                        }
+                    }
+                }
+            }
+            info!("Status stream of instance {} closed", id);
+        });
+
+        Ok(())
+    }
+
+    /// It re-creates an instance: the running instance is destroyed and a new one is created
+    /// from the current definition of its workload.
+    pub async fn patch_instance(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+    ) -> Result<Instance, InstanceError> {
+        let instance = self.get_instance(instance_id, namespace).await?;
+        // a dry run returns the replacement, the instance itself is left running
+        if !self.dry_run {
+            self.delete_instance(instance_id, namespace).await?;
+        }
+
+        let workload_name = workload_name(&instance).to_string();
+
+        // an instance of a `StatefulSet` keeps its name, and its node if it has a volume
+        if let (WorkloadKind::StatefulSet, Some(ordinal)) = (instance.kind, instance.ordinal()) {
+            let node_id = Some(instance.node_id.as_str()).filter(|node| !node.is_empty());
+            return self
+                .create_stateful_instance(&workload_name, namespace, ordinal, node_id)
+                .await;
+        }
+        // an instance of a `DaemonSet` stays on its node
+        if instance.kind == WorkloadKind::DaemonSet && !instance.node_id.is_empty() {
+            return self
+                .create_instance_on_node(&workload_name, namespace, &instance.node_id)
+                .await;
+        }
+        self.create_instance(InstanceDTO { workload_name }, namespace, None)
+            .await
+    }
+
+    /// It asks the scheduler to destroy an instance and removes it from etcd.
+    pub async fn delete_instance(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+    ) -> Result<(), InstanceError> {
+        let instance = self.get_instance(instance_id, namespace).await?;
+
+        // a blocked instance was never sent to the scheduler
+        if instance.status.state != InstanceState::Blocked {
+            let mut scheduler_client = self.scheduler_client(namespace).await?;
+
+            scheduler_client
+                .destroy_instance(Request::new(InstanceIdentifier {
+                    id: instance.id.clone(),
+                }))
+                .await
+                .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?;
+        }
+
+        self.remove_instance(&instance).await
+    }
+
+    /// It removes an instance and its indexes from etcd and releases its IP address, without
+    /// calling the scheduler. The address of an instance of a `StatefulSet` is kept for its name.
+    pub(crate) async fn remove_instance(
+        &mut self,
+        instance: &Instance,
+    ) -> Result<(), InstanceError> {
+        _ = self
+            .etcd_service
+            .delete(&self.id(&instance.id, &instance.namespace))
+            .await;
+        if !instance.ip.is_empty() && instance.kind != WorkloadKind::StatefulSet {
+            self.ipam_service
+                .release(&instance.namespace, &instance.ip)
+                .await;
+        }
+        index::update_index(&mut self.etcd_service, Some(instance), None)
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+        Ok(())
+    }
+
+    /// It releases the subnet of a namespace once it has no instance left.
+    pub async fn release_subnet(&mut self, namespace: &str) -> Result<(), InstanceError> {
+        self.ipam_service
+            .release_subnet(namespace)
+            .await
+            .map_err(InstanceError::Ipam)
+    }
+
+    /// Returns the namespace and the name of the instances of `StatefulSet` workloads holding an
+    /// address, whether they still exist or not.
+    pub async fn sticky_names(&self) -> Result<Vec<(String, String)>, InstanceError> {
+        self.ipam_service
+            .sticky_names()
+            .await
+            .map_err(InstanceError::Ipam)
+    }
+
+    /// It deletes the idempotency keys of the instances created in a namespace.
+    ///
+    /// Returns the number of keys deleted.
+    pub async fn delete_idempotency_keys(&mut self, namespace: &str) -> Result<i64, InstanceError> {
+        let prefix = self.idempotency_id("", namespace);
+        self.etcd_service
+            .delete_prefix(&prefix)
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_string()))
+    }
+
+    /// It releases the address kept for the name of an instance of a `StatefulSet`.
+    pub async fn release_sticky_address(&mut self, namespace: &str, name: &str) {
+        self.ipam_service.release_sticky(namespace, name).await;
+    }
+
+    /// It asks the scheduler to restart an instance in place, keeping its id and its IP. The
+    /// instance is marked as starting until the scheduler reports its new state.
+    pub async fn restart_instance(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+    ) -> Result<Instance, InstanceError> {
+        let mut instance = self.get_instance(instance_id, namespace).await?;
+        // a blocked instance isn't running yet, it is started with its dependencies
+        if instance.status.state == InstanceState::Blocked {
+            return Ok(instance);
+        }
+
+        let mut scheduler_client = self.scheduler_client(namespace).await?;
+
+        scheduler_client
+            .restart_instance(Request::new(InstanceIdentifier {
+                id: instance.id.clone(),
+            }))
+            .await
+            .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?;
+
+        instance.status = InstanceStatus {
+            state: InstanceState::Starting,
+            status_description: "Restarting".to_string(),
+        };
+        instance.update_finished_at(unix_time());
+        self.put_instance(&instance).await?;
+        Ok(instance)
+    }
+
+    /// It asks the scheduler to stop an instance for a reason other than its deletion, e.g. to
+    /// drain its node. The eviction is kept on the instance, which is marked as stopping until
+    /// the scheduler reports its new state.
+    pub async fn evict_instance(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+        eviction: Eviction,
+    ) -> Result<Instance, InstanceError> {
+        let mut instance = self.get_instance(instance_id, namespace).await?;
+        self.check_disruption_budget(&instance).await?;
+
+        // a blocked instance was never sent to the scheduler, it is stopped already
+        let state = if instance.status.state == InstanceState::Blocked {
+            InstanceState::Stopped
+        } else {
+            let mut scheduler_client = self.scheduler_client(namespace).await?;
+            scheduler_client
+                .evict_instance(&instance.id, eviction.clone().into())
+                .await
+                .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?;
+            InstanceState::Stopping
+        };
+
+        info!(
+            "Instance {} evicted ({:?}): {}",
+            instance.id, eviction.reason, eviction.message
+        );
+        instance.status = InstanceStatus {
+            state,
+            status_description: format!("Evicted: {}", eviction.message),
+        };
+        instance.eviction = Some(eviction);
+        self.put_instance(&instance).await?;
+        Ok(instance)
+    }
+
+    /// It asks the scheduler to move a running instance to another node without restarting it,
+    /// keeping its id and its IP. The instance is marked as starting until the target node
+    /// reports it, it stays on its node if the migration fails. It is experimental.
+    pub async fn migrate_instance(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+        node_id: &str,
+    ) -> Result<Instance, InstanceError> {
+        let mut instance = self.get_instance(instance_id, namespace).await?;
+        if instance.status.state != InstanceState::Running {
+            return Err(InstanceError::NotMigratable(format!(
+                "it is {:?}, not running",
+                instance.status.state
+            )));
+        }
+        if instance.is_pinned() {
+            return Err(InstanceError::NotMigratable(
+                "it is pinned to its node".to_string(),
+            ));
+        }
+        if instance.node_id == node_id {
+            return Err(InstanceError::NotMigratable(format!(
+                "it already runs on node {}",
+                node_id
+            )));
+        }
+
+        let mut scheduler_client = self.scheduler_client(namespace).await?;
+        scheduler_client
+            .migrate_instance(&instance.id, node_id)
+            .await
+            .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?;
+
+        info!(
+            "Migrating instance {} from node {} to node {}",
+            instance.id, instance.node_id, node_id
+        );
+        instance.status = InstanceStatus {
+            state: InstanceState::Starting,
+            status_description: format!("Migrating to node {}", node_id),
+        };
+        self.put_instance(&instance).await?;
+        Ok(instance)
+    }
+
+    /// Streams the output of an instance from its node, the last `tail` lines or every line if not
+    /// set, then the next ones if `follow` is set, until the client goes away. The stream ends
+    /// with an error if the node fails to send the output.
+    pub async fn instance_logs(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+        follow: bool,
+        tail: Option<u32>,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>, InstanceError>>, InstanceError> {
+        let instance = self.get_instance(instance_id, namespace).await?;
+
+        let mut scheduler_client = self.scheduler_client(namespace).await?;
+        let chunks = scheduler_client
+            .instance_logs(&instance.id, follow, tail.unwrap_or(0))
+            .await
+            .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?
+            .into_inner();
+
+        Ok(stream::unfold(Some(chunks), |chunks| async move {
+            let mut chunks = chunks?;
+            match chunks.message().await {
+                Ok(Some(chunk)) if chunk.eof => None,
+                Ok(Some(chunk)) => Some((Ok(chunk.data), Some(chunks))),
+                Ok(None) => None,
+                Err(status) => Some((Err(InstanceError::Grpc(status.message().to_string())), None)),
+            }
+        }))
+    }
+
+    /// Runs a command in an instance on its node. The inputs sent on the returned sender are
+    /// forwarded to the command, its stdin being closed once the sender is dropped, and its output
+    /// is streamed until it exits, the last message being its exit code.
+    pub async fn instance_exec(
+        &mut self,
+        instance_id: &str,
+        namespace: &str,
+        command: Vec<String>,
+        tty: bool,
+    ) -> Result<(mpsc::Sender<ExecInput>, Streaming<ExecOutput>), InstanceError> {
+        let instance = self.get_instance(instance_id, namespace).await?;
+
+        let (inputs, receiver) = mpsc::channel(EXEC_BUFFER);
+        let start = ExecInput {
+            input: Some(Input::Start(ExecStart {
+                instance_id: instance.id.clone(),
+                command,
+                tty,
+            })),
+            ..Default::default()
+        };
+        // the channel is empty and its receiver alive, the start is queued at once
+        _ = inputs.send(start).await;
+
+        let mut scheduler_client = self.scheduler_client(namespace).await?;
+        let outputs = scheduler_client
+            .instance_exec(receiver)
+            .await
+            .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?
+            .into_inner();
+        Ok((inputs, outputs))
+    }
+
+    /// Returns an error if stopping an instance would leave its workload with fewer running
+    /// instances than its disruption budget, the instances of a deleted workload have no budget.
+    pub async fn check_disruption_budget(
+        &mut self,
+        instance: &Instance,
+    ) -> Result<(), InstanceError> {
+        let workload = match self
+            .workload_service
+            .get_workload(workload_name(instance), &instance.namespace)
+            .await
+        {
+            Ok(workload) => workload,
+            Err(WorkloadError::WorkloadNotFound) => return Ok(()),
+            Err(err) => return Err(InstanceError::Workload(err)),
+        };
+        let Some(budget) = &workload.disruption_budget else {
+            return Ok(());
+        };
+
+        let instances = self.get_instances_of_workload(&workload).await;
+        if disruption::allows_disruption(Some(budget), instance, &instances) {
+            Ok(())
+        } else {
+            Err(InstanceError::DisruptionBudget(
+                disruption::delayed_description(budget),
+            ))
+        }
+    }
+
+    pub(crate) async fn put_instance(&mut self, instance: &Instance) -> Result<(), InstanceError> {
+        // read from etcd rather than the read cache, whose copy may miss the last write and
+        // leave the index keys of its state behind
+        let previous: Option<Instance> = self
+            .etcd_service
+            .get(&self.id(&instance.id, &instance.namespace))
+            .await
+            .and_then(|value| serde_json::from_str(&value).ok());
+
+        let json = serde_json::to_string(instance)
+            .map_err(|err| InstanceError::InstanceToJson(err.to_string()))?;
+        self.etcd_service
+            .put(&self.id(&instance.id, &instance.namespace), &json)
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+        index::update_index(&mut self.etcd_service, previous.as_ref(), Some(instance))
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+        Ok(())
+    }
+
+    /// Returns the changes of the instances of a namespace matching the filter, as they happen.
+    /// The changes made after `from_version` are replayed first if it is set, unless etcd
+    /// compacted them.
+    pub async fn watch_instances(
+        &mut self,
+        namespace: &str,
+        filter: InstanceFilter,
+        from_version: Option<i64>,
+    ) -> Result<impl Stream<Item = InstanceEvent>, InstanceError> {
+        let prefix = self.id("", namespace);
+        let watch = match from_version {
+            Some(version) => {
+                let compacted = self
+                    .etcd_service
+                    .is_compacted(version)
+                    .await
+                    .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+                if compacted {
+                    return Err(InstanceError::VersionCompacted(version));
+                }
+                self.etcd_service
+                    .watch_prefix_since(&prefix, version + 1)
+                    .await
+            }
+            None => self.etcd_service.watch_prefix(&prefix).await,
+        }
+        .map_err(|err| InstanceError::Etcd(err.to_string()))?;
+
+        // the watcher is kept alongside the stream, dropping it cancels the watch
+        let responses = stream::unfold(watch, |(watcher, mut stream)| async move {
+            match stream.message().await {
+                // e.g. the watch fell behind a compaction, the client lists the instances again
+                Ok(Some(response)) if response.canceled() => {
+                    error!("Instance watch canceled: {}", response.cancel_reason());
+                    None
+                }
+                Ok(Some(response)) => Some((response, (watcher, stream))),
+                Ok(None) => None,
+                Err(err) => {
+                    error!("Instance watch interrupted: {}", err);
+                    None
+                }
+            }
+        });
+
+        Ok(responses
+            .flat_map(|response| {
+                stream::iter(
+                    response
+                        .events()
+                        .iter()
+                        .filter_map(InstanceEvent::from_etcd)
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .filter(move |event| future::ready(filter.matches(&event.instance))))
+    }
+
+    /// Connects to the scheduler running the instances of a namespace, found in the shard map.
+    async fn scheduler_client(
+        &mut self,
+        namespace: &str,
+    ) -> Result<SchedulerClientInterface, InstanceError> {
+        let scheduler_address = self
+            .shard_service
+            .scheduler_for(namespace)
+            .await
+            .map_err(|err| InstanceError::Etcd(err.to_problem().detail))?;
+        SchedulerClientInterface::new(format!("http://{}", scheduler_address))
+            .await
+            .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))
+    }
+
+    pub fn id(&self, instance_id: &str, namespace: &str) -> String {
+        format!("instance.{}.{}", namespace, instance_id)
+    }
+
+    fn idempotency_id(&self, key: &str, namespace: &str) -> String {
+        format!("idempotency.instance.{}.{}", namespace, key)
+    }
+}
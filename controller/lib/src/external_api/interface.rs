@@ -1,4 +1,20 @@
-use super::workload;
+use crate::admission::AdmissionWebhook;
+use crate::backup::BackupConfig;
+use crate::tasks::BackgroundTasks;
+use image_policy::SignaturePolicy;
+
+use super::config::ExternalAPIConfig;
+use super::generic::read_cache::ReadCache;
+use super::middleware::cors::CorsConfig;
+use super::middleware::deadline::Deadline;
+use super::middleware::rate_limit::RateLimit;
+use super::middleware::tracing::Tracing;
+use super::service::model::NodePortRange;
+use super::workload::cache::WorkloadCache;
+use super::{
+    archive, backup, bundle, cronjob, image, ingress, instance, maintenance, metrics, namespace,
+    network_policy, service, shard, usage, workload,
+};
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpResponse, HttpServer};
 use log::info;
@@ -6,26 +22,96 @@ use std::net::SocketAddr;
 
 pub struct ExternalAPIInterface {}
 
+/// `ActixAppState` holds the addresses of the components called by the handlers. It is shared by
+/// every HTTP worker.
 pub struct ActixAppState {
     pub etcd_address: SocketAddr,
+    pub scheduler_address: SocketAddr,
+    pub admission_webhooks: Vec<AdmissionWebhook>,
+    pub image_signature: Option<SignaturePolicy>,
+    pub node_port_range: NodePortRange,
+    pub background_tasks: BackgroundTasks,
+    pub workload_cache: WorkloadCache,
+    pub read_cache: ReadCache,
+    pub backup: BackupConfig,
+}
+
+impl ActixAppState {
+    pub fn new(
+        config: &ExternalAPIConfig,
+        background_tasks: &BackgroundTasks,
+        workload_cache: &WorkloadCache,
+        read_cache: &ReadCache,
+    ) -> Self {
+        ActixAppState {
+            etcd_address: config.etcd_address,
+            scheduler_address: config.scheduler_address,
+            admission_webhooks: config.admission_webhooks.clone(),
+            image_signature: config.image_signature.clone(),
+            node_port_range: config.node_port_range,
+            background_tasks: background_tasks.clone(),
+            workload_cache: workload_cache.clone(),
+            read_cache: read_cache.clone(),
+            backup: config.backup.clone(),
+        }
+    }
 }
 
 impl ExternalAPIInterface {
-    pub async fn new(address: SocketAddr, num_workers: usize, etcd_address: SocketAddr) -> Self {
+    /// It runs the HTTP server until the controller receives SIGINT or SIGTERM. The server then
+    /// stops accepting connections and waits for the in-flight requests, at most
+    /// `shutdown_timeout_seconds`.
+    pub async fn new(config: ExternalAPIConfig, background_tasks: &BackgroundTasks) -> Self {
         info!(
             "Starting {} HTTP worker(s) listening on {}",
-            num_workers, address
+            config.http_server_num_workers, config.http_server_addr
         );
 
+        // The workloads read by the instance requests are cached, evicted as etcd is modified
+        let workload_cache = WorkloadCache::new();
+        workload_cache.start(config.etcd_address, background_tasks);
+        // The eventually consistent reads are served from a mirror of etcd
+        let read_cache = ReadCache::new();
+        read_cache.start(config.etcd_address, background_tasks);
+        let state = web::Data::new(ActixAppState::new(
+            &config,
+            background_tasks,
+            &workload_cache,
+            &read_cache,
+        ));
+        // The limiter is created once so that every worker shares the same buckets
+        let rate_limit = RateLimit::new(config.rate_limit);
+        let cors = config.cors;
+        let deadline = Deadline::new(config.request_timeout_seconds);
+
         HttpServer::new(move || {
             App::new()
-                .app_data(web::Data::new(ActixAppState { etcd_address }))
+                .app_data(state.clone())
                 .route("/health", web::get().to(HttpResponse::Ok))
                 .service(workload::controller::WorkloadController {}.services())
+                .service(instance::controller::InstanceController {}.services())
+                .service(service::controller::ServiceController {}.services())
+                .service(ingress::controller::IngressController {}.services())
+                .service(namespace::controller::NamespaceController {}.services())
+                .service(network_policy::controller::NetworkPolicyController {}.services())
+                .service(cronjob::controller::CronJobController {}.services())
+                .service(shard::controller::ShardController {}.services())
+                .service(usage::controller::UsageController {}.services())
+                .service(metrics::controller::MetricsController {}.services())
+                .service(maintenance::controller::MaintenanceController {}.services())
+                .service(image::controller::ImageController {}.services())
+                .service(bundle::controller::BundleController {}.services())
+                .service(archive::controller::ArchiveController {}.services())
+                .service(backup::controller::BackupController {}.services())
+                .wrap(deadline.clone())
+                .wrap(rate_limit.clone())
+                .wrap(CorsConfig::to_cors(cors.as_ref()))
                 .wrap(Logger::default())
+                .wrap(Tracing)
         })
-        .workers(num_workers)
-        .bind(address)
+        .workers(config.http_server_num_workers)
+        .shutdown_timeout(config.shutdown_timeout_seconds)
+        .bind(config.http_server_addr)
         .unwrap()
         .run()
         .await
@@ -0,0 +1,114 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::MaintenanceWindowDTO;
+use super::service::MaintenanceService;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::generic::problem::Problem;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, Scope};
+pub struct MaintenanceController {}
+impl MaintenanceController {
+    pub fn services(&self) -> Scope {
+        web::scope("/maintenance")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(
+                web::resource("/{node_id}")
+                    .route(web::put().to(MaintenanceController::put_window))
+                    .route(web::get().to(MaintenanceController::window))
+                    .route(web::delete().to(MaintenanceController::delete_window)),
+            )
+            .service(web::resource("").route(web::get().to(MaintenanceController::get_all_windows)))
+    }
+
+    /// `window` is an async function that handle **/maintenance/\<node_id>** route (GET)
+    /// # Description:
+    /// * Get the maintenance window of a node and its progress
+    /// # Arguments:
+    ///
+    /// * `node_id`: web::Path<String> - The id of the node.
+    pub async fn window(
+        node_id: web::Path<String>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut maintenance_service =
+            match MaintenanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        maintenance_service
+            .get_window(&node_id)
+            .await
+            .map_or_else(|e| e.to_http(), |w| w.to_http())
+    }
+
+    /// `put_window` is an async function that handle **/maintenance/\<node_id>** route (PUT)
+    /// # Description:
+    /// * Set the maintenance window of a node, replacing the previous one
+    /// # Arguments:
+    ///
+    /// * `node_id`: web::Path<String> - The id of the node.
+    /// * `body`: web::Json<MaintenanceWindowDTO> - The start and the end of the window.
+    pub async fn put_window(
+        node_id: web::Path<String>,
+        body: web::Json<MaintenanceWindowDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut maintenance_service =
+            match MaintenanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        maintenance_service
+            .put_window(&node_id, body.into_inner())
+            .await
+            .map_or_else(|e| e.to_http(), |w| w.to_http())
+    }
+
+    /// `get_all_windows` is an async function that handle **/maintenance** route (GET)
+    /// # Description:
+    /// * Get the maintenance windows of every node
+    /// # Arguments:
+    ///
+    /// * `pagination`: Option<web::Query<Pagination>>
+    pub async fn get_all_windows(
+        pagination: Option<web::Query<Pagination>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut maintenance_service =
+            match MaintenanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        maintenance_service
+            .get_all_windows(&pagination)
+            .await
+            .to_http()
+    }
+
+    /// `delete_window` is an async function that handle **/maintenance/\<node_id>** route (DELETE)
+    /// # Description:
+    /// * Cancel the maintenance window of a node, the node is uncordoned if it was drained
+    /// # Arguments:
+    ///
+    /// * `node_id`: web::Path<String> - The id of the node.
+    pub async fn delete_window(
+        node_id: web::Path<String>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut maintenance_service =
+            match MaintenanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        match maintenance_service.delete_window(&node_id).await {
+            Ok(()) => HttpResponse::build(StatusCode::NO_CONTENT).body("Remove successfully"),
+            Err(e) => e.to_http(),
+        }
+    }
+}
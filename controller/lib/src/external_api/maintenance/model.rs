@@ -0,0 +1,222 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+
+/// The prefix of the keys of the maintenance windows in etcd.
+pub const MAINTENANCE_PREFIX: &str = "maintenance.";
+
+/// Returns the key of the maintenance window of a node.
+pub fn window_key(node_id: &str) -> String {
+    format!("{}{}", MAINTENANCE_PREFIX, node_id)
+}
+
+pub enum MaintenanceError {
+    WindowNotFound,
+    InvalidWindow(String),
+    Etcd(String),
+    Scheduler(String),
+    JsonToWindow(String),
+    WindowToJson(String),
+}
+
+impl MaintenanceError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            MaintenanceError::WindowNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "maintenance_window_not_found",
+                "The node has no maintenance window",
+            ),
+            MaintenanceError::InvalidWindow(err) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_maintenance_window",
+                format!("Invalid maintenance window: {}", err),
+            ),
+            MaintenanceError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            MaintenanceError::Scheduler(err) => Problem::new(
+                StatusCode::BAD_GATEWAY,
+                "scheduler_error",
+                format!("Failed to cordon the node on the scheduler {}", err),
+            ),
+            MaintenanceError::JsonToWindow(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_maintenance_window",
+                format!(
+                    "Error while converting JSON string to maintenance window: {}",
+                    err
+                ),
+            ),
+            MaintenanceError::WindowToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "maintenance_window_serialization_failed",
+                format!(
+                    "Error while converting the maintenance window to JSON: {}",
+                    err
+                ),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// The body of `PUT /maintenance/<node>`, the times are in seconds since the unix epoch.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MaintenanceWindowDTO {
+    pub start: u64,
+    pub end: u64,
+    #[serde(default)]
+    pub reason: String,
+}
+
+impl MaintenanceWindowDTO {
+    /// Checks that the window ends after it starts, and isn't over at `now`.
+    pub fn validate(&self, now: u64) -> Result<(), MaintenanceError> {
+        if self.end <= self.start {
+            return Err(MaintenanceError::InvalidWindow(
+                "the window must end after it starts".to_string(),
+            ));
+        }
+        if self.end <= now {
+            return Err(MaintenanceError::InvalidWindow(
+                "the window is already over".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The progress of a maintenance window, the window is deleted once over and the node uncordoned.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MaintenanceStatus {
+    /// The window is ahead, the node runs instances as usual
+    #[default]
+    Scheduled,
+    /// No new instance is placed on the node, its instances were moved to the other nodes
+    Drained,
+}
+
+/// What a maintenance window requires of its node at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenancePhase {
+    /// The window is ahead, further than the drain delay
+    Upcoming,
+    /// The window starts within the drain delay or is in progress, the node is drained
+    Drain,
+    /// The window is over, the node is uncordoned
+    Over,
+}
+
+/// A `MaintenanceWindow` is a period during which a node is taken out of the cluster: the node is
+/// cordoned and its instances moved shortly before the window starts, and it is uncordoned once
+/// the window is over.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct MaintenanceWindow {
+    pub node_id: String,
+    pub start: u64,
+    pub end: u64,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub status: MaintenanceStatus,
+}
+
+impl MaintenanceWindow {
+    pub fn new(node_id: &str, dto: MaintenanceWindowDTO) -> Self {
+        MaintenanceWindow {
+            node_id: node_id.to_string(),
+            start: dto.start,
+            end: dto.end,
+            reason: dto.reason,
+            status: MaintenanceStatus::Scheduled,
+        }
+    }
+
+    /// Returns the phase of the window at `now`, the node being drained `drain_seconds` before
+    /// the window starts.
+    pub fn phase(&self, now: u64, drain_seconds: u64) -> MaintenancePhase {
+        if now >= self.end {
+            MaintenancePhase::Over
+        } else if now + drain_seconds >= self.start {
+            MaintenancePhase::Drain
+        } else {
+            MaintenancePhase::Upcoming
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => MaintenanceError::WindowToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MaintenanceWindowVector {
+    pub windows: Vec<MaintenanceWindow>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl MaintenanceWindowVector {
+    pub fn new(windows: Vec<MaintenanceWindow>) -> MaintenanceWindowVector {
+        MaintenanceWindowVector {
+            windows,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => MaintenanceError::WindowToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dto(start: u64, end: u64) -> MaintenanceWindowDTO {
+        MaintenanceWindowDTO {
+            start,
+            end,
+            reason: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(dto(1000, 2000).validate(500).is_ok());
+        // a window in progress can be set, the node is drained right away
+        assert!(dto(1000, 2000).validate(1500).is_ok());
+        assert!(dto(2000, 2000).validate(500).is_err());
+        assert!(dto(1000, 2000).validate(2000).is_err());
+    }
+
+    #[test]
+    fn test_phase() {
+        let window = MaintenanceWindow::new("node-1", dto(1000, 2000));
+        assert_eq!(window.status, MaintenanceStatus::Scheduled);
+        assert_eq!(window.phase(600, 300), MaintenancePhase::Upcoming);
+        assert_eq!(window.phase(700, 300), MaintenancePhase::Drain);
+        assert_eq!(window.phase(1999, 300), MaintenancePhase::Drain);
+        assert_eq!(window.phase(2000, 300), MaintenancePhase::Over);
+    }
+}
@@ -0,0 +1,137 @@
+use std::net::SocketAddr;
+
+use super::model::{
+    window_key, MaintenanceError, MaintenanceStatus, MaintenanceWindow, MaintenanceWindowDTO,
+    MaintenanceWindowVector, MAINTENANCE_PREFIX,
+};
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::instance::service::unix_time;
+use crate::external_api::shard::service::ShardService;
+use crate::grpc_client::interface::SchedulerClientInterface;
+
+/// `MaintenanceService` stores the maintenance windows of the nodes in etcd, and cordons the
+/// nodes on the schedulers.
+///
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `shard_service`: This is the service finding the schedulers of the cluster.
+pub struct MaintenanceService {
+    etcd_service: EtcdClient,
+    shard_service: ShardService,
+}
+
+impl MaintenanceService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<MaintenanceService, MaintenanceError> {
+        let etcd_service = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| MaintenanceError::Etcd(err.to_string()))?;
+        Ok(MaintenanceService {
+            shard_service: ShardService::with_client(etcd_service.clone(), scheduler_address),
+            etcd_service,
+        })
+    }
+
+    pub async fn get_window(
+        &mut self,
+        node_id: &str,
+    ) -> Result<MaintenanceWindow, MaintenanceError> {
+        match self.etcd_service.get(&window_key(node_id)).await {
+            Some(window) => serde_json::from_str(&window)
+                .map_err(|err| MaintenanceError::JsonToWindow(err.to_string())),
+            None => Err(MaintenanceError::WindowNotFound),
+        }
+    }
+
+    /// This function gets the maintenance windows of every node, paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    pub async fn get_all_windows(&mut self, pagination: &Pagination) -> MaintenanceWindowVector {
+        match self
+            .etcd_service
+            .list_prefix(
+                MAINTENANCE_PREFIX,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |_: &MaintenanceWindow| true,
+            )
+            .await
+        {
+            Ok(listing) => MaintenanceWindowVector::new(listing.items)
+                .with_continue_token(listing.continue_token),
+            Err(_) => MaintenanceWindowVector::new(vec![]),
+        }
+    }
+
+    /// It sets the maintenance window of a node, replacing the previous one. A node drained for
+    /// the previous window stays cordoned until the maintenance controller handles the new one.
+    ///
+    /// # Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `window_dto`: The start and the end of the window.
+    pub async fn put_window(
+        &mut self,
+        node_id: &str,
+        window_dto: MaintenanceWindowDTO,
+    ) -> Result<MaintenanceWindow, MaintenanceError> {
+        window_dto.validate(unix_time())?;
+
+        let mut window = MaintenanceWindow::new(node_id, window_dto);
+        match self.get_window(node_id).await {
+            Ok(previous) => window.status = previous.status,
+            Err(MaintenanceError::WindowNotFound) => {}
+            Err(err) => return Err(err),
+        }
+        self.save_window(&window).await?;
+        Ok(window)
+    }
+
+    /// It stores a maintenance window in etcd.
+    pub async fn save_window(
+        &mut self,
+        window: &MaintenanceWindow,
+    ) -> Result<(), MaintenanceError> {
+        let json = serde_json::to_string(window)
+            .map_err(|err| MaintenanceError::WindowToJson(err.to_string()))?;
+        self.etcd_service
+            .put(&window_key(&window.node_id), &json)
+            .await
+            .map_err(|err| MaintenanceError::Etcd(err.to_string()))?;
+        Ok(())
+    }
+
+    /// It deletes the maintenance window of a node, the node is uncordoned if it was drained.
+    pub async fn delete_window(&mut self, node_id: &str) -> Result<(), MaintenanceError> {
+        let window = self.get_window(node_id).await?;
+        if window.status == MaintenanceStatus::Drained {
+            self.cordon(node_id, false).await?;
+        }
+        _ = self.etcd_service.delete(&window_key(node_id)).await;
+        Ok(())
+    }
+
+    /// It cordons a node on every scheduler of the cluster, or uncordons it if `cordoned` is
+    /// false.
+    pub async fn cordon(&mut self, node_id: &str, cordoned: bool) -> Result<(), MaintenanceError> {
+        let schedulers = self
+            .shard_service
+            .schedulers()
+            .await
+            .map_err(MaintenanceError::Etcd)?;
+
+        for scheduler in schedulers {
+            SchedulerClientInterface::new(format!("http://{}", scheduler))
+                .await
+                .map_err(|err| MaintenanceError::Scheduler(format!("{}: {:?}", scheduler, err)))?
+                .cordon_node(node_id, cordoned)
+                .await
+                .map_err(|err| MaintenanceError::Scheduler(format!("{}: {:?}", scheduler, err)))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::service::MetricsService;
+use actix_web::{web, Responder, Scope};
+pub struct MetricsController {}
+impl MetricsController {
+    pub fn services(&self) -> Scope {
+        web::scope("/metrics")
+            .service(
+                web::resource("/nodes").route(web::get().to(MetricsController::get_nodes_metrics)),
+            )
+            .service(
+                web::resource("/instances/{namespace}")
+                    .route(web::get().to(MetricsController::get_instances_metrics)),
+            )
+    }
+
+    /// `get_nodes_metrics` is an async function that handle **/metrics/nodes** route (GET)
+    /// # Description:
+    /// * Get the latest resources and usage of every node of the cluster, and their sum
+    pub async fn get_nodes_metrics(data: web::Data<ActixAppState>) -> impl Responder {
+        let mut metrics_service =
+            match MetricsService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        metrics_service
+            .get_nodes_metrics()
+            .await
+            .map_or_else(|e| e.to_http(), |metrics| metrics.to_http())
+    }
+
+    /// `get_instances_metrics` is an async function that handle **/metrics/instances/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get the latest usage of every instance of a namespace, and their sum
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - The namespace of the instances.
+    pub async fn get_instances_metrics(
+        namespace: web::Path<String>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut metrics_service =
+            match MetricsService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        metrics_service
+            .get_instances_metrics(&namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |metrics| metrics.to_http())
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use proto::scheduler::{ClusterSnapshot, InstanceStatus, ResourceSummary};
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::instance::model::{Instance, InstanceState};
+
+pub enum MetricsError {
+    Scheduler(String),
+    Etcd(String),
+    MetricsToJson(String),
+}
+
+impl MetricsError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            MetricsError::Scheduler(err) => Problem::new(
+                StatusCode::BAD_GATEWAY,
+                "scheduler_error",
+                format!("Scheduler error: {}", err),
+            ),
+            MetricsError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            MetricsError::MetricsToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "metrics_serialization_failed",
+                format!("Error while converting the metrics to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// An amount of each resource: CPU in milliCPU, memory in MB and disk in GB.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceAmount {
+    pub cpu: u64,
+    pub memory: u64,
+    pub disk: u64,
+}
+
+impl ResourceAmount {
+    fn add(&mut self, other: &ResourceAmount) {
+        self.cpu += other.cpu;
+        self.memory += other.memory;
+        self.disk += other.disk;
+    }
+}
+
+impl From<&ResourceSummary> for ResourceAmount {
+    fn from(summary: &ResourceSummary) -> Self {
+        ResourceAmount {
+            cpu: summary.cpu,
+            memory: summary.memory,
+            disk: summary.disk,
+        }
+    }
+}
+
+/// The latest numbers of a node, as last reported to its scheduler.
+///
+/// Properties:
+///
+/// * `id`: The id of the node.
+/// * `connected`: Whether the lifecycle stream of the node is open.
+/// * `limit`: The resources of the node, unset if it didn't report them.
+/// * `usage`: The resources used on the node, unset if it didn't report them.
+/// * `instances`: The number of instances placed on the node.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct NodeMetrics {
+    pub id: String,
+    pub connected: bool,
+    pub limit: Option<ResourceAmount>,
+    pub usage: Option<ResourceAmount>,
+    pub instances: usize,
+}
+
+/// The latest numbers of every node of the cluster, and their sum.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct NodesMetrics {
+    pub nodes: Vec<NodeMetrics>,
+    pub limit: ResourceAmount,
+    pub usage: ResourceAmount,
+}
+
+impl NodesMetrics {
+    /// Aggregates the nodes of a snapshot of the cluster, sorted by id.
+    pub fn from_snapshot(snapshot: &ClusterSnapshot) -> Self {
+        let mut placed: HashMap<&str, usize> = HashMap::new();
+        for placement in snapshot.placements.iter().chain(&snapshot.pending) {
+            *placed.entry(placement.node_id.as_str()).or_default() += 1;
+        }
+
+        let mut metrics = NodesMetrics::default();
+        for node in &snapshot.nodes {
+            let resource = node
+                .status
+                .as_ref()
+                .and_then(|status| status.resource.as_ref());
+            let limit = resource
+                .and_then(|resource| resource.limit.as_ref())
+                .map(ResourceAmount::from);
+            let usage = resource
+                .and_then(|resource| resource.usage.as_ref())
+                .map(ResourceAmount::from);
+            metrics.limit.add(&limit.unwrap_or_default());
+            metrics.usage.add(&usage.unwrap_or_default());
+            metrics.nodes.push(NodeMetrics {
+                id: node.id.clone(),
+                connected: node.connected,
+                limit,
+                usage,
+                instances: placed.get(node.id.as_str()).copied().unwrap_or_default(),
+            });
+        }
+        metrics.nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        metrics
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => MetricsError::MetricsToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// The latest numbers of an instance, as last reported to its scheduler.
+///
+/// Properties:
+///
+/// * `id`: The id of the instance.
+/// * `name`: The name of the instance.
+/// * `workload_id`: The workload of the instance.
+/// * `node_id`: The node the instance runs on, empty until it is placed.
+/// * `state`: The state of the instance stored in etcd.
+/// * `usage`: The resources used by the instance, unset if it didn't report them.
+/// * `logs`: The bytes of the log files of the instance kept on its node.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct InstanceMetrics {
+    pub id: String,
+    pub name: String,
+    pub workload_id: String,
+    pub node_id: String,
+    pub state: InstanceState,
+    pub usage: Option<ResourceAmount>,
+    pub logs: u64,
+}
+
+/// The latest numbers of every instance of a namespace, and their sum.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstancesMetrics {
+    pub namespace: String,
+    pub instances: Vec<InstanceMetrics>,
+    pub usage: ResourceAmount,
+    pub logs: u64,
+}
+
+impl InstancesMetrics {
+    /// Aggregates the instances of a namespace with the usage reported in a snapshot of the
+    /// cluster.
+    pub fn from_snapshot(
+        namespace: &str,
+        instances: Vec<Instance>,
+        snapshot: &ClusterSnapshot,
+    ) -> Self {
+        let statuses: HashMap<&str, &InstanceStatus> = snapshot
+            .placements
+            .iter()
+            .filter_map(|placement| {
+                Some((placement.instance_id.as_str(), placement.status.as_ref()?))
+            })
+            .collect();
+
+        let mut metrics = InstancesMetrics {
+            namespace: namespace.to_string(),
+            ..Default::default()
+        };
+        for instance in instances {
+            let status = statuses.get(instance.id.as_str());
+            let usage = status
+                .and_then(|status| status.resource.as_ref())
+                .and_then(|resource| resource.usage.as_ref())
+                .map(ResourceAmount::from);
+            let logs = status.map_or(0, |status| status.logs);
+            metrics.usage.add(&usage.unwrap_or_default());
+            metrics.logs += logs;
+            metrics.instances.push(InstanceMetrics {
+                id: instance.id,
+                name: instance.name,
+                workload_id: instance.workload_id,
+                node_id: instance.node_id,
+                state: instance.status.state,
+                usage,
+                logs,
+            });
+        }
+        metrics
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => MetricsError::MetricsToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{InstancePlacement, NodeSnapshot, NodeStatus, Resource};
+
+    use super::*;
+    use crate::external_api::workload::model::Workload;
+
+    fn summary(cpu: u64, memory: u64) -> Option<ResourceSummary> {
+        Some(ResourceSummary {
+            cpu,
+            memory,
+            disk: 0,
+        })
+    }
+
+    fn placement(instance_id: &str, node_id: &str, cpu: u64) -> InstancePlacement {
+        InstancePlacement {
+            instance_id: instance_id.to_string(),
+            node_id: node_id.to_string(),
+            status: Some(InstanceStatus {
+                resource: Some(Resource {
+                    limit: None,
+                    usage: summary(cpu, 64),
+                }),
+                logs: cpu * 1000,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn snapshot() -> ClusterSnapshot {
+        ClusterSnapshot {
+            nodes: vec![
+                NodeSnapshot {
+                    id: "b".to_string(),
+                    connected: false,
+                    ..Default::default()
+                },
+                NodeSnapshot {
+                    id: "a".to_string(),
+                    connected: true,
+                    status: Some(NodeStatus {
+                        resource: Some(Resource {
+                            limit: summary(4000, 8192),
+                            usage: summary(1000, 2048),
+                        }),
+                        ..Default::default()
+                    }),
+                    capabilities: None,
+                    cordoned: false,
+                    suspect: false,
+                    features: vec![],
+                },
+            ],
+            placements: vec![placement("1", "a", 250), placement("2", "a", 100)],
+            pending: vec![InstancePlacement {
+                instance_id: "3".to_string(),
+                node_id: "b".to_string(),
+                status: None,
+            }],
+        }
+    }
+
+    fn instance(id: &str) -> Instance {
+        let workload = Workload {
+            id: "default.web".to_string(),
+            name: "web".to_string(),
+            uri: "nginx".to_string(),
+            namespace: "default".to_string(),
+            ..Default::default()
+        };
+        Instance::from_workload(id.to_string(), workload)
+    }
+
+    #[test]
+    fn test_nodes_metrics() {
+        let metrics = NodesMetrics::from_snapshot(&snapshot());
+
+        let nodes: Vec<(&str, usize)> = metrics
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.instances))
+            .collect();
+        assert_eq!(nodes, vec![("a", 2), ("b", 1)]);
+        assert_eq!(metrics.nodes[1].usage, None);
+        assert_eq!(
+            metrics.usage,
+            ResourceAmount {
+                cpu: 1000,
+                memory: 2048,
+                disk: 0
+            }
+        );
+        assert_eq!(metrics.limit.cpu, 4000);
+    }
+
+    #[test]
+    fn test_instances_metrics() {
+        let metrics = InstancesMetrics::from_snapshot(
+            "default",
+            vec![instance("1"), instance("2"), instance("3")],
+            &snapshot(),
+        );
+
+        assert_eq!(metrics.instances.len(), 3);
+        assert_eq!(metrics.instances[0].usage.map(|usage| usage.cpu), Some(250));
+        // not placed yet
+        assert_eq!(metrics.instances[2].usage, None);
+        assert_eq!(metrics.usage.cpu, 350);
+        assert_eq!(metrics.usage.memory, 128);
+        assert_eq!(metrics.instances[0].logs, 250_000);
+        assert_eq!(metrics.logs, 350_000);
+    }
+}
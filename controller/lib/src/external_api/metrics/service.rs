@@ -0,0 +1,73 @@
+use std::net::SocketAddr;
+
+use super::model::{InstancesMetrics, MetricsError, NodesMetrics};
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::instance::model::InstanceFilter;
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::shard::service::ShardService;
+
+/// `MetricsService` aggregates the latest numbers reported to the schedulers of the cluster, so
+/// they are queried in a single place instead of on every node.
+///
+/// Properties:
+///
+/// * `shard_service`: This is the service used to get the snapshot of every scheduler.
+/// * `instance_service`: This is the service used to list the instances of a namespace.
+pub struct MetricsService {
+    shard_service: ShardService,
+    instance_service: InstanceService,
+}
+
+impl MetricsService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<MetricsService, MetricsError> {
+        Ok(MetricsService {
+            shard_service: ShardService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(|err| MetricsError::Etcd(err.to_problem().detail))?,
+            instance_service: InstanceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(|err| MetricsError::Etcd(err.to_problem().detail))?,
+        })
+    }
+
+    /// It returns the latest numbers of every node of the cluster.
+    pub async fn get_nodes_metrics(&mut self) -> Result<NodesMetrics, MetricsError> {
+        let snapshot = self
+            .shard_service
+            .cluster_snapshot()
+            .await
+            .map_err(MetricsError::Scheduler)?;
+        Ok(NodesMetrics::from_snapshot(&snapshot))
+    }
+
+    /// It returns the latest numbers of every instance of a namespace.
+    ///
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the instances.
+    pub async fn get_instances_metrics(
+        &mut self,
+        namespace: &str,
+    ) -> Result<InstancesMetrics, MetricsError> {
+        let instances = self
+            .instance_service
+            .get_all_instances(
+                &Pagination::default(),
+                namespace,
+                &InstanceFilter::default(),
+            )
+            .await
+            .instances;
+        let snapshot = self
+            .shard_service
+            .cluster_snapshot()
+            .await
+            .map_err(MetricsError::Scheduler)?;
+        Ok(InstancesMetrics::from_snapshot(
+            namespace, instances, &snapshot,
+        ))
+    }
+}
@@ -0,0 +1,55 @@
+use actix_cors::Cors;
+use serde::{Deserialize, Serialize};
+
+/// Cross-origin policy configured by an operator in the controller configuration, so that
+/// browser-based dashboards can call the API.
+///
+/// Properties:
+///
+/// * `allowed_origins`: Origins allowed to call the API, `*` allows every origin.
+/// * `allowed_methods`: HTTP methods allowed, every method if empty.
+/// * `allowed_headers`: Request headers allowed, every header if empty.
+/// * `max_age`: How long (in seconds) browsers can cache the preflight responses.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct CorsConfig {
+    pub allowed_origins: Vec<String>,
+    #[serde(default)]
+    pub allowed_methods: Vec<String>,
+    #[serde(default)]
+    pub allowed_headers: Vec<String>,
+    #[serde(default)]
+    pub max_age: Option<usize>,
+}
+
+impl CorsConfig {
+    /// Builds the actix middleware. Without configuration, the cross-origin requests are
+    /// rejected and the same-origin ones go through untouched.
+    pub fn to_cors(config: Option<&CorsConfig>) -> Cors {
+        let config = match config {
+            Some(config) => config,
+            None => return Cors::default(),
+        };
+
+        let mut cors = Cors::default().max_age(config.max_age);
+
+        if config.allowed_origins.iter().any(|origin| origin == "*") {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &config.allowed_origins {
+                cors = cors.allowed_origin(origin);
+            }
+        }
+
+        cors = if config.allowed_methods.is_empty() {
+            cors.allow_any_method()
+        } else {
+            cors.allowed_methods(config.allowed_methods.iter().map(String::as_str))
+        };
+
+        if config.allowed_headers.is_empty() {
+            cors.allow_any_header()
+        } else {
+            cors.allowed_headers(config.allowed_headers.iter().map(String::as_str))
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::time::Duration;
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::Error;
+use futures_util::future::LocalBoxFuture;
+use log::debug;
+use tokio::time::Instant;
+
+use crate::external_api::generic::problem::Problem;
+
+tokio::task_local! {
+    /// The deadline of the request being handled.
+    static DEADLINE: Instant;
+}
+
+/// Returns the time left before the deadline of the request being handled, `None` outside of a
+/// request, e.g. in the background tasks, or when the requests have no deadline.
+pub fn remaining() -> Option<Duration> {
+    DEADLINE
+        .try_with(|deadline| deadline.saturating_duration_since(Instant::now()))
+        .ok()
+}
+
+/// `Deadline` is the actix middleware giving up on the requests not answered within `timeout`,
+/// with a `504 Gateway Timeout`. The handler is dropped, which cancels its calls to the
+/// scheduler. The deadline is readable by the handler through `remaining`, so that it is
+/// forwarded to the scheduler by the gRPC client. Actix drops the handler as well when the client
+/// disconnects before being answered.
+#[derive(Clone, Default)]
+pub struct Deadline {
+    timeout: Option<Duration>,
+}
+
+impl Deadline {
+    /// `new` creates the middleware, the requests have no deadline if `timeout_seconds` is 0.
+    pub fn new(timeout_seconds: u64) -> Self {
+        Deadline {
+            timeout: (timeout_seconds > 0).then(|| Duration::from_secs(timeout_seconds)),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Deadline
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = DeadlineMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(DeadlineMiddleware {
+            service: Rc::new(service),
+            timeout: self.timeout,
+        }))
+    }
+}
+
+pub struct DeadlineMiddleware<S> {
+    service: Rc<S>,
+    timeout: Option<Duration>,
+}
+
+impl<S, B> Service<ServiceRequest> for DeadlineMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => {
+                return Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+            }
+        };
+
+        // the request is kept to answer the timeout, the handler owning the original one
+        let (request, payload) = req.into_parts();
+        let req = ServiceRequest::from_parts(request.clone(), payload);
+        let deadline = Instant::now() + timeout;
+        Box::pin(async move {
+            let handled = DEADLINE.scope(deadline, service.call(req));
+            match tokio::time::timeout_at(deadline, handled).await {
+                Ok(res) => Ok(res?.map_into_left_body()),
+                Err(_) => {
+                    debug!("{} {} timed out", request.method(), request.path());
+                    let problem = Problem::new(
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "request_timeout",
+                        format!("The request wasn't handled within {:?}", timeout),
+                    );
+                    Ok(ServiceResponse::new(request, problem.to_http()).map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_remaining() {
+        assert_eq!(remaining(), None);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let left = DEADLINE.scope(deadline, async { remaining() }).await;
+        assert!(matches!(left, Some(left) if left <= Duration::from_secs(10)));
+
+        let left = DEADLINE.scope(Instant::now(), async { remaining() }).await;
+        assert_eq!(left, Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod cors;
+pub mod deadline;
+pub mod rate_limit;
+pub mod tracing;
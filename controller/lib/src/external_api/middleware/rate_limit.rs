@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{Error, HttpResponse};
+use futures_util::future::LocalBoxFuture;
+use log::debug;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// Number of clients tracked before the idle buckets are dropped.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// How the clients of the API are told apart.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitKey {
+    /// The IP address of the peer
+    Ip,
+    /// The `Authorization` header of the request, the IP address of the peer without it
+    Token,
+}
+
+fn default_key() -> RateLimitKey {
+    RateLimitKey::Ip
+}
+
+/// Reads a rate of requests, rejecting the ones which are not a positive number.
+fn positive_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let rate = f64::deserialize(deserializer)?;
+    if rate.is_finite() && rate > 0.0 {
+        Ok(rate)
+    } else {
+        Err(de::Error::custom(format!(
+            "requests_per_second must be a positive number, got {}",
+            rate
+        )))
+    }
+}
+
+/// Reads a burst of requests, a client could never send a request with an empty one.
+fn positive_burst<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    match u32::deserialize(deserializer)? {
+        0 => Err(de::Error::custom("burst must be at least 1")),
+        burst => Ok(burst),
+    }
+}
+
+/// Limits configured by an operator in the controller configuration.
+///
+/// Properties:
+///
+/// * `requests_per_second`: Sustained rate allowed for each client, positive.
+/// * `burst`: Number of requests a client can send at once after being idle, at least 1.
+/// * `key`: How the clients are identified.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct RateLimitConfig {
+    #[serde(deserialize_with = "positive_rate")]
+    pub requests_per_second: f64,
+    #[serde(deserialize_with = "positive_burst")]
+    pub burst: u32,
+    #[serde(default = "default_key")]
+    pub key: RateLimitKey,
+}
+
+/// A token bucket refilled at `requests_per_second` up to `burst` tokens.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: u32, now: Instant) -> Self {
+        TokenBucket {
+            tokens: burst as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, config: &RateLimitConfig, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * config.requests_per_second).min(config.burst as f64);
+        self.last_refill = now;
+    }
+
+    /// Takes a token from the bucket, or returns how long the client has to wait for the next one.
+    fn try_acquire(&mut self, config: &RateLimitConfig, now: Instant) -> Result<(), Duration> {
+        self.refill(config, now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            // a rate too low to give a token in a representable delay never gives one
+            Err(
+                Duration::try_from_secs_f64((1.0 - self.tokens) / config.requests_per_second)
+                    .unwrap_or(Duration::MAX),
+            )
+        }
+    }
+
+    fn is_full(&self, config: &RateLimitConfig) -> bool {
+        self.tokens >= config.burst as f64
+    }
+}
+
+/// `RateLimiter` holds the buckets of every client, it is shared by all the HTTP workers.
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn check(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            // A full bucket is in the same state as a missing one, forgetting it is free.
+            buckets.retain(|_, bucket| {
+                bucket.refill(&self.config, now);
+                !bucket.is_full(&self.config)
+            });
+        }
+
+        buckets
+            .entry(client.to_string())
+            .or_insert_with(|| TokenBucket::new(self.config.burst, now))
+            .try_acquire(&self.config, now)
+    }
+
+    fn client_key(&self, req: &ServiceRequest) -> String {
+        let ip = req
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+
+        match self.config.key {
+            RateLimitKey::Ip => ip,
+            RateLimitKey::Token => req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .map(|token| format!("token:{}", token))
+                .unwrap_or(ip),
+        }
+    }
+}
+
+/// `RateLimit` is the actix middleware answering `429 Too Many Requests` to the clients going
+/// over their limit. Every request goes through when no limit is configured.
+#[derive(Clone, Default)]
+pub struct RateLimit {
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RateLimit {
+    pub fn new(config: Option<RateLimitConfig>) -> Self {
+        RateLimit {
+            limiter: config.map(|config| Arc::new(RateLimiter::new(config))),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddleware {
+            service: Rc::new(service),
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddleware<S> {
+    service: Rc<S>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        if let Some(limiter) = &self.limiter {
+            let client = limiter.client_key(&req);
+
+            if let Err(retry_after) = limiter.check(&client, Instant::now()) {
+                debug!("Rate limit exceeded by client {}", client);
+
+                let response = HttpResponse::TooManyRequests()
+                    .insert_header((
+                        header::RETRY_AFTER,
+                        retry_after.as_secs_f64().ceil().to_string(),
+                    ))
+                    .body("Too many requests");
+                return Box::pin(ready(Ok(req.into_response(response).map_into_right_body())));
+            }
+        }
+
+        let service = self.service.clone();
+        Box::pin(async move { Ok(service.call(req).await?.map_into_left_body()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(requests_per_second: f64, burst: u32) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second,
+            burst,
+            key: RateLimitKey::Ip,
+        }
+    }
+
+    #[test]
+    fn test_bucket_burst_then_refill() {
+        let config = config(2.0, 3);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(config.burst, now);
+
+        for _ in 0..3 {
+            assert!(bucket.try_acquire(&config, now).is_ok());
+        }
+        assert_eq!(
+            bucket.try_acquire(&config, now),
+            Err(Duration::from_millis(500))
+        );
+
+        assert!(bucket
+            .try_acquire(&config, now + Duration::from_millis(500))
+            .is_ok());
+        assert!(bucket
+            .try_acquire(&config, now + Duration::from_millis(500))
+            .is_err());
+    }
+
+    #[test]
+    fn test_bucket_never_exceeds_burst() {
+        let config = config(10.0, 2);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(config.burst, now);
+
+        bucket.refill(&config, now + Duration::from_secs(60));
+
+        assert!(bucket.is_full(&config));
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_limits() {
+        let parse = |json: &str| serde_json::from_str::<RateLimitConfig>(json);
+        assert!(parse(r#"{"requests_per_second": 2.5, "burst": 5}"#).is_ok());
+        assert!(parse(r#"{"requests_per_second": 0, "burst": 5}"#).is_err());
+        assert!(parse(r#"{"requests_per_second": -1, "burst": 5}"#).is_err());
+        assert!(parse(r#"{"requests_per_second": 1, "burst": 0}"#).is_err());
+    }
+
+    #[test]
+    fn test_bucket_retry_after_unrepresentable() {
+        let config = config(f64::MIN_POSITIVE, 1);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(config.burst, now);
+
+        assert!(bucket.try_acquire(&config, now).is_ok());
+        assert_eq!(bucket.try_acquire(&config, now), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn test_limiter_tracks_clients_separately() {
+        let limiter = RateLimiter::new(config(1.0, 1));
+        let now = Instant::now();
+
+        assert!(limiter.check("10.0.0.1", now).is_ok());
+        assert!(limiter.check("10.0.0.1", now).is_err());
+        assert!(limiter.check("10.0.0.2", now).is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::HeaderMap;
+use actix_web::Error;
+use futures_util::future::LocalBoxFuture;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::{FutureExt, SpanKind, StatusCode, TraceContextExt, Tracer};
+use opentelemetry::{global, KeyValue};
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// `Tracing` is the actix middleware starting a span for every HTTP request, as a child of the
+/// span of the caller when it sends a `traceparent` header. The span is the current context
+/// while the request is handled, so that it is forwarded to the scheduler by the gRPC client.
+#[derive(Clone, Default)]
+pub struct Tracing;
+
+impl<S, B> Transform<S, ServiceRequest> for Tracing
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = TracingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TracingMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct TracingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for TracingMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(req.headers()))
+        });
+
+        let tracer = telemetry::tracer();
+        let span = tracer
+            .span_builder(format!("HTTP {}", req.method()))
+            .with_kind(SpanKind::Server)
+            .with_attributes(vec![
+                KeyValue::new("http.method", req.method().to_string()),
+                KeyValue::new("http.target", req.path().to_string()),
+            ])
+            .start_with_context(&tracer, &parent);
+        let cx = parent.with_span(span);
+
+        let service = self.service.clone();
+        Box::pin(
+            async move {
+                let res = service.call(req).await;
+                let cx = opentelemetry::Context::current();
+                let span = cx.span();
+
+                match &res {
+                    Ok(res) => {
+                        // The route is only known once the request went through the router
+                        if let Some(pattern) = res.request().match_pattern() {
+                            span.update_name::<String>(format!(
+                                "HTTP {} {}",
+                                res.request().method(),
+                                pattern
+                            ));
+                        }
+
+                        let status = res.status();
+                        span.set_attribute(KeyValue::new(
+                            "http.status_code",
+                            i64::from(status.as_u16()),
+                        ));
+                        if status.is_server_error() {
+                            span.set_status(StatusCode::Error, status.to_string());
+                        }
+                    }
+                    Err(err) => span.set_status(StatusCode::Error, err.to_string()),
+                }
+
+                res
+            }
+            .with_context(cx),
+        )
+    }
+}
@@ -1,3 +1,19 @@
+pub mod archive;
+pub mod backup;
+pub mod bundle;
+pub mod config;
+pub mod cronjob;
 pub mod generic;
+pub mod image;
+pub mod ingress;
+pub mod instance;
 pub mod interface;
-mod workload;
+pub mod maintenance;
+pub mod metrics;
+pub mod middleware;
+pub mod namespace;
+pub mod network_policy;
+pub mod service;
+pub mod shard;
+pub mod usage;
+pub mod workload;
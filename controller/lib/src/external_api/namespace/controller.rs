@@ -0,0 +1,38 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::NamespaceDeletionQuery;
+use super::service::NamespaceService;
+use actix_web::{web, Responder, Scope};
+pub struct NamespaceController {}
+impl NamespaceController {
+    pub fn services(&self) -> Scope {
+        web::scope("/namespace").service(
+            web::resource("/{namespace}")
+                .route(web::delete().to(NamespaceController::delete_namespace)),
+        )
+    }
+
+    /// `delete_namespace` is an async function that handle **/namespace/\<namespace>** route (DELETE)
+    /// # Description:
+    /// * Delete a namespace, and every resource in it if `cascade` is true
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - The namespace to delete.
+    /// * `query`: web::Query<NamespaceDeletionQuery> - `?cascade=true` to delete a namespace which isn't empty.
+    pub async fn delete_namespace(
+        namespace: web::Path<String>,
+        query: web::Query<NamespaceDeletionQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut namespace_service =
+            match NamespaceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        namespace_service
+            .delete_namespace(&namespace, query.cascade)
+            .await
+            .map_or_else(|e| e.to_http(), |report| report.to_http())
+    }
+}
@@ -0,0 +1,184 @@
+use actix_web::body::MessageBody;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::bundle::model::BundleError;
+use crate::external_api::cronjob::model::CronJobError;
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::ingress::model::IngressError;
+use crate::external_api::instance::model::InstanceError;
+use crate::external_api::network_policy::model::NetworkPolicyError;
+use crate::external_api::service::model::ServiceError;
+use crate::external_api::workload::model::WorkloadError;
+
+pub enum NamespaceError {
+    NotEmpty(String),
+    CronJob(CronJobError),
+    Workload(WorkloadError),
+    Instance(InstanceError),
+    Service(ServiceError),
+    Ingress(IngressError),
+    NetworkPolicy(NetworkPolicyError),
+    Bundle(BundleError),
+    Etcd(String),
+}
+
+impl NamespaceError {
+    pub fn to_http(&self) -> HttpResponse {
+        match self {
+            NamespaceError::NotEmpty(namespace) => HttpResponse::Conflict().body(format!(
+                "Namespace {} is not empty, use cascade=true to delete its resources",
+                namespace
+            )),
+            NamespaceError::CronJob(err) => err.to_http(),
+            NamespaceError::Workload(err) => err.to_http(),
+            NamespaceError::Instance(err) => err.to_http(),
+            NamespaceError::Service(err) => err.to_http(),
+            NamespaceError::Ingress(err) => err.to_http(),
+            NamespaceError::NetworkPolicy(err) => err.to_http(),
+            NamespaceError::Bundle(err) => err.to_http(),
+            NamespaceError::Etcd(err) => {
+                HttpResponse::InternalServerError().body(format!("Etcd error: {}", err))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct NamespaceDeletionQuery {
+    #[serde(default)]
+    pub cascade: bool,
+}
+
+/// A resource removed while deleting a namespace.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct DeletedResource {
+    pub kind: String,
+    pub name: String,
+}
+
+/// A resource which couldn't be deleted with its namespace, `status` and `detail` being the ones
+/// of the error its own deletion would have answered.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct FailedResource {
+    pub kind: String,
+    pub name: String,
+    pub status: u16,
+    pub detail: String,
+}
+
+/// Report of a namespace deletion, listing the deleted resources in the order they were deleted
+/// and the ones which failed. Deleting the namespace again retries the failed ones.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NamespaceDeletion {
+    pub namespace: String,
+    pub deleted: Vec<DeletedResource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed: Vec<FailedResource>,
+}
+
+impl NamespaceDeletion {
+    pub fn new(namespace: &str) -> NamespaceDeletion {
+        NamespaceDeletion {
+            namespace: namespace.to_string(),
+            deleted: vec![],
+            failed: vec![],
+        }
+    }
+
+    pub fn push(&mut self, kind: &str, name: &str) {
+        self.deleted.push(DeletedResource {
+            kind: kind.to_string(),
+            name: name.to_string(),
+        });
+    }
+
+    /// Records a resource whose deletion failed with `err`.
+    pub fn fail(&mut self, kind: &str, name: &str, err: &NamespaceError) {
+        let response = err.to_http();
+        let status = response.status().as_u16();
+        // the detail of the problem answered, or the plain text body of the older errors
+        let detail = match response.into_body().try_into_bytes() {
+            Ok(body) => match serde_json::from_slice::<Problem>(&body) {
+                Ok(problem) => problem.detail,
+                Err(_) => String::from_utf8_lossy(&body).trim().to_string(),
+            },
+            Err(_) => String::new(),
+        };
+        self.failed.push(FailedResource {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            status,
+            detail,
+        });
+    }
+
+    /// Answers the report, with a 500 status if a resource couldn't be deleted.
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) if !self.failed.is_empty() => HttpResponse::InternalServerError().body(json),
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the namespace deletion report to json: {}",
+                err
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn test_fail() {
+        let mut report = NamespaceDeletion::new("default");
+        report.push("service", "web");
+        report.fail(
+            "instance",
+            "web-1",
+            &NamespaceError::Instance(InstanceError::Grpc("unavailable".to_string())),
+        );
+        report.fail(
+            "service",
+            "api",
+            &NamespaceError::Service(ServiceError::Etcd("timeout".to_string())),
+        );
+
+        assert_eq!(
+            report.failed,
+            vec![
+                FailedResource {
+                    kind: "instance".to_string(),
+                    name: "web-1".to_string(),
+                    status: 502,
+                    detail: "Scheduler error: unavailable".to_string(),
+                },
+                FailedResource {
+                    kind: "service".to_string(),
+                    name: "api".to_string(),
+                    status: 500,
+                    detail: "Etcd error: timeout".to_string(),
+                },
+            ]
+        );
+        assert_eq!(report.to_http().status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_report_without_failure() {
+        let mut report = NamespaceDeletion::new("default");
+        report.push("service", "web");
+
+        assert_eq!(report.to_http().status(), StatusCode::OK);
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "namespace": "default",
+                "deleted": [{"kind": "service", "name": "web"}],
+            })
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::net::SocketAddr;
+
+use log::{info, warn};
+
+use super::model::{NamespaceDeletion, NamespaceError};
+use crate::external_api::bundle::service::BundleService;
+use crate::external_api::cronjob::service::CronJobService;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::ingress::service::IngressService;
+use crate::external_api::instance::model::InstanceFilter;
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::network_policy::service::NetworkPolicyService;
+use crate::external_api::service::service::ServiceService;
+use crate::external_api::workload::service::WorkloadService;
+
+/// `NamespaceService` manages the namespaces as a whole. A namespace isn't stored in etcd, it
+/// exists as long as a resource is stored in it.
+/// Properties:
+///
+/// * `cronjob_service`: This is the service used to delete the cron jobs of the namespace.
+/// * `bundle_service`: This is the service used to delete the bundles of the namespace.
+/// * `workload_service`: This is the service used to delete the workloads of the namespace.
+/// * `instance_service`: This is the service used to destroy the instances of the namespace.
+/// * `service_service`: This is the service used to delete the services of the namespace.
+/// * `ingress_service`: This is the service used to delete the ingresses of the namespace.
+/// * `network_policy_service`: This is the service used to delete the network policies of the
+///   namespace.
+pub struct NamespaceService {
+    cronjob_service: CronJobService,
+    bundle_service: BundleService,
+    workload_service: WorkloadService,
+    instance_service: InstanceService,
+    service_service: ServiceService,
+    ingress_service: IngressService,
+    network_policy_service: NetworkPolicyService,
+}
+
+impl NamespaceService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<NamespaceService, NamespaceError> {
+        Ok(NamespaceService {
+            cronjob_service: CronJobService::new(etcd_address)
+                .await
+                .map_err(NamespaceError::CronJob)?,
+            bundle_service: BundleService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(NamespaceError::Bundle)?,
+            workload_service: WorkloadService::new(etcd_address)
+                .await
+                .map_err(NamespaceError::Workload)?,
+            instance_service: InstanceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(NamespaceError::Instance)?,
+            service_service: ServiceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(NamespaceError::Service)?,
+            ingress_service: IngressService::new(etcd_address)
+                .await
+                .map_err(NamespaceError::Ingress)?,
+            network_policy_service: NetworkPolicyService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(NamespaceError::NetworkPolicy)?,
+        })
+    }
+
+    /// It deletes every resource of a namespace. The resources are deleted from the outside in:
+    /// cron jobs, bundles, ingresses, network policies, services, instances and workloads, then
+    /// the revisions of the workloads and the idempotency keys of the instances, so that nothing
+    /// is left pointing to a deleted resource if the deletion is interrupted.
+    ///
+    /// A resource which can't be deleted is reported and the deletion goes on with the next
+    /// ones, except for the subnet of the namespace which is kept while it has instances.
+    /// Deleting the namespace again retries the resources left.
+    ///
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace to delete.
+    /// * `cascade`: If false, the namespace is only deleted if it is already empty.
+    ///
+    /// # Returns:
+    ///
+    /// The list of the deleted resources and of the ones which failed.
+    pub async fn delete_namespace(
+        &mut self,
+        namespace: &str,
+        cascade: bool,
+    ) -> Result<NamespaceDeletion, NamespaceError> {
+        let cronjobs = self
+            .cronjob_service
+            .get_all_cronjobs(&Pagination::default(), Some(namespace))
+            .await
+            .cronjobs;
+        let bundles = self
+            .bundle_service
+            .get_all_bundles(&Pagination::default(), namespace)
+            .await
+            .bundles;
+        let ingresses = self
+            .ingress_service
+            .get_all_ingresses(&Pagination::default(), Some(namespace))
+            .await
+            .ingresses;
+        let policies = self
+            .network_policy_service
+            .get_all_network_policies(&Pagination::default(), Some(namespace))
+            .await
+            .network_policies;
+        let services = self
+            .service_service
+            .get_all_services(&Pagination::default(), Some(namespace))
+            .await
+            .services;
+        let instances = self
+            .instance_service
+            .get_all_instances(
+                &Pagination::default(),
+                namespace,
+                &InstanceFilter::default(),
+            )
+            .await
+            .instances;
+        let workloads = self
+            .workload_service
+            .get_all_workloads(&Pagination::default(), namespace)
+            .await
+            .workloads;
+
+        let empty = cronjobs.is_empty()
+            && bundles.is_empty()
+            && ingresses.is_empty()
+            && policies.is_empty()
+            && services.is_empty()
+            && instances.is_empty()
+            && workloads.is_empty();
+        if !empty && !cascade {
+            return Err(NamespaceError::NotEmpty(namespace.to_string()));
+        }
+
+        let mut report = NamespaceDeletion::new(namespace);
+        for cronjob in cronjobs {
+            self.cronjob_service
+                .delete_cronjob(&cronjob.name, namespace)
+                .await;
+            info!("Namespace {}: deleted cron job {}", namespace, cronjob.name);
+            report.push("cronjob", &cronjob.name);
+        }
+        for bundle in bundles {
+            // the resources of the bundle are deleted with the namespace
+            match self
+                .bundle_service
+                .delete_bundle(&bundle.name, namespace)
+                .await
+            {
+                Ok(()) => {
+                    info!("Namespace {}: deleted bundle {}", namespace, bundle.name);
+                    report.push("bundle", &bundle.name);
+                }
+                Err(err) => report_failure(
+                    &mut report,
+                    "bundle",
+                    &bundle.name,
+                    NamespaceError::Bundle(err),
+                ),
+            }
+        }
+        for ingress in ingresses {
+            self.ingress_service
+                .delete_ingress(&ingress.name, namespace)
+                .await;
+            info!("Namespace {}: deleted ingress {}", namespace, ingress.name);
+            report.push("ingress", &ingress.name);
+        }
+        for policy in policies {
+            self.network_policy_service
+                .delete_network_policy(&policy.name, namespace)
+                .await;
+            info!(
+                "Namespace {}: deleted network policy {}",
+                namespace, policy.name
+            );
+            report.push("networkpolicy", &policy.name);
+        }
+        for service in services {
+            match self
+                .service_service
+                .delete_service(&service.name, namespace)
+                .await
+            {
+                Ok(()) => {
+                    info!("Namespace {}: deleted service {}", namespace, service.name);
+                    report.push("service", &service.name);
+                }
+                Err(err) => report_failure(
+                    &mut report,
+                    "service",
+                    &service.name,
+                    NamespaceError::Service(err),
+                ),
+            }
+        }
+        let mut instances_left = false;
+        for instance in instances {
+            match self
+                .instance_service
+                .delete_instance(&instance.id, namespace)
+                .await
+            {
+                Ok(_) => {
+                    info!("Namespace {}: deleted instance {}", namespace, instance.id);
+                    report.push("instance", &instance.id);
+                }
+                Err(err) => {
+                    instances_left = true;
+                    report_failure(
+                        &mut report,
+                        "instance",
+                        &instance.id,
+                        NamespaceError::Instance(err),
+                    );
+                }
+            }
+        }
+        match self.instance_service.sticky_names().await {
+            Ok(sticky_names) => {
+                for (sticky_namespace, name) in sticky_names {
+                    if sticky_namespace == namespace {
+                        self.instance_service
+                            .release_sticky_address(namespace, &name)
+                            .await;
+                    }
+                }
+            }
+            Err(err) => report_failure(
+                &mut report,
+                "address",
+                namespace,
+                NamespaceError::Instance(err),
+            ),
+        }
+        // the addresses of the instances left are still taken from the subnet
+        if !instances_left {
+            if let Err(err) = self.instance_service.release_subnet(namespace).await {
+                report_failure(
+                    &mut report,
+                    "subnet",
+                    namespace,
+                    NamespaceError::Instance(err),
+                );
+            }
+        }
+        for workload in workloads {
+            self.workload_service
+                .delete_workload(&workload.name, namespace)
+                .await;
+            info!(
+                "Namespace {}: deleted workload {}",
+                namespace, workload.name
+            );
+            report.push("workload", &workload.name);
+        }
+        if let Err(err) = self
+            .workload_service
+            .delete_namespace_revisions(namespace)
+            .await
+        {
+            report_failure(
+                &mut report,
+                "revisions",
+                namespace,
+                NamespaceError::Workload(err),
+            );
+        }
+        if let Err(err) = self
+            .instance_service
+            .delete_idempotency_keys(namespace)
+            .await
+        {
+            report_failure(
+                &mut report,
+                "idempotencykeys",
+                namespace,
+                NamespaceError::Instance(err),
+            );
+        }
+
+        info!(
+            "Namespace {} deleted with {} resource(s), {} failed",
+            namespace,
+            report.deleted.len(),
+            report.failed.len()
+        );
+        Ok(report)
+    }
+}
+
+/// Logs the failed deletion of a resource of a namespace and adds it to the report.
+fn report_failure(report: &mut NamespaceDeletion, kind: &str, name: &str, err: NamespaceError) {
+    warn!(
+        "Namespace {}: failed to delete {} {}",
+        report.namespace, kind, name
+    );
+    report.fail(kind, name, &err);
+}
@@ -0,0 +1,200 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::NetworkPolicyDTO;
+use super::service::NetworkPolicyService;
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::generic::problem::Problem;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, Scope};
+pub struct NetworkPolicyController {}
+impl NetworkPolicyController {
+    pub fn services(&self) -> Scope {
+        web::scope("/networkpolicy")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
+            .service(
+                web::resource("/{namespace}/{policy_name}/rules")
+                    .route(web::get().to(NetworkPolicyController::rules)),
+            )
+            .service(
+                web::resource("/{namespace}/{policy_name}")
+                    .route(web::delete().to(NetworkPolicyController::delete_network_policy))
+                    .route(web::get().to(NetworkPolicyController::network_policy))
+                    .route(web::patch().to(NetworkPolicyController::patch_network_policy)),
+            )
+            .service(
+                web::resource("/{namespace}")
+                    .route(web::put().to(NetworkPolicyController::put_network_policy))
+                    .route(web::get().to(NetworkPolicyController::get_all_network_policies)),
+            )
+            .service(
+                web::resource("")
+                    .route(web::get().to(NetworkPolicyController::get_cluster_network_policies)),
+            )
+    }
+
+    /// `network_policy` is an async function that handle **/networkpolicy/\<namespace>/<policy_name>** route (GET)
+    /// # Description:
+    /// * Get a network policy
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the policy name.
+    pub async fn network_policy(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, policy_name) = params.into_inner();
+
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        policy_service
+            .get_versioned_network_policy(&policy_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |p| p.to_http())
+    }
+
+    /// `rules` is an async function that handle **/networkpolicy/\<namespace>/<policy_name>/rules** route (GET)
+    /// # Description:
+    /// * Get the rules of a network policy resolved to the addresses of the instances, polled by
+    ///   the node agents to program their firewall
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the policy name.
+    pub async fn rules(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, policy_name) = params.into_inner();
+
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        policy_service
+            .get_rules(&policy_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |r| r.to_http())
+    }
+
+    /// `put_network_policy` is an async function that handle **/networkpolicy/\<namespace>** route (PUT)
+    /// # Description:
+    /// * Create a new network policy
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - This is the namespace that the policy will be created in.
+    /// * `body`: web::Json<NetworkPolicyDTO> - Contain the selector and the rules of the policy.
+    pub async fn put_network_policy(
+        namespace: web::Path<String>,
+        body: web::Json<NetworkPolicyDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        policy_service
+            .create_network_policy(body.into_inner(), &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |p| p.to_http())
+    }
+
+    /// `get_all_network_policies` is an async function that handle **/networkpolicy/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get all network policies in the namespace
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the policies you want to retrieve.
+    /// * `pagination`: Option<web::Query<Pagination>>
+    pub async fn get_all_network_policies(
+        namespace: web::Path<String>,
+        pagination: Option<web::Query<Pagination>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        policy_service
+            .get_all_network_policies(&pagination, Some(&namespace))
+            .await
+            .to_http()
+    }
+
+    /// `get_cluster_network_policies` is an async function that handle **/networkpolicy** route (GET)
+    /// # Description:
+    /// * Get the network policies of every namespace, used by the node agents to find the
+    ///   policies to program
+    pub async fn get_cluster_network_policies(data: web::Data<ActixAppState>) -> impl Responder {
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        policy_service
+            .get_all_network_policies(&Pagination::default(), None)
+            .await
+            .to_http()
+    }
+
+    /// `patch_network_policy` is an async function that handle **/networkpolicy/\<namespace>/<policy_name>** route (PATCH)
+    /// # Description:
+    /// * Replace the selector and the rules of a network policy
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the policy name.
+    /// * `body`: web::Json<NetworkPolicyDTO> - Contain the new selector and rules of the policy.
+    pub async fn patch_network_policy(
+        params: web::Path<(String, String)>,
+        body: web::Json<NetworkPolicyDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, policy_name) = params.into_inner();
+
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        policy_service
+            .update_network_policy(body.into_inner(), &policy_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |p| p.to_http())
+    }
+
+    /// `delete_network_policy` is an async function that handle **/networkpolicy/\<namespace>/<policy_name>** route (DELETE)
+    /// # Description:
+    /// * Delete a network policy
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the policy name.
+    pub async fn delete_network_policy(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, policy_name) = params.into_inner();
+
+        let mut policy_service =
+            match NetworkPolicyService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        policy_service
+            .delete_network_policy(&policy_name, &namespace)
+            .await;
+        HttpResponse::build(StatusCode::NO_CONTENT).body("Remove successfully")
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::model::version_conflict;
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::instance::model::Instance;
+
+pub enum NetworkPolicyError {
+    NetworkPolicyNotFound,
+    Etcd(String),
+    NameAlreadyExists(String),
+    VersionConflict(String, i64),
+    JsonToNetworkPolicy(String),
+    NetworkPolicyToJson(String),
+}
+
+impl NetworkPolicyError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            NetworkPolicyError::NetworkPolicyNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "network_policy_not_found",
+                "Network policy not found",
+            ),
+            NetworkPolicyError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            NetworkPolicyError::NameAlreadyExists(name) => Problem::new(
+                StatusCode::CONFLICT,
+                "network_policy_already_exists",
+                format!("Network policy with name {} already exists", name),
+            ),
+            NetworkPolicyError::VersionConflict(name, version) => {
+                version_conflict("network policy", name, *version)
+            }
+            NetworkPolicyError::JsonToNetworkPolicy(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_network_policy",
+                format!(
+                    "Error while converting JSON string to network policy : {}",
+                    err
+                ),
+            ),
+            NetworkPolicyError::NetworkPolicyToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "network_policy_serialization_failed",
+                format!("Error while converting the network policy to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// Returns `true` if every label of `selector` is set to the same value in `labels`, an empty
+/// selector matches everything.
+pub fn matches_labels(
+    selector: &HashMap<String, String>,
+    labels: &HashMap<String, String>,
+) -> bool {
+    selector
+        .iter()
+        .all(|(key, value)| labels.get(key) == Some(value))
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    Allow,
+    Deny,
+}
+
+/// Selects the instances a rule applies to, by namespace and labels.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PolicyPeer {
+    /// Namespace of the instances, the namespace of the policy if unset
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Labels the instances must have, every instance of the namespace is selected if empty
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+impl PolicyPeer {
+    pub fn matches(&self, policy_namespace: &str, instance: &Instance) -> bool {
+        instance.namespace == self.namespace.as_deref().unwrap_or(policy_namespace)
+            && matches_labels(&self.labels, &instance.labels)
+    }
+}
+
+/// Allows or denies the traffic coming from some instances to the instances of the policy.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct NetworkPolicyRule {
+    pub action: PolicyAction,
+    /// Sources of the traffic, the rule matches every source if empty
+    #[serde(default)]
+    pub from: Vec<PolicyPeer>,
+    /// Destination ports, the rule matches every port if empty
+    #[serde(default)]
+    pub ports: Vec<i32>,
+}
+
+/// A `NetworkPolicy` filters the traffic reaching the instances matched by its selector. The
+/// rules are evaluated in order and the first one matching decides, if the policy has an allow
+/// rule the traffic matched by none of them is denied. Node agents program the rules in their
+/// firewall.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct NetworkPolicy {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+    /// Labels of the instances of the namespace protected by the policy, every instance of the
+    /// namespace if empty
+    #[serde(default)]
+    pub selector: HashMap<String, String>,
+    pub rules: Vec<NetworkPolicyRule>,
+}
+
+impl NetworkPolicy {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => NetworkPolicyError::NetworkPolicyToJson(err.to_string()).to_http(),
+        }
+    }
+
+    /// Returns `true` if the instance is protected by the policy.
+    pub fn selects(&self, instance: &Instance) -> bool {
+        instance.namespace == self.namespace && matches_labels(&self.selector, &instance.labels)
+    }
+
+    /// Resolves the selectors of the policy to the addresses of the instances which aren't
+    /// finished, as programmed by the node agents. The instances being started are included, so
+    /// their agent protects them before they run.
+    pub fn resolve(&self, instances: &[Instance]) -> NetworkPolicyRules {
+        let addresses = |matches: &dyn Fn(&Instance) -> bool| -> Vec<Ipv4Addr> {
+            instances
+                .iter()
+                .filter(|instance| !instance.status.state.is_finished())
+                .filter(|instance| matches(instance))
+                .filter_map(|instance| instance.ip.parse().ok())
+                .collect()
+        };
+
+        NetworkPolicyRules {
+            targets: addresses(&|instance| self.selects(instance)),
+            rules: self
+                .rules
+                .iter()
+                .map(|rule| ResolvedRule {
+                    action: rule.action,
+                    sources: (!rule.from.is_empty()).then(|| {
+                        addresses(&|instance| {
+                            rule.from
+                                .iter()
+                                .any(|peer| peer.matches(&self.namespace, instance))
+                        })
+                    }),
+                    ports: rule.ports.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NetworkPolicyDTO {
+    pub name: String,
+    #[serde(default)]
+    pub selector: HashMap<String, String>,
+    pub rules: Vec<NetworkPolicyRule>,
+    /// If set, the update fails if the policy was modified since this version
+    #[serde(default, skip_serializing)]
+    pub resource_version: Option<i64>,
+}
+
+impl From<NetworkPolicy> for NetworkPolicyDTO {
+    fn from(policy: NetworkPolicy) -> Self {
+        NetworkPolicyDTO {
+            name: policy.name,
+            selector: policy.selector,
+            rules: policy.rules,
+            resource_version: None,
+        }
+    }
+}
+
+/// A rule of a policy with its sources resolved to addresses.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ResolvedRule {
+    pub action: PolicyAction,
+    /// Addresses of the sources, every source if unset
+    pub sources: Option<Vec<Ipv4Addr>>,
+    pub ports: Vec<i32>,
+}
+
+/// Addresses of the instances protected by a policy and its resolved rules.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct NetworkPolicyRules {
+    pub targets: Vec<Ipv4Addr>,
+    pub rules: Vec<ResolvedRule>,
+}
+
+impl NetworkPolicyRules {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => NetworkPolicyError::NetworkPolicyToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NetworkPolicyVector {
+    pub network_policies: Vec<NetworkPolicy>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl NetworkPolicyVector {
+    pub fn new(network_policies: Vec<NetworkPolicy>) -> NetworkPolicyVector {
+        NetworkPolicyVector {
+            network_policies,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => NetworkPolicyError::NetworkPolicyToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::{InstanceState, InstanceStatus};
+
+    fn instance(namespace: &str, ip: &str, app: &str, state: InstanceState) -> Instance {
+        Instance {
+            id: ip.to_string(),
+            name: ip.to_string(),
+            workload_id: format!("{}.{}", namespace, app),
+            ip: ip.to_string(),
+            namespace: namespace.to_string(),
+            status: InstanceStatus {
+                state,
+                status_description: String::new(),
+            },
+            labels: HashMap::from([("app".to_string(), app.to_string())]),
+            ..Default::default()
+        }
+    }
+
+    fn labels(app: &str) -> HashMap<String, String> {
+        HashMap::from([("app".to_string(), app.to_string())])
+    }
+
+    #[test]
+    fn test_matches_labels() {
+        assert!(matches_labels(&HashMap::new(), &labels("db")));
+        assert!(matches_labels(&labels("db"), &labels("db")));
+        assert!(!matches_labels(&labels("db"), &labels("web")));
+        assert!(!matches_labels(&labels("db"), &HashMap::new()));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let policy = NetworkPolicy {
+            id: "networkpolicy.prod.db".to_string(),
+            name: "db".to_string(),
+            namespace: "prod".to_string(),
+            selector: labels("db"),
+            rules: vec![
+                NetworkPolicyRule {
+                    action: PolicyAction::Allow,
+                    from: vec![
+                        PolicyPeer {
+                            namespace: None,
+                            labels: labels("web"),
+                        },
+                        PolicyPeer {
+                            namespace: Some("monitoring".to_string()),
+                            labels: HashMap::new(),
+                        },
+                    ],
+                    ports: vec![5432],
+                },
+                NetworkPolicyRule {
+                    action: PolicyAction::Deny,
+                    from: vec![],
+                    ports: vec![],
+                },
+            ],
+        };
+        let instances = vec![
+            instance("prod", "10.244.0.2", "db", InstanceState::Running),
+            instance("prod", "10.244.0.3", "db", InstanceState::Terminated),
+            instance("prod", "10.244.0.5", "db", InstanceState::Scheduled),
+            instance("prod", "10.244.0.4", "web", InstanceState::Running),
+            instance("dev", "10.244.1.2", "web", InstanceState::Running),
+            instance("monitoring", "10.244.2.2", "probe", InstanceState::Running),
+        ];
+
+        let rules = policy.resolve(&instances);
+        assert_eq!(
+            rules.targets,
+            vec![Ipv4Addr::new(10, 244, 0, 2), Ipv4Addr::new(10, 244, 0, 5)]
+        );
+        assert_eq!(
+            rules.rules[0].sources,
+            Some(vec![
+                Ipv4Addr::new(10, 244, 0, 4),
+                Ipv4Addr::new(10, 244, 2, 2)
+            ])
+        );
+        assert_eq!(rules.rules[0].ports, vec![5432]);
+        assert_eq!(rules.rules[1].action, PolicyAction::Deny);
+        assert_eq!(rules.rules[1].sources, None);
+    }
+}
@@ -0,0 +1,178 @@
+use std::net::SocketAddr;
+
+use super::model::{
+    NetworkPolicy, NetworkPolicyDTO, NetworkPolicyError, NetworkPolicyRules, NetworkPolicyVector,
+};
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::{Pagination, Versioned};
+use crate::external_api::instance::service::InstanceService;
+
+/// `NetworkPolicyService` is the service used by the `NetworkPolicyController` to store network
+/// policies in etcd and to resolve their rules.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `instance_service`: This is the service used to find the instances selected by a policy.
+pub struct NetworkPolicyService {
+    etcd_service: EtcdClient,
+    instance_service: InstanceService,
+}
+
+impl NetworkPolicyService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<NetworkPolicyService, NetworkPolicyError> {
+        Ok(NetworkPolicyService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| NetworkPolicyError::Etcd(err.to_string()))?,
+            instance_service: InstanceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(|_| NetworkPolicyError::Etcd("unable to reach instances".to_string()))?,
+        })
+    }
+
+    pub async fn get_network_policy(
+        &mut self,
+        policy_name: &str,
+        namespace: &str,
+    ) -> Result<NetworkPolicy, NetworkPolicyError> {
+        let id = self.id(policy_name, namespace);
+        match self.etcd_service.get(&id).await {
+            Some(policy) => serde_json::from_str(&policy)
+                .map_err(|err| NetworkPolicyError::JsonToNetworkPolicy(err.to_string())),
+            None => Err(NetworkPolicyError::NetworkPolicyNotFound),
+        }
+    }
+
+    /// Reads a network policy and its version.
+    pub async fn get_versioned_network_policy(
+        &mut self,
+        policy_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<NetworkPolicy>, NetworkPolicyError> {
+        let id = self.id(policy_name, namespace);
+        match self.etcd_service.get_versioned(&id).await {
+            Some((policy, version)) => serde_json::from_str(&policy)
+                .map(|policy| Versioned::new(policy, version))
+                .map_err(|err| NetworkPolicyError::JsonToNetworkPolicy(err.to_string())),
+            None => Err(NetworkPolicyError::NetworkPolicyNotFound),
+        }
+    }
+
+    /// This function gets the network policies of a namespace, or of every namespace if
+    /// `namespace` is `None`, paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    pub async fn get_all_network_policies(
+        &mut self,
+        pagination: &Pagination,
+        namespace: Option<&str>,
+    ) -> NetworkPolicyVector {
+        let prefix = match namespace {
+            Some(namespace) => self.id("", namespace),
+            None => "networkpolicy.".to_string(),
+        };
+        match self
+            .etcd_service
+            .list_prefix(
+                &prefix,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |_: &NetworkPolicy| true,
+            )
+            .await
+        {
+            Ok(listing) => {
+                NetworkPolicyVector::new(listing.items).with_continue_token(listing.continue_token)
+            }
+            Err(_) => NetworkPolicyVector::new(vec![]),
+        }
+    }
+
+    pub async fn create_network_policy(
+        &mut self,
+        policy_dto: NetworkPolicyDTO,
+        namespace: &str,
+    ) -> Result<NetworkPolicy, NetworkPolicyError> {
+        match self.get_network_policy(&policy_dto.name, namespace).await {
+            Ok(policy) => return Err(NetworkPolicyError::NameAlreadyExists(policy.name)),
+            Err(NetworkPolicyError::NetworkPolicyNotFound) => {}
+            Err(err) => return Err(err),
+        }
+
+        let policy = NetworkPolicy {
+            id: self.id(&policy_dto.name, namespace),
+            name: policy_dto.name,
+            namespace: namespace.to_string(),
+            selector: policy_dto.selector,
+            rules: policy_dto.rules,
+        };
+        self.put_network_policy(&policy, None).await?;
+        Ok(policy)
+    }
+
+    /// It replaces the selector and the rules of a network policy, if `resource_version` is set
+    /// only if the policy wasn't modified since.
+    pub async fn update_network_policy(
+        &mut self,
+        policy_dto: NetworkPolicyDTO,
+        policy_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<NetworkPolicy>, NetworkPolicyError> {
+        let mut policy = self.get_network_policy(policy_name, namespace).await?;
+        policy.selector = policy_dto.selector;
+        policy.rules = policy_dto.rules;
+        let version = self
+            .put_network_policy(&policy, policy_dto.resource_version)
+            .await?;
+        Ok(Versioned::new(policy, version))
+    }
+
+    pub async fn delete_network_policy(&mut self, policy_name: &str, namespace: &str) {
+        let id = self.id(policy_name, namespace);
+        _ = self.etcd_service.delete(&id).await;
+    }
+
+    /// It returns the addresses of the instances protected by a policy and of the sources of
+    /// each of its rules, the rules may select instances of every namespace.
+    pub async fn get_rules(
+        &mut self,
+        policy_name: &str,
+        namespace: &str,
+    ) -> Result<NetworkPolicyRules, NetworkPolicyError> {
+        let policy = self.get_network_policy(policy_name, namespace).await?;
+        let instances = self
+            .instance_service
+            .get_instances_of_all_namespaces()
+            .await;
+
+        Ok(policy.resolve(&instances))
+    }
+
+    /// Stores a network policy, only if it is still at the version `expected` if set. Returns
+    /// the version of the policy stored.
+    async fn put_network_policy(
+        &mut self,
+        policy: &NetworkPolicy,
+        expected: Option<i64>,
+    ) -> Result<i64, NetworkPolicyError> {
+        let json = serde_json::to_string(policy)
+            .map_err(|err| NetworkPolicyError::NetworkPolicyToJson(err.to_string()))?;
+        self.etcd_service
+            .put_if_version(&policy.id, &json, expected)
+            .await
+            .map_err(|err| NetworkPolicyError::Etcd(err.to_string()))?
+            .ok_or_else(|| {
+                NetworkPolicyError::VersionConflict(
+                    policy.name.clone(),
+                    expected.unwrap_or_default(),
+                )
+            })
+    }
+
+    pub fn id(&self, name: &str, namespace: &str) -> String {
+        format!("networkpolicy.{}.{}", namespace, name)
+    }
+}
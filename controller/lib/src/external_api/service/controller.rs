@@ -0,0 +1,256 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::{ServiceDTO, SwitchDTO};
+use super::service::ServiceService;
+use crate::external_api::generic::model::Pagination;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, Responder, Scope};
+pub struct ServiceController {}
+impl ServiceController {
+    pub fn services(&self) -> Scope {
+        web::scope("/service")
+            .service(
+                web::resource("/{namespace}/{service_name}/endpoints")
+                    .route(web::get().to(ServiceController::endpoints)),
+            )
+            .service(
+                web::resource("/{namespace}/{service_name}/switch")
+                    .route(web::post().to(ServiceController::switch_service)),
+            )
+            .service(
+                web::resource("/{namespace}/{service_name}/rollback")
+                    .route(web::post().to(ServiceController::rollback_service)),
+            )
+            .service(
+                web::resource("/{namespace}/{service_name}")
+                    .route(web::delete().to(ServiceController::delete_service))
+                    .route(web::get().to(ServiceController::service))
+                    .route(web::patch().to(ServiceController::patch_service)),
+            )
+            .service(
+                web::resource("/{namespace}")
+                    .route(web::put().to(ServiceController::put_service))
+                    .route(web::get().to(ServiceController::get_all_services)),
+            )
+            .service(
+                web::resource("").route(web::get().to(ServiceController::get_cluster_services)),
+            )
+    }
+
+    /// `service` is an async function that handle **/service/\<namespace>/<service_name>** route (GET)
+    /// # Description:
+    /// * Get a service
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the service name.
+    pub async fn service(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, service_name) = params.into_inner();
+
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .get_versioned_service(&service_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |s| s.to_http())
+    }
+
+    /// `endpoints` is an async function that handle **/service/\<namespace>/<service_name>/endpoints** route (GET)
+    /// # Description:
+    /// * Get the virtual IP of a service and the addresses of the running instances behind it
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the service name.
+    pub async fn endpoints(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, service_name) = params.into_inner();
+
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .get_endpoints(&service_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |e| e.to_http())
+    }
+
+    /// `switch_service` is an async function that handle **/service/\<namespace>/<service_name>/switch** route (POST)
+    /// # Description:
+    /// * Route a service to another workload, the workload targeted before is kept for a rollback
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the service name.
+    /// * `body`: web::Json<SwitchDTO> - Contain the workload the service is routed to.
+    pub async fn switch_service(
+        params: web::Path<(String, String)>,
+        body: web::Json<SwitchDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, service_name) = params.into_inner();
+
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .switch_service(body.into_inner(), &service_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |s| s.to_http())
+    }
+
+    /// `rollback_service` is an async function that handle **/service/\<namespace>/<service_name>/rollback** route (POST)
+    /// # Description:
+    /// * Route a service back to the workload it targeted before its last switch
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the service name.
+    pub async fn rollback_service(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, service_name) = params.into_inner();
+
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .rollback_service(&service_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |s| s.to_http())
+    }
+
+    /// `put_service` is an async function that handle **/service/\<namespace>** route (PUT)
+    /// # Description:
+    /// * Create a new service and allocate its virtual IP
+    /// # Arguments:
+    ///
+    /// * `namespace`: web::Path<String> - This is the namespace that the service will be created in.
+    /// * `body`: web::Json<ServiceDTO> - Contain all information required to create the service.
+    pub async fn put_service(
+        namespace: web::Path<String>,
+        body: web::Json<ServiceDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service.with_node_port_range(data.node_port_range),
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .create_service(body.into_inner(), &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |s| s.to_http())
+    }
+
+    /// `get_all_services` is an async function that handle **/service/\<namespace>** route (GET)
+    /// # Description:
+    /// * Get all services in the namespace
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the services you want to retrieve.
+    /// * `pagination`: Option<web::Query<Pagination>>
+    pub async fn get_all_services(
+        namespace: web::Path<String>,
+        pagination: Option<web::Query<Pagination>>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        service_service
+            .get_all_services(&pagination, Some(&namespace))
+            .await
+            .to_http()
+    }
+
+    /// `get_cluster_services` is an async function that handle **/service** route (GET)
+    /// # Description:
+    /// * Get the services of every namespace, used by the node agents to find the virtual IPs
+    ///   to program
+    pub async fn get_cluster_services(data: web::Data<ActixAppState>) -> impl Responder {
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .get_all_services(&Pagination::default(), None)
+            .await
+            .to_http()
+    }
+
+    /// `patch_service` is an async function that handle **/service/\<namespace>/<service_name>** route (PATCH)
+    /// # Description:
+    /// * Update the selector and ports of a service
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the service name.
+    /// * `body`: web::Json<ServiceDTO> - Contain all information required to update the service.
+    pub async fn patch_service(
+        params: web::Path<(String, String)>,
+        body: web::Json<ServiceDTO>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, service_name) = params.into_inner();
+
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service.with_node_port_range(data.node_port_range),
+                Err(e) => return e.to_http(),
+            };
+
+        service_service
+            .update_service(body.into_inner(), &service_name, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |s| s.to_http())
+    }
+
+    /// `delete_service` is an async function that handle **/service/\<namespace>/<service_name>** route (DELETE)
+    /// # Description:
+    /// * Delete a service and release its virtual IP
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the service name.
+    pub async fn delete_service(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, service_name) = params.into_inner();
+
+        let mut service_service =
+            match ServiceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        match service_service
+            .delete_service(&service_name, &namespace)
+            .await
+        {
+            Ok(()) => HttpResponse::build(StatusCode::NO_CONTENT).body("Remove successfully"),
+            Err(e) => e.to_http(),
+        }
+    }
+}
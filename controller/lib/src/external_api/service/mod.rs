@@ -0,0 +1,4 @@
+pub mod controller;
+pub mod model;
+#[allow(clippy::module_inception)]
+pub mod service;
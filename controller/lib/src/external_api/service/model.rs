@@ -0,0 +1,390 @@
+use std::net::Ipv4Addr;
+
+use actix_web::HttpResponse;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::model::version_conflict;
+use crate::external_api::workload::model::Ports;
+
+/// Range in which services virtual IPs are allocated (10.96.0.0/16).
+pub const SERVICE_VIP_NETWORK: Ipv4Addr = Ipv4Addr::new(10, 96, 0, 0);
+pub const SERVICE_VIP_PREFIX_LENGTH: u8 = 16;
+
+/// Range in which the node ports of the `NodePort` services are allocated.
+///
+/// Properties:
+///
+/// * `start`: The first port of the range.
+/// * `end`: The last port of the range, included.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodePortRange {
+    pub start: i32,
+    pub end: i32,
+}
+
+impl Default for NodePortRange {
+    fn default() -> Self {
+        NodePortRange {
+            start: 30000,
+            end: 32767,
+        }
+    }
+}
+
+pub enum ServiceError {
+    ServiceNotFound,
+    Etcd(String),
+    NameAlreadyExists(String),
+    NoVirtualIpAvailable,
+    NoNodePortAvailable,
+    WorkloadNotFound(String),
+    NoRunningInstance(String),
+    NoPreviousWorkload,
+    VersionConflict(String, i64),
+    JsonToService(String),
+    ServiceToJson(String),
+}
+
+impl ServiceError {
+    pub fn to_http(&self) -> HttpResponse {
+        match self {
+            ServiceError::ServiceNotFound => HttpResponse::NotFound().body("Service not found"),
+            ServiceError::Etcd(err) => {
+                HttpResponse::InternalServerError().body(format!("Etcd error: {} ", err))
+            }
+            ServiceError::NameAlreadyExists(name) => {
+                HttpResponse::Conflict().body(format!("Service with name {} already exists", name))
+            }
+            ServiceError::NoVirtualIpAvailable => HttpResponse::InsufficientStorage()
+                .body("No virtual IP address left in the services range"),
+            ServiceError::NoNodePortAvailable => {
+                HttpResponse::InsufficientStorage().body("No port left in the node ports range")
+            }
+            ServiceError::WorkloadNotFound(workload) => {
+                HttpResponse::NotFound().body(format!("Workload {} not found", workload))
+            }
+            ServiceError::NoRunningInstance(workload) => HttpResponse::Conflict().body(format!(
+                "Workload {} has no running instance, the switch must be forced",
+                workload
+            )),
+            ServiceError::NoPreviousWorkload => HttpResponse::Conflict()
+                .body("The service wasn't switched, there is no workload to roll back to"),
+            ServiceError::VersionConflict(name, version) => {
+                version_conflict("service", name, *version).to_http()
+            }
+            ServiceError::JsonToService(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting JSON string to service : {}",
+                err
+            )),
+            ServiceError::ServiceToJson(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the service to JSON: {}",
+                err
+            )),
+        }
+    }
+}
+
+/// Selects the instances a service load-balances across.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServiceSelector {
+    /// Name of the workload whose instances are targeted
+    pub workload: String,
+}
+
+/// How a service is exposed.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ServiceType {
+    /// The service is only reachable on its virtual IP, inside the cluster
+    #[default]
+    ClusterIP,
+    /// The service is also reachable on a port of every node, from outside the cluster
+    NodePort,
+}
+
+/// A port of every node forwarded to a port of a `NodePort` service.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodePort {
+    /// Port of the service, the `source` of one of its ports
+    pub port: i32,
+    /// Port opened on every node
+    pub node_port: i32,
+}
+
+/// A `Service` gives a stable virtual IP to the instances matched by its selector. Node agents
+/// redirect the traffic sent to this virtual IP to one of the ready instances.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Service {
+    pub id: String,
+    pub name: String,
+    pub namespace: String,
+    pub selector: ServiceSelector,
+    pub virtual_ip: Ipv4Addr,
+    pub ports: Vec<Ports>,
+    #[serde(default)]
+    pub service_type: ServiceType,
+    /// Node ports allocated to the ports of a `NodePort` service
+    #[serde(default)]
+    pub node_ports: Vec<NodePort>,
+    /// The selector replaced by the last switch, restored by a rollback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_selector: Option<ServiceSelector>,
+}
+
+impl Service {
+    /// Routes the service to the instances of another workload, the current one is kept so that
+    /// the switch can be rolled back. Returns `false` if the service already targets it.
+    pub fn switch_to(&mut self, selector: ServiceSelector) -> bool {
+        if self.selector == selector {
+            return false;
+        }
+        self.previous_selector = Some(std::mem::replace(&mut self.selector, selector));
+        true
+    }
+
+    /// Routes the service back to the workload it targeted before the last switch, which can in
+    /// turn be rolled back. Returns `false` if the service was never switched.
+    pub fn rollback(&mut self) -> bool {
+        match self.previous_selector.take() {
+            Some(previous) => {
+                self.previous_selector = Some(std::mem::replace(&mut self.selector, previous));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the service to json: {}",
+                err
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ServiceDTO {
+    pub name: String,
+    pub selector: ServiceSelector,
+    pub ports: Vec<Ports>,
+    #[serde(default)]
+    pub service_type: ServiceType,
+    /// If set, the update fails if the service was modified since this version
+    #[serde(default, skip_serializing)]
+    pub resource_version: Option<i64>,
+}
+
+impl From<Service> for ServiceDTO {
+    fn from(service: Service) -> Self {
+        ServiceDTO {
+            name: service.name,
+            selector: service.selector,
+            ports: service.ports,
+            service_type: service.service_type,
+            resource_version: None,
+        }
+    }
+}
+
+/// Body of a blue/green switch of a service.
+///
+/// Properties:
+///
+/// * `workload`: The workload the service is routed to, deployed alongside the current one.
+/// * `force`: Switch even if the workload has no running instance.
+#[derive(Deserialize, Serialize)]
+pub struct SwitchDTO {
+    pub workload: String,
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Addresses of the ready instances behind a service.
+#[derive(Deserialize, Serialize)]
+pub struct ServiceEndpoints {
+    pub virtual_ip: Ipv4Addr,
+    pub ports: Vec<Ports>,
+    pub node_ports: Vec<NodePort>,
+    pub endpoints: Vec<Ipv4Addr>,
+}
+
+impl ServiceEndpoints {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the service endpoints to json: {}",
+                err
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ServiceVector {
+    pub services: Vec<Service>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
+}
+
+impl ServiceVector {
+    pub fn new(services: Vec<Service>) -> ServiceVector {
+        ServiceVector {
+            services,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
+    }
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => HttpResponse::InternalServerError().body(format!(
+                "Error while converting the services to json: {}",
+                err
+            )),
+        }
+    }
+}
+
+/// Returns the first address of the services range that is not already used, skipping the
+/// network and broadcast addresses.
+pub fn next_virtual_ip(used: &[Ipv4Addr]) -> Option<Ipv4Addr> {
+    let network = u32::from(SERVICE_VIP_NETWORK);
+    let size = 1u32 << (32 - SERVICE_VIP_PREFIX_LENGTH);
+
+    (1..size - 1)
+        .map(|offset| Ipv4Addr::from(network + offset))
+        .find(|ip| !used.contains(ip))
+}
+
+/// Allocates a node port to each port of a service, the ports keeping their previous node port.
+/// Returns `None` if the range is exhausted.
+///
+/// Arguments:
+///
+/// * `ports`: The ports of the service.
+/// * `previous`: The node ports the service had before, empty for a new service.
+/// * `used`: The node ports allocated to the other services.
+/// * `range`: The range in which the node ports are allocated.
+pub fn allocate_node_ports(
+    ports: &[Ports],
+    previous: &[NodePort],
+    used: &[i32],
+    range: &NodePortRange,
+) -> Option<Vec<NodePort>> {
+    let mut used = used.to_vec();
+    let mut node_ports = vec![];
+
+    for port in ports {
+        let node_port = match previous
+            .iter()
+            .find(|previous| previous.port == port.source)
+        {
+            Some(previous) => previous.node_port,
+            None => (range.start..=range.end).find(|node_port| !used.contains(node_port))?,
+        };
+        used.push(node_port);
+        node_ports.push(NodePort {
+            port: port.source,
+            node_port,
+        });
+    }
+
+    Some(node_ports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_virtual_ip_first() {
+        assert_eq!(next_virtual_ip(&[]), Some(Ipv4Addr::new(10, 96, 0, 1)));
+    }
+
+    #[test]
+    fn test_next_virtual_ip_reuses_released() {
+        let used = vec![Ipv4Addr::new(10, 96, 0, 1), Ipv4Addr::new(10, 96, 0, 3)];
+        assert_eq!(next_virtual_ip(&used), Some(Ipv4Addr::new(10, 96, 0, 2)));
+    }
+
+    fn selector(workload: &str) -> ServiceSelector {
+        ServiceSelector {
+            workload: workload.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_switch_and_rollback() {
+        let mut service = Service {
+            id: "service.default.web".to_string(),
+            name: "web".to_string(),
+            namespace: "default".to_string(),
+            selector: selector("web-blue"),
+            virtual_ip: Ipv4Addr::new(10, 96, 0, 1),
+            ports: vec![],
+            service_type: ServiceType::ClusterIP,
+            node_ports: vec![],
+            previous_selector: None,
+        };
+        assert!(!service.rollback());
+        assert!(!service.switch_to(selector("web-blue")));
+
+        assert!(service.switch_to(selector("web-green")));
+        assert_eq!(service.selector, selector("web-green"));
+        assert_eq!(service.previous_selector, Some(selector("web-blue")));
+
+        // a rollback can itself be rolled back
+        assert!(service.rollback());
+        assert_eq!(service.selector, selector("web-blue"));
+        assert!(service.rollback());
+        assert_eq!(service.selector, selector("web-green"));
+    }
+
+    #[test]
+    fn test_allocate_node_ports() {
+        let range = NodePortRange {
+            start: 30000,
+            end: 30002,
+        };
+        let ports = vec![
+            Ports {
+                source: 80,
+                destination: 8080,
+            },
+            Ports {
+                source: 443,
+                destination: 8443,
+            },
+        ];
+        let previous = vec![NodePort {
+            port: 443,
+            node_port: 30002,
+        }];
+
+        assert_eq!(
+            allocate_node_ports(&ports, &previous, &[30000, 30002], &range),
+            Some(vec![
+                NodePort {
+                    port: 80,
+                    node_port: 30001
+                },
+                NodePort {
+                    port: 443,
+                    node_port: 30002
+                },
+            ])
+        );
+        assert_eq!(
+            allocate_node_ports(&ports, &[], &[30000, 30001], &range),
+            None
+        );
+    }
+}
@@ -0,0 +1,527 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use futures_util::TryStreamExt;
+
+use super::model::{
+    allocate_node_ports, next_virtual_ip, NodePort, NodePortRange, Service, ServiceDTO,
+    ServiceEndpoints, ServiceError, ServiceSelector, ServiceType, ServiceVector, SwitchDTO,
+};
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::model::{Pagination, Versioned};
+use crate::external_api::instance::model::{InstanceFilter, InstanceState};
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::workload::model::{Ports, WorkloadError};
+use crate::external_api::workload::service::WorkloadService;
+
+/// The prefix of the keys claiming the virtual IPs, each one holds the id of its service.
+const VIRTUAL_IP_PREFIX: &str = "ipam.vip.";
+
+/// The prefix of the keys claiming the node ports, each one holds the id of its service.
+const NODE_PORT_PREFIX: &str = "ipam.nodeport.";
+
+/// The number of addresses tried before giving up, when other controllers claim the same ones.
+const MAX_CLAIM_ATTEMPTS: usize = 8;
+
+/// Returns `true` if the port of a node is in `node_ports`, whichever port of the service it
+/// forwards to.
+fn holds(node_ports: &[NodePort], node_port: &NodePort) -> bool {
+    node_ports
+        .iter()
+        .any(|held| held.node_port == node_port.node_port)
+}
+
+/// `ServiceService` is the service used by the `ServiceController` to store services in etcd and
+/// to resolve their endpoints.
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `instance_service`: This is the service used to find the instances behind a service.
+/// * `workload_service`: This is the service used to check the workload a service is switched to.
+/// * `node_port_range`: The range in which the node ports are allocated.
+pub struct ServiceService {
+    etcd_service: EtcdClient,
+    instance_service: InstanceService,
+    workload_service: WorkloadService,
+    node_port_range: NodePortRange,
+}
+
+impl ServiceService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<ServiceService, ServiceError> {
+        Ok(ServiceService {
+            etcd_service: EtcdClient::new(etcd_address.to_string())
+                .await
+                .map_err(|err| ServiceError::Etcd(err.to_string()))?,
+            instance_service: InstanceService::new(etcd_address, scheduler_address)
+                .await
+                .map_err(|_| ServiceError::Etcd("unable to reach instances".to_string()))?,
+            workload_service: WorkloadService::new(etcd_address)
+                .await
+                .map_err(|_| ServiceError::Etcd("unable to reach workloads".to_string()))?,
+            node_port_range: NodePortRange::default(),
+        })
+    }
+
+    pub fn with_node_port_range(mut self, node_port_range: NodePortRange) -> Self {
+        self.node_port_range = node_port_range;
+        self
+    }
+
+    pub async fn get_service(
+        &mut self,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<Service, ServiceError> {
+        let id = self.id(service_name, namespace);
+        match self.etcd_service.get(&id).await {
+            Some(service) => serde_json::from_str(&service)
+                .map_err(|err| ServiceError::JsonToService(err.to_string())),
+            None => Err(ServiceError::ServiceNotFound),
+        }
+    }
+
+    /// Reads a service and its version.
+    pub async fn get_versioned_service(
+        &mut self,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<Service>, ServiceError> {
+        let id = self.id(service_name, namespace);
+        match self.etcd_service.get_versioned(&id).await {
+            Some((service, version)) => serde_json::from_str(&service)
+                .map(|service| Versioned::new(service, version))
+                .map_err(|err| ServiceError::JsonToService(err.to_string())),
+            None => Err(ServiceError::ServiceNotFound),
+        }
+    }
+
+    /// This function gets the services of a namespace, or of every namespace without one,
+    /// paginated by `pagination`.
+    /// If there is an error, the function always return an empty vector
+    pub async fn get_all_services(
+        &mut self,
+        pagination: &Pagination,
+        namespace: Option<&str>,
+    ) -> ServiceVector {
+        let prefix = match namespace {
+            Some(namespace) => self.id("", namespace),
+            None => "service.".to_string(),
+        };
+        match self
+            .etcd_service
+            .list_prefix(
+                &prefix,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |_: &Service| true,
+            )
+            .await
+        {
+            Ok(listing) => {
+                ServiceVector::new(listing.items).with_continue_token(listing.continue_token)
+            }
+            Err(_) => ServiceVector::new(vec![]),
+        }
+    }
+
+    /// It creates a new service in etcd and allocates it a virtual IP that is unique in the
+    /// cluster, and a node port for each of its ports if it is a `NodePort` service. The service
+    /// is only stored if no service has its name, its addresses are released otherwise.
+    pub async fn create_service(
+        &mut self,
+        service_dto: ServiceDTO,
+        namespace: &str,
+    ) -> Result<Service, ServiceError> {
+        // fails early without claiming anything, the creation below still checks it atomically
+        match self.get_service(&service_dto.name, namespace).await {
+            Ok(service) => return Err(ServiceError::NameAlreadyExists(service.name)),
+            Err(ServiceError::ServiceNotFound) => {}
+            Err(err) => return Err(err),
+        }
+
+        let id = self.id(&service_dto.name, namespace);
+        let virtual_ip = self.claim_virtual_ip(&id).await?;
+        let (node_ports, claimed) = match self
+            .node_ports(&id, service_dto.service_type, &service_dto.ports, &[])
+            .await
+        {
+            Ok(allocated) => allocated,
+            Err(err) => {
+                self.release_virtual_ip(virtual_ip).await?;
+                return Err(err);
+            }
+        };
+
+        let service = Service {
+            id,
+            name: service_dto.name,
+            namespace: namespace.to_string(),
+            selector: service_dto.selector,
+            virtual_ip,
+            ports: service_dto.ports,
+            service_type: service_dto.service_type,
+            node_ports,
+            previous_selector: None,
+        };
+        if let Err(err) = self.create_service_key(&service).await {
+            // a concurrent creation of the service may hold some of the node ports, only the
+            // ones claimed here are released
+            self.release_virtual_ip(virtual_ip).await?;
+            self.release_node_ports(&claimed).await?;
+            return Err(err);
+        }
+        Ok(service)
+    }
+
+    /// It updates the selector, the ports and the type of a service, its virtual IP and the node
+    /// ports of the ports it keeps are kept.
+    /// It updates a service, if `resource_version` is set only if the service wasn't modified
+    /// since.
+    pub async fn update_service(
+        &mut self,
+        service_dto: ServiceDTO,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<Service>, ServiceError> {
+        let mut service = self.get_service(service_name, namespace).await?;
+        let previous = service.node_ports.clone();
+        let (node_ports, claimed) = self
+            .node_ports(
+                &service.id,
+                service_dto.service_type,
+                &service_dto.ports,
+                &previous,
+            )
+            .await?;
+        service.node_ports = node_ports;
+        // the workload of the last switch isn't a rollback target anymore
+        if service.selector != service_dto.selector {
+            service.previous_selector = None;
+        }
+        service.selector = service_dto.selector;
+        service.ports = service_dto.ports;
+        service.service_type = service_dto.service_type;
+        let result = self
+            .put_service(&service, service_dto.resource_version)
+            .await;
+        // the node ports left by the update are released, or the ones claimed for it if it failed
+        let unused: Vec<NodePort> = match &result {
+            Ok(_) => previous
+                .iter()
+                .filter(|node_port| !holds(&service.node_ports, node_port))
+                .copied()
+                .collect(),
+            Err(_) => claimed,
+        };
+        self.release_node_ports(&unused).await?;
+        Ok(Versioned::new(service, result?))
+    }
+
+    /// It routes a service to another workload in a single write, for a blue/green deployment.
+    /// The workload targeted before is kept, a rollback switches back to it.
+    ///
+    /// # Arguments:
+    ///
+    /// * `switch_dto`: The workload the service is routed to, it must have a running instance
+    ///   unless the switch is forced.
+    /// * `service_name`: The name of the service.
+    /// * `namespace`: The namespace of the service and of the workload.
+    pub async fn switch_service(
+        &mut self,
+        switch_dto: SwitchDTO,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<Service, ServiceError> {
+        let Versioned {
+            resource: mut service,
+            resource_version,
+        } = self.get_versioned_service(service_name, namespace).await?;
+
+        match self
+            .workload_service
+            .get_workload(&switch_dto.workload, namespace)
+            .await
+        {
+            Ok(_) => {}
+            Err(WorkloadError::WorkloadNotFound) => {
+                return Err(ServiceError::WorkloadNotFound(switch_dto.workload))
+            }
+            Err(err) => return Err(ServiceError::Etcd(err.to_problem().detail)),
+        }
+        if !switch_dto.force
+            && self
+                .running_addresses(&switch_dto.workload, namespace)
+                .await
+                .is_empty()
+        {
+            return Err(ServiceError::NoRunningInstance(switch_dto.workload));
+        }
+
+        if service.switch_to(ServiceSelector {
+            workload: switch_dto.workload,
+        }) {
+            // a service deleted meanwhile isn't written back, its node ports are released
+            self.put_service(&service, Some(resource_version)).await?;
+        }
+        Ok(service)
+    }
+
+    /// It routes a service back to the workload it targeted before its last switch.
+    pub async fn rollback_service(
+        &mut self,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<Service, ServiceError> {
+        let Versioned {
+            resource: mut service,
+            resource_version,
+        } = self.get_versioned_service(service_name, namespace).await?;
+        if !service.rollback() {
+            return Err(ServiceError::NoPreviousWorkload);
+        }
+        self.put_service(&service, Some(resource_version)).await?;
+        Ok(service)
+    }
+
+    /// It removes a service from etcd and releases its virtual IP, a missing service is already
+    /// deleted.
+    pub async fn delete_service(
+        &mut self,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<(), ServiceError> {
+        let service = match self.get_service(service_name, namespace).await {
+            Ok(service) => service,
+            Err(ServiceError::ServiceNotFound) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.etcd_service
+            .delete_checked(&service.id)
+            .await
+            .map_err(|err| ServiceError::Etcd(err.to_string()))?;
+        self.release_virtual_ip(service.virtual_ip).await?;
+        self.release_node_ports(&service.node_ports).await
+    }
+
+    /// It returns the addresses of the running instances matched by the service selector.
+    pub async fn get_endpoints(
+        &mut self,
+        service_name: &str,
+        namespace: &str,
+    ) -> Result<ServiceEndpoints, ServiceError> {
+        let service = self.get_service(service_name, namespace).await?;
+        let endpoints = self
+            .running_addresses(&service.selector.workload, namespace)
+            .await;
+
+        Ok(ServiceEndpoints {
+            virtual_ip: service.virtual_ip,
+            ports: service.ports,
+            node_ports: service.node_ports,
+            endpoints,
+        })
+    }
+
+    /// Returns the addresses of the running instances of a workload.
+    async fn running_addresses(&mut self, workload_name: &str, namespace: &str) -> Vec<Ipv4Addr> {
+        let workload_id = format!("{}.{}", namespace, workload_name);
+        self.instance_service
+            .get_all_instances(
+                &Pagination::default(),
+                namespace,
+                &InstanceFilter::default(),
+            )
+            .await
+            .instances
+            .into_iter()
+            .filter(|instance| {
+                instance.workload_id == workload_id
+                    && instance.status.state == InstanceState::Running
+            })
+            .filter_map(|instance| instance.ip.parse::<Ipv4Addr>().ok())
+            .collect()
+    }
+
+    /// Returns the virtual IPs claimed, and the ones of the services stored before the claims.
+    async fn used_virtual_ips(&mut self) -> Result<Vec<Ipv4Addr>, ServiceError> {
+        let mut used: Vec<Ipv4Addr> = self
+            .etcd_service
+            .scan_prefix(VIRTUAL_IP_PREFIX, None)
+            .try_filter_map(|(key, _)| async move {
+                Ok(key
+                    .strip_prefix(VIRTUAL_IP_PREFIX)
+                    .and_then(|ip| ip.parse::<Ipv4Addr>().ok()))
+            })
+            .try_collect()
+            .await
+            .map_err(|err| ServiceError::Etcd(err.to_string()))?;
+        used.extend(
+            self.etcd_service
+                .get_all_with_prefix("service.")
+                .await
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|value| serde_json::from_str::<Service>(value).ok())
+                .map(|service| service.virtual_ip),
+        );
+        Ok(used)
+    }
+
+    /// Allocates a virtual IP to a service, claimed by a key created only if it doesn't exist so
+    /// that two services created at once never get the same address.
+    async fn claim_virtual_ip(&mut self, service_id: &str) -> Result<Ipv4Addr, ServiceError> {
+        let mut used = self.used_virtual_ips().await?;
+        for _ in 0..MAX_CLAIM_ATTEMPTS {
+            let ip = next_virtual_ip(&used).ok_or(ServiceError::NoVirtualIpAvailable)?;
+            let key = format!("{}{}", VIRTUAL_IP_PREFIX, ip);
+            match self
+                .etcd_service
+                .put_if_absent(&key, service_id, None)
+                .await
+                .map_err(|err| ServiceError::Etcd(err.to_string()))?
+            {
+                None => return Ok(ip),
+                // another controller claimed it meanwhile
+                Some(_) => used.push(ip),
+            }
+        }
+        Err(ServiceError::NoVirtualIpAvailable)
+    }
+
+    /// Releases the claim of a virtual IP, it can be allocated again.
+    async fn release_virtual_ip(&mut self, ip: Ipv4Addr) -> Result<(), ServiceError> {
+        self.etcd_service
+            .delete_checked(&format!("{}{}", VIRTUAL_IP_PREFIX, ip))
+            .await
+            .map(|_| ())
+            .map_err(|err| ServiceError::Etcd(err.to_string()))
+    }
+
+    /// Allocates the node ports of a service, a `ClusterIP` service has none. The node ports
+    /// not in `previous` are claimed by keys created only if they don't exist, so that two
+    /// services never get the same node port.
+    ///
+    /// # Returns:
+    ///
+    /// The node ports of the service, and the ones claimed by this call, which are the only ones
+    /// to release if the service isn't stored.
+    async fn node_ports(
+        &mut self,
+        service_id: &str,
+        service_type: ServiceType,
+        ports: &[Ports],
+        previous: &[NodePort],
+    ) -> Result<(Vec<NodePort>, Vec<NodePort>), ServiceError> {
+        if service_type != ServiceType::NodePort {
+            return Ok((vec![], vec![]));
+        }
+
+        // the node ports claimed, and the ones of the services stored before the claims
+        let mut used: Vec<i32> = self
+            .etcd_service
+            .scan_prefix(NODE_PORT_PREFIX, None)
+            .try_filter_map(|(key, owner)| async move {
+                Ok(key
+                    .strip_prefix(NODE_PORT_PREFIX)
+                    .and_then(|node_port| node_port.parse::<i32>().ok())
+                    .filter(|_| owner != service_id))
+            })
+            .try_collect()
+            .await
+            .map_err(|err| ServiceError::Etcd(err.to_string()))?;
+        used.extend(
+            self.etcd_service
+                .get_all_with_prefix("service.")
+                .await
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|value| serde_json::from_str::<Service>(value).ok())
+                .filter(|service| service.id != service_id)
+                .flat_map(|service| service.node_ports)
+                .map(|node_port| node_port.node_port),
+        );
+
+        'attempts: for _ in 0..MAX_CLAIM_ATTEMPTS {
+            let node_ports = allocate_node_ports(ports, previous, &used, &self.node_port_range)
+                .ok_or(ServiceError::NoNodePortAvailable)?;
+
+            let mut claimed = vec![];
+            for node_port in node_ports
+                .iter()
+                .filter(|node_port| !holds(previous, node_port))
+            {
+                let key = format!("{}{}", NODE_PORT_PREFIX, node_port.node_port);
+                match self
+                    .etcd_service
+                    .put_if_absent(&key, service_id, None)
+                    .await
+                    .map_err(|err| ServiceError::Etcd(err.to_string()))?
+                {
+                    None => claimed.push(*node_port),
+                    // claimed by a previous attempt, or by a concurrent creation of the service
+                    Some(owner) if owner == service_id => {}
+                    // another controller claimed it meanwhile
+                    Some(_) => {
+                        self.release_node_ports(&claimed).await?;
+                        used.push(node_port.node_port);
+                        continue 'attempts;
+                    }
+                }
+            }
+            return Ok((node_ports, claimed));
+        }
+        Err(ServiceError::NoNodePortAvailable)
+    }
+
+    /// Releases the claims of node ports, they can be allocated again.
+    async fn release_node_ports(&mut self, node_ports: &[NodePort]) -> Result<(), ServiceError> {
+        for node_port in node_ports {
+            self.etcd_service
+                .delete_checked(&format!("{}{}", NODE_PORT_PREFIX, node_port.node_port))
+                .await
+                .map_err(|err| ServiceError::Etcd(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Stores a service, only if it is still at the version `expected` if set. Returns the
+    /// version of the service stored.
+    async fn put_service(
+        &mut self,
+        service: &Service,
+        expected: Option<i64>,
+    ) -> Result<i64, ServiceError> {
+        let json = serde_json::to_string(service)
+            .map_err(|err| ServiceError::ServiceToJson(err.to_string()))?;
+        self.etcd_service
+            .put_if_version(&service.id, &json, expected)
+            .await
+            .map_err(|err| ServiceError::Etcd(err.to_string()))?
+            .ok_or_else(|| {
+                ServiceError::VersionConflict(service.name.clone(), expected.unwrap_or_default())
+            })
+    }
+
+    /// Stores a new service, only if no service has its id, atomically.
+    async fn create_service_key(&mut self, service: &Service) -> Result<(), ServiceError> {
+        let json = serde_json::to_string(service)
+            .map_err(|err| ServiceError::ServiceToJson(err.to_string()))?;
+        match self
+            .etcd_service
+            .put_if_absent(&service.id, &json, None)
+            .await
+            .map_err(|err| ServiceError::Etcd(err.to_string()))?
+        {
+            None => Ok(()),
+            // created by a concurrent request meanwhile
+            Some(_) => Err(ServiceError::NameAlreadyExists(service.name.clone())),
+        }
+    }
+
+    pub fn id(&self, name: &str, namespace: &str) -> String {
+        format!("service.{}.{}", namespace, name)
+    }
+}
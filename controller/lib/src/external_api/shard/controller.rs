@@ -0,0 +1,56 @@
+use crate::external_api::interface::ActixAppState;
+
+use super::model::ShardMap;
+use super::service::ShardService;
+use crate::external_api::generic::problem::Problem;
+use actix_web::{web, Responder, Scope};
+pub struct ShardController {}
+impl ShardController {
+    pub fn services(&self) -> Scope {
+        web::scope("/shard")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .service(
+                web::resource("")
+                    .route(web::get().to(ShardController::get_shard_map))
+                    .route(web::put().to(ShardController::put_shard_map)),
+            )
+    }
+
+    /// `get_shard_map` is an async function that handle **/shard** route (GET)
+    /// # Description:
+    /// * Get the scheduler of each namespace, the others run on the scheduler of the configuration
+    pub async fn get_shard_map(data: web::Data<ActixAppState>) -> impl Responder {
+        let mut shard_service =
+            match ShardService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        shard_service
+            .get_shard_map()
+            .await
+            .map_or_else(|e| e.to_http(), |map| map.to_http())
+    }
+
+    /// `put_shard_map` is an async function that handle **/shard** route (PUT)
+    /// # Description:
+    /// * Replace the scheduler of each namespace, the running instances stay on their scheduler
+    /// # Arguments:
+    ///
+    /// * `body`: web::Json<ShardMap> - The address of the scheduler of each namespace.
+    pub async fn put_shard_map(
+        body: web::Json<ShardMap>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut shard_service =
+            match ShardService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service,
+                Err(e) => return e.to_http(),
+            };
+
+        shard_service
+            .put_shard_map(body.into_inner())
+            .await
+            .map_or_else(|e| e.to_http(), |map| map.to_http())
+    }
+}
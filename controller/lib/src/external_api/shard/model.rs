@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use proto::scheduler::ClusterSnapshot;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+
+/// The key of the shard map in etcd.
+pub const SHARD_MAP_KEY: &str = "shard.map";
+
+pub enum ShardError {
+    Etcd(String),
+    JsonToShardMap(String),
+    ShardMapToJson(String),
+}
+
+impl ShardError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            ShardError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            ShardError::JsonToShardMap(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_shard_map",
+                format!("Error while converting JSON string to shard map: {}", err),
+            ),
+            ShardError::ShardMapToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "shard_map_serialization_failed",
+                format!("Error while converting the shard map to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// `ShardMap` partitions the namespaces across several schedulers. The instances of a namespace
+/// are run by its scheduler, the namespaces missing from the map by the scheduler of the
+/// configuration.
+///
+/// Properties:
+///
+/// * `namespaces`: The address of the gRPC server of the scheduler of each namespace.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShardMap {
+    #[serde(default)]
+    pub namespaces: HashMap<String, SocketAddr>,
+}
+
+impl ShardMap {
+    /// Returns the scheduler of a namespace, `default` if it isn't in the map.
+    pub fn scheduler_for(&self, namespace: &str, default: SocketAddr) -> SocketAddr {
+        self.namespaces.get(namespace).copied().unwrap_or(default)
+    }
+
+    /// Returns every scheduler of the cluster, `default` included, sorted.
+    pub fn schedulers(&self, default: SocketAddr) -> Vec<SocketAddr> {
+        let mut schedulers: Vec<SocketAddr> = self.namespaces.values().copied().collect();
+        schedulers.push(default);
+        schedulers.sort();
+        schedulers.dedup();
+        schedulers
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => ShardError::ShardMapToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Merges the snapshots of the schedulers into the view of the whole cluster, the nodes sorted
+/// by id and the instances by id.
+pub fn merge_snapshots(snapshots: Vec<ClusterSnapshot>) -> ClusterSnapshot {
+    let mut merged = ClusterSnapshot::default();
+    for snapshot in snapshots {
+        merged.nodes.extend(snapshot.nodes);
+        merged.placements.extend(snapshot.placements);
+        merged.pending.extend(snapshot.pending);
+    }
+    merged.nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    merged
+        .placements
+        .sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+    merged
+        .pending
+        .sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{InstancePlacement, NodeSnapshot};
+
+    use super::*;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn test_shard_map() {
+        let map: ShardMap =
+            serde_json::from_str(r#"{"namespaces": {"team-a": "127.0.0.1:50062"}}"#).unwrap();
+
+        assert_eq!(map.scheduler_for("team-a", address(50052)), address(50062));
+        assert_eq!(map.scheduler_for("default", address(50052)), address(50052));
+        assert_eq!(
+            map.schedulers(address(50052)),
+            vec![address(50052), address(50062)]
+        );
+        assert_eq!(
+            ShardMap::default().schedulers(address(50052)),
+            vec![address(50052)]
+        );
+    }
+
+    #[test]
+    fn test_merge_snapshots() {
+        let snapshot = |node: &str, instance: &str| ClusterSnapshot {
+            nodes: vec![NodeSnapshot {
+                id: node.to_string(),
+                ..Default::default()
+            }],
+            placements: vec![InstancePlacement {
+                instance_id: instance.to_string(),
+                node_id: node.to_string(),
+                status: None,
+            }],
+            pending: vec![],
+        };
+
+        let merged = merge_snapshots(vec![snapshot("b", "2"), snapshot("a", "1")]);
+        let nodes: Vec<&str> = merged.nodes.iter().map(|node| node.id.as_str()).collect();
+        assert_eq!(nodes, vec!["a", "b"]);
+        let placements: Vec<&str> = merged
+            .placements
+            .iter()
+            .map(|placement| placement.instance_id.as_str())
+            .collect();
+        assert_eq!(placements, vec!["1", "2"]);
+    }
+}
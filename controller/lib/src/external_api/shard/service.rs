@@ -0,0 +1,108 @@
+use std::net::SocketAddr;
+
+use proto::scheduler::ClusterSnapshot;
+
+use super::model::{merge_snapshots, ShardError, ShardMap, SHARD_MAP_KEY};
+use crate::etcd::EtcdClient;
+use crate::grpc_client::interface::SchedulerClientInterface;
+
+/// `ShardService` reads the shard map from etcd to find the scheduler of each namespace.
+///
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+/// * `scheduler_address`: The scheduler of the namespaces missing from the shard map.
+pub struct ShardService {
+    etcd_service: EtcdClient,
+    scheduler_address: SocketAddr,
+}
+
+impl ShardService {
+    pub async fn new(
+        etcd_address: &SocketAddr,
+        scheduler_address: &SocketAddr,
+    ) -> Result<ShardService, ShardError> {
+        let etcd_service = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| ShardError::Etcd(err.to_string()))?;
+        Ok(ShardService::with_client(etcd_service, scheduler_address))
+    }
+
+    /// Creates the service on an existing etcd client.
+    pub fn with_client(etcd_service: EtcdClient, scheduler_address: &SocketAddr) -> ShardService {
+        ShardService {
+            etcd_service,
+            scheduler_address: *scheduler_address,
+        }
+    }
+
+    /// Returns the shard map, empty if none was stored.
+    pub async fn get_shard_map(&mut self) -> Result<ShardMap, ShardError> {
+        match self.etcd_service.get(SHARD_MAP_KEY).await {
+            Some(map) => serde_json::from_str(&map)
+                .map_err(|err| ShardError::JsonToShardMap(err.to_string())),
+            None => Ok(ShardMap::default()),
+        }
+    }
+
+    /// Replaces the shard map. The instances already running stay on their scheduler, a
+    /// namespace is moved once its instances are re-created.
+    pub async fn put_shard_map(&mut self, map: ShardMap) -> Result<ShardMap, ShardError> {
+        let json = serde_json::to_string(&map)
+            .map_err(|err| ShardError::ShardMapToJson(err.to_string()))?;
+        self.etcd_service
+            .put(SHARD_MAP_KEY, &json)
+            .await
+            .map_err(|err| ShardError::Etcd(err.to_string()))?;
+        Ok(map)
+    }
+
+    /// Returns the address of the scheduler running the instances of a namespace.
+    pub async fn scheduler_for(&mut self, namespace: &str) -> Result<SocketAddr, ShardError> {
+        Ok(self
+            .get_shard_map()
+            .await?
+            .scheduler_for(namespace, self.scheduler_address))
+    }
+
+    /// Returns the address of every scheduler of the cluster.
+    pub async fn schedulers(&mut self) -> Result<Vec<SocketAddr>, String> {
+        Ok(self
+            .get_shard_map()
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .schedulers(self.scheduler_address))
+    }
+
+    /// Returns the snapshot of every scheduler of the cluster. It fails if a scheduler can't be
+    /// reached, a partial view would miss its instances.
+    pub async fn scheduler_snapshots(
+        &mut self,
+    ) -> Result<Vec<(SocketAddr, ClusterSnapshot)>, String> {
+        let schedulers = self.schedulers().await?;
+
+        let mut snapshots = vec![];
+        for scheduler in schedulers {
+            let snapshot = SchedulerClientInterface::new(format!("http://{}", scheduler))
+                .await
+                .map_err(|err| format!("{}: {:?}", scheduler, err))?
+                .cluster_snapshot()
+                .await
+                .map_err(|err| format!("{}: {:?}", scheduler, err))?
+                .into_inner();
+            snapshots.push((scheduler, snapshot));
+        }
+        Ok(snapshots)
+    }
+
+    /// Returns the view of the whole cluster, merged from the snapshots of every scheduler.
+    pub async fn cluster_snapshot(&mut self) -> Result<ClusterSnapshot, String> {
+        let snapshots = self.scheduler_snapshots().await?;
+        Ok(merge_snapshots(
+            snapshots
+                .into_iter()
+                .map(|(_, snapshot)| snapshot)
+                .collect(),
+        ))
+    }
+}
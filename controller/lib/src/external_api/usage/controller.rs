@@ -0,0 +1,68 @@
+use crate::external_api::instance::service::unix_time;
+use crate::external_api::interface::ActixAppState;
+
+use super::model::{instance_key, node_key, UsageQuery};
+use super::service::UsageService;
+use actix_web::{web, Responder, Scope};
+pub struct UsageController {}
+impl UsageController {
+    pub fn services(&self) -> Scope {
+        web::scope("/usage")
+            .service(
+                web::resource("/node/{node}").route(web::get().to(UsageController::get_node_usage)),
+            )
+            .service(
+                web::resource("/instance/{instance}")
+                    .route(web::get().to(UsageController::get_instance_usage)),
+            )
+    }
+
+    /// `get_node_usage` is an async function that handle **/usage/node/\<node>** route (GET)
+    /// # Description:
+    /// * Get the usage history of a node
+    /// # Arguments:
+    ///
+    /// * `node`: web::Path<String> - The id of the node.
+    /// * `query`: web::Query<UsageQuery> - `?hours=N` to only get the last N hours.
+    pub async fn get_node_usage(
+        node: web::Path<String>,
+        query: web::Query<UsageQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        UsageController::get_usage(&node_key(&node), &query, &data).await
+    }
+
+    /// `get_instance_usage` is an async function that handle **/usage/instance/\<instance>** route (GET)
+    /// # Description:
+    /// * Get the usage history of an instance
+    /// # Arguments:
+    ///
+    /// * `instance`: web::Path<String> - The id of the instance.
+    /// * `query`: web::Query<UsageQuery> - `?hours=N` to only get the last N hours.
+    pub async fn get_instance_usage(
+        instance: web::Path<String>,
+        query: web::Query<UsageQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        UsageController::get_usage(&instance_key(&instance), &query, &data).await
+    }
+
+    async fn get_usage(
+        key: &str,
+        query: &UsageQuery,
+        data: &ActixAppState,
+    ) -> actix_web::HttpResponse {
+        let mut usage_service = match UsageService::new(&data.etcd_address).await {
+            Ok(service) => service,
+            Err(e) => return e.to_http(),
+        };
+
+        let since = query
+            .hours
+            .map_or(0, |hours| unix_time().saturating_sub(hours * 3600));
+        usage_service
+            .get_history(key)
+            .await
+            .map_or_else(|e| e.to_http(), |history| history.since(since).to_http())
+    }
+}
@@ -0,0 +1,205 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use proto::scheduler::ResourceSummary;
+use serde::{Deserialize, Serialize};
+
+use crate::external_api::generic::problem::Problem;
+
+/// The prefix of the keys of the usage histories in etcd.
+pub const USAGE_PREFIX: &str = "usage.";
+
+/// Returns the key of the usage history of a node.
+pub fn node_key(node_id: &str) -> String {
+    format!("{}node.{}", USAGE_PREFIX, node_id)
+}
+
+/// Returns the key of the usage history of an instance.
+pub fn instance_key(instance_id: &str) -> String {
+    format!("{}instance.{}", USAGE_PREFIX, instance_id)
+}
+
+pub enum UsageError {
+    UsageNotFound,
+    Etcd(String),
+    JsonToUsage(String),
+    UsageToJson(String),
+}
+
+impl UsageError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            UsageError::UsageNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "usage_not_found",
+                "No usage recorded",
+            ),
+            UsageError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            UsageError::JsonToUsage(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_usage",
+                format!("Error while converting JSON string to usage: {}", err),
+            ),
+            UsageError::UsageToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "usage_serialization_failed",
+                format!("Error while converting the usage to JSON: {}", err),
+            ),
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
+}
+
+/// The average usage over a period of the history.
+///
+/// Properties:
+///
+/// * `timestamp`: The start of the period, in seconds since the unix epoch.
+/// * `cpu`, `memory`, `disk`: The average usage over the period.
+/// * `count`: The number of samples averaged.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsageSample {
+    pub timestamp: u64,
+    pub cpu: u64,
+    pub memory: u64,
+    pub disk: u64,
+    pub count: u64,
+}
+
+impl UsageSample {
+    /// Adds a sample to the average.
+    fn add(&mut self, usage: &ResourceSummary) {
+        let average =
+            |current: u64, sample: u64| (current * self.count + sample) / (self.count + 1);
+        self.cpu = average(self.cpu, usage.cpu);
+        self.memory = average(self.memory, usage.memory);
+        self.disk = average(self.disk, usage.disk);
+        self.count += 1;
+    }
+}
+
+/// `UsageHistory` is the usage of a node or an instance over time, downsampled to one average
+/// per `resolution_seconds`, the oldest first.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct UsageHistory {
+    pub resolution_seconds: u64,
+    pub samples: Vec<UsageSample>,
+}
+
+impl UsageHistory {
+    pub fn new(resolution_seconds: u64) -> Self {
+        UsageHistory {
+            resolution_seconds,
+            samples: vec![],
+        }
+    }
+
+    /// Records a usage sampled at `now`: it is averaged with the samples of the same period, and
+    /// the periods older than `retention_seconds` are dropped.
+    pub fn record(&mut self, usage: &ResourceSummary, now: u64, retention_seconds: u64) {
+        let resolution = self.resolution_seconds.max(1);
+        let period = now - now % resolution;
+
+        match self.samples.last_mut() {
+            Some(last) if last.timestamp == period => last.add(usage),
+            _ => {
+                let mut sample = UsageSample {
+                    timestamp: period,
+                    ..Default::default()
+                };
+                sample.add(usage);
+                self.samples.push(sample);
+            }
+        }
+
+        let oldest = now.saturating_sub(retention_seconds);
+        self.samples
+            .retain(|sample| sample.timestamp + resolution > oldest);
+    }
+
+    /// Returns `true` if no usage was recorded for longer than `retention_seconds`, e.g. the
+    /// node left the cluster or the instance was deleted.
+    pub fn is_expired(&self, now: u64, retention_seconds: u64) -> bool {
+        self.samples.last().is_none_or(|last| {
+            last.timestamp + self.resolution_seconds.max(1) + retention_seconds <= now
+        })
+    }
+
+    /// Keeps the periods ending after `since`, in seconds since the unix epoch.
+    pub fn since(mut self, since: u64) -> Self {
+        let resolution = self.resolution_seconds.max(1);
+        self.samples
+            .retain(|sample| sample.timestamp + resolution > since);
+        self
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => UsageError::UsageToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Query of the usage routes, every parameter is optional.
+///
+/// Properties:
+///
+/// * `hours`: How far back the history goes, the whole history stored if unset.
+#[derive(Deserialize, Default)]
+pub struct UsageQuery {
+    pub hours: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu: u64) -> ResourceSummary {
+        ResourceSummary {
+            cpu,
+            memory: cpu * 2,
+            disk: 0,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut history = UsageHistory::new(60);
+        history.record(&usage(100), 1000, 3600);
+        history.record(&usage(200), 1010, 3600);
+        history.record(&usage(300), 1080, 3600);
+
+        // the two first samples are in the period starting at 960
+        assert_eq!(history.samples.len(), 2);
+        assert_eq!(history.samples[0].timestamp, 960);
+        assert_eq!(history.samples[0].cpu, 150);
+        assert_eq!(history.samples[0].memory, 300);
+        assert_eq!(history.samples[0].count, 2);
+        assert_eq!(history.samples[1].timestamp, 1080);
+
+        // the periods older than the retention are dropped
+        history.record(&usage(400), 1100 + 3600, 3600);
+        let timestamps: Vec<u64> = history.samples.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, vec![1080, 4680]);
+    }
+
+    #[test]
+    fn test_since_and_expiry() {
+        let mut history = UsageHistory::new(60);
+        assert!(history.is_expired(0, 3600));
+        history.record(&usage(100), 1000, 3600);
+        history.record(&usage(100), 2000, 3600);
+
+        assert_eq!(history.clone().since(1500).samples.len(), 1);
+        assert_eq!(history.clone().since(0).samples.len(), 2);
+        assert!(!history.is_expired(2000 + 3600, 3600));
+        assert!(history.is_expired(2000 + 60 + 3600, 3600));
+    }
+}
@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+
+use futures_util::TryStreamExt;
+use proto::scheduler::ResourceSummary;
+
+use super::model::{UsageError, UsageHistory, USAGE_PREFIX};
+use crate::etcd::EtcdClient;
+
+/// `UsageService` stores the usage histories of the nodes and the instances in etcd, recorded by
+/// the usage recorder.
+///
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+pub struct UsageService {
+    etcd_service: EtcdClient,
+}
+
+impl UsageService {
+    pub async fn new(etcd_address: &SocketAddr) -> Result<UsageService, UsageError> {
+        let etcd_service = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| UsageError::Etcd(err.to_string()))?;
+        Ok(UsageService { etcd_service })
+    }
+
+    /// It returns the usage history stored under a key.
+    ///
+    /// # Arguments:
+    ///
+    /// * `key`: The key of the history, see `node_key` and `instance_key`.
+    pub async fn get_history(&mut self, key: &str) -> Result<UsageHistory, UsageError> {
+        match self.etcd_service.get(key).await {
+            Some(history) => serde_json::from_str(&history)
+                .map_err(|err| UsageError::JsonToUsage(err.to_string())),
+            None => Err(UsageError::UsageNotFound),
+        }
+    }
+
+    /// It records a usage in the history stored under a key. The history is started again if
+    /// its resolution changed.
+    ///
+    /// # Arguments:
+    ///
+    /// * `key`: The key of the history.
+    /// * `usage`: The usage sampled.
+    /// * `now`: When the usage was sampled, in seconds since the unix epoch.
+    /// * `resolution_seconds`: The period averaged in each sample of the history.
+    /// * `retention_seconds`: How long the samples are kept.
+    pub async fn record(
+        &mut self,
+        key: &str,
+        usage: &ResourceSummary,
+        now: u64,
+        resolution_seconds: u64,
+        retention_seconds: u64,
+    ) -> Result<(), UsageError> {
+        let mut history = match self.get_history(key).await {
+            Ok(history) if history.resolution_seconds == resolution_seconds => history,
+            Ok(_) | Err(UsageError::UsageNotFound) | Err(UsageError::JsonToUsage(_)) => {
+                UsageHistory::new(resolution_seconds)
+            }
+            Err(err) => return Err(err),
+        };
+        history.record(usage, now, retention_seconds);
+
+        let json = serde_json::to_string(&history)
+            .map_err(|err| UsageError::UsageToJson(err.to_string()))?;
+        self.etcd_service
+            .put(key, &json)
+            .await
+            .map_err(|err| UsageError::Etcd(err.to_string()))?;
+        Ok(())
+    }
+
+    /// It deletes the histories without any usage recorded for longer than the retention.
+    ///
+    /// # Returns:
+    ///
+    /// The number of histories deleted.
+    pub async fn prune(&mut self, now: u64, retention_seconds: u64) -> Result<usize, UsageError> {
+        let expired: Vec<String> = self
+            .etcd_service
+            .scan_prefix(USAGE_PREFIX, None)
+            .try_filter_map(|(key, value)| async move {
+                // a value which isn't a history is deleted too
+                let expired = serde_json::from_str::<UsageHistory>(&value)
+                    .ok()
+                    .is_none_or(|history| history.is_expired(now, retention_seconds));
+                Ok(expired.then_some(key))
+            })
+            .try_collect()
+            .await
+            .map_err(|err| UsageError::Etcd(err.to_string()))?;
+
+        for key in &expired {
+            self.etcd_service.delete(key).await;
+        }
+        Ok(expired.len())
+    }
+}
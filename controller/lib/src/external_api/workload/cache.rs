@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::sync::watch;
+
+use super::model::Workload;
+use crate::etcd::EtcdClient;
+use crate::tasks::BackgroundTasks;
+
+/// The delay before watching etcd again once the watch is interrupted.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A workload of the cache.
+enum Entry {
+    /// The workload is being read from etcd, it is only cached if it wasn't modified meanwhile
+    Loading { readers: usize, stale: bool },
+    /// The workload as stored in etcd
+    Cached(Box<Workload>),
+}
+
+#[derive(Default)]
+struct CacheState {
+    watching: bool,
+    entries: HashMap<String, Entry>,
+}
+
+/// `WorkloadCache` keeps the workloads read from etcd in memory, so that the instance requests
+/// don't read their workload from etcd each time. The workloads are evicted when their key is
+/// modified in etcd, the cache is only used while its watch of etcd runs and is emptied when the
+/// watch is interrupted, so it never serves a workload modified in etcd.
+///
+/// It is shared by the HTTP workers, the clones share the same workloads.
+#[derive(Clone, Default)]
+pub struct WorkloadCache {
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl WorkloadCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawns the watch of etcd evicting the modified workloads in `background_tasks`, it stops
+    /// when the controller shuts down. The cache is unused until the watch runs.
+    pub fn start(&self, etcd_address: SocketAddr, background_tasks: &BackgroundTasks) {
+        let cache = self.clone();
+        background_tasks.spawn(move |mut shutdown: watch::Receiver<bool>| async move {
+            loop {
+                tokio::select! {
+                    result = cache.watch(&etcd_address) => {
+                        cache.set_watching(false);
+                        if let Err(err) = result {
+                            warn!("Workload cache watch interrupted: {}", err);
+                        }
+                    }
+                    Ok(()) = shutdown.changed() => break,
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => {}
+                    Ok(()) = shutdown.changed() => break,
+                }
+            }
+            cache.set_watching(false);
+            info!("Workload cache stopped");
+        });
+    }
+
+    /// Evicts the keys modified in etcd until the watch ends.
+    async fn watch(&self, etcd_address: &SocketAddr) -> Result<(), String> {
+        let mut etcd = EtcdClient::new(etcd_address.to_string())
+            .await
+            .map_err(|err| err.to_string())?;
+        // the workloads are stored without a prefix, every key is watched
+        let (_watcher, mut stream) = etcd.watch_prefix("").await.map_err(|err| err.to_string())?;
+        self.set_watching(true);
+        debug!("Workload cache enabled");
+
+        while let Some(response) = stream.message().await.map_err(|err| err.to_string())? {
+            for event in response.events() {
+                if let Some(key) = event.kv().and_then(|kv| kv.key_str().ok()) {
+                    self.invalidate(key);
+                }
+            }
+        }
+        Err("the watch was closed by etcd".to_string())
+    }
+
+    /// Returns the workload stored under `id` if it is cached.
+    pub fn get(&self, id: &str) -> Option<Workload> {
+        let state = self.lock();
+        match state.entries.get(id) {
+            Some(Entry::Cached(workload)) if state.watching => Some(*workload.clone()),
+            _ => None,
+        }
+    }
+
+    /// Marks the workload stored under `id` as being read from etcd, `finish_load` has to be
+    /// called with what was read. Returns `false` if the cache is unused.
+    pub fn begin_load(&self, id: &str) -> bool {
+        let mut state = self.lock();
+        if !state.watching {
+            return false;
+        }
+        match state.entries.get_mut(id) {
+            Some(Entry::Loading { readers, .. }) => *readers += 1,
+            _ => {
+                state.entries.insert(
+                    id.to_string(),
+                    Entry::Loading {
+                        readers: 1,
+                        stale: false,
+                    },
+                );
+            }
+        }
+        true
+    }
+
+    /// Caches the workload read from etcd under `id`, unless the key was modified since the
+    /// read began.
+    pub fn finish_load(&self, id: &str, workload: Option<&Workload>) {
+        let mut state = self.lock();
+        let watching = state.watching;
+        let Some(Entry::Loading { readers, stale }) = state.entries.get_mut(id) else {
+            return;
+        };
+        *readers -= 1;
+        let (readers, stale) = (*readers, *stale);
+
+        match workload {
+            Some(workload) if watching && !stale => {
+                state
+                    .entries
+                    .insert(id.to_string(), Entry::Cached(Box::new(workload.clone())));
+            }
+            _ if readers == 0 => {
+                state.entries.remove(id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Evicts the workload stored under `id`, the reads in progress are not cached.
+    pub fn invalidate(&self, id: &str) {
+        let mut state = self.lock();
+        match state.entries.get_mut(id) {
+            Some(Entry::Loading { stale, .. }) => *stale = true,
+            Some(Entry::Cached(_)) => {
+                state.entries.remove(id);
+            }
+            None => {}
+        }
+    }
+
+    /// Empties the cache, it is used only while `watching`.
+    fn set_watching(&self, watching: bool) {
+        let mut state = self.lock();
+        state.watching = watching;
+        state.entries.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workload(uri: &str) -> Workload {
+        Workload {
+            id: "default.web".to_string(),
+            name: "web".to_string(),
+            uri: uri.to_string(),
+            namespace: "default".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn cached_uri(cache: &WorkloadCache) -> Option<String> {
+        cache.get("default.web").map(|workload| workload.uri)
+    }
+
+    #[test]
+    fn test_cache_while_watching() {
+        let cache = WorkloadCache::new();
+        assert!(!cache.begin_load("default.web"));
+
+        cache.set_watching(true);
+        assert!(cache.begin_load("default.web"));
+        assert_eq!(cached_uri(&cache), None);
+        cache.finish_load("default.web", Some(&workload("nginx:1.23")));
+        assert_eq!(cached_uri(&cache), Some("nginx:1.23".to_string()));
+
+        cache.invalidate("default.web");
+        assert_eq!(cached_uri(&cache), None);
+
+        cache.begin_load("default.web");
+        cache.finish_load("default.web", Some(&workload("nginx:1.24")));
+        // the workloads modified while the watch is down are not seen
+        cache.set_watching(false);
+        assert_eq!(cached_uri(&cache), None);
+        cache.set_watching(true);
+        assert_eq!(cached_uri(&cache), None);
+    }
+
+    #[test]
+    fn test_modified_during_load() {
+        let cache = WorkloadCache::new();
+        cache.set_watching(true);
+
+        // two reads of the workload, it is modified after the first one read it
+        cache.begin_load("default.web");
+        cache.begin_load("default.web");
+        cache.invalidate("default.web");
+        cache.finish_load("default.web", Some(&workload("nginx:1.23")));
+        assert_eq!(cached_uri(&cache), None);
+        cache.finish_load("default.web", Some(&workload("nginx:1.24")));
+        assert_eq!(cached_uri(&cache), None);
+
+        // the next read is cached
+        cache.begin_load("default.web");
+        cache.finish_load("default.web", Some(&workload("nginx:1.24")));
+        assert_eq!(cached_uri(&cache), Some("nginx:1.24".to_string()));
+
+        // a missing workload is not cached
+        cache.begin_load("default.db");
+        cache.finish_load("default.db", None);
+        assert!(cache.get("default.db").is_none());
+    }
+}
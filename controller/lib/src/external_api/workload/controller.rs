@@ -1,20 +1,51 @@
+use crate::admission::{AdmissionService, Operation, ResourceKind};
 use crate::external_api::interface::ActixAppState;
 
-use super::model::WorkloadDTO;
+use super::model::{RevisionVector, RollbackQuery, WorkloadDTO, WorkloadError};
 use super::service::WorkloadService;
-use crate::external_api::generic::model::Pagination;
+use crate::canary::canary_status;
+use crate::external_api::generic::model::{Consistency, DryRunQuery, Pagination, ReadOptions};
+use crate::external_api::generic::patch::Patch;
+use crate::external_api::generic::problem::Problem;
+use crate::external_api::instance::service::InstanceService;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, Responder, Scope};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder, Scope};
 pub struct WorkloadController {}
 impl WorkloadController {
     pub fn services(&self) -> Scope {
         web::scope("/workload")
+            .app_data(web::JsonConfig::default().error_handler(Problem::json_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(Problem::query_error_handler))
             .service(
                 web::resource("/{namespace}/{workload_id}")
                     .route(web::delete().to(WorkloadController::delete_workload))
                     .route(web::get().to(WorkloadController::workload))
                     .route(web::patch().to(WorkloadController::patch_workload)),
             )
+            .service(
+                web::resource("/{namespace}/{workload_id}/canary")
+                    .route(web::get().to(WorkloadController::canary)),
+            )
+            .service(
+                web::resource("/{namespace}/{workload_id}/canary/promote")
+                    .route(web::post().to(WorkloadController::promote_canary)),
+            )
+            .service(
+                web::resource("/{namespace}/{workload_id}/canary/rollback")
+                    .route(web::post().to(WorkloadController::rollback_canary)),
+            )
+            .service(
+                web::resource("/{namespace}/{workload_id}/diff")
+                    .route(web::post().to(WorkloadController::diff_workload)),
+            )
+            .service(
+                web::resource("/{namespace}/{workload_id}/revisions")
+                    .route(web::get().to(WorkloadController::revisions)),
+            )
+            .service(
+                web::resource("/{namespace}/{workload_id}/rollback")
+                    .route(web::post().to(WorkloadController::rollback_workload)),
+            )
             .service(
                 web::resource("/{namespace}")
                     .route(web::put().to(WorkloadController::put_workload))
@@ -28,24 +59,35 @@ impl WorkloadController {
     ///
     /// * `workload_id`: The workload id to get
     /// * `namespace`: The namespace of the workload
+    /// * `read`: `?consistency=eventual` to read the workload from the cache of the controller,
+    ///   without its `resource_version`
     ///
     /// # Returns:
     ///
     /// A Result<String, WorkloadError>
-
     pub async fn workload(
         params: web::Path<(String, String)>,
+        read: web::Query<ReadOptions>,
         data: web::Data<ActixAppState>,
     ) -> impl Responder {
         let (namespace, workload_id) = params.into_inner();
 
         let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
-            Ok(workload) => workload,
+            Ok(workload) => workload
+                .with_cache(&data.workload_cache)
+                .with_consistency(read.consistency, &data.read_cache),
             Err(e) => return e.to_http(),
         };
 
+        // the version of the workload is only known when it is read from etcd
+        if read.consistency == Consistency::Eventual {
+            return workload_service
+                .get_workload(&workload_id, &namespace)
+                .await
+                .map_or_else(|e| e.to_http(), |w| w.to_http());
+        }
         workload_service
-            .get_workload(&workload_id, &namespace)
+            .get_versioned_workload(&workload_id, &namespace)
             .await
             .map_or_else(|e| e.to_http(), |w| w.to_http())
     }
@@ -57,17 +99,40 @@ impl WorkloadController {
     ///
     /// * `namespace`: web::Path<String> - This is the namespace that the workload will be created in.
     /// * `body`: web::Json<WorkloadDTO> - Contain all information required to create the workload.
-
+    /// * `dry_run`: web::Query<DryRunQuery> - `?dry_run=true` to validate the workload without creating it.
     pub async fn put_workload(
         namespace: web::Path<String>,
         body: web::Json<WorkloadDTO>,
+        dry_run: web::Query<DryRunQuery>,
         data: web::Data<ActixAppState>,
     ) -> impl Responder {
         let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
-            Ok(workload) => workload,
+            Ok(workload) => workload
+                .with_cache(&data.workload_cache)
+                .with_dry_run(dry_run.dry_run),
             Err(e) => return e.to_http(),
         };
         let workload_dto = body.into_inner();
+
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref())
+            .with_dry_run(dry_run.dry_run);
+        if let Err(e) = admission
+            .review(
+                ResourceKind::Workload,
+                Operation::Create,
+                &namespace,
+                &workload_dto.name,
+                serde_json::to_value(&workload_dto).unwrap_or_default(),
+            )
+            .await
+        {
+            return e.to_http();
+        }
+        if let Err(e) = admission.verify_image(&workload_dto.uri).await {
+            return e.to_http();
+        }
+
         workload_service
             .create_workload(workload_dto, &namespace)
             .await
@@ -81,51 +146,191 @@ impl WorkloadController {
     ///
     /// * `namespace`: The namespace of the workloads you want to retrieve.
     /// * `pagination`: Option<web::Query<Pagination>>
-
+    /// * `read`: web::Query<ReadOptions> - `?consistency=eventual` to read the workloads from the cache of the controller.
     pub async fn get_all_workloads(
         namespace: web::Path<String>,
         pagination: Option<web::Query<Pagination>>,
+        read: web::Query<ReadOptions>,
         data: web::Data<ActixAppState>,
     ) -> impl Responder {
         let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
-            Ok(workload) => workload,
+            Ok(workload) => workload
+                .with_cache(&data.workload_cache)
+                .with_consistency(read.consistency, &data.read_cache),
             Err(e) => return e.to_http(),
         };
 
-        match pagination {
-            Some(pagination) => {
-                let workloads = workload_service
-                    .get_all_workloads(pagination.limit, pagination.offset, &namespace)
-                    .await;
-                workloads.to_http()
-            }
-            None => {
-                let workloads = workload_service.get_all_workloads(0, 0, &namespace).await;
-                workloads.to_http()
-            }
-        }
+        let pagination = pagination.map(web::Query::into_inner).unwrap_or_default();
+        workload_service
+            .get_all_workloads(&pagination, &namespace)
+            .await
+            .to_http()
     }
 
     /// `patch_workload` is an asynchronous function that handle **/workload/\<namespace>/<workload_id>** route (PATCH)
     /// # Description:
-    /// * Update a workload
+    /// * Update a workload, with its whole definition or a patch of it
     /// # Arguments:
     ///
     /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
-    /// * `body`: web::Json<WorkloadDTO> - Contain all information required to create the workload.
-
+    /// * `req`: HttpRequest - Its `Content-Type` tells how the body is applied: `application/json` for the whole definition, `application/merge-patch+json` or `application/json-patch+json` for a patch.
+    /// * `body`: web::Bytes - The definition of the workload or the patch applied to it.
+    /// * `dry_run`: web::Query<DryRunQuery> - `?dry_run=true` to validate the update without applying it.
     pub async fn patch_workload(
+        params: web::Path<(String, String)>,
+        req: HttpRequest,
+        body: web::Bytes,
+        dry_run: web::Query<DryRunQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
+            Ok(workload) => workload
+                .with_cache(&data.workload_cache)
+                .with_dry_run(dry_run.dry_run),
+            Err(e) => return e.to_http(),
+        };
+
+        let (namespace, workload_id) = params.into_inner();
+        let workload_dto = match Patch::parse(req.content_type(), &body) {
+            Ok(Some(patch)) => {
+                match workload_service
+                    .patch_workload(&patch, &workload_id, &namespace)
+                    .await
+                {
+                    Ok(workload_dto) => workload_dto,
+                    Err(e) => return e.to_http(),
+                }
+            }
+            Ok(None) => match serde_json::from_slice::<WorkloadDTO>(&body) {
+                Ok(workload_dto) => workload_dto,
+                Err(err) => {
+                    return Problem::new(StatusCode::BAD_REQUEST, "invalid_body", err.to_string())
+                        .to_http()
+                }
+            },
+            Err(e) => return e.to_http(),
+        };
+
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref())
+            .with_dry_run(dry_run.dry_run);
+        if let Err(e) = admission
+            .review(
+                ResourceKind::Workload,
+                Operation::Update,
+                &namespace,
+                &workload_id,
+                serde_json::to_value(&workload_dto).unwrap_or_default(),
+            )
+            .await
+        {
+            return e.to_http();
+        }
+        if let Err(e) = admission.verify_image(&workload_dto.uri).await {
+            return e.to_http();
+        }
+
+        workload_service
+            .update_workload(workload_dto, &workload_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |w| w.to_http())
+    }
+
+    /// `diff_workload` is an async function that handle **/workload/\<namespace>/<workload_id>/diff** route (POST)
+    /// # Description:
+    /// * Get the fields a candidate definition would change in the workload, without updating it
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
+    /// * `body`: web::Json<WorkloadDTO> - The candidate definition of the workload.
+    pub async fn diff_workload(
         params: web::Path<(String, String)>,
         body: web::Json<WorkloadDTO>,
         data: web::Data<ActixAppState>,
     ) -> impl Responder {
+        let (namespace, workload_id) = params.into_inner();
+
         let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
-            Ok(workload) => workload,
+            Ok(workload) => workload.with_cache(&data.workload_cache),
             Err(e) => return e.to_http(),
         };
 
+        workload_service
+            .diff_workload(&body, &workload_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |diff| diff.to_http())
+    }
+
+    /// `revisions` is an async function that handle **/workload/\<namespace>/<workload_id>/revisions** route (GET)
+    /// # Description:
+    /// * Get the definitions the workload was created and updated with, the oldest first
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
+    pub async fn revisions(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
         let (namespace, workload_id) = params.into_inner();
-        let workload_dto = body.into_inner();
+
+        let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
+            Ok(workload) => workload.with_cache(&data.workload_cache),
+            Err(e) => return e.to_http(),
+        };
+
+        workload_service
+            .get_revisions(&workload_id, &namespace)
+            .await
+            .map_or_else(
+                |e| e.to_http(),
+                |revisions| RevisionVector { revisions }.to_http(),
+            )
+    }
+
+    /// `rollback_workload` is an async function that handle **/workload/\<namespace>/<workload_id>/rollback** route (POST)
+    /// # Description:
+    /// * Update a workload with the definition of one of its revisions, stored as a new revision and rolled out to its instances
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
+    /// * `query`: web::Query<RollbackQuery> - `?revision=N` the revision re-deployed.
+    pub async fn rollback_workload(
+        params: web::Path<(String, String)>,
+        query: web::Query<RollbackQuery>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, workload_id) = params.into_inner();
+
+        let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
+            Ok(workload) => workload.with_cache(&data.workload_cache),
+            Err(e) => return e.to_http(),
+        };
+        let workload_dto: WorkloadDTO = match workload_service
+            .get_revision(&workload_id, &namespace, query.revision)
+            .await
+        {
+            Ok(revision) => revision.workload.into(),
+            Err(e) => return e.to_http(),
+        };
+
+        // the previous definition goes through the same checks as an update
+        let admission = AdmissionService::new(&data.admission_webhooks)
+            .with_image_signature(data.image_signature.as_ref());
+        if let Err(e) = admission
+            .review(
+                ResourceKind::Workload,
+                Operation::Update,
+                &namespace,
+                &workload_id,
+                serde_json::to_value(&workload_dto).unwrap_or_default(),
+            )
+            .await
+        {
+            return e.to_http();
+        }
+        if let Err(e) = admission.verify_image(&workload_dto.uri).await {
+            return e.to_http();
+        }
 
         workload_service
             .update_workload(workload_dto, &workload_id, &namespace)
@@ -133,6 +338,94 @@ impl WorkloadController {
             .map_or_else(|e| e.to_http(), |w| w.to_http())
     }
 
+    /// `canary` is an async function that handle **/workload/\<namespace>/<workload_id>/canary** route (GET)
+    /// # Description:
+    /// * Get the instances running the canary of a workload and the ones running its definition
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
+    pub async fn canary(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, workload_id) = params.into_inner();
+
+        let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
+            Ok(workload) => workload.with_cache(&data.workload_cache),
+            Err(e) => return e.to_http(),
+        };
+        let workload = match workload_service
+            .get_workload(&workload_id, &namespace)
+            .await
+        {
+            Ok(workload) => workload,
+            Err(e) => return e.to_http(),
+        };
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service.with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        let instances = instance_service.get_instances_of_workload(&workload).await;
+        match canary_status(&workload, &instances) {
+            Some(status) => status.to_http(),
+            None => WorkloadError::CanaryNotFound.to_http(),
+        }
+    }
+
+    /// `promote_canary` is an async function that handle **/workload/\<namespace>/<workload_id>/canary/promote** route (POST)
+    /// # Description:
+    /// * Replace a workload by its canary, the instances still running the previous definition are re-created
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
+    pub async fn promote_canary(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, workload_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        instance_service
+            .promote_canary(&workload_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |w| w.to_http())
+    }
+
+    /// `rollback_canary` is an async function that handle **/workload/\<namespace>/<workload_id>/canary/rollback** route (POST)
+    /// # Description:
+    /// * Drop the canary of a workload, the instances running it are re-created from the workload
+    /// # Arguments:
+    ///
+    /// * `params`: web::Path<(String, String)> - The first Path parameter is the namespace and the second the workload id.
+    pub async fn rollback_canary(
+        params: web::Path<(String, String)>,
+        data: web::Data<ActixAppState>,
+    ) -> impl Responder {
+        let (namespace, workload_id) = params.into_inner();
+
+        let mut instance_service =
+            match InstanceService::new(&data.etcd_address, &data.scheduler_address).await {
+                Ok(service) => service
+                    .with_background_tasks(&data.background_tasks)
+                    .with_workload_cache(&data.workload_cache),
+                Err(e) => return e.to_http(),
+            };
+
+        instance_service
+            .rollback_canary(&workload_id, &namespace)
+            .await
+            .map_or_else(|e| e.to_http(), |w| w.to_http())
+    }
+
     /// It deletes a workload from etcd
     ///
     /// # Arguments:
@@ -143,18 +436,30 @@ impl WorkloadController {
     /// # Returns:
     ///
     /// A Result<(), WorkloadError>
-
     pub async fn delete_workload(
         params: web::Path<(String, String)>,
         data: web::Data<ActixAppState>,
     ) -> impl Responder {
         let mut workload_service = match WorkloadService::new(&data.etcd_address).await {
-            Ok(workload) => workload,
+            Ok(workload) => workload.with_cache(&data.workload_cache),
             Err(e) => return e.to_http(),
         };
 
         let (namespace, workload_id) = params.into_inner();
 
+        if let Err(e) = AdmissionService::new(&data.admission_webhooks)
+            .review(
+                ResourceKind::Workload,
+                Operation::Delete,
+                &namespace,
+                &workload_id,
+                serde_json::Value::Null,
+            )
+            .await
+        {
+            return e.to_http();
+        }
+
         workload_service
             .delete_workload(&workload_id, &namespace)
             .await;
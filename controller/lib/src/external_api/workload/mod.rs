@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod controller;
 pub mod model;
 pub mod service;
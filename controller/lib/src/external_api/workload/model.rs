@@ -1,38 +1,107 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 use serde::{Deserialize, Serialize};
 
+use crate::external_api::generic::model::version_conflict;
+use crate::external_api::generic::patch::PatchError;
+use crate::external_api::generic::problem::Problem;
+
+#[derive(Debug)]
 pub enum WorkloadError {
     WorkloadNotFound,
     Etcd(String),
     NameAlreadyExists(String),
+    InvalidSpec(String),
+    CanaryInProgress(String),
+    CanaryNotFound,
+    RevisionNotFound(u64),
+    VersionConflict(String, i64),
+    Patch(PatchError),
     JsonToWorkload(String),
     WorkloadToJson(String),
 }
 
 impl WorkloadError {
-    pub fn to_http(&self) -> HttpResponse {
+    pub fn to_problem(&self) -> Problem {
         match self {
-            WorkloadError::WorkloadNotFound => HttpResponse::NotFound().body("Workload not found"),
-            WorkloadError::Etcd(err) => {
-                HttpResponse::InternalServerError().body(format!("Etcd error: {} ", err))
-            }
-            WorkloadError::NameAlreadyExists(name) => {
-                HttpResponse::Conflict().body(format!("Workload with name {} already exists", name))
+            WorkloadError::WorkloadNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "workload_not_found",
+                "Workload not found",
+            ),
+            WorkloadError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            WorkloadError::NameAlreadyExists(name) => Problem::new(
+                StatusCode::CONFLICT,
+                "workload_already_exists",
+                format!("Workload with name {} already exists", name),
+            ),
+            WorkloadError::InvalidSpec(err) => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "invalid_workload",
+                format!("Invalid workload: {}", err),
+            ),
+            WorkloadError::CanaryInProgress(name) => Problem::new(
+                StatusCode::CONFLICT,
+                "canary_in_progress",
+                format!(
+                    "Workload {} has a canary in progress, promote or roll it back first",
+                    name
+                ),
+            ),
+            WorkloadError::CanaryNotFound => Problem::new(
+                StatusCode::NOT_FOUND,
+                "canary_not_found",
+                "The workload has no canary in progress",
+            ),
+            WorkloadError::RevisionNotFound(revision) => Problem::new(
+                StatusCode::NOT_FOUND,
+                "revision_not_found",
+                format!("The workload has no revision {}", revision),
+            ),
+            WorkloadError::VersionConflict(name, version) => {
+                version_conflict("workload", name, *version)
             }
-            WorkloadError::JsonToWorkload(err) => HttpResponse::InternalServerError().body(
+            WorkloadError::Patch(err) => err.to_problem(),
+            WorkloadError::JsonToWorkload(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_workload",
                 format!("Error while converting JSON string to workload : {}", err),
             ),
-            WorkloadError::WorkloadToJson(err) => HttpResponse::InternalServerError().body(
+            WorkloadError::WorkloadToJson(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "workload_serialization_failed",
                 format!("Error while converting the workload to JSON: {}", err),
             ),
         }
     }
+
+    pub fn to_http(&self) -> HttpResponse {
+        self.to_problem().to_http()
+    }
 }
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Type {
+    #[default]
     Container = 0,
+    /// A WebAssembly module, only placed on the nodes running them
+    Wasm = 1,
 }
-#[derive(Deserialize, Serialize, Clone, Debug)]
+
+impl From<Type> for proto::scheduler::Type {
+    fn from(workload_type: Type) -> Self {
+        match workload_type {
+            Type::Container => proto::scheduler::Type::Container,
+            Type::Wasm => proto::scheduler::Type::Wasm,
+        }
+    }
+}
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct Ressources {
     pub cpu: u64,
     pub memory: u64,
@@ -43,7 +112,393 @@ pub struct Ports {
     pub source: i32,
     pub destination: i32,
 }
+/// A container started next to the main container of each instance of a workload. The
+/// containers of an instance run on the same node and share its IP address, its ports and its
+/// volumes.
+///
+/// Properties:
+///
+/// * `name`: The name of the container, unique among the sidecars of the workload.
+/// * `uri`: The image of the container.
+/// * `environment`: The environment variables of the container.
+/// * `resources`: The resources limit of the container, added to the one of the instance.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Container {
+    pub name: String,
+    pub uri: String,
+    #[serde(default)]
+    pub environment: Vec<String>,
+    #[serde(default)]
+    pub resources: Ressources,
+}
+
+impl From<Container> for proto::agent::Container {
+    fn from(container: Container) -> Self {
+        proto::agent::Container {
+            name: container.name,
+            uri: container.uri,
+            environment: container.environment,
+            resource: Some(proto::agent::Resource {
+                limit: Some(proto::agent::ResourceSummary {
+                    cpu: container.resources.cpu,
+                    memory: container.resources.memory,
+                    disk: container.resources.disk,
+                }),
+                usage: None,
+            }),
+        }
+    }
+}
+
+/// Returns an error if a sidecar has no name, a name used by another sidecar, or a name which
+/// can't be part of the name of a container.
+pub fn validate_sidecars(sidecars: &[Container]) -> Result<(), WorkloadError> {
+    let mut names = HashSet::new();
+    for sidecar in sidecars {
+        let valid = !sidecar.name.is_empty()
+            && sidecar
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(WorkloadError::InvalidSpec(format!(
+                "invalid sidecar name {:?}",
+                sidecar.name
+            )));
+        }
+        if !names.insert(sidecar.name.as_str()) {
+            return Err(WorkloadError::InvalidSpec(format!(
+                "duplicate sidecar {}",
+                sidecar.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The sizes of the huge pages the instances may request.
+const HUGE_PAGE_SIZES: [&str; 2] = ["2Mi", "1Gi"];
+
+/// Returns an error if a size of huge pages isn't supported by the nodes.
+pub fn validate_huge_pages(huge_pages: &HashMap<String, u64>) -> Result<(), WorkloadError> {
+    match huge_pages
+        .keys()
+        .find(|size| !HUGE_PAGE_SIZES.contains(&size.as_str()))
+    {
+        Some(size) => Err(WorkloadError::InvalidSpec(format!(
+            "invalid huge page size {}, expected 2Mi or 1Gi",
+            size
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Returns the platform an image is built for from its name, `os/arch` or `os/arch/variant`,
+/// e.g. `linux/arm64` or `linux/arm/v7`, `None` if it isn't one.
+pub fn parse_platform(platform: &str) -> Option<proto::scheduler::Platform> {
+    let parts: Vec<&str> = platform.split('/').collect();
+    if !(2..=3).contains(&parts.len()) || parts.iter().any(|part| part.is_empty()) {
+        return None;
+    }
+    Some(proto::scheduler::Platform {
+        os: parts[0].to_string(),
+        arch: parts[1].to_string(),
+        variant: parts.get(2).unwrap_or(&"").to_string(),
+    })
+}
+
+/// Returns an error if a platform isn't written `os/arch` or `os/arch/variant`.
+pub fn validate_platforms(platforms: &[String]) -> Result<(), WorkloadError> {
+    match platforms
+        .iter()
+        .find(|platform| parse_platform(platform).is_none())
+    {
+        Some(platform) => Err(WorkloadError::InvalidSpec(format!(
+            "invalid platform {}, expected os/arch or os/arch/variant, e.g. linux/arm64",
+            platform
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Seccomp profile of the containers of a workload.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SeccompProfile {
+    /// The default profile of the container runtime
+    #[default]
+    RuntimeDefault,
+    /// No syscall is filtered
+    Unconfined,
+    /// A profile installed on the nodes, relative to their seccomp profiles directory
+    Localhost(String),
+}
+
+/// Security settings of the containers of a workload.
+///
+/// Properties:
+///
+/// * `drop_capabilities`: The capabilities dropped, e.g. `NET_RAW` or `ALL`.
+/// * `seccomp_profile`: The syscalls filter.
+/// * `read_only_root_filesystem`: Mount the root filesystem of the containers read-only.
+/// * `run_as_user`: The uid of the process, the user of the image if unset.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SecurityContext {
+    #[serde(default)]
+    pub drop_capabilities: Vec<String>,
+    #[serde(default)]
+    pub seccomp_profile: SeccompProfile,
+    #[serde(default)]
+    pub read_only_root_filesystem: bool,
+    #[serde(default)]
+    pub run_as_user: Option<u32>,
+}
+
+impl From<SecurityContext> for proto::agent::SecurityContext {
+    fn from(context: SecurityContext) -> Self {
+        proto::agent::SecurityContext {
+            drop_capabilities: context.drop_capabilities,
+            seccomp_profile: match context.seccomp_profile {
+                SeccompProfile::RuntimeDefault => String::new(),
+                SeccompProfile::Unconfined => "unconfined".to_string(),
+                SeccompProfile::Localhost(profile) => format!("localhost/{}", profile),
+            },
+            read_only_root_filesystem: context.read_only_root_filesystem,
+            user: context
+                .run_as_user
+                .map(|uid| uid.to_string())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// How the instances of a workload are expected to run.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WorkloadKind {
+    /// The instances run until they are stopped, an exit is a failure
+    #[default]
+    Service,
+    /// The instances run to completion, new ones are created until enough of them succeed
+    Job,
+    /// An instance runs on every node able to run it, the nodes joining the cluster included
+    DaemonSet,
+    /// The instances have stable names and addresses, they are started and terminated in order
+    StatefulSet,
+}
+
+impl From<WorkloadKind> for proto::agent::WorkloadKind {
+    fn from(kind: WorkloadKind) -> Self {
+        match kind {
+            WorkloadKind::Service | WorkloadKind::DaemonSet | WorkloadKind::StatefulSet => {
+                proto::agent::WorkloadKind::Service
+            }
+            WorkloadKind::Job => proto::agent::WorkloadKind::Job,
+        }
+    }
+}
+
+/// How the instances of a workload are spread over the nodes, to keep a replicated workload
+/// available when a node fails. The instances pinned to a node are placed on it regardless.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Spread {
+    /// The instances share a node only if every node able to run them already runs one
+    Preferred,
+    /// An instance is never placed on a node already running one, it isn't created if there is
+    /// no such node
+    Strict,
+}
+
+impl From<Spread> for proto::scheduler::Spread {
+    fn from(spread: Spread) -> Self {
+        match spread {
+            Spread::Preferred => proto::scheduler::Spread::Preferred,
+            Spread::Strict => proto::scheduler::Spread::Strict,
+        }
+    }
+}
+
+/// How the CPUs of their nodes are shared with the instances of a workload.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuPolicy {
+    /// The instances run on any CPU of their node
+    #[default]
+    None,
+    /// Each instance gets exclusive CPUs of a single NUMA node of its node, one per 1000
+    /// millicpu of its cpu limit and at least one, for the latency-sensitive workloads
+    Static,
+}
+
+impl CpuPolicy {
+    /// Returns the number of CPUs pinned to an instance with a cpu limit of `cpu` millicpu.
+    pub fn exclusive_cpus(&self, cpu: u64) -> u32 {
+        match self {
+            CpuPolicy::None => 0,
+            CpuPolicy::Static => cpu.div_ceil(1000).max(1) as u32,
+        }
+    }
+}
+
+/// How many instances of a workload keep running during the voluntary disruptions: the drains
+/// of the nodes, the evictions and the moves between the versions of a canary. A disruption
+/// which would leave fewer running instances is delayed.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DisruptionBudget {
+    pub min_available: u32,
+}
+
+fn default_completions() -> u32 {
+    1
+}
+
+fn default_backoff_limit() -> u32 {
+    6
+}
+
+/// Run to completion settings of a `Job` workload.
+///
+/// Properties:
+///
+/// * `completions`: The number of instances which must succeed for the job to complete.
+/// * `backoff_limit`: The number of failed instances tolerated before the job fails.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct JobSpec {
+    #[serde(default = "default_completions")]
+    pub completions: u32,
+    #[serde(default = "default_backoff_limit")]
+    pub backoff_limit: u32,
+}
+
+impl Default for JobSpec {
+    fn default() -> Self {
+        JobSpec {
+            completions: default_completions(),
+            backoff_limit: default_backoff_limit(),
+        }
+    }
+}
+
+/// State of a `Job` workload.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JobState {
+    #[default]
+    Active,
+    Complete,
+    Failed,
+}
+
+fn default_replicas() -> u32 {
+    1
+}
+
+/// Settings of a `StatefulSet` workload.
+///
+/// Properties:
+///
+/// * `replicas`: The number of instances, named `<workload>-0` to `<workload>-<replicas - 1>`.
+/// * `volume_path`: Where the volume of each instance is mounted, the instances have no volume
+///   if unset. An instance with a volume always runs on the node holding its volume.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct StatefulSpec {
+    #[serde(default = "default_replicas")]
+    pub replicas: u32,
+    #[serde(default)]
+    pub volume_path: Option<String>,
+}
+
+impl Default for StatefulSpec {
+    fn default() -> Self {
+        StatefulSpec {
+            replicas: default_replicas(),
+            volume_path: None,
+        }
+    }
+}
+
+impl StatefulSpec {
+    /// Returns an error if the volume can't be mounted.
+    pub fn validate(&self) -> Result<(), WorkloadError> {
+        match &self.volume_path {
+            Some(path) if !path.starts_with('/') || path.contains(':') => Err(
+                WorkloadError::InvalidSpec(format!("invalid volume path {}", path)),
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Progress of a `StatefulSet` workload, updated by the stateful set controller.
+///
+/// Properties:
+///
+/// * `nodes`: The node holding the volume of each ordinal, kept when the workload is scaled
+///   down so that the volume is found again when it is scaled up.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatefulStatus {
+    #[serde(default)]
+    pub nodes: BTreeMap<u32, String>,
+}
+
+/// Progress of a `Job` workload, updated by the job controller.
+///
+/// Properties:
+///
+/// * `state`: Whether the job still creates instances.
+/// * `succeeded`: The number of instances which terminated successfully.
+/// * `failed`: The number of instances which failed.
+/// * `counted`: The ids of the finished instances already counted.
+/// * `last_failure_at`: When the last failed instance finished, in seconds since the unix epoch.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JobStatus {
+    pub state: JobState,
+    pub succeeded: u32,
+    pub failed: u32,
+    #[serde(default)]
+    pub counted: Vec<String>,
+    #[serde(default)]
+    pub last_failure_at: Option<u64>,
+}
+
+/// A new version of a `Service` workload run on a fraction of its instances, until it is
+/// promoted or rolled back.
+///
+/// Properties:
+///
+/// * `percentage`: The share of the instances running the new version, rounded up.
+/// * `version`: The new definition of the workload.
 #[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Canary {
+    pub percentage: u8,
+    pub version: Box<Workload>,
+}
+
+/// The instances of one version of a workload, as returned by the canary status.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct VersionStatus {
+    pub uri: String,
+    pub instances: usize,
+    pub running: usize,
+    pub finished: usize,
+}
+
+/// The progress of the canary of a workload, the instances of each version counted apart.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanaryStatus {
+    pub percentage: u8,
+    pub stable: VersionStatus,
+    pub canary: VersionStatus,
+}
+
+impl CanaryStatus {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => WorkloadError::WorkloadToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
 pub struct Workload {
     pub id: String,
     pub name: String,
@@ -53,15 +508,73 @@ pub struct Workload {
     pub resources: Ressources,
     pub ports: Vec<Ports>,
     pub namespace: String,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Seconds after which a finished instance is deleted, finished instances are kept if unset
+    #[serde(default)]
+    pub ttl_seconds_after_finished: Option<u64>,
+    #[serde(default)]
+    pub security_context: SecurityContext,
+    #[serde(default)]
+    pub kind: WorkloadKind,
+    /// Settings of a `Job` workload, the defaults if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_status: Option<JobStatus>,
+    /// Settings of a `StatefulSet` workload, the defaults if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stateful: Option<StatefulSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stateful_status: Option<StatefulStatus>,
+    /// The new version being tried on some of the instances, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary: Option<Canary>,
+    /// Names of the workloads of the namespace which must be ready before the instances are
+    /// created
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Containers started next to the main one in each instance
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<Container>,
+    /// How the instances are spread over the nodes, they may share a node if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<Spread>,
+    /// Placement constraint on the attributes of the nodes, e.g.
+    /// `node.labels.disk == "ssd" && node.arch == "arm64"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    /// Pool of the nodes the instances are placed on, any node if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+    /// Running instances kept during the voluntary disruptions, none if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disruption_budget: Option<DisruptionBudget>,
+    /// Number of devices of each kind mounted into each instance, e.g. `{"gpu": 1}`, advertised
+    /// by the device plugins of the nodes
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub devices: HashMap<String, u32>,
+    /// Whether the instances get exclusive CPUs, they share the CPUs of their node by default
+    #[serde(default)]
+    pub cpu_policy: CpuPolicy,
+    /// Number of huge pages of each size mounted into each instance, `2Mi` or `1Gi`, e.g.
+    /// `{"2Mi": 512}`, set aside on the nodes
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub huge_pages: HashMap<String, u64>,
+    /// Platforms the images are built for, e.g. `linux/amd64` or `linux/arm/v7`, the instances
+    /// are only placed on the nodes of one of them. Any node if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// Number of the revision of the definition, the instances created from another one are
+    /// replaced by a rolling update. 0 for the workloads stored before their revisions
+    #[serde(default)]
+    pub revision: u64,
 }
 impl Workload {
     pub fn to_http(&self) -> HttpResponse {
         match serde_json::to_string(&self) {
             Ok(json) => HttpResponse::Ok().body(json),
-            Err(err) => HttpResponse::InternalServerError().body(format!(
-                "Error while converting the workload to json: {}",
-                err
-            )),
+            Err(err) => WorkloadError::WorkloadToJson(err.to_string()).to_http(),
         }
     }
 }
@@ -71,22 +584,373 @@ pub struct WorkloadDTO {
     pub environment: Vec<String>,
     pub ports: Vec<Ports>,
     pub uri: String,
+    /// Whether the instances run a container image or a WebAssembly module, a container if
+    /// unset
+    #[serde(default)]
+    pub workload_type: Type,
+    /// Resources reserved for the main container of each instance, in millicpus, megabytes of
+    /// memory and gigabytes of disk, nothing is reserved if unset
+    #[serde(default)]
+    pub resources: Ressources,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub ttl_seconds_after_finished: Option<u64>,
+    #[serde(default)]
+    pub security_context: SecurityContext,
+    #[serde(default)]
+    pub kind: WorkloadKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobSpec>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stateful: Option<StatefulSpec>,
+    /// If set, the update is only run on this percentage of the instances of a `Service`
+    /// workload, as a canary
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub canary_percentage: Option<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<Container>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spread: Option<Spread>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub constraint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pool: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disruption_budget: Option<DisruptionBudget>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub devices: HashMap<String, u32>,
+    #[serde(default)]
+    pub cpu_policy: CpuPolicy,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub huge_pages: HashMap<String, u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<String>,
+    /// If set, the update fails if the workload was modified since this version
+    #[serde(default, skip_serializing)]
+    pub resource_version: Option<i64>,
+}
+impl From<Workload> for WorkloadDTO {
+    fn from(workload: Workload) -> Self {
+        WorkloadDTO {
+            name: workload.name,
+            environment: workload.environment,
+            ports: workload.ports,
+            uri: workload.uri,
+            workload_type: workload.workload_type,
+            resources: workload.resources,
+            labels: workload.labels,
+            ttl_seconds_after_finished: workload.ttl_seconds_after_finished,
+            security_context: workload.security_context,
+            kind: workload.kind,
+            job: workload.job,
+            stateful: workload.stateful,
+            canary_percentage: None,
+            depends_on: workload.depends_on,
+            sidecars: workload.sidecars,
+            spread: workload.spread,
+            constraint: workload.constraint,
+            pool: workload.pool,
+            disruption_budget: workload.disruption_budget,
+            devices: workload.devices,
+            cpu_policy: workload.cpu_policy,
+            huge_pages: workload.huge_pages,
+            platforms: workload.platforms,
+            resource_version: None,
+        }
+    }
+}
+
+/// A definition of a workload, stored each time it is created or updated so that it can be
+/// rolled back to.
+///
+/// Properties:
+///
+/// * `revision`: The number of the revision, from 1 for the definition the workload was created
+///   with.
+/// * `created_at`: When the definition was stored, in seconds since the unix epoch.
+/// * `workload`: The definition, without the progress of its controllers nor its canary.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WorkloadRevision {
+    pub revision: u64,
+    pub created_at: u64,
+    pub workload: Workload,
+}
+
+impl WorkloadRevision {
+    pub fn new(revision: u64, created_at: u64, workload: &Workload) -> Self {
+        WorkloadRevision {
+            revision,
+            created_at,
+            workload: Workload {
+                job_status: None,
+                stateful_status: None,
+                canary: None,
+                ..workload.clone()
+            },
+        }
+    }
+}
+
+/// The revisions of a workload, the oldest first.
+#[derive(Deserialize, Serialize)]
+pub struct RevisionVector {
+    pub revisions: Vec<WorkloadRevision>,
+}
+
+impl RevisionVector {
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => WorkloadError::WorkloadToJson(err.to_string()).to_http(),
+        }
+    }
 }
+
+/// Query of the rollback of a workload, `?revision=N` being the revision re-deployed.
+#[derive(Deserialize)]
+pub struct RollbackQuery {
+    pub revision: u64,
+}
+
+/// How a field of a workload changes.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A field of a workload which changes.
+///
+/// Properties:
+///
+/// * `path`: The JSON pointer of the field in the definition, e.g. `/ports/0/destination`.
+/// * `change`: Whether the field is added, removed or modified.
+/// * `old`: The stored value of the field, unset if it is added.
+/// * `new`: The value of the field in the candidate, unset if it is removed.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub path: String,
+    pub change: ChangeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<serde_json::Value>,
+}
+
+/// The fields a candidate definition changes in a stored workload, in the order of their path.
+/// The fields of the objects are compared one by one, as are the items of the lists.
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct WorkloadDiff {
+    pub changes: Vec<FieldChange>,
+}
+
+impl WorkloadDiff {
+    pub fn new(stored: &WorkloadDTO, candidate: &WorkloadDTO) -> Result<Self, WorkloadError> {
+        let stored = serde_json::to_value(stored)
+            .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
+        let candidate = serde_json::to_value(candidate)
+            .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
+
+        let mut diff = WorkloadDiff::default();
+        diff.compare(String::new(), Some(&stored), Some(&candidate));
+        Ok(diff)
+    }
+
+    fn compare(
+        &mut self,
+        path: String,
+        old: Option<&serde_json::Value>,
+        new: Option<&serde_json::Value>,
+    ) {
+        use serde_json::Value;
+
+        match (old, new) {
+            (Some(Value::Object(old)), Some(Value::Object(new))) => {
+                let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+                for key in keys {
+                    let path = format!("{}/{}", path, escape_pointer(key));
+                    self.compare(path, old.get(key), new.get(key));
+                }
+            }
+            (Some(Value::Array(old)), Some(Value::Array(new))) => {
+                for index in 0..old.len().max(new.len()) {
+                    let path = format!("{}/{}", path, index);
+                    self.compare(path, old.get(index), new.get(index));
+                }
+            }
+            (Some(old), Some(new)) if old == new => {}
+            (old, new) => {
+                let change = match (old, new) {
+                    (None, _) => ChangeKind::Added,
+                    (_, None) => ChangeKind::Removed,
+                    _ => ChangeKind::Modified,
+                };
+                self.changes.push(FieldChange {
+                    path,
+                    change,
+                    old: old.cloned(),
+                    new: new.cloned(),
+                });
+            }
+        }
+    }
+
+    pub fn to_http(&self) -> HttpResponse {
+        match serde_json::to_string(&self) {
+            Ok(json) => HttpResponse::Ok().body(json),
+            Err(err) => WorkloadError::WorkloadToJson(err.to_string()).to_http(),
+        }
+    }
+}
+
+/// Escapes a key of an object as a segment of a JSON pointer (RFC 6901).
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct WorkloadVector {
     pub workloads: Vec<Workload>,
+    /// Token to resume the listing, set if it stopped at its limit
+    #[serde(default, rename = "continue", skip_serializing_if = "Option::is_none")]
+    pub continue_token: Option<String>,
 }
 impl WorkloadVector {
     pub fn new(workloads: Vec<Workload>) -> WorkloadVector {
-        WorkloadVector { workloads }
+        WorkloadVector {
+            workloads,
+            continue_token: None,
+        }
+    }
+
+    pub fn with_continue_token(mut self, continue_token: Option<String>) -> Self {
+        self.continue_token = continue_token;
+        self
     }
     pub fn to_http(&self) -> HttpResponse {
         match serde_json::to_string(&self) {
             Ok(json) => HttpResponse::Ok().body(json),
-            Err(err) => HttpResponse::InternalServerError().body(format!(
-                "Error while converting the workload to json: {}",
-                err
-            )),
+            Err(err) => WorkloadError::WorkloadToJson(err.to_string()).to_http(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::Instance;
+    use serde_json::json;
+
+    fn dto(value: serde_json::Value) -> WorkloadDTO {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_workload_diff() {
+        let stored = dto(json!({
+            "name": "web",
+            "environment": ["A=1", "B=2"],
+            "ports": [{"source": 80, "destination": 8080}],
+            "uri": "nginx:1.22",
+            "labels": {"app/tier": "front"},
+        }));
+        let candidate = dto(json!({
+            "name": "web",
+            "environment": ["A=1"],
+            "ports": [{"source": 80, "destination": 9090}],
+            "uri": "nginx:1.23",
+            "resources": {"cpu": 500, "memory": 256, "disk": 0},
+            "labels": {"app/tier": "front", "team": "core"},
+        }));
+
+        let diff = WorkloadDiff::new(&stored, &candidate).unwrap();
+        let paths: Vec<(&str, ChangeKind)> = diff
+            .changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.change))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("/environment/1", ChangeKind::Removed),
+                ("/labels/team", ChangeKind::Added),
+                ("/ports/0/destination", ChangeKind::Modified),
+                ("/resources/cpu", ChangeKind::Modified),
+                ("/resources/memory", ChangeKind::Modified),
+                ("/uri", ChangeKind::Modified),
+            ]
+        );
+        assert_eq!(diff.changes[3].new, Some(json!(500)));
+        assert_eq!(diff.changes[5].old, Some(json!("nginx:1.22")));
+        assert_eq!(diff.changes[5].new, Some(json!("nginx:1.23")));
+
+        assert!(WorkloadDiff::new(&stored, &stored)
+            .unwrap()
+            .changes
+            .is_empty());
+    }
+
+    #[test]
+    fn test_exclusive_cpus() {
+        assert_eq!(CpuPolicy::None.exclusive_cpus(2500), 0);
+        assert_eq!(CpuPolicy::Static.exclusive_cpus(0), 1);
+        assert_eq!(CpuPolicy::Static.exclusive_cpus(1000), 1);
+        assert_eq!(CpuPolicy::Static.exclusive_cpus(2500), 3);
+
+        // the cpu of the definition reaches the instances sent to the scheduler
+        let workload = Workload {
+            id: "default.db".to_string(),
+            name: "db".to_string(),
+            namespace: "default".to_string(),
+            resources: Ressources {
+                cpu: 2500,
+                memory: 512,
+                disk: 0,
+            },
+            cpu_policy: CpuPolicy::Static,
+            ..Default::default()
+        };
+        let instance = Instance::from_workload("a".to_string(), workload);
+        assert_eq!(proto::scheduler::Instance::from(instance).exclusive_cpus, 3);
+    }
+
+    #[test]
+    fn test_instance_type() {
+        let workload = Workload {
+            id: "default.filter".to_string(),
+            name: "filter".to_string(),
+            namespace: "default".to_string(),
+            workload_type: Type::Wasm,
+            ..Default::default()
+        };
+        let instance = Instance::from_workload("a".to_string(), workload);
+        assert_eq!(
+            proto::scheduler::Instance::from(instance).r#type(),
+            proto::scheduler::Type::Wasm
+        );
+    }
+
+    #[test]
+    fn test_parse_platform() {
+        assert_eq!(
+            parse_platform("linux/arm/v7"),
+            Some(proto::scheduler::Platform {
+                os: "linux".to_string(),
+                arch: "arm".to_string(),
+                variant: "v7".to_string(),
+            })
+        );
+        assert_eq!(parse_platform("linux/amd64").unwrap().variant, "");
+        assert!(parse_platform("amd64").is_none());
+        assert!(parse_platform("linux//v7").is_none());
+        assert!(parse_platform("linux/arm/v7/extra").is_none());
+
+        assert!(validate_platforms(&["linux/amd64".to_string()]).is_ok());
+        assert!(validate_platforms(&["linux/amd64".to_string(), "arm64".to_string()]).is_err());
+    }
+}
@@ -1,18 +1,41 @@
+use std::collections::HashSet;
 use std::net::SocketAddr;
 
-use super::model::{Ressources, Type, Workload, WorkloadDTO, WorkloadError, WorkloadVector};
-use crate::etcd::EtcdClient;
-use crate::external_api::generic::filter::FilterService;
+use super::cache::WorkloadCache;
+use super::model::{
+    validate_huge_pages, validate_platforms, validate_sidecars, Canary, Workload, WorkloadDTO,
+    WorkloadDiff, WorkloadError, WorkloadKind, WorkloadRevision, WorkloadVector,
+};
+use crate::etcd::{EtcdClient, Rename};
+use crate::external_api::generic::model::{Consistency, Pagination, Versioned};
+use crate::external_api::generic::patch::{Patch, PatchError};
+use crate::external_api::generic::read_cache::ReadCache;
+use crate::external_api::instance::service::unix_time;
 use serde_json;
 
+/// The kinds of workloads indexed, run by a controller loop.
+const INDEXED_KINDS: [WorkloadKind; 3] = [
+    WorkloadKind::Job,
+    WorkloadKind::DaemonSet,
+    WorkloadKind::StatefulSet,
+];
+
+/// The number of revision numbers tried before giving up, when concurrent updates claim them.
+const MAX_REVISION_ATTEMPTS: usize = 8;
+
 /// `WorkloadService` is a struct that inpired from Controllers Provider Modules architectures. It can be used as a service in the WorkloadController .A service can use other services.
 /// Properties:
 ///
 /// * `etcd_service`: This is the service that will be used to interact with etcd.
-/// * `filter_service`: This is the service that will be used to filter the workloads.
+/// * `cache`: The workloads read from etcd, shared with the other services if set.
+/// * `read_cache`: The mirror of etcd the workloads are read from if set, for the eventually
+///   consistent reads.
+/// * `dry_run`: If true, the workloads created and updated are validated but not stored.
 pub struct WorkloadService {
     etcd_service: EtcdClient,
-    filter_service: FilterService,
+    cache: Option<WorkloadCache>,
+    read_cache: Option<ReadCache>,
+    dry_run: bool,
 }
 
 impl WorkloadService {
@@ -21,36 +44,93 @@ impl WorkloadService {
             etcd_service: EtcdClient::new(etcd_address.to_string())
                 .await
                 .map_err(|err| WorkloadError::Etcd(err.to_string()))?,
-            filter_service: FilterService::new(),
+            cache: None,
+            read_cache: None,
+            dry_run: false,
         };
         Ok(inner)
     }
+
+    /// Reads the workloads from `read_cache` if `consistency` is eventual, instead of etcd.
+    pub fn with_consistency(mut self, consistency: Consistency, read_cache: &ReadCache) -> Self {
+        self.read_cache = (consistency == Consistency::Eventual).then(|| read_cache.clone());
+        self
+    }
+
+    /// Reads the workloads through `cache`, the workloads written by the service are evicted
+    /// from it.
+    pub fn with_cache(mut self, cache: &WorkloadCache) -> Self {
+        self.cache = Some(cache.clone());
+        self
+    }
+
+    /// Validates the workloads created and updated without storing them if `dry_run` is true.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
     pub async fn get_workload(
         &mut self,
         workload_name: &str,
         namespace: &str,
     ) -> Result<Workload, WorkloadError> {
         let id = self.id(workload_name, namespace);
-        match self.etcd_service.get(&id).await {
-            Some(workload) => {
-                let workload: Workload = serde_json::from_str(&workload)
-                    .map_err(|err| WorkloadError::JsonToWorkload(err.to_string()))?;
-                if workload.namespace == namespace {
-                    Ok(workload)
-                } else {
-                    Err(WorkloadError::WorkloadNotFound)
-                }
-            }
+        let workload = match self.read_cache.as_ref().and_then(|cache| cache.get(&id)) {
+            Some(Some(workload)) => serde_json::from_str::<Workload>(&workload)
+                .map_err(|err| WorkloadError::JsonToWorkload(err.to_string()))?,
+            Some(None) => return Err(WorkloadError::WorkloadNotFound),
+            None => match self.cache.as_ref().and_then(|cache| cache.get(&id)) {
+                Some(workload) => workload,
+                None => self.read_workload(&id).await?,
+            },
+        };
+        if workload.namespace == namespace {
+            Ok(workload)
+        } else {
+            Err(WorkloadError::WorkloadNotFound)
+        }
+    }
+
+    /// Reads a workload and its version from etcd, bypassing the caches.
+    pub async fn get_versioned_workload(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Versioned<Workload>, WorkloadError> {
+        let id = self.id(workload_name, namespace);
+        let (workload, version) = self
+            .etcd_service
+            .get_versioned(&id)
+            .await
+            .ok_or(WorkloadError::WorkloadNotFound)?;
+        let workload = serde_json::from_str::<Workload>(&workload)
+            .map_err(|err| WorkloadError::JsonToWorkload(err.to_string()))?;
+        if workload.namespace != namespace {
+            return Err(WorkloadError::WorkloadNotFound);
+        }
+        Ok(Versioned::new(workload, version))
+    }
+
+    /// Reads a workload from etcd, it is kept in the cache if any.
+    async fn read_workload(&mut self, id: &str) -> Result<Workload, WorkloadError> {
+        let cache = self.cache.clone().filter(|cache| cache.begin_load(id));
+        let result = match self.etcd_service.get(id).await {
+            Some(workload) => serde_json::from_str::<Workload>(&workload)
+                .map_err(|err| WorkloadError::JsonToWorkload(err.to_string())),
             None => Err(WorkloadError::WorkloadNotFound),
+        };
+        if let Some(cache) = cache {
+            cache.finish_load(id, result.as_ref().ok());
         }
+        result
     }
 
-    /// This function gets all the workloads from etcd, filters them by namespace and slice the result by limit and offset
+    /// This function gets the workloads of a namespace from etcd, paginated by `pagination`.
     /// If there is an error , the function always return an empty vector
     /// # Arguments:
     ///
-    /// * `limit`: The number of workloads to return.
-    /// * `offset`: The offset of the workloads to be returned.
+    /// * `pagination`: The limit, the offset and the continue token of the listing.
     /// * `namespace`: The namespace to filter by.
     ///
     /// # Returns:
@@ -58,33 +138,39 @@ impl WorkloadService {
     /// A vector of workloads
     pub async fn get_all_workloads(
         &mut self,
-        limit: u32,
-        offset: u32,
+        pagination: &Pagination,
         namespace: &str,
     ) -> WorkloadVector {
-        let mut new_vec: Vec<Workload> = Vec::new();
-        match self.etcd_service.get_all().await {
-            Some(workloads) => {
-                for workload in workloads {
-                    // if workload deserialize failed , we don't want to throw error , so we just don't add it to the vector
-                    if let Ok(workload) = serde_json::from_str::<Workload>(&workload) {
-                        if workload.namespace == namespace {
-                            new_vec.push(workload);
-                        }
-                    }
-                }
-                if offset > 0 {
-                    match self.filter_service.offset(&new_vec, offset) {
-                        Ok(workloads) => new_vec = workloads,
-                        Err(_) => return WorkloadVector::new(vec![]),
-                    }
-                }
-                if limit > 0 {
-                    new_vec = self.filter_service.limit(&new_vec, limit);
-                }
-                WorkloadVector::new(new_vec)
+        // the workloads are stored under `<namespace>.<name>`, the namespace is checked again
+        // as other resources may share the prefix
+        let prefix = self.id("", namespace);
+        let cached = self.read_cache.as_ref().and_then(|cache| {
+            cache.list_prefix(
+                &prefix,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |workload: &Workload| workload.namespace == namespace,
+            )
+        });
+        if let Some(listing) = cached {
+            return WorkloadVector::new(listing.items).with_continue_token(listing.continue_token);
+        }
+        match self
+            .etcd_service
+            .list_prefix(
+                &prefix,
+                pagination.continue_token.as_deref(),
+                pagination.offset,
+                pagination.limit,
+                |workload: &Workload| workload.namespace == namespace,
+            )
+            .await
+        {
+            Ok(listing) => {
+                WorkloadVector::new(listing.items).with_continue_token(listing.continue_token)
             }
-            None => WorkloadVector::new(vec![]),
+            Err(_) => WorkloadVector::new(vec![]),
         }
     }
 
@@ -108,26 +194,55 @@ impl WorkloadService {
             Ok(workload) => Err(WorkloadError::NameAlreadyExists(workload.name)),
             Err(err) => match err {
                 WorkloadError::WorkloadNotFound => {
-                    let workload = Workload {
+                    if workload_dto.canary_percentage.is_some() {
+                        return Err(WorkloadError::InvalidSpec(
+                            "a canary can only update an existing workload".to_string(),
+                        ));
+                    }
+                    let mut workload = Workload {
                         id: new_id.to_string(),
                         name: workload_dto.name,
-                        workload_type: Type::Container,
+                        workload_type: workload_dto.workload_type,
                         uri: workload_dto.uri,
                         environment: workload_dto.environment,
-                        resources: Ressources {
-                            cpu: 0,
-                            memory: 0,
-                            disk: 0,
-                        },
+                        resources: workload_dto.resources,
                         ports: workload_dto.ports,
                         namespace: namespace.to_string(),
+                        labels: workload_dto.labels,
+                        ttl_seconds_after_finished: workload_dto.ttl_seconds_after_finished,
+                        security_context: workload_dto.security_context,
+                        kind: workload_dto.kind,
+                        job: workload_dto.job,
+                        job_status: None,
+                        stateful: workload_dto.stateful,
+                        stateful_status: None,
+                        canary: None,
+                        depends_on: workload_dto.depends_on,
+                        sidecars: workload_dto.sidecars,
+                        spread: workload_dto.spread,
+                        constraint: workload_dto.constraint,
+                        pool: workload_dto.pool,
+                        disruption_budget: workload_dto.disruption_budget,
+                        devices: workload_dto.devices,
+                        cpu_policy: workload_dto.cpu_policy,
+                        huge_pages: workload_dto.huge_pages,
+                        platforms: workload_dto.platforms,
+                        revision: 0,
                     };
-                    let json = serde_json::to_string(&workload)
-                        .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
-                    self.etcd_service
-                        .put(&new_id, &json)
-                        .await
-                        .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
+                    if let Some(stateful) = &workload.stateful {
+                        stateful.validate()?;
+                    }
+                    validate_sidecars(&workload.sidecars)?;
+                    validate_huge_pages(&workload.huge_pages)?;
+                    validate_platforms(&workload.platforms)?;
+                    self.check_dependencies(&workload).await?;
+                    if !self.dry_run {
+                        self.record_revision(&mut workload).await?;
+                        if let Err(err) = self.put_workload(&workload).await {
+                            self.discard_revision(&workload).await;
+                            return Err(err);
+                        }
+                    }
                     Ok(workload)
                 }
                 _ => Err(err),
@@ -135,7 +250,9 @@ impl WorkloadService {
         }
     }
 
-    /// It updates a workload in the etcd
+    /// It updates a workload in the etcd. If the update has a canary percentage, the new
+    /// definition is kept as the canary of the workload, its instances are moved to it by the
+    /// canary controller.
     ///
     /// # Arguments:
     ///
@@ -151,39 +268,599 @@ impl WorkloadService {
         workload_dto: WorkloadDTO,
         workload_name: &str,
         namespace: &str,
-    ) -> Result<Workload, WorkloadError> {
+    ) -> Result<Versioned<Workload>, WorkloadError> {
         // we get the id before update , and the new id after update
         let new_id = self.id(&workload_dto.name, namespace);
-        self.get_workload(workload_name, namespace).await?;
-        let workload = Workload {
+        let Versioned {
+            resource: previous,
+            resource_version,
+        } = self
+            .get_versioned_workload(workload_name, namespace)
+            .await?;
+        if let Some(expected) = workload_dto
+            .resource_version
+            .filter(|expected| *expected != resource_version)
+        {
+            return Err(WorkloadError::VersionConflict(previous.name, expected));
+        }
+        let expected = workload_dto.resource_version;
+        if previous.canary.is_some() {
+            return Err(WorkloadError::CanaryInProgress(previous.name));
+        }
+        let mut workload = Workload {
             id: new_id.to_string(),
             name: workload_dto.name,
-            workload_type: Type::Container,
+            workload_type: workload_dto.workload_type,
             uri: workload_dto.uri,
             environment: workload_dto.environment.to_vec(),
-            resources: Ressources {
-                cpu: 0,
-                memory: 0,
-                disk: 0,
-            },
+            resources: workload_dto.resources,
             ports: workload_dto.ports.to_vec(),
             namespace: namespace.to_string(),
+            labels: workload_dto.labels,
+            ttl_seconds_after_finished: workload_dto.ttl_seconds_after_finished,
+            security_context: workload_dto.security_context,
+            kind: workload_dto.kind,
+            job: workload_dto.job,
+            // the progress of a job is kept, it isn't run again when its definition changes
+            job_status: previous
+                .job_status
+                .clone()
+                .filter(|_| workload_dto.kind == WorkloadKind::Job),
+            stateful: workload_dto.stateful,
+            // the nodes holding the volumes are kept, the instances keep their volume
+            stateful_status: previous
+                .stateful_status
+                .clone()
+                .filter(|_| workload_dto.kind == WorkloadKind::StatefulSet),
+            canary: None,
+            depends_on: workload_dto.depends_on,
+            sidecars: workload_dto.sidecars,
+            spread: workload_dto.spread,
+            constraint: workload_dto.constraint,
+            pool: workload_dto.pool,
+            disruption_budget: workload_dto.disruption_budget,
+            devices: workload_dto.devices,
+            cpu_policy: workload_dto.cpu_policy,
+            huge_pages: workload_dto.huge_pages,
+            platforms: workload_dto.platforms,
+            revision: 0,
         };
-        let json = serde_json::to_string(&workload)
+        if let Some(stateful) = &workload.stateful {
+            stateful.validate()?;
+        }
+        validate_sidecars(&workload.sidecars)?;
+        validate_huge_pages(&workload.huge_pages)?;
+        validate_platforms(&workload.platforms)?;
+        self.check_dependencies(&workload).await?;
+
+        if let Some(percentage) = workload_dto.canary_percentage {
+            if percentage == 0 || percentage > 100 {
+                return Err(WorkloadError::InvalidSpec(format!(
+                    "invalid canary percentage {}",
+                    percentage
+                )));
+            }
+            if previous.kind != WorkloadKind::Service
+                || workload.kind != WorkloadKind::Service
+                || workload.id != previous.id
+            {
+                return Err(WorkloadError::InvalidSpec(
+                    "a canary can only update a Service workload, without renaming it".to_string(),
+                ));
+            }
+            let mut stable = previous;
+            stable.canary = Some(Canary {
+                percentage,
+                version: Box::new(workload),
+            });
+            if self.dry_run {
+                return Ok(Versioned::new(stable, resource_version));
+            }
+            let version = self.put_workload_if(&stable, expected).await?;
+            return Ok(Versioned::new(stable, version));
+        }
+
+        if self.dry_run {
+            return Ok(Versioned::new(workload, resource_version));
+        }
+        // fails early without claiming a revision of the workload having the new name, the
+        // rename still checks it
+        if workload.id != previous.id && self.get_workload(&workload.name, namespace).await.is_ok()
+        {
+            return Err(WorkloadError::NameAlreadyExists(workload.name));
+        }
+        self.record_revision(&mut workload).await?;
+        let stored = if workload.id == previous.id {
+            self.put_workload_if(&workload, expected).await
+        } else {
+            self.rename_workload(&previous, &workload, expected).await
+        };
+        let version = match stored {
+            Ok(version) => version,
+            Err(err) => {
+                self.discard_revision(&workload).await;
+                return Err(err);
+            }
+        };
+        self.start_rollout(&workload).await?;
+        Ok(Versioned::new(workload, version))
+    }
+
+    /// Stores a renamed workload under its new key and deletes its previous key in a single
+    /// transaction, only if no workload has the new name. If `expected` is set, only if the
+    /// previous key is still at this version. The indexes and the revisions of the previous
+    /// name are deleted, the revisions restart with the renamed workload.
+    ///
+    /// Returns the version of the workload stored.
+    async fn rename_workload(
+        &mut self,
+        previous: &Workload,
+        workload: &Workload,
+        expected: Option<i64>,
+    ) -> Result<i64, WorkloadError> {
+        let json = serde_json::to_string(workload)
+            .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
+        let result = self
+            .etcd_service
+            .rename(&previous.id, &workload.id, &json, expected)
+            .await;
+        // evicted even if the write failed, it may have been applied
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&previous.id);
+            cache.invalidate(&workload.id);
+        }
+        let version = match result.map_err(|err| WorkloadError::Etcd(err.to_string()))? {
+            Rename::Renamed(version) => version,
+            Rename::Taken => return Err(WorkloadError::NameAlreadyExists(workload.name.clone())),
+            Rename::Changed => match expected {
+                Some(version) => {
+                    return Err(WorkloadError::VersionConflict(
+                        previous.name.clone(),
+                        version,
+                    ))
+                }
+                None => return Err(WorkloadError::WorkloadNotFound),
+            },
+        };
+
+        self.put_indexes(workload).await?;
+        for kind in INDEXED_KINDS {
+            _ = self
+                .etcd_service
+                .delete(&self.kind_index_id(kind, &previous.id))
+                .await;
+        }
+        _ = self
+            .etcd_service
+            .delete(&self.canary_index_id(&previous.id))
+            .await;
+        _ = self
+            .etcd_service
+            .delete(&self.rollout_index_id(&previous.id))
+            .await;
+        for stored in self.read_revisions(&previous.id).await.unwrap_or_default() {
+            _ = self
+                .etcd_service
+                .delete(&self.revision_id(&previous.id, stored.revision))
+                .await;
+        }
+        Ok(version)
+    }
+
+    /// Returns the definition of a workload with `patch` applied, to update the workload with.
+    /// The definition expects the version of the workload the patch was applied to, unless the
+    /// patch sets it, so that the update fails if the workload was modified meanwhile.
+    pub async fn patch_workload(
+        &mut self,
+        patch: &Patch,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<WorkloadDTO, WorkloadError> {
+        let Versioned {
+            resource,
+            resource_version,
+        } = self
+            .get_versioned_workload(workload_name, namespace)
+            .await?;
+        let mut document = serde_json::to_value(WorkloadDTO::from(resource))
             .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
+        patch.apply(&mut document).map_err(WorkloadError::Patch)?;
+        let mut workload_dto: WorkloadDTO = serde_json::from_value(document).map_err(|err| {
+            WorkloadError::Patch(PatchError::PatchFailed(format!(
+                "the patched workload is invalid: {}",
+                err
+            )))
+        })?;
+        workload_dto
+            .resource_version
+            .get_or_insert(resource_version);
+        Ok(workload_dto)
+    }
+
+    /// Returns the fields `workload_dto` would change in a workload if it was updated with it.
+    /// Nothing is validated, a dry run of the update does.
+    pub async fn diff_workload(
+        &mut self,
+        workload_dto: &WorkloadDTO,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<WorkloadDiff, WorkloadError> {
+        let stored = WorkloadDTO::from(self.get_workload(workload_name, namespace).await?);
+        WorkloadDiff::new(&stored, workload_dto)
+    }
+
+    /// Returns the revisions of a workload, the oldest first. The workloads created before their
+    /// revisions were stored have none until they are updated.
+    pub async fn get_revisions(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Vec<WorkloadRevision>, WorkloadError> {
+        let workload = self.get_workload(workload_name, namespace).await?;
+        self.read_revisions(&workload.id).await
+    }
+
+    /// Returns a revision of a workload.
+    pub async fn get_revision(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+        revision: u64,
+    ) -> Result<WorkloadRevision, WorkloadError> {
+        self.get_revisions(workload_name, namespace)
+            .await?
+            .into_iter()
+            .find(|stored| stored.revision == revision)
+            .ok_or(WorkloadError::RevisionNotFound(revision))
+    }
+
+    /// Reads the revisions of a workload from etcd, the oldest first. The prefix of its
+    /// revisions is shared with the workloads whose name extends its own, their revisions are
+    /// skipped.
+    async fn read_revisions(
+        &mut self,
+        workload_id: &str,
+    ) -> Result<Vec<WorkloadRevision>, WorkloadError> {
+        let values = self
+            .etcd_service
+            .get_all_with_prefix(&format!("revision.{}.", workload_id))
+            .await
+            .ok_or_else(|| WorkloadError::Etcd("can't read the revisions".to_string()))?;
+        let mut revisions: Vec<WorkloadRevision> = values
+            .iter()
+            .filter_map(|value| serde_json::from_str::<WorkloadRevision>(value).ok())
+            .filter(|stored| stored.workload.id == workload_id)
+            .collect();
+        revisions.sort_by_key(|stored| stored.revision);
+        Ok(revisions)
+    }
+
+    /// Deletes the revisions of the workloads of a namespace, the ones left by the workloads
+    /// already deleted included.
+    ///
+    /// Returns the number of revisions deleted.
+    pub async fn delete_namespace_revisions(
+        &mut self,
+        namespace: &str,
+    ) -> Result<usize, WorkloadError> {
+        let values = self
+            .etcd_service
+            .get_all_with_prefix(&format!("revision.{}.", namespace))
+            .await
+            .ok_or_else(|| WorkloadError::Etcd("can't read the revisions".to_string()))?;
+        // the prefix is shared with the namespaces whose name extends this one
+        let keys: Vec<String> = values
+            .iter()
+            .filter_map(|value| serde_json::from_str::<WorkloadRevision>(value).ok())
+            .filter(|stored| stored.workload.namespace == namespace)
+            .map(|stored| self.revision_id(&stored.workload.id, stored.revision))
+            .collect();
+        for key in &keys {
+            self.etcd_service
+                .delete_checked(key)
+                .await
+                .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
+        }
+        Ok(keys.len())
+    }
+
+    /// Stores the definition of a workload as its next revision, before the workload itself so
+    /// that the workload carries the number of its revision. The number is claimed with a
+    /// create-only write, the next one is tried if a concurrent update claimed it first.
+    async fn record_revision(&mut self, workload: &mut Workload) -> Result<(), WorkloadError> {
+        for _ in 0..MAX_REVISION_ATTEMPTS {
+            workload.revision = self
+                .read_revisions(&workload.id)
+                .await?
+                .last()
+                .map_or(0, |stored| stored.revision)
+                + 1;
+            let revision = WorkloadRevision::new(workload.revision, unix_time(), workload);
+            let json = serde_json::to_string(&revision)
+                .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
+            let claimed = self
+                .etcd_service
+                .put_if_absent(
+                    &self.revision_id(&workload.id, workload.revision),
+                    &json,
+                    None,
+                )
+                .await
+                .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
+            if claimed.is_none() {
+                return Ok(());
+            }
+        }
+        Err(WorkloadError::Etcd(format!(
+            "can't claim a revision of workload {}",
+            workload.id
+        )))
+    }
+
+    /// Deletes the revision claimed for a workload which wasn't stored.
+    async fn discard_revision(&mut self, workload: &Workload) {
+        _ = self
+            .etcd_service
+            .delete(&self.revision_id(&workload.id, workload.revision))
+            .await;
+    }
+
+    /// Returns an error if a workload depends on itself, directly or through its dependencies,
+    /// its instances would never start. The dependencies which don't exist yet are accepted.
+    async fn check_dependencies(&mut self, workload: &Workload) -> Result<(), WorkloadError> {
+        let mut visited = HashSet::new();
+        let mut to_visit = workload.depends_on.clone();
+        while let Some(name) = to_visit.pop() {
+            if name == workload.name {
+                return Err(WorkloadError::InvalidSpec(format!(
+                    "workload {} depends on itself",
+                    name
+                )));
+            }
+            if !visited.insert(name.clone()) {
+                continue;
+            }
+            if let Ok(dependency) = self.get_workload(&name, &workload.namespace).await {
+                to_visit.extend(dependency.depends_on);
+            }
+        }
+        Ok(())
+    }
+
+    /// It replaces the definition of a workload by its canary.
+    pub async fn promote_canary(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Workload, WorkloadError> {
+        let previous = self.get_workload(workload_name, namespace).await?;
+        let canary = previous.canary.ok_or(WorkloadError::CanaryNotFound)?;
+
+        let mut workload = *canary.version;
+        workload.canary = None;
+        self.record_revision(&mut workload).await?;
+        if let Err(err) = self.put_workload(&workload).await {
+            self.discard_revision(&workload).await;
+            return Err(err);
+        }
+        Ok(workload)
+    }
+
+    /// It drops the canary of a workload, its definition is left unchanged.
+    pub async fn rollback_canary(
+        &mut self,
+        workload_name: &str,
+        namespace: &str,
+    ) -> Result<Workload, WorkloadError> {
+        let mut workload = self.get_workload(workload_name, namespace).await?;
+        if workload.canary.take().is_none() {
+            return Err(WorkloadError::CanaryNotFound);
+        }
+        self.put_workload(&workload).await?;
+        Ok(workload)
+    }
+
+    /// It stores a workload in etcd, the `Job`, `DaemonSet` and `StatefulSet` workloads are also
+    /// indexed by kind so that their controllers find them without reading every workload, as
+    /// the workloads with a canary.
+    pub async fn put_workload(&mut self, workload: &Workload) -> Result<(), WorkloadError> {
+        self.put_workload_if(workload, None).await.map(|_| ())
+    }
+
+    /// Stores a workload and its indexes, if `expected` is set only if the workload is still at
+    /// this version. Returns the version of the workload stored.
+    async fn put_workload_if(
+        &mut self,
+        workload: &Workload,
+        expected: Option<i64>,
+    ) -> Result<i64, WorkloadError> {
+        let json = serde_json::to_string(workload)
+            .map_err(|err| WorkloadError::WorkloadToJson(err.to_string()))?;
+        let result = self
+            .etcd_service
+            .put_if_version(&workload.id, &json, expected)
+            .await;
+        // evicted even if the write failed, it may have been applied
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&workload.id);
+        }
+        let version = result
+            .map_err(|err| WorkloadError::Etcd(err.to_string()))?
+            .ok_or_else(|| {
+                WorkloadError::VersionConflict(workload.name.clone(), expected.unwrap_or_default())
+            })?;
+        self.put_indexes(workload).await?;
+        Ok(version)
+    }
+
+    /// Writes the kind and canary indexes of a workload, and deletes the ones it no longer has.
+    async fn put_indexes(&mut self, workload: &Workload) -> Result<(), WorkloadError> {
+        for kind in INDEXED_KINDS {
+            let index = self.kind_index_id(kind, &workload.id);
+            if workload.kind == kind {
+                self.etcd_service
+                    .put(&index, &workload.id)
+                    .await
+                    .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
+            } else {
+                _ = self.etcd_service.delete(&index).await;
+            }
+        }
+
+        let index = self.canary_index_id(&workload.id);
+        if workload.canary.is_some() {
+            self.etcd_service
+                .put(&index, &workload.id)
+                .await
+                .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
+        } else {
+            _ = self.etcd_service.delete(&index).await;
+        }
+        Ok(())
+    }
+
+    /// Returns the workloads with a canary in every namespace, the ones which can't be read are
+    /// skipped.
+    pub async fn get_canary_workloads(&mut self) -> Vec<Workload> {
+        let ids = self
+            .etcd_service
+            .get_all_with_prefix(&self.canary_index_id(""))
+            .await
+            .unwrap_or_default();
+
+        let mut workloads = vec![];
+        for id in ids {
+            if let Some(workload) = self
+                .etcd_service
+                .get(&id)
+                .await
+                .and_then(|value| serde_json::from_str::<Workload>(&value).ok())
+                .filter(|workload| workload.canary.is_some())
+            {
+                workloads.push(workload);
+            }
+        }
+        workloads
+    }
+
+    /// Returns the workloads of a kind in every namespace, the ones which can't be read are
+    /// skipped. Only the kinds run by a controller are indexed, there are no `Service` ones.
+    pub async fn get_workloads_of_kind(&mut self, kind: WorkloadKind) -> Vec<Workload> {
+        let ids = self
+            .etcd_service
+            .get_all_with_prefix(&self.kind_index_id(kind, ""))
+            .await
+            .unwrap_or_default();
+
+        let mut workloads = vec![];
+        for id in ids {
+            if let Some(workload) = self
+                .etcd_service
+                .get(&id)
+                .await
+                .and_then(|value| serde_json::from_str::<Workload>(&value).ok())
+                .filter(|workload| workload.kind == kind)
+            {
+                workloads.push(workload);
+            }
+        }
+        workloads
+    }
+
+    /// Indexes a workload whose instances are replaced by a rolling update, the instances of a
+    /// `Job` aren't, they run to completion.
+    async fn start_rollout(&mut self, workload: &Workload) -> Result<(), WorkloadError> {
+        if workload.kind == WorkloadKind::Job {
+            return Ok(());
+        }
         self.etcd_service
-            .put(&new_id, &json)
+            .put(&self.rollout_index_id(&workload.id), &workload.id)
             .await
             .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
-        Ok(workload)
+        Ok(())
+    }
+
+    /// Returns the workloads with a rolling update in progress in every namespace, with their
+    /// version, the ones which can't be read are skipped.
+    pub async fn get_rollout_workloads(&mut self) -> Vec<Versioned<Workload>> {
+        let ids = self
+            .etcd_service
+            .get_all_with_prefix(&self.rollout_index_id(""))
+            .await
+            .unwrap_or_default();
+
+        let mut workloads = vec![];
+        for id in ids {
+            if let Some((value, version)) = self.etcd_service.get_versioned(&id).await {
+                if let Ok(workload) = serde_json::from_str::<Workload>(&value) {
+                    workloads.push(Versioned::new(workload, version));
+                }
+            }
+        }
+        workloads
+    }
+
+    /// Ends the rolling update of a workload, unless the workload was modified since `version`,
+    /// its update may have started another one.
+    pub async fn finish_rollout(
+        &mut self,
+        workload_id: &str,
+        version: i64,
+    ) -> Result<(), WorkloadError> {
+        self.etcd_service
+            .delete_if_unchanged(&self.rollout_index_id(workload_id), workload_id, version)
+            .await
+            .map_err(|err| WorkloadError::Etcd(err.to_string()))?;
+        Ok(())
     }
 
     pub async fn delete_workload(&mut self, workload_name: &str, namespace: &str) {
         let id = self.id(workload_name, namespace);
         _ = self.etcd_service.delete(&id).await;
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&id);
+        }
+        for kind in INDEXED_KINDS {
+            _ = self
+                .etcd_service
+                .delete(&self.kind_index_id(kind, &id))
+                .await;
+        }
+        _ = self.etcd_service.delete(&self.canary_index_id(&id)).await;
+        _ = self.etcd_service.delete(&self.rollout_index_id(&id)).await;
+        for stored in self.read_revisions(&id).await.unwrap_or_default() {
+            _ = self
+                .etcd_service
+                .delete(&self.revision_id(&id, stored.revision))
+                .await;
+        }
     }
 
     pub fn id(&mut self, name: &str, namespace: &str) -> String {
         format!("{}.{}", namespace, name)
     }
+
+    fn kind_index_id(&self, kind: WorkloadKind, workload_id: &str) -> String {
+        let kind = match kind {
+            WorkloadKind::Service => "service",
+            WorkloadKind::Job => "job",
+            WorkloadKind::DaemonSet => "daemonset",
+            WorkloadKind::StatefulSet => "statefulset",
+        };
+        format!("index.{}.{}", kind, workload_id)
+    }
+
+    fn canary_index_id(&self, workload_id: &str) -> String {
+        format!("index.canary.{}", workload_id)
+    }
+
+    fn rollout_index_id(&self, workload_id: &str) -> String {
+        format!("index.rollout.{}", workload_id)
+    }
+
+    /// Returns the key of a revision of a workload, padded so that the revisions are stored in
+    /// order.
+    fn revision_id(&self, workload_id: &str, revision: u64) -> String {
+        format!("revision.{}.{:010}", workload_id, revision)
+    }
 }
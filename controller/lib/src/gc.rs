@@ -0,0 +1,417 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use proto::scheduler::{ClusterSnapshot, InstanceIdentifier};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tonic::Request;
+
+use crate::etcd::EtcdClient;
+use crate::external_api::instance::model::{Instance, InstanceError};
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::shard::model::merge_snapshots;
+use crate::external_api::shard::service::ShardService;
+use crate::grpc_client::interface::SchedulerClientInterface;
+use crate::reconciler::{known_instances, Suspects};
+use crate::tasks::BackgroundTasks;
+
+/// `GcConfig` is the configuration of the garbage collector.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the collector is disabled if 0.
+/// * `dry_run`: If true, the garbage is only logged.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GcConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_interval_seconds() -> u64 {
+    60
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            interval_seconds: default_interval_seconds(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Something left behind by a deletion.
+#[derive(Debug, Clone)]
+pub enum Garbage {
+    /// An instance stored in etcd whose workload was deleted.
+    Instance(Box<Instance>),
+    /// An instance finished for longer than the TTL of its workload.
+    Expired(Box<Instance>),
+    /// A container run by a node for an instance missing from etcd.
+    Container {
+        instance_id: String,
+        node_id: String,
+    },
+}
+
+impl Garbage {
+    /// Identifies the garbage across passes.
+    fn key(&self) -> String {
+        match self {
+            Garbage::Instance(instance) => format!("instance.{}", instance.id),
+            Garbage::Expired(instance) => format!("expired.{}", instance.id),
+            Garbage::Container { instance_id, .. } => format!("container.{}", instance_id),
+        }
+    }
+}
+
+/// Finds the garbage of the cluster.
+///
+/// # Arguments:
+///
+/// * `instances`: The instances stored in etcd, in every namespace.
+/// * `workloads`: The ids of the workloads of the instances which still exist.
+/// * `snapshot`: The placements and the pending instances of the scheduler.
+/// * `now`: The current time, in seconds since the unix epoch.
+///
+/// # Returns:
+///
+/// The instances of deleted workloads, the expired instances and the containers of unknown
+/// instances.
+pub fn find_garbage(
+    instances: &[Instance],
+    workloads: &HashSet<String>,
+    snapshot: &ClusterSnapshot,
+    now: u64,
+) -> Vec<Garbage> {
+    let stored: HashSet<&str> = instances
+        .iter()
+        .map(|instance| instance.id.as_str())
+        .collect();
+
+    let orphaned_instances = instances
+        .iter()
+        .filter(|instance| !workloads.contains(&instance.workload_id))
+        .map(|instance| Garbage::Instance(Box::new(instance.clone())));
+    let expired_instances = instances
+        .iter()
+        .filter(|instance| workloads.contains(&instance.workload_id) && instance.is_expired(now))
+        .map(|instance| Garbage::Expired(Box::new(instance.clone())));
+    let orphaned_containers = snapshot
+        .placements
+        .iter()
+        .filter(|placement| !stored.contains(placement.instance_id.as_str()))
+        .map(|placement| Garbage::Container {
+            instance_id: placement.instance_id.clone(),
+            node_id: placement.node_id.clone(),
+        });
+
+    orphaned_instances
+        .chain(expired_instances)
+        .chain(orphaned_containers)
+        .collect()
+}
+
+/// The totals collected since the controller started. The resources of the orphaned containers
+/// are unknown, only the ones of the instances are counted.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct GcMetrics {
+    pub instances: u64,
+    pub expired: u64,
+    pub containers: u64,
+    pub cpu: u64,
+    pub memory: u64,
+    pub disk: u64,
+}
+
+impl GcMetrics {
+    fn record(&mut self, garbage: &Garbage) {
+        match garbage {
+            Garbage::Instance(instance) | Garbage::Expired(instance) => {
+                if matches!(garbage, Garbage::Instance(_)) {
+                    self.instances += 1;
+                } else {
+                    self.expired += 1;
+                }
+                self.cpu += instance.resources.cpu;
+                self.memory += instance.resources.memory;
+                self.disk += instance.resources.disk;
+            }
+            Garbage::Container { .. } => self.containers += 1,
+        }
+    }
+}
+
+/// `GarbageCollector` periodically deletes the instances whose workload was deleted or which
+/// finished for longer than the `ttl_seconds_after_finished` of their workload, and destroys the
+/// containers of the instances missing from etcd. Like the reconciliation, garbage is only
+/// collected once found by two consecutive passes.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks the collector is spawned in.
+/// * `suspects`: The garbage found by the previous pass.
+/// * `metrics`: The totals collected, shared with `metrics()`.
+pub struct GarbageCollector {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+    suspects: Suspects,
+    metrics: Arc<Mutex<GcMetrics>>,
+}
+
+impl GarbageCollector {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        GarbageCollector {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+            suspects: Suspects::default(),
+            metrics: Arc::new(Mutex::new(GcMetrics::default())),
+        }
+    }
+
+    /// Returns a handle on the totals collected, updated after each pass.
+    pub fn metrics(&self) -> Arc<Mutex<GcMetrics>> {
+        self.metrics.clone()
+    }
+
+    /// Spawns the collector in `background_tasks`, it stops when the controller shuts down.
+    pub fn start(mut self, config: &GcConfig) {
+        if config.interval_seconds == 0 {
+            info!("Garbage collector disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+        let dry_run = config.dry_run;
+        if dry_run {
+            info!("Garbage collector in dry-run mode, the garbage is only logged");
+        }
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.collect(dry_run).await {
+                                warn!("Garbage collection failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Garbage collector stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass: the garbage confirmed by this pass is collected, or logged in dry-run
+    /// mode.
+    async fn collect(&mut self, dry_run: bool) -> Result<(), String> {
+        let snapshots = ShardService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .scheduler_snapshots()
+            .await?;
+        // the containers missing from etcd are destroyed by the scheduler running them
+        let mut owners = HashMap::new();
+        for (scheduler, snapshot) in &snapshots {
+            for placement in snapshot.placements.iter().chain(&snapshot.pending) {
+                owners.insert(placement.instance_id.clone(), *scheduler);
+            }
+        }
+        let snapshot = merge_snapshots(
+            snapshots
+                .into_iter()
+                .map(|(_, snapshot)| snapshot)
+                .collect(),
+        );
+
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?;
+        let instances = instance_service.get_instances_of_all_namespaces().await;
+
+        // the workloads are stored under their id, each one is looked up once
+        let mut etcd_client = EtcdClient::new(self.etcd_address.to_string())
+            .await
+            .map_err(|err| err.to_string())?;
+        let mut workloads = HashMap::new();
+        for instance in &instances {
+            if !workloads.contains_key(&instance.workload_id) {
+                let exists = etcd_client.get(&instance.workload_id).await.is_some();
+                workloads.insert(instance.workload_id.clone(), exists);
+            }
+        }
+        let workloads: HashSet<String> = workloads
+            .into_iter()
+            .filter_map(|(id, exists)| exists.then_some(id))
+            .collect();
+
+        let garbage = self.suspects.confirm(
+            find_garbage(&instances, &workloads, &snapshot, unix_time()),
+            Garbage::key,
+        );
+        debug!("Garbage collection found {} item(s)", garbage.len());
+
+        let known = known_instances(&snapshot);
+        for item in garbage {
+            if dry_run {
+                info!("[dry-run] would collect {:?}", item);
+                continue;
+            }
+
+            let result = match &item {
+                Garbage::Instance(instance) | Garbage::Expired(instance)
+                    if known.contains(instance.id.as_str()) =>
+                {
+                    instance_service
+                        .delete_instance(&instance.id, &instance.namespace)
+                        .await
+                }
+                Garbage::Instance(instance) | Garbage::Expired(instance) => {
+                    instance_service.remove_instance(instance).await
+                }
+                Garbage::Container { instance_id, .. } => {
+                    let scheduler = owners
+                        .get(instance_id)
+                        .copied()
+                        .unwrap_or(self.scheduler_address);
+                    destroy_container(scheduler, instance_id).await
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    info!("Collected {}", item.key());
+                    self.metrics.lock().unwrap().record(&item);
+                }
+                Err(err) => error!(
+                    "Failed to collect {}: {}",
+                    item.key(),
+                    err.to_problem().detail
+                ),
+            }
+        }
+
+        info!(
+            "Garbage collected so far: {:?}",
+            self.metrics.lock().unwrap()
+        );
+        Ok(())
+    }
+}
+
+/// Asks a scheduler to destroy the container of an instance missing from etcd.
+async fn destroy_container(scheduler: SocketAddr, instance_id: &str) -> Result<(), InstanceError> {
+    SchedulerClientInterface::new(format!("http://{}", scheduler))
+        .await
+        .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))?
+        .destroy_instance(Request::new(InstanceIdentifier {
+            id: instance_id.to_string(),
+        }))
+        .await
+        .map(|_| ())
+        .map_err(|err| InstanceError::Grpc(format!("{:?}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::InstancePlacement;
+
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::instance::model::InstanceState;
+    use crate::external_api::workload::model::Ressources;
+
+    fn instance(id: &str, workload_id: &str) -> Instance {
+        Instance {
+            workload_id: workload_id.to_string(),
+            resources: Ressources {
+                cpu: 1,
+                memory: 128,
+                disk: 10,
+            },
+            ..test_instance(id, InstanceState::Running)
+        }
+    }
+
+    #[test]
+    fn test_find_garbage() {
+        let mut finished = instance("4", "default.web");
+        finished.status.state = InstanceState::Terminated;
+        finished.ttl_seconds_after_finished = Some(60);
+        finished.update_finished_at(1000);
+
+        let instances = vec![
+            instance("1", "default.web"),
+            instance("2", "default.gone"),
+            finished,
+        ];
+        let workloads = HashSet::from(["default.web".to_string()]);
+        let snapshot = ClusterSnapshot {
+            nodes: vec![],
+            placements: vec![
+                InstancePlacement {
+                    instance_id: "1".to_string(),
+                    node_id: "a".to_string(),
+                    status: None,
+                },
+                InstancePlacement {
+                    instance_id: "3".to_string(),
+                    node_id: "a".to_string(),
+                    status: None,
+                },
+            ],
+            pending: vec![],
+        };
+
+        let keys = |now| -> Vec<String> {
+            find_garbage(&instances, &workloads, &snapshot, now)
+                .iter()
+                .map(Garbage::key)
+                .collect()
+        };
+        assert_eq!(keys(1059), vec!["instance.2", "container.3"]);
+        assert_eq!(keys(1060), vec!["instance.2", "expired.4", "container.3"]);
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut metrics = GcMetrics::default();
+        metrics.record(&Garbage::Instance(Box::new(instance("1", "default.web"))));
+        metrics.record(&Garbage::Expired(Box::new(instance("2", "default.web"))));
+        metrics.record(&Garbage::Container {
+            instance_id: "3".to_string(),
+            node_id: "a".to_string(),
+        });
+
+        assert_eq!(
+            metrics,
+            GcMetrics {
+                instances: 1,
+                expired: 1,
+                containers: 1,
+                cpu: 2,
+                memory: 256,
+                disk: 20,
+            }
+        );
+    }
+}
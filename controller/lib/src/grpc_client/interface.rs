@@ -1,15 +1,42 @@
+use futures_util::stream;
 use log::{error, info};
+use opentelemetry::Context;
+use proto::agent::{ExecInput, ExecOutput, LogChunk, LogRequest};
+use proto::deadline::{format_deadline, DEADLINE_METADATA};
 use proto::scheduler::instance_service_client::InstanceServiceClient;
-use proto::scheduler::{Instance, InstanceIdentifier, InstanceStatus};
+use proto::scheduler::{
+    ClusterSnapshot, Eviction, ImagePullRequest, Instance, InstanceEvictRequest,
+    InstanceIdentifier, InstanceMigrateRequest, InstanceStatus, NodeCordonRequest,
+    NodeImagePullStatus,
+};
+use proto::version::{protocol_range, PROTOCOL_METADATA};
+use telemetry::grpc::inject_context;
+use tokio::sync::mpsc;
 use tonic::transport::{Channel, Error};
 use tonic::{Request, Response, Status, Streaming};
 
+use crate::external_api::middleware::deadline;
+
 #[derive(Debug)]
 pub enum SchedulerClientInterfaceError {
     ConnectionError(Error),
     RequestFailed(Status),
 }
 
+/// Adds the trace context, the protocol versions spoken by the controller and the time left
+/// before the deadline of the HTTP request being handled to an outgoing request.
+fn prepare_request<T>(request: &mut Request<T>) {
+    inject_context(&Context::current(), request);
+    if let Ok(range) = protocol_range().parse() {
+        request.metadata_mut().insert(PROTOCOL_METADATA, range);
+    }
+    if let Some(remaining) = deadline::remaining() {
+        if let Ok(remaining) = format_deadline(remaining).parse() {
+            request.metadata_mut().insert(DEADLINE_METADATA, remaining);
+        }
+    }
+}
+
 pub struct SchedulerClientInterface {
     instance_client: InstanceServiceClient<Channel>,
 }
@@ -23,16 +50,19 @@ impl SchedulerClientInterface {
             instance_client_address,
         );
 
+        // node statuses and instance lists grow with the cluster, they are compressed both ways
         let instance_client = InstanceServiceClient::connect(instance_client_address)
             .await
-            .map_err(SchedulerClientInterfaceError::ConnectionError)?;
+            .map_err(SchedulerClientInterfaceError::ConnectionError)?
+            .send_gzip()
+            .accept_gzip();
 
         Ok(Self { instance_client })
     }
 
     pub async fn create_instance(
         &mut self,
-        request: Request<Instance>,
+        mut request: Request<Instance>,
     ) -> Result<Response<Streaming<InstanceStatus>>, SchedulerClientInterfaceError> {
         let remote_address = match request.remote_addr() {
             Some(addr) => addr.to_string(),
@@ -47,6 +77,8 @@ impl SchedulerClientInterface {
             remote_address
         );
 
+        prepare_request(&mut request);
+
         self.instance_client
             .create(request)
             .await
@@ -55,7 +87,7 @@ impl SchedulerClientInterface {
 
     pub async fn destroy_instance(
         &mut self,
-        request: Request<InstanceIdentifier>,
+        mut request: Request<InstanceIdentifier>,
     ) -> Result<Response<()>, SchedulerClientInterfaceError> {
         let remote_address = match request.remote_addr() {
             Some(addr) => addr.to_string(),
@@ -70,6 +102,8 @@ impl SchedulerClientInterface {
             remote_address
         );
 
+        prepare_request(&mut request);
+
         self.instance_client
             .destroy(request)
             .await
@@ -78,7 +112,7 @@ impl SchedulerClientInterface {
 
     pub async fn start_instance(
         &mut self,
-        request: Request<InstanceIdentifier>,
+        mut request: Request<InstanceIdentifier>,
     ) -> Result<Response<()>, SchedulerClientInterfaceError> {
         let remote_address = match request.remote_addr() {
             Some(addr) => addr.to_string(),
@@ -93,6 +127,8 @@ impl SchedulerClientInterface {
             remote_address
         );
 
+        prepare_request(&mut request);
+
         self.instance_client
             .start(request)
             .await
@@ -101,7 +137,7 @@ impl SchedulerClientInterface {
 
     pub async fn stop_instance(
         &mut self,
-        request: Request<InstanceIdentifier>,
+        mut request: Request<InstanceIdentifier>,
     ) -> Result<Response<()>, SchedulerClientInterfaceError> {
         let remote_address = match request.remote_addr() {
             Some(addr) => addr.to_string(),
@@ -116,9 +152,189 @@ impl SchedulerClientInterface {
             remote_address
         );
 
+        prepare_request(&mut request);
+
         self.instance_client
             .stop(request)
             .await
             .map_err(SchedulerClientInterfaceError::RequestFailed)
     }
+
+    pub async fn restart_instance(
+        &mut self,
+        mut request: Request<InstanceIdentifier>,
+    ) -> Result<Response<()>, SchedulerClientInterfaceError> {
+        let remote_address = match request.remote_addr() {
+            Some(addr) => addr.to_string(),
+            None => {
+                error!("\"restart_instance\" Failed to get remote address from request");
+                "Error getting address".to_string()
+            }
+        };
+
+        info!(
+            "Calling gRPC procedure \"restart_instance\" to {}",
+            remote_address
+        );
+
+        prepare_request(&mut request);
+
+        self.instance_client
+            .restart(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+
+    /// Fetches the full view of the scheduler: its nodes, the instances placed on them and the
+    /// instances waiting for their first status.
+    pub async fn cluster_snapshot(
+        &mut self,
+    ) -> Result<Response<ClusterSnapshot>, SchedulerClientInterfaceError> {
+        info!("Calling gRPC procedure \"cluster_snapshot\"");
+
+        let mut request = Request::new(());
+        prepare_request(&mut request);
+
+        self.instance_client
+            .snapshot(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+
+    /// Stops placing new instances on a node, or places them on it again if `cordoned` is false.
+    pub async fn cordon_node(
+        &mut self,
+        node_id: &str,
+        cordoned: bool,
+    ) -> Result<Response<()>, SchedulerClientInterfaceError> {
+        info!(
+            "Calling gRPC procedure \"cordon\" for node {} ({})",
+            node_id, cordoned
+        );
+
+        let mut request = Request::new(NodeCordonRequest {
+            node_id: node_id.to_string(),
+            cordoned,
+        });
+        prepare_request(&mut request);
+
+        self.instance_client
+            .cordon(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+
+    /// Stops an instance for a reason other than its deletion, the eviction is attached to the
+    /// statuses of the instance sent afterwards.
+    pub async fn evict_instance(
+        &mut self,
+        instance_id: &str,
+        eviction: Eviction,
+    ) -> Result<Response<()>, SchedulerClientInterfaceError> {
+        info!(
+            "Calling gRPC procedure \"evict\" for instance {}",
+            instance_id
+        );
+
+        let mut request = Request::new(InstanceEvictRequest {
+            id: instance_id.to_string(),
+            eviction: Some(eviction),
+        });
+        prepare_request(&mut request);
+
+        self.instance_client
+            .evict(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+    /// Moves a running instance to another node without restarting it, the scheduler
+    /// checkpoints it on its node and restores it on `node_id`. It is experimental.
+    pub async fn migrate_instance(
+        &mut self,
+        instance_id: &str,
+        node_id: &str,
+    ) -> Result<Response<()>, SchedulerClientInterfaceError> {
+        info!(
+            "Calling gRPC procedure \"migrate\" for instance {} to node {}",
+            instance_id, node_id
+        );
+
+        let mut request = Request::new(InstanceMigrateRequest {
+            id: instance_id.to_string(),
+            node_id: node_id.to_string(),
+        });
+        prepare_request(&mut request);
+
+        self.instance_client
+            .migrate(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+    /// Pulls an image on the given nodes, or on every node connected to the scheduler if
+    /// `node_ids` is empty. The stream of the pull statuses ends once every node is done.
+    pub async fn pull_image(
+        &mut self,
+        uri: &str,
+        node_ids: Vec<String>,
+    ) -> Result<Response<Streaming<NodeImagePullStatus>>, SchedulerClientInterfaceError> {
+        info!("Calling gRPC procedure \"pull\" for image {}", uri);
+
+        let mut request = Request::new(ImagePullRequest {
+            uri: uri.to_string(),
+            node_ids,
+        });
+        prepare_request(&mut request);
+
+        self.instance_client
+            .pull(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+
+    /// Streams the output of an instance from its node, the last `tail` lines or every line if
+    /// 0, then the next ones if `follow` is set. The stream ends with a chunk marked `eof`.
+    pub async fn instance_logs(
+        &mut self,
+        instance_id: &str,
+        follow: bool,
+        tail: u32,
+    ) -> Result<Response<Streaming<LogChunk>>, SchedulerClientInterfaceError> {
+        info!(
+            "Calling gRPC procedure \"logs\" for instance {}",
+            instance_id
+        );
+
+        let mut request = Request::new(LogRequest {
+            instance_id: instance_id.to_string(),
+            follow,
+            tail,
+            ..Default::default()
+        });
+        prepare_request(&mut request);
+
+        self.instance_client
+            .logs(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
+
+    /// Opens an exec session in an instance, the first of `inputs` starting it. The inputs are
+    /// sent until their sender is dropped, and the output ends with the exit code of the command.
+    pub async fn instance_exec(
+        &mut self,
+        inputs: mpsc::Receiver<ExecInput>,
+    ) -> Result<Response<Streaming<ExecOutput>>, SchedulerClientInterfaceError> {
+        info!("Calling gRPC procedure \"exec\"");
+
+        let inputs = stream::unfold(inputs, |mut inputs| async move {
+            inputs.recv().await.map(|input| (input, inputs))
+        });
+        let mut request = Request::new(inputs);
+        prepare_request(&mut request);
+
+        self.instance_client
+            .exec(request)
+            .await
+            .map_err(SchedulerClientInterfaceError::RequestFailed)
+    }
 }
@@ -13,7 +13,11 @@ impl InternalAPIInterface {
 
         tokio::spawn(async move {
             Server::builder()
-                .add_service(NodeServiceServer::new(NodeController::default()))
+                .add_service(
+                    NodeServiceServer::new(NodeController::default())
+                        .send_gzip()
+                        .accept_gzip(),
+                )
                 .serve(address)
                 .await
                 .unwrap();
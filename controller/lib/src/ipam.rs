@@ -0,0 +1,311 @@
+use std::net::Ipv4Addr;
+
+use actix_web::http::StatusCode;
+use futures_util::TryStreamExt;
+
+use crate::etcd::EtcdClient;
+use crate::external_api::generic::problem::Problem;
+
+/// Range in which the subnets of the namespaces are allocated (10.244.0.0/16).
+pub const INSTANCE_NETWORK: Ipv4Addr = Ipv4Addr::new(10, 244, 0, 0);
+pub const INSTANCE_NETWORK_PREFIX_LENGTH: u8 = 16;
+/// Size of the subnet of each namespace (/24), its first address is kept for the gateway.
+pub const NAMESPACE_SUBNET_PREFIX_LENGTH: u8 = 24;
+
+/// How many times an allocation is attempted again when another controller took the address
+/// first.
+const MAX_ALLOCATION_ATTEMPTS: usize = 8;
+
+pub enum IpamError {
+    Etcd(String),
+    NoSubnetAvailable,
+    NoAddressAvailable(String),
+    InvalidSubnet(String),
+    InvalidAddress(String),
+}
+
+impl IpamError {
+    pub fn to_problem(&self) -> Problem {
+        match self {
+            IpamError::Etcd(err) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "etcd_error",
+                format!("Etcd error: {}", err),
+            ),
+            IpamError::NoSubnetAvailable => Problem::new(
+                StatusCode::INSUFFICIENT_STORAGE,
+                "no_subnet_available",
+                "No subnet left in the instances range",
+            ),
+            IpamError::NoAddressAvailable(namespace) => Problem::new(
+                StatusCode::INSUFFICIENT_STORAGE,
+                "no_address_available",
+                format!(
+                    "No IP address left in the subnet of namespace {}",
+                    namespace
+                ),
+            ),
+            IpamError::InvalidSubnet(subnet) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_subnet",
+                format!("Invalid subnet stored in etcd: {}", subnet),
+            ),
+            IpamError::InvalidAddress(ip) => Problem::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "invalid_stored_address",
+                format!("Invalid IP address stored in etcd: {}", ip),
+            ),
+        }
+    }
+}
+
+/// Returns the first subnet of the instances range that is not already used.
+pub fn next_subnet(used: &[Ipv4Addr]) -> Option<Ipv4Addr> {
+    let network = u32::from(INSTANCE_NETWORK);
+    let count = 1u32 << (NAMESPACE_SUBNET_PREFIX_LENGTH - INSTANCE_NETWORK_PREFIX_LENGTH);
+    let size = 1u32 << (32 - NAMESPACE_SUBNET_PREFIX_LENGTH);
+
+    (0..count)
+        .map(|index| Ipv4Addr::from(network + index * size))
+        .find(|subnet| !used.contains(subnet))
+}
+
+/// Returns the first address of `subnet` that is not already used, skipping the network, the
+/// gateway and the broadcast addresses.
+pub fn next_address(subnet: Ipv4Addr, used: &[Ipv4Addr]) -> Option<Ipv4Addr> {
+    let network = u32::from(subnet);
+    let size = 1u32 << (32 - NAMESPACE_SUBNET_PREFIX_LENGTH);
+
+    (2..size - 1)
+        .map(|offset| Ipv4Addr::from(network + offset))
+        .find(|ip| !used.contains(ip))
+}
+
+/// `IpamService` allocates the IP addresses of the instances. Each namespace gets its own subnet
+/// of the instances range on its first instance, the addresses of the instances are allocated in
+/// the subnet of their namespace. Every allocation is recorded in etcd:
+/// - `ipam.namespace.<namespace>` holds the subnet of a namespace
+/// - `ipam.subnet.<subnet>` holds the namespace owning a subnet
+/// - `ipam.address.<namespace>.<ip>` holds the id of the instance owning an address
+/// - `ipam.sticky.<namespace>.<name>` holds the address kept for an instance with a stable name
+///
+/// Properties:
+///
+/// * `etcd_service`: This is the service that will be used to interact with etcd.
+pub struct IpamService {
+    etcd_service: EtcdClient,
+}
+
+impl IpamService {
+    pub fn new(etcd_service: EtcdClient) -> Self {
+        IpamService { etcd_service }
+    }
+
+    /// Returns the subnet of a namespace, allocated if the namespace has none yet.
+    pub async fn subnet(&mut self, namespace: &str) -> Result<Ipv4Addr, IpamError> {
+        let namespace_key = format!("ipam.namespace.{}", namespace);
+        if let Some(subnet) = self.etcd_service.get(&namespace_key).await {
+            return parse_subnet(&subnet);
+        }
+
+        for _ in 0..MAX_ALLOCATION_ATTEMPTS {
+            let used = self.used_addresses("ipam.subnet.").await?;
+            let subnet = next_subnet(&used).ok_or(IpamError::NoSubnetAvailable)?;
+
+            // the subnet is claimed first, so that two namespaces can't get the same one
+            let subnet_key = format!("ipam.subnet.{}", subnet);
+            if self.put_if_absent(&subnet_key, namespace).await?.is_some() {
+                continue;
+            }
+            return match self
+                .put_if_absent(&namespace_key, &subnet.to_string())
+                .await?
+            {
+                None => Ok(subnet),
+                // another controller allocated a subnet to the namespace meanwhile
+                Some(existing) => {
+                    _ = self.etcd_service.delete(&subnet_key).await;
+                    parse_subnet(&existing)
+                }
+            };
+        }
+        Err(IpamError::NoSubnetAvailable)
+    }
+
+    /// Allocates an address to an instance in the subnet of its namespace.
+    pub async fn allocate(
+        &mut self,
+        namespace: &str,
+        instance_id: &str,
+    ) -> Result<Ipv4Addr, IpamError> {
+        let subnet = self.subnet(namespace).await?;
+        let prefix = format!("ipam.address.{}.", namespace);
+
+        for _ in 0..MAX_ALLOCATION_ATTEMPTS {
+            let used = self.used_addresses(&prefix).await?;
+            let ip = next_address(subnet, &used)
+                .ok_or_else(|| IpamError::NoAddressAvailable(namespace.to_string()))?;
+
+            let key = format!("{}{}", prefix, ip);
+            if self.put_if_absent(&key, instance_id).await?.is_none() {
+                return Ok(ip);
+            }
+        }
+        Err(IpamError::NoAddressAvailable(namespace.to_string()))
+    }
+
+    /// Releases the address of an instance, it can be allocated again.
+    pub async fn release(&mut self, namespace: &str, ip: &str) {
+        _ = self
+            .etcd_service
+            .delete(&format!("ipam.address.{}.{}", namespace, ip))
+            .await;
+    }
+
+    /// Allocates an address to an instance with a stable name, the same address is returned for
+    /// the name until it is released by `release_sticky`.
+    ///
+    /// # Arguments:
+    ///
+    /// * `namespace`: The namespace of the instance.
+    /// * `name`: The stable name of the instance, unique in its namespace.
+    pub async fn allocate_sticky(
+        &mut self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Ipv4Addr, IpamError> {
+        let key = format!("ipam.sticky.{}.{}", namespace, name);
+        if let Some(ip) = self.etcd_service.get(&key).await {
+            return parse_address(&ip);
+        }
+
+        let ip = self.allocate(namespace, name).await?;
+        match self.put_if_absent(&key, &ip.to_string()).await? {
+            None => Ok(ip),
+            // another controller allocated an address to the name meanwhile
+            Some(existing) => {
+                self.release(namespace, &ip.to_string()).await;
+                parse_address(&existing)
+            }
+        }
+    }
+
+    /// Releases the address kept for an instance with a stable name.
+    pub async fn release_sticky(&mut self, namespace: &str, name: &str) {
+        let key = format!("ipam.sticky.{}.{}", namespace, name);
+        if let Some(ip) = self.etcd_service.get(&key).await {
+            self.release(namespace, &ip).await;
+        }
+        _ = self.etcd_service.delete(&key).await;
+    }
+
+    /// Returns the namespace and the name of every instance with a stable name holding an
+    /// address.
+    pub async fn sticky_names(&self) -> Result<Vec<(String, String)>, IpamError> {
+        let prefix = "ipam.sticky.";
+        self.etcd_service
+            .scan_prefix(prefix, None)
+            .try_filter_map(|(key, _)| async move {
+                Ok(key.strip_prefix(prefix).and_then(|owner| {
+                    owner
+                        .split_once('.')
+                        .map(|(namespace, name)| (namespace.to_string(), name.to_string()))
+                }))
+            })
+            .try_collect()
+            .await
+            .map_err(|err| IpamError::Etcd(err.to_string()))
+    }
+
+    /// Releases the subnet of a namespace, if none of its addresses is allocated anymore.
+    pub async fn release_subnet(&mut self, namespace: &str) -> Result<(), IpamError> {
+        let namespace_key = format!("ipam.namespace.{}", namespace);
+        let Some(subnet) = self.etcd_service.get(&namespace_key).await else {
+            return Ok(());
+        };
+        if !self
+            .used_addresses(&format!("ipam.address.{}.", namespace))
+            .await?
+            .is_empty()
+        {
+            return Ok(());
+        }
+
+        _ = self.etcd_service.delete(&namespace_key).await;
+        _ = self
+            .etcd_service
+            .delete(&format!("ipam.subnet.{}", subnet))
+            .await;
+        Ok(())
+    }
+
+    /// Returns the addresses ending the keys starting with `prefix`.
+    async fn used_addresses(&self, prefix: &str) -> Result<Vec<Ipv4Addr>, IpamError> {
+        self.etcd_service
+            .scan_prefix(prefix, None)
+            .try_filter_map(|(key, _)| async move {
+                Ok(key
+                    .strip_prefix(prefix)
+                    .and_then(|ip| ip.parse::<Ipv4Addr>().ok()))
+            })
+            .try_collect()
+            .await
+            .map_err(|err| IpamError::Etcd(err.to_string()))
+    }
+
+    async fn put_if_absent(&mut self, key: &str, value: &str) -> Result<Option<String>, IpamError> {
+        self.etcd_service
+            .put_if_absent(key, value, None)
+            .await
+            .map_err(|err| IpamError::Etcd(err.to_string()))
+    }
+}
+
+fn parse_subnet(subnet: &str) -> Result<Ipv4Addr, IpamError> {
+    subnet
+        .parse()
+        .map_err(|_| IpamError::InvalidSubnet(subnet.to_string()))
+}
+
+fn parse_address(ip: &str) -> Result<Ipv4Addr, IpamError> {
+    ip.parse()
+        .map_err(|_| IpamError::InvalidAddress(ip.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_subnet() {
+        assert_eq!(next_subnet(&[]), Some(Ipv4Addr::new(10, 244, 0, 0)));
+
+        let used = vec![Ipv4Addr::new(10, 244, 0, 0), Ipv4Addr::new(10, 244, 2, 0)];
+        assert_eq!(next_subnet(&used), Some(Ipv4Addr::new(10, 244, 1, 0)));
+    }
+
+    #[test]
+    fn test_next_subnet_exhausted() {
+        let used: Vec<Ipv4Addr> = (0..=255).map(|i| Ipv4Addr::new(10, 244, i, 0)).collect();
+        assert_eq!(next_subnet(&used), None);
+    }
+
+    #[test]
+    fn test_next_address() {
+        let subnet = Ipv4Addr::new(10, 244, 3, 0);
+        assert_eq!(
+            next_address(subnet, &[]),
+            Some(Ipv4Addr::new(10, 244, 3, 2))
+        );
+
+        let used = vec![Ipv4Addr::new(10, 244, 3, 2), Ipv4Addr::new(10, 244, 3, 4)];
+        assert_eq!(
+            next_address(subnet, &used),
+            Some(Ipv4Addr::new(10, 244, 3, 3))
+        );
+
+        // the broadcast address is never allocated
+        let used: Vec<Ipv4Addr> = (2..=254).map(|i| Ipv4Addr::new(10, 244, 3, i)).collect();
+        assert_eq!(next_address(subnet, &used), None);
+    }
+}
@@ -0,0 +1,342 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::{Instance, InstanceDTO, InstanceState};
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::workload::model::{JobSpec, JobState, JobStatus, Workload, WorkloadKind};
+use crate::external_api::workload::service::WorkloadService;
+use crate::tasks::BackgroundTasks;
+
+/// The delay before creating an instance after the first failure, doubled after each failure.
+const INITIAL_BACKOFF_SECONDS: u64 = 10;
+
+/// The cap of the delays between the failures.
+const MAX_BACKOFF_SECONDS: u64 = 360;
+
+/// `JobConfig` is the configuration of the job controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+impl Default for JobConfig {
+    fn default() -> Self {
+        JobConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// What the job controller does for a job after counting its instances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobAction {
+    /// Nothing until the next pass
+    Wait,
+    /// Create an instance of the job
+    CreateInstance,
+}
+
+/// Returns the delay before creating an instance after `failed` failures.
+pub fn backoff_seconds(failed: u32) -> u64 {
+    if failed == 0 {
+        return 0;
+    }
+    INITIAL_BACKOFF_SECONDS
+        .saturating_mul(1 << (failed - 1).min(16))
+        .min(MAX_BACKOFF_SECONDS)
+}
+
+/// Counts the instances of a job which finished since the previous pass and decides whether an
+/// instance must be created. A single instance of a job runs at a time.
+///
+/// # Arguments:
+///
+/// * `spec`: The completions and the backoff limit of the job.
+/// * `status`: The progress of the job recorded by the previous pass.
+/// * `instances`: The instances of the job stored in etcd.
+/// * `now`: The current time, in seconds since the unix epoch.
+///
+/// # Returns:
+///
+/// The new progress of the job and what to do.
+pub fn sync_job(
+    spec: &JobSpec,
+    status: &JobStatus,
+    instances: &[Instance],
+    now: u64,
+) -> (JobStatus, JobAction) {
+    let mut status = status.clone();
+    if status.state != JobState::Active {
+        return (status, JobAction::Wait);
+    }
+
+    for instance in instances {
+        if !instance.status.state.is_finished() || status.counted.contains(&instance.id) {
+            continue;
+        }
+        if instance.status.state == InstanceState::Terminated {
+            status.succeeded += 1;
+        } else {
+            status.failed += 1;
+            status.last_failure_at = Some(
+                instance
+                    .finished_at
+                    .unwrap_or(now)
+                    .max(status.last_failure_at.unwrap_or(0)),
+            );
+        }
+        status.counted.push(instance.id.clone());
+    }
+
+    if status.succeeded >= spec.completions {
+        status.state = JobState::Complete;
+        return (status, JobAction::Wait);
+    }
+    if status.failed > spec.backoff_limit {
+        status.state = JobState::Failed;
+        return (status, JobAction::Wait);
+    }
+
+    let active = instances
+        .iter()
+        .any(|instance| !instance.status.state.is_finished());
+    let retry_at = status
+        .last_failure_at
+        .map(|at| at.saturating_add(backoff_seconds(status.failed)))
+        .unwrap_or(0);
+    if active || now < retry_at {
+        return (status, JobAction::Wait);
+    }
+    (status, JobAction::CreateInstance)
+}
+
+/// `JobController` periodically runs the `Job` workloads to completion: it counts their
+/// succeeded and failed instances and creates a new instance, after a backoff if the previous
+/// one failed, until enough of them succeeded or too many failed.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the created instances.
+pub struct JobController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl JobController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        JobController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the job controller in `background_tasks`, it stops when the controller shuts down.
+    pub fn start(self, config: &JobConfig) {
+        if config.interval_seconds == 0 {
+            info!("Job controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Job synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Job controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every job.
+    async fn sync(&self) -> Result<(), String> {
+        let mut workload_service = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let jobs = workload_service
+            .get_workloads_of_kind(WorkloadKind::Job)
+            .await;
+        if jobs.is_empty() {
+            return Ok(());
+        }
+
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        let instances = instance_service.get_instances_of_all_namespaces().await;
+        debug!("Synchronizing {} job(s)", jobs.len());
+
+        for job in jobs {
+            let owned: Vec<Instance> = instances
+                .iter()
+                .filter(|instance| instance.workload_id == job.id)
+                .cloned()
+                .collect();
+            self.sync_one(job, &owned, &mut workload_service, &mut instance_service)
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Records the progress of a job and creates its next instance if needed.
+    async fn sync_one(
+        &self,
+        mut job: Workload,
+        instances: &[Instance],
+        workload_service: &mut WorkloadService,
+        instance_service: &mut InstanceService,
+    ) {
+        let previous = job.job_status.clone().unwrap_or_default();
+        let spec = job.job.clone().unwrap_or_default();
+        let (status, action) = sync_job(&spec, &previous, instances, unix_time());
+
+        if job.job_status.as_ref() != Some(&status) {
+            if status.state != previous.state {
+                info!(
+                    "Job {} is {:?}: {} succeeded, {} failed",
+                    job.id, status.state, status.succeeded, status.failed
+                );
+            }
+            job.job_status = Some(status);
+            if let Err(err) = workload_service.put_workload(&job).await {
+                error!(
+                    "Failed to update job {} status: {}",
+                    job.id,
+                    err.to_problem().detail
+                );
+                return;
+            }
+        }
+
+        if action == JobAction::CreateInstance {
+            match instance_service
+                .create_instance(
+                    InstanceDTO {
+                        workload_name: job.name.clone(),
+                    },
+                    &job.namespace,
+                    None,
+                )
+                .await
+            {
+                Ok(instance) => info!("Job {} started instance {}", job.id, instance.id),
+                Err(err) => error!(
+                    "Failed to start an instance of job {}: {}",
+                    job.id,
+                    err.to_problem().detail
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+
+    fn instance(id: &str, state: InstanceState, finished_at: Option<u64>) -> Instance {
+        Instance {
+            finished_at,
+            kind: WorkloadKind::Job,
+            ..test_instance(id, state)
+        }
+    }
+
+    fn spec(completions: u32, backoff_limit: u32) -> JobSpec {
+        JobSpec {
+            completions,
+            backoff_limit,
+        }
+    }
+
+    #[test]
+    fn test_backoff_seconds() {
+        assert_eq!(backoff_seconds(0), 0);
+        assert_eq!(backoff_seconds(1), 10);
+        assert_eq!(backoff_seconds(3), 40);
+        assert_eq!(backoff_seconds(20), 360);
+    }
+
+    #[test]
+    fn test_sync_job_completes() {
+        let spec = spec(2, 1);
+
+        let (status, action) = sync_job(&spec, &JobStatus::default(), &[], 100);
+        assert_eq!(action, JobAction::CreateInstance);
+
+        let running = [instance("1", InstanceState::Running, None)];
+        let (status, action) = sync_job(&spec, &status, &running, 100);
+        assert_eq!(action, JobAction::Wait);
+
+        let done = [instance("1", InstanceState::Terminated, Some(100))];
+        let (status, action) = sync_job(&spec, &status, &done, 100);
+        assert_eq!((status.succeeded, action), (1, JobAction::CreateInstance));
+
+        // an instance is only counted once
+        let done = [
+            instance("1", InstanceState::Terminated, Some(100)),
+            instance("2", InstanceState::Terminated, Some(110)),
+        ];
+        let (status, action) = sync_job(&spec, &status, &done, 110);
+        assert_eq!(status.succeeded, 2);
+        assert_eq!(status.state, JobState::Complete);
+        assert_eq!(action, JobAction::Wait);
+    }
+
+    #[test]
+    fn test_sync_job_fails_after_backoff_limit() {
+        let spec = spec(1, 1);
+
+        let failed = [instance("1", InstanceState::Failed, Some(100))];
+        let (status, action) = sync_job(&spec, &JobStatus::default(), &failed, 105);
+        assert_eq!((status.failed, action), (1, JobAction::Wait));
+
+        // the next instance is created once the backoff elapsed
+        let (status, action) = sync_job(&spec, &status, &failed, 110);
+        assert_eq!(action, JobAction::CreateInstance);
+
+        let failed = [
+            instance("1", InstanceState::Failed, Some(100)),
+            instance("2", InstanceState::Crashed, Some(120)),
+        ];
+        let (status, action) = sync_job(&spec, &status, &failed, 200);
+        assert_eq!(status.failed, 2);
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!(action, JobAction::Wait);
+    }
+}
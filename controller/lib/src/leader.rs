@@ -0,0 +1,244 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use etcd_client::{LeaseKeepAliveStream, LeaseKeeper};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::etcd::EtcdClient;
+use crate::tasks::BackgroundTasks;
+
+/// `LeaderElectionConfig` is the configuration of the election of the controller running the
+/// background loops, among the replicas sharing the same etcd.
+///
+/// Properties:
+///
+/// * `enabled`: If false, the controller runs the background loops without being elected.
+/// * `name`: The name of the election, shared by the replicas.
+/// * `lease_ttl_seconds`: The delay after which the leader is replaced if it stops responding.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeaderElectionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_name")]
+    pub name: String,
+    #[serde(default = "default_lease_ttl_seconds")]
+    pub lease_ttl_seconds: u64,
+}
+
+fn default_name() -> String {
+    "kudo-controller".to_string()
+}
+
+fn default_lease_ttl_seconds() -> u64 {
+    15
+}
+
+impl Default for LeaderElectionConfig {
+    fn default() -> Self {
+        LeaderElectionConfig {
+            enabled: false,
+            name: default_name(),
+            lease_ttl_seconds: default_lease_ttl_seconds(),
+        }
+    }
+}
+
+/// Returns the delay between two keep alives of a lease expiring after `ttl_seconds`, a third of
+/// it so a lost keep alive doesn't expire the lease.
+pub fn keep_alive_interval(ttl_seconds: u64) -> Duration {
+    Duration::from_secs((ttl_seconds / 3).max(1))
+}
+
+/// Returns when a leader whose lease expiring after `ttl_seconds` was last renewed at
+/// `last_renewal` steps down, a fifth of the lease before it expires so that it stops leading
+/// before another replica can be elected.
+pub fn step_down_deadline(last_renewal: Instant, ttl_seconds: u64) -> Instant {
+    let ttl = Duration::from_secs(ttl_seconds);
+    last_renewal + ttl - ttl / 5
+}
+
+/// Returns the value identifying a replica in the election, its host name followed by a random
+/// suffix so two replicas on the same host can be told apart.
+pub fn candidate_identity(hostname: Option<String>) -> String {
+    format!(
+        "{}-{}",
+        hostname.unwrap_or_else(|| "controller".to_string()),
+        &uuid::Uuid::new_v4().simple().to_string()[..8]
+    )
+}
+
+/// `LeaderElection` elects the replica of the controller running the background loops: each
+/// replica campaigns with a lease kept alive in etcd, the elected one leads until its lease
+/// expires or it shuts down. Every replica serves the API.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `background_tasks`: The tasks the election runs in.
+pub struct LeaderElection {
+    etcd_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl LeaderElection {
+    pub fn new(etcd_address: SocketAddr, background_tasks: &BackgroundTasks) -> Self {
+        LeaderElection {
+            etcd_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the election in `background_tasks`, the lease is revoked when the controller shuts
+    /// down so another replica takes over right away.
+    ///
+    /// Returns the leadership of this replica, always `true` if the election is disabled.
+    pub fn start(self, config: &LeaderElectionConfig) -> watch::Receiver<bool> {
+        if !config.enabled {
+            info!("Leader election disabled, running the background loops");
+            return watch::channel(true).1;
+        }
+        let (elected, leader) = watch::channel(false);
+        let config = config.clone();
+        let identity = candidate_identity(std::env::var("HOSTNAME").ok());
+        let retry_delay = keep_alive_interval(config.lease_ttl_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                info!("Campaigning as {} in election {}", identity, config.name);
+                loop {
+                    match self.lead(&config, &identity, &elected, &mut shutdown).await {
+                        Ok(()) => break,
+                        Err(err) => warn!("Leader election failed, campaigning again: {}", err),
+                    }
+                    tokio::select! {
+                        _ = tokio::time::sleep(retry_delay) => {}
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Leader election stopped");
+            },
+        );
+        leader
+    }
+
+    /// Campaigns with a new lease and keeps it alive until the controller shuts down, `elected`
+    /// is set while this replica leads.
+    async fn lead(
+        &self,
+        config: &LeaderElectionConfig,
+        identity: &str,
+        elected: &watch::Sender<bool>,
+        shutdown: &mut watch::Receiver<bool>,
+    ) -> Result<(), String> {
+        let mut etcd_client = EtcdClient::new(self.etcd_address.to_string())
+            .await
+            .map_err(|err| err.to_string())?;
+        let ttl = config.lease_ttl_seconds.max(1);
+        let mut last_renewal = Instant::now();
+        let lease = etcd_client
+            .grant_lease(ttl as i64)
+            .await
+            .map_err(|err| err.to_string())?;
+        let (mut keeper, mut responses) = etcd_client
+            .keep_lease_alive(lease)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        let mut candidate = etcd_client.clone();
+        let campaign = candidate.campaign(&config.name, identity, lease);
+        tokio::pin!(campaign);
+        let mut campaigning = true;
+        let interval = keep_alive_interval(ttl);
+        let mut ticker = tokio::time::interval(interval);
+
+        let result = loop {
+            let deadline = step_down_deadline(last_renewal, ttl);
+            tokio::select! {
+                result = &mut campaign, if campaigning => {
+                    campaigning = false;
+                    match result {
+                        Ok(()) => {
+                            info!(
+                                "Elected leader of {}, running the background loops",
+                                config.name
+                            );
+                            elected.send_replace(true);
+                        }
+                        Err(err) => break Err(err.to_string()),
+                    }
+                }
+                _ = ticker.tick() => {
+                    // the lease is renewed at the earliest when the keep alive is sent
+                    let sent = Instant::now();
+                    let timeout = interval.min(deadline.saturating_duration_since(sent));
+                    if let Err(err) = keep_alive(&mut keeper, &mut responses, timeout).await {
+                        break Err(err);
+                    }
+                    last_renewal = sent;
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    break Err(format!("lease {} not renewed in time", lease));
+                }
+                Ok(()) = shutdown.changed() => break Ok(()),
+            }
+        };
+
+        if elected.send_replace(false) {
+            info!("No longer leader of {}", config.name);
+        }
+        // the lease expires by itself if etcd can't be reached
+        if let Err(err) = etcd_client.revoke_lease(lease).await {
+            warn!("Failed to revoke the lease {}: {}", lease, err);
+        }
+        result
+    }
+}
+
+/// Keeps the lease alive once, fails if etcd doesn't answer within `timeout` or if the lease
+/// already expired.
+async fn keep_alive(
+    keeper: &mut LeaseKeeper,
+    responses: &mut LeaseKeepAliveStream,
+    timeout: Duration,
+) -> Result<(), String> {
+    keeper.keep_alive().await.map_err(|err| err.to_string())?;
+    match tokio::time::timeout(timeout, responses.message()).await {
+        Ok(Ok(Some(response))) if response.ttl() > 0 => Ok(()),
+        Ok(Ok(_)) => Err(format!("lease {} expired", keeper.id())),
+        Ok(Err(err)) => Err(err.to_string()),
+        Err(_) => Err(format!("lease {} not kept alive in time", keeper.id())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_alive_interval() {
+        assert_eq!(keep_alive_interval(15), Duration::from_secs(5));
+        assert_eq!(keep_alive_interval(1), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_step_down_deadline() {
+        let now = Instant::now();
+        assert_eq!(step_down_deadline(now, 15), now + Duration::from_secs(12));
+        // a leader steps down before another replica is elected once its lease expires
+        assert!(step_down_deadline(now, 1) < now + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_candidate_identity() {
+        let identity = candidate_identity(Some("controller-0".to_string()));
+        assert!(identity.starts_with("controller-0-"));
+        assert_ne!(
+            identity,
+            candidate_identity(Some("controller-0".to_string()))
+        );
+        assert!(candidate_identity(None).starts_with("controller-"));
+    }
+}
@@ -1,4 +1,23 @@
+pub mod admission;
+pub mod alerting;
+pub mod backup;
+pub mod canary;
+pub mod cron;
+pub mod daemon;
+pub mod dependency;
+pub mod disruption;
 pub mod etcd;
 pub mod external_api;
+pub mod gc;
 pub mod grpc_client;
 pub mod internal_api;
+pub mod ipam;
+pub mod job;
+pub mod leader;
+pub mod maintenance;
+pub mod notification;
+pub mod reconciler;
+pub mod rollout;
+pub mod stateful;
+pub mod tasks;
+pub mod usage;
@@ -0,0 +1,301 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::generic::model::Pagination;
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::maintenance::model::{
+    MaintenancePhase, MaintenanceStatus, MaintenanceWindow,
+};
+use crate::external_api::maintenance::service::MaintenanceService;
+use crate::tasks::BackgroundTasks;
+
+/// `MaintenanceConfig` is the configuration of the maintenance controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+/// * `drain_seconds`: How long before the start of a maintenance window its node is drained.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MaintenanceConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_drain_seconds")]
+    pub drain_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    30
+}
+
+fn default_drain_seconds() -> u64 {
+    300
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        MaintenanceConfig {
+            interval_seconds: default_interval_seconds(),
+            drain_seconds: default_drain_seconds(),
+        }
+    }
+}
+
+/// Returns the instances moved off a node being drained: the ones running or starting on it,
+/// except the pinned ones which can't run elsewhere and the blocked ones not placed yet.
+pub fn instances_to_drain<'a>(node_id: &str, instances: &'a [Instance]) -> Vec<&'a Instance> {
+    instances
+        .iter()
+        .filter(|instance| {
+            instance.node_id == node_id
+                && !instance.is_pinned()
+                && !instance.status.state.is_finished()
+                && instance.status.state != InstanceState::Blocked
+        })
+        .collect()
+}
+
+/// `MaintenanceController` periodically prepares the nodes for their maintenance windows: a node
+/// is cordoned and its instances moved to the other nodes shortly before its window starts, and
+/// it is uncordoned once the window is over.
+///
+/// The schedulers keep the cordoned nodes in memory, so the nodes in maintenance are cordoned
+/// again on every pass in case a scheduler restarted.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the moved instances.
+pub struct MaintenanceController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl MaintenanceController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        MaintenanceController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the maintenance controller in `background_tasks`, it stops when the controller
+    /// shuts down.
+    pub fn start(self, config: &MaintenanceConfig) {
+        if config.interval_seconds == 0 {
+            info!("Maintenance controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+        let drain_seconds = config.drain_seconds;
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync(drain_seconds).await {
+                                warn!("Maintenance synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Maintenance controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every maintenance window.
+    async fn sync(&self, drain_seconds: u64) -> Result<(), String> {
+        let mut maintenance_service =
+            MaintenanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?;
+        let windows = maintenance_service
+            .get_all_windows(&Pagination::default())
+            .await
+            .windows;
+        if windows.is_empty() {
+            return Ok(());
+        }
+        debug!("Synchronizing {} maintenance window(s)", windows.len());
+
+        let now = unix_time();
+        for window in windows {
+            let result = match window.phase(now, drain_seconds) {
+                MaintenancePhase::Upcoming if window.status == MaintenanceStatus::Drained => {
+                    self.undrain(&mut maintenance_service, window).await
+                }
+                MaintenancePhase::Upcoming => Ok(()),
+                MaintenancePhase::Drain => self.drain(&mut maintenance_service, window).await,
+                MaintenancePhase::Over => {
+                    let node_id = window.node_id.clone();
+                    match maintenance_service.delete_window(&node_id).await {
+                        Ok(()) => {
+                            info!("Maintenance of node {} is over, uncordoned it", node_id);
+                            Ok(())
+                        }
+                        Err(err) => Err(err.to_problem().detail),
+                    }
+                }
+            };
+            if let Err(err) = result {
+                error!("Failed to prepare the maintenance of a node: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Cordons the node of a window and moves its instances to the other nodes, they are moved
+    /// once: later passes only cordon the node again. The moves breaking the disruption budget
+    /// of their workload are left to the next passes, the node is drained once none is left.
+    async fn drain(
+        &self,
+        maintenance_service: &mut MaintenanceService,
+        window: MaintenanceWindow,
+    ) -> Result<(), String> {
+        maintenance_service
+            .cordon(&window.node_id, true)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        if window.status == MaintenanceStatus::Drained {
+            return Ok(());
+        }
+
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        let instances = instance_service.get_instances_of_all_namespaces().await;
+        let mut delayed = 0;
+        for instance in instances_to_drain(&window.node_id, &instances) {
+            // the moves already done are read back from etcd, their new instances don't run yet
+            if let Err(err) = instance_service.check_disruption_budget(instance).await {
+                debug!(
+                    "Delayed the move of instance {} off node {}: {}",
+                    instance.id,
+                    window.node_id,
+                    err.to_problem().detail
+                );
+                delayed += 1;
+                continue;
+            }
+            match instance_service
+                .patch_instance(&instance.id, &instance.namespace)
+                .await
+            {
+                Ok(moved) => info!(
+                    "Moved instance {} off node {} before its maintenance, replaced by {}",
+                    instance.id, window.node_id, moved.id
+                ),
+                Err(err) => error!(
+                    "Failed to move instance {} off node {}: {}",
+                    instance.id,
+                    window.node_id,
+                    err.to_problem().detail
+                ),
+            }
+        }
+        if delayed > 0 {
+            info!(
+                "{} instance(s) left on node {}, delayed by their disruption budget",
+                delayed, window.node_id
+            );
+            return Ok(());
+        }
+        info!("Drained node {} before its maintenance", window.node_id);
+        let window = MaintenanceWindow {
+            status: MaintenanceStatus::Drained,
+            ..window
+        };
+        maintenance_service
+            .save_window(&window)
+            .await
+            .map_err(|err| err.to_problem().detail)
+    }
+
+    /// Uncordons the node of a window postponed after its node was drained, its instances
+    /// aren't moved back.
+    async fn undrain(
+        &self,
+        maintenance_service: &mut MaintenanceService,
+        window: MaintenanceWindow,
+    ) -> Result<(), String> {
+        maintenance_service
+            .cordon(&window.node_id, false)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        info!(
+            "Maintenance of node {} was postponed, uncordoned it",
+            window.node_id
+        );
+        let window = MaintenanceWindow {
+            status: MaintenanceStatus::Scheduled,
+            ..window
+        };
+        maintenance_service
+            .save_window(&window)
+            .await
+            .map_err(|err| err.to_problem().detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::WorkloadKind;
+
+    fn instance(id: &str, node_id: &str, state: InstanceState, kind: WorkloadKind) -> Instance {
+        Instance {
+            node_id: node_id.to_string(),
+            kind,
+            ..test_instance(id, state)
+        }
+    }
+
+    #[test]
+    fn test_instances_to_drain() {
+        let instances = vec![
+            instance("a", "node-1", InstanceState::Running, WorkloadKind::Service),
+            instance("b", "node-2", InstanceState::Running, WorkloadKind::Service),
+            instance(
+                "c",
+                "node-1",
+                InstanceState::Starting,
+                WorkloadKind::Service,
+            ),
+            instance("d", "node-1", InstanceState::Terminated, WorkloadKind::Job),
+            instance(
+                "e",
+                "node-1",
+                InstanceState::Running,
+                WorkloadKind::DaemonSet,
+            ),
+            instance("f", "node-1", InstanceState::Blocked, WorkloadKind::Service),
+        ];
+
+        let ids: Vec<&str> = instances_to_drain("node-1", &instances)
+            .iter()
+            .map(|instance| instance.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["a", "c"]);
+    }
+}
@@ -0,0 +1,498 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use proto::scheduler::ClusterSnapshot;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::instance::service::{unix_time, InstanceService};
+use crate::external_api::shard::service::ShardService;
+use crate::external_api::workload::model::{JobState, Workload, WorkloadKind};
+use crate::external_api::workload::service::WorkloadService;
+use crate::tasks::BackgroundTasks;
+
+/// `NotificationConfig` is the configuration of the lifecycle notifier.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the notifier is disabled if 0 or without
+///   webhooks.
+/// * `webhooks`: The endpoints notified of the lifecycle events.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default)]
+    pub webhooks: Vec<NotificationWebhook>,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+fn default_timeout_seconds() -> u64 {
+    5
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        NotificationConfig {
+            interval_seconds: default_interval_seconds(),
+            webhooks: vec![],
+        }
+    }
+}
+
+/// A webhook registered by an operator in the controller configuration, e.g. a Slack incoming
+/// webhook or a CI trigger.
+///
+/// Properties:
+///
+/// * `name`: Name of the webhook, used in the logs.
+/// * `url`: HTTP endpoint receiving each `LifecycleEvent` as JSON (POST).
+/// * `events`: Kinds of events sent to this webhook, every kind if empty.
+/// * `timeout_seconds`: Maximum duration of a request.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct NotificationWebhook {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<EventKind>,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+impl NotificationWebhook {
+    pub fn applies_to(&self, kind: EventKind) -> bool {
+        self.events.is_empty() || self.events.contains(&kind)
+    }
+}
+
+/// Kind of a lifecycle event.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// An instance changed state, e.g. from `Starting` to `Running`
+    InstanceStateChanged,
+    /// A node connected to its scheduler
+    NodeRegistered,
+    /// A node disconnected from its scheduler
+    NodeLost,
+    /// Every instance of a workload runs, or its job completed
+    DeploymentCompleted,
+}
+
+/// Body sent to the webhooks.
+///
+/// Properties:
+///
+/// * `event`: The kind of the event.
+/// * `subject`: What the event is about, e.g. `instance/<id>`, `node/<id>` or
+///   `workload/<namespace>.<name>`.
+/// * `namespace`: The namespace of the instance or the workload, unset for the nodes.
+/// * `message`: A description of the event.
+/// * `timestamp`: When the event was seen, in seconds since the unix epoch.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct LifecycleEvent {
+    pub event: EventKind,
+    pub subject: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+/// An instance as seen by a pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SeenInstance {
+    name: String,
+    namespace: String,
+    state: InstanceState,
+}
+
+/// `LifecycleState` is the state of the cluster seen by a pass, the events are the differences
+/// between two passes.
+///
+/// Properties:
+///
+/// * `instances`: The instances stored in etcd, by id.
+/// * `nodes`: The nodes connected to a scheduler.
+/// * `deployed`: The workloads whose instances all run, or whose job completed, with their
+///   namespace.
+#[derive(Debug, Default)]
+pub struct LifecycleState {
+    instances: HashMap<String, SeenInstance>,
+    nodes: HashSet<String>,
+    deployed: BTreeMap<String, String>,
+}
+
+impl LifecycleState {
+    /// Returns the state of the cluster.
+    ///
+    /// # Arguments:
+    ///
+    /// * `instances`: The instances stored in etcd, in every namespace.
+    /// * `jobs`: The `Job` workloads, in every namespace.
+    /// * `snapshot`: The nodes known by the schedulers.
+    pub fn observe(instances: &[Instance], jobs: &[Workload], snapshot: &ClusterSnapshot) -> Self {
+        let mut workloads: HashMap<&str, (&str, bool)> = HashMap::new();
+        for instance in instances {
+            if instance.kind == WorkloadKind::Job {
+                continue;
+            }
+            let running = instance.status.state == InstanceState::Running;
+            workloads
+                .entry(&instance.workload_id)
+                .and_modify(|(_, all_running)| *all_running &= running)
+                .or_insert((&instance.namespace, running));
+        }
+        let mut deployed: BTreeMap<String, String> = workloads
+            .into_iter()
+            .filter(|(_, (_, all_running))| *all_running)
+            .map(|(id, (namespace, _))| (id.to_string(), namespace.to_string()))
+            .collect();
+        deployed.extend(
+            jobs.iter()
+                .filter(|job| {
+                    job.job_status
+                        .as_ref()
+                        .is_some_and(|status| status.state == JobState::Complete)
+                })
+                .map(|job| (job.id.clone(), job.namespace.clone())),
+        );
+
+        LifecycleState {
+            instances: instances
+                .iter()
+                .map(|instance| {
+                    let seen = SeenInstance {
+                        name: instance.name.clone(),
+                        namespace: instance.namespace.clone(),
+                        state: instance.status.state.clone(),
+                    };
+                    (instance.id.clone(), seen)
+                })
+                .collect(),
+            nodes: snapshot
+                .nodes
+                .iter()
+                .filter(|node| node.connected)
+                .map(|node| node.id.clone())
+                .collect(),
+            deployed,
+        }
+    }
+
+    /// Returns the events which happened between this state and the `next` one, seen at `now`.
+    /// The instances created or deleted in between only raise their state changes.
+    pub fn events(&self, next: &LifecycleState, now: u64) -> Vec<LifecycleEvent> {
+        let event =
+            |event, subject: String, namespace: Option<&str>, message: String| LifecycleEvent {
+                event,
+                subject,
+                namespace: namespace.map(String::from),
+                message,
+                timestamp: now,
+            };
+        let mut events = vec![];
+
+        let mut nodes: Vec<&String> = next.nodes.difference(&self.nodes).collect();
+        nodes.sort();
+        events.extend(nodes.into_iter().map(|node| {
+            event(
+                EventKind::NodeRegistered,
+                format!("node/{}", node),
+                None,
+                format!("Node {} registered", node),
+            )
+        }));
+        let mut nodes: Vec<&String> = self.nodes.difference(&next.nodes).collect();
+        nodes.sort();
+        events.extend(nodes.into_iter().map(|node| {
+            event(
+                EventKind::NodeLost,
+                format!("node/{}", node),
+                None,
+                format!("Node {} lost", node),
+            )
+        }));
+
+        let mut instances: Vec<(&String, &SeenInstance)> = next.instances.iter().collect();
+        instances.sort_by_key(|(id, _)| *id);
+        for (id, instance) in instances {
+            let previous = self.instances.get(id).map(|previous| &previous.state);
+            if previous == Some(&instance.state) {
+                continue;
+            }
+            let message = match previous {
+                Some(previous) => format!(
+                    "Instance {} went from {:?} to {:?}",
+                    instance.name, previous, instance.state
+                ),
+                None => format!("Instance {} created {:?}", instance.name, instance.state),
+            };
+            events.push(event(
+                EventKind::InstanceStateChanged,
+                format!("instance/{}", id),
+                Some(&instance.namespace),
+                message,
+            ));
+        }
+
+        for (workload_id, namespace) in &next.deployed {
+            if !self.deployed.contains_key(workload_id) {
+                events.push(event(
+                    EventKind::DeploymentCompleted,
+                    format!("workload/{}", workload_id),
+                    Some(namespace),
+                    format!("Workload {} deployed", workload_id),
+                ));
+            }
+        }
+        events
+    }
+}
+
+/// `LifecycleNotifier` periodically compares the state of the cluster with the previous pass,
+/// and sends the instance state changes, the node registrations and losses and the completed
+/// deployments to the webhooks of the configuration. The first pass only records the state, the
+/// events which happened while the controller was down are not sent.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks the notifier runs in.
+/// * `previous`: The state seen by the previous pass.
+/// * `client`: The client of the webhooks.
+pub struct LifecycleNotifier {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+    previous: Option<LifecycleState>,
+    client: reqwest::Client,
+}
+
+impl LifecycleNotifier {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        LifecycleNotifier {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+            previous: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Spawns the notifier in `background_tasks`, it stops when the controller shuts down.
+    pub fn start(mut self, config: &NotificationConfig) {
+        if config.interval_seconds == 0 || config.webhooks.is_empty() {
+            info!("Lifecycle notifier disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+        let webhooks = config.webhooks.clone();
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.notify(&webhooks).await {
+                                warn!("Lifecycle notification failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Lifecycle notifier stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass, and sends the events since the previous one.
+    async fn notify(&mut self, webhooks: &[NotificationWebhook]) -> Result<(), String> {
+        let snapshot = ShardService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .cluster_snapshot()
+            .await?;
+        let instances = InstanceService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .get_instances_of_all_namespaces()
+            .await;
+        let jobs = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .get_workloads_of_kind(WorkloadKind::Job)
+            .await;
+
+        let state = LifecycleState::observe(&instances, &jobs, &snapshot);
+        let events = match &self.previous {
+            Some(previous) => previous.events(&state, unix_time()),
+            None => vec![],
+        };
+        self.previous = Some(state);
+        debug!("{} lifecycle event(s)", events.len());
+
+        for event in &events {
+            for webhook in webhooks
+                .iter()
+                .filter(|webhook| webhook.applies_to(event.event))
+            {
+                self.send(webhook, event).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends an event to a webhook, a failure is only logged.
+    async fn send(&self, webhook: &NotificationWebhook, event: &LifecycleEvent) {
+        let result = self
+            .client
+            .post(&webhook.url)
+            .timeout(Duration::from_secs(webhook.timeout_seconds))
+            .json(event)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        if let Err(err) = result {
+            warn!(
+                "Failed to notify webhook {} of {:?} on {}: {}",
+                webhook.name, event.event, event.subject, err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::NodeSnapshot;
+
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::JobStatus;
+
+    fn workload(name: &str, kind: WorkloadKind) -> Workload {
+        Workload {
+            id: format!("default.{}", name),
+            name: name.to_string(),
+            uri: "nginx".to_string(),
+            namespace: "default".to_string(),
+            kind,
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(nodes: &[&str]) -> ClusterSnapshot {
+        ClusterSnapshot {
+            nodes: nodes
+                .iter()
+                .map(|id| NodeSnapshot {
+                    id: id.to_string(),
+                    connected: true,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn kinds(events: &[LifecycleEvent]) -> Vec<(EventKind, &str)> {
+        events
+            .iter()
+            .map(|event| (event.event, event.subject.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_events() {
+        let before = LifecycleState::observe(
+            &[
+                test_instance("1", InstanceState::Running),
+                test_instance("2", InstanceState::Starting),
+            ],
+            &[],
+            &snapshot(&["a", "b"]),
+        );
+        let after = LifecycleState::observe(
+            &[
+                test_instance("1", InstanceState::Running),
+                test_instance("2", InstanceState::Running),
+            ],
+            &[],
+            &snapshot(&["a", "c"]),
+        );
+
+        let events = before.events(&after, 100);
+        assert_eq!(
+            kinds(&events),
+            vec![
+                (EventKind::NodeRegistered, "node/c"),
+                (EventKind::NodeLost, "node/b"),
+                (EventKind::InstanceStateChanged, "instance/2"),
+                (EventKind::DeploymentCompleted, "workload/default.web"),
+            ]
+        );
+        assert_eq!(
+            events[2].message,
+            "Instance web-2 went from Starting to Running"
+        );
+        assert_eq!(events[2].namespace.as_deref(), Some("default"));
+        assert_eq!(events[0].namespace, None);
+
+        // nothing changed
+        assert!(after
+            .events(
+                &LifecycleState::observe(
+                    &[
+                        test_instance("1", InstanceState::Running),
+                        test_instance("2", InstanceState::Running),
+                    ],
+                    &[],
+                    &snapshot(&["a", "c"]),
+                ),
+                200
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn test_job_completed() {
+        let mut job = workload("migrate", WorkloadKind::Job);
+        let before = LifecycleState::observe(&[], &[job.clone()], &snapshot(&[]));
+        job.job_status = Some(JobStatus {
+            state: JobState::Complete,
+            ..Default::default()
+        });
+        let after = LifecycleState::observe(&[], &[job], &snapshot(&[]));
+
+        assert_eq!(
+            kinds(&before.events(&after, 100)),
+            vec![(EventKind::DeploymentCompleted, "workload/default.migrate")]
+        );
+    }
+
+    #[test]
+    fn test_webhook_filter() {
+        let webhook: NotificationWebhook = serde_json::from_str(
+            r#"{"name": "ci", "url": "http://ci.local/hook", "events": ["deployment_completed"]}"#,
+        )
+        .unwrap();
+        assert_eq!(webhook.timeout_seconds, 5);
+        assert!(webhook.applies_to(EventKind::DeploymentCompleted));
+        assert!(!webhook.applies_to(EventKind::NodeLost));
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use proto::scheduler::ClusterSnapshot;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::{Instance, InstanceState, InstanceStatus};
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::shard::service::ShardService;
+use crate::tasks::BackgroundTasks;
+
+/// `ReconcilerConfig` is the configuration of the reconciliation loop.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the loop is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReconcilerConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    30
+}
+
+impl Default for ReconcilerConfig {
+    fn default() -> Self {
+        ReconcilerConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// `Suspects` keeps the findings of the previous pass of a loop. The state of etcd and of the
+/// scheduler are not read atomically, e.g. an instance being created may be seen in etcd before
+/// the scheduler knows it, so a finding is only acted upon when two consecutive passes agree.
+#[derive(Debug, Default)]
+pub struct Suspects {
+    keys: HashSet<String>,
+}
+
+impl Suspects {
+    /// Keeps the findings already found by the previous pass, the others are remembered for the
+    /// next one.
+    pub fn confirm<T>(&mut self, findings: Vec<T>, key: impl Fn(&T) -> String) -> Vec<T> {
+        let previous = std::mem::take(&mut self.keys);
+        self.keys = findings.iter().map(&key).collect();
+
+        findings
+            .into_iter()
+            .filter(|finding| previous.contains(&key(finding)))
+            .collect()
+    }
+}
+
+/// Returns `true` if the instance is expected to be known by the scheduler.
+fn should_run(instance: &Instance) -> bool {
+    matches!(
+        instance.status.state,
+        InstanceState::Running
+            | InstanceState::Starting
+            | InstanceState::Scheduling
+            | InstanceState::Scheduled
+            | InstanceState::Pulling
+    )
+}
+
+/// Compares the instances stored in etcd with the state of the scheduler.
+///
+/// # Arguments:
+///
+/// * `instances`: The instances stored in etcd, in every namespace.
+/// * `snapshot`: The placements and the pending instances of the scheduler.
+///
+/// # Returns:
+///
+/// The instances which should run but are unknown to the scheduler. The instances pinned to a
+/// disconnected node are left until the node comes back or their controller deletes them.
+pub fn lost_instances(instances: &[Instance], snapshot: &ClusterSnapshot) -> Vec<Instance> {
+    let known = known_instances(snapshot);
+
+    instances
+        .iter()
+        .filter(|instance| should_run(instance) && !known.contains(instance.id.as_str()))
+        .filter(|instance| {
+            !instance.is_pinned()
+                || snapshot
+                    .nodes
+                    .iter()
+                    .any(|node| node.id == instance.node_id && node.connected)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Returns the ids of the instances placed or pending in the scheduler.
+pub fn known_instances(snapshot: &ClusterSnapshot) -> HashSet<&str> {
+    snapshot
+        .placements
+        .iter()
+        .chain(snapshot.pending.iter())
+        .map(|placement| placement.instance_id.as_str())
+        .collect()
+}
+
+/// `Reconciler` periodically schedules again the instances lost by the scheduler, e.g. after the
+/// scheduler restarted. The instances run by the scheduler but missing from etcd are left to the
+/// garbage collector.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the re-created instances.
+/// * `suspects`: The lost instances found by the previous pass.
+pub struct Reconciler {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+    suspects: Suspects,
+}
+
+impl Reconciler {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        Reconciler {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+            suspects: Suspects::default(),
+        }
+    }
+
+    /// Spawns the reconciliation loop in `background_tasks`, it stops when the controller shuts
+    /// down.
+    pub fn start(mut self, config: &ReconcilerConfig) {
+        if config.interval_seconds == 0 {
+            info!("Reconciliation loop disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.reconcile().await {
+                                warn!("Reconciliation failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Reconciliation loop stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass: the lost instances confirmed by this pass are scheduled again.
+    async fn reconcile(&mut self) -> Result<(), String> {
+        let snapshot = ShardService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .cluster_snapshot()
+            .await?;
+
+        let instances = InstanceService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .get_instances_of_all_namespaces()
+            .await;
+
+        let lost = self
+            .suspects
+            .confirm(lost_instances(&instances, &snapshot), |instance| {
+                instance.id.clone()
+            });
+        debug!("Reconciliation found {} lost instance(s)", lost.len());
+
+        for instance in lost {
+            self.reschedule(instance).await;
+        }
+        Ok(())
+    }
+
+    /// Schedules again an instance lost by the scheduler, keeping its id.
+    async fn reschedule(&self, mut instance: Instance) {
+        warn!(
+            "Scheduling again instance {} unknown to the scheduler",
+            instance.id
+        );
+
+        let result = async {
+            let mut instance_service =
+                InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                    .await?
+                    .with_background_tasks(&self.background_tasks);
+
+            instance.status = InstanceStatus {
+                state: InstanceState::Scheduling,
+                status_description: "Rescheduled by the reconciler".to_string(),
+            };
+            if !instance.is_pinned() {
+                instance.node_id = String::new();
+            }
+            instance_service.put_instance(&instance).await?;
+            instance_service.schedule_instance(&instance).await
+        }
+        .await;
+
+        if let Err(err) = result {
+            error!(
+                "Failed to schedule again instance {}: {}",
+                instance.id,
+                err.to_problem().detail
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{InstancePlacement, NodeSnapshot};
+
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::WorkloadKind;
+
+    fn placement(id: &str) -> InstancePlacement {
+        InstancePlacement {
+            instance_id: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lost_instances() {
+        let instances = vec![
+            test_instance("placed", InstanceState::Running),
+            test_instance("pending", InstanceState::Scheduling),
+            test_instance("lost", InstanceState::Running),
+            test_instance("stopped", InstanceState::Stopped),
+        ];
+        let snapshot = ClusterSnapshot {
+            nodes: vec![],
+            placements: vec![placement("placed"), placement("orphan")],
+            pending: vec![placement("pending")],
+        };
+
+        let lost: Vec<String> = lost_instances(&instances, &snapshot)
+            .into_iter()
+            .map(|instance| instance.id)
+            .collect();
+        assert_eq!(lost, vec!["lost"]);
+    }
+
+    #[test]
+    fn test_lost_daemon_instances() {
+        let mut instances = vec![
+            test_instance("connected", InstanceState::Running),
+            test_instance("disconnected", InstanceState::Running),
+        ];
+        for (instance, node_id) in instances.iter_mut().zip(["a", "b"]) {
+            instance.kind = WorkloadKind::DaemonSet;
+            instance.node_id = node_id.to_string();
+        }
+        let snapshot = ClusterSnapshot {
+            nodes: vec![
+                NodeSnapshot {
+                    id: "a".to_string(),
+                    connected: true,
+                    ..Default::default()
+                },
+                NodeSnapshot {
+                    id: "b".to_string(),
+                    connected: false,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let lost: Vec<String> = lost_instances(&instances, &snapshot)
+            .into_iter()
+            .map(|instance| instance.id)
+            .collect();
+        assert_eq!(lost, vec!["connected"]);
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut suspects = Suspects::default();
+
+        let first = suspects.confirm(vec!["a"], |id| id.to_string());
+        assert!(first.is_empty());
+
+        let second = suspects.confirm(vec!["a", "b"], |id| id.to_string());
+        assert_eq!(second, vec!["a"]);
+
+        let third = suspects.confirm(vec!["a"], |id| id.to_string());
+        assert_eq!(third, vec!["a"]);
+    }
+}
@@ -0,0 +1,287 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::disruption;
+use crate::external_api::generic::model::Versioned;
+use crate::external_api::instance::model::{Instance, InstanceState};
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::workload::model::Workload;
+use crate::external_api::workload::service::WorkloadService;
+use crate::tasks::BackgroundTasks;
+
+/// `RolloutConfig` is the configuration of the rollout controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RolloutConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+impl Default for RolloutConfig {
+    fn default() -> Self {
+        RolloutConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// What the rollout controller does for a workload being updated, a single instance is replaced
+/// per pass.
+#[derive(Debug)]
+pub enum RolloutAction {
+    /// Nothing until the next pass
+    Wait,
+    /// Every instance runs the revision of the workload
+    Done,
+    /// Re-create the instance from the definition of the workload
+    Replace(Box<Instance>),
+}
+
+/// Decides which instance of a workload is replaced by one created from its revision, once all
+/// the instances run and if its disruption budget allows to stop one of them. The instances of
+/// a workload with a canary are left to the canary controller.
+///
+/// # Arguments:
+///
+/// * `workload`: The workload being updated.
+/// * `instances`: The instances of the workload stored in etcd.
+pub fn plan_rollout(workload: &Workload, instances: &[Instance]) -> RolloutAction {
+    if workload.canary.is_some() {
+        return RolloutAction::Wait;
+    }
+    let mut active: Vec<&Instance> = instances
+        .iter()
+        .filter(|instance| !instance.status.state.is_finished())
+        .collect();
+    active.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let Some(outdated) = active
+        .iter()
+        .find(|instance| instance.revision != workload.revision)
+    else {
+        return RolloutAction::Done;
+    };
+    if active
+        .iter()
+        .any(|instance| instance.status.state != InstanceState::Running)
+    {
+        return RolloutAction::Wait;
+    }
+    if disruption::allows_disruption(workload.disruption_budget.as_ref(), outdated, instances) {
+        RolloutAction::Replace(Box::new((*outdated).clone()))
+    } else {
+        RolloutAction::Wait
+    }
+}
+
+/// `RolloutController` periodically replaces the instances of the updated workloads created from
+/// a previous revision, one at a time, so that the workload keeps running during its update or
+/// its rollback.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the created instances.
+pub struct RolloutController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl RolloutController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        RolloutController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the rollout controller in `background_tasks`, it stops when the controller shuts
+    /// down.
+    pub fn start(self, config: &RolloutConfig) {
+        if config.interval_seconds == 0 {
+            info!("Rollout controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Rollout synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Rollout controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every workload being updated.
+    async fn sync(&self) -> Result<(), String> {
+        let mut workload_service = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let workloads = workload_service.get_rollout_workloads().await;
+        if workloads.is_empty() {
+            return Ok(());
+        }
+
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        debug!("Synchronizing {} rollout(s)", workloads.len());
+
+        for Versioned {
+            resource: workload,
+            resource_version,
+        } in workloads
+        {
+            let instances = instance_service.get_instances_of_workload(&workload).await;
+            match plan_rollout(&workload, &instances) {
+                RolloutAction::Wait => {}
+                RolloutAction::Done => {
+                    info!(
+                        "Workload {} runs its revision {}",
+                        workload.id, workload.revision
+                    );
+                    if let Err(err) = workload_service
+                        .finish_rollout(&workload.id, resource_version)
+                        .await
+                    {
+                        error!(
+                            "Failed to end the rollout of workload {}: {}",
+                            workload.id,
+                            err.to_problem().detail
+                        );
+                    }
+                }
+                RolloutAction::Replace(instance) => {
+                    info!(
+                        "Revision {} of workload {} replaces instance {}",
+                        workload.revision, workload.id, instance.id
+                    );
+                    if let Err(err) = instance_service
+                        .patch_instance(&instance.id, &instance.namespace)
+                        .await
+                    {
+                        error!(
+                            "Failed to replace instance {} of workload {}: {}",
+                            instance.id,
+                            workload.id,
+                            err.to_problem().detail
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::{Canary, DisruptionBudget};
+
+    fn workload(revision: u64) -> Workload {
+        Workload {
+            id: "default.web".to_string(),
+            name: "web".to_string(),
+            uri: "nginx:1.23".to_string(),
+            namespace: "default".to_string(),
+            revision,
+            ..Default::default()
+        }
+    }
+
+    fn instance(id: &str, state: InstanceState, revision: u64) -> Instance {
+        Instance {
+            revision,
+            ..test_instance(id, state)
+        }
+    }
+
+    fn step(workload: &Workload, instances: &[Instance]) -> String {
+        match plan_rollout(workload, instances) {
+            RolloutAction::Wait => "wait".to_string(),
+            RolloutAction::Done => "done".to_string(),
+            RolloutAction::Replace(instance) => format!("replace {}", instance.id),
+        }
+    }
+
+    #[test]
+    fn test_plan_rollout() {
+        let running = |id, revision| instance(id, InstanceState::Running, revision);
+
+        let instances = [running("a", 1), running("b", 1)];
+        assert_eq!(step(&workload(2), &instances), "replace a");
+        assert_eq!(step(&workload(1), &instances), "done");
+
+        // a rollback is a new revision, its instances are replaced as well
+        let instances = [running("a", 3), running("b", 2)];
+        assert_eq!(step(&workload(3), &instances), "replace b");
+
+        // the replacement runs before the next instance is replaced
+        let instances = [instance("a", InstanceState::Starting, 2), running("b", 1)];
+        assert_eq!(step(&workload(2), &instances), "wait");
+
+        // the finished instances are not replaced
+        let instances = [instance("a", InstanceState::Crashed, 1), running("b", 2)];
+        assert_eq!(step(&workload(2), &instances), "done");
+    }
+
+    #[test]
+    fn test_plan_rollout_respects_disruption_budget() {
+        let running = |id, revision| instance(id, InstanceState::Running, revision);
+        let mut updated = workload(2);
+        updated.disruption_budget = Some(DisruptionBudget { min_available: 2 });
+
+        let instances = [running("a", 1), running("b", 1)];
+        assert_eq!(step(&updated, &instances), "wait");
+
+        let instances = [running("a", 1), running("b", 1), running("c", 1)];
+        assert_eq!(step(&updated, &instances), "replace a");
+    }
+
+    #[test]
+    fn test_plan_rollout_leaves_canary() {
+        let mut updated = workload(2);
+        updated.canary = Some(Canary {
+            percentage: 50,
+            version: Box::new(workload(0)),
+        });
+
+        let instances = [instance("a", InstanceState::Running, 1)];
+        assert_eq!(step(&updated, &instances), "wait");
+    }
+}
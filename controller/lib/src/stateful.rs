@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::model::{parse_ordinal, Instance, InstanceState};
+use crate::external_api::instance::service::InstanceService;
+use crate::external_api::workload::model::{Workload, WorkloadKind};
+use crate::external_api::workload::service::WorkloadService;
+use crate::tasks::BackgroundTasks;
+
+/// `StatefulConfig` is the configuration of the stateful set controller.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two passes, the controller is disabled if 0.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatefulConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    10
+}
+
+impl Default for StatefulConfig {
+    fn default() -> Self {
+        StatefulConfig {
+            interval_seconds: default_interval_seconds(),
+        }
+    }
+}
+
+/// What the stateful set controller does for a `StatefulSet` workload, a single step per pass.
+#[derive(Debug)]
+pub enum StatefulAction {
+    /// Nothing until the next pass
+    Wait,
+    /// Create the instance with this ordinal
+    Create(u32),
+    /// Delete an instance, a duplicate, one without a stable name or one scaled down
+    Delete(Instance),
+    /// Delete a finished instance and create it again with the same ordinal
+    Replace(Instance),
+}
+
+/// Decides the next step of a `StatefulSet` workload. The instances are started in the order of
+/// their ordinals, each one once the previous ones run, and terminated in the reverse order.
+///
+/// # Arguments:
+///
+/// * `workload_name`: The name of the `StatefulSet` workload.
+/// * `replicas`: The number of instances wanted.
+/// * `instances`: The instances of the workload stored in etcd.
+pub fn plan_stateful(workload_name: &str, replicas: u32, instances: &[Instance]) -> StatefulAction {
+    let mut sorted: Vec<&Instance> = instances.iter().collect();
+    // the instance kept for an ordinal is the first one still running
+    sorted.sort_by_key(|instance| (instance.status.state.is_finished(), instance.id.as_str()));
+
+    let mut by_ordinal = BTreeMap::new();
+    for instance in sorted {
+        let Some(ordinal) = parse_ordinal(workload_name, &instance.name) else {
+            return StatefulAction::Delete(instance.clone());
+        };
+        if by_ordinal.insert(ordinal, instance).is_some() {
+            return StatefulAction::Delete(instance.clone());
+        }
+    }
+
+    for ordinal in 0..replicas {
+        match by_ordinal.get(&ordinal) {
+            None => return StatefulAction::Create(ordinal),
+            Some(instance) if instance.status.state.is_finished() => {
+                return StatefulAction::Replace((*instance).clone())
+            }
+            Some(instance) if instance.status.state != InstanceState::Running => {
+                return StatefulAction::Wait
+            }
+            Some(_) => {}
+        }
+    }
+
+    match by_ordinal.range(replicas..).next_back() {
+        Some((_, instance)) => StatefulAction::Delete((*instance).clone()),
+        None => StatefulAction::Wait,
+    }
+}
+
+/// Returns the names holding an address which no instance nor `StatefulSet` workload uses
+/// anymore, the workload was deleted or scaled down.
+///
+/// # Arguments:
+///
+/// * `sticky_names`: The namespace and the name of the instances holding an address.
+/// * `workloads`: The `StatefulSet` workloads of every namespace.
+/// * `instances`: The instances of every namespace.
+pub fn orphan_sticky_names(
+    sticky_names: &[(String, String)],
+    workloads: &[Workload],
+    instances: &[Instance],
+) -> Vec<(String, String)> {
+    sticky_names
+        .iter()
+        .filter(|(namespace, name)| {
+            let wanted = workloads.iter().any(|workload| {
+                workload.namespace == *namespace
+                    && parse_ordinal(&workload.name, name).is_some_and(|ordinal| {
+                        ordinal < workload.stateful.clone().unwrap_or_default().replicas
+                    })
+            });
+            let used = instances
+                .iter()
+                .any(|instance| instance.namespace == *namespace && instance.name == *name);
+            !wanted && !used
+        })
+        .cloned()
+        .collect()
+}
+
+/// `StatefulSetController` periodically brings the instances of each `StatefulSet` workload to
+/// its replicas, one step at a time. Each instance keeps its name and its address when it is
+/// replaced, and its node if it has a volume, the node holding a volume is recorded in the
+/// status of the workload.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks writing the status of the created instances.
+pub struct StatefulSetController {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl StatefulSetController {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        StatefulSetController {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the stateful set controller in `background_tasks`, it stops when the controller
+    /// shuts down.
+    pub fn start(self, config: &StatefulConfig) {
+        if config.interval_seconds == 0 {
+            info!("Stateful set controller disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.sync().await {
+                                warn!("Stateful set synchronization failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Stateful set controller stopped");
+            },
+        );
+    }
+
+    /// Runs a single pass over every `StatefulSet` workload, then releases the addresses no
+    /// longer used.
+    async fn sync(&self) -> Result<(), String> {
+        let mut workload_service = WorkloadService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        let workloads = workload_service
+            .get_workloads_of_kind(WorkloadKind::StatefulSet)
+            .await;
+
+        let mut instance_service =
+            InstanceService::new(&self.etcd_address, &self.scheduler_address)
+                .await
+                .map_err(|err| err.to_problem().detail)?
+                .with_background_tasks(&self.background_tasks);
+        let sticky_names = instance_service
+            .sticky_names()
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        if workloads.is_empty() && sticky_names.is_empty() {
+            return Ok(());
+        }
+
+        let instances = instance_service.get_instances_of_all_namespaces().await;
+        debug!("Synchronizing {} stateful set(s)", workloads.len());
+
+        for workload in workloads.clone() {
+            let owned: Vec<Instance> = instances
+                .iter()
+                .filter(|instance| instance.workload_id == workload.id)
+                .cloned()
+                .collect();
+            self.sync_one(
+                workload,
+                &owned,
+                &mut workload_service,
+                &mut instance_service,
+            )
+            .await;
+        }
+
+        for (namespace, name) in orphan_sticky_names(&sticky_names, &workloads, &instances) {
+            info!("Releasing the address of instance {}.{}", namespace, name);
+            instance_service
+                .release_sticky_address(&namespace, &name)
+                .await;
+        }
+        Ok(())
+    }
+
+    /// Records the nodes holding the volumes of a `StatefulSet` and runs its next step.
+    async fn sync_one(
+        &self,
+        mut workload: Workload,
+        instances: &[Instance],
+        workload_service: &mut WorkloadService,
+        instance_service: &mut InstanceService,
+    ) {
+        let spec = workload.stateful.clone().unwrap_or_default();
+        let mut status = workload.stateful_status.clone().unwrap_or_default();
+        for instance in instances {
+            if instance.volumes.is_empty() || instance.node_id.is_empty() {
+                continue;
+            }
+            if let Some(ordinal) = parse_ordinal(&workload.name, &instance.name) {
+                status.nodes.insert(ordinal, instance.node_id.clone());
+            }
+        }
+        if workload.stateful_status.as_ref() != Some(&status) {
+            workload.stateful_status = Some(status.clone());
+            if let Err(err) = workload_service.put_workload(&workload).await {
+                error!(
+                    "Failed to update stateful set {} status: {}",
+                    workload.id,
+                    err.to_problem().detail
+                );
+                return;
+            }
+        }
+
+        let ordinal = match plan_stateful(&workload.name, spec.replicas, instances) {
+            StatefulAction::Wait => return,
+            StatefulAction::Create(ordinal) => ordinal,
+            StatefulAction::Delete(instance) => {
+                info!(
+                    "Stateful set {} deletes instance {}",
+                    workload.id, instance.name
+                );
+                self.delete(&workload, &instance, instance_service).await;
+                // the address of a scaled down instance is released, its volume is kept
+                if parse_ordinal(&workload.name, &instance.name)
+                    .is_none_or(|ordinal| ordinal >= spec.replicas)
+                {
+                    instance_service
+                        .release_sticky_address(&workload.namespace, &instance.name)
+                        .await;
+                }
+                return;
+            }
+            StatefulAction::Replace(instance) => {
+                info!(
+                    "Stateful set {} replaces {:?} instance {}",
+                    workload.id, instance.status.state, instance.name
+                );
+                if !self.delete(&workload, &instance, instance_service).await {
+                    return;
+                }
+                match parse_ordinal(&workload.name, &instance.name) {
+                    Some(ordinal) => ordinal,
+                    None => return,
+                }
+            }
+        };
+
+        let node_id = status.nodes.get(&ordinal).map(String::as_str);
+        match instance_service
+            .create_stateful_instance(&workload.name, &workload.namespace, ordinal, node_id)
+            .await
+        {
+            Ok(instance) => info!(
+                "Stateful set {} started instance {}",
+                workload.id, instance.name
+            ),
+            Err(err) => error!(
+                "Failed to start instance {} of stateful set {}: {}",
+                ordinal,
+                workload.id,
+                err.to_problem().detail
+            ),
+        }
+    }
+
+    /// Deletes an instance of a `StatefulSet`, it is removed from etcd even if the scheduler
+    /// doesn't know it anymore. Returns `false` if it couldn't be deleted.
+    async fn delete(
+        &self,
+        workload: &Workload,
+        instance: &Instance,
+        instance_service: &mut InstanceService,
+    ) -> bool {
+        let Err(err) = instance_service
+            .delete_instance(&instance.id, &instance.namespace)
+            .await
+        else {
+            return true;
+        };
+        debug!(
+            "Scheduler failed to destroy instance {}: {}",
+            instance.id,
+            err.to_problem().detail
+        );
+        if let Err(err) = instance_service.remove_instance(instance).await {
+            error!(
+                "Failed to delete instance {} of stateful set {}: {}",
+                instance.name,
+                workload.id,
+                err.to_problem().detail
+            );
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_api::instance::model::test_instance;
+    use crate::external_api::workload::model::StatefulSpec;
+
+    fn instance(id: &str, name: &str, state: InstanceState) -> Instance {
+        Instance {
+            name: name.to_string(),
+            kind: WorkloadKind::StatefulSet,
+            ..test_instance(id, state)
+        }
+    }
+
+    fn workload(replicas: u32) -> Workload {
+        Workload {
+            id: "default.web".to_string(),
+            name: "web".to_string(),
+            uri: "nginx".to_string(),
+            namespace: "default".to_string(),
+            kind: WorkloadKind::StatefulSet,
+            stateful: Some(StatefulSpec {
+                replicas,
+                volume_path: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn step(replicas: u32, instances: &[Instance]) -> String {
+        match plan_stateful("web", replicas, instances) {
+            StatefulAction::Wait => "wait".to_string(),
+            StatefulAction::Create(ordinal) => format!("create {}", ordinal),
+            StatefulAction::Delete(instance) => format!("delete {}", instance.id),
+            StatefulAction::Replace(instance) => format!("replace {}", instance.id),
+        }
+    }
+
+    #[test]
+    fn test_plan_stateful_starts_in_order() {
+        assert_eq!(step(2, &[]), "create 0");
+
+        let starting = [instance("a", "web-0", InstanceState::Starting)];
+        assert_eq!(step(2, &starting), "wait");
+
+        let running = [instance("a", "web-0", InstanceState::Running)];
+        assert_eq!(step(2, &running), "create 1");
+
+        let crashed = [
+            instance("a", "web-0", InstanceState::Crashed),
+            instance("b", "web-1", InstanceState::Running),
+        ];
+        assert_eq!(step(2, &crashed), "replace a");
+
+        let ready = [
+            instance("a", "web-0", InstanceState::Running),
+            instance("b", "web-1", InstanceState::Running),
+        ];
+        assert_eq!(step(2, &ready), "wait");
+    }
+
+    #[test]
+    fn test_plan_stateful_terminates_in_reverse_order() {
+        let instances = [
+            instance("a", "web-0", InstanceState::Running),
+            instance("b", "web-1", InstanceState::Running),
+            instance("c", "web-2", InstanceState::Running),
+        ];
+        assert_eq!(step(1, &instances), "delete c");
+        assert_eq!(step(1, &instances[..2]), "delete b");
+        assert_eq!(step(0, &instances[..1]), "delete a");
+    }
+
+    #[test]
+    fn test_plan_stateful_deletes_unstable_names() {
+        let instances = [
+            instance("a", "web-0", InstanceState::Running),
+            instance("b", "web-0", InstanceState::Running),
+        ];
+        assert_eq!(step(1, &instances), "delete b");
+
+        let instances = [
+            instance("a", "web-0", InstanceState::Running),
+            instance("b", "web-01", InstanceState::Running),
+            instance("c", "web-4f2b", InstanceState::Running),
+        ];
+        assert_eq!(step(1, &instances), "delete b");
+        assert_eq!(
+            step(1, &[instances[0].clone(), instances[2].clone()]),
+            "delete c"
+        );
+    }
+
+    #[test]
+    fn test_orphan_sticky_names() {
+        let sticky = |name: &str| ("default".to_string(), name.to_string());
+        let names = [
+            sticky("web-0"),
+            sticky("web-1"),
+            sticky("web-2"),
+            sticky("db-0"),
+        ];
+        let instances = [instance("c", "web-2", InstanceState::Running)];
+
+        assert_eq!(
+            orphan_sticky_names(&names, &[workload(2)], &instances),
+            vec![sticky("db-0")]
+        );
+        assert_eq!(
+            orphan_sticky_names(&names, &[workload(1)], &[]),
+            vec![sticky("web-1"), sticky("web-2"), sticky("db-0")]
+        );
+    }
+}
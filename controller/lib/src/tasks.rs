@@ -0,0 +1,112 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// `BackgroundTasks` keeps track of the tasks outliving the HTTP requests which spawned them
+/// (e.g. the tasks writing the status of the instances to etcd), so that the controller can
+/// wait for them before exiting. It also tells the periodic loops whether this replica leads
+/// the cluster, only the leader runs them.
+#[derive(Clone)]
+pub struct BackgroundTasks {
+    shutdown: Arc<watch::Sender<bool>>,
+    handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    leader: watch::Receiver<bool>,
+}
+
+impl Default for BackgroundTasks {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BackgroundTasks {
+    pub fn new() -> Self {
+        let (shutdown, _) = watch::channel(false);
+        // a single replica always leads
+        let (_, leader) = watch::channel(true);
+        BackgroundTasks {
+            shutdown: Arc::new(shutdown),
+            handles: Arc::new(Mutex::new(vec![])),
+            leader,
+        }
+    }
+
+    /// Makes the periodic loops follow the leadership elected among the replicas, see
+    /// `LeaderElection`.
+    pub fn with_leader(mut self, leader: watch::Receiver<bool>) -> Self {
+        self.leader = leader;
+        self
+    }
+
+    /// Returns `true` if this replica leads the cluster: the periodic loops skip their passes
+    /// otherwise, the replicas standing by only serve the API.
+    pub fn is_leader(&self) -> bool {
+        *self.leader.borrow()
+    }
+
+    /// Spawns a task. The task receives a signal changing when the controller shuts down, it is
+    /// expected to finish its current work and to return.
+    pub fn spawn<F, T>(&self, task: F)
+    where
+        F: FnOnce(watch::Receiver<bool>) -> T,
+        T: Future<Output = ()> + Send + 'static,
+    {
+        let handle = tokio::spawn(task(self.shutdown.subscribe()));
+
+        let mut handles = self.handles.lock().unwrap();
+        handles.retain(|handle| !handle.is_finished());
+        handles.push(handle);
+    }
+
+    /// Signals the shutdown to the tasks and waits for them, at most `timeout`.
+    pub async fn shutdown(&self, timeout: Duration) {
+        _ = self.shutdown.send(true);
+
+        let handles: Vec<JoinHandle<()>> = self.handles.lock().unwrap().drain(..).collect();
+        info!("Waiting for {} background task(s)", handles.len());
+
+        let drain = async {
+            for handle in handles {
+                _ = handle.await;
+            }
+        };
+        if tokio::time::timeout(timeout, drain).await.is_err() {
+            warn!("Background tasks still running after {:?}", timeout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_tasks() {
+        let tasks = BackgroundTasks::new();
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::channel(1);
+
+        tasks.spawn(|mut shutdown| async move {
+            _ = shutdown.changed().await;
+            done_tx.send(()).await.unwrap();
+        });
+
+        tasks.shutdown(Duration::from_secs(1)).await;
+        assert!(done_rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn test_leadership() {
+        let tasks = BackgroundTasks::new();
+        assert!(tasks.is_leader());
+
+        let (elected, leader) = watch::channel(false);
+        let tasks = tasks.with_leader(leader);
+        assert!(!tasks.is_leader());
+        elected.send(true).unwrap();
+        assert!(tasks.is_leader());
+    }
+}
@@ -0,0 +1,230 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use proto::scheduler::{ClusterSnapshot, ResourceSummary};
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::external_api::instance::service::unix_time;
+use crate::external_api::shard::service::ShardService;
+use crate::external_api::usage::model::{instance_key, node_key};
+use crate::external_api::usage::service::UsageService;
+use crate::tasks::BackgroundTasks;
+
+/// `UsageConfig` is the configuration of the usage recorder.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The delay between two samples, the recorder is disabled if 0.
+/// * `resolution_seconds`: The period averaged in each sample of the histories.
+/// * `retention_hours`: How long the samples are kept.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageConfig {
+    #[serde(default = "default_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_resolution_seconds")]
+    pub resolution_seconds: u64,
+    #[serde(default = "default_retention_hours")]
+    pub retention_hours: u64,
+}
+
+fn default_interval_seconds() -> u64 {
+    60
+}
+
+fn default_resolution_seconds() -> u64 {
+    300
+}
+
+fn default_retention_hours() -> u64 {
+    24
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            interval_seconds: default_interval_seconds(),
+            resolution_seconds: default_resolution_seconds(),
+            retention_hours: default_retention_hours(),
+        }
+    }
+}
+
+/// Returns the usage reported in a snapshot of the cluster, as the key of its history and the
+/// usage: the nodes first, then the placed instances. The nodes and instances which didn't report
+/// a usage are skipped.
+pub fn sampled_usages(snapshot: &ClusterSnapshot) -> Vec<(String, ResourceSummary)> {
+    let nodes = snapshot.nodes.iter().filter_map(|node| {
+        let usage = node.status.as_ref()?.resource.as_ref()?.usage.clone()?;
+        Some((node_key(&node.id), usage))
+    });
+    let instances = snapshot.placements.iter().filter_map(|placement| {
+        let usage = placement
+            .status
+            .as_ref()?
+            .resource
+            .as_ref()?
+            .usage
+            .clone()?;
+        Some((instance_key(&placement.instance_id), usage))
+    });
+    nodes.chain(instances).collect()
+}
+
+/// `UsageRecorder` periodically samples the usage of the nodes and the instances reported to
+/// the schedulers, and keeps a downsampled history of each in etcd, served by the `/usage/`
+/// routes.
+///
+/// Properties:
+///
+/// * `etcd_address`: The address of etcd.
+/// * `scheduler_address`: The address of the gRPC server of the scheduler.
+/// * `background_tasks`: The tasks the recorder runs in.
+pub struct UsageRecorder {
+    etcd_address: SocketAddr,
+    scheduler_address: SocketAddr,
+    background_tasks: BackgroundTasks,
+}
+
+impl UsageRecorder {
+    pub fn new(
+        etcd_address: SocketAddr,
+        scheduler_address: SocketAddr,
+        background_tasks: &BackgroundTasks,
+    ) -> Self {
+        UsageRecorder {
+            etcd_address,
+            scheduler_address,
+            background_tasks: background_tasks.clone(),
+        }
+    }
+
+    /// Spawns the usage recorder in `background_tasks`, it stops when the controller shuts down.
+    pub fn start(self, config: &UsageConfig) {
+        if config.interval_seconds == 0 {
+            info!("Usage recorder disabled");
+            return;
+        }
+        let interval = Duration::from_secs(config.interval_seconds);
+        let config = config.clone();
+
+        self.background_tasks.clone().spawn(
+            move |mut shutdown: watch::Receiver<bool>| async move {
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    tokio::select! {
+                        _ = ticker.tick() => {
+                            if !self.background_tasks.is_leader() {
+                                continue;
+                            }
+                            if let Err(err) = self.record(&config).await {
+                                warn!("Usage recording failed: {}", err);
+                            }
+                        }
+                        Ok(()) = shutdown.changed() => break,
+                    }
+                }
+                info!("Usage recorder stopped");
+            },
+        );
+    }
+
+    /// Records a single sample of every node and instance, and drops the histories which
+    /// expired.
+    async fn record(&self, config: &UsageConfig) -> Result<(), String> {
+        let snapshot = ShardService::new(&self.etcd_address, &self.scheduler_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?
+            .cluster_snapshot()
+            .await?;
+        let mut usage_service = UsageService::new(&self.etcd_address)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+
+        let now = unix_time();
+        let retention_seconds = config.retention_hours * 3600;
+        let usages = sampled_usages(&snapshot);
+        debug!("Recording the usage of {} resource(s)", usages.len());
+        for (key, usage) in usages {
+            usage_service
+                .record(
+                    &key,
+                    &usage,
+                    now,
+                    config.resolution_seconds,
+                    retention_seconds,
+                )
+                .await
+                .map_err(|err| err.to_problem().detail)?;
+        }
+
+        let pruned = usage_service
+            .prune(now, retention_seconds)
+            .await
+            .map_err(|err| err.to_problem().detail)?;
+        if pruned > 0 {
+            info!("Dropped {} expired usage histories", pruned);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{InstancePlacement, InstanceStatus, NodeSnapshot, NodeStatus, Resource};
+
+    use super::*;
+
+    fn resource(cpu: u64) -> Option<Resource> {
+        Some(Resource {
+            limit: None,
+            usage: Some(ResourceSummary {
+                cpu,
+                ..Default::default()
+            }),
+        })
+    }
+
+    #[test]
+    fn test_sampled_usages() {
+        let snapshot = ClusterSnapshot {
+            nodes: vec![
+                NodeSnapshot {
+                    id: "a".to_string(),
+                    status: Some(NodeStatus {
+                        resource: resource(1000),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                // never reported a status
+                NodeSnapshot {
+                    id: "b".to_string(),
+                    ..Default::default()
+                },
+            ],
+            placements: vec![InstancePlacement {
+                instance_id: "1".to_string(),
+                node_id: "a".to_string(),
+                status: Some(InstanceStatus {
+                    resource: resource(250),
+                    ..Default::default()
+                }),
+            }],
+            pending: vec![],
+        };
+
+        let usages: Vec<(String, u64)> = sampled_usages(&snapshot)
+            .into_iter()
+            .map(|(key, usage)| (key, usage.cpu))
+            .collect();
+        assert_eq!(
+            usages,
+            vec![
+                ("usage.node.a".to_string(), 1000),
+                ("usage.instance.1".to_string(), 250)
+            ]
+        );
+    }
+}
@@ -1,3 +1,18 @@
+use controller_lib::alerting::AlertingConfig;
+use controller_lib::canary::CanaryConfig;
+use controller_lib::cron::CronConfig;
+use controller_lib::daemon::DaemonConfig;
+use controller_lib::dependency::DependencyConfig;
+use controller_lib::external_api::config::ExternalAPIConfig;
+use controller_lib::gc::GcConfig;
+use controller_lib::job::JobConfig;
+use controller_lib::leader::LeaderElectionConfig;
+use controller_lib::maintenance::MaintenanceConfig;
+use controller_lib::notification::NotificationConfig;
+use controller_lib::reconciler::ReconcilerConfig;
+use controller_lib::rollout::RolloutConfig;
+use controller_lib::stateful::StatefulConfig;
+use controller_lib::usage::UsageConfig;
 use serde::{Deserialize, Serialize};
 use std::net::{Ipv4Addr, SocketAddr};
 
@@ -5,6 +20,36 @@ use std::net::{Ipv4Addr, SocketAddr};
 pub struct KudoControllerConfig {
     pub internal_api: InternalAPIConfig,
     pub external_api: ExternalAPIConfig,
+    #[serde(default)]
+    pub reconciler: ReconcilerConfig,
+    #[serde(default)]
+    pub gc: GcConfig,
+    #[serde(default)]
+    pub job: JobConfig,
+    #[serde(default)]
+    pub cron: CronConfig,
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub stateful: StatefulConfig,
+    #[serde(default)]
+    pub canary: CanaryConfig,
+    #[serde(default)]
+    pub rollout: RolloutConfig,
+    #[serde(default)]
+    pub dependency: DependencyConfig,
+    #[serde(default)]
+    pub usage: UsageConfig,
+    #[serde(default)]
+    pub alerting: AlertingConfig,
+    #[serde(default)]
+    pub notification: NotificationConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub leader_election: LeaderElectionConfig,
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -12,13 +57,6 @@ pub struct InternalAPIConfig {
     pub grpc_server_addr: SocketAddr,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ExternalAPIConfig {
-    pub http_server_addr: SocketAddr,
-    pub http_server_num_workers: usize,
-    pub etcd_address: SocketAddr,
-}
-
 impl Default for KudoControllerConfig {
     fn default() -> Self {
         KudoControllerConfig {
@@ -28,17 +66,22 @@ impl Default for KudoControllerConfig {
                     50051,
                 ),
             },
-            external_api: ExternalAPIConfig {
-                http_server_addr: SocketAddr::new(
-                    std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-                    3000,
-                ),
-                http_server_num_workers: 1,
-                etcd_address: SocketAddr::new(
-                    std::net::IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-                    2379,
-                ),
-            },
+            external_api: ExternalAPIConfig::default(),
+            reconciler: ReconcilerConfig::default(),
+            gc: GcConfig::default(),
+            job: JobConfig::default(),
+            cron: CronConfig::default(),
+            daemon: DaemonConfig::default(),
+            stateful: StatefulConfig::default(),
+            canary: CanaryConfig::default(),
+            rollout: RolloutConfig::default(),
+            dependency: DependencyConfig::default(),
+            usage: UsageConfig::default(),
+            alerting: AlertingConfig::default(),
+            notification: NotificationConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            leader_election: LeaderElectionConfig::default(),
+            otlp_endpoint: None,
         }
     }
 }
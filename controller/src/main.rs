@@ -1,7 +1,27 @@
+use controller_lib::alerting::AlertEvaluator;
+use controller_lib::backup::{self, BackupScheduler};
+use controller_lib::canary::CanaryController;
+use controller_lib::cron::CronJobController;
+use controller_lib::daemon::DaemonSetController;
+use controller_lib::dependency::DependencyController;
 use controller_lib::external_api;
+use controller_lib::external_api::instance::service::InstanceService;
+use controller_lib::gc::GarbageCollector;
 use controller_lib::internal_api;
+use controller_lib::job::JobController;
+use controller_lib::leader::LeaderElection;
+use controller_lib::maintenance::MaintenanceController;
+use controller_lib::notification::LifecycleNotifier;
+use controller_lib::reconciler::Reconciler;
+use controller_lib::rollout::RolloutController;
+use controller_lib::stateful::StatefulSetController;
+use controller_lib::tasks::BackgroundTasks;
+use controller_lib::usage::UsageRecorder;
+use log::{info, warn};
 
 use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
 
 mod config;
 
@@ -10,18 +30,168 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Init Logger
     env_logger::init();
 
+    // `controller restore <backup> <data dir>` restores a backup of etcd, etcd being stopped
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("restore") {
+        let (Some(snapshot), Some(data_dir)) = (args.get(2), args.get(3)) else {
+            return Err("Usage: controller restore <backup> <data dir>".into());
+        };
+        backup::restore(Path::new(snapshot), Path::new(data_dir))?;
+        info!("Backup restored, start etcd with --data-dir {}", data_dir);
+        return Ok(());
+    }
+
     let config: config::KudoControllerConfig = confy::load_path("controller.conf")?;
 
+    telemetry::init("controller", config.otlp_endpoint.as_deref())?;
+
     // gRPC Server
     internal_api::interface::InternalAPIInterface::new(config.internal_api.grpc_server_addr).await;
 
     // HTTP Server
-    external_api::interface::ExternalAPIInterface::new(
-        config.external_api.http_server_addr,
-        config.external_api.http_server_num_workers,
+    let background_tasks = BackgroundTasks::new();
+    let shutdown_timeout = Duration::from_secs(config.external_api.shutdown_timeout_seconds);
+
+    // Leader election, only the elected replica runs the loops below, all of them serve the API
+    let leader = LeaderElection::new(config.external_api.etcd_address, &background_tasks)
+        .start(&config.leader_election);
+    let background_tasks = background_tasks.with_leader(leader);
+
+    // Reconciliation loop, bringing the scheduler back to the state stored in etcd
+    Reconciler::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.reconciler);
+
+    // Garbage collector, cleaning up what deletions left behind
+    GarbageCollector::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.gc);
+
+    // Job controller, running the Job workloads to completion
+    JobController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.job);
+
+    // Cron job controller, creating the jobs of the cron jobs on schedule
+    CronJobController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.cron);
+
+    // Daemon set controller, running an instance of the DaemonSet workloads on every node
+    DaemonSetController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.daemon);
+
+    // Stateful set controller, running the instances of the StatefulSet workloads in order
+    StatefulSetController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.stateful);
+
+    // Canary controller, running the canaries of the workloads on their share of the instances
+    CanaryController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.canary);
+
+    // Rollout controller, replacing the instances of the updated workloads one at a time
+    RolloutController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.rollout);
+
+    // Dependency controller, starting the instances once the dependencies of their workload are ready
+    DependencyController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.dependency);
+
+    // Usage recorder, keeping the usage history of the nodes and the instances
+    UsageRecorder::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.usage);
+
+    // Alert evaluator, firing the alert rules of the configuration
+    AlertEvaluator::new(
         config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
     )
-    .await;
+    .start(&config.alerting);
+
+    // Lifecycle notifier, sending the instance, node and deployment events to the webhooks
+    LifecycleNotifier::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.notification);
+
+    // Maintenance controller, draining the nodes before their maintenance windows
+    MaintenanceController::new(
+        config.external_api.etcd_address,
+        config.external_api.scheduler_address,
+        &background_tasks,
+    )
+    .start(&config.maintenance);
+
+    // Backup scheduler, taking the snapshots of etcd
+    BackupScheduler::new(config.external_api.etcd_address, &background_tasks)
+        .start(&config.external_api.backup);
+
+    // Backfill of the instance indexes, the instances stored before them aren't found by the
+    // filtered listings otherwise
+    let indexed = match InstanceService::new(
+        &config.external_api.etcd_address,
+        &config.external_api.scheduler_address,
+    )
+    .await
+    {
+        Ok(mut instance_service) => instance_service.rebuild_indexes().await,
+        Err(err) => Err(err),
+    };
+    match indexed {
+        Ok(count) => info!("Indexed {} instance(s)", count),
+        Err(err) => warn!(
+            "Failed to rebuild the instance indexes: {}",
+            err.to_problem().detail
+        ),
+    }
+
+    external_api::interface::ExternalAPIInterface::new(config.external_api, &background_tasks)
+        .await;
+
+    // The HTTP server is stopped, flush the writes of the background tasks before exiting
+    info!("Shutting down");
+    background_tasks.shutdown(shutdown_timeout).await;
+
+    telemetry::shutdown();
 
     Ok(())
 }
@@ -0,0 +1,288 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+pub mod reference;
+pub mod registry;
+
+use reference::{signature_tag, ImageReference};
+use registry::{RegistryClient, SIGNATURE_ANNOTATION};
+
+/// The OID of the elliptic curve public keys, the only ones signed by cosign by default.
+const EC_PUBLIC_KEY_OID: &str = "1.2.840.10045.2.1";
+
+#[derive(Error, Debug)]
+pub enum SignatureError {
+    #[error("invalid image reference {0}")]
+    InvalidReference(String),
+    #[error("invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("unable to reach the registry: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("the registry answered {0}")]
+    Registry(reqwest::StatusCode),
+    #[error("the image {0} is not signed")]
+    Unsigned(String),
+    #[error("no valid signature for the image {0}")]
+    InvalidSignature(String),
+}
+
+/// What to do with an image whose signature can't be verified.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PolicyMode {
+    /// Reject the image
+    #[default]
+    Enforce,
+    /// Log a warning and run the image anyway
+    Warn,
+}
+
+fn default_timeout_seconds() -> u64 {
+    10
+}
+
+/// `SignaturePolicy` requires the images to be signed, cosign-style, by one of the keys.
+///
+/// Properties:
+///
+/// * `public_keys`: The PEM ECDSA P-256 public keys the images may be signed with.
+/// * `mode`: What to do with an unsigned or tampered image.
+/// * `timeout_seconds`: Maximum duration of each call to the registry.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct SignaturePolicy {
+    pub public_keys: Vec<String>,
+    #[serde(default)]
+    pub mode: PolicyMode,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+/// Returns the digest of some content, as written in the manifests.
+pub fn sha256_digest(content: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, content);
+    let hex: String = digest
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256:{}", hex)
+}
+
+/// Parses a PEM ECDSA P-256 public key, returning its encoded point.
+pub fn parse_public_key(pem: &str) -> Result<Vec<u8>, SignatureError> {
+    let invalid = |reason: &str| SignatureError::InvalidPublicKey(reason.to_string());
+
+    let body: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = base64::decode(body.trim()).map_err(|_| invalid("invalid base64"))?;
+    let (_, key) = SubjectPublicKeyInfo::from_der(&der).map_err(|_| invalid("invalid DER"))?;
+    if key.algorithm.algorithm.to_id_string() != EC_PUBLIC_KEY_OID {
+        return Err(invalid("not an elliptic curve key"));
+    }
+
+    Ok(key.subject_public_key.data.to_vec())
+}
+
+/// Checks a cosign signature: the payload is signed by one of the keys and it is about the
+/// manifest `digest`.
+///
+/// Arguments:
+///
+/// * `payload`: The simple signing payload, a JSON document naming the signed manifest.
+/// * `signature`: The base64 ASN.1 ECDSA signature of the payload.
+/// * `digest`: The digest of the manifest of the image.
+/// * `public_keys`: The encoded points of the trusted keys.
+pub fn verify_payload(
+    payload: &[u8],
+    signature: &str,
+    digest: &str,
+    public_keys: &[Vec<u8>],
+) -> bool {
+    let signature = match base64::decode(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let signed = public_keys.iter().any(|public_key| {
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, public_key)
+            .verify(payload, &signature)
+            .is_ok()
+    });
+
+    // the signature of another image must not be accepted for this one
+    signed
+        && serde_json::from_slice::<Value>(payload)
+            .ok()
+            .and_then(|payload| {
+                payload
+                    .pointer("/critical/image/docker-manifest-digest")
+                    .and_then(Value::as_str)
+                    .map(|signed_digest| signed_digest == digest)
+            })
+            .unwrap_or(false)
+}
+
+/// `Verifier` checks the signatures of the images against a `SignaturePolicy`, before they are
+/// accepted by the controller and before they are run by the node agents.
+pub struct Verifier {
+    public_keys: Vec<Vec<u8>>,
+    mode: PolicyMode,
+    timeout: Duration,
+}
+
+impl Verifier {
+    pub fn new(policy: &SignaturePolicy) -> Result<Self, SignatureError> {
+        Ok(Verifier {
+            public_keys: policy
+                .public_keys
+                .iter()
+                .map(|pem| parse_public_key(pem))
+                .collect::<Result<_, _>>()?,
+            mode: policy.mode,
+            timeout: Duration::from_secs(policy.timeout_seconds),
+        })
+    }
+
+    /// Returns the reference of the image to run: pinned to the digest whose signature was
+    /// verified, so the image can't be replaced between the verification and the pull. In the
+    /// `Warn` mode, an image failing the verification is returned as is.
+    pub async fn check(&self, image: &str) -> Result<String, SignatureError> {
+        match self.verify(image).await {
+            Ok(pinned) => Ok(pinned),
+            Err(err) if self.mode == PolicyMode::Warn => {
+                warn!("running image {} anyway: {}", image, err);
+                Ok(image.to_string())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Verifies that the image has a signature by one of the keys, returning the reference of
+    /// the image pinned to its digest.
+    pub async fn verify(&self, image: &str) -> Result<String, SignatureError> {
+        let reference = ImageReference::parse(image)?;
+        let mut registry = RegistryClient::new(reference.clone(), self.timeout)?;
+
+        let digest = registry.resolve_digest().await?;
+        let manifest = registry
+            .manifest(&signature_tag(&digest))
+            .await?
+            .ok_or_else(|| SignatureError::Unsigned(image.to_string()))?;
+
+        for layer in manifest.layers.iter() {
+            let signature = match layer.annotations.get(SIGNATURE_ANNOTATION) {
+                Some(signature) => signature,
+                None => continue,
+            };
+            let payload = registry.blob(&layer.digest).await?;
+            if sha256_digest(&payload) != layer.digest {
+                continue;
+            }
+
+            if verify_payload(&payload, signature, &digest, &self.public_keys) {
+                info!("verified the signature of image {}", image);
+                return Ok(reference.pinned(&digest));
+            }
+        }
+
+        Err(SignatureError::InvalidSignature(image.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+    use super::*;
+
+    /// The DER prefix of the P-256 public keys, before their encoded point.
+    const P256_SPKI_PREFIX: &str = "3059301306072a8648ce3d020106082a8648ce3d030107034200";
+
+    fn generate_key() -> (EcdsaKeyPair, String) {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap();
+
+        let mut der: Vec<u8> = (0..P256_SPKI_PREFIX.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&P256_SPKI_PREFIX[i..i + 2], 16).unwrap())
+            .collect();
+        der.extend_from_slice(key_pair.public_key().as_ref());
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::encode(der)
+        );
+        (key_pair, pem)
+    }
+
+    fn sign(key_pair: &EcdsaKeyPair, payload: &[u8]) -> String {
+        let signature = key_pair.sign(&SystemRandom::new(), payload).unwrap();
+        base64::encode(signature.as_ref())
+    }
+
+    #[test]
+    fn test_verify_payload() {
+        let (key_pair, pem) = generate_key();
+        let public_keys = vec![parse_public_key(&pem).unwrap()];
+        let payload =
+            br#"{"critical":{"image":{"docker-manifest-digest":"sha256:abc"},"type":"cosign container image signature"}}"#;
+        let signature = sign(&key_pair, payload);
+
+        assert!(verify_payload(
+            payload,
+            &signature,
+            "sha256:abc",
+            &public_keys
+        ));
+        // signed, but for another image
+        assert!(!verify_payload(
+            payload,
+            &signature,
+            "sha256:def",
+            &public_keys
+        ));
+
+        // tampered payload
+        let tampered = br#"{"critical":{"image":{"docker-manifest-digest":"sha256:def"}}}"#;
+        assert!(!verify_payload(
+            tampered,
+            &signature,
+            "sha256:def",
+            &public_keys
+        ));
+
+        // signed by another key
+        let (_, other) = generate_key();
+        let other_keys = vec![parse_public_key(&other).unwrap()];
+        assert!(!verify_payload(
+            payload,
+            &signature,
+            "sha256:abc",
+            &other_keys
+        ));
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        assert!(parse_public_key("-----BEGIN PUBLIC KEY-----\nnot a key\n").is_err());
+        let (_, pem) = generate_key();
+        assert_eq!(parse_public_key(&pem).unwrap().len(), 65);
+    }
+
+    #[test]
+    fn test_sha256_digest() {
+        assert_eq!(
+            sha256_digest(b""),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::SignatureError;
+
+/// The registry of the images without registry, e.g. `alpine:3`.
+pub const DEFAULT_REGISTRY: &str = "registry-1.docker.io";
+
+/// A parsed image reference, e.g. `ghcr.io/kudo/app:1.0` or `alpine@sha256:...`.
+///
+/// Properties:
+///
+/// * `registry`: The host of the registry.
+/// * `repository`: The repository in the registry, `library/` is added to the official images.
+/// * `reference`: The tag or the digest of the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub reference: String,
+}
+
+impl ImageReference {
+    pub fn parse(image: &str) -> Result<Self, SignatureError> {
+        let invalid = || SignatureError::InvalidReference(image.to_string());
+
+        // the first component is a registry if it looks like a host
+        let (registry, path) = match image.split_once('/') {
+            Some((host, path))
+                if host.contains('.') || host.contains(':') || host == "localhost" =>
+            {
+                (host.to_string(), path)
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), image),
+        };
+
+        let (repository, reference) = if let Some((repository, digest)) = path.split_once('@') {
+            (repository, digest.to_string())
+        } else {
+            match path.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, tag.to_string()),
+                _ => (path, "latest".to_string()),
+            }
+        };
+        if repository.is_empty() || reference.is_empty() {
+            return Err(invalid());
+        }
+
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository.to_string()
+        };
+
+        Ok(ImageReference {
+            registry,
+            repository,
+            reference,
+        })
+    }
+
+    /// Returns `true` if the reference is a digest rather than a tag.
+    pub fn is_digest(&self) -> bool {
+        self.reference.starts_with("sha256:")
+    }
+
+    /// Returns the reference of the image pinned to `digest`.
+    pub fn pinned(&self, digest: &str) -> String {
+        format!("{}/{}@{}", self.registry, self.repository, digest)
+    }
+}
+
+/// Returns the tag of the cosign signature of the image with the manifest `digest`.
+pub fn signature_tag(digest: &str) -> String {
+    digest.replace(':', "-") + ".sig"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let reference = ImageReference::parse("alpine").unwrap();
+        assert_eq!(reference.registry, DEFAULT_REGISTRY);
+        assert_eq!(reference.repository, "library/alpine");
+        assert_eq!(reference.reference, "latest");
+
+        let reference = ImageReference::parse("localhost:5000/kudo/app:1.0").unwrap();
+        assert_eq!(reference.registry, "localhost:5000");
+        assert_eq!(reference.repository, "kudo/app");
+        assert_eq!(reference.reference, "1.0");
+
+        let reference = ImageReference::parse("ghcr.io/kudo/app@sha256:abc").unwrap();
+        assert!(reference.is_digest());
+        assert_eq!(
+            reference.pinned("sha256:abc"),
+            "ghcr.io/kudo/app@sha256:abc"
+        );
+
+        assert!(ImageReference::parse("app:").is_err());
+    }
+
+    #[test]
+    fn test_signature_tag() {
+        assert_eq!(signature_tag("sha256:abc"), "sha256-abc.sig");
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use reqwest::{header, Client, Response, StatusCode};
+use serde::Deserialize;
+
+use crate::reference::ImageReference;
+use crate::{sha256_digest, SignatureError};
+
+/// The manifest types accepted when resolving the digest of an image.
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// The annotation of the layers of a cosign signature holding the signature of the payload.
+pub const SIGNATURE_ANNOTATION: &str = "dev.cosignproject.cosign/signature";
+
+#[derive(Deserialize, Debug)]
+pub struct Manifest {
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Layer {
+    pub digest: String,
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+/// Parses the `WWW-Authenticate` challenge of a registry, e.g.
+/// `Bearer realm="https://auth.docker.io/token",service="registry.docker.io"`.
+pub fn parse_challenge(header: &str) -> Option<HashMap<String, String>> {
+    let parameters = header.strip_prefix("Bearer ")?;
+    Some(
+        parameters
+            .split(',')
+            .filter_map(|parameter| parameter.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim_matches('"').to_string()))
+            .collect(),
+    )
+}
+
+/// `RegistryClient` reads the manifests and blobs of a repository with the OCI distribution API,
+/// getting an anonymous token when the registry asks for one.
+pub struct RegistryClient {
+    client: Client,
+    image: ImageReference,
+    token: Option<String>,
+}
+
+impl RegistryClient {
+    pub fn new(image: ImageReference, timeout: Duration) -> Result<Self, SignatureError> {
+        Ok(RegistryClient {
+            client: Client::builder().timeout(timeout).build()?,
+            image,
+            token: None,
+        })
+    }
+
+    /// Returns the digest of the manifest of the image.
+    pub async fn resolve_digest(&mut self) -> Result<String, SignatureError> {
+        if self.image.is_digest() {
+            return Ok(self.image.reference.clone());
+        }
+
+        let reference = self.image.reference.clone();
+        let response = self.manifest_response(&reference).await?;
+        let header_digest = response
+            .headers()
+            .get("Docker-Content-Digest")
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+
+        match header_digest {
+            Some(digest) => Ok(digest),
+            None => Ok(sha256_digest(&response.bytes().await?)),
+        }
+    }
+
+    /// Returns the manifest tagged `tag`, `None` if there is no such tag.
+    pub async fn manifest(&mut self, tag: &str) -> Result<Option<Manifest>, SignatureError> {
+        match self.manifest_response(tag).await {
+            Ok(response) => Ok(Some(response.json().await?)),
+            Err(SignatureError::Registry(status)) if status == StatusCode::NOT_FOUND => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the content of a blob.
+    pub async fn blob(&mut self, digest: &str) -> Result<Vec<u8>, SignatureError> {
+        let path = format!("blobs/{}", digest);
+        Ok(self.get(&path, None).await?.bytes().await?.to_vec())
+    }
+
+    async fn manifest_response(&mut self, reference: &str) -> Result<Response, SignatureError> {
+        let path = format!("manifests/{}", reference);
+        self.get(&path, Some(MANIFEST_TYPES)).await
+    }
+
+    async fn get(&mut self, path: &str, accept: Option<&str>) -> Result<Response, SignatureError> {
+        let url = format!(
+            "https://{}/v2/{}/{}",
+            self.image.registry, self.image.repository, path
+        );
+
+        let mut retried = false;
+        loop {
+            let mut request = self.client.get(&url);
+            if let Some(accept) = accept {
+                request = request.header(header::ACCEPT, accept);
+            }
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+
+            let response = request.send().await?;
+            match response.status() {
+                StatusCode::UNAUTHORIZED if !retried => {
+                    let challenge = response
+                        .headers()
+                        .get(header::WWW_AUTHENTICATE)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(parse_challenge)
+                        .ok_or(SignatureError::Registry(StatusCode::UNAUTHORIZED))?;
+                    self.token = Some(self.fetch_token(&challenge).await?);
+                    retried = true;
+                }
+                status if status.is_success() => return Ok(response),
+                status => return Err(SignatureError::Registry(status)),
+            }
+        }
+    }
+
+    async fn fetch_token(
+        &self,
+        challenge: &HashMap<String, String>,
+    ) -> Result<String, SignatureError> {
+        let realm = challenge
+            .get("realm")
+            .ok_or(SignatureError::Registry(StatusCode::UNAUTHORIZED))?;
+        let scope = challenge
+            .get("scope")
+            .cloned()
+            .unwrap_or_else(|| format!("repository:{}:pull", self.image.repository));
+
+        let mut query = vec![("scope", scope)];
+        if let Some(service) = challenge.get("service") {
+            query.push(("service", service.clone()));
+        }
+
+        let response: TokenResponse = self
+            .client
+            .get(realm)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        response
+            .token
+            .or(response.access_token)
+            .ok_or(SignatureError::Registry(StatusCode::UNAUTHORIZED))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_challenge() {
+        let challenge = parse_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io""#,
+        )
+        .unwrap();
+        assert_eq!(challenge["realm"], "https://auth.docker.io/token");
+        assert_eq!(challenge["service"], "registry.docker.io");
+
+        assert!(parse_challenge("Basic realm=\"registry\"").is_none());
+    }
+}
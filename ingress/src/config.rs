@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+/// `Config` is a struct that contains the configuration of the ingress proxy.
+///
+/// Properties:
+///
+/// * `host`: The IP address the proxy listens on.
+/// * `port`: The port the proxy listens on.
+/// * `controller_url`: The URL of the controller external API, used to fetch the ingresses.
+/// * `refresh_interval`: The number of seconds between two refreshes of the routes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub controller_url: String,
+    pub refresh_interval: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: "0.0.0.0".to_string(),
+            port: 80,
+            controller_url: "http://127.0.0.1:3000".to_string(),
+            refresh_interval: 5,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::convert::Infallible;
+use std::env;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Client, Server};
+use log::{debug, info};
+use tokio::sync::RwLock;
+
+use config::Config;
+use router::Router;
+use sync::RouteSynchronizer;
+
+mod config;
+mod proxy;
+mod router;
+mod sync;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    info!("starting up");
+
+    info!("loading config");
+    let mut dir = env::current_dir()?; // get executable path
+    dir.push("ingress.conf"); // add config file name
+
+    // load config from path
+    let config: Config = confy::load_path(dir.as_path())?;
+    debug!("config: {:?}", config);
+
+    let addr: SocketAddr = format!("{}:{}", config.host, config.port)
+        .parse()
+        .context("invalid listening address in configuration file")?;
+
+    let router = Arc::new(RwLock::new(Router::default()));
+
+    // keep the routes in sync with the ingresses of the controller
+    let synchronizer = RouteSynchronizer::new(config.controller_url.clone(), router.clone());
+    let refresh_interval = Duration::from_secs(config.refresh_interval);
+    tokio::spawn(async move { synchronizer.run(refresh_interval).await });
+
+    let client = Client::new();
+    let make_service = make_service_fn(move |_| {
+        let router = router.clone();
+        let client = client.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                proxy::forward(request, router.clone(), client.clone())
+            }))
+        }
+    });
+
+    info!("ingress proxy listening on {}", addr);
+    Server::bind(&addr).serve(make_service).await?;
+
+    info!("shutting down");
+    Ok(())
+}
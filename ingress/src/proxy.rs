@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use hyper::client::HttpConnector;
+use hyper::header::HOST;
+use hyper::{Body, Client, Request, Response, StatusCode, Uri};
+use log::{debug, error};
+use tokio::sync::RwLock;
+
+use crate::router::Router;
+
+/// Forwards a request to the backend of the route matching its host and path.
+///
+/// Answers `404 Not Found` when no route matches and `502 Bad Gateway` when the backend
+/// can't be reached.
+pub async fn forward(
+    mut request: Request<Body>,
+    router: Arc<RwLock<Router>>,
+    client: Client<HttpConnector>,
+) -> Result<Response<Body>, hyper::Error> {
+    let host = request
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| request.uri().host())
+        .unwrap_or_default()
+        .to_string();
+    let path_and_query = request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string());
+
+    let backend = match router.read().await.resolve(&host, request.uri().path()) {
+        Some(backend) => backend,
+        None => {
+            debug!("no route for {}{}", host, path_and_query);
+            return Ok(status_response(StatusCode::NOT_FOUND));
+        }
+    };
+
+    let uri: Uri = match format!("http://{}{}", backend, path_and_query).parse() {
+        Ok(uri) => uri,
+        Err(_) => return Ok(status_response(StatusCode::BAD_REQUEST)),
+    };
+    debug!("forwarding {}{} to {}", host, path_and_query, uri);
+    *request.uri_mut() = uri;
+
+    match client.request(request).await {
+        Ok(response) => Ok(response),
+        Err(err) => {
+            error!("failed to reach backend {} : {}", backend, err);
+            Ok(status_response(StatusCode::BAD_GATEWAY))
+        }
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::from(
+        status.canonical_reason().unwrap_or_default().to_string(),
+    ));
+    *response.status_mut() = status;
+    response
+}
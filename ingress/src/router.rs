@@ -0,0 +1,98 @@
+use std::net::SocketAddr;
+
+/// A `Route` forwards the requests matching a host and a path prefix to a backend.
+///
+/// Properties:
+///
+/// * `host`: The host to match, an empty host matches every request.
+/// * `path`: The path prefix to match.
+/// * `backend`: The address the requests are forwarded to (a service virtual IP and port).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub host: String,
+    pub path: String,
+    pub backend: SocketAddr,
+}
+
+/// `Router` holds the routes built from the ingresses of the cluster.
+#[derive(Debug, Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new(routes: Vec<Route>) -> Self {
+        Router { routes }
+    }
+
+    /// Returns the backend of the most specific route matching the request: routes with an
+    /// explicit host win over catch-all routes, then the longest path prefix wins.
+    ///
+    /// Arguments:
+    ///
+    /// * `host`: The `Host` header of the request, the port is ignored.
+    /// * `path`: The path of the request.
+    pub fn resolve(&self, host: &str, path: &str) -> Option<SocketAddr> {
+        let host = host.split(':').next().unwrap_or_default();
+
+        self.routes
+            .iter()
+            .filter(|route| route.host.is_empty() || route.host.eq_ignore_ascii_case(host))
+            .filter(|route| matches_path(&route.path, path))
+            .max_by_key(|route| (!route.host.is_empty(), route.path.len()))
+            .map(|route| route.backend)
+    }
+}
+
+/// A path prefix only matches on segment boundaries, `/api` matches `/api` and `/api/users`
+/// but not `/apis`.
+fn matches_path(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(host: &str, path: &str, port: u16) -> Route {
+        Route {
+            host: host.to_string(),
+            path: path.to_string(),
+            backend: SocketAddr::from(([10, 96, 0, 1], port)),
+        }
+    }
+
+    #[test]
+    fn test_resolve_longest_prefix() {
+        let router = Router::new(vec![route("", "/", 80), route("", "/api", 8080)]);
+
+        assert_eq!(
+            router.resolve("example.com", "/api/users").unwrap().port(),
+            8080
+        );
+        assert_eq!(router.resolve("example.com", "/apis").unwrap().port(), 80);
+        assert_eq!(router.resolve("example.com", "/").unwrap().port(), 80);
+    }
+
+    #[test]
+    fn test_resolve_host_first() {
+        let router = Router::new(vec![route("", "/api", 80), route("example.com", "/", 8080)]);
+
+        assert_eq!(
+            router.resolve("example.com:80", "/api").unwrap().port(),
+            8080
+        );
+        assert_eq!(router.resolve("other.com", "/api").unwrap().port(), 80);
+    }
+
+    #[test]
+    fn test_resolve_no_route() {
+        let router = Router::new(vec![route("example.com", "/", 80)]);
+
+        assert_eq!(router.resolve("other.com", "/"), None);
+    }
+}
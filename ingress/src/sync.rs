@@ -0,0 +1,127 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use hyper::client::HttpConnector;
+use hyper::Client;
+use log::{debug, error, info};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::router::{Route, Router};
+
+#[derive(Debug, Deserialize)]
+struct IngressRule {
+    host: String,
+    path: String,
+    service: String,
+    port: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ingress {
+    name: String,
+    namespace: String,
+    rules: Vec<IngressRule>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IngressVector {
+    ingresses: Vec<Ingress>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Service {
+    virtual_ip: Ipv4Addr,
+}
+
+/// `RouteSynchronizer` periodically rebuilds the routes of the proxy from the ingresses stored
+/// in the controller.
+pub struct RouteSynchronizer {
+    client: Client<HttpConnector>,
+    controller_url: String,
+    router: Arc<RwLock<Router>>,
+}
+
+impl RouteSynchronizer {
+    pub fn new(controller_url: String, router: Arc<RwLock<Router>>) -> Self {
+        RouteSynchronizer {
+            client: Client::new(),
+            controller_url: controller_url.trim_end_matches('/').to_string(),
+            router,
+        }
+    }
+
+    /// Refreshes the routes every `interval`, a failed refresh keeps the previous routes.
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+
+        loop {
+            ticker.tick().await;
+            match self.fetch_routes().await {
+                Ok(routes) => {
+                    debug!("refreshed ingress routes : {:?}", routes);
+                    *self.router.write().await = Router::new(routes);
+                }
+                Err(err) => error!("failed to refresh ingress routes : {:?}", err),
+            }
+        }
+    }
+
+    async fn fetch_routes(&self) -> Result<Vec<Route>> {
+        let ingresses: IngressVector = self.get("/ingress").await?;
+        let mut routes = vec![];
+
+        for ingress in ingresses.ingresses {
+            for rule in ingress.rules {
+                let port = match u16::try_from(rule.port) {
+                    Ok(port) if port != 0 => port,
+                    _ => {
+                        info!(
+                            "skipping rule of ingress {} targeting invalid port {} of service {}",
+                            ingress.name, rule.port, rule.service
+                        );
+                        continue;
+                    }
+                };
+                let service: Service = match self
+                    .get(&format!("/service/{}/{}", ingress.namespace, rule.service))
+                    .await
+                {
+                    Ok(service) => service,
+                    Err(err) => {
+                        info!(
+                            "skipping rule of ingress {} targeting service {} : {:?}",
+                            ingress.name, rule.service, err
+                        );
+                        continue;
+                    }
+                };
+
+                routes.push(Route {
+                    host: rule.host,
+                    path: rule.path,
+                    backend: SocketAddr::from((service.virtual_ip, port)),
+                });
+            }
+        }
+
+        Ok(routes)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &str) -> Result<T> {
+        let uri = format!("{}{}", self.controller_url, endpoint)
+            .parse()
+            .context("invalid controller url")?;
+        let response = self.client.get(uri).await?;
+
+        if !response.status().is_success() {
+            anyhow::bail!("controller answered {} on {}", response.status(), endpoint);
+        }
+
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        serde_json::from_slice(&body).context("unable to parse controller response")
+    }
+}
@@ -1,11 +1,17 @@
+use std::collections::HashMap;
+
 use anyhow::Context;
 use log::debug;
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async, tungstenite::client::IntoClientRequest, MaybeTlsStream, WebSocketStream,
+};
 
 use crate::{client::types::IdResponse, resource::workload};
 
-use super::request::Client;
+use super::{request::Client, workload::Port};
 
 #[derive(Debug, Serialize)]
 struct CreateRequestBody {
@@ -30,21 +36,36 @@ pub async fn create(client: &Client, namespace: &str, workload_id: &str) -> anyh
     Ok(response.id)
 }
 
+/// Current state of an instance, as reported by the node running it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstanceStatus {
+    pub state: String,
+    pub status_description: String,
+}
+
+/// Instance of a workload, as returned by the controller.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Instance {
     pub id: String,
     pub name: String,
+    pub workload_id: String,
     pub r#type: String,
     pub uri: String,
-    pub ports: Vec<String>,
-    pub env: Vec<String>,
+    pub environment: Vec<String>,
     pub resources: workload::Resources,
-    pub status: String,
+    pub ports: Vec<Port>,
+    pub ip: String,
+    pub namespace: String,
+    /// id of the node running the instance, empty until it is scheduled
+    #[serde(default)]
+    pub node_id: String,
+    pub status: InstanceStatus,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetInstancesResponse {
-    pub count: u64,
     pub instances: Vec<Instance>,
 
     /// used for formatting in the Display impl
@@ -62,11 +83,7 @@ pub async fn list(client: &Client, namespace: &str) -> anyhow::Result<GetInstanc
         )
         .await
         .context("Error getting instances")?;
-    debug!(
-        "{} total instances, {} instances received ",
-        response.count,
-        response.instances.len()
-    );
+    debug!("{} instances received", response.instances.len());
     Ok(response)
 }
 
@@ -87,13 +104,132 @@ pub async fn get(client: &Client, namespace: &str, instance_id: &str) -> anyhow:
 /// Delete an instance with the given id.
 pub async fn delete(client: &Client, namespace: &str, instance_id: &str) -> anyhow::Result<()> {
     (*client)
-        .send_json_request::<(), ()>(
+        .send_empty_request(
             &format!("/instance/{}/{}", namespace, instance_id),
             Method::DELETE,
-            None,
         )
         .await
         .context("Error deleting instance")?;
     debug!("Instance {} deleted", instance_id);
     Ok(())
 }
+
+/// Open the log stream of an instance.
+///
+/// The body of the response is the output of the instance, line by line,
+/// it stays open for the new lines when `follow` is set.
+pub async fn logs(
+    client: &Client,
+    namespace: &str,
+    instance_id: &str,
+    follow: bool,
+    tail: Option<u32>,
+) -> anyhow::Result<reqwest::Response> {
+    let mut endpoint = format!(
+        "/instance/{}/{}/logs?follow={}",
+        namespace, instance_id, follow
+    );
+    if let Some(tail) = tail {
+        endpoint.push_str(&format!("&tail={}", tail));
+    }
+
+    let response = (*client)
+        .send_stream_request(&endpoint, Method::GET)
+        .await
+        .with_context(|| format!("Error getting logs of instance {}", instance_id))?;
+    debug!("Log stream of instance {} opened", instance_id);
+    Ok(response)
+}
+
+/// Message of the control channel of an exec session, sent as a websocket text message.
+/// The input and output of the command are sent as binary messages.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExecMessage {
+    /// Size of the terminal of the user, sent when the session starts and on every resize
+    Resize { cols: u16, rows: u16 },
+    /// Exit code of the command, sent by the controller when it ends
+    Exit { code: i32 },
+}
+
+/// Open an exec session running `command` in an instance.
+pub async fn exec(
+    client: &Client,
+    namespace: &str,
+    instance_id: &str,
+    command: &[String],
+    tty: bool,
+) -> anyhow::Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let mut url = (*client)
+        .websocket_url(&format!("/instance/{}/{}/exec", namespace, instance_id))
+        .context("Error building exec url")?;
+    url.query_pairs_mut()
+        .extend_pairs(command.iter().map(|arg| ("command", arg)))
+        .append_pair("tty", &tty.to_string());
+
+    let mut request = url
+        .into_client_request()
+        .context("Error building exec request")?;
+    if let Some(token) = (*client).token() {
+        request.headers_mut().insert(
+            "Authorization",
+            format!("Bearer {}", token)
+                .parse()
+                .context("Invalid token")?,
+        );
+    }
+
+    let (stream, _) = connect_async(request)
+        .await
+        .with_context(|| format!("Error opening exec session in instance {}", instance_id))?;
+    debug!("Exec session opened in instance {}", instance_id);
+    Ok(stream)
+}
+
+/// Change of an instance, streamed by the controller to the clients watching the instances.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InstanceEvent {
+    /// `Added`, `Modified` or `Deleted`
+    pub r#type: String,
+    pub instance: Instance,
+}
+
+/// Parse a line of the server-sent events stream of the instances,
+/// returns `None` for the lines carrying no event (e.g. the blank separators).
+pub fn parse_event(line: &str) -> Option<anyhow::Result<InstanceEvent>> {
+    let data = line.strip_prefix("data:")?;
+    Some(serde_json::from_str(data.trim()).context("Error parsing instance event"))
+}
+
+/// Open the stream of the changes of the instances of a namespace.
+pub async fn watch(client: &Client, namespace: &str) -> anyhow::Result<reqwest::Response> {
+    let response = (*client)
+        .send_stream_request(&format!("/instance/{}?watch=true", namespace), Method::GET)
+        .await
+        .context("Error watching instances")?;
+    debug!("Watching the instances of namespace {}", namespace);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::lines::LineBuffer;
+
+    #[test]
+    fn test_parse_instance_event() {
+        let mut buffer = LineBuffer::default();
+        let lines = buffer.push(
+            br#"data: {"type":"Modified","instance":{"id":"42","name":"web-42","workload_id":"default.web","type":"Container","uri":"nginx","environment":[],"resources":{"cpu":0,"memory":0,"disk":0},"ports":[],"ip":"","namespace":"default","node_id":"node-1","status":{"state":"Running","status_description":""}}}
+
+"#,
+        );
+
+        let events: Vec<_> = lines.iter().filter_map(|line| parse_event(line)).collect();
+        assert_eq!(events.len(), 1);
+
+        let event = events.into_iter().next().unwrap().unwrap();
+        assert_eq!(event.r#type, "Modified");
+        assert_eq!(event.instance.status.state, "Running");
+    }
+}
@@ -0,0 +1,49 @@
+/// Splits the chunks of a streamed response body into lines.
+#[derive(Default)]
+pub struct LineBuffer {
+    pending: Vec<u8>,
+}
+
+impl LineBuffer {
+    /// Adds a chunk to the buffer and returns the lines it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend_from_slice(chunk);
+
+        let mut lines = vec![];
+        while let Some(end) = self.pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            lines.push(
+                String::from_utf8_lossy(&line)
+                    .trim_end_matches(['\n', '\r'])
+                    .to_string(),
+            );
+        }
+        lines
+    }
+
+    /// Returns the last line if the stream didn't end with a line break.
+    pub fn finish(self) -> Option<String> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&self.pending).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer_splits_chunks() {
+        let mut buffer = LineBuffer::default();
+
+        assert_eq!(buffer.push(b"first li"), Vec::<String>::new());
+        assert_eq!(
+            buffer.push(b"ne\r\nsecond\nthi"),
+            vec!["first line", "second"]
+        );
+        assert_eq!(buffer.finish(), Some("thi".to_string()));
+    }
+}
@@ -1,4 +1,5 @@
 pub mod instance;
+pub mod lines;
 pub mod namespace;
 pub mod node;
 pub mod request;
@@ -36,7 +36,7 @@ pub async fn list(client: &Client) -> Result<GetNamespacesResponse> {
 /// Delete an namespace with the given id.
 pub async fn delete(client: &Client, name: &str) -> anyhow::Result<()> {
     (*client)
-        .send_json_request::<(), ()>(&format!("/namespace/{}", name), Method::DELETE, None)
+        .send_empty_request(&format!("/namespace/{}", name), Method::DELETE)
         .await
         .context("Error deleting namespace")?;
     debug!("Namespace {} deleted", name);
@@ -5,10 +5,11 @@ use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use serde::Serialize;
 
-// Represent the error returned by the controller when a request fails
+// Represent the error returned by the controller when a request fails (RFC 7807 problem details)
 #[derive(Deserialize)]
 struct ErrorResponse {
-    pub error: String,
+    #[serde(alias = "error")]
+    pub detail: String,
 }
 
 // Error returned by this module when an endpoint returns an error.
@@ -47,6 +48,7 @@ impl std::fmt::Display for RequestError {
 pub struct Client {
     client: reqwest::Client,
     base_url: reqwest::Url,
+    token: Option<String>,
 }
 
 impl Client {
@@ -63,15 +65,39 @@ impl Client {
             "Content-Type",
             header::HeaderValue::from_static("application/json"),
         );
+        if let Some(token) = &config.token {
+            let mut value = header::HeaderValue::from_str(&format!("Bearer {}", token))?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
 
         Ok(Client {
             client: reqwest::Client::builder()
                 .default_headers(headers)
                 .build()?,
             base_url,
+            token: config.token.clone(),
         })
     }
 
+    // Returns the token authenticating the requests, if any.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    // Returns the websocket url of an endpoint of the controller (ws:// or wss://).
+    pub fn websocket_url(&self, endpoint: &str) -> Result<reqwest::Url, RequestError> {
+        let mut url = self
+            .base_url
+            .join(endpoint)
+            .map_err(RequestError::ParseError)?;
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+
+        // only fails when switching from or to a scheme without host, not the case here
+        _ = url.set_scheme(scheme);
+        Ok(url)
+    }
+
     // Send a request to the controller.
     async fn send_request<U: Serialize>(
         &self,
@@ -83,7 +109,7 @@ impl Client {
             .base_url
             .join(endpoint)
             .map_err(RequestError::ParseError)?;
-        let mut request = (*self).client.request(method, url);
+        let mut request = self.client.request(method, url);
 
         if let Some(body) = body {
             request = request.json(body);
@@ -103,23 +129,49 @@ impl Client {
     ) -> Result<T, RequestError> {
         let response = self.send_request(endpoint, method, body).await?;
 
-        // Check if the response is an error.
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-
-            // Read the error message from the response body.
-
-            let error_response: ErrorResponse =
-                response.json().await.map_err(RequestError::ReqwestError)?;
-            return Err(RequestError::ErrStatusCode(ErrStatusCode {
-                error: error_response.error,
-                status,
-            }));
-        }
-
-        response
+        check_status(response)
+            .await?
             .json::<T>()
             .await
             .map_err(RequestError::ReqwestError)
     }
+
+    // Send a request to the controller, ignoring the body of the response (e.g. a deletion).
+    //
+    // returns a `RequestError` if a non-2xx response is received.
+    pub async fn send_empty_request(
+        &self,
+        endpoint: &str,
+        method: reqwest::Method,
+    ) -> Result<(), RequestError> {
+        let response = self.send_request::<()>(endpoint, method, None).await?;
+        check_status(response).await.map(|_| ())
+    }
+
+    // Send a request to the controller and return the response, to read its body as it comes.
+    //
+    // returns a `RequestError` if a non-2xx response is received.
+    pub async fn send_stream_request(
+        &self,
+        endpoint: &str,
+        method: reqwest::Method,
+    ) -> Result<Response, RequestError> {
+        let response = self.send_request::<()>(endpoint, method, None).await?;
+        check_status(response).await
+    }
+}
+
+// Check if the response is an error, the error message is read from the response body.
+async fn check_status(response: Response) -> Result<Response, RequestError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status().as_u16();
+
+    // Some errors (e.g. from a proxy) are not problem details.
+    let body = response.text().await.map_err(RequestError::ReqwestError)?;
+    let error = serde_json::from_str::<ErrorResponse>(&body)
+        .map(|error_response| error_response.detail)
+        .unwrap_or(body);
+    Err(RequestError::ErrStatusCode(ErrStatusCode { error, status }))
 }
@@ -1,64 +1,212 @@
+use std::{collections::HashMap, str::FromStr};
+
 use anyhow::{Context, Result};
 use log::debug;
 use reqwest::Method;
 use serde::{Deserialize, Serialize};
 
-use crate::{
-    client::types::IdResponse,
-    resource::{self, workload},
-};
+use crate::resource::workload;
 
 use super::request::{Client, RequestError};
 
+/// Port exposed by a workload, as represented by the controller.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Port {
+    pub source: i32,
+    pub destination: i32,
+}
+
+impl FromStr for Port {
+    type Err = anyhow::Error;
+
+    /// Parses a `source:destination` port mapping, or a single port exposed as is.
+    fn from_str(port: &str) -> Result<Self> {
+        let parse = |value: &str| {
+            value
+                .trim()
+                .parse::<i32>()
+                .with_context(|| format!("Invalid port mapping {}", port))
+        };
+
+        match port.split_once(':') {
+            Some((source, destination)) => Ok(Port {
+                source: parse(source)?,
+                destination: parse(destination)?,
+            }),
+            None => {
+                let port = parse(port)?;
+                Ok(Port {
+                    source: port,
+                    destination: port,
+                })
+            }
+        }
+    }
+}
+
+/// Body of the requests creating or updating a workload.
+#[derive(Debug, Serialize)]
+struct WorkloadBody<'a> {
+    name: &'a str,
+    uri: &'a str,
+    environment: &'a [String],
+    resources: &'a workload::Resources,
+    ports: Vec<Port>,
+    labels: HashMap<String, String>,
+    ttl_seconds_after_finished: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    security_context: Option<&'a workload::SecurityContext>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<workload::WorkloadKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job: Option<&'a workload::JobSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stateful: Option<&'a workload::StatefulSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canary_percentage: Option<u8>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sidecars: Vec<SidecarBody<'a>>,
+}
+
+/// Sidecar of a workload, as represented by the controller.
+#[derive(Debug, Serialize)]
+struct SidecarBody<'a> {
+    name: &'a str,
+    uri: &'a str,
+    environment: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<&'a workload::Resources>,
+}
+
+impl<'a> From<&'a workload::Sidecar> for SidecarBody<'a> {
+    fn from(sidecar: &'a workload::Sidecar) -> Self {
+        SidecarBody {
+            name: &sidecar.name,
+            uri: &sidecar.uri,
+            environment: sidecar.env.as_deref().unwrap_or_default(),
+            resources: sidecar.resources.as_ref(),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a workload::Workload> for WorkloadBody<'a> {
+    type Error = anyhow::Error;
+
+    fn try_from(workload: &'a workload::Workload) -> Result<Self> {
+        let ports = workload
+            .ports
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|port| port.parse())
+            .collect::<Result<Vec<Port>>>()?;
+
+        Ok(WorkloadBody {
+            name: &workload.name,
+            uri: &workload.uri,
+            environment: workload.env.as_deref().unwrap_or_default(),
+            resources: &workload.resources,
+            ports,
+            labels: workload.labels.clone().unwrap_or_default(),
+            ttl_seconds_after_finished: workload.ttl_seconds_after_finished,
+            security_context: workload.security_context.as_ref(),
+            kind: workload.kind,
+            job: workload.job.as_ref(),
+            stateful: workload.stateful.as_ref(),
+            canary_percentage: workload.canary_percentage,
+            sidecars: workload
+                .sidecars
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .map(SidecarBody::from)
+                .collect(),
+        })
+    }
+}
+
+/// Workload stored in the cluster, as returned by the controller.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct WorkloadInfo {
+    pub id: String,
+    pub name: String,
+    pub uri: String,
+    pub environment: Vec<String>,
+    pub resources: workload::Resources,
+    pub ports: Vec<Port>,
+    pub namespace: String,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub ttl_seconds_after_finished: Option<u64>,
+}
+
 /// Creates a workload in the cluster.
 ///
-/// Returns the id of the workload.
+/// Returns the workload created.
 pub async fn create(
     client: &Client,
     namespace: &str,
     workload: &workload::Workload,
-) -> std::result::Result<String, RequestError> {
-    let response: IdResponse = (*client)
+) -> Result<WorkloadInfo> {
+    let body = WorkloadBody::try_from(workload)?;
+    let response: WorkloadInfo = (*client)
         .send_json_request(
             format!("/workload/{}", namespace).as_str(),
             Method::PUT,
-            Some(workload),
+            Some(&body),
         )
-        .await?;
+        .await
+        .context("Error creating workload")?;
     debug!("Workload {} created", response.id);
-    Ok(response.id)
+    Ok(response)
 }
 
 /// Updates a workload in the cluster.
 ///
-/// Returns the id of the workload.
+/// Returns the workload updated.
 pub async fn update(
     client: &Client,
     namespace: &str,
     workload: &workload::Workload,
-) -> std::result::Result<String, RequestError> {
-    let response: IdResponse = (*client)
+) -> Result<WorkloadInfo> {
+    let body = WorkloadBody::try_from(workload)?;
+    let response: WorkloadInfo = (*client)
         .send_json_request(
             format!("/workload/{}/{}", namespace, workload.name).as_str(),
             Method::PATCH,
-            Some(workload),
+            Some(&body),
         )
-        .await?;
+        .await
+        .context("Error updating workload")?;
     debug!("Workload {} updated", response.id);
-    Ok(response.id)
+    Ok(response)
+}
+
+/// Checks if a workload exists in the cluster.
+pub async fn exists(client: &Client, namespace: &str, name: &str) -> Result<bool> {
+    let response = (*client)
+        .send_json_request::<WorkloadInfo, ()>(
+            &format!("/workload/{}/{}", namespace, name),
+            Method::GET,
+            None,
+        )
+        .await;
+
+    match response {
+        Ok(_) => Ok(true),
+        Err(RequestError::ErrStatusCode(err)) if err.status == 404 => Ok(false),
+        Err(err) => Err(err).context("Error getting workload"),
+    }
 }
 
 /// Get info about a workload.
 ///
 /// Returns the workload info.
-pub async fn get(
-    client: &Client,
-    namespace: &str,
-    workload_id: &str,
-) -> Result<workload::Workload> {
-    let response: workload::Workload = (*client)
-        .send_json_request::<workload::Workload, ()>(
-            &format!("/workload/{}/{}", namespace, workload_id),
+pub async fn get(client: &Client, namespace: &str, name: &str) -> Result<WorkloadInfo> {
+    let response: WorkloadInfo = (*client)
+        .send_json_request::<WorkloadInfo, ()>(
+            &format!("/workload/{}/{}", namespace, name),
             Method::GET,
             None,
         )
@@ -68,9 +216,8 @@ pub async fn get(
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-pub struct GetWorkloadResponse {
-    pub count: u64,
-    pub workloads: Vec<resource::Resource>,
+pub struct GetWorkloadsResponse {
+    pub workloads: Vec<WorkloadInfo>,
     #[serde(skip)]
     pub show_header: bool,
 }
@@ -78,33 +225,49 @@ pub struct GetWorkloadResponse {
 /// Get the workloads in the cluster.
 ///
 /// Returns a vector of workloads.
-pub async fn list(client: &Client, namespace: &str) -> Result<GetWorkloadResponse> {
-    let response: GetWorkloadResponse = (*client)
-        .send_json_request::<GetWorkloadResponse, ()>(
+pub async fn list(client: &Client, namespace: &str) -> Result<GetWorkloadsResponse> {
+    let response: GetWorkloadsResponse = (*client)
+        .send_json_request::<GetWorkloadsResponse, ()>(
             format!("/workload/{}", namespace).as_str(),
             Method::GET,
             None,
         )
         .await
         .context("Error getting workloads")?;
-    debug!(
-        "{} total workloads, {} workloads received ",
-        response.count,
-        response.workloads.len()
-    );
+    debug!("{} workloads received", response.workloads.len());
     Ok(response)
 }
 
 /// Delete a workload.
-pub async fn delete(client: &Client, id: &str) -> Result<()> {
-    let response = (*client)
-        .send_json_request::<IdResponse, ()>(
-            format!("/workload/{}", id).as_str(),
-            Method::DELETE,
-            None,
-        )
+pub async fn delete(client: &Client, namespace: &str, name: &str) -> Result<()> {
+    (*client)
+        .send_empty_request(&format!("/workload/{}/{}", namespace, name), Method::DELETE)
         .await
         .context("Error deleting workload")?;
-    debug!("Workload {} deleted", response.id);
+    debug!("Workload {} deleted", name);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port() {
+        assert_eq!(
+            "8080:80".parse::<Port>().unwrap(),
+            Port {
+                source: 8080,
+                destination: 80
+            }
+        );
+        assert_eq!(
+            "8080".parse::<Port>().unwrap(),
+            Port {
+                source: 8080,
+                destination: 8080
+            }
+        );
+        assert!("http:80".parse::<Port>().is_err());
+    }
+}
@@ -35,65 +35,146 @@ pub fn get_verbosity_level_from_string(verbosity_level_str: &str) -> LevelFilter
     }
 }
 
+fn default_context_name() -> String {
+    "default".to_string()
+}
+
+// Cluster to target: where its controller is, how to authenticate and which namespace to use by default.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct Context {
+    pub name: String,
+    #[serde(default = "default_controller_url")]
+    pub controller_url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+impl Context {
+    pub fn new(name: &str) -> Self {
+        Context {
+            name: name.to_string(),
+            controller_url: default_controller_url(),
+            token: None,
+            namespace: None,
+        }
+    }
+}
+
 // Serializable configuration struct
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ConfigFile {
-    #[serde(default = "default_controller_url")]
-    controller_url: String,
     #[serde(default = "default_log_level_str")]
     verbosity_level: String,
+    #[serde(default = "default_context_name")]
+    pub current_context: String,
+    #[serde(default)]
+    pub contexts: Vec<Context>,
+    // Controller url of the config files written before the contexts, used as the `default` context.
+    #[serde(default, skip_serializing)]
+    controller_url: Option<String>,
 }
 
 impl Default for ConfigFile {
     fn default() -> Self {
         ConfigFile {
-            controller_url: default_controller_url(),
             verbosity_level: default_log_level_str(),
+            current_context: default_context_name(),
+            contexts: vec![Context::new(&default_context_name())],
+            controller_url: None,
+        }
+    }
+}
+
+impl ConfigFile {
+    // Returns the context with the given name.
+    pub fn context(&self, name: &str) -> Option<&Context> {
+        self.contexts.iter().find(|context| context.name == name)
+    }
+
+    // Returns the context with the given name, created if it doesn't exist.
+    pub fn context_mut(&mut self, name: &str) -> &mut Context {
+        let index = match self
+            .contexts
+            .iter()
+            .position(|context| context.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.contexts.push(Context::new(name));
+                self.contexts.len() - 1
+            }
+        };
+        &mut self.contexts[index]
+    }
+
+    // Turns the controller url of a config file written before the contexts into the `default` context.
+    fn migrate(&mut self) {
+        if let Some(controller_url) = self.controller_url.take() {
+            if self.contexts.is_empty() {
+                let mut context = Context::new(&default_context_name());
+                context.controller_url = controller_url;
+                self.contexts.push(context);
+            }
         }
     }
 }
 
 // This struct contains the configuration for the application.
 pub struct Config {
-    #[allow(dead_code)]
-    config_file: PathBuf,
+    pub config_file: PathBuf,
+    pub context: String,
     pub controller_url: String,
+    pub token: Option<String>,
     pub verbosity_level: LevelFilter,
     pub namespace: String,
 }
 
+// Returns the path of the config file.
+pub fn config_file_path() -> PathBuf {
+    env::var("KUDO_CONFIG")
+        .map(|path| Path::new(&path).to_path_buf())
+        .unwrap_or_else(|_| default_config_file_path())
+}
+
 // Read the config file and return a Config object.
 // If the file does not exist, creates one with the default values.
-fn read_config_file(path: &PathBuf) -> Result<ConfigFile, Box<dyn std::error::Error>> {
-    let config_file: ConfigFile = if !path.exists() {
-        let parent = path.parent();
-
-        // Create the config directory if it doesn't exist.
-        if let Some(parent) = parent {
-            create_dir_all(parent)?;
-        }
-
-        let file_handler = File::create(path)?;
-
+pub fn read_config_file(path: &PathBuf) -> Result<ConfigFile, Box<dyn std::error::Error>> {
+    let mut config_file: ConfigFile = if !path.exists() {
         let default = Default::default();
-        serde_yaml::to_writer(file_handler, &default)?;
+        write_config_file(path, &default)?;
         default
     } else {
         let file = File::open(path)?;
         serde_yaml::from_reader(file)?
     };
+    config_file.migrate();
     Ok(config_file)
 }
 
+// Write the config file, creating its directory if needed.
+pub fn write_config_file(
+    path: &PathBuf,
+    config_file: &ConfigFile,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Create the config directory if it doesn't exist.
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+
+    let file_handler = File::create(path)?;
+    serde_yaml::to_writer(file_handler, config_file)?;
+    Ok(())
+}
+
 // Read the configuration from the config file and the environment variables.
-// The environment variables override the values in the config file.
-pub fn read_config() -> Result<Config, Box<dyn std::error::Error>> {
+// The environment variables override the values in the config file,
+// `context` (from the command line) overrides the current context of the config file.
+pub fn read_config(context: Option<&str>) -> Result<Config, Box<dyn std::error::Error>> {
     // Read the config file
 
-    let file_path = env::var("KUDO_CONFIG")
-        .map(|path| Path::new(&path).to_path_buf())
-        .unwrap_or_else(|_| default_config_file_path());
-
+    let file_path = config_file_path();
     let config_file = read_config_file(&file_path)?;
 
     // get the verbosity level
@@ -103,15 +184,31 @@ pub fn read_config() -> Result<Config, Box<dyn std::error::Error>> {
 
     let verbosity_level = get_verbosity_level_from_string(&verbosity_level_string);
 
+    // get the context to use
+
+    let context_name = match context {
+        Some(context) => context.to_string(),
+        None => check_env_override("KUDO_CONTEXT", &config_file.current_context),
+    };
+    let context = match config_file.context(&context_name) {
+        Some(context) => context.clone(),
+        None if context_name == default_context_name() => Context::new(&context_name),
+        None => {
+            return Err(format!("Context {} not found in the config file", context_name).into())
+        }
+    };
+
     // get the right controller url
 
-    let controller_url = check_env_override("KUDO_CONTROLLER_URL", &config_file.controller_url);
+    let controller_url = check_env_override("KUDO_CONTROLLER_URL", &context.controller_url);
 
     Ok(Config {
         config_file: file_path,
+        context: context.name,
         controller_url,
+        token: env::var("KUDO_TOKEN").ok().or(context.token),
         verbosity_level,
-        namespace: "default".to_string(),
+        namespace: context.namespace.unwrap_or_else(|| "default".to_string()),
     })
 }
 
@@ -119,3 +216,34 @@ pub fn read_config() -> Result<Config, Box<dyn std::error::Error>> {
 fn check_env_override(env_var: &str, config_var: &str) -> String {
     env::var(env_var).unwrap_or_else(|_| config_var.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_config_file_without_contexts() {
+        let mut config_file: ConfigFile =
+            serde_yaml::from_str("controller_url: http://10.0.0.1:8080\nverbosity_level: info\n")
+                .unwrap();
+        config_file.migrate();
+
+        assert_eq!(config_file.current_context, "default");
+        assert_eq!(
+            config_file.context("default").unwrap().controller_url,
+            "http://10.0.0.1:8080"
+        );
+    }
+
+    #[test]
+    fn test_context_mut_creates_missing_context() {
+        let mut config_file = ConfigFile::default();
+        config_file.context_mut("prod").namespace = Some("web".to_string());
+
+        assert_eq!(config_file.contexts.len(), 2);
+        assert_eq!(
+            config_file.context("prod").unwrap().namespace.as_deref(),
+            Some("web")
+        );
+    }
+}
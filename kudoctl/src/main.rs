@@ -24,10 +24,16 @@ struct Cli {
     host: Option<String>,
 
     /// Define which namespace to target
-    /// If not defined, the default namespace is used
+    /// If not defined, the namespace of the context is used, `default` otherwise
     #[clap(short, long, global = true)]
     namespace: Option<String>,
 
+    /// Define which context of the config file to use
+    ///
+    /// This has priority over the current context of the config file and the KUDO_CONTEXT environment variable.
+    #[clap(long, global = true)]
+    context: Option<String>,
+
     /// Execute a command on the connected cluster
     #[clap(subcommand)]
     command: subcommands::Subcommands,
@@ -41,7 +47,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // parse config
 
-    let mut global_config = config::read_config()?;
+    let mut global_config = config::read_config(cli.context.as_deref())?;
 
     // set verbosity level
 
@@ -56,7 +62,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         global_config.controller_url = host.to_string();
     }
 
-    global_config.namespace = cli.namespace.unwrap_or_else(|| "default".to_string());
+    if let Some(namespace) = cli.namespace {
+        global_config.namespace = namespace;
+    }
 
     subcommands::match_subcommand(cli.command, &global_config).await;
 
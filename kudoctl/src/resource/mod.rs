@@ -1,4 +1,5 @@
 pub mod parse;
+pub mod selector;
 pub mod workload;
 
 use serde::{Deserialize, Serialize};
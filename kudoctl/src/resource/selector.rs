@@ -0,0 +1,106 @@
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{bail, Result};
+
+/// Operator of a requirement of a label selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operator {
+    Equals,
+    NotEquals,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Requirement {
+    key: String,
+    operator: Operator,
+    value: String,
+}
+
+/// Label selector, such as `app=web,tier!=cache`.
+///
+/// A resource matches when every requirement is met,
+/// a `!=` requirement is met when the label is missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    requirements: Vec<Requirement>,
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(selector: &str) -> Result<Self> {
+        let mut requirements = vec![];
+
+        for requirement in selector.split(',').map(str::trim) {
+            let (key, operator, value) = if let Some((key, value)) = requirement.split_once("!=") {
+                (key, Operator::NotEquals, value)
+            } else if let Some((key, value)) = requirement.split_once("==") {
+                (key, Operator::Equals, value)
+            } else if let Some((key, value)) = requirement.split_once('=') {
+                (key, Operator::Equals, value)
+            } else {
+                bail!(
+                    "Invalid selector requirement \"{}\", expected key=value or key!=value",
+                    requirement
+                );
+            };
+
+            if key.trim().is_empty() {
+                bail!(
+                    "Invalid selector requirement \"{}\", missing key",
+                    requirement
+                );
+            }
+            requirements.push(Requirement {
+                key: key.trim().to_string(),
+                operator,
+                value: value.trim().to_string(),
+            });
+        }
+
+        Ok(Selector { requirements })
+    }
+}
+
+impl Selector {
+    /// Returns true if the labels meet every requirement of the selector.
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        self.requirements.iter().all(|requirement| {
+            let value = labels.get(&requirement.key);
+            match requirement.operator {
+                Operator::Equals => value == Some(&requirement.value),
+                Operator::NotEquals => value != Some(&requirement.value),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn labels(labels: &[(&str, &str)]) -> HashMap<String, String> {
+        labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_selector_matches() {
+        let selector: Selector = "app=web, tier!=cache".parse().unwrap();
+
+        assert!(selector.matches(&labels(&[("app", "web")])));
+        assert!(selector.matches(&labels(&[("app", "web"), ("tier", "front")])));
+        assert!(!selector.matches(&labels(&[("app", "web"), ("tier", "cache")])));
+        assert!(!selector.matches(&labels(&[("app", "api")])));
+        assert!(!selector.matches(&labels(&[])));
+    }
+
+    #[test]
+    fn test_parse_invalid_selector() {
+        assert!("app".parse::<Selector>().is_err());
+        assert!("=web".parse::<Selector>().is_err());
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 // Workload definition (serialized to YAML)
@@ -10,6 +12,90 @@ pub struct Workload {
     pub ports: Option<Vec<String>>,
     /// environment variables to set on the workload
     pub env: Option<Vec<String>>,
+    /// labels to select the workload and its instances (e.g. `app: web`)
+    pub labels: Option<HashMap<String, String>>,
+    /// seconds after which a terminated or failed instance is deleted, kept forever if unset
+    pub ttl_seconds_after_finished: Option<u64>,
+    /// security settings of the containers, the defaults of the runtime if unset
+    pub security_context: Option<SecurityContext>,
+    /// `Service` (the default), `Job` for a workload running to completion, `DaemonSet` for an
+    /// instance on every node or `StatefulSet` for instances with stable names
+    pub kind: Option<WorkloadKind>,
+    /// completions and backoff limit of a `Job` workload
+    pub job: Option<JobSpec>,
+    /// replicas and volume of a `StatefulSet` workload
+    pub stateful: Option<StatefulSpec>,
+    /// percentage of the instances running an update of a `Service` workload, as a canary
+    pub canary_percentage: Option<u8>,
+    /// containers started next to the main one in each instance, sharing its IP and volumes
+    pub sidecars: Option<Vec<Sidecar>>,
+}
+
+// Container started next to the main container of each instance
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Sidecar {
+    // unique among the sidecars of the workload
+    pub name: String,
+    pub uri: String,
+    pub env: Option<Vec<String>>,
+    // added to the resources of the instance, none if unset
+    pub resources: Option<Resources>,
+}
+
+// How the instances of a workload are expected to run
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum WorkloadKind {
+    Service,
+    Job,
+    DaemonSet,
+    StatefulSet,
+}
+
+// Run to completion settings of a `Job` workload
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobSpec {
+    // number of instances which must succeed, 1 if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completions: Option<u32>,
+    // number of failed instances tolerated before the job fails, 6 if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backoff_limit: Option<u32>,
+}
+
+// Settings of a `StatefulSet` workload, its instances are named `<name>-0`, `<name>-1`...
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatefulSpec {
+    // number of instances, 1 if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replicas: Option<u32>,
+    // where the volume of each instance is mounted, no volume if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_path: Option<String>,
+}
+
+// Seccomp profile of the containers (e.g. `Unconfined` or `Localhost: audit.json`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub enum SeccompProfile {
+    #[default]
+    RuntimeDefault,
+    Unconfined,
+    // profile installed in the seccomp profiles directory of the nodes
+    Localhost(String),
+}
+
+// Security settings of the containers of a workload
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SecurityContext {
+    // capabilities to drop (e.g. `NET_RAW`, `ALL`)
+    #[serde(default)]
+    pub drop_capabilities: Vec<String>,
+    #[serde(default)]
+    pub seccomp_profile: SeccompProfile,
+    #[serde(default)]
+    pub read_only_root_filesystem: bool,
+    // uid the process runs as, the user of the image if unset
+    #[serde(default)]
+    pub run_as_user: Option<u32>,
 }
 
 // Resources assigned to a workload
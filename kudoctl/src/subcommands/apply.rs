@@ -1,12 +1,10 @@
 use std::io::{self, Read};
 
 use crate::{
-    client::{
-        self,
-        request::{Client, RequestError},
-    },
+    client::{self, request::Client},
     config,
     resource::Resource,
+    subcommands::output::{self, OutputFormat},
 };
 use anyhow::{bail, Context, Result};
 use clap::Args;
@@ -18,13 +16,25 @@ pub struct Apply {
     #[clap(short, long)]
     file: Option<String>,
 
-    /// If the resource already exists, don’t update it   
+    /// If the resource already exists, don’t update it
     #[clap(long)]
     no_update: bool,
+
+    /// Change the output format
+    #[clap(
+        short = 'o',
+        long = "output",
+        short_alias = 'F',
+        alias = "format",
+        arg_enum,
+        value_parser
+    )]
+    format: Option<OutputFormat>,
 }
 
 pub async fn execute(args: Apply, conf: &config::Config) -> Result<String> {
     let client = Client::new(conf).context("Error creating client")?;
+    let format = args.format.unwrap_or(OutputFormat::HumanReadable);
 
     // read the yaml file if -f is used
     let yaml = if let Some(file) = args.file {
@@ -52,57 +62,36 @@ pub async fn execute(args: Apply, conf: &config::Config) -> Result<String> {
     let resource_data: Resource =
         serde_yaml::from_str(&yaml).context("Error parsing file resource")?;
 
-    let mut needs_instance_create = true;
-
     match resource_data {
         Resource::Workload(ref workload) => {
             debug!("Pushing workload {}", workload.name);
 
-            let workload_id = match client::workload::create(&client, &conf.namespace, workload)
-                .await
-            {
-                Ok(id) => {
-                    info!("Workload {} created", id);
-                    id
-                }
-
-                Err(e) => match e {
-                    RequestError::ErrStatusCode(ref status) => {
-                        if status.status == 409 {
-                            info!("Workload {} already exists", workload.name);
-                            needs_instance_create = false;
-
-                            let res = client::workload::update(&client, &conf.namespace, workload)
-                                .await
-                                .context("Error updating workload");
-                            match res {
-                                Ok(id) => {
-                                    info!("Workload {} updated", id);
-                                    id
-                                }
-                                Err(e) => {
-                                    bail!("Error updating workload: {}", e);
-                                }
-                            }
-                        } else {
-                            bail!("Error creating workload {}: {}", workload.name, e);
-                        }
-                    }
-                    _ => bail!("Error creating workload {}: {}", workload.name, e),
-                },
-            };
+            // create the workload or update it if it already exists
+            let exists = client::workload::exists(&client, &conf.namespace, &workload.name).await?;
+
+            let result = if !exists {
+                let result = client::workload::create(&client, &conf.namespace, workload).await?;
 
-            if needs_instance_create {
                 let instance_id =
                     client::instance::create(&client, &conf.namespace, &workload.name).await?;
 
                 info!(
                     "Workload {} created with id {} and started with instance {}",
-                    workload.name, workload_id, instance_id
+                    workload.name, result.id, instance_id
                 );
-            }
+                result
+            } else if args.no_update {
+                bail!(
+                    "Workload {} already exists and --no-update is set",
+                    workload.name
+                );
+            } else {
+                let result = client::workload::update(&client, &conf.namespace, workload).await?;
+                info!("Workload {} updated", result.id);
+                result
+            };
+
+            output::format_output(result, format)
         }
     }
-
-    Ok("".to_string())
 }
@@ -0,0 +1,168 @@
+use crate::{
+    client::{self, request::Client},
+    config,
+};
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory, ValueEnum};
+use clap_complete::{generate, Shell};
+
+#[derive(Debug, Args)]
+/// Print the completion script of a shell.
+///
+/// Load it with `source <(kudoctl completion bash)` (or zsh),
+/// or `kudoctl completion fish | source`.
+pub struct Completion {
+    #[clap(arg_enum, value_parser)]
+    shell: CompletionShell,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+// Completion of the values fetched from the controller, `kudoctl complete-values` printing them.
+// The namespace given on the command line is forwarded to list the instances of this namespace.
+
+const BASH_DYNAMIC: &str = r#"
+_kudoctl_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}" namespace=() i
+    for ((i = 1; i < COMP_CWORD; i++)); do
+        if [[ "${COMP_WORDS[i]}" == "-n" || "${COMP_WORDS[i]}" == "--namespace" ]]; then
+            namespace=(-n "${COMP_WORDS[i+1]}")
+        fi
+    done
+    case "$prev" in
+        -n|--namespace)
+            COMPREPLY=($(compgen -W "$(kudoctl complete-values namespaces 2>/dev/null)" -- "$cur"))
+            return 0 ;;
+        instance|logs|exec)
+            COMPREPLY=($(compgen -W "$(kudoctl "${namespace[@]}" complete-values instances 2>/dev/null)" -- "$cur"))
+            return 0 ;;
+        workload|resource)
+            COMPREPLY=($(compgen -W "$(kudoctl "${namespace[@]}" complete-values workloads 2>/dev/null)" -- "$cur"))
+            return 0 ;;
+    esac
+    _kudoctl "$@"
+}
+complete -F _kudoctl_dynamic -o bashdefault -o default kudoctl
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_kudoctl_dynamic() {
+    local namespace=() i
+    for ((i = 2; i < CURRENT; i++)); do
+        if [[ "${words[i]}" == (-n|--namespace) ]]; then
+            namespace=(-n "${words[i+1]}")
+        fi
+    done
+    case "${words[CURRENT-1]}" in
+        -n|--namespace)
+            compadd -- ${(f)"$(kudoctl complete-values namespaces 2>/dev/null)"} ;;
+        instance|logs|exec)
+            compadd -- ${(f)"$(kudoctl $namespace complete-values instances 2>/dev/null)"} ;;
+        workload|resource)
+            compadd -- ${(f)"$(kudoctl $namespace complete-values workloads 2>/dev/null)"} ;;
+        *)
+            _kudoctl "$@" ;;
+    esac
+}
+compdef _kudoctl_dynamic kudoctl
+"#;
+
+const FISH_DYNAMIC: &str = r#"
+function __kudoctl_namespace
+    set -l tokens (commandline -opc)
+    for i in (seq (count $tokens))
+        if contains -- $tokens[$i] -n --namespace; and test $i -lt (count $tokens)
+            echo -n $tokens[(math $i + 1)]
+        end
+    end
+end
+function __kudoctl_complete
+    set -l namespace (__kudoctl_namespace)
+    if test -n "$namespace"
+        kudoctl -n $namespace complete-values $argv 2>/dev/null
+    else
+        kudoctl complete-values $argv 2>/dev/null
+    end
+end
+complete -c kudoctl -s n -l namespace -f -a "(kudoctl complete-values namespaces 2>/dev/null)"
+complete -c kudoctl -n "__fish_seen_subcommand_from logs exec instance" -f -a "(__kudoctl_complete instances)"
+complete -c kudoctl -n "__fish_seen_subcommand_from workload resource" -f -a "(__kudoctl_complete workloads)"
+"#;
+
+/// completion subcommand execution, the script is returned to be printed on stdout.
+pub async fn execute(args: Completion) -> Result<String> {
+    let (shell, dynamic) = match args.shell {
+        CompletionShell::Bash => (Shell::Bash, BASH_DYNAMIC),
+        CompletionShell::Zsh => (Shell::Zsh, ZSH_DYNAMIC),
+        CompletionShell::Fish => (Shell::Fish, FISH_DYNAMIC),
+    };
+
+    let mut script = vec![];
+    generate(shell, &mut crate::Cli::command(), "kudoctl", &mut script);
+
+    let script = String::from_utf8(script).context("Error generating the completion script")?;
+    Ok(format!("{}{}", script, dynamic))
+}
+
+#[derive(Debug, Args)]
+/// Print the values to complete, one per line (used by the completion scripts).
+pub struct Complete {
+    #[clap(arg_enum, value_parser)]
+    subject: CompleteSubjects,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum CompleteSubjects {
+    Namespaces,
+    Instances,
+    Workloads,
+}
+
+/// complete-values subcommand execution
+pub async fn execute_complete(args: Complete, conf: &config::Config) -> Result<String> {
+    let client = Client::new(conf).context("Error creating client")?;
+
+    let values: Vec<String> = match args.subject {
+        CompleteSubjects::Namespaces => client::namespace::list(&client)
+            .await?
+            .namespaces
+            .into_iter()
+            .map(|namespace| namespace.name)
+            .collect(),
+        CompleteSubjects::Instances => client::instance::list(&client, &conf.namespace)
+            .await?
+            .instances
+            .into_iter()
+            .map(|instance| instance.id)
+            .collect(),
+        CompleteSubjects::Workloads => client::workload::list(&client, &conf.namespace)
+            .await?
+            .workloads
+            .into_iter()
+            .map(|workload| workload.name)
+            .collect(),
+    };
+    Ok(values.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bash_completion_script() {
+        let script = execute(Completion {
+            shell: CompletionShell::Bash,
+        })
+        .await
+        .unwrap();
+
+        assert!(script.contains("_kudoctl()"));
+        assert!(script.contains("complete -F _kudoctl_dynamic"));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::{self, read_config_file, write_config_file};
+use crate::subcommands::output::Table;
+use anyhow::{anyhow, bail, Result};
+use clap::{Args, Subcommand};
+
+#[derive(Debug, Args)]
+/// Manage the contexts of the config file, each context targeting a cluster.
+pub struct ConfigSubcommand {
+    #[clap(subcommand)]
+    command: ConfigCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// Set the context used by the next commands
+    UseContext {
+        /// Name of the context
+        name: String,
+    },
+
+    /// Create a context or update its properties
+    SetContext {
+        /// Name of the context
+        name: String,
+
+        /// Url of the controller of the cluster
+        #[clap(long)]
+        controller_url: Option<String>,
+
+        /// Token authenticating the requests to the controller
+        #[clap(long)]
+        token: Option<String>,
+
+        /// Namespace targeted when the --namespace flag is not set
+        #[clap(long = "default-namespace")]
+        default_namespace: Option<String>,
+    },
+
+    /// Remove a context
+    DeleteContext {
+        /// Name of the context
+        name: String,
+    },
+
+    /// List the contexts of the config file
+    GetContexts,
+
+    /// Print the name of the context in use
+    CurrentContext,
+}
+
+/// config subcommand execution, reads and updates the config file.
+pub async fn execute(args: ConfigSubcommand, conf: &config::Config) -> Result<String> {
+    let path = &conf.config_file;
+    let mut config_file = read_config_file(path).map_err(|err| anyhow!("{}", err))?;
+
+    let output = match args.command {
+        ConfigCommands::UseContext { name } => {
+            if config_file.context(&name).is_none() {
+                bail!("Context {} not found, create it with set-context", name);
+            }
+            config_file.current_context = name.clone();
+            format!("Switched to context {}", name)
+        }
+        ConfigCommands::SetContext {
+            name,
+            controller_url,
+            token,
+            default_namespace,
+        } => {
+            let context = config_file.context_mut(&name);
+            if let Some(controller_url) = controller_url {
+                context.controller_url = controller_url;
+            }
+            if let Some(token) = token {
+                context.token = Some(token);
+            }
+            if let Some(namespace) = default_namespace {
+                context.namespace = Some(namespace);
+            }
+            format!("Context {} set", name)
+        }
+        ConfigCommands::DeleteContext { name } => {
+            if config_file.context(&name).is_none() {
+                bail!("Context {} not found", name);
+            }
+            config_file.contexts.retain(|context| context.name != name);
+            if config_file.current_context == name {
+                config_file.current_context = "default".to_string();
+            }
+            format!("Context {} deleted", name)
+        }
+        ConfigCommands::GetContexts => {
+            let mut table = Table::new(&["CURRENT", "NAME", "CONTROLLER", "NAMESPACE"], true);
+            for context in &config_file.contexts {
+                table.add_row(vec![
+                    if context.name == conf.context {
+                        "*"
+                    } else {
+                        ""
+                    }
+                    .to_string(),
+                    context.name.clone(),
+                    context.controller_url.clone(),
+                    context.namespace.clone().unwrap_or_default(),
+                ]);
+            }
+            return Ok(table.to_string().trim_end().to_string());
+        }
+        ConfigCommands::CurrentContext => return Ok(conf.context.clone()),
+    };
+
+    write_config_file(path, &config_file).map_err(|err| anyhow!("{}", err))?;
+    Ok(output)
+}
@@ -1,41 +1,125 @@
-use crate::config;
-use anyhow::Result;
+use std::io::{self, BufRead, Write};
+
+use crate::{client::request::Client, config, resource::selector::Selector};
+use anyhow::{bail, Context, Result};
 use clap::{Args, ValueEnum};
-mod instance;
-mod namespace;
-mod resource;
+use log::{debug, info};
+mod target;
+use target::Target;
 
 #[derive(Debug, Args)]
 /// Delete a kudo subject, this is not reversible.
 pub struct Subcommand {
     /// Define the type of element(s) to delete
-    #[clap(arg_enum, value_parser)]
-    subject: Subjects,
+    ///
+    /// Use singular to delete one element (ID parameter is required),
+    /// use plural to delete the elements matching --selector, or every element with --all.
+    #[clap(arg_enum, value_parser, required_unless_present = "file")]
+    subject: Option<Subjects>,
 
     /// Identifier of the element to delete
     #[clap(value_name = "ID")]
-    id: String,
+    id: Option<String>,
+
+    /// Delete the resources described in a yaml file
+    #[clap(short, long, conflicts_with_all = &["subject", "selector", "all"])]
+    file: Option<String>,
+
+    /// Delete the elements whose labels match the selector (e.g. `app=web,tier!=cache`)
+    #[clap(short = 'l', long, value_parser)]
+    selector: Option<Selector>,
+
+    /// Delete every element of the type in the namespace
+    #[clap(long, conflicts_with = "selector")]
+    all: bool,
+
+    /// Don’t ask for a confirmation before deleting several elements
+    #[clap(long)]
+    force: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum Subjects {
-    /// resources
-    Resource,
+    /// workloads
+    #[clap(alias = "resource")]
+    Workload,
+    #[clap(alias = "resources")]
+    Workloads,
 
     /// instances
     Instance,
+    Instances,
 
     /// namespaces
     Namespace,
 }
 
-/// match the subcommand to get the correct info
+/// Asks the user to confirm the deletion of the targets, on stderr to keep stdout for the results.
+fn confirm(targets: &[Target]) -> Result<bool> {
+    let mut stderr = io::stderr();
+    for target in targets {
+        writeln!(stderr, "{}", target)?;
+    }
+    write!(stderr, "Delete these {} element(s)? [y/N] ", targets.len())?;
+    stderr.flush()?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("Error reading the confirmation")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Resolves the elements to delete, then deletes them.
 pub async fn execute(args: Subcommand, conf: &config::Config) -> Result<String> {
-    match args.subject {
-        Subjects::Resource => resource::execute(conf, args.id.as_str()).await,
-        Subjects::Instance => instance::execute(conf, args.id.as_str()).await,
-        Subjects::Namespace => namespace::execute(conf, args.id.as_str()).await,
-    }?;
+    let client = Client::new(conf).context("Error creating client")?;
+    let namespace = conf.namespace.as_str();
+
+    // a single element named on the command line is deleted without confirmation
+    let (targets, needs_confirmation) = if let Some(file) = &args.file {
+        let yaml = std::fs::read_to_string(file)
+            .with_context(|| format!("Error reading file {}", file))?;
+        (target::from_file(&yaml)?, true)
+    } else {
+        let subject = args
+            .subject
+            .context("You must provide a subject or a file")?;
+        let selector = args.selector.as_ref();
+        if matches!(subject, Subjects::Workloads | Subjects::Instances)
+            && selector.is_none()
+            && !args.all
+        {
+            bail!("You must provide a selector (--selector) or --all to delete several elements");
+        }
+        let id = || {
+            args.id
+                .clone()
+                .context("You must provide the identifier of the element")
+        };
+
+        match subject {
+            Subjects::Workload => (vec![Target::Workload(id()?)], false),
+            Subjects::Instance => (vec![Target::Instance(id()?)], false),
+            Subjects::Namespace => (vec![Target::Namespace(id()?)], false),
+            Subjects::Workloads => (target::workloads(&client, namespace, selector).await?, true),
+            Subjects::Instances => (target::instances(&client, namespace, selector).await?, true),
+        }
+    };
+
+    if targets.is_empty() {
+        info!("Nothing to delete");
+        return Ok(String::new());
+    }
+    if needs_confirmation && !args.force && !confirm(&targets)? {
+        bail!("Deletion cancelled");
+    }
 
-    Ok(String::new())
+    let mut deleted = vec![];
+    for target in targets {
+        debug!("Deleting {}", target);
+        target.delete(&client, namespace).await?;
+        deleted.push(format!("{} deleted", target));
+    }
+    Ok(deleted.join("\n"))
 }
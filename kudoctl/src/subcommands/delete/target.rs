@@ -0,0 +1,114 @@
+use std::fmt::Display;
+
+use crate::{
+    client::{self, request::Client},
+    resource::{selector::Selector, Resource},
+};
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Element of the cluster to delete.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Target {
+    Workload(String),
+    Instance(String),
+    Namespace(String),
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Workload(name) => write!(f, "workload/{}", name),
+            Target::Instance(id) => write!(f, "instance/{}", id),
+            Target::Namespace(name) => write!(f, "namespace/{}", name),
+        }
+    }
+}
+
+impl Target {
+    /// Requests the deletion of the element.
+    pub async fn delete(&self, client: &Client, namespace: &str) -> Result<()> {
+        match self {
+            Target::Workload(name) => client::workload::delete(client, namespace, name).await,
+            Target::Instance(id) => client::instance::delete(client, namespace, id).await,
+            Target::Namespace(name) => client::namespace::delete(client, name).await,
+        }
+    }
+}
+
+/// Returns the elements described by a yaml file, which may hold several documents.
+pub fn from_file(yaml: &str) -> Result<Vec<Target>> {
+    serde_yaml::Deserializer::from_str(yaml)
+        .map(|document| {
+            let resource =
+                Resource::deserialize(document).context("Error parsing file resource")?;
+            Ok(match resource {
+                Resource::Workload(workload) => Target::Workload(workload.name),
+            })
+        })
+        .collect()
+}
+
+/// Returns the workloads of the namespace matching the selector, every workload without selector.
+pub async fn workloads(
+    client: &Client,
+    namespace: &str,
+    selector: Option<&Selector>,
+) -> Result<Vec<Target>> {
+    Ok(client::workload::list(client, namespace)
+        .await?
+        .workloads
+        .into_iter()
+        .filter(|workload| selector.is_none_or(|selector| selector.matches(&workload.labels)))
+        .map(|workload| Target::Workload(workload.name))
+        .collect())
+}
+
+/// Returns the instances of the namespace matching the selector, every instance without selector.
+pub async fn instances(
+    client: &Client,
+    namespace: &str,
+    selector: Option<&Selector>,
+) -> Result<Vec<Target>> {
+    Ok(client::instance::list(client, namespace)
+        .await?
+        .instances
+        .into_iter()
+        .filter(|instance| selector.is_none_or(|selector| selector.matches(&instance.labels)))
+        .map(|instance| Target::Instance(instance.id))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_targets_from_file() {
+        let yaml = r#"
+kind: workload
+name: web
+uri: nginx
+resources:
+  cpu: 1
+  memory: 2
+  disk: 3
+---
+kind: workload
+name: api
+uri: api
+resources:
+  cpu: 1
+  memory: 2
+  disk: 3
+"#;
+
+        assert_eq!(
+            from_file(yaml).unwrap(),
+            vec![
+                Target::Workload("web".to_string()),
+                Target::Workload("api".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{
+    client::{self, instance::Instance, request::Client},
+    config,
+    subcommands::output::{self, OutputFormat, Render},
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Full description of an instance, the human readable format
+/// shows every property of the instance grouped by section.
+#[derive(Serialize)]
+#[serde(transparent)]
+struct InstanceDescription(Instance);
+
+/// describe instance <id> subcommand execution
+/// Does the request, then formats the output.
+pub async fn execute(conf: &config::Config, format: OutputFormat, id: &str) -> Result<String> {
+    let client = Client::new(conf).context("Error creating client")?;
+    let result = client::instance::get(&client, &conf.namespace, id).await?;
+
+    output::format_output(InstanceDescription(result), format)
+}
+
+impl Display for InstanceDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let instance = &self.0;
+
+        writeln!(f, "Id:          {}", instance.id)?;
+        writeln!(f, "Name:        {}", instance.name)?;
+        writeln!(f, "Namespace:   {}", instance.namespace)?;
+        writeln!(f, "Workload:    {}", instance.workload_id)?;
+        writeln!(f, "Type:        {}", instance.r#type)?;
+        writeln!(f, "Uri:         {}", instance.uri)?;
+
+        writeln!(f, "Status:")?;
+        writeln!(f, "  State:       {}", instance.status.state)?;
+        writeln!(
+            f,
+            "  Description: {}",
+            output::or_none(&instance.status.status_description)
+        )?;
+
+        writeln!(f, "Placement:")?;
+        writeln!(f, "  Node: {}", output::or_none(&instance.node_id))?;
+        writeln!(f, "  Ip:   {}", output::or_none(&instance.ip))?;
+
+        writeln!(f, "Resources:")?;
+        writeln!(f, "  Cpu:    {} milliCPU", instance.resources.cpu)?;
+        writeln!(f, "  Memory: {} mB", instance.resources.memory)?;
+        writeln!(f, "  Disk:   {} GB", instance.resources.disk)?;
+
+        writeln!(
+            f,
+            "Ports:       {}",
+            output::or_none(&output::format_ports(&instance.ports))
+        )?;
+
+        writeln!(f, "Environment:")?;
+        for env_var in &instance.environment {
+            writeln!(f, "  {}", env_var)?;
+        }
+        Ok(())
+    }
+}
+
+impl Render for InstanceDescription {
+    fn names(&self) -> Vec<String> {
+        vec![format!("instance/{}", self.0.id)]
+    }
+}
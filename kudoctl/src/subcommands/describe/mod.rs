@@ -0,0 +1,43 @@
+use crate::config;
+use crate::subcommands::output::OutputFormat;
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+mod instance;
+
+#[derive(Debug, Args)]
+/// Show the details of a kudo subject.
+pub struct DescribeSubcommand {
+    /// Change the output format
+    #[clap(
+        short = 'o',
+        long = "output",
+        short_alias = 'F',
+        alias = "format",
+        arg_enum,
+        value_parser
+    )]
+    format: Option<OutputFormat>,
+
+    /// Define the type of element to describe
+    #[clap(arg_enum, value_parser)]
+    subject: DescribeSubjects,
+
+    /// Identifier of the element to describe
+    #[clap(value_name = "ID")]
+    id: String,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+enum DescribeSubjects {
+    /// instances
+    Instance,
+}
+
+/// match the subcommand to describe the correct element
+pub async fn execute(args: DescribeSubcommand, conf: &config::Config) -> Result<String> {
+    let format = args.format.unwrap_or(OutputFormat::HumanReadable);
+
+    match args.subject {
+        DescribeSubjects::Instance => instance::execute(conf, format, &args.id).await,
+    }
+}
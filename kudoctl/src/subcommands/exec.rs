@@ -0,0 +1,193 @@
+use std::io::Write;
+
+use crate::{
+    client::{self, instance::ExecMessage, request::Client},
+    config,
+};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use crossterm::terminal;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, warn};
+use tokio::{io::AsyncReadExt, sync::mpsc};
+use tokio_tungstenite::tungstenite::{protocol::frame::coding::CloseCode, Message};
+
+#[derive(Debug, Args)]
+/// Run a command in an instance.
+pub struct Exec {
+    /// Id of the instance
+    #[clap(value_name = "INSTANCE")]
+    instance: String,
+
+    /// Forward stdin to the command
+    #[clap(short, long)]
+    interactive: bool,
+
+    /// Run the command in a terminal, the local terminal is switched to raw mode
+    #[clap(short, long)]
+    tty: bool,
+
+    /// Command to run, after `--`
+    #[clap(last = true, required = true, value_name = "COMMAND")]
+    command: Vec<String>,
+}
+
+/// Keeps the terminal in raw mode while the session is open, restores it when dropped.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> Result<Self> {
+        terminal::enable_raw_mode().context("Error switching the terminal to raw mode")?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Err(err) = terminal::disable_raw_mode() {
+            warn!("Error restoring the terminal: {}", err);
+        }
+    }
+}
+
+/// Returns the message announcing the current size of the terminal.
+fn resize_message() -> Option<Message> {
+    let (cols, rows) = terminal::size().ok()?;
+    serde_json::to_string(&ExecMessage::Resize { cols, rows })
+        .ok()
+        .map(Message::Text)
+}
+
+/// Forwards stdin to the session until it is closed.
+async fn forward_stdin(messages: mpsc::UnboundedSender<Message>) {
+    let mut stdin = tokio::io::stdin();
+    let mut buffer = [0; 1024];
+
+    while let Ok(read) = stdin.read(&mut buffer).await {
+        if read == 0
+            || messages
+                .send(Message::Binary(buffer[..read].to_vec()))
+                .is_err()
+        {
+            break;
+        }
+    }
+}
+
+/// Sends the new size of the terminal to the session every time it is resized.
+#[cfg(unix)]
+async fn forward_resizes(messages: mpsc::UnboundedSender<Message>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut resizes = match signal(SignalKind::window_change()) {
+        Ok(resizes) => resizes,
+        Err(err) => {
+            warn!("Terminal resizes won't be forwarded: {}", err);
+            return;
+        }
+    };
+    while resizes.recv().await.is_some() {
+        if let Some(message) = resize_message() {
+            if messages.send(message).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn forward_resizes(_messages: mpsc::UnboundedSender<Message>) {}
+
+/// Runs the session, returns the exit code of the command.
+async fn run_session(args: &Exec, conf: &config::Config) -> Result<i32> {
+    let client = Client::new(conf).context("Error creating client")?;
+    let session = client::instance::exec(
+        &client,
+        &conf.namespace,
+        &args.instance,
+        &args.command,
+        args.tty,
+    )
+    .await?;
+    let (mut sink, mut stream) = session.split();
+
+    // every message to the controller goes through this channel, written by a single task
+    let (messages_tx, mut messages_rx) = mpsc::unbounded_channel();
+    if args.tty {
+        if let Some(message) = resize_message() {
+            _ = messages_tx.send(message);
+        }
+        tokio::spawn(forward_resizes(messages_tx.clone()));
+    }
+    if args.interactive {
+        tokio::spawn(forward_stdin(messages_tx.clone()));
+    }
+    drop(messages_tx);
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = messages_rx.recv().await {
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+        _ = sink.close().await;
+    });
+
+    let mut exit_code = 0;
+    let mut stdout = std::io::stdout();
+    while let Some(message) = stream.next().await {
+        match message.context("Error reading the exec session")? {
+            Message::Binary(output) => {
+                stdout.write_all(&output)?;
+                stdout.flush()?;
+            }
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(ExecMessage::Exit { code }) => exit_code = code,
+                _ => debug!("Ignoring exec message {}", text),
+            },
+            Message::Close(Some(frame)) if frame.code != CloseCode::Normal => {
+                writer.abort();
+                bail!("The exec session failed: {}", frame.reason);
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    writer.abort();
+    Ok(exit_code)
+}
+
+pub async fn execute(args: Exec, conf: &config::Config) -> Result<String> {
+    let exit_code = {
+        let _raw_mode = if args.tty {
+            Some(RawMode::enable()?)
+        } else {
+            None
+        };
+        run_session(&args, conf).await?
+    };
+
+    // the terminal is restored, the exit code of the command becomes the one of kudoctl
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_message_format() {
+        assert_eq!(
+            serde_json::to_string(&ExecMessage::Resize { cols: 80, rows: 24 }).unwrap(),
+            r#"{"type":"resize","cols":80,"rows":24}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<ExecMessage>(r#"{"type":"exit","code":127}"#).unwrap(),
+            ExecMessage::Exit { code: 127 }
+        );
+    }
+}
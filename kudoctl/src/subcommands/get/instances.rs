@@ -1,33 +1,124 @@
-use super::output::{self, OutputFormat};
 use crate::{
-    client::{self, instance::GetInstancesResponse, request::Client},
+    client::{
+        self,
+        instance::{GetInstancesResponse, InstanceEvent},
+        lines::LineBuffer,
+        request::Client,
+    },
     config,
+    subcommands::output::{self, OutputFormat, Render, Table},
 };
 use anyhow::{Context, Result};
 use std::fmt::Display;
 
 /// get instances subcommand execution
 /// Does the request, then formats the output.
+/// With `watch`, the changes of the instances are printed as they happen, after the current list.
 pub async fn execute(
     conf: &config::Config,
     format: OutputFormat,
     show_header: bool,
+    watch: bool,
 ) -> Result<String> {
     let client = Client::new(conf).context("Error creating client")?;
+
+    // subscribe before listing, so that no change happens unnoticed in between
+    let stream = if watch {
+        Some(client::instance::watch(&client, &conf.namespace).await?)
+    } else {
+        None
+    };
+
     let mut result = client::instance::list(&client, &conf.namespace).await?;
     result.show_header = show_header;
-    output::format_output(result, format)
+    let output = output::format_output(result, format)?;
+
+    let mut stream = match stream {
+        Some(stream) => stream,
+        None => return Ok(output),
+    };
+    if !output.is_empty() {
+        println!("{}", output.trim_end());
+    }
+
+    let mut buffer = LineBuffer::default();
+    while let Some(chunk) = stream
+        .chunk()
+        .await
+        .context("Error reading the instance changes")?
+    {
+        for line in buffer.push(&chunk) {
+            if let Some(event) = client::instance::parse_event(&line) {
+                println!("{}", format_event(event?, format)?.trim_end());
+            }
+        }
+    }
+
+    Ok(String::new())
 }
 
-impl Display for GetInstancesResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.show_header {
-            writeln!(f, "ID\tSTATUS")?;
+/// Formats a change of an instance: JSON and YAML carry the type of the change,
+/// the other formats print the new state of the instance as a row of the list.
+fn format_event(event: InstanceEvent, format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Json => serde_json::to_string(&event).context("Error formatting output"),
+        OutputFormat::Yaml => serde_yaml::to_string(&event)
+            .map(|yaml| format!("---\n{}", yaml))
+            .context("Error formatting output"),
+        _ => output::format_output(
+            GetInstancesResponse {
+                instances: vec![event.instance],
+                show_header: false,
+            },
+            format,
+        ),
+    }
+}
+
+impl GetInstancesResponse {
+    fn table(&self, wide: bool) -> Table {
+        let mut headers = vec!["ID", "NAME", "STATE", "NODE", "IP"];
+        if wide {
+            headers.extend(["WORKLOAD", "PORTS", "DESCRIPTION"]);
         }
+        let mut table = Table::new(&headers, self.show_header);
 
         for inst in &self.instances {
-            writeln!(f, "{}\t{}\n", inst.id, inst.status)?;
+            let mut row = vec![
+                inst.id.clone(),
+                inst.name.clone(),
+                inst.status.state.clone(),
+                output::or_none(&inst.node_id),
+                output::or_none(&inst.ip),
+            ];
+            if wide {
+                row.extend([
+                    inst.workload_id.clone(),
+                    output::format_ports(&inst.ports),
+                    inst.status.status_description.clone(),
+                ]);
+            }
+            table.add_row(row);
         }
-        Ok(())
+        table
+    }
+}
+
+impl Display for GetInstancesResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.table(false))
+    }
+}
+
+impl Render for GetInstancesResponse {
+    fn wide(&self) -> String {
+        self.table(true).to_string()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.instances
+            .iter()
+            .map(|instance| format!("instance/{}", instance.id))
+            .collect()
     }
 }
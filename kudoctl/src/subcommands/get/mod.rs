@@ -3,24 +3,34 @@ mod instances;
 mod namespaces;
 mod node;
 mod nodes;
-mod output;
-mod resource;
-mod resources;
-use self::output::OutputFormat;
+mod workload;
+mod workloads;
+use super::output::OutputFormat;
 use crate::config;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::{Args, ValueEnum};
 
 #[derive(Debug, Args)]
 pub struct GetSubcommand {
     /// Change the output format
-    #[clap(short = 'F', long, arg_enum, value_parser)]
+    #[clap(
+        short = 'o',
+        long = "output",
+        short_alias = 'F',
+        alias = "format",
+        arg_enum,
+        value_parser
+    )]
     format: Option<OutputFormat>,
 
     /// Don’t show header (human readable only)
     #[clap(long)]
     no_header: bool,
 
+    /// Print the changes as they happen after the list (instances only)
+    #[clap(short, long)]
+    watch: bool,
+
     /// Define the type of element(s) to get
     ///
     /// Add an id after the type to get the element.
@@ -36,9 +46,11 @@ pub struct GetSubcommand {
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum GetSubjects {
-    /// resources
-    Resources,
-    Resource,
+    /// workloads
+    #[clap(alias = "resources")]
+    Workloads,
+    #[clap(alias = "resource")]
+    Workload,
 
     /// instances
     Instances,
@@ -57,10 +69,14 @@ pub async fn execute(args: GetSubcommand, conf: &config::Config) -> Result<Strin
     let format = args.format.unwrap_or(OutputFormat::HumanReadable);
     let show_header = !args.no_header;
 
+    if args.watch && args.subject != GetSubjects::Instances {
+        bail!("--watch is only supported for instances");
+    }
+
     match args.subject {
-        GetSubjects::Resources => resources::execute(conf, format, show_header).await,
-        GetSubjects::Resource => resource::execute(conf, format, args.id).await,
-        GetSubjects::Instances => instances::execute(conf, format, show_header).await,
+        GetSubjects::Workloads => workloads::execute(conf, format, show_header).await,
+        GetSubjects::Workload => workload::execute(conf, format, args.id).await,
+        GetSubjects::Instances => instances::execute(conf, format, show_header, args.watch).await,
         GetSubjects::Instance => instance::execute(conf, format, args.id).await,
         GetSubjects::Nodes => nodes::execute(conf, format, show_header).await,
         GetSubjects::Node => node::execute(conf, format, args.id).await,
@@ -1,4 +1,4 @@
-use super::output::{self, OutputFormat};
+use crate::subcommands::output::{self, OutputFormat, Render, Table};
 use crate::{
     client::{self, namespace::GetNamespacesResponse, request::Client},
     config,
@@ -21,13 +21,23 @@ pub async fn execute(
 
 impl Display for GetNamespacesResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.show_header {
-            writeln!(f, "NAMEI\tINSTANCES")?;
-        }
+        let mut table = Table::new(&["NAME", "INSTANCES"], self.show_header);
 
-        for node in &self.namespaces {
-            writeln!(f, "{}\t{}", node.name, node.instances.len())?;
+        for namespace in &self.namespaces {
+            table.add_row(vec![
+                namespace.name.clone(),
+                namespace.instances.len().to_string(),
+            ]);
         }
-        Ok(())
+        write!(f, "{}", table)
+    }
+}
+
+impl Render for GetNamespacesResponse {
+    fn names(&self) -> Vec<String> {
+        self.namespaces
+            .iter()
+            .map(|namespace| format!("namespace/{}", namespace.name))
+            .collect()
     }
 }
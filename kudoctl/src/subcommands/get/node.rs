@@ -1,4 +1,4 @@
-use super::output::{self, OutputFormat};
+use crate::subcommands::output::{self, OutputFormat, Render};
 use crate::{
     client::{self, node::Node, request::Client},
     config,
@@ -46,3 +46,9 @@ impl Display for Node {
         Ok(())
     }
 }
+
+impl Render for Node {
+    fn names(&self) -> Vec<String> {
+        vec![format!("node/{}", self.id)]
+    }
+}
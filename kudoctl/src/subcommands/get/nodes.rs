@@ -1,4 +1,4 @@
-use super::output::{self, OutputFormat};
+use crate::subcommands::output::{self, OutputFormat, Render, Table};
 use crate::{
     client::{self, node::GetNodesResponse, request::Client},
     config,
@@ -19,21 +19,48 @@ pub async fn execute(
     output::format_output(result, format)
 }
 
-impl Display for GetNodesResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.show_header {
-            writeln!(f, "ID\tSTATUS\tINSTANCES")?;
+impl GetNodesResponse {
+    fn table(&self, wide: bool) -> Table {
+        let mut headers = vec!["ID", "STATUS", "INSTANCES"];
+        if wide {
+            headers.extend(["CPU", "MEMORY", "DISK"]);
         }
+        let mut table = Table::new(&headers, self.show_header);
 
         for node in &self.nodes {
-            writeln!(
-                f,
-                "{}\t{}\t{}\n",
-                node.id,
-                node.status_description,
-                node.instances.len()
-            )?;
+            let mut row = vec![
+                node.id.clone(),
+                node.status_description.clone(),
+                node.instances.len().to_string(),
+            ];
+            if wide {
+                row.extend([
+                    format!("{}m", node.resource.cpu),
+                    format!("{}mB", node.resource.memory),
+                    format!("{}GB", node.resource.disk),
+                ]);
+            }
+            table.add_row(row);
         }
-        Ok(())
+        table
+    }
+}
+
+impl Display for GetNodesResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.table(false))
+    }
+}
+
+impl Render for GetNodesResponse {
+    fn wide(&self) -> String {
+        self.table(true).to_string()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.nodes
+            .iter()
+            .map(|node| format!("node/{}", node.id))
+            .collect()
     }
 }
@@ -0,0 +1,47 @@
+use crate::{
+    client::{self, request::Client, workload::WorkloadInfo},
+    config,
+    subcommands::output::{self, OutputFormat, Render},
+};
+use anyhow::{bail, Context, Result};
+use std::fmt::Display;
+
+/// get workload <name> subcommand execution
+/// Does the request, then formats the output.
+pub async fn execute(
+    conf: &config::Config,
+    format: OutputFormat,
+    search: Option<String>,
+) -> Result<String> {
+    if let Some(search) = search {
+        let client = Client::new(conf).context("Error creating client")?;
+        let result = client::workload::get(&client, &conf.namespace, search.as_str()).await?;
+
+        output::format_output(result, format)
+    } else {
+        bail!("You must provide a workload name");
+    }
+}
+
+impl Display for WorkloadInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "id : {}", self.id)?;
+        writeln!(f, "name : {}", self.name)?;
+        writeln!(f, "namespace : {}", self.namespace)?;
+        writeln!(f, "uri : {}", self.uri)?;
+        writeln!(f, "ports : {}", output::format_ports(&self.ports))?;
+        writeln!(f, "env variables : {}", self.environment.join(","))?;
+        writeln!(
+            f,
+            "resources : {}milliCPU, {}mB memory, {}GB disk",
+            self.resources.cpu, self.resources.memory, self.resources.disk
+        )?;
+        Ok(())
+    }
+}
+
+impl Render for WorkloadInfo {
+    fn names(&self) -> Vec<String> {
+        vec![format!("workload/{}", self.name)]
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{
+    client::{self, request::Client, workload::GetWorkloadsResponse},
+    config,
+    subcommands::output::{self, OutputFormat, Render, Table},
+};
+use anyhow::{Context, Result};
+use std::fmt::Display;
+
+/// get workloads subcommand execution
+/// Does the request, then formats the output.
+pub async fn execute(
+    conf: &config::Config,
+    format: OutputFormat,
+    show_header: bool,
+) -> Result<String> {
+    let client = Client::new(conf).context("Error creating client")?;
+    let mut result = client::workload::list(&client, &conf.namespace).await?;
+    result.show_header = show_header;
+    output::format_output(result, format)
+}
+
+impl GetWorkloadsResponse {
+    fn table(&self, wide: bool) -> Table {
+        let mut headers = vec!["NAME", "URI", "PORTS"];
+        if wide {
+            headers.extend(["ID", "ENV"]);
+        }
+        let mut table = Table::new(&headers, self.show_header);
+
+        for workload in &self.workloads {
+            let mut row = vec![
+                workload.name.clone(),
+                workload.uri.clone(),
+                output::format_ports(&workload.ports),
+            ];
+            if wide {
+                row.extend([workload.id.clone(), workload.environment.join(",")]);
+            }
+            table.add_row(row);
+        }
+        table
+    }
+}
+
+impl Display for GetWorkloadsResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.table(false))
+    }
+}
+
+impl Render for GetWorkloadsResponse {
+    fn wide(&self) -> String {
+        self.table(true).to_string()
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.workloads
+            .iter()
+            .map(|workload| format!("workload/{}", workload.name))
+            .collect()
+    }
+}
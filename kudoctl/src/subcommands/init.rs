@@ -0,0 +1,257 @@
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use crate::config::{self, read_config_file, write_config_file};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Args;
+use futures_util::future::select_all;
+use log::{info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use tokio::process::{Child, Command};
+
+/// Name of the config file of the scheduler, read from its working directory.
+pub const SCHEDULER_CONFIG: &str = "scheduler.conf";
+/// Name of the config file of the controller, read from its working directory.
+pub const CONTROLLER_CONFIG: &str = "controller.conf";
+/// Context of the config file targeting the development cluster.
+const LOCAL_CONTEXT: &str = "local";
+
+const SCHEDULER_PORT: u16 = 50052;
+const JOIN_PORT: u16 = 50053;
+const NODE_PORT: u16 = 50054;
+const CONTROLLER_GRPC_ADDRESS: &str = "127.0.0.1:50051";
+const CONTROLLER_HTTP_ADDRESS: &str = "127.0.0.1:3000";
+const DEFAULT_ETCD_ADDRESS: &str = "127.0.0.1:2379";
+
+#[derive(Debug, Args)]
+/// Start a development cluster in the foreground: etcd, the scheduler and the controller.
+///
+/// Each component runs as a child process of kudoctl from its own binary, they aren't embedded in one process.
+/// The resources have no embedded storage, so etcd is started from its binary unless `--etcd-address` is given.
+/// The configs of the components are written in the cluster directory on the first run, the next runs start the same cluster again.
+/// The `local` context targeting the controller becomes the current context.
+/// Print the config of the nodes joining the cluster with `kudoctl join`.
+pub struct Init {
+    /// Directory of the configs, data and logs of the cluster, `~/.kudo/cluster` by default
+    #[clap(long)]
+    dir: Option<PathBuf>,
+
+    /// Directory of the etcd, scheduler and controller binaries, they are searched in the PATH otherwise
+    #[clap(long)]
+    bin_dir: Option<PathBuf>,
+
+    /// Address of an etcd already running, instead of starting one
+    #[clap(long)]
+    etcd_address: Option<String>,
+
+    /// Address the scheduler listens on, the nodes join the cluster through it
+    #[clap(long, default_value = "127.0.0.1")]
+    advertise_address: String,
+}
+
+/// A child process running a component of the cluster.
+struct Component {
+    name: &'static str,
+    process: Child,
+}
+
+/// Returns the directory of the cluster, `~/.kudo/cluster` if not set.
+pub fn cluster_dir(dir: Option<PathBuf>) -> Result<PathBuf> {
+    match dir {
+        Some(dir) => Ok(dir),
+        None => Ok(config::config_file_path()
+            .parent()
+            .ok_or_else(|| anyhow!("Could not find the directory of the config file"))?
+            .join("cluster")),
+    }
+}
+
+/// Returns a random join token.
+fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+/// Returns the config of a scheduler listening on `host`, with a CA issuing the certificates of
+/// the nodes presenting `token`.
+fn scheduler_config(host: &str, token: &str) -> String {
+    format!(
+        r#"host = "{host}"
+port = {SCHEDULER_PORT}
+
+[pki]
+ca_certificate = "ca.crt"
+ca_key = "ca.key"
+join_tokens = ["{token}"]
+join_port = {JOIN_PORT}
+node_port = {NODE_PORT}
+server_names = ["localhost", "{host}"]
+"#
+    )
+}
+
+/// Returns the config of a controller using the scheduler listening on `scheduler_host`.
+fn controller_config(etcd_address: &str, scheduler_host: &str) -> String {
+    format!(
+        r#"[internal_api]
+grpc_server_addr = "{CONTROLLER_GRPC_ADDRESS}"
+
+[external_api]
+http_server_addr = "{CONTROLLER_HTTP_ADDRESS}"
+http_server_num_workers = 1
+etcd_address = "{etcd_address}"
+scheduler_address = "{scheduler_host}:{SCHEDULER_PORT}"
+"#
+    )
+}
+
+/// Writes a config file unless it already exists, the configs edited by hand are kept.
+fn write_config(path: &Path, content: impl FnOnce() -> String) -> Result<()> {
+    if path.exists() {
+        info!("Using the existing config {}", path.display());
+        return Ok(());
+    }
+    fs::write(path, content()).with_context(|| format!("Error writing {}", path.display()))
+}
+
+/// Starts a component in the cluster directory, its output is written in `<name>.log`.
+fn spawn(
+    name: &'static str,
+    bin_dir: Option<&Path>,
+    dir: &Path,
+    args: &[&str],
+) -> Result<Component> {
+    let program = bin_dir
+        .map(|bin_dir| bin_dir.join(name))
+        .unwrap_or_else(|| PathBuf::from(name));
+    let log_path = dir.join(format!("{}.log", name));
+    let log = File::create(&log_path)
+        .with_context(|| format!("Error creating {}", log_path.display()))?;
+
+    let process = Command::new(&program)
+        .args(args)
+        .current_dir(dir)
+        .env(
+            "RUST_LOG",
+            env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
+        )
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Error starting {}, is it installed?", program.display()))?;
+
+    info!("Started {}, logs in {}", name, log_path.display());
+    Ok(Component { name, process })
+}
+
+/// Makes the `local` context, targeting the controller of the cluster, the current one.
+fn use_local_context(conf: &config::Config) -> Result<()> {
+    let path = &conf.config_file;
+    let mut config_file = read_config_file(path).map_err(|err| anyhow!("{}", err))?;
+    config_file.context_mut(LOCAL_CONTEXT).controller_url =
+        format!("http://{}", CONTROLLER_HTTP_ADDRESS);
+    config_file.current_context = LOCAL_CONTEXT.to_string();
+    write_config_file(path, &config_file).map_err(|err| anyhow!("{}", err))
+}
+
+/// init subcommand execution, runs the cluster until Ctrl-C or until one of its components exits.
+pub async fn execute(args: Init, conf: &config::Config) -> Result<String> {
+    let dir = cluster_dir(args.dir)?;
+    fs::create_dir_all(&dir).with_context(|| format!("Error creating {}", dir.display()))?;
+
+    let etcd_address = args
+        .etcd_address
+        .clone()
+        .unwrap_or_else(|| DEFAULT_ETCD_ADDRESS.to_string());
+    write_config(&dir.join(SCHEDULER_CONFIG), || {
+        scheduler_config(&args.advertise_address, &generate_token())
+    })?;
+    write_config(&dir.join(CONTROLLER_CONFIG), || {
+        controller_config(&etcd_address, &args.advertise_address)
+    })?;
+    use_local_context(conf)?;
+
+    let bin_dir = args.bin_dir.as_deref();
+    let mut components = vec![];
+    if args.etcd_address.is_none() {
+        components.push(spawn("etcd", bin_dir, &dir, &["--data-dir", "etcd"])?);
+    }
+    components.push(spawn("scheduler", bin_dir, &dir, &[])?);
+    components.push(spawn("controller", bin_dir, &dir, &[])?);
+
+    info!(
+        "Cluster running, the controller listens on http://{}",
+        CONTROLLER_HTTP_ADDRESS
+    );
+    info!(
+        "Run `kudoctl join --dir {}` to print the config of a node, Ctrl-C to stop the cluster",
+        dir.display()
+    );
+
+    let exited = tokio::select! {
+        (status, index, _) = select_all(
+            components
+                .iter_mut()
+                .map(|component| Box::pin(component.process.wait())),
+        ) => Some((index, status)),
+        _ = tokio::signal::ctrl_c() => None,
+    };
+
+    for component in components.iter_mut() {
+        // the component which exited is already reaped
+        if let Ok(None) = component.process.try_wait() {
+            if let Err(err) = component.process.kill().await {
+                warn!("Error stopping {}: {}", component.name, err);
+            }
+        }
+    }
+
+    match exited {
+        Some((index, status)) => bail!(
+            "{} exited ({}), see {}",
+            components[index].name,
+            status.map_or_else(|err| err.to_string(), |status| status.to_string()),
+            dir.join(format!("{}.log", components[index].name))
+                .display()
+        ),
+        None => Ok("Cluster stopped".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_configs() {
+        let scheduler: toml::Value =
+            toml::from_str(&scheduler_config("10.0.0.1", "secret")).unwrap();
+        assert_eq!(scheduler["host"].as_str(), Some("10.0.0.1"));
+        assert_eq!(
+            scheduler["pki"]["join_tokens"].as_array().unwrap()[0].as_str(),
+            Some("secret")
+        );
+        assert_eq!(
+            scheduler["pki"]["join_port"].as_integer(),
+            Some(JOIN_PORT.into())
+        );
+
+        let controller: toml::Value =
+            toml::from_str(&controller_config("127.0.0.1:2379", "10.0.0.1")).unwrap();
+        assert_eq!(
+            controller["external_api"]["scheduler_address"].as_str(),
+            Some("10.0.0.1:50052")
+        );
+
+        let token = generate_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, generate_token());
+    }
+}
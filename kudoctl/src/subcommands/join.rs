@@ -0,0 +1,95 @@
+use std::{fs, path::PathBuf};
+
+use super::init::{cluster_dir, SCHEDULER_CONFIG};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Args)]
+/// Print the config of a node agent joining the cluster started by `kudoctl init`.
+///
+/// The config holds the addresses of the scheduler, a join token and the certificate of the CA of the cluster.
+/// The scheduler must have been started once, it creates the CA.
+pub struct Join {
+    /// Directory of the cluster, `~/.kudo/cluster` by default
+    #[clap(long)]
+    dir: Option<PathBuf>,
+}
+
+/// The part of the config of the scheduler read to join its cluster.
+#[derive(Debug, Deserialize)]
+struct SchedulerConfig {
+    host: String,
+    pki: Option<PkiConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PkiConfig {
+    ca_certificate: PathBuf,
+    join_tokens: Vec<String>,
+    join_port: u16,
+    node_port: u16,
+}
+
+/// The config of a node agent.
+#[derive(Debug, Serialize)]
+struct NodeAgentConfig {
+    scheduler: SchedulerJoinConfig,
+}
+
+/// The scheduler a node agent joins.
+///
+/// Properties:
+///
+/// * `join_address`: The address of the bootstrap service, issuing the certificate of the node.
+/// * `node_address`: The address of the node service, used with the certificate of the node.
+/// * `join_token`: The token presented to the bootstrap service.
+/// * `ca_certificate`: The PEM certificate of the CA of the cluster, authenticating the scheduler.
+#[derive(Debug, Serialize)]
+struct SchedulerJoinConfig {
+    join_address: String,
+    node_address: String,
+    join_token: String,
+    ca_certificate: String,
+}
+
+/// join subcommand execution, returns the config of a node agent.
+pub async fn execute(args: Join) -> Result<String> {
+    let dir = cluster_dir(args.dir)?;
+    let path = dir.join(SCHEDULER_CONFIG);
+    let content = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "Error reading {}, start the cluster with `kudoctl init` first",
+            path.display()
+        )
+    })?;
+    let scheduler: SchedulerConfig =
+        toml::from_str(&content).with_context(|| format!("Error parsing {}", path.display()))?;
+
+    let Some(pki) = scheduler.pki else {
+        bail!("The scheduler has no CA, the nodes can't join the cluster");
+    };
+    let Some(join_token) = pki.join_tokens.first() else {
+        bail!(
+            "The scheduler has no join token, add one to {}",
+            path.display()
+        );
+    };
+    let ca_path = dir.join(&pki.ca_certificate);
+    let ca_certificate = fs::read_to_string(&ca_path).with_context(|| {
+        format!(
+            "Error reading {}, the CA is created when the scheduler starts",
+            ca_path.display()
+        )
+    })?;
+
+    let config = NodeAgentConfig {
+        scheduler: SchedulerJoinConfig {
+            join_address: format!("{}:{}", scheduler.host, pki.join_port),
+            node_address: format!("{}:{}", scheduler.host, pki.node_port),
+            join_token: join_token.clone(),
+            ca_certificate,
+        },
+    };
+    Ok(toml::to_string_pretty(&config)?.trim_end().to_string())
+}
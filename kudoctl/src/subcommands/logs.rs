@@ -0,0 +1,115 @@
+use std::io::Write;
+
+use crate::{
+    client::{self, lines::LineBuffer, request::Client},
+    config,
+};
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use futures_util::future::join_all;
+use log::{debug, warn};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Args)]
+/// Print the output of an instance, or of every instance of a workload.
+pub struct Logs {
+    /// Id of the instance
+    #[clap(value_name = "INSTANCE", required_unless_present = "workload")]
+    instance: Option<String>,
+
+    /// Print the logs of every instance of the workload, each line prefixed by the instance name
+    #[clap(short, long, conflicts_with = "instance")]
+    workload: Option<String>,
+
+    /// Keep streaming the new lines
+    #[clap(short, long)]
+    follow: bool,
+
+    /// Number of lines to show from the end of the logs, every line is shown if not set
+    #[clap(long)]
+    tail: Option<u32>,
+}
+
+/// Reads the log stream of an instance and sends its lines, with the given prefix.
+async fn stream_logs(
+    client: &Client,
+    conf: &config::Config,
+    instance_id: &str,
+    args: &Logs,
+    prefix: String,
+    lines: mpsc::UnboundedSender<String>,
+) -> Result<()> {
+    let mut response =
+        client::instance::logs(client, &conf.namespace, instance_id, args.follow, args.tail)
+            .await?;
+
+    let mut buffer = LineBuffer::default();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_context(|| format!("Error reading logs of instance {}", instance_id))?
+    {
+        for line in buffer.push(&chunk) {
+            _ = lines.send(format!("{}{}", prefix, line));
+        }
+    }
+    if let Some(line) = buffer.finish() {
+        _ = lines.send(format!("{}{}", prefix, line));
+    }
+
+    debug!("Log stream of instance {} closed", instance_id);
+    Ok(())
+}
+
+pub async fn execute(args: Logs, conf: &config::Config) -> Result<String> {
+    let client = Client::new(conf).context("Error creating client")?;
+
+    // the instances to follow, with the prefix of their lines
+    let targets: Vec<(String, String)> = match (&args.instance, &args.workload) {
+        (_, Some(workload)) => {
+            let workload_id = format!("{}.{}", conf.namespace, workload);
+            let instances: Vec<(String, String)> = client::instance::list(&client, &conf.namespace)
+                .await?
+                .instances
+                .into_iter()
+                .filter(|instance| instance.workload_id == workload_id)
+                .map(|instance| (instance.id, format!("[{}] ", instance.name)))
+                .collect();
+
+            if instances.is_empty() {
+                bail!("No instance of workload {} found", workload);
+            }
+            instances
+        }
+        (Some(instance), None) => vec![(instance.clone(), String::new())],
+        (None, None) => bail!("You must provide an instance id or a workload"),
+    };
+
+    let (lines_tx, mut lines_rx) = mpsc::unbounded_channel();
+    let streams: Vec<_> = targets
+        .iter()
+        .map(|(id, prefix)| stream_logs(&client, conf, id, &args, prefix.clone(), lines_tx.clone()))
+        .collect();
+    drop(lines_tx);
+
+    let print = async {
+        let mut stdout = std::io::stdout();
+        while let Some(line) = lines_rx.recv().await {
+            _ = writeln!(stdout, "{}", line);
+        }
+    };
+    let (results, _) = tokio::join!(join_all(streams), print);
+
+    // a stream failing doesn't stop the others, report it once they are all done
+    if targets.len() == 1 {
+        results.into_iter().try_for_each(|result| result)?;
+    } else {
+        for ((id, _), result) in targets.iter().zip(results) {
+            if let Err(err) = result {
+                warn!("Logs of instance {} interrupted: {:#}", id, err);
+            }
+        }
+    }
+
+    Ok(String::new())
+}
@@ -2,14 +2,31 @@ use crate::config;
 use clap::Subcommand;
 use log::error;
 mod apply;
+mod completion;
+mod context;
 mod delete;
+mod describe;
+mod exec;
 mod get;
+mod init;
+mod join;
+mod logs;
+mod output;
 
 #[derive(Subcommand)]
 pub enum Subcommands {
     Apply(apply::Apply),
     Get(get::GetSubcommand),
+    Describe(describe::DescribeSubcommand),
+    Logs(logs::Logs),
+    Exec(exec::Exec),
+    Config(context::ConfigSubcommand),
+    Completion(completion::Completion),
+    #[clap(name = "complete-values", hide = true)]
+    Complete(completion::Complete),
     Delete(delete::Subcommand),
+    Init(init::Init),
+    Join(join::Join),
 }
 
 /// Match the subcommand to execute
@@ -21,7 +38,15 @@ pub async fn match_subcommand(command: Subcommands, conf: &config::Config) {
     let result = match command {
         Subcommands::Apply(args) => apply::execute(args, conf).await,
         Subcommands::Get(args) => get::execute(args, conf).await,
+        Subcommands::Describe(args) => describe::execute(args, conf).await,
+        Subcommands::Logs(args) => logs::execute(args, conf).await,
+        Subcommands::Exec(args) => exec::execute(args, conf).await,
+        Subcommands::Config(args) => context::execute(args, conf).await,
+        Subcommands::Completion(args) => completion::execute(args).await,
+        Subcommands::Complete(args) => completion::execute_complete(args, conf).await,
         Subcommands::Delete(args) => delete::execute(args, conf).await,
+        Subcommands::Init(args) => init::execute(args, conf).await,
+        Subcommands::Join(args) => join::execute(args).await,
     };
 
     // Print the result or the error
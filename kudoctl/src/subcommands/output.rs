@@ -0,0 +1,141 @@
+use std::fmt::Display;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::client::workload::Port;
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum OutputFormat {
+    /// Human readable format
+    HumanReadable,
+    /// Human readable format, with every column for the lists
+    Wide,
+    /// Kind and name of the elements, one per line (e.g. `instance/<id>`)
+    Name,
+    /// JSON format
+    Json,
+    /// YAML format
+    Yaml,
+}
+
+/// Output of a command, rendered in the format chosen with `--output`.
+///
+/// The human readable format is the `Display` implementation,
+/// JSON and YAML serialize the model returned by the controller.
+pub trait Render: Serialize + Display {
+    /// Human readable format with every column, the same as `Display` if not overridden.
+    fn wide(&self) -> String {
+        self.to_string()
+    }
+
+    /// Kind and name of the elements, e.g. `instance/<id>`.
+    fn names(&self) -> Vec<String>;
+}
+
+/// Formats the output of a command.
+pub fn format_output<T: Render>(output: T, format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::HumanReadable => Ok(format!("{}", output)),
+        OutputFormat::Wide => Ok(output.wide()),
+        OutputFormat::Name => Ok(output.names().join("\n")),
+        OutputFormat::Json => serde_json::to_string(&output).map_err(anyhow::Error::from),
+        OutputFormat::Yaml => serde_yaml::to_string(&output).map_err(anyhow::Error::from),
+    }
+    .context("Error formatting output")
+}
+
+/// Formats the ports of a workload or an instance as `source->destination` pairs.
+pub fn format_ports(ports: &[Port]) -> String {
+    ports
+        .iter()
+        .map(|port| format!("{}->{}", port.source, port.destination))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// Returns the value, or `<none>` when it is empty (e.g. the node of an instance not scheduled yet).
+pub fn or_none(value: &str) -> String {
+    if value.is_empty() {
+        "<none>".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Table printed by the human readable format of the `get` commands,
+/// each column being as wide as its widest cell.
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+    show_header: bool,
+}
+
+impl Table {
+    pub fn new(headers: &[&str], show_header: bool) -> Self {
+        Table {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: vec![],
+            show_header,
+        }
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut widths: Vec<usize> = self.headers.iter().map(|header| header.len()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut write_row = |row: &[String]| {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<String>>()
+                .join("   ");
+            writeln!(f, "{}", line.trim_end())
+        };
+
+        if self.show_header {
+            write_row(&self.headers)?;
+        }
+        for row in &self.rows {
+            write_row(row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_alignment() {
+        let mut table = Table::new(&["ID", "STATE"], true);
+        table.add_row(vec!["1".to_string(), "Running".to_string()]);
+        table.add_row(vec!["1234".to_string(), "Scheduling".to_string()]);
+
+        assert_eq!(
+            table.to_string(),
+            "ID     STATE\n1      Running\n1234   Scheduling\n"
+        );
+    }
+
+    #[test]
+    fn test_table_without_header() {
+        let mut table = Table::new(&["ID"], false);
+        table.add_row(vec!["1".to_string()]);
+
+        assert_eq!(table.to_string(), "1\n");
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    fs,
+    io::Write,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use cidr::Ipv4Inet;
+use serde_json::{json, Map, Value};
+
+use crate::error::KudoNetworkError;
+use crate::port::Port;
+use crate::utils::{namespace_name, run_command};
+
+/// Directory of the network configurations, shared with the other container runtimes
+pub const DEFAULT_CONF_DIR: &str = "/etc/cni/net.d";
+/// Directory of the plugins executables
+pub const DEFAULT_BIN_DIR: &str = "/opt/cni/bin";
+/// Directory of the network namespaces created by `ip netns`
+pub const NETNS_DIR: &str = "/var/run/netns";
+/// Name of the interface created by the plugins inside the instance namespace
+pub const INSTANCE_INTERFACE: &str = "eth0";
+
+/// Where the network configurations and the plugins are found
+pub struct CniConfig {
+    pub conf_dir: PathBuf,
+    pub bin_dirs: Vec<PathBuf>,
+}
+
+impl Default for CniConfig {
+    fn default() -> Self {
+        Self {
+            conf_dir: PathBuf::from(DEFAULT_CONF_DIR),
+            bin_dirs: vec![PathBuf::from(DEFAULT_BIN_DIR)],
+        }
+    }
+}
+
+/// A network configuration list, the plugins are invoked in order to add an instance to the
+/// network and in reverse order to remove it
+#[derive(Debug)]
+pub struct NetworkConfigList {
+    pub name: String,
+    pub cni_version: String,
+    pub plugins: Vec<Value>,
+}
+
+impl NetworkConfigList {
+    /// Parse a `.conflist` file, or a `.conf` file holding a single plugin
+    pub fn from_json(json: &str, is_list: bool) -> Result<Self, KudoNetworkError> {
+        let config: Value = serde_json::from_str(json)
+            .map_err(|e| KudoNetworkError::CniError(format!("Invalid configuration: {}", e)))?;
+
+        let field = |name: &str| {
+            config
+                .get(name)
+                .and_then(Value::as_str)
+                .map(String::from)
+                .ok_or_else(|| KudoNetworkError::CniError(format!("Missing field {}", name)))
+        };
+        let name = field("name")?;
+        let cni_version = field("cniVersion")?;
+
+        let plugins = if is_list {
+            config
+                .get("plugins")
+                .and_then(Value::as_array)
+                .cloned()
+                .ok_or_else(|| KudoNetworkError::CniError("Missing field plugins".to_string()))?
+        } else {
+            vec![config.clone()]
+        };
+        if plugins.is_empty() {
+            return Err(KudoNetworkError::CniError(format!(
+                "Network {} has no plugin",
+                name
+            )));
+        }
+
+        Ok(Self {
+            name,
+            cni_version,
+            plugins,
+        })
+    }
+
+    /// Load the first configuration of `conf_dir` in lexical order, as the other runtimes do
+    pub fn load(conf_dir: &Path) -> Result<Self, KudoNetworkError> {
+        let mut files: Vec<PathBuf> = fs::read_dir(conf_dir)
+            .map_err(|e| {
+                KudoNetworkError::CniError(format!("Cannot read {}: {}", conf_dir.display(), e))
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(|extension| extension.to_str()),
+                    Some("conflist" | "conf" | "json")
+                )
+            })
+            .collect();
+        files.sort();
+
+        let file = files.first().ok_or_else(|| {
+            KudoNetworkError::CniError(format!(
+                "No network configuration in {}",
+                conf_dir.display()
+            ))
+        })?;
+        let json = fs::read_to_string(file).map_err(|e| {
+            KudoNetworkError::CniError(format!("Cannot read {}: {}", file.display(), e))
+        })?;
+        Self::from_json(&json, file.extension().is_some_and(|e| e == "conflist"))
+    }
+
+    /// Build the configuration given to a plugin: its own configuration, completed with the name
+    /// and version of the network, the result of the previous plugin and the runtime
+    /// configuration of the capabilities it supports
+    pub fn plugin_config(
+        &self,
+        index: usize,
+        prev_result: Option<&Value>,
+        ports: &[Port],
+    ) -> Value {
+        let mut config = self.plugins[index].as_object().cloned().unwrap_or_default();
+        config.insert("name".to_string(), json!(self.name));
+        config.insert("cniVersion".to_string(), json!(self.cni_version));
+        if let Some(prev_result) = prev_result {
+            config.insert("prevResult".to_string(), prev_result.clone());
+        }
+
+        let port_mappings = config
+            .get("capabilities")
+            .and_then(|capabilities| capabilities.get("portMappings"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if port_mappings && !ports.is_empty() {
+            let mappings: Vec<Value> = ports
+                .iter()
+                .flat_map(|port| {
+                    ["tcp", "udp"].map(|protocol| {
+                        json!({
+                            "hostPort": port.source,
+                            "containerPort": port.destination,
+                            "protocol": protocol,
+                        })
+                    })
+                })
+                .collect();
+            let mut runtime_config = Map::new();
+            runtime_config.insert("portMappings".to_string(), json!(mappings));
+            config.insert("runtimeConfig".to_string(), Value::Object(runtime_config));
+        }
+
+        Value::Object(config)
+    }
+}
+
+/// The interface configured by the plugins for an instance
+pub struct CniResult {
+    pub namespace_name: String,
+    pub interface_name: String,
+    /// The addresses given to the instance, with their mask
+    pub ips: Vec<Ipv4Inet>,
+}
+
+/// Parse the IPv4 addresses of the result of a plugin
+pub fn result_ips(result: &Value) -> Vec<Ipv4Inet> {
+    result
+        .get("ips")
+        .and_then(Value::as_array)
+        .map(|ips| {
+            ips.iter()
+                .filter_map(|ip| ip.get("address").and_then(Value::as_str))
+                .filter_map(|address| Ipv4Inet::from_str(address).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Invoke the CNI plugins of a network to configure the network of the instances, instead of
+/// the network set up by `instance::setup_instance`. The node agent adds the main container of
+/// each instance it creates with `add_instance`, when a CNI network is configured on its node
+pub struct CniNetwork {
+    config: CniConfig,
+    network: NetworkConfigList,
+}
+
+impl CniNetwork {
+    pub fn load(config: CniConfig) -> Result<Self, KudoNetworkError> {
+        let network = NetworkConfigList::load(&config.conf_dir)?;
+        Ok(Self { config, network })
+    }
+
+    /// Create the network namespace of an instance and add it to the network
+    pub fn setup_instance(
+        &self,
+        instance_id: &str,
+        ports: &[Port],
+    ) -> Result<CniResult, KudoNetworkError> {
+        let namespace = namespace_name(instance_id.to_string());
+        run_command("ip", &["netns", "add", &namespace])?;
+
+        let netns = PathBuf::from(NETNS_DIR).join(&namespace);
+        let ips = self.add_instance(instance_id, &netns, None, ports)?;
+        Ok(CniResult {
+            ips,
+            namespace_name: namespace,
+            interface_name: INSTANCE_INTERFACE.to_string(),
+        })
+    }
+
+    /// Remove an instance from the network and delete its network namespace
+    pub fn clean_instance(
+        &self,
+        instance_id: &str,
+        ports: &[Port],
+    ) -> Result<(), KudoNetworkError> {
+        let namespace = namespace_name(instance_id.to_string());
+        let netns = PathBuf::from(NETNS_DIR).join(&namespace);
+        self.remove_instance(instance_id, &netns, ports)?;
+
+        run_command("ip", &["netns", "del", &namespace])?;
+        Ok(())
+    }
+
+    /// Add an instance to the network through an existing network namespace, e.g.
+    /// `/proc/<pid>/ns/net` of its container, and return the addresses given to it. The plugins
+    /// are asked for `ip` if set, the address allocated to the instance by the controller
+    pub fn add_instance(
+        &self,
+        instance_id: &str,
+        netns: &Path,
+        ip: Option<Ipv4Addr>,
+        ports: &[Port],
+    ) -> Result<Vec<Ipv4Inet>, KudoNetworkError> {
+        let args = ip.map(|ip| format!("IgnoreUnknown=1;IP={}", ip));
+
+        let mut prev_result = None;
+        for index in 0..self.network.plugins.len() {
+            let config = self
+                .network
+                .plugin_config(index, prev_result.as_ref(), ports);
+            let result = self.exec(&config, "ADD", instance_id, netns, args.as_deref())?;
+            prev_result = Some(result);
+        }
+        Ok(prev_result.as_ref().map(result_ips).unwrap_or_default())
+    }
+
+    /// Remove an instance from the network, its network namespace being left as is. The plugins
+    /// release what they allocated even if the namespace is already gone
+    pub fn remove_instance(
+        &self,
+        instance_id: &str,
+        netns: &Path,
+        ports: &[Port],
+    ) -> Result<(), KudoNetworkError> {
+        for index in (0..self.network.plugins.len()).rev() {
+            let config = self.network.plugin_config(index, None, ports);
+            self.exec(&config, "DEL", instance_id, netns, None)?;
+        }
+        Ok(())
+    }
+
+    /// Find the executable of a plugin in the bin directories
+    fn plugin_path(&self, plugin_type: &str) -> Result<PathBuf, KudoNetworkError> {
+        self.config
+            .bin_dirs
+            .iter()
+            .map(|dir| dir.join(plugin_type))
+            .find(|path| path.is_file())
+            .ok_or_else(|| KudoNetworkError::CniError(format!("Plugin {} not found", plugin_type)))
+    }
+
+    /// Run a plugin with the CNI protocol: the parameters are given in the environment, the
+    /// configuration on stdin and the result is read on stdout
+    fn exec(
+        &self,
+        config: &Value,
+        command: &str,
+        instance_id: &str,
+        netns: &Path,
+        args: Option<&str>,
+    ) -> Result<Value, KudoNetworkError> {
+        let plugin_type = config
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| KudoNetworkError::CniError("Plugin without type".to_string()))?;
+        let path = self.plugin_path(plugin_type)?;
+        let cni_path = std::env::join_paths(&self.config.bin_dirs)
+            .map_err(|e| KudoNetworkError::CniError(e.to_string()))?;
+
+        let mut plugin = Command::new(&path);
+        plugin
+            .env("CNI_COMMAND", command)
+            .env("CNI_CONTAINERID", instance_id)
+            .env("CNI_NETNS", netns)
+            .env("CNI_IFNAME", INSTANCE_INTERFACE)
+            .env("CNI_PATH", cni_path);
+        if let Some(args) = args {
+            plugin.env("CNI_ARGS", args);
+        }
+        let mut child = plugin
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| KudoNetworkError::CommandError(Box::new(e)))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(config.to_string().as_bytes())
+                .map_err(|e| KudoNetworkError::CommandError(Box::new(e)))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| KudoNetworkError::CommandError(Box::new(e)))?;
+
+        let stdout: Value = serde_json::from_slice(&output.stdout).unwrap_or(Value::Null);
+        if !output.status.success() {
+            // the plugins report their errors as {"code": .., "msg": ..} on stdout
+            let message = stdout
+                .get("msg")
+                .and_then(Value::as_str)
+                .map(String::from)
+                .unwrap_or_else(|| String::from_utf8_lossy(&output.stderr).to_string());
+            return Err(KudoNetworkError::CniError(format!(
+                "{} {} : {}",
+                plugin_type, command, message
+            )));
+        }
+        Ok(stdout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFLIST: &str = r#"{
+        "cniVersion": "0.4.0",
+        "name": "kudo",
+        "plugins": [
+            {"type": "bridge", "bridge": "kbr0", "ipam": {"type": "host-local"}},
+            {"type": "portmap", "capabilities": {"portMappings": true}}
+        ]
+    }"#;
+
+    #[test]
+    fn test_from_json() {
+        let network = NetworkConfigList::from_json(CONFLIST, true).unwrap();
+        assert_eq!(network.name, "kudo");
+        assert_eq!(network.plugins.len(), 2);
+
+        let single = NetworkConfigList::from_json(
+            r#"{"cniVersion": "0.4.0", "name": "kudo", "type": "bridge"}"#,
+            false,
+        )
+        .unwrap();
+        assert_eq!(single.plugins[0]["type"], "bridge");
+
+        assert!(NetworkConfigList::from_json(r#"{"name": "kudo"}"#, true).is_err());
+    }
+
+    #[test]
+    fn test_plugin_config() {
+        let network = NetworkConfigList::from_json(CONFLIST, true).unwrap();
+        let prev_result = json!({"ips": [{"address": "10.244.0.2/24"}]});
+        let ports = vec![Port::new(8080, 80)];
+
+        let bridge = network.plugin_config(0, None, &ports);
+        assert_eq!(bridge["name"], "kudo");
+        assert_eq!(bridge["cniVersion"], "0.4.0");
+        assert!(bridge.get("runtimeConfig").is_none());
+
+        let portmap = network.plugin_config(1, Some(&prev_result), &ports);
+        assert_eq!(portmap["prevResult"], prev_result);
+        assert_eq!(
+            portmap["runtimeConfig"]["portMappings"][0],
+            json!({"hostPort": 8080, "containerPort": 80, "protocol": "tcp"})
+        );
+    }
+
+    #[test]
+    fn test_result_ips() {
+        let result = json!({"ips": [{"address": "10.244.0.2/24"}, {"address": "fd00::2/64"}]});
+        let ips = result_ips(&result);
+        assert_eq!(ips.len(), 1);
+        assert_eq!(ips[0].to_string(), "10.244.0.2/24");
+    }
+}
@@ -13,6 +13,8 @@ pub enum KudoNetworkError {
     RouteLocalnetError(String),
     /// Failed to enable ip_forward setting
     IPForwardError(String),
+    /// Failed to load a CNI configuration or a CNI plugin failed
+    CniError(String),
 }
 
 impl Display for KudoNetworkError {
@@ -25,6 +27,7 @@ impl Display for KudoNetworkError {
             }
             KudoNetworkError::RouteLocalnetError(e) => write!(f, "Route localnet error: {}", e),
             KudoNetworkError::IPForwardError(e) => write!(f, "IP forward error: {}", e),
+            KudoNetworkError::CniError(e) => write!(f, "CNI error: {}", e),
         }
     }
 }
@@ -1,5 +1,8 @@
+pub mod cni;
 pub mod error;
 pub mod instance;
 pub mod node;
+pub mod policy;
 pub mod port;
+pub mod service;
 pub mod utils;
@@ -0,0 +1,170 @@
+pub mod request;
+
+use crate::error::KudoNetworkError;
+use crate::utils::{policy_chain_name, run_command};
+
+use request::{CleanPolicyRequest, PolicyAction, SetupPolicyRequest};
+
+/// Build the rules of the chain of a policy, as iptables arguments appended to the chain.
+/// The replies of the connections already accepted are let through, then the rules are matched
+/// in order. If the policy allows some traffic, the rest of the traffic reaching the targets is
+/// dropped.
+pub fn policy_rules(chain: &str, request: &SetupPolicyRequest) -> Vec<Vec<String>> {
+    let mut rules = vec![vec![
+        "-m".to_string(),
+        "conntrack".to_string(),
+        "--ctstate".to_string(),
+        "ESTABLISHED,RELATED".to_string(),
+        "-j".to_string(),
+        "ACCEPT".to_string(),
+    ]];
+
+    for target in request.targets.iter() {
+        let destination = format!("{}/32", target);
+
+        for rule in request.rules.iter() {
+            let verdict = match rule.action {
+                PolicyAction::Allow => "ACCEPT",
+                PolicyAction::Deny => "DROP",
+            };
+            let sources = match &rule.sources {
+                Some(sources) => sources
+                    .iter()
+                    .map(|ip| Some(format!("{}/32", ip)))
+                    .collect(),
+                None => vec![None],
+            };
+
+            for source in sources.iter() {
+                let mut args = vec!["-d".to_string(), destination.clone()];
+                if let Some(source) = source {
+                    args.extend(["-s".to_string(), source.clone()]);
+                }
+
+                if rule.ports.is_empty() {
+                    args.extend(["-j".to_string(), verdict.to_string()]);
+                    rules.push(args);
+                    continue;
+                }
+                for port in rule.ports.iter() {
+                    for protocol in ["tcp", "udp"] {
+                        let mut port_args = args.clone();
+                        port_args.extend([
+                            "-p".to_string(),
+                            protocol.to_string(),
+                            "--dport".to_string(),
+                            port.to_string(),
+                            "-j".to_string(),
+                            verdict.to_string(),
+                        ]);
+                        rules.push(port_args);
+                    }
+                }
+            }
+        }
+
+        if request
+            .rules
+            .iter()
+            .any(|rule| rule.action == PolicyAction::Allow)
+        {
+            rules.push(vec![
+                "-d".to_string(),
+                destination,
+                "-j".to_string(),
+                "DROP".to_string(),
+            ]);
+        }
+    }
+
+    rules
+        .into_iter()
+        .map(|args| {
+            let mut rule = vec!["-A".to_string(), chain.to_string()];
+            rule.extend(args);
+            rule
+        })
+        .collect()
+}
+
+/// Program the firewall rules of a network policy: the traffic forwarded to the instances
+/// protected by the policy goes through the chain of the policy.
+/// Calling this function again with the same policy replaces its rules.
+/// The node agent calls it for every policy of the cluster before starting an instance.
+pub fn setup_policy(request: SetupPolicyRequest) -> Result<(), KudoNetworkError> {
+    let chain = policy_chain_name(request.policy_id.clone());
+
+    // The chain may already exist if the policy is updated, in this case the jump rule is
+    // already in place and we only need to flush the previous rules
+    if run_command("iptables", &["-N", &chain]).is_ok() {
+        run_command("iptables", &["-I", "FORWARD", "-j", &chain])?;
+    } else {
+        run_command("iptables", &["-F", &chain])?;
+    }
+
+    for rule in policy_rules(&chain, &request) {
+        let args: Vec<&str> = rule.iter().map(String::as_str).collect();
+        run_command("iptables", &args)?;
+    }
+
+    Ok(())
+}
+
+/// Remove the firewall rules of a network policy and its iptables chain
+pub fn clean_policy(request: CleanPolicyRequest) -> Result<(), KudoNetworkError> {
+    let chain = policy_chain_name(request.policy_id);
+
+    run_command("iptables", &["-D", "FORWARD", "-j", &chain])?;
+    run_command("iptables", &["-F", &chain])?;
+    run_command("iptables", &["-X", &chain])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::request::PolicyRule;
+    use super::*;
+
+    #[test]
+    fn test_policy_rules() {
+        let request = SetupPolicyRequest::new(
+            "db".to_string(),
+            vec![Ipv4Addr::new(10, 244, 0, 2)],
+            vec![PolicyRule::new(
+                PolicyAction::Allow,
+                Some(vec![Ipv4Addr::new(10, 244, 0, 4)]),
+                vec![5432],
+            )],
+        );
+
+        let rules: Vec<String> = policy_rules("KPOL-db", &request)
+            .iter()
+            .map(|rule| rule.join(" "))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                "-A KPOL-db -m conntrack --ctstate ESTABLISHED,RELATED -j ACCEPT",
+                "-A KPOL-db -d 10.244.0.2/32 -s 10.244.0.4/32 -p tcp --dport 5432 -j ACCEPT",
+                "-A KPOL-db -d 10.244.0.2/32 -s 10.244.0.4/32 -p udp --dport 5432 -j ACCEPT",
+                "-A KPOL-db -d 10.244.0.2/32 -j DROP",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_policy_rules_deny_only() {
+        let request = SetupPolicyRequest::new(
+            "db".to_string(),
+            vec![Ipv4Addr::new(10, 244, 0, 2)],
+            vec![PolicyRule::new(PolicyAction::Deny, None, vec![])],
+        );
+
+        let rules = policy_rules("KPOL-db", &request);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[1].join(" "), "-A KPOL-db -d 10.244.0.2/32 -j DROP");
+    }
+}
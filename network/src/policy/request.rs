@@ -0,0 +1,60 @@
+use std::net::Ipv4Addr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyAction {
+    Allow,
+    Deny,
+}
+
+/// A rule of a network policy, matching the traffic by source and destination port
+pub struct PolicyRule {
+    pub action: PolicyAction,
+    /// IP addresses of the sources, every source is matched if `None`
+    pub sources: Option<Vec<Ipv4Addr>>,
+    /// Destination ports, every port is matched if empty
+    pub ports: Vec<i32>,
+}
+
+impl PolicyRule {
+    pub fn new(action: PolicyAction, sources: Option<Vec<Ipv4Addr>>, ports: Vec<i32>) -> Self {
+        Self {
+            action,
+            sources,
+            ports,
+        }
+    }
+}
+
+// Setup
+pub struct SetupPolicyRequest {
+    /// Unique identifier of the policy. This identifier is used to create
+    /// the iptables chain of the policy
+    pub policy_id: String,
+    /// IP addresses of the instances protected by the policy
+    pub targets: Vec<Ipv4Addr>,
+    /// Rules evaluated in order, the first one matching decides
+    pub rules: Vec<PolicyRule>,
+}
+
+impl SetupPolicyRequest {
+    pub fn new(policy_id: String, targets: Vec<Ipv4Addr>, rules: Vec<PolicyRule>) -> Self {
+        Self {
+            policy_id,
+            targets,
+            rules,
+        }
+    }
+}
+
+// Clean up
+pub struct CleanPolicyRequest {
+    /// Unique identifier of the policy. This identifier is used to find
+    /// the iptables chain of the policy
+    pub policy_id: String,
+}
+
+impl CleanPolicyRequest {
+    pub fn new(policy_id: String) -> Self {
+        Self { policy_id }
+    }
+}
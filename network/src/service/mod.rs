@@ -0,0 +1,126 @@
+pub mod request;
+
+use crate::error::KudoNetworkError;
+use crate::utils::{run_command, service_chain_name};
+
+use request::{CleanServiceRequest, SetupServiceRequest};
+
+/// Program the virtual IP of a service: traffic sent to the virtual IP is redirected to one of
+/// the service endpoints, chosen randomly with an equal probability. The traffic sent to the node
+/// ports of the service on any address of the node is redirected the same way.
+/// Calling this function again with the same service replaces the endpoints.
+pub fn setup_service(request: SetupServiceRequest) -> Result<(), KudoNetworkError> {
+    let chain = service_chain_name(request.service_id.clone());
+    let virtual_ip = format!("{}/32", request.virtual_ip);
+
+    // The chain may already exist if the service endpoints are updated, in this case the jump
+    // rules are already in place and we only need to flush the previous endpoints
+    if run_command("iptables", &["-t", "nat", "-N", &chain]).is_ok() {
+        for hook in ["PREROUTING", "OUTPUT"] {
+            run_command(
+                "iptables",
+                &["-t", "nat", "-A", hook, "-d", &virtual_ip, "-j", &chain],
+            )?;
+        }
+    } else {
+        run_command("iptables", &["-t", "nat", "-F", &chain])?;
+    }
+
+    // The node ports may change when the service is updated, so their jump rules are checked
+    // one by one
+    for port in request.node_ports.iter() {
+        for hook in ["PREROUTING", "OUTPUT"] {
+            for protocol in ["tcp", "udp"] {
+                let args = node_port_jump(hook, protocol, port.source, &chain);
+                let mut check = vec!["-t", "nat", "-C"];
+                check.extend(args.iter().map(String::as_str));
+                if run_command("iptables", &check).is_err() {
+                    let mut append = vec!["-t", "nat", "-A"];
+                    append.extend(args.iter().map(String::as_str));
+                    run_command("iptables", &append)?;
+                }
+            }
+        }
+    }
+
+    let endpoints_count = request.endpoints.len();
+    for port in request.ports.iter().chain(request.node_ports.iter()) {
+        let source_port = port.source.to_string();
+
+        for (index, endpoint) in request.endpoints.iter().enumerate() {
+            let destination = format!("{}:{}", endpoint, port.destination);
+            let probability = format!("{:.5}", 1.0 / (endpoints_count - index) as f64);
+
+            for protocol in ["tcp", "udp"] {
+                let mut args = vec![
+                    "-t",
+                    "nat",
+                    "-A",
+                    &chain,
+                    "-p",
+                    protocol,
+                    "--dport",
+                    &source_port,
+                ];
+
+                // The last endpoint catches all the remaining traffic
+                if index < endpoints_count - 1 {
+                    args.extend(["-m", "statistic", "--mode", "random"]);
+                    args.extend(["--probability", &probability]);
+                }
+                args.extend(["-j", "DNAT", "--to-destination", &destination]);
+
+                run_command("iptables", &args)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the virtual IP of a service and its iptables chain
+pub fn clean_service(request: CleanServiceRequest) -> Result<(), KudoNetworkError> {
+    let chain = service_chain_name(request.service_id);
+    let virtual_ip = format!("{}/32", request.virtual_ip);
+
+    for hook in ["PREROUTING", "OUTPUT"] {
+        run_command(
+            "iptables",
+            &["-t", "nat", "-D", hook, "-d", &virtual_ip, "-j", &chain],
+        )?;
+
+        for node_port in request.node_ports.iter() {
+            for protocol in ["tcp", "udp"] {
+                let mut delete = vec!["-t", "nat", "-D"];
+                let args = node_port_jump(hook, protocol, *node_port, &chain);
+                delete.extend(args.iter().map(String::as_str));
+                run_command("iptables", &delete)?;
+            }
+        }
+    }
+    run_command("iptables", &["-t", "nat", "-F", &chain])?;
+    run_command("iptables", &["-t", "nat", "-X", &chain])?;
+
+    Ok(())
+}
+
+/// The rule jumping from `hook` to the chain of a service for the traffic sent to a node port on
+/// any local address
+fn node_port_jump(hook: &str, protocol: &str, node_port: i32, chain: &str) -> Vec<String> {
+    [
+        hook,
+        "-m",
+        "addrtype",
+        "--dst-type",
+        "LOCAL",
+        "-p",
+        protocol,
+        "--dport",
+        &node_port.to_string(),
+        "-j",
+        chain,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
@@ -0,0 +1,57 @@
+use std::net::Ipv4Addr;
+
+use crate::port::Port;
+
+// Setup
+pub struct SetupServiceRequest {
+    /// Unique identifier of the service. This identifier is used to create
+    /// the iptables chain of the service
+    pub service_id: String,
+    /// Virtual IP address of the service. Must be unique inside the cluster
+    pub virtual_ip: Ipv4Addr,
+    /// Ports exposed on the virtual IP, `destination` is the port of the instances
+    pub ports: Vec<Port>,
+    /// Ports exposed on every address of the node, `destination` is the port of the instances
+    pub node_ports: Vec<Port>,
+    /// IP addresses of the ready instances to load balance across
+    pub endpoints: Vec<Ipv4Addr>,
+}
+
+impl SetupServiceRequest {
+    pub fn new(
+        service_id: String,
+        virtual_ip: Ipv4Addr,
+        ports: Vec<Port>,
+        node_ports: Vec<Port>,
+        endpoints: Vec<Ipv4Addr>,
+    ) -> Self {
+        Self {
+            service_id,
+            virtual_ip,
+            ports,
+            node_ports,
+            endpoints,
+        }
+    }
+}
+
+// Clean up
+pub struct CleanServiceRequest {
+    /// Unique identifier of the service. This identifier is used to find
+    /// the iptables chain of the service
+    pub service_id: String,
+    /// Virtual IP address of the service
+    pub virtual_ip: Ipv4Addr,
+    /// Ports exposed on every address of the node
+    pub node_ports: Vec<i32>,
+}
+
+impl CleanServiceRequest {
+    pub fn new(service_id: String, virtual_ip: Ipv4Addr, node_ports: Vec<i32>) -> Self {
+        Self {
+            service_id,
+            virtual_ip,
+            node_ports,
+        }
+    }
+}
@@ -4,9 +4,13 @@ use std::{
     process::{Command, Output},
 };
 
+use ring::digest::{digest, SHA256};
+
 use crate::error::KudoNetworkError;
 
 const IFACE_MAX_SIZE: usize = 12;
+/// The hexadecimal characters of the hash of an id kept in the name of its chain.
+const CHAIN_HASH_SIZE: usize = 16;
 
 pub(crate) fn bridge_name(node_id: String) -> String {
     format!("kbr{}", &node_id[..min(IFACE_MAX_SIZE, node_id.len())])
@@ -50,6 +54,26 @@ pub(crate) fn veth_in_name(instance_id: String) -> String {
     )
 }
 
+/// Returns the name of the chain of an id, `prefix` followed by the beginning of the sha256 of
+/// the id: iptables chain names are limited to 28 characters, and truncating the ids would give
+/// the same chain to the ids sharing their beginning.
+fn chain_name(prefix: &str, id: &str) -> String {
+    let hash: String = digest(&SHA256, id.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}-{}", prefix, &hash[..CHAIN_HASH_SIZE])
+}
+
+pub(crate) fn service_chain_name(service_id: String) -> String {
+    chain_name("KSVC", &service_id)
+}
+
+pub(crate) fn policy_chain_name(policy_id: String) -> String {
+    chain_name("KPOL", &policy_id)
+}
+
 fn wrap_command_output(
     output: Result<Output, std::io::Error>,
     cmd: &str,
@@ -75,3 +99,34 @@ fn wrap_command_output(
         Err(e) => Err(KudoNetworkError::CommandError(Box::new(e))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_chain_name() {
+        let chain = service_chain_name("default.backend-service-v1".to_string());
+        assert!(chain.starts_with("KSVC-"));
+        assert_eq!(chain.len(), 5 + CHAIN_HASH_SIZE);
+        assert_eq!(
+            chain,
+            service_chain_name("default.backend-service-v1".to_string())
+        );
+        assert_ne!(
+            chain,
+            service_chain_name("default.backend-service-v2".to_string())
+        );
+        assert_eq!(service_chain_name("défaut.épée".to_string()).len(), 21);
+    }
+
+    #[test]
+    fn test_policy_chain_name() {
+        let chain = policy_chain_name("default.deny-all".to_string());
+        assert!(chain.starts_with("KPOL-"));
+        assert_ne!(
+            chain,
+            policy_chain_name("default.deny-all-but-dns".to_string())
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+use log::{debug, info};
+use prost::Message;
+use proto::scheduler::SchedulerAnnouncement;
+
+/// The port the schedulers announce themselves on by default.
+const DEFAULT_BROADCAST_PORT: u16 = 50055;
+
+/// How long the announcements are waited for by default, several intervals of the scheduler.
+const DEFAULT_BROADCAST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest announcement read.
+const MAX_ANNOUNCEMENT_SIZE: usize = 1024;
+
+/*
+  Configures how the agent listens to the announcements broadcast by the scheduler on the local
+  network, used instead of a configured scheduler address
+*/
+#[derive(Debug, Clone)]
+pub struct BroadcastConfig {
+    pub cluster: String,
+    pub port: u16,
+    pub timeout: Duration,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        BroadcastConfig {
+            cluster: "kudo".to_string(),
+            port: DEFAULT_BROADCAST_PORT,
+            timeout: DEFAULT_BROADCAST_TIMEOUT,
+        }
+    }
+}
+
+/*
+  A scheduler found on the local network, `join_address` is the address of its bootstrap service
+  if the cluster has a CA
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredScheduler {
+    pub node_address: SocketAddr,
+    pub join_address: Option<SocketAddr>,
+}
+
+/*
+  Waits for the announcement of a scheduler of the cluster, returns the first one received or a
+  `TimedOut` error once the timeout elapsed. The announcements are UDP broadcasts, not mDNS, they
+  are only received from the schedulers of the same network segment. The agent calls it each time
+  it connects to the scheduler, when its configuration has a broadcast section
+*/
+pub fn discover(config: &BroadcastConfig) -> io::Result<DiscoveredScheduler> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, config.port))?;
+    info!(
+        "waiting for the scheduler of cluster {} on port {}",
+        config.cluster, config.port
+    );
+
+    let deadline = Instant::now() + config.timeout;
+    let mut buffer = [0; MAX_ANNOUNCEMENT_SIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no scheduler of cluster {} announced", config.cluster),
+            ));
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let (size, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(err) => return Err(err),
+        };
+
+        if let Some(scheduler) = parse_announcement(&buffer[..size], source, &config.cluster) {
+            info!("found scheduler at {}", scheduler.node_address);
+            return Ok(scheduler);
+        }
+    }
+}
+
+/*
+  Returns the scheduler announced in a datagram received from `source`, if it is a valid
+  announcement of the cluster
+*/
+fn parse_announcement(
+    datagram: &[u8],
+    source: SocketAddr,
+    cluster: &str,
+) -> Option<DiscoveredScheduler> {
+    let announcement = match SchedulerAnnouncement::decode(datagram) {
+        Ok(announcement) => announcement,
+        Err(err) => {
+            debug!("invalid announcement from {}: {}", source, err);
+            return None;
+        }
+    };
+    if announcement.cluster != cluster {
+        debug!(
+            "ignored the scheduler of cluster {} at {}",
+            announcement.cluster, source
+        );
+        return None;
+    }
+
+    let host = match announcement.host.as_str() {
+        "" => source.ip(),
+        host => host.parse().ok()?,
+    };
+    let port = |port: u32| u16::try_from(port).ok().filter(|port| *port != 0);
+    Some(DiscoveredScheduler {
+        node_address: SocketAddr::new(host, port(announcement.node_port)?),
+        join_address: port(announcement.join_port).map(|port| SocketAddr::new(host, port)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram(cluster: &str, host: &str, join_port: u32) -> Vec<u8> {
+        SchedulerAnnouncement {
+            cluster: cluster.to_string(),
+            host: host.to_string(),
+            node_port: 50054,
+            join_port,
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_parse_announcement() {
+        let source: SocketAddr = "192.168.1.10:41000".parse().unwrap();
+
+        assert_eq!(
+            parse_announcement(&datagram("kudo", "", 50053), source, "kudo"),
+            Some(DiscoveredScheduler {
+                node_address: "192.168.1.10:50054".parse().unwrap(),
+                join_address: Some("192.168.1.10:50053".parse().unwrap()),
+            })
+        );
+        assert_eq!(
+            parse_announcement(&datagram("kudo", "10.0.0.1", 0), source, "kudo"),
+            Some(DiscoveredScheduler {
+                node_address: "10.0.0.1:50054".parse().unwrap(),
+                join_address: None,
+            })
+        );
+
+        // another cluster, or not an announcement
+        assert_eq!(
+            parse_announcement(&datagram("lab", "", 0), source, "kudo"),
+            None
+        );
+        assert_eq!(parse_announcement(b"\xff\xff\xff", source, "kudo"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use log::debug;
+use proto::scheduler::{NodeCapabilities, Platform};
+
+/// The first unprivileged port of linux, when the sysctl can't be read.
+const DEFAULT_UNPRIVILEGED_PORT_START: u32 = 1024;
+
+/// The cgroup controllers needed to enforce the resource limits of the instances.
+const LIMIT_CONTROLLERS: [&str; 2] = ["cpu", "memory"];
+
+/*
+  Returns the effective uid in the content of /proc/self/status
+*/
+fn effective_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|uids| uids.split_whitespace().nth(1))
+        .and_then(|uid| uid.parse().ok())
+}
+
+/*
+  Returns true if every controller needed to enforce the limits is in a cgroup.controllers file
+*/
+fn has_limit_controllers(controllers: &str) -> bool {
+    let available: Vec<&str> = controllers.split_whitespace().collect();
+    LIMIT_CONTROLLERS
+        .iter()
+        .all(|controller| available.contains(controller))
+}
+
+/*
+  Returns true if the resource limits can be enforced, a rootless node needs the cgroup v2
+  controllers to be delegated to its user
+*/
+fn cgroup_limits(uid: u32) -> bool {
+    let root = Path::new("/sys/fs/cgroup");
+    if uid == 0 {
+        return root.join("cgroup.controllers").exists() || root.join("memory").exists();
+    }
+
+    let delegated = root.join(format!(
+        "user.slice/user-{uid}.slice/user@{uid}.service/cgroup.controllers"
+    ));
+    fs::read_to_string(delegated)
+        .map(|controllers| has_limit_controllers(&controllers))
+        .unwrap_or(false)
+}
+
+/*
+  Returns the name of an architecture as used by the container images, e.g. `arm64` for
+  `aarch64`, matched by the placement constraints of the workloads
+*/
+fn arch_name(arch: &str) -> &str {
+    match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        arch => arch,
+    }
+}
+
+/*
+  Returns the capabilities of the node, restricted when the agent runs without root: its
+  containers run in a user namespace with slirp4netns networking, they can't publish the
+  privileged ports and their limits are enforced only if the cgroups are delegated. The pool
+  and the labels are set by the operator of the node in its configuration, the capabilities are
+  sent at registration
+*/
+pub fn detect(pool: String, labels: HashMap<String, String>) -> NodeCapabilities {
+    let uid = fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| effective_uid(&status))
+        .unwrap_or(0);
+    let rootless = uid != 0;
+
+    let unprivileged_port_start = if rootless {
+        fs::read_to_string("/proc/sys/net/ipv4/ip_unprivileged_port_start")
+            .ok()
+            .and_then(|port| port.trim().parse().ok())
+            .unwrap_or(DEFAULT_UNPRIVILEGED_PORT_START)
+    } else {
+        0
+    };
+
+    let capabilities = NodeCapabilities {
+        rootless,
+        cgroup_limits: cgroup_limits(uid),
+        unprivileged_port_start,
+        cluster_network: !rootless,
+        arch: arch_name(std::env::consts::ARCH).to_string(),
+        labels,
+        pool,
+    };
+    debug!("node capabilities: {:?}", capabilities);
+
+    capabilities
+}
+
+/*
+  Returns the platform of the node, sent at registration so that the scheduler only places on it
+  the instances whose images are built for it
+*/
+pub fn platform() -> Platform {
+    Platform {
+        os: std::env::consts::OS.to_string(),
+        arch: arch_name(std::env::consts::ARCH).to_string(),
+        variant: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_uid() {
+        let status =
+            "Name:\tnode-agent\nUid:\t1000\t1001\t1000\t1000\nGid:\t1000\t1000\t1000\t1000\n";
+        assert_eq!(effective_uid(status), Some(1001));
+        assert_eq!(effective_uid("Name:\tnode-agent\n"), None);
+    }
+
+    #[test]
+    fn test_arch_name() {
+        assert_eq!(arch_name("x86_64"), "amd64");
+        assert_eq!(arch_name("aarch64"), "arm64");
+        assert_eq!(arch_name("riscv64"), "riscv64");
+    }
+
+    #[test]
+    fn test_has_limit_controllers() {
+        assert!(has_limit_controllers("cpuset cpu io memory pids\n"));
+        assert!(!has_limit_controllers("memory pids\n"));
+    }
+}
@@ -1,3 +1,7 @@
+pub mod broadcast;
+pub mod capabilities;
+pub mod status;
+
 use std::{thread::sleep, time::Duration};
 
 use log::debug;
@@ -0,0 +1,226 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+use proto::scheduler::NodeStatus;
+
+/// The longest time without a status sent, the scheduler sees the node as alive meanwhile.
+const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The share of a limit a usage has to move by to be sent before the heartbeat, in percent.
+const DEFAULT_USAGE_THRESHOLD_PERCENT: u64 = 5;
+
+/*
+  Configures which node statuses are sent to the scheduler: the statuses are sampled every second
+  but only the significant changes are sent, the unchanged status is sent again as a heartbeat.
+  `interval` is the shortest time between two statuses sent, set by the scheduler to slow down
+  the statuses when it is loaded, none until it did
+*/
+#[derive(Debug, Clone)]
+pub struct StatusUpdateConfig {
+    pub max_interval: Duration,
+    pub usage_threshold_percent: u64,
+    pub interval: Duration,
+}
+
+impl Default for StatusUpdateConfig {
+    fn default() -> Self {
+        StatusUpdateConfig {
+            max_interval: DEFAULT_MAX_INTERVAL,
+            usage_threshold_percent: DEFAULT_USAGE_THRESHOLD_PERCENT,
+            interval: Duration::ZERO,
+        }
+    }
+}
+
+/*
+  Keeps the last status sent to the scheduler to decide if a new sample has to be sent, the node
+  agent samples its status every second
+*/
+#[derive(Debug)]
+pub struct StatusFilter {
+    config: StatusUpdateConfig,
+    last_sent: Option<(NodeStatus, Instant)>,
+}
+
+impl StatusFilter {
+    pub fn new(config: StatusUpdateConfig) -> Self {
+        StatusFilter {
+            config,
+            last_sent: None,
+        }
+    }
+
+    /*
+      Returns true if the status sampled at `now` has to be sent, it is then kept as the last
+      status sent. A status is sent when the node changed state, limits, devices, pinned CPUs or
+      reserved huge pages, when a usage moved by the threshold or when the heartbeat is due
+    */
+    pub fn should_send(&mut self, status: &NodeStatus, now: Instant) -> bool {
+        let send = match &self.last_sent {
+            None => true,
+            // the changes wait for the interval asked by the scheduler
+            Some((_, sent_at)) if now.duration_since(*sent_at) < self.config.interval => false,
+            Some((last, sent_at)) => {
+                now.duration_since(*sent_at) >= self.config.max_interval.max(self.config.interval)
+                    || last.status != status.status
+                    || last.status_description != status.status_description
+                    || last.devices != status.devices
+                    || last.numa_nodes != status.numa_nodes
+                    || last.huge_pages != status.huge_pages
+                    || self.changed_resource(last, status)
+            }
+        };
+
+        if send {
+            self.last_sent = Some((status.clone(), now));
+        } else {
+            debug!("node status unchanged, not sent");
+        }
+        send
+    }
+
+    /*
+      Sets the interval asked by the scheduler, in the register response or later on the
+      lifecycle stream, 0 letting the agent send its statuses as they change
+    */
+    pub fn set_interval(&mut self, interval_ms: u32) {
+        let interval = Duration::from_millis(interval_ms.into());
+        if interval != self.config.interval {
+            debug!("node statuses sent every {:?} at most", interval);
+            self.config.interval = interval;
+        }
+    }
+
+    /*
+      Forgets the last status sent, the next one is sent whatever it is, e.g. after the agent
+      reconnected to the scheduler
+    */
+    pub fn reset(&mut self) {
+        self.last_sent = None;
+    }
+
+    fn changed_resource(&self, last: &NodeStatus, status: &NodeStatus) -> bool {
+        let (last, current) = match (&last.resource, &status.resource) {
+            (Some(last), Some(current)) => (last, current),
+            (None, None) => return false,
+            _ => return true,
+        };
+        if last.limit != current.limit {
+            return true;
+        }
+
+        let limit = current.limit.clone().unwrap_or_default();
+        let last_usage = last.usage.clone().unwrap_or_default();
+        let usage = current.usage.clone().unwrap_or_default();
+        let threshold = self.config.usage_threshold_percent;
+        [
+            (last_usage.cpu, usage.cpu, limit.cpu),
+            (last_usage.memory, usage.memory, limit.memory),
+            (last_usage.disk, usage.disk, limit.disk),
+        ]
+        .into_iter()
+        .any(|(last, current, limit)| significant(last, current, limit, threshold))
+    }
+}
+
+/*
+  Returns true if a usage moved by at least `threshold` percent of its limit, any move is
+  significant without a limit
+*/
+fn significant(last: u64, current: u64, limit: u64, threshold: u64) -> bool {
+    let delta = last.abs_diff(current);
+    if limit == 0 {
+        return delta > 0;
+    }
+    delta * 100 >= limit * threshold
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::agent::Device;
+    use proto::scheduler::{Resource, ResourceSummary, Status};
+
+    use super::*;
+
+    fn status(cpu: u64, memory: u64) -> NodeStatus {
+        NodeStatus {
+            id: "node".to_string(),
+            status: Status::Running.into(),
+            status_description: String::new(),
+            resource: Some(Resource {
+                limit: Some(ResourceSummary {
+                    cpu: 4000,
+                    memory: 8000,
+                    disk: 100,
+                }),
+                usage: Some(ResourceSummary {
+                    cpu,
+                    memory,
+                    disk: 10,
+                }),
+            }),
+            devices: vec![],
+            numa_nodes: vec![],
+            huge_pages: vec![],
+        }
+    }
+
+    #[test]
+    fn test_should_send() {
+        let mut filter = StatusFilter::new(StatusUpdateConfig::default());
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        assert!(filter.should_send(&status(1000, 2000), at(0)));
+        assert!(!filter.should_send(&status(1000, 2000), at(1)));
+        // below 5% of the limits
+        assert!(!filter.should_send(&status(1100, 2300), at(2)));
+        assert!(filter.should_send(&status(1200, 2000), at(3)));
+        // the drift is measured from the last status sent
+        assert!(!filter.should_send(&status(1300, 2000), at(4)));
+        assert!(filter.should_send(&status(1400, 2000), at(5)));
+
+        let mut stopping = status(1400, 2000);
+        stopping.status = Status::Stopping.into();
+        assert!(filter.should_send(&stopping, at(6)));
+
+        // a device plugin advertised a new device
+        stopping.devices.push(Device {
+            id: "0".to_string(),
+            kind: "gpu".to_string(),
+            paths: vec!["/dev/nvidia0".to_string()],
+        });
+        assert!(filter.should_send(&stopping, at(6)));
+
+        // heartbeat
+        assert!(!filter.should_send(&stopping, at(35)));
+        assert!(filter.should_send(&stopping, at(36)));
+
+        filter.reset();
+        assert!(filter.should_send(&stopping, at(37)));
+    }
+
+    #[test]
+    fn test_interval() {
+        let mut filter = StatusFilter::new(StatusUpdateConfig::default());
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+        filter.set_interval(4000);
+
+        assert!(filter.should_send(&status(1000, 2000), at(0)));
+        // the change waits for the interval
+        assert!(!filter.should_send(&status(2000, 2000), at(3)));
+        assert!(filter.should_send(&status(2000, 2000), at(4)));
+
+        filter.set_interval(0);
+        assert!(filter.should_send(&status(3000, 2000), at(5)));
+    }
+
+    #[test]
+    fn test_significant() {
+        assert!(!significant(100, 100, 0, 5));
+        assert!(significant(100, 101, 0, 5));
+        assert!(!significant(100, 104, 100, 5));
+        assert!(significant(105, 100, 100, 5));
+    }
+}
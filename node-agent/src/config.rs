@@ -0,0 +1,186 @@
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use image_policy::SignaturePolicy;
+use network::cni::CniConfig;
+use node_manager::broadcast::BroadcastConfig;
+use serde_derive::{Deserialize, Serialize};
+
+/// `AgentConfig` is the configuration of the node agent, read from `agent.conf`.
+///
+/// Properties:
+///
+/// * `node_id`: The id of the node, unique in the cluster.
+/// * `pool`: The pool the node belongs to, the default pool if empty.
+/// * `reconnect_delay_seconds`: The delay before the agent connects again to the scheduler, once
+///   its lifecycle stream was closed.
+/// * `labels`: The labels of the node, matched by the placement constraints of the workloads.
+/// * `scheduler`: How the agent connects to the scheduler, as printed by `kudoctl join`.
+/// * `broadcast`: The announcements of the scheduler the agent listens to, its addresses replacing
+///   the ones of `scheduler`. The configured addresses are used if empty.
+/// * `cni`: The CNI network the instances are added to, the network of the container runtime
+///   being used if empty.
+/// * `controller`: The controller the network policies and the services are read from, none
+///   being programmed on the node if empty.
+/// * `image_signature`: The keys the images must be signed with, verified again before they are
+///   pulled, as the controller does when it accepts them. The images aren't verified if empty.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AgentConfig {
+    pub node_id: String,
+    #[serde(default)]
+    pub pool: String,
+    #[serde(default = "default_reconnect_delay_seconds")]
+    pub reconnect_delay_seconds: u64,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub broadcast: Option<BroadcastSettings>,
+    #[serde(default)]
+    pub cni: Option<CniSettings>,
+    #[serde(default)]
+    pub controller: Option<ControllerSettings>,
+    #[serde(default)]
+    pub image_signature: Option<SignaturePolicy>,
+}
+
+fn default_reconnect_delay_seconds() -> u64 {
+    5
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        AgentConfig {
+            node_id: String::new(),
+            pool: String::new(),
+            reconnect_delay_seconds: default_reconnect_delay_seconds(),
+            labels: HashMap::new(),
+            scheduler: SchedulerConfig::default(),
+            broadcast: None,
+            cni: None,
+            controller: None,
+            image_signature: None,
+        }
+    }
+}
+
+/// `SchedulerConfig` is the scheduler the agent registers with. With a CA certificate, the agent
+/// joins the cluster with its token and uses the client certificate it is issued, otherwise it
+/// connects in plaintext and authenticates with its secret, if any.
+///
+/// Properties:
+///
+/// * `node_address`: The address of the node service of the scheduler, e.g. `10.0.0.1:50052`.
+/// * `join_address`: The address of the bootstrap service, issuing the certificate of the node.
+/// * `join_token`: The token presented to the bootstrap service.
+/// * `ca_certificate`: The PEM certificate of the CA of the cluster, authenticating the scheduler.
+/// * `server_name`: The name the certificate of the scheduler is checked against, one of its
+///   `server_names`.
+/// * `node_secret`: The shared secret of the node, when the scheduler has no CA.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SchedulerConfig {
+    pub node_address: String,
+    pub join_address: Option<String>,
+    pub join_token: Option<String>,
+    pub ca_certificate: Option<String>,
+    pub server_name: String,
+    pub node_secret: Option<String>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            node_address: "127.0.0.1:50052".to_string(),
+            join_address: None,
+            join_token: None,
+            ca_certificate: None,
+            server_name: "localhost".to_string(),
+            node_secret: None,
+        }
+    }
+}
+
+/// `BroadcastSettings` are the announcements broadcast by the scheduler on the local network, see
+/// the `[broadcast]` section of the scheduler. The agent waits for one each time it connects.
+///
+/// Properties:
+///
+/// * `cluster`: The name of the cluster, the schedulers of the other clusters are ignored.
+/// * `port`: The UDP port the announcements are sent to.
+/// * `timeout_seconds`: How long an announcement is waited for, the agent retries beyond.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BroadcastSettings {
+    pub cluster: String,
+    pub port: u16,
+    pub timeout_seconds: u64,
+}
+
+impl Default for BroadcastSettings {
+    fn default() -> Self {
+        let config = BroadcastConfig::default();
+        BroadcastSettings {
+            cluster: config.cluster,
+            port: config.port,
+            timeout_seconds: config.timeout.as_secs(),
+        }
+    }
+}
+
+impl From<&BroadcastSettings> for BroadcastConfig {
+    fn from(settings: &BroadcastSettings) -> Self {
+        BroadcastConfig {
+            cluster: settings.cluster.clone(),
+            port: settings.port,
+            timeout: Duration::from_secs(settings.timeout_seconds),
+        }
+    }
+}
+
+/// `CniSettings` are where the CNI network of the node is configured, the first configuration of
+/// `conf_dir` being used as the other runtimes do.
+///
+/// Properties:
+///
+/// * `conf_dir`: The directory of the network configurations.
+/// * `bin_dirs`: The directories of the plugins executables.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CniSettings {
+    pub conf_dir: PathBuf,
+    pub bin_dirs: Vec<PathBuf>,
+}
+
+impl Default for CniSettings {
+    fn default() -> Self {
+        let config = CniConfig::default();
+        CniSettings {
+            conf_dir: config.conf_dir,
+            bin_dirs: config.bin_dirs,
+        }
+    }
+}
+
+impl From<&CniSettings> for CniConfig {
+    fn from(settings: &CniSettings) -> Self {
+        CniConfig {
+            conf_dir: settings.conf_dir.clone(),
+            bin_dirs: settings.bin_dirs.clone(),
+        }
+    }
+}
+
+/// `ControllerSettings` are how the agent reads the network resources of the cluster on the API
+/// of the controller.
+///
+/// Properties:
+///
+/// * `url`: The URL of the controller, e.g. `http://10.0.0.1:8080`.
+/// * `token`: The bearer token sent to the controller, if it requires one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerSettings {
+    pub url: String,
+    #[serde(default)]
+    pub token: Option<String>,
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use log::info;
+use node_manager::broadcast::{self, BroadcastConfig};
+use proto::scheduler::{
+    bootstrap_service_client::BootstrapServiceClient, node_service_client::NodeServiceClient,
+    NodeJoinRequest, NodeRegisterRequest, NodeRegisterResponse,
+};
+use proto::version;
+use rcgen::{Certificate as KeyPair, CertificateParams, DistinguishedName, DnType};
+use tonic::{
+    codegen::InterceptedService,
+    metadata::AsciiMetadataValue,
+    service::Interceptor,
+    transport::{Certificate, Channel, ClientTlsConfig, Identity},
+    Request, Status,
+};
+
+use crate::config::{BroadcastSettings, SchedulerConfig};
+
+/// The metadata key carrying the id of the node, checked by the scheduler with its secret.
+const NODE_ID_METADATA: &str = "x-node-id";
+
+/// The client of the node service of the scheduler.
+pub type SchedulerClient = NodeServiceClient<InterceptedService<Channel, NodeCredentials>>;
+
+/// `NodeCredentials` adds the id and the shared secret of the node to its requests, when the
+/// scheduler authenticates the nodes by secret. The nodes with a client certificate send none.
+#[derive(Debug, Clone)]
+pub struct NodeCredentials {
+    node_id: AsciiMetadataValue,
+    authorization: Option<AsciiMetadataValue>,
+}
+
+impl Interceptor for NodeCredentials {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(authorization) = &self.authorization {
+            let metadata = request.metadata_mut();
+            metadata.insert(NODE_ID_METADATA, self.node_id.clone());
+            metadata.insert("authorization", authorization.clone());
+        }
+        Ok(request)
+    }
+}
+
+/// Connects to the node service of the scheduler. With a CA certificate, the node first joins the
+/// cluster to be issued a client certificate, returned with the client to be sent at registration.
+pub async fn connect(
+    node_id: &str,
+    config: &SchedulerConfig,
+) -> Result<(SchedulerClient, Option<String>)> {
+    let credentials = NodeCredentials {
+        node_id: node_id.parse().context("Invalid node id")?,
+        authorization: config
+            .node_secret
+            .as_ref()
+            .filter(|_| config.ca_certificate.is_none())
+            .map(|secret| format!("Bearer {}", secret).parse())
+            .transpose()
+            .context("Invalid node secret")?,
+    };
+
+    let (channel, certificate) = match &config.ca_certificate {
+        Some(ca_certificate) => {
+            let (certificate, key) = join(node_id, config, ca_certificate).await?;
+            let tls =
+                tls_config(config, ca_certificate).identity(Identity::from_pem(&certificate, key));
+            let channel = Channel::from_shared(format!("https://{}", config.node_address))?
+                .tls_config(tls)?
+                .connect()
+                .await
+                .with_context(|| format!("Error connecting to {}", config.node_address))?;
+            (channel, Some(certificate))
+        }
+        None => {
+            let channel = Channel::from_shared(format!("http://{}", config.node_address))?
+                .connect()
+                .await
+                .with_context(|| format!("Error connecting to {}", config.node_address))?;
+            (channel, None)
+        }
+    };
+    info!("connected to the scheduler at {}", config.node_address);
+
+    Ok((
+        NodeServiceClient::with_interceptor(channel, credentials),
+        certificate,
+    ))
+}
+
+/// Waits for the announcement of the scheduler on the local network, returns `scheduler` with the
+/// announced addresses.
+pub async fn discover(
+    scheduler: &SchedulerConfig,
+    settings: &BroadcastSettings,
+) -> Result<SchedulerConfig> {
+    let config = BroadcastConfig::from(settings);
+    let announced = tokio::task::spawn_blocking(move || broadcast::discover(&config))
+        .await?
+        .context("No scheduler announced on the local network")?;
+
+    Ok(SchedulerConfig {
+        node_address: announced.node_address.to_string(),
+        join_address: announced
+            .join_address
+            .map(|address| address.to_string())
+            .or_else(|| scheduler.join_address.clone()),
+        ..scheduler.clone()
+    })
+}
+
+/// Registers the node with the scheduler, the request being completed by the caller.
+pub async fn register(
+    client: &mut SchedulerClient,
+    request: NodeRegisterRequest,
+) -> Result<NodeRegisterResponse> {
+    let request = NodeRegisterRequest {
+        version: Some(version::version_info(env!("CARGO_PKG_VERSION"))),
+        ..request
+    };
+    let response = client
+        .register(request)
+        .await
+        .context("The scheduler refused the registration of the node")?
+        .into_inner();
+    info!(
+        "registered with the scheduler {}: {}",
+        response
+            .version
+            .as_ref()
+            .map_or("", |version| version.component_version.as_str()),
+        response.description
+    );
+    Ok(response)
+}
+
+/// Joins the cluster with the token of the node, returns the PEM client certificate issued to the
+/// node and its PEM private key.
+async fn join(
+    node_id: &str,
+    config: &SchedulerConfig,
+    ca_certificate: &str,
+) -> Result<(String, String)> {
+    let join_address = config
+        .join_address
+        .as_ref()
+        .context("The scheduler has a CA but no join address is configured")?;
+    let token = config
+        .join_token
+        .clone()
+        .context("The scheduler has a CA but no join token is configured")?;
+
+    let mut params = CertificateParams::default();
+    params.distinguished_name = DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, node_id);
+    let key_pair = KeyPair::from_params(params)?;
+
+    let channel = Channel::from_shared(format!("https://{}", join_address))?
+        .tls_config(tls_config(config, ca_certificate))?
+        .connect()
+        .await
+        .with_context(|| format!("Error connecting to {}", join_address))?;
+    let response = BootstrapServiceClient::new(channel)
+        .join(NodeJoinRequest {
+            node_id: node_id.to_string(),
+            token,
+            csr: key_pair.serialize_request_pem()?,
+        })
+        .await
+        .context("The scheduler refused the node to join the cluster")?
+        .into_inner();
+    info!("joined the cluster as node {}", node_id);
+
+    Ok((response.certificate, key_pair.serialize_private_key_pem()))
+}
+
+/// Returns the TLS settings authenticating the scheduler with the CA of the cluster.
+fn tls_config(config: &SchedulerConfig, ca_certificate: &str) -> ClientTlsConfig {
+    ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(ca_certificate))
+        .domain_name(&config.server_name)
+}
@@ -0,0 +1,55 @@
+use anyhow::{Context, Result};
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde::de::DeserializeOwned;
+
+use crate::config::ControllerSettings;
+
+/// `ControllerClient` reads the network resources of the cluster on the API of the controller,
+/// programmed by the agent on its node.
+///
+/// Properties:
+///
+/// * `client`: The HTTP client, sending the token of the agent if any.
+/// * `base_url`: The URL of the controller.
+#[derive(Debug, Clone)]
+pub struct ControllerClient {
+    client: reqwest::Client,
+    base_url: reqwest::Url,
+}
+
+impl ControllerClient {
+    pub fn new(settings: &ControllerSettings) -> Result<Self> {
+        let base_url = reqwest::Url::parse(&settings.url)
+            .with_context(|| format!("Invalid controller URL {}", settings.url))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        if let Some(token) = &settings.token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .context("Invalid controller token")?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+
+        Ok(ControllerClient {
+            client: reqwest::Client::builder()
+                .default_headers(headers)
+                .build()?,
+            base_url,
+        })
+    }
+
+    /// Reads a resource of the controller, `path` being relative to its URL.
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let url = self.base_url.join(path)?;
+        self.client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("Error reading {}", url))?
+            .json()
+            .await
+            .with_context(|| format!("Invalid response of {}", url))
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use futures_util::FutureExt;
+use log::{debug, info, warn};
+use proto::agent::{
+    exec_input::Input, exec_output::Output, instance_command::Command, node_message::Message,
+    ExecOutput, ExecStart, ImagePull, Instance, InstanceCommand, InstanceStatus, LogChunk,
+    LogRequest, NodeMessage, Signal as SignalKind, SignalInstruction, Status,
+};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use workload_manager::workload_manager::{ExecInput, Signal, WorkloadManager};
+
+use crate::connection::SchedulerClient;
+
+/// How many messages can be queued for the scheduler, their senders wait beyond.
+const MESSAGE_BUFFER: usize = 64;
+
+/// How many progress statuses of an image pull can be queued, the next ones are dropped.
+const PULL_BUFFER: usize = 8;
+
+/// How many chunks of the output of an instance, or of a command run in it, can be queued, the
+/// runtime is read from again once they are sent.
+const LOG_BUFFER: usize = 16;
+
+/// The streams sent to the scheduler, by stream id. Dropping the sender of a stream stops it.
+type Streams = Arc<Mutex<HashMap<String, oneshot::Sender<()>>>>;
+
+/// The inputs of the exec sessions whose command reads its stdin, by session id. The inputs are
+/// queued in the order the scheduler sent them, dropping the sender of a session closes its stdin.
+type Sessions = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<ExecInput>>>>;
+
+/// `LifecycleClient` keeps the lifecycle stream of the node open with the scheduler: it runs the
+/// commands sent by the scheduler on the workloads of the node and sends back the statuses of the
+/// instances, of the image pulls and of the checkpoints, and the output of the instances and of
+/// the commands run in them. Each command runs in a task of its own, so a slow creation doesn't
+/// delay the next commands.
+///
+/// Properties:
+///
+/// * `node_id`: The id of the node, the first message of each stream.
+/// * `workloads`: The workloads of the instances of the node.
+/// * `statuses`: The intermediate statuses of the instances being created, sent by `workloads`.
+/// * `intervals`: The interval asked by the scheduler between two statuses of the node, in
+///   milliseconds.
+/// * `streams`: The log streams and the exec sessions being sent, until the scheduler closes them.
+/// * `sessions`: The inputs of the exec sessions being run.
+pub struct LifecycleClient {
+    node_id: String,
+    workloads: WorkloadManager,
+    statuses: mpsc::Receiver<InstanceStatus>,
+    intervals: watch::Sender<u32>,
+    streams: Streams,
+    sessions: Sessions,
+}
+
+impl LifecycleClient {
+    pub fn new(
+        node_id: String,
+        workloads: WorkloadManager,
+        statuses: mpsc::Receiver<InstanceStatus>,
+        intervals: watch::Sender<u32>,
+    ) -> Self {
+        LifecycleClient {
+            node_id,
+            workloads,
+            statuses,
+            intervals,
+            streams: Streams::default(),
+            sessions: Sessions::default(),
+        }
+    }
+
+    /// Sets the interval between two statuses of the node, as asked by the scheduler in its
+    /// register response or on the lifecycle stream.
+    pub fn set_status_interval(&self, interval_ms: u32) {
+        self.intervals.send_replace(interval_ms);
+    }
+
+    /// Opens the lifecycle stream of the node and runs the commands received on it, returns once
+    /// the scheduler closed it. The workloads keep running meanwhile, the caller opens the stream
+    /// again.
+    pub async fn run(&mut self, client: &mut SchedulerClient) -> Result<()> {
+        let (sender, receiver) = mpsc::channel(MESSAGE_BUFFER);
+        // the node identifies itself with the first message of the stream
+        sender
+            .send(message(Message::NodeId(self.node_id.clone())))
+            .await?;
+        let mut commands = client
+            .lifecycle(ReceiverStream::new(receiver))
+            .await?
+            .into_inner();
+        info!("lifecycle stream of node {} opened", self.node_id);
+
+        loop {
+            tokio::select! {
+                command = commands.message() => match command? {
+                    Some(command) => self.handle(command, &sender),
+                    None => return Ok(()),
+                },
+                Some(status) = self.statuses.recv() => {
+                    _ = sender.send(message(Message::Status(status))).await;
+                }
+            }
+        }
+    }
+
+    /// Runs a command of the scheduler, its statuses being sent on `sender`. The commands whose
+    /// deadline passed are dropped, their caller gave up.
+    fn handle(&self, command: InstanceCommand, sender: &mpsc::Sender<NodeMessage>) {
+        if is_expired(command.deadline) {
+            debug!("dropped a command past its deadline: {:?}", command.command);
+            return;
+        }
+        let Some(command) = command.command else {
+            return;
+        };
+
+        let workloads = self.workloads.clone();
+        let sender = sender.clone();
+        match command {
+            Command::Create(instance) => {
+                let mut creation = Box::pin(create(workloads, instance, sender));
+                // the instance is known to the manager from the first poll of its creation, so
+                // the signals received next find it even while its image is pulled
+                if (&mut creation).now_or_never().is_none() {
+                    tokio::spawn(creation);
+                }
+            }
+            Command::Signal(instruction) => {
+                tokio::spawn(signal(workloads, instruction, sender));
+            }
+            Command::Pull(ImagePull { uri }) => {
+                tokio::spawn(async move {
+                    let (statuses, mut progress) = mpsc::channel(PULL_BUFFER);
+                    let pull = workloads.pull(&uri, statuses);
+                    let forward = async {
+                        while let Some(status) = progress.recv().await {
+                            _ = sender.send(message(Message::Pull(status))).await;
+                        }
+                    };
+                    tokio::join!(pull, forward);
+                });
+            }
+            Command::Checkpoint(checkpoint) => {
+                tokio::spawn(async move {
+                    let status = workloads.checkpoint(checkpoint).await;
+                    _ = sender.send(message(Message::Checkpoint(status))).await;
+                });
+            }
+            Command::StatusIntervalMs(interval_ms) => self.set_status_interval(interval_ms),
+            Command::Logs(request) => {
+                let (stop, stopped) = oneshot::channel();
+                let stream_id = request.stream_id.clone();
+                lock(&self.streams).insert(stream_id.clone(), stop);
+                let streams = self.streams.clone();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = logs(workloads, request, sender) => {}
+                        _ = stopped => debug!("log stream {} closed by the scheduler", stream_id),
+                    }
+                    lock(&streams).remove(&stream_id);
+                });
+            }
+            Command::CloseStream(stream_id) => {
+                lock(&self.streams).remove(&stream_id);
+                lock(&self.sessions).remove(&stream_id);
+            }
+            Command::Exec(exec) => {
+                let session_id = exec.session_id;
+                let input = match exec.input {
+                    Some(Input::Start(start)) => {
+                        self.start_session(session_id, start, workloads, sender);
+                        return;
+                    }
+                    Some(Input::Stdin(data)) => ExecInput::Stdin(data),
+                    Some(Input::Resize(size)) => ExecInput::Resize(
+                        u16::try_from(size.cols).unwrap_or(u16::MAX),
+                        u16::try_from(size.rows).unwrap_or(u16::MAX),
+                    ),
+                    Some(Input::CloseStdin(_)) => {
+                        lock(&self.sessions).remove(&session_id);
+                        return;
+                    }
+                    None => return,
+                };
+                match lock(&self.sessions).get(&session_id) {
+                    Some(inputs) => _ = inputs.send(input),
+                    None => debug!("dropped an input of exec session {}", session_id),
+                }
+            }
+        }
+    }
+
+    /// Runs the command of an exec session in a task, until it exits or the scheduler closes the
+    /// session.
+    fn start_session(
+        &self,
+        session_id: String,
+        start: ExecStart,
+        workloads: WorkloadManager,
+        sender: mpsc::Sender<NodeMessage>,
+    ) {
+        let (stop, stopped) = oneshot::channel();
+        let (inputs, received) = mpsc::unbounded_channel();
+        lock(&self.streams).insert(session_id.clone(), stop);
+        lock(&self.sessions).insert(session_id.clone(), inputs);
+        let streams = self.streams.clone();
+        let sessions = self.sessions.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = exec(workloads, &session_id, start, received, sender) => {}
+                _ = stopped => debug!("exec session {} closed by the scheduler", session_id),
+            }
+            lock(&streams).remove(&session_id);
+            lock(&sessions).remove(&session_id);
+        });
+    }
+}
+
+/// Creates the workload of an instance and reports whether it runs.
+async fn create(workloads: WorkloadManager, instance: Instance, sender: mpsc::Sender<NodeMessage>) {
+    let id = instance.id.clone();
+    let status = match workloads.create(instance).await {
+        Ok(()) => {
+            info!("instance {} is running", id);
+            instance_status(&id, Status::Running, String::new())
+        }
+        Err(err) => {
+            warn!("failed to create instance {}: {:#}", id, err);
+            instance_status(&id, Status::Failed, format!("{:#}", err))
+        }
+    };
+    _ = sender.send(message(Message::Status(status))).await;
+}
+
+/// Sends a signal to the workload of an instance and reports it once terminated. The workloads
+/// are only stopped or killed, the instances are restarted by creating them again.
+async fn signal(
+    workloads: WorkloadManager,
+    instruction: SignalInstruction,
+    sender: mpsc::Sender<NodeMessage>,
+) {
+    let kind = instruction.signal();
+    let Some(instance) = instruction.instance else {
+        return;
+    };
+    let signal = match kind {
+        SignalKind::Stop => Signal::Stop,
+        SignalKind::Kill => Signal::Kill,
+        other => {
+            warn!(
+                "signal {:?} of instance {} not supported",
+                other, instance.id
+            );
+            return;
+        }
+    };
+
+    match workloads.signal(&instance.id, signal).await {
+        Ok(()) => {
+            let status = instance_status(&instance.id, Status::Terminated, String::new());
+            _ = sender.send(message(Message::Status(status))).await;
+        }
+        Err(err) => warn!("failed to signal instance {}: {:#}", instance.id, err),
+    }
+}
+
+/// Sends the output of an instance to the scheduler, the last chunk tells whether it was sent.
+async fn logs(workloads: WorkloadManager, request: LogRequest, sender: mpsc::Sender<NodeMessage>) {
+    let stream_id = request.stream_id.clone();
+    let (output, mut chunks) = mpsc::channel(LOG_BUFFER);
+    let stream = workloads.logs(&request, output);
+    let forward = async {
+        while let Some(data) = chunks.recv().await {
+            let chunk = LogChunk {
+                stream_id: stream_id.clone(),
+                data,
+                ..Default::default()
+            };
+            if sender.send(message(Message::Logs(chunk))).await.is_err() {
+                break;
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(stream, forward);
+
+    let error = result.err().map_or_else(String::new, |err| {
+        warn!(
+            "failed to stream the logs of {}: {:#}",
+            request.instance_id, err
+        );
+        format!("{:#}", err)
+    });
+    let last = LogChunk {
+        stream_id,
+        eof: true,
+        error,
+        ..Default::default()
+    };
+    _ = sender.send(message(Message::Logs(last))).await;
+}
+
+/// Runs the command of an exec session and sends its output to the scheduler, the last message
+/// telling how it exited.
+async fn exec(
+    workloads: WorkloadManager,
+    session_id: &str,
+    start: ExecStart,
+    inputs: mpsc::UnboundedReceiver<ExecInput>,
+    sender: mpsc::Sender<NodeMessage>,
+) {
+    let (output, mut chunks) = mpsc::channel(LOG_BUFFER);
+    let run = workloads.exec(&start, inputs, output);
+    let forward = async {
+        while let Some(data) = chunks.recv().await {
+            let output = exec_output(session_id, Output::Stdout(data));
+            if sender.send(output).await.is_err() {
+                break;
+            }
+        }
+    };
+    let (result, ()) = tokio::join!(run, forward);
+
+    let last = match result {
+        Ok(exit_code) => Output::ExitCode(exit_code as i32),
+        Err(err) => {
+            warn!(
+                "failed to run {:?} in {}: {:#}",
+                start.command, start.instance_id, err
+            );
+            Output::Error(format!("{:#}", err))
+        }
+    };
+    _ = sender.send(exec_output(session_id, last)).await;
+}
+
+fn instance_status(id: &str, status: Status, description: String) -> InstanceStatus {
+    InstanceStatus {
+        id: id.to_string(),
+        status: status.into(),
+        description,
+        ..Default::default()
+    }
+}
+
+fn message(message: Message) -> NodeMessage {
+    NodeMessage {
+        message: Some(message),
+    }
+}
+
+fn exec_output(session_id: &str, output: Output) -> NodeMessage {
+    message(Message::Exec(ExecOutput {
+        session_id: session_id.to_string(),
+        output: Some(output),
+    }))
+}
+
+fn lock<T>(map: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // the maps are left consistent by every critical section, a poisoned lock is still usable
+    map.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Returns `true` if the deadline of a command passed, in milliseconds since the unix epoch, 0
+/// being no deadline.
+fn is_expired(deadline: i64) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as i64);
+    deadline > 0 && deadline < now
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_expired() {
+        assert!(!is_expired(0));
+        assert!(is_expired(1));
+        assert!(!is_expired(i64::MAX));
+    }
+}
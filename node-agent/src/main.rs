@@ -1,3 +1,138 @@
-fn main() {
-    println!("Hello, world!");
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use image_policy::Verifier;
+use log::{debug, info, warn};
+use network::cni::CniNetwork;
+use node_manager::capabilities;
+use proto::scheduler::{Feature, NodeRegisterRequest};
+use tokio::sync::{mpsc, watch};
+use workload_manager::workload_manager::{
+    cpus::CpuManager, devices::DeviceRegistry, hugepages::HugePagesManager, WorkloadManager,
+};
+
+use config::AgentConfig;
+use controller::ControllerClient;
+use lifecycle::LifecycleClient;
+use policies::PolicyEnforcer;
+use services::ServiceProxy;
+use status::StatusReporter;
+
+mod config;
+mod connection;
+mod controller;
+mod lifecycle;
+mod policies;
+mod services;
+mod status;
+
+/// Name of the config file of the agent, read from its working directory.
+const AGENT_CONFIG: &str = "agent.conf";
+
+/// The optional operations this build of the agent performs, it doesn't run the WASM instances.
+const FEATURES: [Feature; 3] = [Feature::Checkpoint, Feature::Logs, Feature::Exec];
+
+/// How many intermediate statuses of the instances can be queued, the next ones are dropped.
+const STATUS_BUFFER: usize = 64;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    info!("starting up");
+
+    let config: AgentConfig = confy::load_path(AGENT_CONFIG)
+        .with_context(|| format!("Error reading {}", AGENT_CONFIG))?;
+    debug!("config: {:?}", config);
+    if config.node_id.is_empty() {
+        bail!("Set the id of the node in {}", AGENT_CONFIG);
+    }
+
+    let (statuses, receiver) = mpsc::channel(STATUS_BUFFER);
+    let devices = DeviceRegistry::default();
+    let cpus = CpuManager::detect().unwrap_or_else(|err| {
+        warn!("no CPU can be pinned to the instances: {:#}", err);
+        CpuManager::default()
+    });
+    let huge_pages = HugePagesManager::detect().unwrap_or_else(|err| {
+        warn!("no huge pages can be reserved to the instances: {:#}", err);
+        HugePagesManager::default()
+    });
+    let verifier = config
+        .image_signature
+        .as_ref()
+        .map(Verifier::new)
+        .transpose()
+        .context("Error reading the image signature policy")?;
+    let mut workloads = WorkloadManager::new(verifier)
+        .with_statuses(statuses)
+        .with_devices(devices.clone())
+        .with_cpus(cpus.clone())
+        .with_huge_pages(huge_pages.clone());
+    if let Some(cni) = &config.cni {
+        let network = CniNetwork::load(cni.into())
+            .map_err(|err| anyhow!("Error loading the CNI network: {}", err))?;
+        workloads = workloads.with_network(network);
+    }
+    if let Some(settings) = &config.controller {
+        let controller = ControllerClient::new(settings)?;
+        let policies = Arc::new(PolicyEnforcer::new(controller.clone()));
+        workloads = workloads.with_start_hook(policies.clone());
+        tokio::spawn(async move { policies.run().await });
+
+        let mut services = ServiceProxy::new(controller);
+        tokio::spawn(async move { services.run().await });
+    }
+    let (intervals, interval) = watch::channel(0);
+    let mut lifecycle =
+        LifecycleClient::new(config.node_id.clone(), workloads, receiver, intervals);
+    let mut status = StatusReporter::new(config.node_id.clone(), interval)
+        .with_devices(devices)
+        .with_cpus(cpus)
+        .with_huge_pages(huge_pages);
+    let reconnect_delay = Duration::from_secs(config.reconnect_delay_seconds);
+
+    // the node registers again each time its lifecycle stream is closed, e.g. by a restart of
+    // the scheduler, its workloads keep running meanwhile
+    loop {
+        if let Err(err) = run(&config, &mut lifecycle, &mut status).await {
+            warn!("disconnected from the scheduler: {:#}", err);
+        }
+        tokio::time::sleep(reconnect_delay).await;
+    }
+}
+
+/// Registers the node with the scheduler and runs its lifecycle and status streams until one of
+/// them is closed.
+async fn run(
+    config: &AgentConfig,
+    lifecycle: &mut LifecycleClient,
+    status: &mut StatusReporter,
+) -> Result<()> {
+    let scheduler = match &config.broadcast {
+        Some(broadcast) => connection::discover(&config.scheduler, broadcast).await?,
+        None => config.scheduler.clone(),
+    };
+    let (mut client, certificate) = connection::connect(&config.node_id, &scheduler).await?;
+    let request = NodeRegisterRequest {
+        id: config.node_id.clone(),
+        certificate: certificate.unwrap_or_default(),
+        capabilities: Some(capabilities::detect(
+            config.pool.clone(),
+            config.labels.clone(),
+        )),
+        features: FEATURES.iter().map(|feature| *feature as i32).collect(),
+        platform: Some(capabilities::platform()),
+        ..Default::default()
+    };
+    let response = connection::register(&mut client, request).await?;
+    lifecycle.set_status_interval(response.status_interval_ms);
+
+    let mut status_client = client.clone();
+    tokio::select! {
+        result = lifecycle.run(&mut client) => result?,
+        result = status.run(&mut status_client) => result?,
+    }
+    info!("the scheduler closed the streams of the node");
+    Ok(())
 }
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use network::policy::{
+    self,
+    request::{CleanPolicyRequest, PolicyAction, PolicyRule, SetupPolicyRequest},
+};
+use proto::agent::Instance;
+use serde_derive::Deserialize;
+use tokio::sync::Mutex;
+use workload_manager::workload_manager::StartHook;
+
+use crate::controller::ControllerClient;
+
+/// The delay between two syncs of the network policies, catching up with the policies and the
+/// instances changed since the last one.
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The policies of the cluster, as listed by `GET /networkpolicy`.
+#[derive(Debug, Deserialize)]
+struct NetworkPolicyVector {
+    network_policies: Vec<NetworkPolicy>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NetworkPolicy {
+    id: String,
+    name: String,
+    namespace: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolvedRule {
+    action: Action,
+    sources: Option<Vec<Ipv4Addr>>,
+    ports: Vec<i32>,
+}
+
+/// The rules of a policy resolved by the controller, as served by
+/// `GET /networkpolicy/<namespace>/<name>/rules`.
+#[derive(Debug, Deserialize)]
+struct NetworkPolicyRules {
+    targets: Vec<Ipv4Addr>,
+    rules: Vec<ResolvedRule>,
+}
+
+impl From<ResolvedRule> for PolicyRule {
+    fn from(rule: ResolvedRule) -> Self {
+        let action = match rule.action {
+            Action::Allow => PolicyAction::Allow,
+            Action::Deny => PolicyAction::Deny,
+        };
+        PolicyRule::new(action, rule.sources, rule.ports)
+    }
+}
+
+/// `PolicyEnforcer` programs the network policies of the cluster in the firewall of the node.
+/// The policies are synced before each instance is started, so it is protected before it runs,
+/// and periodically to follow the changes of the policies and of the other instances.
+///
+/// Properties:
+///
+/// * `controller`: The controller serving the policies and their rules.
+/// * `programmed`: The ids of the policies programmed on the node, a sync holding it until it
+///   is done so the syncs don't interleave their iptables commands.
+pub struct PolicyEnforcer {
+    controller: ControllerClient,
+    programmed: Mutex<HashSet<String>>,
+}
+
+impl PolicyEnforcer {
+    pub fn new(controller: ControllerClient) -> Self {
+        PolicyEnforcer {
+            controller,
+            programmed: Mutex::default(),
+        }
+    }
+
+    /// Programs the rules of every policy of the cluster and removes the chains of the deleted
+    /// ones, fails at the first policy which can't be programmed.
+    pub async fn sync(&self) -> Result<()> {
+        let mut programmed = self.programmed.lock().await;
+        let policies: NetworkPolicyVector = self.controller.get("networkpolicy").await?;
+
+        let ids: HashSet<String> = policies
+            .network_policies
+            .iter()
+            .map(|policy| policy.id.clone())
+            .collect();
+        let deleted: Vec<String> = programmed.difference(&ids).cloned().collect();
+        for id in deleted {
+            let request = CleanPolicyRequest::new(id.clone());
+            match blocking(move || policy::clean_policy(request)).await {
+                Ok(()) => info!("removed network policy {}", id),
+                Err(err) => warn!("failed to remove network policy {}: {:#}", id, err),
+            }
+            programmed.remove(&id);
+        }
+
+        for network_policy in policies.network_policies {
+            let rules: NetworkPolicyRules = self
+                .controller
+                .get(&format!(
+                    "networkpolicy/{}/{}/rules",
+                    network_policy.namespace, network_policy.name
+                ))
+                .await?;
+            let request = SetupPolicyRequest::new(
+                network_policy.id.clone(),
+                rules.targets,
+                rules.rules.into_iter().map(PolicyRule::from).collect(),
+            );
+            blocking(move || policy::setup_policy(request))
+                .await
+                .with_context(|| {
+                    format!("Error programming network policy {}", network_policy.id)
+                })?;
+            programmed.insert(network_policy.id);
+        }
+        Ok(())
+    }
+
+    /// Syncs the policies until the agent stops, a failed sync being retried on the next tick.
+    pub async fn run(&self) {
+        let mut ticker = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.sync().await {
+                warn!("failed to sync the network policies: {:#}", err);
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl StartHook for PolicyEnforcer {
+    /// Syncs the policies before the instance starts, its address being among their targets
+    /// from its scheduling. The instance doesn't start if they can't be programmed.
+    async fn before_start(&self, instance: &Instance) -> Result<()> {
+        self.sync().await.with_context(|| {
+            format!(
+                "Can't enforce the network policies of instance {}",
+                instance.id
+            )
+        })
+    }
+}
+
+/// Runs a function of the network crate, which runs `iptables`, on a blocking thread.
+pub async fn blocking<F, E>(function: F) -> Result<()>
+where
+    F: FnOnce() -> Result<(), E> + Send + 'static,
+    E: ToString,
+{
+    tokio::task::spawn_blocking(move || function().map_err(|err| err.to_string()))
+        .await?
+        .map_err(|err| anyhow!(err))
+}
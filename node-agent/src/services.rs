@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{info, warn};
+use network::{
+    port::Port,
+    service::{
+        self,
+        request::{CleanServiceRequest, SetupServiceRequest},
+    },
+};
+use serde_derive::Deserialize;
+
+use crate::controller::ControllerClient;
+use crate::policies::blocking;
+
+/// The delay between two syncs of the services, following the instances started or stopped
+/// behind them.
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The services of the cluster, as listed by `GET /service`.
+#[derive(Debug, Deserialize)]
+struct ServiceVector {
+    services: Vec<Service>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Service {
+    id: String,
+    name: String,
+    namespace: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Ports {
+    source: i32,
+    destination: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct NodePort {
+    port: i32,
+    node_port: i32,
+}
+
+/// The virtual IP of a service and its ready instances, as served by
+/// `GET /service/<namespace>/<name>/endpoints`.
+#[derive(Debug, Deserialize)]
+struct ServiceEndpoints {
+    virtual_ip: Ipv4Addr,
+    ports: Vec<Ports>,
+    node_ports: Vec<NodePort>,
+    endpoints: Vec<Ipv4Addr>,
+}
+
+impl ServiceEndpoints {
+    /// Returns the node ports of the service forwarded to the port of the instances behind the
+    /// port of the service they are opened for.
+    fn node_ports(&self) -> Vec<Port> {
+        self.node_ports
+            .iter()
+            .filter_map(|node_port| {
+                self.ports
+                    .iter()
+                    .find(|port| port.source == node_port.port)
+                    .map(|port| Port::new(node_port.node_port, port.destination))
+            })
+            .collect()
+    }
+}
+
+/// What is programmed on the node for a service, removed with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Programmed {
+    virtual_ip: Ipv4Addr,
+    node_ports: Vec<i32>,
+}
+
+/// `ServiceProxy` programs the virtual IPs and the node ports of the services of the cluster on
+/// the node, redirecting their traffic to the ready instances behind them.
+///
+/// Properties:
+///
+/// * `controller`: The controller serving the services and their endpoints.
+/// * `programmed`: The virtual IP and the node ports programmed for each service, by id.
+pub struct ServiceProxy {
+    controller: ControllerClient,
+    programmed: HashMap<String, Programmed>,
+}
+
+impl ServiceProxy {
+    pub fn new(controller: ControllerClient) -> Self {
+        ServiceProxy {
+            controller,
+            programmed: HashMap::new(),
+        }
+    }
+
+    /// Programs every service of the cluster with its current endpoints and removes the deleted
+    /// services. A service which can't be programmed is retried on the next sync.
+    pub async fn sync(&mut self) -> anyhow::Result<()> {
+        let services: ServiceVector = self.controller.get("service").await?;
+
+        let ids: HashSet<&String> = services
+            .services
+            .iter()
+            .map(|service| &service.id)
+            .collect();
+        let deleted: Vec<String> = self
+            .programmed
+            .keys()
+            .filter(|id| !ids.contains(id))
+            .cloned()
+            .collect();
+        for id in deleted {
+            self.clean(&id).await;
+        }
+
+        for service in &services.services {
+            if let Err(err) = self.setup(service).await {
+                warn!("failed to program service {}: {:#}", service.id, err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Syncs the services until the agent stops, a failed sync being retried on the next tick.
+    pub async fn run(&mut self) {
+        let mut ticker = tokio::time::interval(SYNC_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.sync().await {
+                warn!("failed to sync the services: {:#}", err);
+            }
+        }
+    }
+
+    /// Programs a service with its current endpoints. Its rules are removed first if its node
+    /// ports changed, programming it again only replacing its endpoints.
+    async fn setup(&mut self, service: &Service) -> anyhow::Result<()> {
+        let endpoints: ServiceEndpoints = self
+            .controller
+            .get(&format!(
+                "service/{}/{}/endpoints",
+                service.namespace, service.name
+            ))
+            .await?;
+        let node_ports = endpoints.node_ports();
+        let programmed = Programmed {
+            virtual_ip: endpoints.virtual_ip,
+            node_ports: node_ports.iter().map(|port| port.source).collect(),
+        };
+        if self
+            .programmed
+            .get(&service.id)
+            .is_some_and(|previous| *previous != programmed)
+        {
+            self.clean(&service.id).await;
+        }
+
+        let request = SetupServiceRequest::new(
+            service.id.clone(),
+            endpoints.virtual_ip,
+            endpoints
+                .ports
+                .iter()
+                .map(|port| Port::new(port.source, port.destination))
+                .collect(),
+            node_ports,
+            endpoints.endpoints,
+        );
+        blocking(move || service::setup_service(request))
+            .await
+            .with_context(|| format!("Error programming virtual IP {}", programmed.virtual_ip))?;
+        if self
+            .programmed
+            .insert(service.id.clone(), programmed)
+            .is_none()
+        {
+            info!("programmed service {}", service.id);
+        }
+        Ok(())
+    }
+
+    /// Removes the rules of a service, they are forgotten even if they can't be removed.
+    async fn clean(&mut self, id: &str) {
+        let Some(programmed) = self.programmed.remove(id) else {
+            return;
+        };
+        let request =
+            CleanServiceRequest::new(id.to_string(), programmed.virtual_ip, programmed.node_ports);
+        match blocking(move || service::clean_service(request)).await {
+            Ok(()) => info!("removed service {}", id),
+            Err(err) => warn!("failed to remove service {}: {:#}", id, err),
+        }
+    }
+}
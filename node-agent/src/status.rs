@@ -0,0 +1,163 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use log::warn;
+use node_manager::{
+    status::{StatusFilter, StatusUpdateConfig},
+    NodeSystem,
+};
+use proto::agent::Device;
+use proto::scheduler::{NodeStatus, Resource, ResourceSummary, Status};
+use tokio::sync::{mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use workload_manager::workload_manager::{
+    cpus::CpuManager,
+    devices::{DeviceRegistry, DEVICE_PLUGINS_DIR},
+    hugepages::HugePagesManager,
+};
+
+use crate::connection::SchedulerClient;
+
+/// The delay between two samples of the status of the node.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The delay between two runs of the device plugins of the node.
+const DEVICE_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How many statuses can be queued for the scheduler, the sampling waits beyond.
+const STATUS_BUFFER: usize = 4;
+
+/// `StatusReporter` samples the status of the node and sends it on the status stream of the
+/// scheduler, `filter` holding back the samples without significant change until the heartbeat
+/// is due.
+///
+/// Properties:
+///
+/// * `node_id`: The id of the node, in each status.
+/// * `system`: The resources of the node, `None` while a sample is being taken.
+/// * `filter`: Decides which samples are sent.
+/// * `intervals`: The interval asked by the scheduler between two statuses, in milliseconds.
+/// * `devices`: The devices advertised by the plugins of the node, and when they were last run.
+/// * `cpus`: The CPUs of each NUMA node of the node and how many are pinned.
+/// * `huge_pages`: The huge pages of each size of the node and how many are reserved.
+pub struct StatusReporter {
+    node_id: String,
+    system: Option<NodeSystem>,
+    filter: StatusFilter,
+    intervals: watch::Receiver<u32>,
+    devices: Option<(DeviceRegistry, Option<Instant>)>,
+    cpus: CpuManager,
+    huge_pages: HugePagesManager,
+}
+
+impl StatusReporter {
+    pub fn new(node_id: String, intervals: watch::Receiver<u32>) -> Self {
+        StatusReporter {
+            node_id,
+            system: Some(NodeSystem::new()),
+            filter: StatusFilter::new(StatusUpdateConfig::default()),
+            intervals,
+            devices: None,
+            cpus: CpuManager::default(),
+            huge_pages: HugePagesManager::default(),
+        }
+    }
+
+    /// Reports the devices of `registry`, refreshed from the device plugins of the node every
+    /// minute, the node having no device without it.
+    pub fn with_devices(mut self, registry: DeviceRegistry) -> Self {
+        self.devices = Some((registry, None));
+        self
+    }
+
+    /// Reports the NUMA nodes of `manager`, the node having no CPU to pin without it.
+    pub fn with_cpus(mut self, manager: CpuManager) -> Self {
+        self.cpus = manager;
+        self
+    }
+
+    /// Reports the huge pages of `manager`, the node having no huge pages without it.
+    pub fn with_huge_pages(mut self, manager: HugePagesManager) -> Self {
+        self.huge_pages = manager;
+        self
+    }
+
+    /// Opens the status stream of the node and sends its statuses on it, returns once the
+    /// scheduler closed it. The first status of each stream is always sent.
+    pub async fn run(&mut self, client: &mut SchedulerClient) -> Result<()> {
+        self.filter.reset();
+        let (sender, receiver) = mpsc::channel(STATUS_BUFFER);
+        let call = client.status(ReceiverStream::new(receiver));
+
+        let sampling = async {
+            let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if self.intervals.has_changed()? {
+                    self.filter
+                        .set_interval(*self.intervals.borrow_and_update());
+                }
+
+                let status = self.sample().await?;
+                if self.filter.should_send(&status, Instant::now())
+                    && sender.send(status).await.is_err()
+                {
+                    return Ok(());
+                }
+            }
+        };
+
+        tokio::select! {
+            result = call => result.map(|_| ()).map_err(Into::into),
+            result = sampling => result,
+        }
+    }
+
+    /// Samples the resources of the node, the CPU usage being measured over a fraction of a
+    /// second on a blocking thread.
+    async fn sample(&mut self) -> Result<NodeStatus> {
+        let mut system = self.system.take().unwrap_or_default();
+        let (system, resource) = tokio::task::spawn_blocking(move || {
+            let resource = Resource {
+                limit: Some(ResourceSummary {
+                    cpu: system.total_cpu(),
+                    memory: system.total_memory(),
+                    disk: system.total_disk(),
+                }),
+                usage: Some(ResourceSummary {
+                    cpu: system.used_cpu(),
+                    memory: system.used_memory(),
+                    disk: system.used_disk(),
+                }),
+            };
+            (system, resource)
+        })
+        .await?;
+        self.system = Some(system);
+
+        Ok(NodeStatus {
+            id: self.node_id.clone(),
+            status: Status::Running.into(),
+            resource: Some(resource),
+            devices: self.sample_devices().await,
+            numa_nodes: self.cpus.numa_nodes(),
+            huge_pages: self.huge_pages.huge_pages(),
+            ..Default::default()
+        })
+    }
+
+    /// Returns the devices of the node, after running its device plugins again if they are due.
+    async fn sample_devices(&mut self) -> Vec<Device> {
+        let Some((registry, refreshed_at)) = &mut self.devices else {
+            return vec![];
+        };
+        if refreshed_at.is_none_or(|at| at.elapsed() >= DEVICE_REFRESH_INTERVAL) {
+            if let Err(err) = registry.refresh(Path::new(DEVICE_PLUGINS_DIR)).await {
+                warn!("failed to list the devices of the node: {:#}", err);
+            }
+            *refreshed_at = Some(Instant::now());
+        }
+        registry.devices()
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use proto::agent::NumaNode;
+
+/// The sysfs directory of the NUMA nodes of the node.
+const NUMA_NODES_DIR: &str = "/sys/devices/system/node";
+
+/// The online CPUs of the node, read when it has no NUMA node.
+const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
+
+/// Parses a list of CPUs as written by the kernel, e.g. `0-3,8-11`.
+pub fn parse_cpu_list(list: &str) -> Result<Vec<u32>> {
+    let mut cpus = vec![];
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let first: u32 = first
+            .parse()
+            .with_context(|| format!("Invalid CPU list {}. ", list))?;
+        let last: u32 = last
+            .parse()
+            .with_context(|| format!("Invalid CPU list {}. ", list))?;
+        if first > last {
+            bail!("Invalid CPU list {}. ", list);
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Formats a sorted list of CPUs as the cpuset of a container, e.g. `0-3,8`.
+pub fn format_cpu_list(cpus: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(first, last)| {
+            if first == last {
+                first.to_string()
+            } else {
+                format!("{}-{}", first, last)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Reads the CPUs of each NUMA node of the node in `numa_dir`, a node without NUMA being a
+/// single NUMA node holding its `online` CPUs.
+fn read_topology(numa_dir: &Path, online: &Path) -> Result<Vec<(u32, Vec<u32>)>> {
+    let mut topology = vec![];
+    if let Ok(entries) = fs::read_dir(numa_dir) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let name = entry.file_name();
+            let Some(id) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("node"))
+                .and_then(|id| id.parse().ok())
+            else {
+                continue;
+            };
+            let list = fs::read_to_string(entry.path().join("cpulist"))
+                .with_context(|| format!("Can't read the CPUs of NUMA node {}. ", id))?;
+            topology.push((id, parse_cpu_list(&list)?));
+        }
+    }
+
+    if topology.is_empty() {
+        let list = fs::read_to_string(online).context("Can't read the online CPUs. ")?;
+        topology.push((0, parse_cpu_list(&list)?));
+    }
+    topology.sort();
+    Ok(topology)
+}
+
+/// The CPUs pinned to an instance, all on the same NUMA node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuSet {
+    pub numa_node: u32,
+    pub cpus: Vec<u32>,
+}
+
+/// The CPUs of each NUMA node and the ones pinned to each instance.
+#[derive(Debug, Default)]
+struct Cpus {
+    topology: Vec<(u32, Vec<u32>)>,
+    allocations: HashMap<String, CpuSet>,
+}
+
+impl Cpus {
+    /// Returns the CPUs of a NUMA node not pinned to any instance.
+    fn free(&self, cpus: &[u32]) -> Vec<u32> {
+        cpus.iter()
+            .filter(|cpu| {
+                !self
+                    .allocations
+                    .values()
+                    .any(|cpuset| cpuset.cpus.contains(cpu))
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// `CpuManager` pins exclusive CPUs to the instances with the static CPU policy, so their
+/// latency doesn't suffer from the other instances. The CPUs of an instance are taken from a
+/// single NUMA node, its memory being allocated on the same node, and the NUMA node with the
+/// fewest free CPUs fitting the instance is used, keeping the larger ones for the larger
+/// instances. The other instances aren't pinned, they may run on any CPU.
+#[derive(Debug, Clone, Default)]
+pub struct CpuManager {
+    cpus: Arc<Mutex<Cpus>>,
+}
+
+impl CpuManager {
+    /// Creates a manager of the CPUs of the node, read from sysfs.
+    pub fn detect() -> Result<Self> {
+        let topology = read_topology(Path::new(NUMA_NODES_DIR), Path::new(ONLINE_CPUS))?;
+        Ok(Self::with_topology(topology))
+    }
+
+    /// Creates a manager of the CPUs of each NUMA node, by NUMA node id.
+    pub fn with_topology(topology: Vec<(u32, Vec<u32>)>) -> Self {
+        CpuManager {
+            cpus: Arc::new(Mutex::new(Cpus {
+                topology,
+                allocations: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Returns the NUMA nodes of the node and how many of their CPUs are pinned, reported in the
+    /// status of the node.
+    pub fn numa_nodes(&self) -> Vec<NumaNode> {
+        let cpus = self.lock();
+        cpus.topology
+            .iter()
+            .map(|(id, node_cpus)| NumaNode {
+                id: *id,
+                cpus: node_cpus.clone(),
+                pinned: (node_cpus.len() - cpus.free(node_cpus).len()) as u32,
+            })
+            .collect()
+    }
+
+    /// Pins `count` free CPUs of a single NUMA node to an instance, `None` if it requests none.
+    /// The CPUs are kept until the instance is released.
+    pub(crate) fn allocate(&self, instance_id: &str, count: u32) -> Result<Option<CpuSet>> {
+        if count == 0 {
+            return Ok(None);
+        }
+        let count = count as usize;
+
+        let mut cpus = self.lock();
+        let free: Vec<(u32, Vec<u32>)> = cpus
+            .topology
+            .iter()
+            .map(|(id, node_cpus)| (*id, cpus.free(node_cpus)))
+            .collect();
+        let Some((numa_node, free_cpus)) = free
+            .iter()
+            .filter(|(_, free_cpus)| free_cpus.len() >= count)
+            .min_by_key(|(id, free_cpus)| (free_cpus.len(), *id))
+        else {
+            let largest = free.iter().map(|(_, cpus)| cpus.len()).max();
+            bail!(
+                "{} exclusive CPU(s) requested, at most {} free on a NUMA node. ",
+                count,
+                largest.unwrap_or(0)
+            );
+        };
+
+        let cpuset = CpuSet {
+            numa_node: *numa_node,
+            cpus: free_cpus[..count].to_vec(),
+        };
+        cpus.allocations
+            .insert(instance_id.to_string(), cpuset.clone());
+        Ok(Some(cpuset))
+    }
+
+    /// Frees the CPUs pinned to an instance, once it is gone.
+    pub(crate) fn release(&self, instance_id: &str) {
+        self.lock().allocations.remove(instance_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cpus> {
+        // the CPUs are left consistent by every critical section, a poisoned lock is still
+        // usable
+        self.cpus
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_list() {
+        assert_eq!(parse_cpu_list("0-3,8\n").unwrap(), vec![0, 1, 2, 3, 8]);
+        assert_eq!(parse_cpu_list("").unwrap(), Vec::<u32>::new());
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8, 10, 11]), "0-3,8,10-11");
+        assert_eq!(format_cpu_list(&[]), "");
+    }
+
+    #[test]
+    fn test_read_topology() {
+        let dir = std::env::temp_dir().join("kudo-numa-test");
+        fs::create_dir_all(dir.join("node1")).unwrap();
+        fs::create_dir_all(dir.join("node0")).unwrap();
+        fs::create_dir_all(dir.join("power")).unwrap();
+        fs::write(dir.join("node0/cpulist"), "0-1\n").unwrap();
+        fs::write(dir.join("node1/cpulist"), "2-3\n").unwrap();
+
+        let topology = read_topology(&dir, Path::new("/nonexistent")).unwrap();
+        assert_eq!(topology, vec![(0, vec![0, 1]), (1, vec![2, 3])]);
+
+        fs::remove_dir_all(&dir).unwrap();
+        let online = std::env::temp_dir().join("kudo-online-cpus-test");
+        fs::write(&online, "0-7\n").unwrap();
+        let topology = read_topology(&dir, &online).unwrap();
+        assert_eq!(topology, vec![(0, (0..8).collect::<Vec<u32>>())]);
+        fs::remove_file(&online).unwrap();
+    }
+
+    #[test]
+    fn test_allocate() {
+        let manager = CpuManager::with_topology(vec![(0, vec![0, 1, 2, 3]), (1, vec![4, 5])]);
+        assert_eq!(manager.allocate("a", 0).unwrap(), None);
+
+        // the smallest NUMA node fitting the instance is used
+        let first = manager.allocate("a", 2).unwrap().unwrap();
+        assert_eq!(first.numa_node, 1);
+        assert_eq!(first.cpus, vec![4, 5]);
+        let second = manager.allocate("b", 3).unwrap().unwrap();
+        assert_eq!(second.numa_node, 0);
+        assert_eq!(second.cpus, vec![0, 1, 2]);
+
+        // the CPUs of an instance never span two NUMA nodes
+        assert!(manager.allocate("c", 2).is_err());
+        assert_eq!(manager.numa_nodes()[0].pinned, 3);
+
+        manager.release("a");
+        assert_eq!(manager.allocate("c", 2).unwrap().unwrap().cpus, vec![4, 5]);
+    }
+}
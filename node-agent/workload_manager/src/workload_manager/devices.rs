@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use proto::agent::Device;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// The directory of the device plugins of the node, each plugin being an executable named after
+/// the kind of the devices it advertises.
+pub const DEVICE_PLUGINS_DIR: &str = "/etc/kudo/device-plugins";
+
+/// How long a device plugin may take to list its devices.
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Parses the devices listed by a plugin: one device per line, its id followed by the device
+/// files mounted into the containers using it, e.g. `0 /dev/nvidia0 /dev/nvidiactl`. The empty
+/// lines and the comments starting with `#` are skipped.
+fn parse_devices(kind: &str, output: &str) -> Result<Vec<Device>> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let id = fields.next().unwrap_or_default().to_string();
+            let paths: Vec<String> = fields.map(str::to_string).collect();
+            if paths.is_empty() {
+                bail!("Device {} of plugin {} has no device file. ", id, kind);
+            }
+            if let Some(path) = paths.iter().find(|path| !Path::new(path).is_absolute()) {
+                bail!("Device file {} of plugin {} is not absolute. ", path, kind);
+            }
+            Ok(Device {
+                id,
+                kind: kind.to_string(),
+                paths,
+            })
+        })
+        .collect()
+}
+
+/// Runs a device plugin with the `list` argument and returns the devices it advertises.
+async fn list_devices(plugin: &Path) -> Result<Vec<Device>> {
+    let kind = plugin
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("Invalid device plugin {}. ", plugin.display()))?;
+
+    let output = Command::new(plugin).arg("list").kill_on_drop(true).output();
+    let output = timeout(PLUGIN_TIMEOUT, output)
+        .await
+        .with_context(|| format!("Device plugin {} timed out. ", kind))?
+        .with_context(|| format!("Can't run device plugin {}. ", kind))?;
+    if !output.status.success() {
+        bail!(
+            "Device plugin {} failed: {}",
+            kind,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    parse_devices(kind, &String::from_utf8_lossy(&output.stdout))
+}
+
+/// The devices advertised by the plugins and the ones allocated to each instance.
+#[derive(Debug, Default)]
+struct Devices {
+    advertised: Vec<Device>,
+    allocations: HashMap<String, Vec<Device>>,
+}
+
+/// `DeviceRegistry` keeps the devices advertised by the device plugins of the node, e.g. its GPUs,
+/// and the instances they are allocated to. A plugin is an executable of the plugins directory,
+/// run with the `list` argument, which prints the devices of its kind: each instance requesting
+/// devices of a kind gets some free ones, mounted into its main container.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceRegistry {
+    devices: Arc<Mutex<Devices>>,
+}
+
+impl DeviceRegistry {
+    /// Runs every plugin of `plugins_dir` and keeps the devices they advertise, a missing
+    /// directory meaning no plugin. The devices of the plugins which answered are kept even if
+    /// others failed, their errors being returned.
+    pub async fn refresh(&self, plugins_dir: &Path) -> Result<()> {
+        let mut plugins: Vec<PathBuf> = match fs::read_dir(plugins_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file())
+                .collect(),
+            Err(_) => vec![],
+        };
+        plugins.sort();
+
+        let mut advertised = vec![];
+        let mut errors = vec![];
+        for plugin in plugins {
+            match list_devices(&plugin).await {
+                Ok(devices) => advertised.extend(devices),
+                Err(err) => errors.push(format!("{:#}", err)),
+            }
+        }
+        self.lock().advertised = advertised;
+
+        if !errors.is_empty() {
+            bail!("{}", errors.join(" "));
+        }
+        Ok(())
+    }
+
+    /// Returns the devices advertised by the plugins, reported in the status of the node.
+    pub fn devices(&self) -> Vec<Device> {
+        self.lock().advertised.clone()
+    }
+
+    /// Allocates free devices of each requested kind to an instance, none if any kind lacks
+    /// devices. The devices are kept until the instance is released.
+    pub(crate) fn allocate(
+        &self,
+        instance_id: &str,
+        requests: &HashMap<String, u32>,
+    ) -> Result<Vec<Device>> {
+        let mut kinds: Vec<(&String, &u32)> = requests.iter().collect();
+        kinds.sort();
+
+        let mut devices = self.lock();
+        let used: Vec<Device> = devices.allocations.values().flatten().cloned().collect();
+        let mut allocated: Vec<Device> = vec![];
+        for (kind, count) in kinds {
+            let count = *count as usize;
+            let free: Vec<&Device> = devices
+                .advertised
+                .iter()
+                .filter(|device| device.kind == *kind && !used.contains(device))
+                .take(count)
+                .collect();
+            if free.len() < count {
+                bail!(
+                    "{} device(s) of kind {} requested, {} free on the node. ",
+                    count,
+                    kind,
+                    free.len()
+                );
+            }
+            allocated.extend(free.into_iter().cloned());
+        }
+
+        if !allocated.is_empty() {
+            devices
+                .allocations
+                .insert(instance_id.to_string(), allocated.clone());
+        }
+        Ok(allocated)
+    }
+
+    /// Frees the devices allocated to an instance, once it is gone.
+    pub(crate) fn release(&self, instance_id: &str) {
+        self.lock().allocations.remove(instance_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Devices> {
+        // the devices are left consistent by every critical section, a poisoned lock is still
+        // usable
+        self.devices
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(output: &str) -> DeviceRegistry {
+        let registry = DeviceRegistry::default();
+        registry.lock().advertised = parse_devices("gpu", output).unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_parse_devices() {
+        let devices = parse_devices(
+            "gpu",
+            "# nvidia\n0 /dev/nvidia0 /dev/nvidiactl\n\n1 /dev/nvidia1 /dev/nvidiactl\n",
+        )
+        .unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1].id, "1");
+        assert_eq!(devices[1].kind, "gpu");
+        assert_eq!(devices[1].paths, vec!["/dev/nvidia1", "/dev/nvidiactl"]);
+
+        assert!(parse_devices("gpu", "0\n").is_err());
+        assert!(parse_devices("gpu", "0 dev/nvidia0\n").is_err());
+    }
+
+    #[test]
+    fn test_allocate() {
+        let registry = registry("0 /dev/nvidia0\n1 /dev/nvidia1\n");
+        let requests = HashMap::from([("gpu".to_string(), 1)]);
+
+        let first = registry.allocate("a", &requests).unwrap();
+        let second = registry.allocate("b", &requests).unwrap();
+        assert_eq!(first[0].id, "0");
+        assert_eq!(second[0].id, "1");
+        assert!(registry.allocate("c", &requests).is_err());
+        assert!(registry
+            .allocate("c", &HashMap::from([("fpga".to_string(), 1)]))
+            .is_err());
+        assert!(registry.allocate("c", &HashMap::new()).unwrap().is_empty());
+
+        registry.release("a");
+        assert_eq!(registry.allocate("c", &requests).unwrap()[0].id, "0");
+    }
+
+    #[tokio::test]
+    async fn test_refresh_without_plugins() {
+        let registry = registry("0 /dev/nvidia0\n");
+        registry
+            .refresh(Path::new("/nonexistent/device-plugins"))
+            .await
+            .unwrap();
+        assert!(registry.devices().is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use proto::agent::HugePages;
+
+/// The sysfs directory of the huge pages of the node, a directory per page size.
+const HUGE_PAGES_DIR: &str = "/sys/kernel/mm/hugepages";
+
+/// Returns the name of a page size given in kB, as requested by the workloads, e.g. `2Mi`.
+fn size_name(kb: u64) -> String {
+    if kb.is_multiple_of(1024 * 1024) {
+        format!("{}Gi", kb / (1024 * 1024))
+    } else if kb.is_multiple_of(1024) {
+        format!("{}Mi", kb / 1024)
+    } else {
+        format!("{}Ki", kb)
+    }
+}
+
+/// Returns the bytes of a page size named as requested by the workloads, e.g. `2Mi`.
+pub fn page_bytes(size: &str) -> Result<u64> {
+    let (value, unit) = size.split_at(size.len().saturating_sub(2));
+    let value: u64 = value
+        .parse()
+        .with_context(|| format!("Invalid huge page size {}. ", size))?;
+    match unit {
+        "Ki" => Ok(value * 1024),
+        "Mi" => Ok(value * 1024 * 1024),
+        "Gi" => Ok(value * 1024 * 1024 * 1024),
+        _ => bail!("Invalid huge page size {}. ", size),
+    }
+}
+
+/// Reads the number of huge pages of each size reserved on the node in `dir`, by size name. A
+/// node without huge pages has none.
+fn read_huge_pages(dir: &Path) -> Result<HashMap<String, u64>> {
+    let mut pages = HashMap::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(pages);
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = entry.file_name();
+        let Some(kb) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("hugepages-"))
+            .and_then(|size| size.strip_suffix("kB"))
+            .and_then(|kb| kb.parse().ok())
+        else {
+            continue;
+        };
+        let count = fs::read_to_string(entry.path().join("nr_hugepages"))
+            .with_context(|| format!("Can't read the huge pages of {} kB. ", kb))?;
+        let count = count
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid number of huge pages of {} kB. ", kb))?;
+        pages.insert(size_name(kb), count);
+    }
+    Ok(pages)
+}
+
+/// The huge pages of the node and the ones reserved by each instance.
+#[derive(Debug, Default)]
+struct Pages {
+    total: HashMap<String, u64>,
+    reservations: HashMap<String, HashMap<String, u64>>,
+}
+
+impl Pages {
+    /// Returns the pages of a size reserved by the instances.
+    fn reserved(&self, size: &str) -> u64 {
+        self.reservations
+            .values()
+            .filter_map(|pages| pages.get(size))
+            .sum()
+    }
+}
+
+/// `HugePagesManager` reserves the huge pages of the node to the instances requesting them. The
+/// pages are set aside by the kernel of the node, e.g. with the `hugepages` boot parameter, and
+/// an instance gets a hugetlbfs mount of each size it requests, capped to its pages: the pages
+/// of a size are shared among the instances until none is left.
+#[derive(Debug, Clone, Default)]
+pub struct HugePagesManager {
+    pages: Arc<Mutex<Pages>>,
+}
+
+impl HugePagesManager {
+    /// Creates a manager of the huge pages of the node, read from sysfs.
+    pub fn detect() -> Result<Self> {
+        let pages = read_huge_pages(Path::new(HUGE_PAGES_DIR))?;
+        Ok(Self::with_pages(pages))
+    }
+
+    /// Creates a manager of the number of huge pages of each size, by size name, e.g. `2Mi`.
+    pub fn with_pages(total: HashMap<String, u64>) -> Self {
+        HugePagesManager {
+            pages: Arc::new(Mutex::new(Pages {
+                total,
+                reservations: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Returns the huge pages of each size of the node and how many are reserved, reported in
+    /// the status of the node.
+    pub fn huge_pages(&self) -> Vec<HugePages> {
+        let pages = self.lock();
+        let mut huge_pages: Vec<HugePages> = pages
+            .total
+            .iter()
+            .map(|(size, total)| HugePages {
+                size: size.clone(),
+                total: *total,
+                reserved: pages.reserved(size),
+            })
+            .collect();
+        huge_pages.sort_by(|a, b| a.size.cmp(&b.size));
+        huge_pages
+    }
+
+    /// Reserves the requested number of huge pages of each size to an instance, none if a size
+    /// lacks pages. The pages are kept until the instance is released.
+    pub(crate) fn allocate(
+        &self,
+        instance_id: &str,
+        requests: &HashMap<String, u64>,
+    ) -> Result<()> {
+        let mut pages = self.lock();
+        let mut sizes: Vec<(&String, &u64)> = requests.iter().collect();
+        sizes.sort();
+        for (size, count) in sizes {
+            let total = pages.total.get(size).copied().unwrap_or(0);
+            let free = total.saturating_sub(pages.reserved(size));
+            if *count > free {
+                bail!(
+                    "{} huge page(s) of {} requested, {} free on the node. ",
+                    count,
+                    size,
+                    free
+                );
+            }
+        }
+
+        if requests.values().any(|count| *count > 0) {
+            pages
+                .reservations
+                .insert(instance_id.to_string(), requests.clone());
+        }
+        Ok(())
+    }
+
+    /// Frees the huge pages reserved by an instance, once it is gone.
+    pub(crate) fn release(&self, instance_id: &str) {
+        self.lock().reservations.remove(instance_id);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Pages> {
+        // the pages are left consistent by every critical section, a poisoned lock is still
+        // usable
+        self.pages
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(size_name(2048), "2Mi");
+        assert_eq!(size_name(1048576), "1Gi");
+        assert_eq!(size_name(64), "64Ki");
+
+        assert_eq!(page_bytes("2Mi").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(page_bytes("1Gi").unwrap(), 1024 * 1024 * 1024);
+        assert!(page_bytes("2M").is_err());
+        assert!(page_bytes("Gi").is_err());
+    }
+
+    #[test]
+    fn test_read_huge_pages() {
+        let dir = std::env::temp_dir().join("kudo-hugepages-test");
+        fs::create_dir_all(dir.join("hugepages-2048kB")).unwrap();
+        fs::create_dir_all(dir.join("hugepages-1048576kB")).unwrap();
+        fs::write(dir.join("hugepages-2048kB/nr_hugepages"), "512\n").unwrap();
+        fs::write(dir.join("hugepages-1048576kB/nr_hugepages"), "0\n").unwrap();
+
+        let pages = read_huge_pages(&dir).unwrap();
+        assert_eq!(
+            pages,
+            HashMap::from([("2Mi".to_string(), 512), ("1Gi".to_string(), 0)])
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(read_huge_pages(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_allocate() {
+        let manager = HugePagesManager::with_pages(HashMap::from([("2Mi".to_string(), 512)]));
+        let requests = |count| HashMap::from([("2Mi".to_string(), count)]);
+
+        manager.allocate("a", &requests(256)).unwrap();
+        manager.allocate("b", &requests(200)).unwrap();
+        assert!(manager.allocate("c", &requests(100)).is_err());
+        assert!(manager
+            .allocate("c", &HashMap::from([("1Gi".to_string(), 1)]))
+            .is_err());
+        assert_eq!(manager.huge_pages()[0].reserved, 456);
+
+        manager.release("a");
+        manager.allocate("c", &requests(100)).unwrap();
+        assert_eq!(manager.huge_pages()[0].reserved, 300);
+    }
+}
@@ -1 +1,666 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use image_policy::Verifier;
+use network::cni::CniNetwork;
+use proto::agent::{
+    Checkpoint, CheckpointStatus, ExecStart, ImagePullState, ImagePullStatus, Instance,
+    InstanceStatus, LogRequest, Status,
+};
+use tokio::sync::{mpsc, oneshot};
+
+use cpus::CpuManager;
+use devices::DeviceRegistry;
+use hugepages::HugePagesManager;
+use workload::{disk_quota, workload_trait::Workload, LogConfig, StatusReporter};
+
+pub mod cpus;
+pub mod devices;
+pub mod hugepages;
 pub mod workload;
+
+/// How many signals can be queued for an instance, its senders wait beyond.
+const SIGNAL_BUFFER: usize = 8;
+
+/// A signal sent to the workload of an instance, both remove the workload once it stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Stop the workload gracefully
+    Stop,
+    /// Kill the workload
+    Kill,
+}
+
+/// An input of a command run in the workload of an instance, its stdin is closed once the sender
+/// of the inputs is dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecInput {
+    /// Bytes written to the stdin of the command
+    Stdin(Vec<u8>),
+    /// New size of the terminal of the command, in columns and rows
+    Resize(u16, u16),
+}
+
+/// An operation applied to the workload of an instance by its task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operation {
+    Signal(Signal),
+    /// Checkpoint the memory of the workload, see `Workload::checkpoint`
+    Checkpoint {
+        name: String,
+        leave_running: bool,
+    },
+}
+
+/// An operation and the channel its result is sent back on.
+type Command = (Operation, oneshot::Sender<Result<()>>);
+
+/// `InstanceTask` is the task driving the workload of an instance.
+///
+/// Properties:
+///
+/// * `commands`: The channel of the operations sent to the task.
+/// * `workload_id`: The id of the workload in its runtime, once it is created.
+struct InstanceTask {
+    commands: mpsc::Sender<Command>,
+    workload_id: Option<String>,
+}
+
+/// The delay between two checks of the disk used by an instance.
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// `DiskMonitor` periodically checks the disk an instance uses on the node. Its writable layers
+/// and its volumes are checked against its quota: the writable layers are capped by the runtime
+/// if its storage driver supports it, but the volumes aren't, so the instance is killed once it
+/// exceeds its quota. Its logs are rotated by the runtime, they are only reported.
+///
+/// Properties:
+///
+/// * `quota`: The bytes the instance may use, 0 without quota.
+/// * `reporter`: The channel the usage is reported on, if set.
+struct DiskMonitor {
+    quota: u64,
+    reporter: Option<StatusReporter>,
+}
+
+/// `StartHook` runs before the workload of each instance is created, once the resources of the
+/// instance are allocated, e.g. to program the firewall of the node for it. The creation of the
+/// instance fails if it does.
+#[tonic::async_trait]
+pub trait StartHook: Send + Sync {
+    async fn before_start(&self, instance: &Instance) -> Result<()>;
+}
+
+/// `WorkloadManager` runs the workloads of the instances of the node. Each instance is driven by
+/// its own task, which creates its workload then applies the signals sent to it in order, so a
+/// slow operation on an instance (an image pull, a graceful stop) doesn't delay the others. The
+/// manager only keeps the channel of each task, its lock is never held while a workload is being
+/// operated.
+///
+/// Properties:
+///
+/// * `instances`: The task of each instance, by instance id.
+/// * `verifier`: Verifies the signature of the images before they are run, if set.
+/// * `statuses`: The channel the intermediate statuses of the instances being created are sent
+///   on, e.g. the progress of the pull of their image, if set.
+/// * `logs`: The retention of the logs of the instances.
+/// * `devices`: The devices of the node advertised by its plugins, allocated to the instances
+///   requesting them.
+/// * `cpus`: The CPUs of the node, pinned to the instances requesting exclusive CPUs.
+/// * `huge_pages`: The huge pages of the node, reserved by the instances requesting them.
+/// * `network`: The CNI network of the node the instances are added to, the network of the
+///   runtime being used without it.
+/// * `start_hook`: Runs before the workload of each instance is created, if set.
+#[derive(Clone, Default)]
+pub struct WorkloadManager {
+    instances: Arc<Mutex<HashMap<String, InstanceTask>>>,
+    verifier: Option<Arc<Verifier>>,
+    statuses: Option<mpsc::Sender<InstanceStatus>>,
+    logs: LogConfig,
+    devices: DeviceRegistry,
+    cpus: CpuManager,
+    huge_pages: HugePagesManager,
+    network: Option<Arc<CniNetwork>>,
+    start_hook: Option<Arc<dyn StartHook>>,
+}
+
+impl WorkloadManager {
+    pub fn new(verifier: Option<Verifier>) -> Self {
+        WorkloadManager {
+            instances: Arc::default(),
+            verifier: verifier.map(Arc::new),
+            statuses: None,
+            logs: LogConfig::default(),
+            devices: DeviceRegistry::default(),
+            cpus: CpuManager::default(),
+            huge_pages: HugePagesManager::default(),
+            network: None,
+            start_hook: None,
+        }
+    }
+
+    /// Sends the intermediate statuses of the instances being created on `sender`, a status
+    /// being dropped if the receiver lags behind.
+    pub fn with_statuses(mut self, sender: mpsc::Sender<InstanceStatus>) -> Self {
+        self.statuses = Some(sender);
+        self
+    }
+
+    /// Rotates the log files of the instances and keeps their latest files as `config` says,
+    /// instead of the default retention.
+    pub fn with_logs(mut self, config: LogConfig) -> Self {
+        self.logs = config;
+        self
+    }
+
+    /// Mounts the devices of `registry` into the instances requesting them, the registry being
+    /// refreshed by the agent as the plugins advertise their devices.
+    pub fn with_devices(mut self, registry: DeviceRegistry) -> Self {
+        self.devices = registry;
+        self
+    }
+
+    /// Pins the CPUs of `manager` to the instances requesting exclusive CPUs, the node having no
+    /// CPU to pin without it.
+    pub fn with_cpus(mut self, manager: CpuManager) -> Self {
+        self.cpus = manager;
+        self
+    }
+
+    /// Reserves the huge pages of `manager` to the instances requesting them, the node having no
+    /// huge pages without it.
+    pub fn with_huge_pages(mut self, manager: HugePagesManager) -> Self {
+        self.huge_pages = manager;
+        self
+    }
+
+    /// Adds the instances to the CNI network `network`, their ports being mapped on the node, and
+    /// removes them from it once their workload is gone.
+    pub fn with_network(mut self, network: CniNetwork) -> Self {
+        self.network = Some(Arc::new(network));
+        self
+    }
+
+    /// Runs `hook` before the workload of each instance is created.
+    pub fn with_start_hook(mut self, hook: Arc<dyn StartHook>) -> Self {
+        self.start_hook = Some(hook);
+        self
+    }
+
+    /// Creates the workload of an instance in a task of its own, returns once it runs. An
+    /// instance with a disk limit is killed once it uses more disk than its limit, and the disk
+    /// used by the running instances and their logs is sent on `statuses`. The devices, the
+    /// exclusive CPUs and the huge pages requested by the instance are allocated to it until it is
+    /// gone, its creation fails if the node lacks some.
+    pub async fn create(&self, instance: Instance) -> Result<()> {
+        let id = instance.id.clone();
+        let verifier = self.verifier.clone();
+        let logs = self.logs;
+        let registry = self.devices.clone();
+        let cpu_manager = self.cpus.clone();
+        let huge_pages = self.huge_pages.clone();
+        let network = self.network.clone();
+        let start_hook = self.start_hook.clone();
+        let reporter = self
+            .statuses
+            .clone()
+            .map(|sender| StatusReporter::new(id.clone(), sender));
+        let quota = disk_quota(&instance);
+        let monitor = (quota > 0 || reporter.is_some()).then(|| DiskMonitor {
+            quota,
+            reporter: reporter.clone(),
+        });
+        self.start(id, monitor, async move {
+            let devices = registry.allocate(&instance.id, &instance.devices)?;
+            let cpuset = cpu_manager.allocate(&instance.id, instance.exclusive_cpus)?;
+            huge_pages.allocate(&instance.id, &instance.huge_pages)?;
+            if let Some(hook) = start_hook {
+                hook.before_start(&instance).await?;
+            }
+            workload::create(
+                instance,
+                verifier.as_deref(),
+                reporter.as_ref(),
+                &logs,
+                &devices,
+                cpuset.as_ref(),
+                network,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Pulls an image ahead of the instances using it, so they start without waiting for it.
+    /// The progress of the pull is sent on `statuses`, a status being dropped if the receiver
+    /// lags behind, and the last status sent tells whether the image was pulled.
+    pub async fn pull(&self, uri: &str, statuses: mpsc::Sender<ImagePullStatus>) {
+        let status = |state: ImagePullState, description: String| ImagePullStatus {
+            uri: uri.to_string(),
+            state: state.into(),
+            description,
+        };
+        let progress = |description| {
+            _ = statuses.try_send(status(ImagePullState::ImagePulling, description));
+        };
+
+        let result = workload::pull(uri, self.verifier.as_deref(), &progress).await;
+        let last = match result {
+            Ok(()) => status(ImagePullState::ImagePulled, format!("Pulled {}", uri)),
+            Err(err) => status(ImagePullState::ImagePullFailed, format!("{:#}", err)),
+        };
+        _ = statuses.send(last).await;
+    }
+
+    /// Sends a signal to the workload of an instance and waits for it to be applied. The signals
+    /// sent to an instance still being created are applied once it runs.
+    pub async fn signal(&self, instance_id: &str, signal: Signal) -> Result<()> {
+        self.apply(instance_id, Operation::Signal(signal)).await
+    }
+
+    /// Checkpoints the memory of the workload of an instance on the node, experimental. The
+    /// instance is stopped once checkpointed unless the checkpoint leaves it running, and an
+    /// instance created with the checkpoint as `restore` resumes from it.
+    pub async fn checkpoint(&self, checkpoint: Checkpoint) -> CheckpointStatus {
+        let operation = Operation::Checkpoint {
+            name: checkpoint.name.clone(),
+            leave_running: checkpoint.leave_running,
+        };
+        let result = self.apply(&checkpoint.instance_id, operation).await;
+        CheckpointStatus {
+            instance_id: checkpoint.instance_id,
+            name: checkpoint.name,
+            error: result
+                .err()
+                .map_or_else(String::new, |err| format!("{:#}", err)),
+        }
+    }
+
+    /// Streams the output of the workload of an instance to `output`, until `output` is closed
+    /// or, unless the request follows the output, until the current output was sent.
+    pub async fn logs(&self, request: &LogRequest, output: mpsc::Sender<Vec<u8>>) -> Result<()> {
+        let workload_id = self.workload_id(&request.instance_id)?;
+        workload::logs(&workload_id, request.follow, request.tail, &output).await
+    }
+
+    /// Runs a command in the workload of an instance, `inputs` being forwarded to it and its output
+    /// sent to `output` until it exits. Returns the exit code of the command.
+    pub async fn exec(
+        &self,
+        start: &ExecStart,
+        inputs: mpsc::UnboundedReceiver<ExecInput>,
+        output: mpsc::Sender<Vec<u8>>,
+    ) -> Result<i64> {
+        let workload_id = self.workload_id(&start.instance_id)?;
+        workload::exec(&workload_id, &start.command, start.tty, inputs, &output).await
+    }
+
+    /// Returns the ids of the instances created or being created.
+    pub fn instance_ids(&self) -> Vec<String> {
+        self.lock().keys().cloned().collect()
+    }
+
+    /// Returns the id of the workload of an instance in its runtime, once it is created.
+    fn workload_id(&self, instance_id: &str) -> Result<String> {
+        self.lock()
+            .get(instance_id)
+            .ok_or_else(|| anyhow!("Instance {} not found. ", instance_id))?
+            .workload_id
+            .clone()
+            .ok_or_else(|| anyhow!("Instance {} is being created. ", instance_id))
+    }
+
+    /// Sends an operation to the task of an instance and waits for it to be applied.
+    async fn apply(&self, instance_id: &str, operation: Operation) -> Result<()> {
+        let sender = self
+            .lock()
+            .get(instance_id)
+            .map(|task| task.commands.clone())
+            .ok_or_else(|| anyhow!("Instance {} not found. ", instance_id))?;
+
+        let (reply, result) = oneshot::channel();
+        sender
+            .send((operation, reply))
+            .await
+            .map_err(|_| anyhow!("Instance {} stopped. ", instance_id))?;
+        result
+            .await
+            .with_context(|| format!("Instance {} stopped. ", instance_id))?
+    }
+
+    /// Spawns the task of an instance, which creates its workload with `create` then applies the
+    /// operations sent to the instance until one of them stops it, or until `monitor` kills it.
+    async fn start<W, F>(&self, id: String, monitor: Option<DiskMonitor>, create: F) -> Result<()>
+    where
+        W: Workload + Send + Sync + 'static,
+        F: Future<Output = Result<W>> + Send + 'static,
+    {
+        let (sender, mut commands) = mpsc::channel::<Command>(SIGNAL_BUFFER);
+        {
+            let mut instances = self.lock();
+            if instances.contains_key(&id) {
+                bail!("Instance {} already exists. ", id);
+            }
+            instances.insert(
+                id.clone(),
+                InstanceTask {
+                    commands: sender,
+                    workload_id: None,
+                },
+            );
+        }
+
+        let manager = self.clone();
+        let (created, result) = oneshot::channel();
+        tokio::spawn(async move {
+            let workload = match create.await {
+                Ok(workload) => {
+                    if let Some(task) = manager.lock().get_mut(&id) {
+                        task.workload_id = Some(workload.id());
+                    }
+                    _ = created.send(Ok(()));
+                    workload
+                }
+                Err(err) => {
+                    manager.lock().remove(&id);
+                    manager.devices.release(&id);
+                    manager.cpus.release(&id);
+                    manager.huge_pages.release(&id);
+                    _ = created.send(Err(err));
+                    return;
+                }
+            };
+
+            let mut checks = tokio::time::interval(DISK_CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    command = commands.recv() => {
+                        let Some((operation, reply)) = command else {
+                            break;
+                        };
+                        let (result, running) = match operation {
+                            Operation::Signal(Signal::Stop) => (workload.stop().await, false),
+                            Operation::Signal(Signal::Kill) => (workload.kill().await, false),
+                            Operation::Checkpoint { name, leave_running } => {
+                                (workload.checkpoint(&name, leave_running).await, leave_running)
+                            }
+                        };
+                        // a workload checkpointed without being left running is gone
+                        let stopped = result.is_ok() && !running;
+                        _ = reply.send(result);
+                        if stopped {
+                            break;
+                        }
+                    }
+                    _ = checks.tick(), if monitor.is_some() => {
+                        if let Some(monitor) = &monitor {
+                            if exceeds_quota(&workload, monitor).await {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            manager.lock().remove(&id);
+            manager.devices.release(&id);
+            manager.cpus.release(&id);
+            manager.huge_pages.release(&id);
+        });
+
+        result.await.context("Instance task stopped. ")?
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, InstanceTask>> {
+        // the map is left consistent by every critical section, a poisoned lock is still usable
+        self.instances
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Checks the disk used by a workload against its quota, reports it with the size of its logs
+/// and kills the workload if it exceeds its quota. Returns `true` if the workload was killed.
+async fn exceeds_quota<W: Workload + Sync>(workload: &W, monitor: &DiskMonitor) -> bool {
+    let usage = match workload.disk_usage().await {
+        Ok(usage) => usage,
+        // the check is retried on the next tick
+        Err(_) => return false,
+    };
+    if monitor.quota == 0 || usage <= monitor.quota {
+        if let Some(reporter) = &monitor.reporter {
+            let logs = workload.log_usage().await.unwrap_or(0);
+            reporter.report_disk(usage, monitor.quota, logs);
+        }
+        return false;
+    }
+    if workload.kill().await.is_err() {
+        return false;
+    }
+    if let Some(reporter) = &monitor.reporter {
+        reporter.report(
+            Status::Failed,
+            format!(
+                "Killed after using {} bytes, beyond its disk quota of {} bytes",
+                usage, monitor.quota
+            ),
+        );
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    /// A workload whose graceful stop waits for `release`.
+    struct SlowWorkload {
+        release: Arc<Notify>,
+    }
+
+    #[tonic::async_trait]
+    impl Workload for SlowWorkload {
+        fn id(&self) -> String {
+            "slow".to_string()
+        }
+
+        async fn stop(&self) -> Result<()> {
+            self.release.notified().await;
+            Ok(())
+        }
+
+        async fn kill(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A workload using more disk than any quota.
+    struct FullWorkload;
+
+    #[tonic::async_trait]
+    impl Workload for FullWorkload {
+        fn id(&self) -> String {
+            "full".to_string()
+        }
+
+        async fn stop(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn kill(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn disk_usage(&self) -> Result<u64> {
+            Ok(u64::MAX)
+        }
+
+        async fn log_usage(&self) -> Result<u64> {
+            Ok(4096)
+        }
+    }
+
+    fn workload(release: &Arc<Notify>) -> SlowWorkload {
+        SlowWorkload {
+            release: release.clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_instances_run_concurrently() {
+        let manager = WorkloadManager::default();
+        let release = Arc::new(Notify::new());
+
+        manager
+            .start("a".to_string(), None, {
+                let workload = workload(&release);
+                async move { Ok(workload) }
+            })
+            .await
+            .unwrap();
+        manager
+            .start("b".to_string(), None, {
+                let workload = workload(&release);
+                async move { Ok(workload) }
+            })
+            .await
+            .unwrap();
+
+        // an instance still being created, as during a slow image pull
+        let creating = tokio::spawn({
+            let manager = manager.clone();
+            let workload = workload(&release);
+            let release = release.clone();
+            async move {
+                manager
+                    .start("c".to_string(), None, async move {
+                        release.notified().await;
+                        Ok(workload)
+                    })
+                    .await
+            }
+        });
+        let stopping = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.signal("a", Signal::Stop).await }
+        });
+
+        // the other instances are not blocked by the slow ones
+        tokio::time::timeout(Duration::from_secs(5), manager.signal("b", Signal::Kill))
+            .await
+            .expect("the kill of b waited for the other instances")
+            .unwrap();
+        assert!(manager.signal("b", Signal::Kill).await.is_err());
+
+        release.notify_waiters();
+        creating.await.unwrap().unwrap();
+        stopping.await.unwrap().unwrap();
+
+        let mut ids = manager.instance_ids();
+        ids.sort();
+        assert_eq!(ids, vec!["c".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_creation_releases_the_id() {
+        let manager = WorkloadManager::default();
+
+        let result = manager
+            .start::<SlowWorkload, _>("a".to_string(), None, async { Err(anyhow!("pull failed")) })
+            .await;
+        assert!(result.is_err());
+        assert!(manager.instance_ids().is_empty());
+
+        let release = Arc::new(Notify::new());
+        let (first, second) = (workload(&release), workload(&release));
+        manager
+            .start("a".to_string(), None, async move { Ok(first) })
+            .await
+            .unwrap();
+        assert!(manager
+            .start("a".to_string(), None, async move { Ok(second) })
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_checkpoint_keeps_the_instance() {
+        let manager = WorkloadManager::default();
+        let release = Arc::new(Notify::new());
+        let slow = workload(&release);
+        manager
+            .start("a".to_string(), None, async move { Ok(slow) })
+            .await
+            .unwrap();
+
+        let checkpoint = Checkpoint {
+            instance_id: "a".to_string(),
+            name: "before-upgrade".to_string(),
+            leave_running: false,
+        };
+        let status = manager.checkpoint(checkpoint.clone()).await;
+        assert_eq!(status.name, "before-upgrade");
+        assert!(!status.error.is_empty());
+        assert_eq!(manager.instance_ids(), vec!["a".to_string()]);
+
+        let status = manager
+            .checkpoint(Checkpoint {
+                instance_id: "b".to_string(),
+                ..checkpoint
+            })
+            .await;
+        assert!(status.error.contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn test_instance_exceeding_its_disk_quota_is_killed() {
+        let manager = WorkloadManager::default();
+        let (sender, mut statuses) = mpsc::channel(4);
+        let monitor = DiskMonitor {
+            quota: 1024,
+            reporter: Some(StatusReporter::new("a".to_string(), sender)),
+        };
+
+        manager
+            .start("a".to_string(), Some(monitor), async { Ok(FullWorkload) })
+            .await
+            .unwrap();
+
+        let status = statuses.recv().await.unwrap();
+        assert_eq!(status.status(), Status::Failed);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !manager.instance_ids().is_empty() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the instance is forgotten once killed");
+    }
+
+    #[tokio::test]
+    async fn test_usage_without_quota_is_reported() {
+        let manager = WorkloadManager::default();
+        let (sender, mut statuses) = mpsc::channel(4);
+        let monitor = DiskMonitor {
+            quota: 0,
+            reporter: Some(StatusReporter::new("a".to_string(), sender)),
+        };
+
+        manager
+            .start("a".to_string(), Some(monitor), async { Ok(FullWorkload) })
+            .await
+            .unwrap();
+
+        let status = statuses.recv().await.unwrap();
+        assert_eq!(status.status(), Status::Running);
+        assert_eq!(status.logs, 4096);
+        assert_eq!(status.resource.unwrap().limit, None);
+        assert_eq!(manager.instance_ids(), vec!["a".to_string()]);
+    }
+}
@@ -1,88 +1,814 @@
+use std::collections::HashMap;
+use std::env;
+use std::net::Ipv4Addr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
 use bollard::container::{
-    Config, KillContainerOptions, RemoveContainerOptions, RenameContainerOptions,
-    StopContainerOptions,
+    Config, InspectContainerOptions, KillContainerOptions, LogsOptions, RemoveContainerOptions,
+    RenameContainerOptions, StopContainerOptions,
+};
+use bollard::exec::{CreateExecOptions, ResizeExecOptions, StartExecResults};
+use bollard::models::{
+    CreateImageInfo, DeviceMapping, HostConfig, HostConfigLogConfig, Mount, MountTypeEnum,
+    MountVolumeOptions, MountVolumeOptionsDriverConfig,
 };
-use bollard::Docker;
+use bollard::{Docker, API_DEFAULT_VERSION};
 
-use anyhow::{Context, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 
 use bollard::image::CreateImageOptions;
 use futures_util::TryStreamExt;
+use network::cni::CniNetwork;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
 
 use super::workload_trait::Workload;
-use proto::agent::Instance;
+use super::{LogConfig, StatusReporter, BYTES_PER_GB};
+use crate::workload_manager::cpus::{format_cpu_list, CpuSet};
+use crate::workload_manager::hugepages::page_bytes;
+use crate::workload_manager::ExecInput;
+use proto::agent::{Checkpoint, Device, Instance, Port, Resource, SecurityContext, Status, Volume};
+
+/// Timeout of the requests to the container runtime, in seconds
+const DOCKER_TIMEOUT: u64 = 120;
+
+/// Socket of the docker daemon running as root
+const ROOTFUL_SOCKET: &str = "/var/run/docker.sock";
+
+/// Sockets of the rootless runtimes speaking the docker api, relative to `XDG_RUNTIME_DIR`
+const ROOTLESS_SOCKETS: [&str; 2] = ["docker.sock", "podman/podman.sock"];
+
+/// Returns the socket of the container runtime: `DOCKER_HOST` if it is a unix socket, else the
+/// socket of a rootless docker or podman of the user if one is running, else the one of the root
+/// daemon.
+fn runtime_socket(
+    docker_host: Option<&str>,
+    runtime_dir: Option<&Path>,
+    exists: impl Fn(&Path) -> bool,
+) -> String {
+    if let Some(socket) = docker_host.and_then(|host| host.strip_prefix("unix://")) {
+        return socket.to_string();
+    }
+
+    runtime_dir
+        .into_iter()
+        .flat_map(|dir| ROOTLESS_SOCKETS.iter().map(move |socket| dir.join(socket)))
+        .find(|socket| exists(socket))
+        .map(|socket| socket.to_string_lossy().into_owned())
+        .unwrap_or_else(|| ROOTFUL_SOCKET.to_string())
+}
+
+/// Returns the socket of the container runtime of the node.
+fn socket() -> String {
+    let docker_host = env::var("DOCKER_HOST").ok();
+    let runtime_dir = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from);
+    runtime_socket(docker_host.as_deref(), runtime_dir.as_deref(), Path::exists)
+}
+
+/// Connects to the container runtime, rootless or not.
+fn connect() -> Result<Docker> {
+    Docker::connect_with_socket(&socket(), DOCKER_TIMEOUT, API_DEFAULT_VERSION)
+        .context("Can't connect to docker socket. ")
+}
+
+/// Runs the docker cli against the container runtime of the node, for the features missing from
+/// its api client such as the checkpoints.
+async fn docker_cli(args: &[&str]) -> Result<()> {
+    let output = tokio::process::Command::new("docker")
+        .env("DOCKER_HOST", format!("unix://{}", socket()))
+        .args(args)
+        .output()
+        .await
+        .context("Can't run the docker cli. ")?;
+    if !output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Directory of the checkpoints written on the node, in a directory per instance
+const CHECKPOINTS_DIR: &str = "/var/lib/kudo/checkpoints";
+
+/// Returns the directory of the checkpoints of an instance, after checking that the instance and
+/// the checkpoint name a single directory each, so the checkpoint stays inside `checkpoints_dir`.
+fn checkpoint_dir(instance_id: &str, name: &str, checkpoints_dir: &Path) -> Result<PathBuf> {
+    for part in [instance_id, name] {
+        let mut components = Path::new(part).components();
+        if !matches!(
+            (components.next(), components.next()),
+            (Some(Component::Normal(_)), None)
+        ) {
+            bail!("Invalid checkpoint {} of instance {}. ", name, instance_id);
+        }
+    }
+    Ok(checkpoints_dir.join(instance_id))
+}
+
+/// Directory of the seccomp profiles installed on the node, referenced as `localhost/<profile>`
+const SECCOMP_PROFILES_DIR: &str = "/var/lib/kudo/seccomp";
+
+/// Returns the docker security option applying a seccomp profile, `None` for the default
+/// profile of docker.
+fn seccomp_option(profile: &str, profiles_dir: &Path) -> Result<Option<String>> {
+    if profile.is_empty() {
+        return Ok(None);
+    }
+    if profile == "unconfined" {
+        return Ok(Some("seccomp=unconfined".to_string()));
+    }
+
+    let name = match profile.strip_prefix("localhost/") {
+        Some(name) => Path::new(name),
+        None => bail!("Unknown seccomp profile {}. ", profile),
+    };
+    // the profile must stay inside the profiles directory
+    if !name.components().all(|c| matches!(c, Component::Normal(_))) {
+        bail!("Invalid seccomp profile {}. ", profile);
+    }
+
+    // docker expects the content of the profile, not its path
+    let content = std::fs::read_to_string(profiles_dir.join(name))
+        .with_context(|| format!("Can't read seccomp profile {}. ", profile))?;
+    Ok(Some(format!("seccomp={}", content)))
+}
+
+/// Returns the host configuration of a container applying its security context and mounting its
+/// volumes.
+fn host_config(
+    context: &SecurityContext,
+    volumes: &[Volume],
+    profiles_dir: &Path,
+) -> Result<HostConfig> {
+    Ok(HostConfig {
+        binds: (!volumes.is_empty()).then(|| {
+            volumes
+                .iter()
+                .map(|volume| format!("{}:{}", volume.name, volume.path))
+                .collect()
+        }),
+        cap_drop: (!context.drop_capabilities.is_empty())
+            .then(|| context.drop_capabilities.clone()),
+        readonly_rootfs: Some(context.read_only_root_filesystem),
+        security_opt: seccomp_option(&context.seccomp_profile, profiles_dir)?
+            .map(|option| vec![option]),
+        ..Default::default()
+    })
+}
+
+/// Returns the host configuration of a sidecar: it joins the network namespace of the main
+/// container of its instance, sharing its IP address and ports, and mounts the same volumes.
+fn sidecar_host_config(
+    main_id: &str,
+    context: &SecurityContext,
+    volumes: &[Volume],
+    profiles_dir: &Path,
+) -> Result<HostConfig> {
+    Ok(HostConfig {
+        network_mode: Some(format!("container:{}", main_id)),
+        ..host_config(context, volumes, profiles_dir)?
+    })
+}
+
+/// Returns the storage option capping the writable layer of a container to its disk limit, `None`
+/// without limit.
+fn storage_opt(resource: Option<&Resource>) -> Option<HashMap<String, String>> {
+    let disk = resource.and_then(|resource| resource.limit.as_ref())?.disk;
+    (disk > 0).then(|| {
+        HashMap::from([(
+            "size".to_string(),
+            disk.saturating_mul(BYTES_PER_GB).to_string(),
+        )])
+    })
+}
+
+/// Returns the device files of the devices allocated to a container, mounted at the same path,
+/// `None` without device.
+fn device_mappings(devices: &[Device]) -> Option<Vec<DeviceMapping>> {
+    (!devices.is_empty()).then(|| {
+        devices
+            .iter()
+            .flat_map(|device| &device.paths)
+            .map(|path| DeviceMapping {
+                path_on_host: Some(path.clone()),
+                path_in_container: Some(path.clone()),
+                cgroup_permissions: Some("rwm".to_string()),
+            })
+            .collect()
+    })
+}
+
+/// Returns the hugetlbfs mounts of a container, one per size of its huge pages at
+/// `/dev/hugepages-<size>`, each capped to its pages, `None` without huge pages. The mounts are
+/// anonymous volumes, removed with the container.
+fn huge_pages_mounts(huge_pages: &HashMap<String, u64>) -> Result<Option<Vec<Mount>>> {
+    let mut sizes: Vec<(&String, &u64)> =
+        huge_pages.iter().filter(|(_, count)| **count > 0).collect();
+    sizes.sort();
+
+    let mut mounts = vec![];
+    for (size, count) in sizes {
+        let bytes = page_bytes(size)?;
+        let options = HashMap::from([
+            ("type".to_string(), "hugetlbfs".to_string()),
+            ("device".to_string(), "hugetlbfs".to_string()),
+            (
+                "o".to_string(),
+                format!("pagesize={},size={}", bytes, bytes.saturating_mul(*count)),
+            ),
+        ]);
+        mounts.push(Mount {
+            target: Some(format!("/dev/hugepages-{}", size)),
+            typ: Some(MountTypeEnum::VOLUME),
+            volume_options: Some(MountVolumeOptions {
+                driver_config: Some(MountVolumeOptionsDriverConfig {
+                    name: Some("local".to_string()),
+                    options: Some(options),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+    Ok((!mounts.is_empty()).then_some(mounts))
+}
+
+/// Returns the logging configuration of a container: its output is written to a log file
+/// rotated once it reaches `max_size`, the `max_files` latest files being kept.
+fn log_config(logs: &LogConfig) -> HostConfigLogConfig {
+    HostConfigLogConfig {
+        typ: Some("json-file".to_string()),
+        config: Some(HashMap::from([
+            ("max-size".to_string(), logs.max_size.to_string()),
+            ("max-file".to_string(), logs.max_files.max(1).to_string()),
+        ])),
+    }
+}
+
+/// Returns the bytes of a log file and of its rotated files, named after it in the same
+/// directory (`<file>.1`, `<file>.2.gz`...), 0 if it doesn't exist.
+fn log_files_size(path: &Path) -> u64 {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return 0;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let name = name.to_string_lossy();
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(name.as_ref())
+        })
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Returns the name of the container of a sidecar, suffixed by the name of the sidecar.
+fn sidecar_name(instance_name: &str, sidecar: &str) -> String {
+    format!("{}-{}", instance_name, sidecar)
+}
+
+/// The progress of the pull of an image, summed over its layers.
+///
+/// Properties:
+///
+/// * `layers`: The bytes downloaded and the size of each layer, by layer id.
+#[derive(Debug, Default)]
+struct PullProgress {
+    layers: HashMap<String, (u64, u64)>,
+}
+
+impl PullProgress {
+    /// Keeps the progress of a layer reported by the runtime, returns `true` if the percentage
+    /// of the image downloaded changed.
+    fn update(&mut self, info: &CreateImageInfo) -> bool {
+        let Some(id) = &info.id else {
+            return false;
+        };
+        let before = self.percentage();
+
+        match info.status.as_deref() {
+            Some("Downloading") => {
+                let detail = info.progress_detail.clone().unwrap_or_default();
+                if let (Some(current), Some(total)) = (detail.current, detail.total) {
+                    if total > 0 {
+                        let total = total as u64;
+                        self.layers
+                            .insert(id.clone(), ((current.max(0) as u64).min(total), total));
+                    }
+                }
+            }
+            Some("Download complete" | "Pull complete") => {
+                if let Some((current, total)) = self.layers.get_mut(id) {
+                    *current = *total;
+                }
+            }
+            _ => {}
+        }
+        self.percentage() != before
+    }
+
+    /// Returns the bytes downloaded and the size of the layers seen so far.
+    fn bytes(&self) -> (u64, u64) {
+        self.layers
+            .values()
+            .fold((0, 0), |(current, total), layer| {
+                (current + layer.0, total + layer.1)
+            })
+    }
 
+    /// Returns the percentage of the layers seen so far which is downloaded.
+    fn percentage(&self) -> u64 {
+        match self.bytes() {
+            (_, 0) => 0,
+            (current, total) => current * 100 / total,
+        }
+    }
+
+    /// Returns the status description of an instance pulling the image `uri`.
+    fn description(&self, uri: &str) -> String {
+        let (current, total) = self.bytes();
+        format!(
+            "Pulling {}: {}% ({:.1} MB of {:.1} MB)",
+            uri,
+            self.percentage(),
+            current as f64 / 1_000_000.0,
+            total as f64 / 1_000_000.0
+        )
+    }
+}
+
+/// Pulls the image of a container, the description of its progress is passed to `progress`.
+async fn pull_image(
+    docker: &Docker,
+    uri: &str,
+    progress: &(dyn Fn(String) + Send + Sync),
+) -> Result<(), Error> {
+    progress(format!("Pulling {}", uri));
+
+    let mut layers = PullProgress::default();
+    let mut pull = docker.create_image(
+        Some(CreateImageOptions {
+            from_image: uri,
+            ..Default::default()
+        }),
+        None,
+        None,
+    );
+    while let Some(info) = pull
+        .try_next()
+        .await
+        .with_context(|| format!("Can't create image {}. ", uri))?
+    {
+        if layers.update(&info) {
+            progress(layers.description(uri));
+        }
+    }
+    Ok(())
+}
+
+/// Pulls an image ahead of the instances using it, the description of its progress is passed
+/// to `progress`.
+pub async fn pull(uri: &str, progress: &(dyn Fn(String) + Send + Sync)) -> Result<(), Error> {
+    let docker = connect()?;
+    pull_image(&docker, uri, progress).await
+}
+
+/// Returns the number of lines of output the runtime sends from the end of the logs, every line
+/// if `tail` is 0.
+fn log_tail(tail: u32) -> String {
+    match tail {
+        0 => "all".to_string(),
+        tail => tail.to_string(),
+    }
+}
+
+/// Streams the output of a container to `output`, the last `tail` lines or every line if 0, then
+/// the next ones if `follow` is set, until `output` is closed.
+pub async fn logs(
+    id: &str,
+    follow: bool,
+    tail: u32,
+    output: &mpsc::Sender<Vec<u8>>,
+) -> Result<(), Error> {
+    let docker = connect()?;
+    let options = LogsOptions {
+        follow,
+        stdout: true,
+        stderr: true,
+        tail: log_tail(tail),
+        ..Default::default()
+    };
+
+    let mut logs = docker.logs(id, Some(options));
+    while let Some(chunk) = logs
+        .try_next()
+        .await
+        .context("Can't read docker container logs. ")?
+    {
+        if output.send(chunk.into_bytes().to_vec()).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Runs a command in a container, writes `inputs` to its stdin until their sender is dropped and
+/// sends its output to `output`, until the command exits or `output` is closed. Returns the exit
+/// code of the command.
+pub async fn exec(
+    id: &str,
+    command: &[String],
+    tty: bool,
+    mut inputs: mpsc::UnboundedReceiver<ExecInput>,
+    output: &mpsc::Sender<Vec<u8>>,
+) -> Result<i64, Error> {
+    if command.is_empty() {
+        bail!("No command to run. ");
+    }
+    let docker = connect()?;
+    let options = CreateExecOptions {
+        attach_stdin: Some(true),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        tty: Some(tty),
+        cmd: Some(command.to_vec()),
+        ..Default::default()
+    };
+    let exec_id = docker
+        .create_exec(id, options)
+        .await
+        .context("Can't create docker exec. ")?
+        .id;
+    let StartExecResults::Attached {
+        output: mut attached,
+        input,
+    } = docker
+        .start_exec(&exec_id, None)
+        .await
+        .context("Can't start docker exec. ")?
+    else {
+        bail!("Docker exec {} is detached. ", exec_id);
+    };
+
+    // the stdin of the command is dropped once the inputs end, or once the command stops reading
+    let mut stdin = Some(input);
+    let mut inputs_open = true;
+    loop {
+        tokio::select! {
+            chunk = attached.try_next() => match chunk.context("Can't read docker exec output. ")? {
+                Some(chunk) => {
+                    if output.send(chunk.into_bytes().to_vec()).await.is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            },
+            input = inputs.recv(), if inputs_open => match input {
+                Some(ExecInput::Stdin(data)) => {
+                    if let Some(writer) = stdin.as_mut() {
+                        if writer.write_all(&data).await.is_err() {
+                            stdin = None;
+                        }
+                    }
+                }
+                Some(ExecInput::Resize(cols, rows)) => {
+                    // the terminal keeps its size if the runtime refuses it
+                    let size = ResizeExecOptions {
+                        width: cols,
+                        height: rows,
+                    };
+                    _ = docker.resize_exec(&exec_id, size).await;
+                }
+                None => {
+                    inputs_open = false;
+                    if let Some(mut writer) = stdin.take() {
+                        _ = writer.shutdown().await;
+                    }
+                }
+            },
+        }
+    }
+
+    let exit_code = docker
+        .inspect_exec(&exec_id)
+        .await
+        .context("Can't inspect docker exec. ")?
+        .exit_code;
+    Ok(exit_code.unwrap_or_default())
+}
+
+/// Creates a container, names it and starts it, returns its id. The writable layer of the
+/// container is left uncapped if the storage driver of the runtime can't cap it, the disk quota of
+/// the instance is then only enforced by its periodic checks. With `restore`, the memory of the
+/// container is restored from the checkpoint instead of starting its process.
+async fn start_container(
+    docker: &Docker,
+    name: String,
+    mut config: Config<&str>,
+    restore: Option<&Checkpoint>,
+) -> Result<String> {
+    let container_id = match docker
+        .create_container::<&str, &str>(None, config.clone())
+        .await
+    {
+        Ok(response) => response.id,
+        Err(err) if err.to_string().contains("storage-opt") => {
+            if let Some(host_config) = config.host_config.as_mut() {
+                host_config.storage_opt = None;
+            }
+            docker
+                .create_container::<&str, &str>(None, config)
+                .await
+                .context("Can't create container. ")?
+                .id
+        }
+        Err(err) => return Err(Error::new(err).context("Can't create container. ")),
+    };
+
+    docker
+        .rename_container(container_id.as_str(), RenameContainerOptions { name })
+        .await
+        .ok();
+
+    match restore {
+        Some(checkpoint) => {
+            let dir = checkpoint_dir(
+                &checkpoint.instance_id,
+                &checkpoint.name,
+                Path::new(CHECKPOINTS_DIR),
+            )?;
+            docker_cli(&[
+                "start",
+                "--checkpoint",
+                &checkpoint.name,
+                "--checkpoint-dir",
+                &dir.to_string_lossy(),
+                &container_id,
+            ])
+            .await
+            .context("Can't restore container. ")?;
+        }
+        None => docker
+            .start_container::<String>(container_id.as_str(), None)
+            .await
+            .context("Can't start container. ")?,
+    }
+
+    Ok(container_id)
+}
+
+/// Removes a container, stopped or not.
+async fn remove_container(docker: &Docker, id: &str) -> Result<(), Error> {
+    docker
+        .remove_container(
+            id,
+            Some(RemoveContainerOptions {
+                force: true,
+                // the anonymous volumes, e.g. the huge pages mounts, the named ones are kept
+                v: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .context("Can't remove container. ")?;
+    Ok(())
+}
+
+/// The membership of an instance in the CNI network of the node, through the network namespace
+/// of its main container.
+///
+/// Properties:
+///
+/// * `cni`: The CNI network of the node.
+/// * `netns`: The network namespace of the main container, `/proc/<pid>/ns/net`.
+/// * `ports`: The ports of the instance mapped on the node.
+struct InstanceNetwork {
+    cni: Arc<CniNetwork>,
+    netns: PathBuf,
+    ports: Vec<Port>,
+}
+
+/// Returns the ports of an instance as the network crate expects them.
+fn network_ports(ports: &[Port]) -> Vec<network::port::Port> {
+    ports
+        .iter()
+        .map(|port| network::port::Port::new(port.source, port.destination))
+        .collect()
+}
+
+impl InstanceNetwork {
+    /// Returns the network of an instance whose main container was started with `cni`.
+    async fn new(
+        docker: &Docker,
+        container_id: &str,
+        ports: &[Port],
+        cni: Arc<CniNetwork>,
+    ) -> Result<Self> {
+        let pid = docker
+            .inspect_container(container_id, None)
+            .await
+            .context("Can't inspect docker container. ")?
+            .state
+            .and_then(|state| state.pid)
+            .filter(|pid| *pid > 0)
+            .ok_or_else(|| anyhow!("Container {} has no process. ", container_id))?;
+
+        Ok(InstanceNetwork {
+            cni,
+            netns: PathBuf::from(format!("/proc/{}/ns/net", pid)),
+            ports: ports.to_vec(),
+        })
+    }
+
+    /// Adds the instance to the network, the plugins running in the network namespace of its main
+    /// container. The address allocated to the instance by the controller is asked for, so the
+    /// network policies and the services reach it.
+    async fn add(&self, instance_id: &str, ip: &str) -> Result<()> {
+        let cni = self.cni.clone();
+        let id = instance_id.to_string();
+        let netns = self.netns.clone();
+        let ip = ip.parse::<Ipv4Addr>().ok();
+        let ports = network_ports(&self.ports);
+        tokio::task::spawn_blocking(move || {
+            cni.add_instance(&id, &netns, ip, &ports)
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        })
+        .await?
+        .map_err(|err| anyhow!("Can't add the instance to the network: {}. ", err))
+    }
+
+    /// Removes the instance from the network. Its namespace is gone with its container, the
+    /// plugins release its address and its port mappings anyway.
+    async fn remove(&self, instance_id: &str) -> Result<()> {
+        let cni = self.cni.clone();
+        let id = instance_id.to_string();
+        let netns = self.netns.clone();
+        let ports = network_ports(&self.ports);
+        tokio::task::spawn_blocking(move || {
+            cni.remove_instance(&id, &netns, &ports)
+                .map_err(|err| err.to_string())
+        })
+        .await?
+        .map_err(|err| anyhow!("Can't remove the instance from the network: {}. ", err))
+    }
+}
+
+/// The containers of an instance: the main one, holding the network namespace, and its sidecars.
+/// `volumes` are the names of the volumes mounted in the containers, `network` the CNI network
+/// the instance was added to, if any.
 pub struct Container {
+    instance_id: String,
     id: String,
+    sidecars: Vec<String>,
+    volumes: Vec<String>,
+    network: Option<InstanceNetwork>,
 }
 
 impl Container {
     //
-    // Create a new workload (container) and start it
+    // Create a new workload (container and sidecars) and start it, with `cni` the main
+    // container is added to the CNI network instead of the network of docker
     //
-    pub async fn new(instance: Instance) -> Result<Self, Error> {
-        let docker =
-            Docker::connect_with_socket_defaults().context("Can't connect to docker socket. ")?;
-
-        docker
-            .create_image(
-                Some(CreateImageOptions {
-                    from_image: instance.uri.clone(),
-                    ..Default::default()
-                }),
-                None,
-                None,
-            )
-            .try_collect::<Vec<_>>()
-            .await
-            .context("Can't create image. ")?;
+    pub async fn new(
+        instance: Instance,
+        reporter: Option<&StatusReporter>,
+        logs: &LogConfig,
+        devices: &[Device],
+        cpuset: Option<&CpuSet>,
+        cni: Option<Arc<CniNetwork>>,
+    ) -> Result<Self, Error> {
+        let docker = connect()?;
+        if instance.restore.is_some() && !instance.sidecars.is_empty() {
+            bail!("Can't restore an instance with sidecars from a checkpoint. ");
+        }
+
+        let progress = |description| {
+            if let Some(reporter) = reporter {
+                reporter.report(Status::Pulling, description);
+            }
+        };
+        pull_image(&docker, &instance.uri, &progress).await?;
+        for sidecar in &instance.sidecars {
+            pull_image(&docker, &sidecar.uri, &progress).await?;
+        }
 
+        let security_context = instance.security_context.clone().unwrap_or_default();
+        let profiles_dir = Path::new(SECCOMP_PROFILES_DIR);
+        let user = (!security_context.user.is_empty()).then_some(security_context.user.as_str());
         let container_config: Config<&str> = Config {
             image: Some(instance.uri.as_str()),
             tty: Some(true),
+            user,
+            host_config: Some(HostConfig {
+                storage_opt: storage_opt(instance.resource.as_ref()),
+                log_config: Some(log_config(logs)),
+                devices: device_mappings(devices),
+                mounts: huge_pages_mounts(&instance.huge_pages)?,
+                // the memory is allocated on the NUMA node of the pinned CPUs
+                cpuset_cpus: cpuset.map(|cpuset| format_cpu_list(&cpuset.cpus)),
+                cpuset_mems: cpuset.map(|cpuset| cpuset.numa_node.to_string()),
+                // the plugins configure the interfaces of the instance once it runs
+                network_mode: cni.is_some().then(|| "none".to_string()),
+                ..host_config(&security_context, &instance.volumes, profiles_dir)?
+            }),
             ..Default::default()
         };
 
-        let container_id = docker
-            .create_container::<&str, &str>(None, container_config)
-            .await
-            .context("Can't create container. ")?
-            .id;
-
-        docker
-            .rename_container(
-                container_id.as_str(),
-                RenameContainerOptions {
-                    name: instance.name,
-                },
+        let container_id = start_container(
+            &docker,
+            instance.name.clone(),
+            container_config,
+            instance.restore.as_ref(),
+        )
+        .await?;
+        let mut container = Container {
+            instance_id: instance.id.clone(),
+            id: container_id,
+            sidecars: Vec::with_capacity(instance.sidecars.len()),
+            volumes: instance
+                .volumes
+                .iter()
+                .map(|volume| volume.name.clone())
+                .collect(),
+            network: None,
+        };
+
+        if let Some(cni) = cni {
+            let added =
+                match InstanceNetwork::new(&docker, &container.id, &instance.ports, cni).await {
+                    Ok(network) => {
+                        let network = container.network.insert(network);
+                        network.add(&instance.id, &instance.ip).await
+                    }
+                    Err(err) => Err(err),
+                };
+            if let Err(err) = added {
+                container.remove().await.ok();
+                return Err(err);
+            }
+        }
+
+        for sidecar in &instance.sidecars {
+            let environment: Vec<&str> = sidecar.environment.iter().map(String::as_str).collect();
+            let config = sidecar_host_config(
+                &container.id,
+                &security_context,
+                &instance.volumes,
+                profiles_dir,
             )
-            .await
-            .ok();
+            .map(|host_config| Config {
+                image: Some(sidecar.uri.as_str()),
+                tty: Some(true),
+                user,
+                env: Some(environment),
+                host_config: Some(HostConfig {
+                    storage_opt: storage_opt(sidecar.resource.as_ref()),
+                    log_config: Some(log_config(logs)),
+                    ..host_config
+                }),
+                ..Default::default()
+            });
+            let started = match config {
+                Ok(config) => {
+                    let name = sidecar_name(&instance.name, &sidecar.name);
+                    start_container(&docker, name, config, None).await
+                }
+                Err(err) => Err(err),
+            };
 
-        docker
-            .start_container::<String>(container_id.as_str(), None)
-            .await
-            .context("Can't start container. ")?;
+            match started {
+                Ok(id) => container.sidecars.push(id),
+                Err(err) => {
+                    // the instance runs with all its containers or not at all
+                    container.remove().await.ok();
+                    return Err(err.context(format!("Can't start sidecar {}. ", sidecar.name)));
+                }
+            }
+        }
 
-        Ok(Container { id: container_id })
+        Ok(container)
     }
 
     //
-    // Removes a container
+    // Removes the containers of the instance, the sidecars first, then removes the instance
+    // from the CNI network
     //
     async fn remove(&self) -> Result<(), Error> {
-        let docker =
-            Docker::connect_with_socket_defaults().context("Can't connect to docker socket. ")?;
-        docker
-            .remove_container(
-                self.id().as_str(),
-                Some(RemoveContainerOptions {
-                    force: true,
-                    ..Default::default()
-                }),
-            )
-            .await
-            .context("Can't remove container. ")?;
+        let docker = connect()?;
+        for id in self.sidecars.iter().chain([&self.id]) {
+            remove_container(&docker, id).await?;
+        }
+
+        if let Some(network) = &self.network {
+            network.remove(&self.instance_id).await?;
+        }
 
         Ok(())
     }
@@ -95,21 +821,22 @@ impl Workload for Container {
     }
 
     //
-    // Gracefully stop a workload
+    // Gracefully stop a workload, the sidecars first
     //
     async fn stop(&self) -> Result<(), Error> {
-        let docker =
-            Docker::connect_with_socket_defaults().context("Can't connect to docker socket. ")?;
+        let docker = connect()?;
 
-        docker
-            .stop_container(
-                self.id().as_str(),
-                Some(StopContainerOptions {
-                    ..Default::default()
-                }),
-            )
-            .await
-            .context("Can't stop docker container.")?;
+        for id in self.sidecars.iter().chain([&self.id]) {
+            docker
+                .stop_container(
+                    id.as_str(),
+                    Some(StopContainerOptions {
+                        ..Default::default()
+                    }),
+                )
+                .await
+                .context("Can't stop docker container.")?;
+        }
 
         self.remove().await?;
 
@@ -121,34 +848,128 @@ impl Workload for Container {
     // (equivalent to a `kill -9` on linux)
     //
     async fn kill(&self) -> Result<(), Error> {
-        let docker =
-            Docker::connect_with_socket_defaults().context("Can't connect to docker socket. ")?;
+        let docker = connect()?;
 
-        docker
-            .kill_container(
-                self.id().as_str(),
-                Some(KillContainerOptions { signal: "SIGKILL" }),
-            )
-            .await
-            .context("Can't kill docker container. ")?;
+        for id in self.sidecars.iter().chain([&self.id]) {
+            docker
+                .kill_container(
+                    id.as_str(),
+                    Some(KillContainerOptions { signal: "SIGKILL" }),
+                )
+                .await
+                .context("Can't kill docker container. ")?;
+        }
 
         self.remove().await?;
 
         Ok(())
     }
+
+    //
+    // Sums the writable layers of the containers and the volumes they mount
+    //
+    async fn disk_usage(&self) -> Result<u64, Error> {
+        let docker = connect()?;
+
+        let mut usage = 0;
+        for id in self.sidecars.iter().chain([&self.id]) {
+            let inspect = docker
+                .inspect_container(id, Some(InspectContainerOptions { size: true }))
+                .await
+                .context("Can't inspect docker container. ")?;
+            usage += inspect.size_rw.unwrap_or(0).max(0) as u64;
+        }
+
+        if !self.volumes.is_empty() {
+            let volumes = docker
+                .df()
+                .await
+                .context("Can't read the disk usage of the volumes. ")?
+                .volumes
+                .unwrap_or_default();
+            usage += volumes
+                .iter()
+                .filter(|volume| self.volumes.contains(&volume.name))
+                .filter_map(|volume| volume.usage_data.as_ref())
+                .map(|data| data.size.max(0) as u64)
+                .sum::<u64>();
+        }
+
+        Ok(usage)
+    }
+
+    //
+    // Checkpoints the memory of the main container with CRIU, the containers are removed once
+    // checkpointed unless they are left running
+    //
+    async fn checkpoint(&self, name: &str, leave_running: bool) -> Result<(), Error> {
+        if !self.sidecars.is_empty() {
+            bail!("Can't checkpoint an instance with sidecars. ");
+        }
+        let dir = checkpoint_dir(&self.instance_id, name, Path::new(CHECKPOINTS_DIR))?;
+        std::fs::create_dir_all(&dir).context("Can't create the checkpoint directory. ")?;
+
+        docker_cli(&[
+            "checkpoint",
+            "create",
+            "--checkpoint-dir",
+            &dir.to_string_lossy(),
+            &format!("--leave-running={}", leave_running),
+            &self.id,
+            name,
+        ])
+        .await
+        .context("Can't checkpoint docker container. ")?;
+
+        if !leave_running {
+            self.remove().await?;
+        }
+        Ok(())
+    }
+
+    //
+    // Sums the log files of the containers kept on the node, rotated ones included
+    //
+    async fn log_usage(&self) -> Result<u64, Error> {
+        let docker = connect()?;
+
+        let mut usage = 0;
+        for id in self.sidecars.iter().chain([&self.id]) {
+            let inspect = docker
+                .inspect_container(id, None)
+                .await
+                .context("Can't inspect docker container. ")?;
+            if let Some(path) = inspect.log_path.filter(|path| !path.is_empty()) {
+                usage += log_files_size(Path::new(&path));
+            }
+        }
+
+        Ok(usage)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::workload_manager::workload::workload_trait::Workload;
 
-    use super::Container;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    use super::{
+        checkpoint_dir, device_mappings, host_config, huge_pages_mounts, log_config,
+        log_files_size, log_tail, runtime_socket, seccomp_option, sidecar_host_config,
+        sidecar_name, storage_opt, Container, PullProgress,
+    };
+    use crate::workload_manager::workload::LogConfig;
     use anyhow::{Error, Result};
     use bollard::{
         container::{ListContainersOptions, RemoveContainerOptions},
+        models::{CreateImageInfo, ProgressDetail},
         Docker,
     };
-    use proto::agent::{Instance, Resource, ResourceSummary, Type};
+    use proto::agent::{
+        Device, Instance, Resource, ResourceSummary, SecurityContext, Type, Volume,
+    };
 
     const IMAGE: &str = "alpine:3";
 
@@ -176,9 +997,17 @@ mod tests {
             resource: Some(resource),
             status: 1,
             r#type: Type::Container.into(),
+            security_context: None,
+            kind: Default::default(),
+            volumes: Vec::new(),
+            sidecars: Vec::new(),
+            restore: None,
+            devices: HashMap::new(),
+            exclusive_cpus: 0,
+            huge_pages: HashMap::new(),
         };
 
-        Ok(Container::new(instance).await?)
+        Container::new(instance, None, &LogConfig::default(), &[], None, None).await
     }
 
     async fn create_container_test() -> Result<(), Error> {
@@ -225,12 +1054,9 @@ mod tests {
             }))
             .await?;
 
-        assert_eq!(
-            result
-                .iter()
-                .any(|cont| cont.id.as_ref().unwrap() == &container.id),
-            false
-        );
+        assert!(!result
+            .iter()
+            .any(|cont| cont.id.as_ref().unwrap() == &container.id));
 
         Ok(())
     }
@@ -244,4 +1070,233 @@ mod tests {
     fn test_stop_container() {
         tokio_test::block_on(stop_container_test()).unwrap();
     }
+
+    #[test]
+    fn test_host_config() {
+        let context = SecurityContext {
+            drop_capabilities: vec!["NET_RAW".to_string()],
+            seccomp_profile: "unconfined".to_string(),
+            read_only_root_filesystem: true,
+            user: "1000".to_string(),
+        };
+        let volumes = [Volume {
+            name: "kudo-default-db-0".to_string(),
+            path: "/var/lib/data".to_string(),
+        }];
+        let config = host_config(&context, &volumes, Path::new("/nonexistent")).unwrap();
+        assert_eq!(config.cap_drop, Some(vec!["NET_RAW".to_string()]));
+        assert_eq!(
+            config.binds,
+            Some(vec!["kudo-default-db-0:/var/lib/data".to_string()])
+        );
+        assert_eq!(config.readonly_rootfs, Some(true));
+        assert_eq!(
+            config.security_opt,
+            Some(vec!["seccomp=unconfined".to_string()])
+        );
+
+        let config =
+            host_config(&SecurityContext::default(), &[], Path::new("/nonexistent")).unwrap();
+        assert_eq!(config.cap_drop, None);
+        assert_eq!(config.binds, None);
+        assert_eq!(config.security_opt, None);
+    }
+
+    #[test]
+    fn test_sidecar_host_config() {
+        let volumes = [Volume {
+            name: "kudo-default-db-0".to_string(),
+            path: "/var/lib/data".to_string(),
+        }];
+        let config = sidecar_host_config(
+            "0123abcd",
+            &SecurityContext::default(),
+            &volumes,
+            Path::new("/nonexistent"),
+        )
+        .unwrap();
+        assert_eq!(config.network_mode, Some("container:0123abcd".to_string()));
+        assert_eq!(
+            config.binds,
+            Some(vec!["kudo-default-db-0:/var/lib/data".to_string()])
+        );
+
+        assert_eq!(sidecar_name("web-1", "proxy"), "web-1-proxy");
+    }
+
+    #[test]
+    fn test_storage_opt() {
+        let resource = |disk| Resource {
+            limit: Some(ResourceSummary {
+                disk,
+                ..Default::default()
+            }),
+            usage: None,
+        };
+        assert_eq!(storage_opt(None), None);
+        assert_eq!(storage_opt(Some(&resource(0))), None);
+        assert_eq!(
+            storage_opt(Some(&resource(2))).unwrap().get("size"),
+            Some(&"2000000000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_device_mappings() {
+        assert_eq!(device_mappings(&[]), None);
+
+        let devices = [Device {
+            id: "0".to_string(),
+            kind: "gpu".to_string(),
+            paths: vec!["/dev/nvidia0".to_string(), "/dev/nvidiactl".to_string()],
+        }];
+        let mappings = device_mappings(&devices).unwrap();
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[1].path_on_host.as_deref(), Some("/dev/nvidiactl"));
+        assert_eq!(
+            mappings[1].path_in_container.as_deref(),
+            Some("/dev/nvidiactl")
+        );
+    }
+
+    #[test]
+    fn test_huge_pages_mounts() {
+        assert_eq!(huge_pages_mounts(&HashMap::new()).unwrap(), None);
+        assert!(huge_pages_mounts(&HashMap::from([("2M".to_string(), 1)])).is_err());
+
+        let mounts = huge_pages_mounts(&HashMap::from([
+            ("2Mi".to_string(), 512),
+            ("1Gi".to_string(), 0),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(mounts.len(), 1);
+        assert_eq!(mounts[0].target.as_deref(), Some("/dev/hugepages-2Mi"));
+        let options = mounts[0]
+            .volume_options
+            .as_ref()
+            .and_then(|options| options.driver_config.as_ref())
+            .and_then(|config| config.options.as_ref())
+            .unwrap();
+        assert_eq!(options["o"], "pagesize=2097152,size=1073741824");
+    }
+
+    #[test]
+    fn test_checkpoint_dir() {
+        let dir = Path::new("/var/lib/kudo/checkpoints");
+        assert_eq!(
+            checkpoint_dir("1234", "before-upgrade", dir).unwrap(),
+            dir.join("1234")
+        );
+        assert!(checkpoint_dir("1234", "", dir).is_err());
+        assert!(checkpoint_dir("..", "before-upgrade", dir).is_err());
+        assert!(checkpoint_dir("1234", "../../etc", dir).is_err());
+        assert!(checkpoint_dir("/1234", "before-upgrade", dir).is_err());
+    }
+
+    #[test]
+    fn test_log_config() {
+        let config = log_config(&LogConfig {
+            max_size: 1_000_000,
+            max_files: 0,
+        });
+        assert_eq!(config.typ, Some("json-file".to_string()));
+        let options = config.config.unwrap();
+        assert_eq!(options.get("max-size"), Some(&"1000000".to_string()));
+        // the current file is always kept
+        assert_eq!(options.get("max-file"), Some(&"1".to_string()));
+    }
+
+    #[test]
+    fn test_log_tail() {
+        assert_eq!(log_tail(0), "all");
+        assert_eq!(log_tail(20), "20");
+    }
+
+    #[test]
+    fn test_log_files_size() {
+        let dir = std::env::temp_dir().join("kudo-logs-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("abcd-json.log"), [0; 100]).unwrap();
+        std::fs::write(dir.join("abcd-json.log.1"), [0; 1000]).unwrap();
+        std::fs::write(dir.join("efgh-json.log"), [0; 10]).unwrap();
+
+        assert_eq!(log_files_size(&dir.join("abcd-json.log")), 1100);
+        assert_eq!(log_files_size(&dir.join("ijkl-json.log")), 0);
+        assert_eq!(log_files_size(Path::new("/nonexistent/abcd-json.log")), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_seccomp_option() {
+        let dir = std::env::temp_dir().join("kudo-seccomp-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("audit.json"),
+            r#"{"defaultAction":"SCMP_ACT_LOG"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            seccomp_option("localhost/audit.json", &dir).unwrap(),
+            Some(r#"seccomp={"defaultAction":"SCMP_ACT_LOG"}"#.to_string())
+        );
+        assert!(seccomp_option("localhost/../audit.json", &dir).is_err());
+        assert!(seccomp_option("localhost/missing.json", &dir).is_err());
+        assert!(seccomp_option("strict", &dir).is_err());
+    }
+
+    #[test]
+    fn test_pull_progress() {
+        let info = |id: &str, status: &str, current: i64, total: i64| CreateImageInfo {
+            id: Some(id.to_string()),
+            status: Some(status.to_string()),
+            progress_detail: Some(ProgressDetail {
+                current: Some(current),
+                total: Some(total),
+            }),
+            ..Default::default()
+        };
+        let mut progress = PullProgress::default();
+
+        assert!(!progress.update(&CreateImageInfo {
+            status: Some("Pulling from library/nginx".to_string()),
+            ..Default::default()
+        }));
+        assert!(progress.update(&info("a", "Downloading", 1_000_000, 4_000_000)));
+        assert!(progress.update(&info("b", "Downloading", 0, 6_000_000)));
+        assert_eq!(
+            progress.description("nginx"),
+            "Pulling nginx: 10% (1.0 MB of 10.0 MB)"
+        );
+        // the same percentage isn't reported twice
+        assert!(!progress.update(&info("b", "Downloading", 10, 6_000_000)));
+
+        // the extraction of a layer doesn't count as a download
+        assert!(progress.update(&info("a", "Download complete", 0, 0)));
+        assert!(!progress.update(&info("a", "Extracting", 0, 4_000_000)));
+        assert_eq!(progress.percentage(), 40);
+    }
+
+    #[test]
+    fn test_runtime_socket() {
+        let runtime_dir = Path::new("/run/user/1000");
+        let none = |_: &Path| false;
+        let podman = |socket: &Path| socket.ends_with("podman/podman.sock");
+
+        assert_eq!(
+            runtime_socket(Some("unix:///tmp/docker.sock"), Some(runtime_dir), none),
+            "/tmp/docker.sock"
+        );
+        assert_eq!(
+            runtime_socket(None, Some(runtime_dir), podman),
+            "/run/user/1000/podman/podman.sock"
+        );
+        assert_eq!(
+            runtime_socket(None, Some(runtime_dir), none),
+            "/var/run/docker.sock"
+        );
+        assert_eq!(runtime_socket(None, None, none), "/var/run/docker.sock");
+    }
 }
@@ -1,12 +1,177 @@
-use anyhow::Result;
-use proto::agent::{Instance, Type};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use image_policy::Verifier;
+use network::cni::CniNetwork;
+use proto::agent::{Device, Instance, InstanceStatus, Resource, ResourceSummary, Status, Type};
+use tokio::sync::mpsc;
 use workload_trait::Workload;
 
+use super::cpus::CpuSet;
+use super::ExecInput;
+
 mod container;
 pub mod workload_trait;
 
-pub async fn create(instance: Instance) -> Result<impl Workload> {
+/// The unit of the disk limits of the instances, as the disk of the nodes.
+pub const BYTES_PER_GB: u64 = 1_000_000_000;
+
+/// Returns the disk quota of an instance in bytes, summed over its containers, 0 without limit.
+pub fn disk_quota(instance: &Instance) -> u64 {
+    let disk = |resource: Option<&Resource>| {
+        resource
+            .and_then(|resource| resource.limit.as_ref())
+            .map_or(0, |limit| limit.disk)
+    };
+    let sidecars: u64 = instance
+        .sidecars
+        .iter()
+        .map(|sidecar| disk(sidecar.resource.as_ref()))
+        .sum();
+    (disk(instance.resource.as_ref()) + sidecars).saturating_mul(BYTES_PER_GB)
+}
+
+/// `LogConfig` is the retention of the logs of the instances on the node: the log file of each
+/// container is rotated once it reaches `max_size` bytes and only the `max_files` latest files
+/// are kept, so the logs of an instance can't fill the disk of the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogConfig {
+    pub max_size: u64,
+    pub max_files: u32,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            max_size: 10_000_000,
+            max_files: 3,
+        }
+    }
+}
+
+/// `StatusReporter` sends the intermediate statuses of an instance while its workload is being
+/// created, e.g. the progress of the pull of its image.
+///
+/// Properties:
+///
+/// * `id`: The id of the instance.
+/// * `sender`: The channel the statuses are sent on.
+#[derive(Debug, Clone)]
+pub struct StatusReporter {
+    id: String,
+    sender: mpsc::Sender<InstanceStatus>,
+}
+
+impl StatusReporter {
+    pub fn new(id: String, sender: mpsc::Sender<InstanceStatus>) -> Self {
+        StatusReporter { id, sender }
+    }
+
+    /// Sends a status of the instance. It is dropped if the receiver lags behind, the next one
+    /// replacing it, so a slow receiver doesn't slow down the creation.
+    pub fn report(&self, status: Status, description: String) {
+        _ = self.sender.try_send(InstanceStatus {
+            id: self.id.clone(),
+            status: status.into(),
+            description,
+            resource: None,
+            logs: 0,
+        });
+    }
+
+    /// Sends the disk used by the running instance against its quota, 0 without quota, and the
+    /// size of its logs, all in bytes.
+    pub fn report_disk(&self, usage: u64, quota: u64, logs: u64) {
+        let megabytes = |bytes: u64| bytes as f64 / 1_000_000.0;
+        let disk = match quota {
+            0 => format!("{:.1} MB of disk", megabytes(usage)),
+            quota => format!(
+                "{:.1} MB of its {:.1} MB disk quota",
+                megabytes(usage),
+                megabytes(quota)
+            ),
+        };
+        _ = self.sender.try_send(InstanceStatus {
+            id: self.id.clone(),
+            status: Status::Running.into(),
+            description: format!("Using {} and {:.1} MB of logs", disk, megabytes(logs)),
+            resource: Some(Resource {
+                limit: (quota > 0).then(|| ResourceSummary {
+                    disk: quota / BYTES_PER_GB,
+                    ..Default::default()
+                }),
+                usage: Some(ResourceSummary {
+                    disk: usage / BYTES_PER_GB,
+                    ..Default::default()
+                }),
+            }),
+            logs,
+        });
+    }
+}
+
+/// Creates the workload of an instance. With a `verifier`, the signature of the image is
+/// verified first and the instance runs the image pinned to the verified digest. With a
+/// `reporter`, the progress of the creation is sent on it. Its logs are kept as `logs` says, the
+/// `devices` allocated to it are mounted into its main container and the main container only
+/// runs on the CPUs of `cpuset`, if set. With `cni`, the instance is added to the CNI network of
+/// the node instead of the network of the runtime.
+pub async fn create(
+    mut instance: Instance,
+    verifier: Option<&Verifier>,
+    reporter: Option<&StatusReporter>,
+    logs: &LogConfig,
+    devices: &[Device],
+    cpuset: Option<&CpuSet>,
+    cni: Option<Arc<CniNetwork>>,
+) -> Result<impl Workload> {
+    if let Some(verifier) = verifier {
+        instance.uri = verifier.check(&instance.uri).await?;
+    }
+
     match instance.r#type() {
-        Type::Container => container::Container::new(instance).await,
+        Type::Container => {
+            container::Container::new(instance, reporter, logs, devices, cpuset, cni).await
+        }
+        // this build doesn't report the WASM feature, the scheduler doesn't place them here
+        Type::Wasm => bail!("instance {} is a WASM module, not supported", instance.id),
     }
 }
+
+/// Streams the output of the main container of an instance to `output`, the last `tail` lines or
+/// every line if 0, then the next ones if `follow` is set, until `output` is closed.
+pub async fn logs(
+    workload_id: &str,
+    follow: bool,
+    tail: u32,
+    output: &mpsc::Sender<Vec<u8>>,
+) -> Result<()> {
+    container::logs(workload_id, follow, tail, output).await
+}
+
+/// Runs a command in the main container of an instance, `inputs` being written to its stdin and
+/// its output sent to `output` until it exits. Returns the exit code of the command.
+pub async fn exec(
+    workload_id: &str,
+    command: &[String],
+    tty: bool,
+    inputs: mpsc::UnboundedReceiver<ExecInput>,
+    output: &mpsc::Sender<Vec<u8>>,
+) -> Result<i64> {
+    container::exec(workload_id, command, tty, inputs, output).await
+}
+
+/// Pulls the image of a container ahead of the instances using it, the description of its
+/// progress is passed to `progress`. With a `verifier`, the signature of the image is verified
+/// first and the verified digest is pulled, the one the instances will run.
+pub async fn pull(
+    uri: &str,
+    verifier: Option<&Verifier>,
+    progress: &(dyn Fn(String) + Send + Sync),
+) -> Result<()> {
+    let uri = match verifier {
+        Some(verifier) => verifier.check(uri).await?,
+        None => uri.to_string(),
+    };
+    container::pull(&uri, progress).await
+}
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 #[tonic::async_trait]
 pub trait Workload {
@@ -14,4 +14,28 @@ pub trait Workload {
     // (equivalent to a `kill -9` on linux)
     //
     async fn kill(&self) -> Result<()>;
+
+    //
+    // Returns the bytes written by a workload on the disk of the node
+    // (its writable layers and its volumes), 0 if it isn't measured
+    //
+    async fn disk_usage(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    //
+    // Returns the bytes of the log files of a workload kept on the node,
+    // 0 if they aren't measured
+    //
+    async fn log_usage(&self) -> Result<u64> {
+        Ok(0)
+    }
+
+    //
+    // Checkpoints the memory of a workload under `name` (experimental),
+    // the workload is stopped once checkpointed unless `leave_running` is set
+    //
+    async fn checkpoint(&self, _name: &str, _leave_running: bool) -> Result<()> {
+        Err(anyhow!("The workload can't be checkpointed. "))
+    }
 }
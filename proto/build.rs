@@ -1,12 +1,19 @@
+use std::{env, path::PathBuf};
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure().compile(
-        &[
-            "./src/controller.proto",
-            "./src/agent.proto",
-            "./src/scheduler.proto",
-            "./src/network.proto",
-        ],
-        &["./src/"],
-    )?;
+    // the descriptors are served by the gRPC reflection services
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+
+    tonic_build::configure()
+        .file_descriptor_set_path(out_dir.join("kudo_descriptor.bin"))
+        .compile(
+            &[
+                "./src/controller.proto",
+                "./src/agent.proto",
+                "./src/scheduler.proto",
+                "./src/network.proto",
+            ],
+            &["./src/"],
+        )?;
     Ok(())
 }
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// The metadata key carrying the time left before the caller of an RPC gives up, in
+/// milliseconds. Unlike `grpc-timeout`, it doesn't end the streams returned by the call, it only
+/// bounds the work done before answering it.
+pub const DEADLINE_METADATA: &str = "x-kudo-deadline";
+
+/// Returns the value of the `x-kudo-deadline` metadata for the time left to the caller.
+pub fn format_deadline(remaining: Duration) -> String {
+    remaining.as_millis().to_string()
+}
+
+/// Parses the value of the `x-kudo-deadline` metadata into the time left to the caller.
+pub fn parse_deadline(value: &str) -> Option<Duration> {
+    value.trim().parse().ok().map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_deadline() {
+        let remaining = Duration::from_millis(1500);
+        assert_eq!(parse_deadline(&format_deadline(remaining)), Some(remaining));
+        assert_eq!(parse_deadline("-1"), None);
+        assert_eq!(parse_deadline("1s"), None);
+    }
+}
@@ -1,3 +1,9 @@
+/// The encoded descriptors of all the kudo protos, used by the gRPC reflection services.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("kudo_descriptor");
+
+pub mod deadline;
+pub mod version;
+
 pub mod network {
     #![allow(clippy::all)]
     tonic::include_proto!("network");
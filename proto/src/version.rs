@@ -0,0 +1,66 @@
+use crate::scheduler::VersionInfo;
+
+/// The newest version of the kudo protocol spoken by this build, bumped on breaking changes of
+/// the protos.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The oldest version of the kudo protocol still spoken by this build.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// The metadata key carrying the protocol versions supported by the caller of an RPC, as
+/// `<min>-<max>`.
+pub const PROTOCOL_METADATA: &str = "x-kudo-protocol";
+
+/// Returns the version information of a component, sent to its peers.
+///
+/// Arguments:
+///
+/// * `component_version`: The version of the component, usually its `CARGO_PKG_VERSION`.
+pub fn version_info(component_version: &str) -> VersionInfo {
+    VersionInfo {
+        component_version: component_version.to_string(),
+        min_protocol_version: MIN_PROTOCOL_VERSION,
+        max_protocol_version: PROTOCOL_VERSION,
+    }
+}
+
+/// Returns the newest protocol version supported by both this build and a peer supporting the
+/// versions from `min` to `max`, `None` if they have no version in common.
+pub fn negotiate(min: u32, max: u32) -> Option<u32> {
+    let version = max.min(PROTOCOL_VERSION);
+    (version >= min.max(MIN_PROTOCOL_VERSION)).then_some(version)
+}
+
+/// Returns the value of the `x-kudo-protocol` metadata for this build.
+pub fn protocol_range() -> String {
+    format!("{}-{}", MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)
+}
+
+/// Parses the value of the `x-kudo-protocol` metadata into the versions supported by the peer.
+pub fn parse_protocol_range(value: &str) -> Option<(u32, u32)> {
+    let (min, max) = value.split_once('-')?;
+    Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate(1, 1), Some(1));
+        assert_eq!(negotiate(1, 5), Some(PROTOCOL_VERSION));
+        assert_eq!(negotiate(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 2), None);
+        assert_eq!(negotiate(2, 1), None);
+    }
+
+    #[test]
+    fn test_protocol_range() {
+        assert_eq!(
+            parse_protocol_range(&protocol_range()),
+            Some((MIN_PROTOCOL_VERSION, PROTOCOL_VERSION))
+        );
+        assert_eq!(parse_protocol_range("2"), None);
+        assert_eq!(parse_protocol_range("a-b"), None);
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tonic::{service::Interceptor, Request, Status};
+
+use crate::{pki, NodeIdentifier};
+
+/// The metadata key carrying the id of the node sending a request.
+pub const NODE_ID_METADATA: &str = "x-node-id";
+
+/// The id of a node authenticated by the `NodeAuthenticator`, added to the extensions of its
+/// requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticatedNode(pub NodeIdentifier);
+
+/// `NodeAuthenticator` is the interceptor of the node service. Every request must carry the id
+/// of the node in the `x-node-id` metadata and its shared secret in the `authorization` metadata,
+/// as a bearer token. When the nodes have client certificates, the node is instead identified by
+/// the common name of its certificate.
+///
+/// Properties:
+///
+/// * `secrets`: The shared secret of each node allowed to connect, the authentication is
+///   disabled when empty.
+/// * `certificates`: The nodes are authenticated by their client certificate, the secrets are
+///   ignored.
+#[derive(Debug, Clone)]
+pub struct NodeAuthenticator {
+    secrets: Arc<HashMap<NodeIdentifier, String>>,
+    certificates: bool,
+}
+
+impl NodeAuthenticator {
+    pub fn new(secrets: HashMap<NodeIdentifier, String>) -> Self {
+        NodeAuthenticator {
+            secrets: Arc::new(secrets),
+            certificates: false,
+        }
+    }
+
+    /// Creates an authenticator identifying the nodes by their client certificate, the
+    /// certificate being verified against the CA by the TLS handshake.
+    pub fn with_certificates() -> Self {
+        NodeAuthenticator {
+            secrets: Arc::new(HashMap::new()),
+            certificates: true,
+        }
+    }
+
+    /// Returns `true` if the requests are authenticated.
+    pub fn is_enabled(&self) -> bool {
+        self.certificates || !self.secrets.is_empty()
+    }
+}
+
+impl Interceptor for NodeAuthenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if !self.is_enabled() {
+            return Ok(request);
+        }
+
+        if self.certificates {
+            let node_id = request
+                .peer_certs()
+                .and_then(|certificates| {
+                    certificates
+                        .first()
+                        .and_then(|certificate| pki::node_id(certificate.get_ref()))
+                })
+                .ok_or_else(|| Status::unauthenticated("missing client certificate"))?;
+            request.extensions_mut().insert(AuthenticatedNode(node_id));
+            return Ok(request);
+        }
+
+        let metadata = request.metadata();
+        let node_id = metadata
+            .get(NODE_ID_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("missing node id"))?;
+        let secret = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("missing node secret"))?;
+
+        match self.secrets.get(node_id) {
+            Some(expected) if constant_time_eq(expected.as_bytes(), secret.as_bytes()) => {
+                let node = AuthenticatedNode(node_id.to_string());
+                request.extensions_mut().insert(node);
+                Ok(request)
+            }
+            _ => Err(Status::unauthenticated("unknown node or invalid secret")),
+        }
+    }
+}
+
+/// Returns the id of the node authenticated for a request, `None` if the authentication is
+/// disabled.
+pub fn authenticated_node<T>(request: &Request<T>) -> Option<NodeIdentifier> {
+    request
+        .extensions()
+        .get::<AuthenticatedNode>()
+        .map(|node| node.0.clone())
+}
+
+/// Checks that the node id claimed in a message is the one of the authenticated node, so a node
+/// can't act on behalf of another one.
+///
+/// Arguments:
+///
+/// * `authenticated`: The id of the authenticated node, `None` if the authentication is disabled.
+/// * `claimed`: The node id sent in the message.
+#[allow(clippy::result_large_err)]
+pub fn check_identity(authenticated: Option<&str>, claimed: &str) -> Result<(), Status> {
+    match authenticated {
+        Some(node_id) if node_id != claimed => Err(Status::permission_denied(format!(
+            "node {} can't act as node {}",
+            node_id, claimed
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Compares two secrets in a time independent of their content.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authenticator() -> NodeAuthenticator {
+        NodeAuthenticator::new(HashMap::from([(
+            "node-1".to_string(),
+            "secret".to_string(),
+        )]))
+    }
+
+    fn request(node_id: &str, secret: &str) -> Request<()> {
+        let mut request = Request::new(());
+        let metadata = request.metadata_mut();
+        metadata.insert(NODE_ID_METADATA, node_id.parse().unwrap());
+        metadata.insert(
+            "authorization",
+            format!("Bearer {}", secret).parse().unwrap(),
+        );
+        request
+    }
+
+    #[test]
+    fn test_valid_secret() {
+        let request = authenticator().call(request("node-1", "secret")).unwrap();
+        assert_eq!(authenticated_node(&request), Some("node-1".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_secret() {
+        let mut authenticator = authenticator();
+        for request in [
+            request("node-1", "wrong"),
+            request("node-2", "secret"),
+            Request::new(()),
+        ] {
+            let err = authenticator.call(request).unwrap_err();
+            assert_eq!(err.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[test]
+    fn test_disabled() {
+        let request = NodeAuthenticator::new(HashMap::new())
+            .call(Request::new(()))
+            .unwrap();
+        assert_eq!(authenticated_node(&request), None);
+    }
+
+    #[test]
+    fn test_missing_certificate() {
+        let err = NodeAuthenticator::with_certificates()
+            .call(request("node-1", "secret"))
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_check_identity() {
+        assert!(check_identity(Some("node-1"), "node-1").is_ok());
+        assert!(check_identity(None, "node-2").is_ok());
+        let err = check_identity(Some("node-1"), "node-2").unwrap_err();
+        assert_eq!(err.code(), tonic::Code::PermissionDenied);
+    }
+}
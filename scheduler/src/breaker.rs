@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::time::Instant;
+
+use crate::config::BreakerConfig;
+
+/// `BreakerPolicy` decides when the commands sent to a node are paused.
+///
+/// Properties:
+///
+/// * `failure_threshold`: The number of consecutive failures opening the breaker of a node.
+/// * `cooldown`: How long the commands are paused once the breaker is open.
+#[derive(Debug, Clone)]
+pub struct BreakerPolicy {
+    pub failure_threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl From<&BreakerConfig> for BreakerPolicy {
+    fn from(config: &BreakerConfig) -> Self {
+        BreakerPolicy {
+            failure_threshold: config.failure_threshold.max(1),
+            cooldown: Duration::from_secs(config.cooldown),
+        }
+    }
+}
+
+impl Default for BreakerPolicy {
+    fn default() -> Self {
+        BreakerPolicy::from(&BreakerConfig::default())
+    }
+}
+
+/// The state of the breaker of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakerState {
+    /// The commands are sent, `failures` being the consecutive failures so far
+    Closed { failures: u32 },
+    /// The commands are refused until `until`, the node is suspect
+    Open { until: Instant },
+    /// The cooldown is over, the next command is a trial closing the breaker if it succeeds
+    HalfOpen,
+}
+
+impl Default for BreakerState {
+    fn default() -> Self {
+        BreakerState::Closed { failures: 0 }
+    }
+}
+
+/// `Breaker` is the circuit breaker of a node. It opens after `failure_threshold` consecutive
+/// commands failed, e.g. weren't taken in time by a hung node: the commands of the node are then
+/// refused at once for `cooldown`, instead of piling up waiting for the timeout. The next command
+/// is then a trial, closing the breaker if it succeeds or opening it again otherwise.
+///
+/// The id of the node is only given to the methods for their logs, a new breaker is closed.
+#[derive(Debug, Default)]
+pub struct Breaker {
+    state: BreakerState,
+}
+
+impl Breaker {
+    /// Returns `true` if a command can be sent to the node, moving the breaker to half-open once
+    /// the cooldown is over.
+    pub fn allow(&mut self, node_id: &str) -> bool {
+        match self.state {
+            BreakerState::Open { until } if Instant::now() < until => false,
+            BreakerState::Open { .. } => {
+                info!("trying a command on suspect node {}", node_id);
+                self.state = BreakerState::HalfOpen;
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Closes the breaker after a successful command.
+    pub fn record_success(&mut self, node_id: &str) {
+        if self.is_suspect() {
+            info!("node {} isn't suspect anymore", node_id);
+        }
+        self.state = BreakerState::default();
+    }
+
+    /// Counts a failed command, opening the breaker at the threshold of the policy or if the
+    /// trial after the cooldown failed.
+    pub fn record_failure(&mut self, node_id: &str, policy: &BreakerPolicy) {
+        let failures = match self.state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::Open { .. } => return,
+            BreakerState::HalfOpen => policy.failure_threshold,
+        };
+
+        self.state = if failures >= policy.failure_threshold {
+            warn!(
+                "node {} is suspect after {} failed commands, its commands are paused for {:?}",
+                node_id, failures, policy.cooldown
+            );
+            BreakerState::Open {
+                until: Instant::now() + policy.cooldown,
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+    }
+
+    /// Returns `true` while the commands of the node are paused or the trial is pending.
+    pub fn is_suspect(&self) -> bool {
+        matches!(
+            self.state,
+            BreakerState::Open { .. } | BreakerState::HalfOpen
+        )
+    }
+
+    /// Returns `true` while the commands of the node are paused, until the cooldown is over.
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, BreakerState::Open { until } if Instant::now() < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(cooldown: Duration) -> BreakerPolicy {
+        BreakerPolicy {
+            failure_threshold: 2,
+            cooldown,
+        }
+    }
+
+    #[test]
+    fn test_open_after_threshold() {
+        let policy = policy(Duration::from_secs(60));
+        let mut breaker = Breaker::default();
+        breaker.record_failure("a", &policy);
+        assert!(breaker.allow("a"));
+        breaker.record_success("a");
+        breaker.record_failure("a", &policy);
+        assert!(!breaker.is_suspect());
+
+        breaker.record_failure("a", &policy);
+        assert!(breaker.is_suspect());
+        assert!(breaker.is_paused());
+        assert!(!breaker.allow("a"));
+        assert!(Breaker::default().allow("b"));
+
+        // a node opening a new lifecycle stream starts with a new breaker
+        breaker = Breaker::default();
+        assert!(breaker.allow("a"));
+    }
+
+    #[test]
+    fn test_trial_after_cooldown() {
+        let policy = policy(Duration::ZERO);
+        let mut breaker = Breaker::default();
+        breaker.record_failure("a", &policy);
+        breaker.record_failure("a", &policy);
+        assert!(!breaker.is_paused());
+
+        // the trial fails, the breaker opens again at once
+        assert!(breaker.allow("a"));
+        breaker.record_failure("a", &policy);
+        assert!(breaker.is_suspect());
+
+        assert!(breaker.allow("a"));
+        breaker.record_success("a");
+        assert!(!breaker.is_suspect());
+    }
+}
@@ -0,0 +1,107 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use log::{debug, info, warn};
+use prost::Message;
+use proto::scheduler::SchedulerAnnouncement;
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+use crate::config::{BroadcastConfig, Config};
+
+/// It builds the announcement of the scheduler: the node service is served on the port of the
+/// mutual TLS server if the cluster has a CA, on the port of the gRPC server otherwise.
+///
+/// Arguments:
+///
+/// * `config`: The configuration of the scheduler
+/// * `broadcast`: The settings of the announcements
+///
+/// Returns:
+///
+/// The announcement broadcast on the local network
+pub fn announcement(config: &Config, broadcast: &BroadcastConfig) -> SchedulerAnnouncement {
+    let (node_port, join_port) = match &config.pki {
+        Some(pki) => (pki.node_port, pki.join_port),
+        None => (config.port, 0),
+    };
+
+    SchedulerAnnouncement {
+        cluster: broadcast.cluster.clone(),
+        host: broadcast.advertise_host.clone().unwrap_or_default(),
+        node_port: node_port.into(),
+        join_port: join_port.into(),
+    }
+}
+
+/// It broadcasts the announcement of the scheduler on the local network at each interval, so
+/// the node agents configured to discover the scheduler find it. A failed broadcast is logged and
+/// retried at the next interval.
+///
+/// Arguments:
+///
+/// * `config`: The configuration of the scheduler
+/// * `broadcast`: The settings of the announcements
+///
+/// Returns:
+///
+/// A JoinHandle<()>
+pub fn announce(config: &Config, broadcast: &BroadcastConfig) -> JoinHandle<()> {
+    let payload = announcement(config, broadcast).encode_to_vec();
+    let target = SocketAddr::from((broadcast.broadcast_address, broadcast.port));
+    let mut interval = tokio::time::interval(broadcast.interval());
+
+    tokio::spawn(async move {
+        let socket = match bind_broadcast().await {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!(
+                    "could not open the broadcast socket, not announced: {}",
+                    err
+                );
+                return;
+            }
+        };
+        info!("announcing the scheduler on {}", target);
+
+        loop {
+            interval.tick().await;
+            match socket.send_to(&payload, target).await {
+                Ok(_) => debug!("scheduler announced on {}", target),
+                Err(err) => warn!("could not announce the scheduler on {}: {}", target, err),
+            }
+        }
+    })
+}
+
+async fn bind_broadcast() -> std::io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PkiConfig;
+
+    #[test]
+    fn test_announcement() {
+        let mut config = Config::default();
+        let broadcast = BroadcastConfig {
+            advertise_host: Some("10.0.0.1".to_string()),
+            ..Default::default()
+        };
+
+        let plain = announcement(&config, &broadcast);
+        assert_eq!(plain.cluster, "kudo");
+        assert_eq!(plain.host, "10.0.0.1");
+        assert_eq!(plain.node_port, 50052);
+        assert_eq!(plain.join_port, 0);
+
+        // with a CA, the nodes join through the bootstrap service then use the mutual TLS server
+        config.pki = Some(PkiConfig::default());
+        let secure = announcement(&config, &BroadcastConfig::default());
+        assert_eq!(secure.host, "");
+        assert_eq!(secure.node_port, 50054);
+        assert_eq!(secure.join_port, 50053);
+    }
+}
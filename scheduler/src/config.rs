@@ -1,3 +1,5 @@
+use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf, time::Duration};
+
 use serde_derive::{Deserialize, Serialize};
 
 /// `Config` is a struct that contains the configuration of the scheduler.
@@ -6,10 +8,99 @@ use serde_derive::{Deserialize, Serialize};
 ///
 /// * `host`: The hostname or IP address of the gRPC server.
 /// * `port`: The port that the gRPC server will listen on.
+/// * `otlp_endpoint`: The OTLP collector the spans are exported to, no export if empty.
+/// * `debug_address`: The address of the read-only debug HTTP server, not served if empty.
+/// * `grpc`: The keepalive and timeout settings of the gRPC connections.
+/// * `retry`: The retries of the instance creations failing for a transient reason.
+/// * `breaker`: When the commands sent to a node failing repeatedly are paused.
+/// * `status`: The interval the nodes send their statuses at.
+/// * `agent_versions`: The versions of the node agents accepted at registration.
+/// * `queue`: The bounds of the event queue.
+/// * `node_secrets`: The shared secret of each node allowed to use the node service, by node id.
+///   The nodes aren't authenticated if empty.
+/// * `pki`: The CA issuing the client certificates of the nodes. If set, the node service is only
+///   served with mutual TLS and the node secrets are ignored.
+/// * `broadcast`: The announcements of the scheduler on the local network, not announced if empty.
+/// * `profiles`: How the instances are placed on the nodes, for each namespace.
+/// * `rebalance`: The moves of instances between the nodes to even out their load, not moved if
+///   empty.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub host: String,
     pub port: u16,
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    #[serde(default)]
+    pub debug_address: Option<String>,
+    #[serde(default)]
+    pub grpc: GrpcConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+    #[serde(default)]
+    pub status: StatusConfig,
+    #[serde(default)]
+    pub agent_versions: AgentVersionConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub node_secrets: HashMap<String, String>,
+    #[serde(default)]
+    pub pki: Option<PkiConfig>,
+    #[serde(default)]
+    pub broadcast: Option<BroadcastConfig>,
+    #[serde(default)]
+    pub profiles: ProfilesConfig,
+    #[serde(default)]
+    pub rebalance: Option<RebalanceConfig>,
+}
+
+/// `GrpcConfig` contains the keepalive and timeout settings of the gRPC connections, in seconds.
+///
+/// Properties:
+///
+/// * `keepalive_interval`: The interval of the HTTP/2 pings sent on idle connections.
+/// * `keepalive_timeout`: The time a ping is waited for before the connection is closed.
+/// * `tcp_keepalive`: The TCP keepalive of the accepted connections.
+/// * `request_timeout`: The deadline of each RPC, and of each command sent to a node so a hung
+///   node doesn't block the event handlers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub keepalive_interval: u64,
+    pub keepalive_timeout: u64,
+    pub tcp_keepalive: u64,
+    pub request_timeout: u64,
+}
+
+impl GrpcConfig {
+    pub fn keepalive_interval(&self) -> Duration {
+        Duration::from_secs(self.keepalive_interval)
+    }
+
+    pub fn keepalive_timeout(&self) -> Duration {
+        Duration::from_secs(self.keepalive_timeout)
+    }
+
+    pub fn tcp_keepalive(&self) -> Duration {
+        Duration::from_secs(self.tcp_keepalive)
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout)
+    }
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        GrpcConfig {
+            keepalive_interval: 30,
+            keepalive_timeout: 20,
+            tcp_keepalive: 60,
+            request_timeout: 10,
+        }
+    }
 }
 
 impl Default for Config {
@@ -17,6 +108,334 @@ impl Default for Config {
         Config {
             host: "127.0.0.1".to_string(),
             port: 50052,
+            otlp_endpoint: None,
+            debug_address: None,
+            grpc: GrpcConfig::default(),
+            retry: RetryConfig::default(),
+            breaker: BreakerConfig::default(),
+            status: StatusConfig::default(),
+            agent_versions: AgentVersionConfig::default(),
+            queue: QueueConfig::default(),
+            node_secrets: HashMap::new(),
+            pki: None,
+            broadcast: None,
+            profiles: ProfilesConfig::default(),
+            rebalance: None,
+        }
+    }
+}
+
+/// `RetryConfig` contains the retry settings of the instance creations, the delays are in
+/// milliseconds.
+///
+/// Properties:
+///
+/// * `max_attempts`: The number of attempts, the first one included.
+/// * `initial_backoff`: The maximum delay before the first retry, doubled for each next one.
+/// * `max_backoff`: The cap of the delays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub initial_backoff: u64,
+    pub max_backoff: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 5,
+            initial_backoff: 100,
+            max_backoff: 5000,
+        }
+    }
+}
+
+/// `BreakerConfig` contains the settings of the circuit breakers of the nodes.
+///
+/// Properties:
+///
+/// * `failure_threshold`: The number of consecutive commands a node fails to take before it is
+///   suspect and its commands are paused.
+/// * `cooldown`: How long the commands of a suspect node are paused, in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32,
+    pub cooldown: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            failure_threshold: 3,
+            cooldown: 30,
+        }
+    }
+}
+
+/// `StatusConfig` contains the intervals of the statuses sent by the nodes, in milliseconds. The
+/// interval grows with the load of the scheduler.
+///
+/// Properties:
+///
+/// * `interval`: The interval asked to the nodes when the scheduler isn't loaded.
+/// * `max_interval`: The longest interval asked to the nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusConfig {
+    pub interval: u64,
+    pub max_interval: u64,
+}
+
+impl Default for StatusConfig {
+    fn default() -> Self {
+        StatusConfig {
+            interval: 1000,
+            max_interval: 8000,
+        }
+    }
+}
+
+/// `AgentVersionConfig` contains the range of versions of the node agents supported by the
+/// scheduler, every version is supported if no bound is set. The pre-release and build suffixes
+/// of the versions are ignored, e.g. `0.3.0-rc.1` is `0.3.0`.
+///
+/// Properties:
+///
+/// * `min`: The oldest version supported, e.g. `0.3.0`.
+/// * `max`: The newest version supported.
+/// * `action`: What happens to the nodes whose agent is outside of the range, or didn't send its
+///   version.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AgentVersionConfig {
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub action: SkewAction,
+}
+
+/// `SkewAction` is what happens to a node whose agent version isn't supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkewAction {
+    /// The registration of the node is refused.
+    #[default]
+    Reject,
+    /// The node is registered cordoned, so no instance is placed on it until an operator
+    /// uncordons it.
+    Quarantine,
+}
+
+/// `QueueConfig` contains the bounds of the event queue of the scheduler.
+///
+/// Properties:
+///
+/// * `events`: The number of events waiting to be handled, the requests are rejected with
+///   `RESOURCE_EXHAUSTED` beyond.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    pub events: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig { events: 1024 }
+    }
+}
+
+/// `PkiConfig` contains the settings of the CA of the cluster and of the servers using it.
+///
+/// Properties:
+///
+/// * `ca_certificate`: The PEM certificate of the CA, created with its key if missing.
+/// * `ca_key`: The PEM private key of the CA.
+/// * `join_tokens`: The tokens the nodes present to get their client certificate.
+/// * `join_port`: The port of the bootstrap service, served with TLS but without client
+///   certificate.
+/// * `node_port`: The port of the node service, served with mutual TLS.
+/// * `server_names`: The DNS names and IP addresses of the scheduler, in its certificate.
+/// * `ca_validity_days`: How long a new CA is valid.
+/// * `node_certificate_validity_days`: How long the certificates of the nodes are valid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PkiConfig {
+    pub ca_certificate: PathBuf,
+    pub ca_key: PathBuf,
+    pub join_tokens: Vec<String>,
+    pub join_port: u16,
+    pub node_port: u16,
+    pub server_names: Vec<String>,
+    pub ca_validity_days: i64,
+    pub node_certificate_validity_days: i64,
+}
+
+impl Default for PkiConfig {
+    fn default() -> Self {
+        PkiConfig {
+            ca_certificate: PathBuf::from("ca.crt"),
+            ca_key: PathBuf::from("ca.key"),
+            join_tokens: vec![],
+            join_port: 50053,
+            node_port: 50054,
+            server_names: vec!["localhost".to_string()],
+            ca_validity_days: 3650,
+            node_certificate_validity_days: 365,
+        }
+    }
+}
+
+/// `BroadcastConfig` contains the settings of the announcements broadcast by the scheduler on the
+/// local network, letting the node agents find it without a configured address. They are plain
+/// UDP broadcasts, not mDNS records, so they don't cross routers.
+///
+/// Properties:
+///
+/// * `cluster`: The name of the cluster, the agents only join the scheduler of their cluster.
+/// * `port`: The UDP port the announcements are sent to, the agents listen on it.
+/// * `broadcast_address`: The address the announcements are sent to.
+/// * `advertise_host`: The address of the scheduler announced to the agents, the source address
+///   of the announcements is used if empty.
+/// * `interval_seconds`: The interval between two announcements.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BroadcastConfig {
+    pub cluster: String,
+    pub port: u16,
+    pub broadcast_address: Ipv4Addr,
+    pub advertise_host: Option<String>,
+    pub interval_seconds: u64,
+}
+
+impl BroadcastConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_seconds)
+    }
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        BroadcastConfig {
+            cluster: "kudo".to_string(),
+            port: 50055,
+            broadcast_address: Ipv4Addr::BROADCAST,
+            advertise_host: None,
+            interval_seconds: 5,
+        }
+    }
+}
+
+/// `ProfilesConfig` contains the scheduling profiles, so the tenants of a cluster with different
+/// needs, e.g. batch jobs and latency-sensitive services, can share it.
+///
+/// Properties:
+///
+/// * `default`: The profile of the namespaces without their own one.
+/// * `namespaces`: The profile of each namespace, by namespace name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfilesConfig {
+    pub default: ProfileConfig,
+    pub namespaces: HashMap<String, ProfileConfig>,
+}
+
+impl ProfilesConfig {
+    /// Returns the profile of a namespace, the default one if it has none.
+    pub fn get(&self, namespace: &str) -> &ProfileConfig {
+        self.namespaces.get(namespace).unwrap_or(&self.default)
+    }
+}
+
+/// `ProfileConfig` contains how the instances of a namespace are placed on the nodes.
+///
+/// Properties:
+///
+/// * `strategy`: Which node among the ones able to run an instance is picked.
+/// * `weights`: How the load of a node is computed to compare it with the others.
+/// * `overcommit`: The percentage of the capacity of a node its instances may use, e.g. 150 to
+///   overcommit the nodes by half.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub strategy: Strategy,
+    pub weights: WeightsConfig,
+    pub overcommit: u64,
+}
+
+impl Default for ProfileConfig {
+    fn default() -> Self {
+        ProfileConfig {
+            strategy: Strategy::Spread,
+            weights: WeightsConfig::default(),
+            overcommit: 100,
+        }
+    }
+}
+
+/// `Strategy` is the node an instance is placed on among the ones able to run it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// The least loaded node, to even out the load of the nodes.
+    Spread,
+    /// The most loaded node, to keep the other ones free for the larger instances.
+    Binpack,
+}
+
+/// `WeightsConfig` contains the weight of each criteria in the load of a node.
+///
+/// Properties:
+///
+/// * `instances`: The weight of the number of instances placed on the node.
+/// * `cpu`: The weight of the percentage of the cpu of the node in use.
+/// * `memory`: The weight of the percentage of the memory of the node in use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeightsConfig {
+    pub instances: u64,
+    pub cpu: u64,
+    pub memory: u64,
+}
+
+impl Default for WeightsConfig {
+    fn default() -> Self {
+        WeightsConfig {
+            instances: 1,
+            cpu: 0,
+            memory: 0,
+        }
+    }
+}
+
+/// `RebalanceConfig` contains the settings of the rebalancing of the cluster: an instance is
+/// periodically moved from the most loaded node to the least loaded one while their loads are
+/// too far apart, e.g. after a node joined the cluster.
+///
+/// Properties:
+///
+/// * `interval_seconds`: The interval between two moves.
+/// * `threshold`: The difference of load between two nodes from which an instance is moved, the
+///   loads being computed with the weights of the default profile. It must exceed the load of
+///   an instance, or the instance would be moved back at the next interval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RebalanceConfig {
+    pub interval_seconds: u64,
+    pub threshold: u64,
+}
+
+impl RebalanceConfig {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_seconds)
+    }
+}
+
+impl Default for RebalanceConfig {
+    fn default() -> Self {
+        RebalanceConfig {
+            interval_seconds: 60,
+            threshold: 2,
         }
     }
 }
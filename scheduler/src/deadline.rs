@@ -0,0 +1,92 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use proto::deadline::{parse_deadline, DEADLINE_METADATA};
+use tokio::time::Instant;
+use tonic::Request;
+
+/// `Deadline` is when the caller of a request gives up on it, read from the `x-kudo-deadline`
+/// metadata of its call. It travels with the events of the request, so that the work it started
+/// is dropped instead of completing for nobody, and it bounds the commands sent to the nodes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Deadline(Option<Instant>);
+
+impl Deadline {
+    /// The deadline of a request whose caller waits for it as long as needed.
+    pub fn none() -> Self {
+        Deadline(None)
+    }
+
+    /// The deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(Some(Instant::now() + timeout))
+    }
+
+    /// Reads the deadline of a request, `none` if the caller didn't send one.
+    pub fn from_request<T>(request: &Request<T>) -> Self {
+        request
+            .metadata()
+            .get(DEADLINE_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_deadline)
+            .map_or_else(Deadline::none, Deadline::after)
+    }
+
+    /// Returns `true` once the caller gave up.
+    pub fn is_expired(&self) -> bool {
+        matches!(self.0, Some(deadline) if deadline <= Instant::now())
+    }
+
+    /// Returns `timeout`, shortened to the time left before the deadline.
+    pub fn bound(&self, timeout: Duration) -> Duration {
+        match self.0 {
+            Some(deadline) => timeout.min(deadline.saturating_duration_since(Instant::now())),
+            None => timeout,
+        }
+    }
+
+    /// Returns the deadline in milliseconds since the unix epoch, as sent to the nodes, 0 if
+    /// there is none.
+    pub fn unix_millis(&self) -> i64 {
+        let deadline = match self.0 {
+            Some(deadline) => deadline,
+            None => return 0,
+        };
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        (SystemTime::now() + remaining)
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_millis() as i64)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_request() {
+        let mut request = Request::new(());
+        assert_eq!(Deadline::from_request(&request), Deadline::none());
+
+        request
+            .metadata_mut()
+            .insert(DEADLINE_METADATA, "60000".parse().unwrap());
+        let deadline = Deadline::from_request(&request);
+        assert!(!deadline.is_expired());
+        assert!(deadline.bound(Duration::from_secs(120)) <= Duration::from_secs(60));
+        assert_eq!(
+            deadline.bound(Duration::from_secs(1)),
+            Duration::from_secs(1)
+        );
+        assert!(deadline.unix_millis() > 0);
+    }
+
+    #[test]
+    fn test_expired() {
+        let deadline = Deadline::after(Duration::ZERO);
+        assert!(deadline.is_expired());
+        assert_eq!(deadline.bound(Duration::from_secs(1)), Duration::ZERO);
+        assert!(!Deadline::none().is_expired());
+        assert_eq!(Deadline::none().unix_millis(), 0);
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{error, info};
+use proto::scheduler::{ClusterSnapshot, InstancePlacement};
+use serde_derive::Serialize;
+use tokio::task::JoinHandle;
+
+use crate::{
+    handler::{HandlerContext, Middleware},
+    manager::Manager,
+    queue::EventQueue,
+    Event, EventKind,
+};
+
+/// The number of events kept by the `EventHistory`.
+const HISTORY_SIZE: usize = 100;
+
+/// An event handled by the scheduler.
+///
+/// Properties:
+///
+/// * `kind`: The kind of the event.
+/// * `handled_at`: When the handler returned, in milliseconds since the unix epoch.
+/// * `duration_ms`: The time taken by the handler, in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRecord {
+    pub kind: String,
+    pub handled_at: u128,
+    pub duration_ms: f64,
+}
+
+/// `EventHistory` is a middleware keeping the last events handled, served by the debug server.
+#[derive(Debug, Clone, Default)]
+pub struct EventHistory {
+    records: Arc<Mutex<VecDeque<EventRecord>>>,
+}
+
+impl EventHistory {
+    /// Returns the events kept, the most recent last.
+    pub fn records(&self) -> Vec<EventRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[tonic::async_trait]
+impl Middleware for EventHistory {
+    async fn after(&self, kind: EventKind, elapsed: Duration, _context: &HandlerContext) {
+        let record = EventRecord {
+            kind: format!("{:?}", kind),
+            handled_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+            duration_ms: elapsed.as_secs_f64() * 1000.0,
+        };
+
+        let mut records = self.records.lock().unwrap();
+        if records.len() == HISTORY_SIZE {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+}
+
+/// A node in the state served by the debug server.
+#[derive(Debug, Serialize)]
+pub struct NodeView {
+    pub id: String,
+    pub connected: bool,
+    /// No new instance is placed on the node
+    pub cordoned: bool,
+    /// The commands of the node are paused after it failed to take several of them
+    pub suspect: bool,
+    /// The optional operations the agent of the node performs, all of them if empty
+    pub features: Vec<String>,
+    pub status: Option<String>,
+    pub status_description: Option<String>,
+}
+
+/// An instance placement in the state served by the debug server.
+#[derive(Debug, Serialize)]
+pub struct PlacementView {
+    pub instance_id: String,
+    pub node_id: String,
+    pub status: Option<String>,
+}
+
+impl From<InstancePlacement> for PlacementView {
+    fn from(placement: InstancePlacement) -> Self {
+        PlacementView {
+            status: placement
+                .status
+                .as_ref()
+                .map(|status| format!("{:?}", status.status())),
+            instance_id: placement.instance_id,
+            node_id: placement.node_id,
+        }
+    }
+}
+
+/// The state of the scheduler served by the debug server.
+#[derive(Debug, Serialize)]
+pub struct StateView {
+    pub nodes: Vec<NodeView>,
+    pub placements: Vec<PlacementView>,
+    pub pending_queue_length: usize,
+    pub pending: Vec<PlacementView>,
+}
+
+impl From<ClusterSnapshot> for StateView {
+    fn from(snapshot: ClusterSnapshot) -> Self {
+        StateView {
+            nodes: snapshot
+                .nodes
+                .into_iter()
+                .map(|node| NodeView {
+                    features: node
+                        .features()
+                        .map(|feature| format!("{:?}", feature))
+                        .collect(),
+                    status: node
+                        .status
+                        .as_ref()
+                        .map(|status| format!("{:?}", status.status())),
+                    status_description: node.status.map(|status| status.status_description),
+                    id: node.id,
+                    connected: node.connected,
+                    cordoned: node.cordoned,
+                    suspect: node.suspect,
+                })
+                .collect(),
+            placements: snapshot.placements.into_iter().map(Into::into).collect(),
+            pending_queue_length: snapshot.pending.len(),
+            pending: snapshot.pending.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Returns a JSON response.
+fn json_response(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Returns the JSON error response of `status`.
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    let body = serde_json::json!({ "error": message }).to_string();
+    json_response(status, body)
+}
+
+/// Handles a request to the debug server:
+/// - `GET /state` returns the nodes, the placements and the pending instances
+/// - `GET /events` returns the last events handled
+/// - `GET /queue` returns the depth of the event queue and the number of events it rejected
+async fn handle(
+    request: Request<Body>,
+    events: EventQueue,
+    history: EventHistory,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET {
+        return Ok(error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "the debug server is read-only",
+        ));
+    }
+
+    let body = match request.uri().path() {
+        "/state" => {
+            let (tx, rx) = Manager::create_oneshot_channel();
+            if let Err(status) = events.try_send(Event::ClusterSnapshot(tx)) {
+                return Ok(error_response(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    status.message(),
+                ));
+            }
+            match rx.await {
+                Ok(Ok(snapshot)) => serde_json::to_string(&StateView::from(snapshot.into_inner())),
+                _ => {
+                    return Ok(error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "could not get the state of the scheduler",
+                    ))
+                }
+            }
+        }
+        "/events" => serde_json::to_string(&history.records()),
+        "/queue" => serde_json::to_string(&events.metrics()),
+        _ => return Ok(error_response(StatusCode::NOT_FOUND, "not found")),
+    };
+
+    Ok(match body {
+        Ok(body) => json_response(StatusCode::OK, body),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+    })
+}
+
+/// Serves the read-only debug HTTP server.
+///
+/// Arguments:
+///
+/// * `address`: The address the server listens on.
+/// * `events`: The event queue, used to get the state of the scheduler and its depth.
+/// * `history`: The middleware keeping the last events handled.
+///
+/// Returns:
+///
+/// A JoinHandle<()>
+pub fn serve(address: SocketAddr, events: EventQueue, history: EventHistory) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let events = events.clone();
+            let history = history.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(request, events.clone(), history.clone())
+                }))
+            }
+        });
+
+        info!("started debug server at {}", address);
+        if let Err(err) = Server::bind(&address).serve(make_service).await {
+            error!("debug server failed: {}", err);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{InstanceStatus, NodeSnapshot, Status};
+
+    use super::*;
+
+    #[test]
+    fn test_state_view() {
+        let snapshot = ClusterSnapshot {
+            nodes: vec![NodeSnapshot {
+                id: "a".to_string(),
+                connected: true,
+                status: None,
+                capabilities: None,
+                cordoned: false,
+                suspect: false,
+                features: vec![],
+            }],
+            placements: vec![InstancePlacement {
+                instance_id: "1".to_string(),
+                node_id: "a".to_string(),
+                status: Some(InstanceStatus {
+                    status: Status::Running.into(),
+                    ..Default::default()
+                }),
+            }],
+            pending: vec![InstancePlacement::default()],
+        };
+
+        let view = serde_json::to_value(StateView::from(snapshot)).unwrap();
+        assert_eq!(view["nodes"][0]["connected"], true);
+        assert_eq!(view["placements"][0]["status"], "Running");
+        assert_eq!(view["pending_queue_length"], 1);
+    }
+}
@@ -0,0 +1,283 @@
+use log::{debug, info, warn};
+use proto::agent::Signal;
+use tonic::Response;
+
+use super::{EventHandler, HandlerContext};
+use crate::{retry::is_transient, Event, EventKind};
+
+/// Places a new instance on a node, its statuses are streamed back to the caller. A creation
+/// failing for a transient reason is queued again after a backoff, until the attempts or the
+/// retry budget are exhausted, or the deadline of the caller expires.
+pub struct InstanceCreateHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceCreateHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceCreate
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceCreate(instance, tx, deadline) = event else {
+            return;
+        };
+        info!("received instance create event : {:?}", instance);
+
+        // the caller is gone, there is nobody to create the instance for anymore
+        if tx.is_closed() {
+            context.create_attempts.remove(&instance.id);
+            return;
+        }
+
+        let id = instance.id.clone();
+        let status = match context
+            .connections
+            .create((*instance).clone(), tx.clone())
+            .await
+        {
+            Ok(_) => {
+                context.create_attempts.remove(&id);
+                context.retry_budget.record_success();
+                return;
+            }
+            Err(status) => status,
+        };
+
+        let attempts = context.create_attempts.entry(id.clone()).or_insert(0);
+        *attempts += 1;
+        // the retries stop once the caller gave up
+        if is_transient(&status) && !deadline.is_expired() {
+            context.retry_budget.record_failure();
+
+            if context.retry_policy.should_retry(*attempts) && context.retry_budget.can_retry() {
+                let backoff = context.retry_policy.backoff(*attempts);
+                warn!(
+                    "creation of instance {} failed ({}), retrying in {:?}",
+                    id,
+                    status.message(),
+                    backoff
+                );
+
+                let events = context.events.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(backoff).await;
+                    // the retry is dropped if the scheduler is overloaded, the caller is told so
+                    if let Err(status) =
+                        events.try_send(Event::InstanceCreate(instance, tx.clone(), deadline))
+                    {
+                        _ = tx.send(Err(status)).await;
+                    }
+                });
+                return;
+            }
+        }
+
+        context.create_attempts.remove(&id);
+        _ = tx.send(Err(status)).await;
+    }
+}
+
+/// Sends a signal to an instance, one handler is registered for each of start, stop, destroy
+/// and restart.
+pub struct InstanceSignalHandler {
+    kind: EventKind,
+    signal: Signal,
+}
+
+impl InstanceSignalHandler {
+    /// `new` creates the handler of the `kind` events, which sends `signal` to the instance.
+    pub fn new(kind: EventKind, signal: Signal) -> Self {
+        InstanceSignalHandler { kind, signal }
+    }
+}
+
+#[tonic::async_trait]
+impl EventHandler for InstanceSignalHandler {
+    fn kind(&self) -> EventKind {
+        self.kind
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let (id, tx) = match event {
+            Event::InstanceStart(id, tx, _)
+            | Event::InstanceStop(id, tx, _)
+            | Event::InstanceDestroy(id, tx, _)
+            | Event::InstanceRestart(id, tx, _) => (id, tx),
+            _ => return,
+        };
+        info!("received {:?} event : {:?}", self.kind, id);
+
+        // the caller gave up while the event was queued, the instance is left as it is
+        if tx.is_closed() {
+            return;
+        }
+
+        let result = context.connections.signal(&id, self.signal).await;
+        _ = tx.send(result.map(Response::new));
+    }
+}
+
+/// Stops an instance evicted by the controller, the eviction is attached to its next statuses.
+pub struct InstanceEvictHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceEvictHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceEvict
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceEvict(request, tx, _) = event else {
+            return;
+        };
+        info!("received instance evict event : {:?}", request);
+
+        let result = match request.eviction {
+            Some(eviction) => context.connections.evict(&request.id, eviction).await,
+            None => Err(tonic::Status::invalid_argument(format!(
+                "the eviction of instance {} has no reason",
+                request.id
+            ))),
+        };
+        _ = tx.send(result.map(Response::new));
+    }
+}
+
+/// Answers the full view of the scheduler.
+pub struct ClusterSnapshotHandler;
+
+#[tonic::async_trait]
+impl EventHandler for ClusterSnapshotHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::ClusterSnapshot
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::ClusterSnapshot(tx) = event else {
+            return;
+        };
+        info!("received cluster snapshot event");
+
+        _ = tx.send(Ok(Response::new(context.connections.snapshot())));
+    }
+}
+
+/// Pulls an image on the nodes ahead of a deployment, the statuses of the pulls are streamed
+/// back to the caller until every node pulled it or failed to.
+pub struct ImagePullHandler;
+
+#[tonic::async_trait]
+impl EventHandler for ImagePullHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::ImagePull
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::ImagePull(request, tx, _) = event else {
+            return;
+        };
+        info!("received image pull event : {:?}", request);
+
+        if let Err(status) = context.connections.pull(request, tx.clone()).await {
+            _ = tx.send(Err(status)).await;
+        }
+    }
+}
+
+/// Checkpoints the memory of an instance on its node, the result is sent back once the node
+/// wrote the checkpoint or failed to.
+pub struct InstanceCheckpointHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceCheckpointHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceCheckpoint
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceCheckpoint(request, tx, _) = event else {
+            return;
+        };
+        info!("received instance checkpoint event : {:?}", request);
+
+        context.connections.checkpoint(request, tx).await;
+    }
+}
+
+/// Migrates a running instance to another node with its memory, the caller is answered once the
+/// instance is checkpointed and sent to the other node.
+pub struct InstanceMigrateHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceMigrateHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceMigrate
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceMigrate(request, tx, _) = event else {
+            return;
+        };
+        info!("received instance migrate event : {:?}", request);
+
+        context.connections.live_migrate(request, tx).await;
+    }
+}
+
+/// Streams the output of an instance from its node, the chunks are streamed back to the caller
+/// until the last one.
+pub struct InstanceLogsHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceLogsHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceLogs
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceLogs(request, tx, opened, _) = event else {
+            return;
+        };
+        info!("received instance logs event : {:?}", request);
+
+        _ = opened.send(context.connections.logs(request, tx).await);
+    }
+}
+
+/// Starts an exec session on the node of an instance, its output is streamed back to the caller
+/// until the command exits.
+pub struct InstanceExecHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceExecHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceExec
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceExec(start, tx, opened, _) = event else {
+            return;
+        };
+        info!("received instance exec event : {:?}", start);
+
+        _ = opened.send(context.connections.exec(start, tx).await);
+    }
+}
+
+/// Forwards an input of the caller to its exec session.
+pub struct InstanceExecInputHandler;
+
+#[tonic::async_trait]
+impl EventHandler for InstanceExecInputHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::InstanceExecInput
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::InstanceExecInput(input) = event else {
+            return;
+        };
+        debug!("received input of exec session {}", input.session_id);
+
+        context.connections.exec_input(input).await;
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use log::{debug, warn};
+use proto::scheduler::{
+    instance_service_server::InstanceServiceServer, node_service_server::NodeServiceServer,
+};
+use tonic_health::server::HealthReporter;
+
+use super::{HandlerContext, Middleware};
+use crate::{
+    instance_listener::InstanceListener, lifecycle::NodeConnections, node_listener::NodeListener,
+    Event, EventKind,
+};
+
+/// Logs every event received.
+pub struct LoggingMiddleware;
+
+#[tonic::async_trait]
+impl Middleware for LoggingMiddleware {
+    async fn before(&self, event: &Event, _context: &HandlerContext) {
+        debug!("received event : {:?}", event);
+    }
+}
+
+/// Logs the time taken by each handler, with a warning when it is slower than `slow`.
+pub struct TimingMiddleware {
+    slow: Duration,
+}
+
+impl TimingMiddleware {
+    pub fn new(slow: Duration) -> Self {
+        TimingMiddleware { slow }
+    }
+}
+
+#[tonic::async_trait]
+impl Middleware for TimingMiddleware {
+    async fn after(&self, kind: EventKind, elapsed: Duration, _context: &HandlerContext) {
+        if elapsed > self.slow {
+            warn!("handling a {:?} event took {:?}", kind, elapsed);
+        } else {
+            debug!("handled a {:?} event in {:?}", kind, elapsed);
+        }
+    }
+}
+
+/// Updates the health of the services after each event.
+pub struct HealthMiddleware {
+    reporter: HealthReporter,
+}
+
+impl HealthMiddleware {
+    pub fn new(reporter: HealthReporter) -> Self {
+        HealthMiddleware { reporter }
+    }
+
+    /// Reports the instance service as serving only while a node is connected, as the instances
+    /// can't be placed otherwise. The node service is always serving.
+    ///
+    /// Arguments:
+    ///
+    /// * `reporter`: The reporter of the `grpc.health.v1.Health` service
+    /// * `connections`: The lifecycle streams of the nodes
+    pub async fn report(reporter: &mut HealthReporter, connections: &NodeConnections) {
+        reporter
+            .set_serving::<NodeServiceServer<NodeListener>>()
+            .await;
+
+        if connections.has_nodes() {
+            reporter
+                .set_serving::<InstanceServiceServer<InstanceListener>>()
+                .await;
+        } else {
+            reporter
+                .set_not_serving::<InstanceServiceServer<InstanceListener>>()
+                .await;
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Middleware for HealthMiddleware {
+    async fn after(&self, _kind: EventKind, _elapsed: Duration, context: &HandlerContext) {
+        Self::report(&mut self.reporter.clone(), &context.connections).await;
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use proto::agent::Signal;
+
+use crate::{
+    deadline::Deadline,
+    lifecycle::NodeConnections,
+    queue::EventQueue,
+    retry::{RetryBudget, RetryPolicy},
+    skew::VersionPolicy,
+    throttle::StatusThrottle,
+    Event, EventKind,
+};
+
+pub mod instance;
+pub mod middleware;
+pub mod node;
+
+/// `HandlerContext` is the state shared by the event handlers and the middlewares.
+///
+/// Properties:
+///
+/// * `connections`: The lifecycle streams of the nodes and the placements of the instances.
+/// * `events`: The sender of the event bus, to queue the retries of the events.
+/// * `retry_policy`: The delays and number of attempts of the retries.
+/// * `retry_budget`: The budget stopping the retries when most calls fail.
+/// * `create_attempts`: The failed attempts of each instance being created.
+/// * `status_throttle`: The interval the nodes send their statuses at for the load of the queue.
+/// * `version_policy`: The range of agent versions accepted at registration.
+#[derive(Debug)]
+pub struct HandlerContext {
+    pub connections: NodeConnections,
+    pub events: EventQueue,
+    pub retry_policy: RetryPolicy,
+    pub retry_budget: RetryBudget,
+    pub create_attempts: HashMap<String, u32>,
+    pub status_throttle: StatusThrottle,
+    pub version_policy: VersionPolicy,
+}
+
+impl HandlerContext {
+    pub fn new(
+        connections: NodeConnections,
+        events: EventQueue,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        HandlerContext {
+            connections,
+            events,
+            retry_policy,
+            retry_budget: RetryBudget::default(),
+            create_attempts: HashMap::new(),
+            status_throttle: StatusThrottle::default(),
+            version_policy: VersionPolicy::default(),
+        }
+    }
+
+    /// Throttles the statuses of the nodes with the given intervals instead of the default ones.
+    pub fn with_status_throttle(mut self, status_throttle: StatusThrottle) -> Self {
+        self.status_throttle = status_throttle;
+        self
+    }
+
+    /// Checks the agent versions of the registering nodes against the given policy instead of
+    /// accepting every version.
+    pub fn with_version_policy(mut self, version_policy: VersionPolicy) -> Self {
+        self.version_policy = version_policy;
+        self
+    }
+
+    /// Returns the interval the nodes send their statuses at for the current load.
+    pub fn status_interval(&self) -> Duration {
+        self.status_throttle.interval(&self.events.metrics())
+    }
+}
+
+/// An `EventHandler` handles the events of a single kind.
+#[tonic::async_trait]
+pub trait EventHandler: Send + Sync {
+    /// The kind of the events handled, the registry only dispatches events of this kind.
+    fn kind(&self) -> EventKind;
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext);
+}
+
+/// A `Middleware` runs around the dispatch of every event, in the order of registration before
+/// the handler and in the reverse order after it.
+#[tonic::async_trait]
+pub trait Middleware: Send + Sync {
+    async fn before(&self, _event: &Event, _context: &HandlerContext) {}
+
+    async fn after(&self, _kind: EventKind, _elapsed: Duration, _context: &HandlerContext) {}
+}
+
+/// `EventRegistry` maps each kind of event to its handler and runs the middlewares around them.
+#[derive(Default)]
+pub struct EventRegistry {
+    handlers: HashMap<EventKind, Box<dyn EventHandler>>,
+    middlewares: Vec<Box<dyn Middleware>>,
+}
+
+impl EventRegistry {
+    /// `new` creates a registry with the handlers of all the scheduler events and no middleware.
+    pub fn new() -> Self {
+        let mut registry = EventRegistry::default();
+        registry
+            .register(instance::InstanceCreateHandler)
+            .register(instance::InstanceSignalHandler::new(
+                EventKind::InstanceStart,
+                Signal::Start,
+            ))
+            .register(instance::InstanceSignalHandler::new(
+                EventKind::InstanceStop,
+                Signal::Stop,
+            ))
+            .register(instance::InstanceSignalHandler::new(
+                EventKind::InstanceDestroy,
+                Signal::Kill,
+            ))
+            .register(instance::InstanceSignalHandler::new(
+                EventKind::InstanceRestart,
+                Signal::Restart,
+            ))
+            .register(instance::InstanceEvictHandler)
+            .register(instance::ClusterSnapshotHandler)
+            .register(instance::ImagePullHandler)
+            .register(instance::InstanceCheckpointHandler)
+            .register(instance::InstanceMigrateHandler)
+            .register(instance::InstanceLogsHandler)
+            .register(instance::InstanceExecHandler)
+            .register(instance::InstanceExecInputHandler)
+            .register(node::RebalanceHandler)
+            .register(node::NodeRegisterHandler)
+            .register(node::NodeUnregisterHandler)
+            .register(node::NodeStatusHandler)
+            .register(node::NodeCordonHandler)
+            .register(node::NodeConnectedHandler)
+            .register(node::NodeDisconnectedHandler)
+            .register(node::NodeInstanceStatusHandler)
+            .register(node::NodeImagePullStatusHandler)
+            .register(node::NodeCheckpointStatusHandler)
+            .register(node::NodeLogsHandler)
+            .register(node::NodeExecOutputHandler);
+        registry
+    }
+
+    /// Registers the handler of a kind of events, replacing the previous one.
+    pub fn register(&mut self, handler: impl EventHandler + 'static) -> &mut Self {
+        self.handlers.insert(handler.kind(), Box::new(handler));
+        self
+    }
+
+    /// Adds a middleware run around the dispatch of every event.
+    pub fn with_middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    /// Dispatches an event to the handler of its kind, between the middlewares. The commands the
+    /// handler sends to the nodes are bounded by the deadline of the event.
+    ///
+    /// Arguments:
+    ///
+    /// * `event`: The event to handle.
+    /// * `context`: The state shared by the handlers.
+    pub async fn dispatch(&self, event: Event, context: &mut HandlerContext) {
+        let kind = event.kind();
+        let handler = match self.handlers.get(&kind) {
+            Some(handler) => handler,
+            None => {
+                warn!("no handler registered for {:?} events", kind);
+                return;
+            }
+        };
+
+        for middleware in &self.middlewares {
+            middleware.before(&event, context).await;
+        }
+
+        let start = Instant::now();
+        context.connections.set_deadline(event.deadline());
+        handler.handle(event, context).await;
+        context.connections.set_deadline(Deadline::none());
+        let elapsed = start.elapsed();
+
+        for middleware in self.middlewares.iter().rev() {
+            middleware.after(kind, elapsed, context).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tonic::Response;
+
+    use super::*;
+    use crate::manager::Manager;
+
+    /// Records the calls of the middleware hooks.
+    struct Recorder(&'static str, Arc<Mutex<Vec<String>>>);
+
+    #[tonic::async_trait]
+    impl Middleware for Recorder {
+        async fn before(&self, event: &Event, _context: &HandlerContext) {
+            let call = format!("{} before {:?}", self.0, event.kind());
+            self.1.lock().unwrap().push(call);
+        }
+
+        async fn after(&self, kind: EventKind, _elapsed: Duration, _context: &HandlerContext) {
+            let call = format!("{} after {:?}", self.0, kind);
+            self.1.lock().unwrap().push(call);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut registry = EventRegistry::new();
+        registry
+            .with_middleware(Recorder("first", calls.clone()))
+            .with_middleware(Recorder("second", calls.clone()));
+        let (events, _) = EventQueue::channel(1);
+        let mut context = HandlerContext::new(
+            NodeConnections::new(Duration::from_secs(1)),
+            events,
+            RetryPolicy {
+                max_attempts: 1,
+                initial_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            },
+        );
+
+        let (tx, rx) = Manager::create_oneshot_channel();
+        registry
+            .dispatch(Event::ClusterSnapshot(tx), &mut context)
+            .await;
+
+        let snapshot = rx.await.unwrap().map(Response::into_inner).unwrap();
+        assert!(snapshot.nodes.is_empty());
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "first before ClusterSnapshot",
+                "second before ClusterSnapshot",
+                "second after ClusterSnapshot",
+                "first after ClusterSnapshot",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_retry() {
+        let registry = EventRegistry::new();
+        let (events, mut queued) = EventQueue::channel(1);
+        let mut context = HandlerContext::new(
+            NodeConnections::new(Duration::from_secs(1)),
+            events,
+            RetryPolicy {
+                max_attempts: 2,
+                initial_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            },
+        );
+
+        // no node is connected, the creation is queued again once
+        let (tx, mut rx) = Manager::create_mpsc_channel();
+        let instance = proto::scheduler::Instance {
+            id: "1".to_string(),
+            ..Default::default()
+        };
+        registry
+            .dispatch(
+                Event::InstanceCreate(Box::new(instance), tx, Deadline::none()),
+                &mut context,
+            )
+            .await;
+        let (retry, _) = queued.recv().await.unwrap();
+        assert_eq!(retry.kind(), EventKind::InstanceCreate);
+
+        registry.dispatch(retry, &mut context).await;
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+        assert!(context.create_attempts.is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+use log::{debug, info, warn};
+use proto::{
+    scheduler::{Feature, NodeRegisterRequest, NodeRegisterResponse, NodeUnregisterResponse},
+    version::{self, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION},
+};
+use tonic::Response;
+
+use super::{EventHandler, HandlerContext};
+use crate::{
+    skew::{Verdict, VersionPolicy},
+    Event, EventKind,
+};
+
+/// Registers a node after checking it speaks a protocol version in common with the scheduler
+/// and runs a supported agent version, the other nodes being rejected or quarantined, i.e.
+/// registered cordoned. It keeps the capabilities, features and platform the node reports to
+/// place on it only the instances it can run, and to send it only the operations its agent
+/// performs. The node is told the interval to send its statuses at.
+pub struct NodeRegisterHandler;
+
+impl NodeRegisterHandler {
+    /// Checks that a registering node speaks a protocol version in common with the scheduler, and
+    /// that the version of its agent is supported.
+    ///
+    /// Arguments:
+    ///
+    /// * `request`: The register request of the node
+    /// * `policy`: The range of agent versions supported by the scheduler
+    ///
+    /// Returns:
+    ///
+    /// The response with the version of the scheduler and the negotiated protocol version, and
+    /// whether the node is quarantined.
+    #[allow(clippy::result_large_err)]
+    fn register(
+        request: &NodeRegisterRequest,
+        policy: &VersionPolicy,
+    ) -> Result<(Response<NodeRegisterResponse>, bool), tonic::Status> {
+        if request.capabilities.is_some() && request.id.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "a node reporting its capabilities must send its id",
+            ));
+        }
+
+        let protocol_version = match &request.version {
+            Some(node) => version::negotiate(node.min_protocol_version, node.max_protocol_version)
+                .ok_or_else(|| {
+                    tonic::Status::failed_precondition(format!(
+                        "node {} speaks protocol versions {} to {}, the scheduler {} to {}",
+                        node.component_version,
+                        node.min_protocol_version,
+                        node.max_protocol_version,
+                        MIN_PROTOCOL_VERSION,
+                        PROTOCOL_VERSION
+                    ))
+                })?,
+            None => {
+                warn!(
+                    "node registered without version, assuming protocol version {}",
+                    MIN_PROTOCOL_VERSION
+                );
+                MIN_PROTOCOL_VERSION
+            }
+        };
+
+        let component_version = request
+            .version
+            .as_ref()
+            .map(|node| node.component_version.as_str());
+        let description = match policy.check(&request.id, component_version) {
+            Verdict::Accept => None,
+            Verdict::Quarantine(reason) if !request.id.is_empty() => {
+                warn!("{}, the node is quarantined", reason);
+                Some(format!(
+                    "{}, the node is quarantined: no instance is placed on it until it is \
+                     uncordoned",
+                    reason
+                ))
+            }
+            Verdict::Quarantine(reason) | Verdict::Reject(reason) => {
+                warn!("{}, the registration is rejected", reason);
+                return Err(tonic::Status::failed_precondition(format!(
+                    "{}, upgrade the agent of the node to a supported version",
+                    reason
+                )));
+            }
+        };
+
+        let quarantined = description.is_some();
+        let response = Response::new(NodeRegisterResponse {
+            description: description.unwrap_or_default(),
+            version: Some(version::version_info(env!("CARGO_PKG_VERSION"))),
+            protocol_version,
+            ..Default::default()
+        });
+        Ok((response, quarantined))
+    }
+}
+
+#[tonic::async_trait]
+impl EventHandler for NodeRegisterHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeRegister
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeRegister(request, tx) = event else {
+            return;
+        };
+        info!("received node register event : {:?}", request);
+
+        let interval = context.status_interval();
+        let response = match Self::register(&request, &context.version_policy) {
+            Ok((mut response, quarantined)) => {
+                response.get_mut().status_interval_ms = interval.as_millis() as u32;
+                if quarantined {
+                    context.connections.cordon(request.id.clone(), true);
+                }
+                Ok(response)
+            }
+            Err(status) => Err(status),
+        };
+        if response.is_ok() && !request.id.is_empty() {
+            context
+                .connections
+                .register_status_interval(request.id.clone(), interval);
+            context.connections.register_features(
+                request.id.clone(),
+                request
+                    .features()
+                    .filter(|feature| *feature != Feature::Unspecified)
+                    .collect(),
+            );
+            context
+                .connections
+                .register_platform(request.id.clone(), request.platform);
+            context
+                .connections
+                .register_capabilities(request.id, request.capabilities);
+        }
+        _ = tx.send(response);
+    }
+}
+
+/// Unregisters a node.
+pub struct NodeUnregisterHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeUnregisterHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeUnregister
+    }
+
+    async fn handle(&self, event: Event, _context: &mut HandlerContext) {
+        let Event::NodeUnregister(request, tx) = event else {
+            return;
+        };
+        info!("received node unregister event : {:?}", request);
+
+        _ = tx.send(Ok(Response::new(NodeUnregisterResponse::default())));
+    }
+}
+
+/// Keeps the last status sent by a node, and tells the node to slow down its statuses when the
+/// scheduler is loaded, or to speed them up again.
+pub struct NodeStatusHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeStatusHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeStatus
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeStatus(status, tx) = event else {
+            return;
+        };
+        info!("received node status event : {:?}", status);
+
+        let interval = context.status_interval();
+        if let Err(err) = context
+            .connections
+            .throttle_status(&status.id, interval)
+            .await
+        {
+            debug!("node {} not told its status interval: {}", status.id, err);
+        }
+        context.connections.update_node_status(status);
+        _ = tx.send(Ok(())).await;
+    }
+}
+
+/// Moves an instance from the most loaded node to a less loaded one, queued periodically when the
+/// rebalancing is enabled.
+pub struct RebalanceHandler;
+
+#[tonic::async_trait]
+impl EventHandler for RebalanceHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::Rebalance
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::Rebalance(threshold) = event else {
+            return;
+        };
+        debug!("received rebalance event");
+
+        match context.connections.rebalance(threshold).await {
+            Ok(Some(id)) => info!("moved instance {} to rebalance the nodes", id),
+            Ok(None) => debug!("the nodes are balanced, or no instance can be moved"),
+            Err(err) => warn!("could not rebalance the nodes: {}", err.message()),
+        }
+    }
+}
+
+/// Stops placing new instances on a node, or places them on it again.
+pub struct NodeCordonHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeCordonHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeCordon
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeCordon(request, tx) = event else {
+            return;
+        };
+        info!("received node cordon event : {:?}", request);
+
+        context
+            .connections
+            .cordon(request.node_id, request.cordoned);
+        _ = tx.send(Ok(Response::new(())));
+    }
+}
+
+/// Registers the lifecycle stream opened by a node.
+pub struct NodeConnectedHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeConnectedHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeConnected
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeConnected(node_id, sender) = event else {
+            return;
+        };
+        context.connections.connect(node_id, sender);
+    }
+}
+
+/// Forgets a node whose lifecycle stream is closed.
+pub struct NodeDisconnectedHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeDisconnectedHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeDisconnected
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeDisconnected(node_id) = event else {
+            return;
+        };
+        context.connections.disconnect(&node_id).await;
+    }
+}
+
+/// Forwards the status of an instance sent by its node.
+pub struct NodeInstanceStatusHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeInstanceStatusHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeInstanceStatus
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeInstanceStatus(node_id, status) = event else {
+            return;
+        };
+        debug!(
+            "received instance status from node {} : {:?}",
+            node_id, status
+        );
+
+        context.connections.report(&node_id, status).await;
+    }
+}
+
+/// Forwards the progress of the pull of an image sent by a node.
+pub struct NodeImagePullStatusHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeImagePullStatusHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeImagePullStatus
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeImagePullStatus(node_id, status) = event else {
+            return;
+        };
+        debug!(
+            "received image pull status from node {} : {:?}",
+            node_id, status
+        );
+
+        context.connections.report_pull(&node_id, status).await;
+    }
+}
+
+/// Answers the checkpoint of an instance with the result sent by its node.
+pub struct NodeCheckpointStatusHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeCheckpointStatusHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeCheckpointStatus
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeCheckpointStatus(node_id, status) = event else {
+            return;
+        };
+        debug!(
+            "received checkpoint status from node {} : {:?}",
+            node_id, status
+        );
+
+        context
+            .connections
+            .report_checkpoint(&node_id, status)
+            .await;
+    }
+}
+
+/// Forwards a chunk of the output of an instance sent by its node.
+pub struct NodeLogsHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeLogsHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeLogs
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeLogs(node_id, chunk) = event else {
+            return;
+        };
+        debug!(
+            "received log chunk of stream {} from node {}",
+            chunk.stream_id, node_id
+        );
+
+        context.connections.report_logs(&node_id, chunk).await;
+    }
+}
+
+/// Forwards an output of an exec session sent by its node.
+pub struct NodeExecOutputHandler;
+
+#[tonic::async_trait]
+impl EventHandler for NodeExecOutputHandler {
+    fn kind(&self) -> EventKind {
+        EventKind::NodeExecOutput
+    }
+
+    async fn handle(&self, event: Event, context: &mut HandlerContext) {
+        let Event::NodeExecOutput(node_id, output) = event else {
+            return;
+        };
+        debug!(
+            "received output of exec session {} from node {}",
+            output.session_id, node_id
+        );
+
+        context.connections.report_exec(&node_id, output).await;
+    }
+}
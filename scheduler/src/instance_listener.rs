@@ -1,25 +1,58 @@
-use log::debug;
-use tokio::sync::mpsc;
+use log::{debug, warn};
+use telemetry::grpc::server_context;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
 
+use proto::agent::{
+    exec_input::Input, Checkpoint, CheckpointStatus, ExecInput, ExecOutput, LogChunk, LogRequest,
+};
 use proto::scheduler::{
-    instance_service_server::InstanceService, Instance, InstanceIdentifier, InstanceStatus,
+    instance_service_server::InstanceService, ClusterSnapshot, ImagePullRequest, Instance,
+    InstanceEvictRequest, InstanceIdentifier, InstanceMigrateRequest, InstanceStatus,
+    NodeCordonRequest, NodeImagePullStatus,
 };
+use proto::version::{self, PROTOCOL_METADATA};
 
-use crate::{manager::Manager, Event};
+use crate::{deadline::Deadline, manager::Manager, queue::EventQueue, Event};
 
 #[derive(Debug)]
 pub struct InstanceListener {
-    sender: mpsc::Sender<Event>,
+    sender: EventQueue,
 }
 
 impl InstanceListener {
-    pub fn new(sender: mpsc::Sender<Event>) -> Self {
+    pub fn new(sender: EventQueue) -> Self {
         InstanceListener { sender }
     }
 }
 
+/// The interceptor of the instance service, it refuses the calls of a controller which has no
+/// protocol version in common with the scheduler. The calls without protocol versions are
+/// accepted with a warning.
+#[allow(clippy::result_large_err)]
+pub fn check_protocol(request: Request<()>) -> Result<Request<()>, Status> {
+    let range = request
+        .metadata()
+        .get(PROTOCOL_METADATA)
+        .and_then(|value| value.to_str().ok());
+
+    match range.map(version::parse_protocol_range) {
+        Some(Some((min, max))) => {
+            if version::negotiate(min, max).is_none() {
+                return Err(Status::failed_precondition(format!(
+                    "the controller speaks protocol versions {} to {}, the scheduler {}",
+                    min,
+                    max,
+                    version::protocol_range()
+                )));
+            }
+        }
+        Some(None) => return Err(Status::invalid_argument("invalid protocol versions")),
+        None => warn!("received a call without protocol versions"),
+    }
+    Ok(request)
+}
+
 #[tonic::async_trait]
 impl InstanceService for InstanceListener {
     async fn create(
@@ -27,75 +60,211 @@ impl InstanceService for InstanceListener {
         request: Request<Instance>,
     ) -> Result<Response<Self::CreateStream>, Status> {
         debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Create");
+        let deadline = Deadline::from_request(&request);
         let (tx, rx) = Manager::create_mpsc_channel();
 
-        match self
-            .sender
-            .send(Event::InstanceCreate(request.into_inner(), tx))
-            .await
-        {
-            Ok(_) => {
-                return Ok(Response::new(ReceiverStream::new(rx)));
-            }
-            Err(_) => {
-                return Err(Status::internal("could not send event to manager"));
-            }
-        }
+        self.sender.try_send_with_context(
+            Event::InstanceCreate(Box::new(request.into_inner()), tx, deadline),
+            &cx,
+        )?;
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
     type CreateStream = ReceiverStream<Result<InstanceStatus, Status>>;
 
     async fn start(&self, request: Request<InstanceIdentifier>) -> Result<Response<()>, Status> {
         debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Start");
+        let deadline = Deadline::from_request(&request);
         let (tx, rx) = Manager::create_oneshot_channel();
 
-        match self
-            .sender
-            .send(Event::InstanceStart(request.into_inner().id, tx))
-            .await
-        {
-            Ok(_) => {
-                return rx.await.unwrap();
-            }
-            Err(_) => {
-                return Err(Status::internal("could not send event to manager"));
-            }
-        }
+        self.sender.try_send_with_context(
+            Event::InstanceStart(request.into_inner().id, tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
     }
 
     async fn stop(&self, request: Request<InstanceIdentifier>) -> Result<Response<()>, Status> {
         debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Stop");
+        let deadline = Deadline::from_request(&request);
         let (tx, rx) = Manager::create_oneshot_channel();
 
-        match self
-            .sender
-            .send(Event::InstanceStop(request.into_inner().id, tx))
-            .await
-        {
-            Ok(_) => {
-                return rx.await.unwrap();
-            }
-            Err(_) => {
-                return Err(Status::internal("could not send event to manager"));
-            }
-        }
+        self.sender.try_send_with_context(
+            Event::InstanceStop(request.into_inner().id, tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
     }
 
     async fn destroy(&self, request: Request<InstanceIdentifier>) -> Result<Response<()>, Status> {
         debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Destroy");
+        let deadline = Deadline::from_request(&request);
         let (tx, rx) = Manager::create_oneshot_channel();
 
-        match self
-            .sender
-            .send(Event::InstanceDestroy(request.into_inner().id, tx))
-            .await
-        {
-            Ok(_) => {
-                return rx.await.unwrap();
-            }
-            Err(_) => {
-                return Err(Status::internal("could not send event to manager"));
+        self.sender.try_send_with_context(
+            Event::InstanceDestroy(request.into_inner().id, tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
+    }
+
+    async fn restart(&self, request: Request<InstanceIdentifier>) -> Result<Response<()>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Restart");
+        let deadline = Deadline::from_request(&request);
+        let (tx, rx) = Manager::create_oneshot_channel();
+
+        self.sender.try_send_with_context(
+            Event::InstanceRestart(request.into_inner().id, tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
+    }
+
+    async fn snapshot(&self, request: Request<()>) -> Result<Response<ClusterSnapshot>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Snapshot");
+        let (tx, rx) = Manager::create_oneshot_channel();
+
+        self.sender
+            .try_send_with_context(Event::ClusterSnapshot(tx), &cx)?;
+        rx.await.unwrap()
+    }
+
+    async fn cordon(&self, request: Request<NodeCordonRequest>) -> Result<Response<()>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Cordon");
+        let (tx, rx) = Manager::create_oneshot_channel();
+
+        self.sender
+            .try_send_with_context(Event::NodeCordon(request.into_inner(), tx), &cx)?;
+        rx.await.unwrap()
+    }
+
+    async fn evict(&self, request: Request<InstanceEvictRequest>) -> Result<Response<()>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Evict");
+        let deadline = Deadline::from_request(&request);
+        let (tx, rx) = Manager::create_oneshot_channel();
+
+        self.sender.try_send_with_context(
+            Event::InstanceEvict(request.into_inner(), tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
+    }
+    async fn pull(
+        &self,
+        request: Request<ImagePullRequest>,
+    ) -> Result<Response<Self::PullStream>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Pull");
+        let deadline = Deadline::from_request(&request);
+        let (tx, rx) = Manager::create_mpsc_channel();
+
+        self.sender
+            .try_send_with_context(Event::ImagePull(request.into_inner(), tx, deadline), &cx)?;
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type PullStream = ReceiverStream<Result<NodeImagePullStatus, Status>>;
+
+    async fn checkpoint(
+        &self,
+        request: Request<Checkpoint>,
+    ) -> Result<Response<CheckpointStatus>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Checkpoint");
+        let deadline = Deadline::from_request(&request);
+        let (tx, rx) = Manager::create_oneshot_channel();
+
+        self.sender.try_send_with_context(
+            Event::InstanceCheckpoint(request.into_inner(), tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
+    }
+
+    async fn migrate(
+        &self,
+        request: Request<InstanceMigrateRequest>,
+    ) -> Result<Response<()>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Migrate");
+        let deadline = Deadline::from_request(&request);
+        let (tx, rx) = Manager::create_oneshot_channel();
+
+        self.sender.try_send_with_context(
+            Event::InstanceMigrate(request.into_inner(), tx, deadline),
+            &cx,
+        )?;
+        rx.await.unwrap()
+    }
+
+    async fn logs(
+        &self,
+        request: Request<LogRequest>,
+    ) -> Result<Response<Self::LogsStream>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Logs");
+        let deadline = Deadline::from_request(&request);
+        let (tx, rx) = Manager::create_mpsc_channel();
+        let (opened_tx, opened_rx) = Manager::create_oneshot_channel();
+
+        self.sender.try_send_with_context(
+            Event::InstanceLogs(request.into_inner(), tx, opened_tx, deadline),
+            &cx,
+        )?;
+        opened_rx.await.unwrap()?;
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type LogsStream = ReceiverStream<Result<LogChunk, Status>>;
+
+    async fn exec(
+        &self,
+        request: Request<Streaming<ExecInput>>,
+    ) -> Result<Response<Self::ExecStream>, Status> {
+        debug!("received request: {:?}", request);
+        let cx = server_context(&request, "InstanceService/Exec");
+        let deadline = Deadline::from_request(&request);
+        let mut inputs = request.into_inner();
+        let start = inputs
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("the exec session wasn't started"))?;
+        let (tx, rx) = Manager::create_mpsc_channel();
+        let (opened_tx, opened_rx) = Manager::create_oneshot_channel();
+
+        self.sender
+            .try_send_with_context(Event::InstanceExec(start, tx, opened_tx, deadline), &cx)?;
+        let session_id = opened_rx.await.unwrap()?;
+
+        // forward the next inputs of the caller to the session, its stdin is closed once the
+        // caller closes its stream
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            while let Ok(Some(input)) = inputs.message().await {
+                let input = ExecInput {
+                    session_id: session_id.clone(),
+                    ..input
+                };
+                if sender.send(Event::InstanceExecInput(input)).await.is_err() {
+                    return;
+                }
             }
-        }
+            let close = ExecInput {
+                session_id,
+                input: Some(Input::CloseStdin(true)),
+            };
+            _ = sender.send(Event::InstanceExecInput(close)).await;
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
+
+    type ExecStream = ReceiverStream<Result<ExecOutput, Status>>;
 }
@@ -1,16 +1,35 @@
+use deadline::Deadline;
+use lifecycle::CommandSender;
+use proto::agent;
 use proto::scheduler::{
-    Instance, InstanceStatus, NodeRegisterRequest, NodeRegisterResponse, NodeStatus,
-    NodeUnregisterRequest, NodeUnregisterResponse,
+    ClusterSnapshot, ImagePullRequest, Instance, InstanceEvictRequest, InstanceMigrateRequest,
+    InstanceStatus, NodeCordonRequest, NodeImagePullStatus, NodeRegisterRequest,
+    NodeRegisterResponse, NodeStatus, NodeUnregisterRequest, NodeUnregisterResponse,
 };
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
 use tonic::Response;
 
+pub mod auth;
+pub mod breaker;
+pub mod broadcast;
 pub mod config;
+pub mod deadline;
+pub mod debug;
+pub mod handler;
 pub mod instance_listener;
+pub mod lifecycle;
 pub mod manager;
 pub mod node_listener;
+pub mod parser;
+pub mod pki;
+pub mod queue;
+pub mod rebalance;
+pub mod retry;
+pub mod skew;
 pub mod storage;
+pub mod throttle;
+pub mod tls;
 
 #[derive(Error, Debug)]
 pub enum SchedulerError {
@@ -20,6 +39,10 @@ pub enum SchedulerError {
     ConfigReadError(#[from] confy::ConfyError),
     #[error("invalid grpc address in configuration file")]
     InvalidGrpcAddress,
+    #[error("invalid debug address in configuration file")]
+    InvalidDebugAddress,
+    #[error("invalid agent version in configuration file")]
+    InvalidAgentVersion,
     #[error(transparent)]
     Other(#[from] anyhow::Error),
     #[error("unknown scheduler error")]
@@ -38,21 +61,69 @@ pub type NodeIdentifier = String;
 pub enum Event {
     // Instance events
     InstanceCreate(
-        Instance,
+        Box<Instance>,
         mpsc::Sender<Result<InstanceStatus, tonic::Status>>,
+        Deadline,
     ),
     InstanceStart(
         NodeIdentifier,
         oneshot::Sender<Result<Response<()>, tonic::Status>>,
+        Deadline,
     ),
     InstanceStop(
         NodeIdentifier,
         oneshot::Sender<Result<Response<()>, tonic::Status>>,
+        Deadline,
     ),
     InstanceDestroy(
         NodeIdentifier,
         oneshot::Sender<Result<Response<()>, tonic::Status>>,
+        Deadline,
     ),
+    InstanceRestart(
+        NodeIdentifier,
+        oneshot::Sender<Result<Response<()>, tonic::Status>>,
+        Deadline,
+    ),
+    InstanceEvict(
+        InstanceEvictRequest,
+        oneshot::Sender<Result<Response<()>, tonic::Status>>,
+        Deadline,
+    ),
+    ClusterSnapshot(oneshot::Sender<Result<Response<ClusterSnapshot>, tonic::Status>>),
+    ImagePull(
+        ImagePullRequest,
+        mpsc::Sender<Result<NodeImagePullStatus, tonic::Status>>,
+        Deadline,
+    ),
+    InstanceCheckpoint(
+        agent::Checkpoint,
+        oneshot::Sender<Result<Response<agent::CheckpointStatus>, tonic::Status>>,
+        Deadline,
+    ),
+    InstanceMigrate(
+        InstanceMigrateRequest,
+        oneshot::Sender<Result<Response<()>, tonic::Status>>,
+        Deadline,
+    ),
+    /// Opens a log stream, replies once its node took it
+    InstanceLogs(
+        agent::LogRequest,
+        mpsc::Sender<Result<agent::LogChunk, tonic::Status>>,
+        oneshot::Sender<Result<(), tonic::Status>>,
+        Deadline,
+    ),
+    /// Starts an exec session, replies with its id once its node took it
+    InstanceExec(
+        agent::ExecInput,
+        mpsc::Sender<Result<agent::ExecOutput, tonic::Status>>,
+        oneshot::Sender<Result<String, tonic::Status>>,
+        Deadline,
+    ),
+    /// Forwards an input to a running exec session
+    InstanceExecInput(agent::ExecInput),
+    /// Moves an instance off the most loaded node if the difference of load reaches the threshold
+    Rebalance(u64),
 
     // Node events
     NodeRegister(
@@ -64,4 +135,97 @@ pub enum Event {
         oneshot::Sender<Result<Response<NodeUnregisterResponse>, tonic::Status>>,
     ),
     NodeStatus(NodeStatus, mpsc::Sender<Result<(), tonic::Status>>),
+    NodeCordon(
+        NodeCordonRequest,
+        oneshot::Sender<Result<Response<()>, tonic::Status>>,
+    ),
+    NodeConnected(NodeIdentifier, CommandSender),
+    NodeDisconnected(NodeIdentifier),
+    NodeInstanceStatus(NodeIdentifier, agent::InstanceStatus),
+    NodeImagePullStatus(NodeIdentifier, agent::ImagePullStatus),
+    NodeCheckpointStatus(NodeIdentifier, agent::CheckpointStatus),
+    NodeLogs(NodeIdentifier, agent::LogChunk),
+    NodeExecOutput(NodeIdentifier, agent::ExecOutput),
+}
+
+/// `EventKind` identifies a variant of `Event`, the handlers are registered by kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    InstanceCreate,
+    InstanceStart,
+    InstanceStop,
+    InstanceDestroy,
+    InstanceRestart,
+    InstanceEvict,
+    ClusterSnapshot,
+    ImagePull,
+    InstanceCheckpoint,
+    InstanceMigrate,
+    InstanceLogs,
+    InstanceExec,
+    InstanceExecInput,
+    Rebalance,
+    NodeRegister,
+    NodeUnregister,
+    NodeStatus,
+    NodeCordon,
+    NodeConnected,
+    NodeDisconnected,
+    NodeInstanceStatus,
+    NodeImagePullStatus,
+    NodeCheckpointStatus,
+    NodeLogs,
+    NodeExecOutput,
+}
+
+impl Event {
+    /// Returns the kind of the event.
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::InstanceCreate(..) => EventKind::InstanceCreate,
+            Event::InstanceStart(..) => EventKind::InstanceStart,
+            Event::InstanceStop(..) => EventKind::InstanceStop,
+            Event::InstanceDestroy(..) => EventKind::InstanceDestroy,
+            Event::InstanceRestart(..) => EventKind::InstanceRestart,
+            Event::InstanceEvict(..) => EventKind::InstanceEvict,
+            Event::ClusterSnapshot(..) => EventKind::ClusterSnapshot,
+            Event::ImagePull(..) => EventKind::ImagePull,
+            Event::InstanceCheckpoint(..) => EventKind::InstanceCheckpoint,
+            Event::InstanceMigrate(..) => EventKind::InstanceMigrate,
+            Event::InstanceLogs(..) => EventKind::InstanceLogs,
+            Event::InstanceExec(..) => EventKind::InstanceExec,
+            Event::InstanceExecInput(..) => EventKind::InstanceExecInput,
+            Event::Rebalance(..) => EventKind::Rebalance,
+            Event::NodeRegister(..) => EventKind::NodeRegister,
+            Event::NodeUnregister(..) => EventKind::NodeUnregister,
+            Event::NodeStatus(..) => EventKind::NodeStatus,
+            Event::NodeCordon(..) => EventKind::NodeCordon,
+            Event::NodeConnected(..) => EventKind::NodeConnected,
+            Event::NodeDisconnected(..) => EventKind::NodeDisconnected,
+            Event::NodeInstanceStatus(..) => EventKind::NodeInstanceStatus,
+            Event::NodeImagePullStatus(..) => EventKind::NodeImagePullStatus,
+            Event::NodeCheckpointStatus(..) => EventKind::NodeCheckpointStatus,
+            Event::NodeLogs(..) => EventKind::NodeLogs,
+            Event::NodeExecOutput(..) => EventKind::NodeExecOutput,
+        }
+    }
+
+    /// Returns when the caller of the event gives up on it, `none` for the events which aren't
+    /// requests of the controller.
+    pub fn deadline(&self) -> Deadline {
+        match self {
+            Event::InstanceCreate(.., deadline)
+            | Event::InstanceStart(.., deadline)
+            | Event::InstanceStop(.., deadline)
+            | Event::InstanceDestroy(.., deadline)
+            | Event::InstanceRestart(.., deadline)
+            | Event::InstanceEvict(.., deadline)
+            | Event::ImagePull(.., deadline)
+            | Event::InstanceCheckpoint(.., deadline)
+            | Event::InstanceMigrate(.., deadline)
+            | Event::InstanceLogs(.., deadline)
+            | Event::InstanceExec(.., deadline) => *deadline,
+            _ => Deadline::none(),
+        }
+    }
 }
@@ -0,0 +1,226 @@
+use log::{debug, info};
+use proto::{
+    agent::{self, instance_command::Command},
+    scheduler::Feature,
+};
+use tokio::sync::oneshot;
+use tonic::Response;
+
+use crate::NodeIdentifier;
+
+use super::{MigrateSender, NodeConnections};
+
+/// The sending half of the result of a checkpoint returned to the controller.
+pub type CheckpointSender =
+    oneshot::Sender<Result<Response<agent::CheckpointStatus>, tonic::Status>>;
+
+/// Who waits for a checkpoint being written by a node.
+#[derive(Debug)]
+pub(super) enum CheckpointWaiter {
+    /// The caller of the checkpoint, answered with its result
+    Caller(CheckpointSender),
+    /// The live migration of the instance, restored on `target` once checkpointed
+    Migration {
+        target: NodeIdentifier,
+        reply: MigrateSender,
+    },
+}
+
+impl CheckpointWaiter {
+    /// Answers the waiter that the checkpoint couldn't be written.
+    pub(super) fn fail(self, status: tonic::Status) {
+        match self {
+            CheckpointWaiter::Caller(reply) => _ = reply.send(Err(status)),
+            CheckpointWaiter::Migration { reply, .. } => _ = reply.send(Err(status)),
+        }
+    }
+}
+
+impl NodeConnections {
+    /// Asks the node of an instance to checkpoint its memory, `reply` is answered once the node
+    /// wrote the checkpoint or failed to. Only one checkpoint of an instance with a given name is
+    /// written at a time.
+    ///
+    /// Arguments:
+    ///
+    /// * `request`: The instance to checkpoint and the name of the checkpoint.
+    /// * `reply`: The channel the result of the checkpoint is sent on.
+    pub async fn checkpoint(&mut self, request: agent::Checkpoint, reply: CheckpointSender) {
+        let waiter = CheckpointWaiter::Caller(reply);
+        match self.checkpoint_node(&request) {
+            Ok(node_id) => _ = self.write_checkpoint(&node_id, request, waiter).await,
+            Err(err) => waiter.fail(err),
+        }
+    }
+
+    /// Answers the waiter of a checkpoint with the result sent by the node writing it, or
+    /// restores the instance on the target of its live migration.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node which sent the result.
+    /// * `status`: The result of the checkpoint.
+    pub async fn report_checkpoint(&mut self, node_id: &str, status: agent::CheckpointStatus) {
+        let key = (status.instance_id.clone(), status.name.clone());
+        if self
+            .checkpoints
+            .get(&key)
+            .is_none_or(|(checkpoint_node_id, _)| checkpoint_node_id != node_id)
+        {
+            debug!(
+                "ignored checkpoint {} of instance {} sent by node {}, nobody waits for it",
+                key.1, key.0, node_id
+            );
+            return;
+        }
+        match self.checkpoints.remove(&key) {
+            Some((_, CheckpointWaiter::Caller(reply))) => {
+                _ = reply.send(Ok(Response::new(status)));
+            }
+            Some((_, CheckpointWaiter::Migration { target, reply })) => {
+                let result = self.restore(node_id, &target, status).await;
+                _ = reply.send(result.map(Response::new));
+            }
+            None => {}
+        }
+    }
+
+    /// Returns the node of the instance to checkpoint, if the checkpoint is valid, isn't being
+    /// written already and the agent of the node performs the checkpoints.
+    #[allow(clippy::result_large_err)]
+    pub(super) fn checkpoint_node(
+        &self,
+        request: &agent::Checkpoint,
+    ) -> Result<NodeIdentifier, tonic::Status> {
+        if request.name.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "the checkpoint has no name",
+            ));
+        }
+        let key = (request.instance_id.clone(), request.name.clone());
+        if self.checkpoints.contains_key(&key) {
+            return Err(tonic::Status::already_exists(format!(
+                "checkpoint {} of instance {} is being written",
+                key.1, key.0
+            )));
+        }
+        let node_id = self
+            .placements
+            .get(&key.0)
+            .map(|placement| placement.node_id.clone())
+            .ok_or_else(|| tonic::Status::not_found(format!("instance {} is not placed", key.0)))?;
+        if !self.supports(&node_id, Feature::Checkpoint) {
+            return Err(tonic::Status::failed_precondition(format!(
+                "node {} of instance {} doesn't checkpoint instances",
+                node_id, key.0
+            )));
+        }
+        Ok(node_id)
+    }
+
+    /// Sends a checkpoint command to a node and keeps `waiter` until the node answers, the
+    /// waiter fails right away if the node can't be reached.
+    ///
+    /// Returns:
+    ///
+    /// `true` if the command was sent.
+    pub(super) async fn write_checkpoint(
+        &mut self,
+        node_id: &str,
+        request: agent::Checkpoint,
+        waiter: CheckpointWaiter,
+    ) -> bool {
+        let key = (request.instance_id.clone(), request.name.clone());
+        match self.send(node_id, Command::Checkpoint(request)).await {
+            Ok(()) => {
+                info!(
+                    "node {} is writing checkpoint {} of instance {}",
+                    node_id, key.1, key.0
+                );
+                self.checkpoints.insert(key, (node_id.to_string(), waiter));
+                true
+            }
+            Err(err) => {
+                waiter.fail(err);
+                false
+            }
+        }
+    }
+
+    /// Reports the checkpoints being written by a disconnected node as failed to their waiters.
+    pub(super) fn fail_checkpoints(&mut self, node_id: &str) {
+        let checkpoints: Vec<(String, String)> = self
+            .checkpoints
+            .iter()
+            .filter(|(_, (checkpoint_node_id, _))| checkpoint_node_id == node_id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in checkpoints {
+            if let Some((_, waiter)) = self.checkpoints.remove(&key) {
+                waiter.fail(tonic::Status::unavailable(format!(
+                    "node {} writing the checkpoint disconnected",
+                    node_id
+                )));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use crate::lifecycle::tests::{instance, TIMEOUT};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoint() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+        let (tx, _rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx).await.unwrap();
+        commands.recv().await.unwrap().unwrap();
+
+        let request = agent::Checkpoint {
+            instance_id: "1".to_string(),
+            name: "before-upgrade".to_string(),
+            leave_running: true,
+        };
+        let (reply, result) = oneshot::channel();
+        connections.checkpoint(request.clone(), reply).await;
+        let command = commands.recv().await.unwrap().unwrap().command;
+        assert_eq!(command, Some(Command::Checkpoint(request.clone())));
+
+        // the same checkpoint isn't written twice at a time
+        let (reply, other) = oneshot::channel();
+        connections.checkpoint(request.clone(), reply).await;
+        let err = other.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        let status = agent::CheckpointStatus {
+            instance_id: "1".to_string(),
+            name: "before-upgrade".to_string(),
+            error: String::new(),
+        };
+        // only the node of the instance answers
+        connections.report_checkpoint("b", status.clone()).await;
+        connections.report_checkpoint("a", status.clone()).await;
+        assert_eq!(result.await.unwrap().unwrap().into_inner(), status);
+
+        let (reply, result) = oneshot::channel();
+        connections.checkpoint(request, reply).await;
+        drop(commands);
+        connections.disconnect("a").await;
+        let err = result.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+
+        let (reply, result) = oneshot::channel();
+        connections
+            .checkpoint(agent::Checkpoint::default(), reply)
+            .await;
+        let err = result.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,242 @@
+use log::{debug, warn};
+use proto::{
+    agent::{self, exec_input::Input, exec_output::Output, instance_command::Command},
+    scheduler::Feature,
+};
+use tokio::{sync::mpsc, time::timeout};
+
+use super::NodeConnections;
+
+/// The sending half of the stream of the output of an exec session returned to the controller.
+pub type ExecSender = mpsc::Sender<Result<agent::ExecOutput, tonic::Status>>;
+
+impl NodeConnections {
+    /// Starts an exec session on the node of an instance, the output sent by the node is forwarded
+    /// to `watcher` until the command exits, or until the watcher is gone. Returns the id of the
+    /// session, which its next inputs are sent with.
+    ///
+    /// Arguments:
+    ///
+    /// * `start`: The input starting the session, its session id is set by the scheduler.
+    /// * `watcher`: The stream of the output of the session.
+    pub async fn exec(
+        &mut self,
+        mut start: agent::ExecInput,
+        watcher: ExecSender,
+    ) -> Result<String, tonic::Status> {
+        let Some(Input::Start(command)) = &start.input else {
+            return Err(tonic::Status::invalid_argument(
+                "the first input of an exec session must start it",
+            ));
+        };
+        let node_id = self.stream_node(&command.instance_id, Feature::Exec, "run commands")?;
+        start.session_id = self.next_stream_id();
+
+        let session_id = start.session_id.clone();
+        self.send(&node_id, Command::Exec(start)).await?;
+        debug!("node {} opened exec session {}", node_id, session_id);
+        self.execs.insert(session_id.clone(), (node_id, watcher));
+        Ok(session_id)
+    }
+
+    /// Forwards an input of an exec session to its node. The inputs of the sessions which ended
+    /// are dropped, and the session fails if its node doesn't take the input.
+    ///
+    /// Arguments:
+    ///
+    /// * `input`: The input, with the id of its session.
+    pub async fn exec_input(&mut self, input: agent::ExecInput) {
+        let Some((node_id, _)) = self.execs.get(&input.session_id) else {
+            debug!(
+                "ignored an input of exec session {}, it ended",
+                input.session_id
+            );
+            return;
+        };
+        if matches!(input.input, Some(Input::Start(_))) {
+            debug!("ignored a restart of exec session {}", input.session_id);
+            return;
+        }
+
+        let node_id = node_id.clone();
+        let session_id = input.session_id.clone();
+        if let Err(status) = self.send(&node_id, Command::Exec(input)).await {
+            warn!(
+                "node {} didn't take an input of exec session {}",
+                node_id, session_id
+            );
+            if let Some((_, watcher)) = self.execs.remove(&session_id) {
+                _ = timeout(self.timeout, watcher.send(Err(status))).await;
+            }
+            self.close_stream(&node_id, session_id).await;
+        }
+    }
+
+    /// Forwards an output of an exec session sent by a node to its watcher. The session is
+    /// forgotten once its command exited, and closed on the node once nobody watches it anymore.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node which sent the output.
+    /// * `output`: The output of the session.
+    pub async fn report_exec(&mut self, node_id: &str, output: agent::ExecOutput) {
+        if self
+            .execs
+            .get(&output.session_id)
+            .is_none_or(|(session_node_id, _)| session_node_id != node_id)
+        {
+            debug!(
+                "ignored exec session {} sent by node {}, nobody watches it",
+                output.session_id, node_id
+            );
+            return;
+        }
+        let Some((_, watcher)) = self.execs.remove(&output.session_id) else {
+            return;
+        };
+
+        let session_id = output.session_id.clone();
+        let (message, ended) = match output.output {
+            Some(Output::Error(error)) => (Err(tonic::Status::unavailable(error)), true),
+            Some(Output::ExitCode(_)) => (Ok(output), true),
+            _ => (Ok(output), false),
+        };
+        match timeout(self.timeout, watcher.send(message)).await {
+            Ok(Ok(())) if !ended => {
+                self.execs
+                    .insert(session_id, (node_id.to_string(), watcher));
+            }
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => {
+                debug!("watcher of exec session {} is gone", session_id);
+                self.close_stream(node_id, session_id).await;
+            }
+            Err(_) => {
+                warn!(
+                    "watcher of exec session {} is hung, it is dropped",
+                    session_id
+                );
+                self.close_stream(node_id, session_id).await;
+            }
+        }
+    }
+
+    /// Reports the exec sessions of a disconnected node as interrupted to their watchers.
+    pub(super) async fn fail_execs(&mut self, node_id: &str) {
+        let sessions: Vec<String> = self
+            .execs
+            .iter()
+            .filter(|(_, (session_node_id, _))| session_node_id == node_id)
+            .map(|(session_id, _)| session_id.clone())
+            .collect();
+        for session_id in sessions {
+            if let Some((_, watcher)) = self.execs.remove(&session_id) {
+                let status = tonic::Status::unavailable(format!(
+                    "node {} running the command disconnected",
+                    node_id
+                ));
+                _ = timeout(self.timeout, watcher.send(Err(status))).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use crate::lifecycle::tests::{instance, TIMEOUT};
+
+    use super::*;
+
+    fn output(session_id: &str, output: Output) -> agent::ExecOutput {
+        agent::ExecOutput {
+            session_id: session_id.to_string(),
+            output: Some(output),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exec() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+        let (tx, _rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx).await.unwrap();
+        commands.recv().await.unwrap().unwrap();
+
+        let start = agent::ExecInput {
+            input: Some(Input::Start(agent::ExecStart {
+                instance_id: "1".to_string(),
+                command: vec!["sh".to_string()],
+                tty: true,
+            })),
+            ..Default::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let session_id = connections.exec(start.clone(), tx).await.unwrap();
+        let command = commands.recv().await.unwrap().unwrap().command;
+        let Some(Command::Exec(sent)) = command else {
+            panic!("expected an exec session, got {:?}", command);
+        };
+        assert_eq!(sent.session_id, session_id);
+
+        // the inputs are forwarded to the node of the session
+        let stdin = agent::ExecInput {
+            session_id: session_id.clone(),
+            input: Some(Input::Stdin(b"exit 3\n".to_vec())),
+        };
+        connections.exec_input(stdin.clone()).await;
+        let command = commands.recv().await.unwrap().unwrap().command;
+        assert_eq!(command, Some(Command::Exec(stdin)));
+
+        // only the node of the session sends its output
+        connections
+            .report_exec("b", output(&session_id, Output::Stdout(b"other".to_vec())))
+            .await;
+        connections
+            .report_exec("a", output(&session_id, Output::Stdout(b"$ ".to_vec())))
+            .await;
+        connections
+            .report_exec("a", output(&session_id, Output::ExitCode(3)))
+            .await;
+        let received = rx.recv().await.unwrap().unwrap();
+        assert_eq!(received.output, Some(Output::Stdout(b"$ ".to_vec())));
+        let received = rx.recv().await.unwrap().unwrap();
+        assert_eq!(received.output, Some(Output::ExitCode(3)));
+        assert!(rx.recv().await.is_none());
+
+        // the inputs of an ended session are dropped
+        connections
+            .exec_input(agent::ExecInput {
+                session_id,
+                input: Some(Input::CloseStdin(true)),
+            })
+            .await;
+        assert!(commands.try_recv().is_err());
+
+        // the session is closed on the node once its watcher is gone
+        let (tx, rx) = mpsc::channel(4);
+        let session_id = connections.exec(start.clone(), tx).await.unwrap();
+        commands.recv().await.unwrap().unwrap();
+        drop(rx);
+        connections
+            .report_exec("a", output(&session_id, Output::Stdout(b"$ ".to_vec())))
+            .await;
+        let command = commands.recv().await.unwrap().unwrap().command;
+        assert_eq!(command, Some(Command::CloseStream(session_id)));
+
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.exec(start, tx).await.unwrap();
+        drop(commands);
+        connections.disconnect("a").await;
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+
+        let err = connections
+            .exec(agent::ExecInput::default(), mpsc::channel(1).0)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,226 @@
+use log::{debug, info, warn};
+use proto::{
+    agent::{self, instance_command::Command},
+    scheduler::Feature,
+};
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::NodeIdentifier;
+
+use super::NodeConnections;
+
+/// The sending half of the stream of the output of an instance returned to the controller.
+pub type LogSender = mpsc::Sender<Result<agent::LogChunk, tonic::Status>>;
+
+impl NodeConnections {
+    /// Asks the node of an instance for its output, the chunks sent by the node are forwarded to
+    /// `watcher` until the last one, or until the watcher is gone.
+    ///
+    /// Arguments:
+    ///
+    /// * `request`: The instance and the output to send, its stream id is set by the scheduler.
+    /// * `watcher`: The stream of the output.
+    pub async fn logs(
+        &mut self,
+        mut request: agent::LogRequest,
+        watcher: LogSender,
+    ) -> Result<(), tonic::Status> {
+        let node_id = self.stream_node(&request.instance_id, Feature::Logs, "stream logs")?;
+        request.stream_id = self.next_stream_id();
+
+        let stream_id = request.stream_id.clone();
+        self.send(&node_id, Command::Logs(request)).await?;
+        debug!("node {} opened log stream {}", node_id, stream_id);
+        self.logs.insert(stream_id, (node_id, watcher));
+        Ok(())
+    }
+
+    /// Forwards a chunk of output sent by a node to the watcher of its stream. The stream is
+    /// forgotten after its last chunk, and closed on the node once nobody watches it anymore.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node which sent the chunk.
+    /// * `chunk`: The chunk of output.
+    pub async fn report_logs(&mut self, node_id: &str, chunk: agent::LogChunk) {
+        if self
+            .logs
+            .get(&chunk.stream_id)
+            .is_none_or(|(stream_node_id, _)| stream_node_id != node_id)
+        {
+            debug!(
+                "ignored log stream {} sent by node {}, nobody watches it",
+                chunk.stream_id, node_id
+            );
+            return;
+        }
+        let Some((_, watcher)) = self.logs.remove(&chunk.stream_id) else {
+            return;
+        };
+
+        let stream_id = chunk.stream_id.clone();
+        let eof = chunk.eof;
+        let message = if chunk.error.is_empty() {
+            Ok(chunk)
+        } else {
+            Err(tonic::Status::unavailable(chunk.error))
+        };
+        match timeout(self.timeout, watcher.send(message)).await {
+            Ok(Ok(())) if !eof => {
+                self.logs.insert(stream_id, (node_id.to_string(), watcher));
+            }
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => {
+                debug!("watcher of log stream {} is gone", stream_id);
+                self.close_stream(node_id, stream_id).await;
+            }
+            Err(_) => {
+                warn!("watcher of log stream {} is hung, it is dropped", stream_id);
+                self.close_stream(node_id, stream_id).await;
+            }
+        }
+    }
+
+    /// Returns the node of an instance whose agent performs `feature`, for the streams opened on
+    /// the instance.
+    #[allow(clippy::result_large_err)]
+    pub(super) fn stream_node(
+        &self,
+        instance_id: &str,
+        feature: Feature,
+        operation: &str,
+    ) -> Result<NodeIdentifier, tonic::Status> {
+        let node_id = self
+            .placements
+            .get(instance_id)
+            .map(|placement| placement.node_id.clone())
+            .ok_or_else(|| {
+                tonic::Status::not_found(format!("instance {} is not placed", instance_id))
+            })?;
+        if !self.supports(&node_id, feature) {
+            return Err(tonic::Status::failed_precondition(format!(
+                "node {} of instance {} doesn't {}",
+                node_id, instance_id, operation
+            )));
+        }
+        Ok(node_id)
+    }
+
+    /// Returns the id of a new stream opened on a node.
+    pub(super) fn next_stream_id(&mut self) -> String {
+        self.streams += 1;
+        self.streams.to_string()
+    }
+
+    /// Tells a node to stop sending a stream nobody watches anymore.
+    pub(super) async fn close_stream(&mut self, node_id: &str, stream_id: String) {
+        info!("closing stream {} of node {}", stream_id, node_id);
+        _ = self.send(node_id, Command::CloseStream(stream_id)).await;
+    }
+
+    /// Reports the log streams of a disconnected node as interrupted to their watchers.
+    pub(super) async fn fail_logs(&mut self, node_id: &str) {
+        let streams: Vec<String> = self
+            .logs
+            .iter()
+            .filter(|(_, (stream_node_id, _))| stream_node_id == node_id)
+            .map(|(stream_id, _)| stream_id.clone())
+            .collect();
+        for stream_id in streams {
+            if let Some((_, watcher)) = self.logs.remove(&stream_id) {
+                let status = tonic::Status::unavailable(format!(
+                    "node {} sending the logs disconnected",
+                    node_id
+                ));
+                _ = timeout(self.timeout, watcher.send(Err(status))).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use crate::lifecycle::tests::{instance, TIMEOUT};
+
+    use super::*;
+
+    fn chunk(stream_id: &str, data: &[u8], eof: bool) -> agent::LogChunk {
+        agent::LogChunk {
+            stream_id: stream_id.to_string(),
+            data: data.to_vec(),
+            eof,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logs() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+        let (tx, _rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx).await.unwrap();
+        commands.recv().await.unwrap().unwrap();
+
+        let request = agent::LogRequest {
+            instance_id: "1".to_string(),
+            follow: true,
+            tail: 10,
+            ..Default::default()
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.logs(request.clone(), tx).await.unwrap();
+        let command = commands.recv().await.unwrap().unwrap().command;
+        let Some(Command::Logs(sent)) = command else {
+            panic!("expected a log request, got {:?}", command);
+        };
+        assert_eq!(sent.instance_id, "1");
+        assert!(!sent.stream_id.is_empty());
+
+        // only the node of the stream sends it
+        connections
+            .report_logs("b", chunk(&sent.stream_id, b"other", false))
+            .await;
+        connections
+            .report_logs("a", chunk(&sent.stream_id, b"hello\n", false))
+            .await;
+        connections
+            .report_logs("a", chunk(&sent.stream_id, b"", true))
+            .await;
+        assert_eq!(rx.recv().await.unwrap().unwrap().data, b"hello\n");
+        assert!(rx.recv().await.unwrap().unwrap().eof);
+        assert!(rx.recv().await.is_none());
+
+        // the stream is closed on the node once its watcher is gone
+        let (tx, rx) = mpsc::channel(4);
+        connections.logs(request.clone(), tx).await.unwrap();
+        let Some(Command::Logs(sent)) = commands.recv().await.unwrap().unwrap().command else {
+            panic!("expected a log request");
+        };
+        drop(rx);
+        connections
+            .report_logs("a", chunk(&sent.stream_id, b"hello\n", false))
+            .await;
+        let command = commands.recv().await.unwrap().unwrap().command;
+        assert_eq!(command, Some(Command::CloseStream(sent.stream_id)));
+
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.logs(request, tx).await.unwrap();
+        drop(commands);
+        connections.disconnect("a").await;
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+
+        let request = agent::LogRequest {
+            instance_id: "2".to_string(),
+            ..Default::default()
+        };
+        let err = connections
+            .logs(request, mpsc::channel(1).0)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+}
@@ -0,0 +1,432 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::info;
+use proto::{
+    agent::{self, instance_command::Command, Signal},
+    scheduler::{Feature, InstanceMigrateRequest, Status},
+};
+use tokio::sync::oneshot;
+use tonic::Response;
+
+use crate::NodeIdentifier;
+
+use super::{
+    placement::{effective_spread, parse_constraint},
+    CheckpointWaiter, NodeConnections,
+};
+
+/// The sending half of the result of a live migration returned to the controller.
+pub type MigrateSender = oneshot::Sender<Result<Response<()>, tonic::Status>>;
+
+impl NodeConnections {
+    /// Moves an instance from the most loaded node to a node whose load is lower by `threshold` or
+    /// more, the loads being weighted by the default profile. Only the running instances which
+    /// are neither pinned to their node nor run to completion are moved, and only if their
+    /// workload keeps its disruption budget. The cordoned nodes are left out.
+    ///
+    /// Arguments:
+    ///
+    /// * `threshold`: The difference of load between two nodes from which an instance is moved.
+    ///
+    /// Returns:
+    ///
+    /// The id of the moved instance, `None` if the nodes are balanced or no instance can move.
+    pub async fn rebalance(&mut self, threshold: u64) -> Result<Option<String>, tonic::Status> {
+        self.disconnect_closed().await;
+        let weights = self.profiles.default.weights.clone();
+        let mut loads: Vec<(u64, NodeIdentifier)> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.is_connected() && !node.cordoned)
+            .map(|(node_id, _)| (self.load(node_id, &weights), node_id.clone()))
+            .collect();
+        loads.sort();
+        let Some((highest, from)) = loads.last().cloned() else {
+            return Ok(None);
+        };
+
+        let mut candidates: Vec<String> = self
+            .placements
+            .iter()
+            .filter(|(id, placement)| {
+                placement.node_id == from
+                    && placement.request.node_id.is_empty()
+                    && !placement.migrating
+                    && placement.request.kind() != agent::WorkloadKind::Job
+                    && placement
+                        .status
+                        .as_ref()
+                        .is_some_and(|status| status.status() == Status::Running)
+                    && self.keeps_budget(id, placement)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        candidates.sort();
+
+        for id in candidates {
+            let request = &self.placements[&id].request;
+            let Ok(constraint) = parse_constraint(request) else {
+                continue;
+            };
+            let spread = effective_spread(request);
+            // the least loaded node able to run the instance
+            let target = loads.iter().find(|(load, node_id)| {
+                *node_id != from
+                    && highest - load >= threshold.max(1)
+                    && self
+                        .unmet(node_id, request, constraint.as_ref(), spread)
+                        .is_none()
+            });
+            if let Some((_, node_id)) = target {
+                let node_id = node_id.clone();
+                self.migrate(&id, &node_id).await?;
+                return Ok(Some(id));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Moves an instance to another node: it is killed on its node and created again with the
+    /// same id on the other one, its statuses keep being forwarded to its watcher. The watcher is
+    /// notified that the instance is unavailable if the creation fails.
+    ///
+    /// Arguments:
+    ///
+    /// * `id`: The id of the instance.
+    /// * `node_id`: The id of the node the instance is moved to.
+    async fn migrate(&mut self, id: &str, node_id: &str) -> Result<(), tonic::Status> {
+        let placement = self
+            .placements
+            .get(id)
+            .ok_or_else(|| tonic::Status::not_found(format!("instance {} is not placed", id)))?;
+        let from = placement.node_id.clone();
+        let command = Command::Create(placement.instance.clone());
+
+        self.signal(id, Signal::Kill).await?;
+        if let Err(err) = self.send(node_id, command).await {
+            let message = format!(
+                "instance could not be moved to node {}: {}",
+                node_id,
+                err.message()
+            );
+            self.lose(id, message).await;
+            return Err(err);
+        }
+
+        // the statuses the previous node sends from now on are ignored
+        if let Some(placement) = self.placements.get_mut(id) {
+            placement.node_id = node_id.to_string();
+            placement.status = None;
+        }
+        info!(
+            "instance {} moved from node {} to node {}",
+            id, from, node_id
+        );
+        Ok(())
+    }
+
+    /// Migrates a running instance to another node with its memory, experimental: its node
+    /// checkpoints it and stops it, then it is restored from the checkpoint on the other node
+    /// with the same id and IP address. `reply` is answered once the restore is sent to the other
+    /// node, the checkpoints must be on a storage shared by the nodes. The instance keeps running
+    /// on its node if the checkpoint fails, and the statuses of its node are ignored meanwhile.
+    ///
+    /// Arguments:
+    ///
+    /// * `request`: The instance to migrate and the node it is restored on.
+    /// * `reply`: The channel the result of the migration is sent on.
+    #[allow(clippy::result_large_err)]
+    pub async fn live_migrate(&mut self, request: InstanceMigrateRequest, reply: MigrateSender) {
+        let checkpoint = agent::Checkpoint {
+            instance_id: request.id.clone(),
+            name: format!("migration-{}", unix_time()),
+            leave_running: false,
+        };
+        let source = self
+            .migration_source(&request)
+            .and_then(|source| self.checkpoint_node(&checkpoint).map(|_| source));
+        let source = match source {
+            Ok(source) => source,
+            Err(err) => {
+                _ = reply.send(Err(err));
+                return;
+            }
+        };
+
+        let waiter = CheckpointWaiter::Migration {
+            target: request.node_id,
+            reply,
+        };
+        if self.write_checkpoint(&source, checkpoint, waiter).await {
+            if let Some(placement) = self.placements.get_mut(&request.id) {
+                placement.migrating = true;
+            }
+        }
+    }
+
+    /// Returns the node an instance is migrated from, if it runs there and the target of its
+    /// migration is connected and able to run it.
+    #[allow(clippy::result_large_err)]
+    fn migration_source(
+        &self,
+        request: &InstanceMigrateRequest,
+    ) -> Result<NodeIdentifier, tonic::Status> {
+        let placement = self.placements.get(&request.id).ok_or_else(|| {
+            tonic::Status::not_found(format!("instance {} is not placed", request.id))
+        })?;
+        if placement.migrating {
+            return Err(tonic::Status::already_exists(format!(
+                "instance {} is being migrated",
+                request.id
+            )));
+        }
+        if placement
+            .status
+            .as_ref()
+            .is_none_or(|status| status.status() != Status::Running)
+        {
+            return Err(tonic::Status::failed_precondition(format!(
+                "instance {} is not running",
+                request.id
+            )));
+        }
+        if placement.node_id == request.node_id {
+            return Err(tonic::Status::failed_precondition(format!(
+                "instance {} already runs on node {}",
+                request.id, request.node_id
+            )));
+        }
+        if !self.is_connected(&request.node_id) {
+            return Err(tonic::Status::unavailable(format!(
+                "node {} is not connected",
+                request.node_id
+            )));
+        }
+        if !self.supports(&request.node_id, Feature::Checkpoint) {
+            return Err(tonic::Status::failed_precondition(format!(
+                "node {} doesn't restore checkpoints",
+                request.node_id
+            )));
+        }
+
+        let constraint = parse_constraint(&placement.request)?;
+        let spread = effective_spread(&placement.request);
+        if let Some(requirement) = self.unmet(
+            &request.node_id,
+            &placement.request,
+            constraint.as_ref(),
+            spread,
+        ) {
+            return Err(tonic::Status::failed_precondition(format!(
+                "node {} can't run instance {}: {}",
+                request.node_id, request.id, requirement
+            )));
+        }
+        Ok(placement.node_id.clone())
+    }
+
+    /// Restores an instance checkpointed by `source` on `target`, the end of its live migration.
+    /// The instance keeps running on `source` if the checkpoint failed, and it is lost if the
+    /// restore can't be sent to `target`.
+    pub(super) async fn restore(
+        &mut self,
+        source: &str,
+        target: &str,
+        status: agent::CheckpointStatus,
+    ) -> Result<(), tonic::Status> {
+        let id = status.instance_id.clone();
+        let placement = self.placements.get_mut(&id).ok_or_else(|| {
+            tonic::Status::not_found(format!("instance {} was removed while migrating", id))
+        })?;
+        placement.migrating = false;
+        if !status.error.is_empty() {
+            return Err(tonic::Status::failed_precondition(format!(
+                "instance {} could not be checkpointed: {}",
+                id, status.error
+            )));
+        }
+
+        let instance = agent::Instance {
+            restore: Some(agent::Checkpoint {
+                instance_id: id.clone(),
+                name: status.name,
+                leave_running: false,
+            }),
+            ..placement.instance.clone()
+        };
+        if let Err(err) = self.send(target, Command::Create(instance)).await {
+            let message = format!(
+                "instance could not be restored on node {}: {}",
+                target,
+                err.message()
+            );
+            self.lose(&id, message).await;
+            return Err(err);
+        }
+
+        // the statuses the previous node sends from now on are ignored
+        if let Some(placement) = self.placements.get_mut(&id) {
+            placement.node_id = target.to_string();
+            placement.status = None;
+        }
+        info!(
+            "instance {} migrated live from node {} to node {}",
+            id, source, target
+        );
+        Ok(())
+    }
+}
+
+/// Returns the current time, in seconds since the unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use crate::lifecycle::tests::{instance, TIMEOUT};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_live_migrate() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut source) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx).await.unwrap();
+        source.recv().await.unwrap().unwrap();
+        let (node, mut target) = mpsc::channel(4);
+        connections.connect("b".to_string(), node);
+
+        let request = InstanceMigrateRequest {
+            id: "1".to_string(),
+            node_id: "b".to_string(),
+        };
+        // only a running instance is migrated
+        let (reply, result) = oneshot::channel();
+        connections.live_migrate(request.clone(), reply).await;
+        let err = result.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        let reported = |status: agent::Status| agent::InstanceStatus {
+            id: "1".to_string(),
+            status: status.into(),
+            ..Default::default()
+        };
+        connections
+            .report("a", reported(agent::Status::Running))
+            .await;
+        rx.recv().await.unwrap().unwrap();
+
+        let (reply, result) = oneshot::channel();
+        connections.live_migrate(request, reply).await;
+        let checkpoint = match source.recv().await.unwrap().unwrap().command {
+            Some(Command::Checkpoint(checkpoint)) => checkpoint,
+            command => panic!("unexpected command {:?}", command),
+        };
+        assert!(!checkpoint.leave_running);
+
+        // the instance stopped by the checkpoint isn't reported
+        connections
+            .report("a", reported(agent::Status::Terminated))
+            .await;
+        assert!(rx.try_recv().is_err());
+
+        let status = agent::CheckpointStatus {
+            instance_id: "1".to_string(),
+            name: checkpoint.name.clone(),
+            error: String::new(),
+        };
+        connections.report_checkpoint("a", status).await;
+        result.await.unwrap().unwrap();
+        match target.recv().await.unwrap().unwrap().command {
+            Some(Command::Create(instance)) => {
+                assert_eq!(instance.id, "1");
+                assert_eq!(instance.restore, Some(checkpoint));
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        connections
+            .report("b", reported(agent::Status::Running))
+            .await;
+        let status = rx.recv().await.unwrap().unwrap();
+        assert_eq!(status.node_id, "b");
+    }
+
+    #[tokio::test]
+    async fn test_rebalance() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, mut commands_a) = mpsc::channel(8);
+        let (node_b, mut commands_b) = mpsc::channel(8);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.cordon("b".to_string(), true);
+
+        let (tx, mut rx) = mpsc::channel(8);
+        for id in ["1", "2", "3"] {
+            let mut replica = instance(id);
+            replica.workload_id = "default.web".to_string();
+            replica.min_available = 2;
+            connections.create(replica, tx.clone()).await.unwrap();
+            commands_a.recv().await.unwrap().unwrap();
+        }
+        connections.cordon("b".to_string(), false);
+
+        // the instances which don't run yet are not moved
+        assert_eq!(connections.rebalance(2).await.unwrap(), None);
+        for id in ["1", "2", "3"] {
+            let status = agent::InstanceStatus {
+                id: id.to_string(),
+                status: agent::Status::Running.into(),
+                ..Default::default()
+            };
+            connections.report("a", status).await;
+            rx.recv().await.unwrap().unwrap();
+        }
+
+        assert_eq!(
+            connections.rebalance(2).await.unwrap(),
+            Some("1".to_string())
+        );
+        match commands_a.recv().await.unwrap().unwrap().command {
+            Some(Command::Signal(instruction)) => {
+                assert_eq!(instruction.signal(), Signal::Kill)
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+        match commands_b.recv().await.unwrap().unwrap().command {
+            Some(Command::Create(instance)) => assert_eq!(instance.id, "1"),
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        // the status sent by the previous node is not forwarded
+        let status = agent::InstanceStatus {
+            id: "1".to_string(),
+            status: agent::Status::Terminated.into(),
+            ..Default::default()
+        };
+        connections.report("a", status).await;
+        let status = agent::InstanceStatus {
+            id: "1".to_string(),
+            status: agent::Status::Starting.into(),
+            ..Default::default()
+        };
+        connections.report("b", status).await;
+        let status = rx.recv().await.unwrap().unwrap();
+        assert_eq!(status.node_id, "b");
+        assert_eq!(status.status(), Status::Starting);
+
+        // moving 2 or 3 would leave a single running instance of the workload, 4 doesn't run yet
+        connections.cordon("b".to_string(), true);
+        connections.create(instance("4"), tx).await.unwrap();
+        connections.cordon("b".to_string(), false);
+        assert_eq!(connections.rebalance(1).await.unwrap(), None);
+    }
+}
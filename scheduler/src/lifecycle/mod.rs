@@ -0,0 +1,1570 @@
+use std::{collections::HashMap, time::Duration};
+
+use log::{debug, info, warn};
+use opentelemetry::Context;
+use proto::{
+    agent::{self, instance_command::Command, InstanceCommand, Signal, SignalInstruction},
+    scheduler::{
+        ClusterSnapshot, Eviction, Feature, Instance, InstancePlacement, InstanceStatus,
+        NodeCapabilities, NodeSnapshot, NodeStatus, Platform, Resource, ResourceSummary, Spread,
+        Status, Type,
+    },
+};
+use telemetry::grpc::inject_fields;
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::breaker::{Breaker, BreakerPolicy};
+use crate::config::{ProfilesConfig, Strategy};
+use crate::deadline::Deadline;
+use crate::NodeIdentifier;
+
+mod checkpoint;
+mod exec;
+mod logs;
+mod migrate;
+mod node;
+mod placement;
+mod pull;
+
+pub use checkpoint::CheckpointSender;
+pub use exec::ExecSender;
+pub use logs::LogSender;
+pub use migrate::MigrateSender;
+pub use pull::PullSender;
+
+use checkpoint::CheckpointWaiter;
+use node::NodeState;
+use placement::{effective_spread, parse_constraint, platform_name, Placement};
+
+/// The sending half of the lifecycle stream of a node.
+pub type CommandSender = mpsc::Sender<Result<InstanceCommand, tonic::Status>>;
+
+/// The sending half of the status stream returned to the creator of an instance.
+pub type StatusSender = mpsc::Sender<Result<InstanceStatus, tonic::Status>>;
+
+/// `NodeConnections` keeps the lifecycle streams opened by the nodes, the node each instance is
+/// placed on and the status streams of the instances being watched.
+///
+/// The placement of the instances, the image pulls, the checkpoints, the migrations, the log
+/// streams and the exec sessions are handled in the submodules of the same name.
+///
+/// Properties:
+///
+/// * `nodes`: The state of each known node, connected or not.
+/// * `placements`: The node each instance is placed on.
+/// * `watchers`: The status stream of each instance, the statuses sent by the nodes are forwarded to it.
+/// * `pulls`: The streams watching each image being pulled by a node, by node and image.
+/// * `checkpoints`: The node writing each checkpoint and who waits for it, by instance and
+///   checkpoint name.
+/// * `logs`: The node sending each log stream and its watcher, by stream id.
+/// * `execs`: The node running each exec session and its watcher, by session id.
+/// * `streams`: The number of streams and sessions opened on the nodes, the id of the last one.
+/// * `profiles`: How the instances are placed on the nodes, selected by their namespace.
+/// * `timeout`: The deadline of each message sent on a stream, so a hung peer doesn't block the
+///   event handlers.
+/// * `deadline`: When the caller of the event being handled gives up, the commands sent for it
+///   are bounded by it.
+/// * `breaker_policy`: When the breaker of a node opens, pausing its commands, and for how long.
+#[derive(Debug)]
+pub struct NodeConnections {
+    nodes: HashMap<NodeIdentifier, NodeState>,
+    placements: HashMap<String, Placement>,
+    watchers: HashMap<String, StatusSender>,
+    pulls: HashMap<(NodeIdentifier, String), Vec<PullSender>>,
+    checkpoints: HashMap<(String, String), (NodeIdentifier, CheckpointWaiter)>,
+    logs: HashMap<String, (NodeIdentifier, LogSender)>,
+    execs: HashMap<String, (NodeIdentifier, ExecSender)>,
+    streams: u64,
+    profiles: ProfilesConfig,
+    timeout: Duration,
+    deadline: Deadline,
+    breaker_policy: BreakerPolicy,
+}
+
+impl NodeConnections {
+    /// `new` creates a new `NodeConnections` without any connected node.
+    ///
+    /// Arguments:
+    ///
+    /// * `timeout`: The deadline of each message sent on a stream.
+    pub fn new(timeout: Duration) -> Self {
+        NodeConnections {
+            nodes: HashMap::new(),
+            placements: HashMap::new(),
+            watchers: HashMap::new(),
+            pulls: HashMap::new(),
+            checkpoints: HashMap::new(),
+            logs: HashMap::new(),
+            execs: HashMap::new(),
+            streams: 0,
+            profiles: ProfilesConfig::default(),
+            timeout,
+            deadline: Deadline::none(),
+            breaker_policy: BreakerPolicy::default(),
+        }
+    }
+
+    /// Places the instances with the given scheduling profiles instead of the default one.
+    ///
+    /// Arguments:
+    ///
+    /// * `profiles`: The profile of each namespace.
+    pub fn with_profiles(mut self, profiles: ProfilesConfig) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    /// Pauses the commands of the nodes failing repeatedly with the given policy instead of the
+    /// default one.
+    ///
+    /// Arguments:
+    ///
+    /// * `policy`: When the breaker of a node opens and how long it stays open.
+    pub fn with_breakers(mut self, policy: BreakerPolicy) -> Self {
+        self.breaker_policy = policy;
+        self
+    }
+
+    /// Sets the deadline of the event being handled, the commands aren't sent to the nodes once
+    /// it is expired and their delivery is bounded by it.
+    ///
+    /// Arguments:
+    ///
+    /// * `deadline`: When the caller of the event gives up, `none` between two events.
+    pub fn set_deadline(&mut self, deadline: Deadline) {
+        self.deadline = deadline;
+    }
+
+    /// Returns the state of a node, known from now on.
+    fn node_mut(&mut self, node_id: NodeIdentifier) -> &mut NodeState {
+        self.nodes.entry(node_id).or_default()
+    }
+
+    /// Returns the ids of the nodes whose lifecycle stream is connected.
+    fn connected(&self) -> impl Iterator<Item = &NodeIdentifier> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.is_connected())
+            .map(|(node_id, _)| node_id)
+    }
+
+    /// Returns `true` if the lifecycle stream of a node is connected.
+    fn is_connected(&self, node_id: &str) -> bool {
+        self.nodes.get(node_id).is_some_and(NodeState::is_connected)
+    }
+
+    /// Registers the lifecycle stream of a node, replacing the previous one if the node reconnects.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `sender`: The sending half of the stream.
+    pub fn connect(&mut self, node_id: NodeIdentifier, sender: CommandSender) {
+        info!("node {} connected its lifecycle stream", node_id);
+        let node = self.node_mut(node_id);
+        node.breaker = Breaker::default();
+        node.sender = Some(sender);
+    }
+
+    /// Keeps the capabilities reported by a node when it registers.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `capabilities`: The capabilities of the node, `None` if it can run any instance.
+    pub fn register_capabilities(
+        &mut self,
+        node_id: NodeIdentifier,
+        capabilities: Option<NodeCapabilities>,
+    ) {
+        if let Some(capabilities) = &capabilities {
+            info!("node {} registered with {:?}", node_id, capabilities);
+        }
+        self.node_mut(node_id).capabilities = capabilities;
+    }
+
+    /// Keeps the features reported by a node when it registers.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `features`: The operations the agent of the node performs, every one if empty.
+    pub fn register_features(&mut self, node_id: NodeIdentifier, features: Vec<Feature>) {
+        if !features.is_empty() {
+            info!("node {} registered with features {:?}", node_id, features);
+        }
+        self.node_mut(node_id).features =
+            (!features.is_empty()).then(|| features.into_iter().collect());
+    }
+
+    /// Keeps the platform reported by a node when it registers.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `platform`: The platform of the node, `None` if its agent predates the platforms.
+    pub fn register_platform(&mut self, node_id: NodeIdentifier, platform: Option<Platform>) {
+        if let Some(platform) = &platform {
+            info!("node {} registered on {}", node_id, platform_name(platform));
+        }
+        self.node_mut(node_id).platform = platform;
+    }
+
+    /// Returns `true` if the agent of a node performs an operation.
+    pub fn supports(&self, node_id: &str, feature: Feature) -> bool {
+        self.nodes
+            .get(node_id)
+            .is_none_or(|node| node.supports(feature))
+    }
+
+    /// Keeps the interval a node was told to send its statuses at when it registered.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `interval`: The interval sent in the register response.
+    pub fn register_status_interval(&mut self, node_id: NodeIdentifier, interval: Duration) {
+        self.node_mut(node_id).status_interval = Some(interval);
+    }
+
+    /// Tells a node the interval to send its statuses at through its lifecycle stream, if it
+    /// differs from the one it was last told, e.g. to slow it down when the scheduler is loaded.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `interval`: The interval for the current load of the scheduler.
+    pub async fn throttle_status(
+        &mut self,
+        node_id: &str,
+        interval: Duration,
+    ) -> Result<(), tonic::Status> {
+        if !self
+            .nodes
+            .get(node_id)
+            .is_some_and(|node| node.is_connected() && node.status_interval != Some(interval))
+        {
+            return Ok(());
+        }
+
+        debug!("node {} sends its statuses every {:?}", node_id, interval);
+        let command = Command::StatusIntervalMs(interval.as_millis() as u32);
+        self.send(node_id, command).await?;
+        self.node_mut(node_id.to_string()).status_interval = Some(interval);
+        Ok(())
+    }
+
+    /// Stops placing new instances on a node, e.g. before its maintenance, or places them on it
+    /// again. The instances already placed on the node keep running.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `cordoned`: If true, only the instances pinned to the node are placed on it.
+    pub fn cordon(&mut self, node_id: NodeIdentifier, cordoned: bool) {
+        let node = self.node_mut(node_id.clone());
+        if node.cordoned == cordoned {
+            return;
+        }
+        node.cordoned = cordoned;
+        if cordoned {
+            info!("node {} cordoned", node_id);
+        } else {
+            info!("node {} uncordoned", node_id);
+        }
+    }
+
+    /// Returns `true` if at least one node has its lifecycle stream connected.
+    pub fn has_nodes(&self) -> bool {
+        self.connected().next().is_some()
+    }
+
+    /// Forgets a node whose lifecycle stream is closed, the watchers of its instances are notified
+    /// that they are unavailable and its image pulls, checkpoints, log streams and exec sessions
+    /// are reported as failed.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    pub async fn disconnect(&mut self, node_id: &str) {
+        // the node may have opened a new stream in the meantime
+        let Some(node) = self.nodes.get_mut(node_id).filter(|node| node.is_closed()) else {
+            return;
+        };
+        info!("node {} disconnected its lifecycle stream", node_id);
+        node.sender = None;
+        node.breaker = Breaker::default();
+        node.status_interval = None;
+
+        let lost: Vec<String> = self
+            .placements
+            .iter()
+            .filter(|(_, placement)| placement.node_id == node_id)
+            .map(|(id, _)| id.clone())
+            .collect();
+
+        for id in lost {
+            self.placements.remove(&id);
+            if let Some(watcher) = self.watchers.remove(&id) {
+                let status = tonic::Status::unavailable(format!(
+                    "node {} hosting the instance disconnected",
+                    node_id
+                ));
+                _ = timeout(self.timeout, watcher.send(Err(status))).await;
+            }
+        }
+
+        self.fail_pulls(node_id).await;
+        self.fail_checkpoints(node_id);
+        self.fail_logs(node_id).await;
+        self.fail_execs(node_id).await;
+    }
+
+    /// Places an instance on the connected and uncordoned node picked by the profile of its
+    /// namespace among the ones able to run it, by default the one hosting the fewest instances,
+    /// or on the node it is pinned to, and sends it the creation command.
+    /// The nodes running the fewest instances of its workload come first if it is spread, the
+    /// ones running any are excluded if it is strictly spread, and the ones outside of its pool
+    /// or not matching its constraint are excluded. The statuses of the instance are forwarded to
+    /// `watcher`.
+    ///
+    /// Arguments:
+    ///
+    /// * `instance`: The instance to create.
+    /// * `watcher`: The status stream of the instance.
+    ///
+    /// Returns:
+    ///
+    /// The id of the node the instance is placed on.
+    pub async fn create(
+        &mut self,
+        instance: Instance,
+        watcher: StatusSender,
+    ) -> Result<NodeIdentifier, tonic::Status> {
+        self.disconnect_closed().await;
+        if !self.has_nodes() {
+            return Err(tonic::Status::unavailable(
+                "no node is connected to the scheduler",
+            ));
+        }
+
+        if !instance.node_id.is_empty() && !self.is_connected(&instance.node_id) {
+            return Err(tonic::Status::unavailable(format!(
+                "node {} of instance {} is not connected",
+                instance.node_id, instance.id
+            )));
+        }
+
+        let constraint = parse_constraint(&instance)?;
+        let profile = self.profiles.get(&instance.namespace);
+        let spread = effective_spread(&instance);
+        let mut unmet = None;
+        let node_id = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.is_connected())
+            .filter(|(node_id, node)| {
+                if instance.node_id.is_empty() {
+                    !node.cordoned && !node.breaker.is_paused()
+                } else {
+                    **node_id == instance.node_id
+                }
+            })
+            .map(|(node_id, _)| node_id)
+            .filter(
+                |node_id| match self.unmet(node_id, &instance, constraint.as_ref(), spread) {
+                    Some(requirement) => {
+                        debug!(
+                            "node {} can't run instance {}: {}",
+                            node_id, instance.id, requirement
+                        );
+                        unmet = Some(requirement);
+                        false
+                    }
+                    None => true,
+                },
+            )
+            .min_by_key(|node_id| {
+                let siblings = match spread {
+                    Spread::Any => 0,
+                    Spread::Preferred | Spread::Strict => {
+                        self.siblings(node_id, &instance.workload_id)
+                    }
+                };
+                let load = self.load(node_id, &profile.weights);
+                // the most loaded node comes first when binpacking
+                let load = match profile.strategy {
+                    Strategy::Spread => load,
+                    Strategy::Binpack => u64::MAX - load,
+                };
+                (siblings, load, node_id.to_string())
+            })
+            .cloned()
+            .ok_or_else(|| {
+                tonic::Status::failed_precondition(format!(
+                    "no connected node can run instance {}: {}",
+                    instance.id,
+                    unmet.unwrap_or_default()
+                ))
+            })?;
+
+        let workload_id = instance.workload_id.clone();
+        let request = instance.clone();
+        let instance = to_agent_instance(instance);
+        let command = Command::Create(instance.clone());
+        self.send(&node_id, command).await?;
+
+        debug!("instance {} placed on node {}", instance.id, node_id);
+        self.watchers.insert(instance.id.clone(), watcher);
+        self.placements.insert(
+            instance.id.clone(),
+            Placement {
+                node_id: node_id.clone(),
+                workload_id,
+                request,
+                instance,
+                status: None,
+                eviction: None,
+                migrating: false,
+            },
+        );
+        Ok(node_id)
+    }
+
+    /// Sends a signal to an instance through the lifecycle stream of its node.
+    ///
+    /// Arguments:
+    ///
+    /// * `id`: The id of the instance.
+    /// * `signal`: The signal to send.
+    pub async fn signal(&mut self, id: &str, signal: Signal) -> Result<(), tonic::Status> {
+        let placement = self
+            .placements
+            .get(id)
+            .ok_or_else(|| tonic::Status::not_found(format!("instance {} is not placed", id)))?;
+
+        let command = Command::Signal(SignalInstruction {
+            instance: Some(placement.instance.clone()),
+            signal: signal.into(),
+        });
+        let node_id = placement.node_id.clone();
+        self.send(&node_id, command).await
+    }
+
+    /// Stops an instance evicted by the controller, e.g. to drain its node. The eviction is
+    /// attached to the statuses of the instance sent afterwards, so it is told apart from a
+    /// deletion.
+    ///
+    /// Arguments:
+    ///
+    /// * `id`: The id of the instance.
+    /// * `eviction`: The reason of the eviction.
+    pub async fn evict(&mut self, id: &str, eviction: Eviction) -> Result<(), tonic::Status> {
+        let placement = self
+            .placements
+            .get_mut(id)
+            .ok_or_else(|| tonic::Status::not_found(format!("instance {} is not placed", id)))?;
+
+        info!(
+            "instance {} evicted ({:?}): {}",
+            id,
+            eviction.reason(),
+            eviction.message
+        );
+        placement.eviction = Some(eviction);
+        self.signal(id, Signal::Stop).await
+    }
+
+    /// Forgets an instance which no node runs anymore, its watcher is notified that it is
+    /// unavailable.
+    ///
+    /// Arguments:
+    ///
+    /// * `id`: The id of the instance.
+    /// * `message`: Why the instance is lost.
+    async fn lose(&mut self, id: &str, message: String) {
+        self.placements.remove(id);
+        if let Some(watcher) = self.watchers.remove(id) {
+            let status = tonic::Status::unavailable(message);
+            _ = timeout(self.timeout, watcher.send(Err(status))).await;
+        }
+    }
+
+    /// Keeps a status sent by a node and forwards it to the watcher of the instance. The instance
+    /// is forgotten once it is terminated, or once it failed if it runs to completion as it won't
+    /// be restarted.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node which sent the status.
+    /// * `status`: The status of the instance.
+    pub async fn report(&mut self, node_id: &str, status: agent::InstanceStatus) {
+        let mut status = to_scheduler_status(node_id, status);
+        let id = status.id.clone();
+        if self
+            .placements
+            .get(&id)
+            .is_some_and(|placement| placement.node_id != node_id || placement.migrating)
+        {
+            debug!(
+                "ignored the status of instance {} sent by node {}, it was moved",
+                id, node_id
+            );
+            return;
+        }
+        let mut terminated = status.status() == Status::Terminated;
+
+        if let Some(placement) = self.placements.get_mut(&id) {
+            status.eviction = placement.eviction.clone();
+            terminated |= placement.instance.kind() == agent::WorkloadKind::Job
+                && status.status() == Status::Failed;
+            placement.status = Some(status.clone());
+        }
+
+        if let Some(watcher) = self.watchers.get(&id) {
+            match timeout(self.timeout, watcher.send(Ok(status))).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) => {
+                    debug!("watcher of instance {} is gone", id);
+                    self.watchers.remove(&id);
+                }
+                Err(_) => {
+                    warn!("watcher of instance {} is hung, it is dropped", id);
+                    self.watchers.remove(&id);
+                }
+            }
+        }
+
+        if terminated {
+            self.watchers.remove(&id);
+            self.placements.remove(&id);
+        }
+    }
+
+    /// Keeps the last status sent by a node, returned in the snapshots.
+    ///
+    /// Arguments:
+    ///
+    /// * `status`: The status of the node.
+    pub fn update_node_status(&mut self, status: NodeStatus) {
+        let node_id = status.id.clone();
+        self.node_mut(node_id).status = Some(status);
+    }
+
+    /// Returns the full view of the scheduler: the known nodes, the instances placed on them and
+    /// the ones which didn't report a status yet, sorted by id.
+    pub fn snapshot(&self) -> ClusterSnapshot {
+        // the nodes which only registered aren't shown until they connect or send a status
+        let mut nodes: Vec<NodeSnapshot> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.is_connected() || node.status.is_some())
+            .map(|(node_id, node)| NodeSnapshot {
+                id: node_id.clone(),
+                connected: node.is_connected(),
+                status: node.status.clone(),
+                capabilities: node.capabilities.clone(),
+                cordoned: node.cordoned,
+                suspect: node.breaker.is_suspect(),
+                features: node
+                    .features
+                    .as_ref()
+                    .map(|features| {
+                        let mut features: Vec<i32> =
+                            features.iter().map(|&feature| feature.into()).collect();
+                        features.sort();
+                        features
+                    })
+                    .unwrap_or_default(),
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut placements: Vec<InstancePlacement> = self
+            .placements
+            .iter()
+            .map(|(id, placement)| InstancePlacement {
+                instance_id: id.clone(),
+                node_id: placement.node_id.clone(),
+                status: placement.status.clone(),
+            })
+            .collect();
+        placements.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+        let (placements, pending) = placements
+            .into_iter()
+            .partition(|placement| placement.status.is_some());
+
+        ClusterSnapshot {
+            nodes,
+            placements,
+            pending,
+        }
+    }
+
+    /// Forgets the nodes whose lifecycle stream closed since their last command, so an instance
+    /// is not placed on a node which is gone while another one could run it. A node which
+    /// reconnects opens a new stream, the scheduler never dials the nodes itself.
+    async fn disconnect_closed(&mut self) {
+        let closed: Vec<NodeIdentifier> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.is_closed())
+            .map(|(node_id, _)| node_id.clone())
+            .collect();
+        for node_id in closed {
+            self.disconnect(&node_id).await;
+        }
+    }
+
+    /// Sends a command to a node, the node is forgotten if its stream is closed. The command is
+    /// dropped if the caller of the event being handled already gave up, and refused at once
+    /// while the node is suspect.
+    async fn send(&mut self, node_id: &str, command: Command) -> Result<(), tonic::Status> {
+        let not_connected =
+            || tonic::Status::unavailable(format!("node {} is not connected", node_id));
+        let node = self.nodes.get_mut(node_id).ok_or_else(not_connected)?;
+        let sender = node.sender.clone().ok_or_else(not_connected)?;
+
+        if !node.breaker.allow(node_id) {
+            return Err(tonic::Status::unavailable(format!(
+                "node {} is suspect, its commands are paused",
+                node_id
+            )));
+        }
+
+        if self.deadline.is_expired() {
+            return Err(tonic::Status::deadline_exceeded(format!(
+                "the caller gave up before the command was sent to node {}",
+                node_id
+            )));
+        }
+        let command = InstanceCommand {
+            command: Some(command),
+            deadline: self.deadline.unix_millis(),
+            trace_context: inject_fields(&Context::current()),
+        };
+        match timeout(self.deadline.bound(self.timeout), sender.send(Ok(command))).await {
+            Ok(Ok(())) => {
+                node.breaker.record_success(node_id);
+                Ok(())
+            }
+            Ok(Err(_)) => {
+                warn!("lifecycle stream of node {} is closed", node_id);
+                self.disconnect(node_id).await;
+                Err(not_connected())
+            }
+            Err(_) => {
+                // a command cut short by the deadline of its caller doesn't tell the node is hung
+                if !self.deadline.is_expired() {
+                    node.breaker.record_failure(node_id, &self.breaker_policy);
+                }
+                Err(tonic::Status::deadline_exceeded(format!(
+                    "node {} didn't take the command in time",
+                    node_id
+                )))
+            }
+        }
+    }
+}
+
+/// Converts an instance from the scheduler api to the agent one.
+fn to_agent_instance(instance: Instance) -> agent::Instance {
+    let r#type = match instance.r#type() {
+        Type::Container => agent::Type::Container,
+        Type::Wasm => agent::Type::Wasm,
+    };
+    agent::Instance {
+        id: instance.id,
+        name: instance.name,
+        r#type: r#type.into(),
+        status: agent::Status::Scheduled.into(),
+        uri: instance.uri,
+        environment: instance.environnement,
+        resource: instance.resource.map(|resource| agent::Resource {
+            limit: resource.limit.map(to_agent_summary),
+            usage: resource.usage.map(to_agent_summary),
+        }),
+        ports: instance
+            .ports
+            .into_iter()
+            .map(|port| agent::Port {
+                source: port.source,
+                destination: port.destination,
+            })
+            .collect(),
+        ip: instance.ip,
+        security_context: instance.security_context,
+        kind: instance.kind,
+        volumes: instance.volumes,
+        sidecars: instance.sidecars,
+        restore: instance.restore,
+        devices: instance.devices,
+        exclusive_cpus: instance.exclusive_cpus,
+        huge_pages: instance.huge_pages,
+    }
+}
+
+fn to_agent_summary(summary: ResourceSummary) -> agent::ResourceSummary {
+    agent::ResourceSummary {
+        cpu: summary.cpu,
+        memory: summary.memory,
+        disk: summary.disk,
+    }
+}
+
+fn to_scheduler_summary(summary: agent::ResourceSummary) -> ResourceSummary {
+    ResourceSummary {
+        cpu: summary.cpu,
+        memory: summary.memory,
+        disk: summary.disk,
+    }
+}
+
+/// Converts an instance status sent by a node to the scheduler api one.
+fn to_scheduler_status(node_id: &str, status: agent::InstanceStatus) -> InstanceStatus {
+    let state = match status.status() {
+        agent::Status::Running => Status::Running,
+        agent::Status::Starting => Status::Starting,
+        agent::Status::Stopping => Status::Stopping,
+        agent::Status::Destroying => Status::Destroying,
+        agent::Status::Terminated => Status::Terminated,
+        agent::Status::Crashed | agent::Status::Failed => Status::Failed,
+        agent::Status::Scheduling => Status::Scheduling,
+        agent::Status::Scheduled => Status::Scheduled,
+        agent::Status::Pulling => Status::Pulling,
+    };
+
+    InstanceStatus {
+        id: status.id,
+        status: state.into(),
+        status_description: status.description,
+        resource: status.resource.map(|resource| Resource {
+            limit: resource.limit.map(to_scheduler_summary),
+            usage: resource.usage.map(to_scheduler_summary),
+        }),
+        node_id: node_id.to_string(),
+        eviction: None,
+        logs: status.logs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::{EvictionReason, Port};
+    use tokio::sync::oneshot;
+
+    use crate::config::ProfileConfig;
+
+    use super::*;
+
+    pub(super) const TIMEOUT: Duration = Duration::from_millis(100);
+
+    pub(super) fn instance(id: &str) -> Instance {
+        Instance {
+            id: id.to_string(),
+            name: id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_without_node() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (tx, _rx) = mpsc::channel(1);
+
+        let err = connections.create(instance("a"), tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_create_balances_nodes() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, mut commands_a) = mpsc::channel(4);
+        let (node_b, mut commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            connections.create(instance("1"), tx.clone()).await.unwrap(),
+            "a"
+        );
+        assert_eq!(connections.create(instance("2"), tx).await.unwrap(), "b");
+
+        let command = commands_a.recv().await.unwrap().unwrap().command;
+        assert!(matches!(command, Some(Command::Create(instance)) if instance.id == "1"));
+        let command = commands_b.recv().await.unwrap().unwrap().command;
+        assert!(matches!(command, Some(Command::Create(instance)) if instance.id == "2"));
+    }
+
+    #[tokio::test]
+    async fn test_create_skips_closed_node() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+
+        // "b" is gone but its stream was not used since
+        drop(commands_b);
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            connections.create(instance("1"), tx.clone()).await.unwrap(),
+            "a"
+        );
+        assert_eq!(connections.create(instance("2"), tx).await.unwrap(), "a");
+        assert!(!connections
+            .snapshot()
+            .nodes
+            .iter()
+            .any(|node| node.id == "b"));
+    }
+
+    #[tokio::test]
+    async fn test_create_on_capable_node() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.register_capabilities(
+            "a".to_string(),
+            Some(NodeCapabilities {
+                rootless: true,
+                unprivileged_port_start: 1024,
+                ..Default::default()
+            }),
+        );
+
+        let mut web = instance("1");
+        web.ports = vec![Port {
+            source: 80,
+            destination: 80,
+        }];
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            connections.create(web.clone(), tx.clone()).await.unwrap(),
+            "b"
+        );
+        assert_eq!(
+            connections.create(instance("2"), tx.clone()).await.unwrap(),
+            "a"
+        );
+
+        connections.register_capabilities(
+            "b".to_string(),
+            Some(NodeCapabilities {
+                rootless: true,
+                unprivileged_port_start: 1024,
+                ..Default::default()
+            }),
+        );
+        let err = connections.create(web, tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_features() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, mut commands_a) = mpsc::channel(4);
+        let (node_b, mut commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.register_features("a".to_string(), vec![Feature::Checkpoint]);
+        assert!(!connections.supports("a", Feature::Wasm));
+        assert!(connections.supports("b", Feature::Wasm));
+
+        // the WASM instances are only placed on the nodes running them
+        let mut module = instance("1");
+        module.r#type = Type::Wasm.into();
+        let (tx, _rx) = mpsc::channel(4);
+        assert_eq!(connections.create(module, tx.clone()).await.unwrap(), "b");
+        match commands_b.recv().await.unwrap().unwrap().command {
+            Some(Command::Create(instance)) => assert_eq!(instance.r#type(), agent::Type::Wasm),
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        // an agent without the checkpoints isn't asked to write one
+        connections.register_features("b".to_string(), vec![Feature::Wasm]);
+        let mut pinned = instance("2");
+        pinned.node_id = "b".to_string();
+        connections.create(pinned, tx).await.unwrap();
+        commands_b.recv().await.unwrap().unwrap();
+        let (reply, result) = oneshot::channel();
+        let request = agent::Checkpoint {
+            instance_id: "2".to_string(),
+            name: "before-upgrade".to_string(),
+            leave_running: true,
+        };
+        connections.checkpoint(request, reply).await;
+        let err = result.await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(commands_b.try_recv().is_err());
+        assert!(commands_a.try_recv().is_err());
+
+        let snapshot = connections.snapshot();
+        assert_eq!(snapshot.nodes[0].features, vec![Feature::Checkpoint as i32]);
+        assert_eq!(snapshot.nodes[1].features, vec![Feature::Wasm as i32]);
+
+        // a node registering again without features performs all of them
+        connections.register_features("b".to_string(), vec![]);
+        assert!(connections.supports("b", Feature::Checkpoint));
+    }
+
+    #[tokio::test]
+    async fn test_create_on_platform() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let mut commands = vec![];
+        for id in ["a", "b", "c"] {
+            let (node, node_commands) = mpsc::channel(4);
+            connections.connect(id.to_string(), node);
+            commands.push(node_commands);
+        }
+        let platform = |arch: &str| Platform {
+            os: "linux".to_string(),
+            arch: arch.to_string(),
+            ..Default::default()
+        };
+        connections.register_platform("a".to_string(), Some(platform("arm64")));
+        connections.register_platform("b".to_string(), Some(platform("amd64")));
+        // a node predating the platforms is matched on the architecture of its capabilities
+        connections.register_capabilities(
+            "c".to_string(),
+            Some(NodeCapabilities {
+                arch: "arm64".to_string(),
+                ..Default::default()
+            }),
+        );
+
+        let mut amd64 = instance("1");
+        amd64.platforms = vec![platform("amd64")];
+        let (tx, _rx) = mpsc::channel(4);
+        assert_eq!(connections.create(amd64, tx.clone()).await.unwrap(), "b");
+
+        let mut arm64 = instance("2");
+        arm64.platforms = vec![platform("arm64")];
+        let node_id = connections.create(arm64, tx.clone()).await.unwrap();
+        assert!(node_id == "a" || node_id == "c");
+
+        let mut riscv = instance("3");
+        riscv.platforms = vec![platform("riscv64")];
+        let err = connections.create(riscv, tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err.message().contains("linux/riscv64"));
+    }
+
+    #[tokio::test]
+    async fn test_create_with_devices() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.update_node_status(NodeStatus {
+            id: "b".to_string(),
+            devices: vec![agent::Device {
+                id: "0".to_string(),
+                kind: "gpu".to_string(),
+                paths: vec!["/dev/nvidia0".to_string()],
+            }],
+            ..Default::default()
+        });
+
+        let gpu = |id| Instance {
+            devices: HashMap::from([("gpu".to_string(), 1)]),
+            ..instance(id)
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(connections.create(gpu("1"), tx.clone()).await.unwrap(), "b");
+
+        // the only device of the node is used
+        let err = connections.create(gpu("2"), tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err
+            .message()
+            .contains("1 gpu device(s) requested, 0 free on the node"));
+    }
+
+    #[tokio::test]
+    async fn test_create_with_exclusive_cpus() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, _commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let pinned = |id, cpus| Instance {
+            exclusive_cpus: cpus,
+            ..instance(id)
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        let err = connections
+            .create(pinned("1", 2), tx.clone())
+            .await
+            .unwrap_err();
+        assert!(err.message().contains("CPU topology"));
+
+        connections.update_node_status(NodeStatus {
+            id: "a".to_string(),
+            numa_nodes: vec![
+                agent::NumaNode {
+                    id: 0,
+                    cpus: vec![0, 1, 2, 3],
+                    pinned: 1,
+                },
+                agent::NumaNode {
+                    id: 1,
+                    cpus: vec![4, 5],
+                    pinned: 0,
+                },
+            ],
+            ..Default::default()
+        });
+        // the instance takes the second NUMA node, the first one keeps 3 free CPUs
+        connections
+            .create(pinned("1", 2), tx.clone())
+            .await
+            .unwrap();
+        connections
+            .create(pinned("2", 3), tx.clone())
+            .await
+            .unwrap();
+
+        // the CPUs of an instance never span two NUMA nodes
+        let err = connections.create(pinned("3", 1), tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err
+            .message()
+            .contains("1 exclusive CPU(s) requested, at most 0 free on a NUMA node"));
+    }
+
+    #[tokio::test]
+    async fn test_create_with_huge_pages() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, _commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+        connections.update_node_status(NodeStatus {
+            id: "a".to_string(),
+            huge_pages: vec![agent::HugePages {
+                size: "2Mi".to_string(),
+                total: 512,
+                reserved: 128,
+            }],
+            ..Default::default()
+        });
+
+        let huge = |id, pages| Instance {
+            huge_pages: HashMap::from([("2Mi".to_string(), pages)]),
+            ..instance(id)
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        connections
+            .create(huge("1", 256), tx.clone())
+            .await
+            .unwrap();
+
+        // the pages of the instance not reported by the node yet are taken into account
+        let err = connections
+            .create(huge("2", 256), tx.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(err
+            .message()
+            .contains("256 huge page(s) of 2Mi requested, 128 free on the node"));
+
+        let gigantic = Instance {
+            huge_pages: HashMap::from([("1Gi".to_string(), 1)]),
+            ..instance("3")
+        };
+        assert!(connections.create(gigantic, tx).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_on_pinned_node() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+
+        let (tx, _rx) = mpsc::channel(1);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+
+        // "a" hosts an instance already but the instance is pinned to it
+        let mut daemon = instance("2");
+        daemon.node_id = "a".to_string();
+        assert_eq!(connections.create(daemon, tx.clone()).await.unwrap(), "a");
+
+        let mut daemon = instance("3");
+        daemon.node_id = "c".to_string();
+        let err = connections.create(daemon, tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn test_create_skips_cordoned_node() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.cordon("a".to_string(), true);
+
+        let (tx, _rx) = mpsc::channel(1);
+        assert_eq!(
+            connections.create(instance("1"), tx.clone()).await.unwrap(),
+            "b"
+        );
+        assert_eq!(
+            connections.create(instance("2"), tx.clone()).await.unwrap(),
+            "b"
+        );
+        assert!(connections.snapshot().nodes[0].cordoned);
+
+        // the instances pinned to the node are still placed on it
+        let mut daemon = instance("3");
+        daemon.node_id = "a".to_string();
+        assert_eq!(connections.create(daemon, tx.clone()).await.unwrap(), "a");
+
+        connections.cordon("a".to_string(), false);
+        assert_eq!(connections.create(instance("4"), tx).await.unwrap(), "a");
+        assert!(!connections.snapshot().nodes[0].cordoned);
+    }
+
+    #[tokio::test]
+    async fn test_create_spreads_workload() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(8);
+        let (node_b, _commands_b) = mpsc::channel(8);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+
+        let replica = |id: &str, spread: Spread| {
+            let mut replica = instance(id);
+            replica.workload_id = "default.web".to_string();
+            replica.set_spread(spread);
+            replica
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+        assert_eq!(
+            connections
+                .create(replica("3", Spread::Strict), tx.clone())
+                .await
+                .unwrap(),
+            "b"
+        );
+        connections.create(instance("2"), tx.clone()).await.unwrap();
+        // b hosts fewer instances, but one of the workload
+        assert_eq!(
+            connections
+                .create(replica("4", Spread::Strict), tx.clone())
+                .await
+                .unwrap(),
+            "a"
+        );
+
+        let err = connections
+            .create(replica("5", Spread::Strict), tx.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        assert!(connections
+            .create(replica("5", Spread::Preferred), tx)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_matches_constraint() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.register_capabilities(
+            "b".to_string(),
+            Some(NodeCapabilities {
+                arch: "arm64".to_string(),
+                labels: HashMap::from([("disk".to_string(), "ssd".to_string())]),
+                ..Default::default()
+            }),
+        );
+
+        let constrained = |id: &str, constraint: &str| {
+            let mut constrained = instance(id);
+            constrained.constraint = constraint.to_string();
+            constrained
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+        assert_eq!(
+            connections
+                .create(
+                    constrained("2", r#"node.labels.disk == "ssd" && node.arch == "arm64""#),
+                    tx.clone()
+                )
+                .await
+                .unwrap(),
+            "b"
+        );
+        assert_eq!(
+            connections
+                .create(constrained("3", r#"node.labels.disk != "ssd""#), tx.clone())
+                .await
+                .unwrap(),
+            "a"
+        );
+
+        let err = connections
+            .create(constrained("4", r#"node.arch == "riscv64""#), tx.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        let err = connections
+            .create(constrained("5", "node.arch = arm64"), tx)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_create_in_pool() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+        connections.register_capabilities(
+            "b".to_string(),
+            Some(NodeCapabilities {
+                pool: "gpu".to_string(),
+                ..Default::default()
+            }),
+        );
+
+        let pooled = |id: &str, pool: &str| {
+            let mut pooled = instance(id);
+            pooled.pool = pool.to_string();
+            pooled
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+        assert_eq!(
+            connections
+                .create(pooled("2", "gpu"), tx.clone())
+                .await
+                .unwrap(),
+            "b"
+        );
+        assert_eq!(
+            connections
+                .create(pooled("3", "gpu"), tx.clone())
+                .await
+                .unwrap(),
+            "b"
+        );
+
+        let err = connections
+            .create(pooled("4", "batch"), tx)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_create_with_namespace_profile() {
+        let profiles = ProfilesConfig {
+            namespaces: HashMap::from([(
+                "batch".to_string(),
+                ProfileConfig {
+                    strategy: Strategy::Binpack,
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        let mut connections = NodeConnections::new(TIMEOUT).with_profiles(profiles);
+        let (node_a, _commands_a) = mpsc::channel(4);
+        let (node_b, _commands_b) = mpsc::channel(4);
+        connections.connect("a".to_string(), node_a);
+        connections.connect("b".to_string(), node_b);
+
+        let namespaced = |id: &str, namespace: &str| {
+            let mut namespaced = instance(id);
+            namespaced.namespace = namespace.to_string();
+            namespaced
+        };
+        let (tx, _rx) = mpsc::channel(1);
+        // the batch instances are packed on the same node
+        for id in ["1", "2", "3"] {
+            assert_eq!(
+                connections
+                    .create(namespaced(id, "batch"), tx.clone())
+                    .await
+                    .unwrap(),
+                "a"
+            );
+        }
+        // the other namespaces keep the default profile
+        assert_eq!(
+            connections
+                .create(namespaced("4", "web"), tx)
+                .await
+                .unwrap(),
+            "b"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_report_and_disconnect() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx).await.unwrap();
+
+        let status = agent::InstanceStatus {
+            id: "1".to_string(),
+            status: agent::Status::Crashed.into(),
+            ..Default::default()
+        };
+        connections.report("a", status).await;
+        let status = rx.recv().await.unwrap().unwrap();
+        assert_eq!(status.status(), Status::Failed);
+        assert_eq!(status.node_id, "a");
+
+        drop(commands);
+        connections.disconnect("a").await;
+        let err = rx.recv().await.unwrap().unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+
+        let err = connections.signal("1", Signal::Stop).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_evict() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx).await.unwrap();
+        commands.recv().await.unwrap().unwrap();
+
+        let eviction = Eviction {
+            reason: EvictionReason::Preemption.into(),
+            message: "preempted by instance 2".to_string(),
+        };
+        connections.evict("1", eviction.clone()).await.unwrap();
+        let command = commands.recv().await.unwrap().unwrap();
+        match command.command {
+            Some(Command::Signal(instruction)) => {
+                assert_eq!(instruction.signal(), Signal::Stop)
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+
+        let status = agent::InstanceStatus {
+            id: "1".to_string(),
+            status: agent::Status::Stopping.into(),
+            ..Default::default()
+        };
+        connections.report("a", status).await;
+        let status = rx.recv().await.unwrap().unwrap();
+        assert_eq!(status.eviction, Some(eviction.clone()));
+
+        let err = connections.evict("2", eviction).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_breaker() {
+        let mut connections = NodeConnections::new(TIMEOUT).with_breakers(BreakerPolicy {
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+        });
+        // the node never reads its stream, it is full after the first command
+        let (node, _commands) = mpsc::channel(1);
+        connections.connect("a".to_string(), node);
+
+        let (tx, _rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+        for id in ["2", "3"] {
+            let err = connections
+                .create(instance(id), tx.clone())
+                .await
+                .unwrap_err();
+            assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        }
+        assert!(connections.snapshot().nodes[0].suspect);
+
+        // the commands of the node are refused at once, no new instance is placed on it
+        let err = connections.signal("1", Signal::Stop).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unavailable);
+        let err = connections.create(instance("4"), tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+
+        // the node opens a new stream
+        let (node, _commands) = mpsc::channel(1);
+        connections.connect("a".to_string(), node);
+        assert!(!connections.snapshot().nodes[0].suspect);
+        connections.signal("1", Signal::Stop).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_throttle_status() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        connections.register_status_interval("a".to_string(), Duration::from_secs(1));
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        // the node already sends its statuses every second
+        connections
+            .throttle_status("a", Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(commands.try_recv().is_err());
+
+        for _ in 0..2 {
+            connections
+                .throttle_status("a", Duration::from_secs(4))
+                .await
+                .unwrap();
+        }
+        let command = commands.recv().await.unwrap().unwrap();
+        assert_eq!(command.command, Some(Command::StatusIntervalMs(4000)));
+        assert!(commands.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let (tx, _rx) = mpsc::channel(4);
+        connections.set_deadline(Deadline::after(Duration::from_secs(60)));
+        connections.create(instance("1"), tx).await.unwrap();
+        let command = commands.recv().await.unwrap().unwrap();
+        assert!(command.deadline > 0);
+
+        // the caller gave up, the node isn't asked to stop the instance
+        connections.set_deadline(Deadline::after(Duration::ZERO));
+        let err = connections.signal("1", Signal::Stop).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+        assert!(commands.try_recv().is_err());
+
+        connections.set_deadline(Deadline::none());
+        connections.signal("1", Signal::Stop).await.unwrap();
+        let command = commands.recv().await.unwrap().unwrap();
+        assert_eq!(command.deadline, 0);
+    }
+
+    #[tokio::test]
+    async fn test_trace_context() {
+        use opentelemetry::global;
+        use opentelemetry::sdk::propagation::TraceContextPropagator;
+        use opentelemetry::trace::{
+            FutureExt, SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        };
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let (tx, _rx) = mpsc::channel(4);
+        let cx = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+        connections
+            .create(instance("1"), tx)
+            .with_context(cx)
+            .await
+            .unwrap();
+        let command = commands.recv().await.unwrap().unwrap();
+        assert_eq!(
+            command.trace_context.get("traceparent").unwrap(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_job_is_forgotten() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, _commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let (tx, _rx) = mpsc::channel(4);
+        let mut job = instance("1");
+        job.kind = agent::WorkloadKind::Job.into();
+        connections.create(job, tx.clone()).await.unwrap();
+        connections.create(instance("2"), tx).await.unwrap();
+
+        for id in ["1", "2"] {
+            let status = agent::InstanceStatus {
+                id: id.to_string(),
+                status: agent::Status::Failed.into(),
+                ..Default::default()
+            };
+            connections.report("a", status).await;
+        }
+
+        // the failed service is kept to be restarted, the failed job is over
+        let snapshot = connections.snapshot();
+        assert_eq!(snapshot.placements.len(), 1);
+        assert_eq!(snapshot.placements[0].instance_id, "2");
+    }
+
+    #[tokio::test]
+    async fn test_hung_node() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        // the stream of the node is never read, it is full after the first command
+        let (node, _commands) = mpsc::channel(1);
+        connections.connect("a".to_string(), node);
+
+        let (tx, _rx) = mpsc::channel(1);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+        let err = connections.create(instance("2"), tx).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, _commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+        connections.update_node_status(NodeStatus {
+            id: "b".to_string(),
+            ..Default::default()
+        });
+
+        let (tx, _rx) = mpsc::channel(4);
+        connections.create(instance("1"), tx.clone()).await.unwrap();
+        connections.create(instance("2"), tx).await.unwrap();
+        let status = agent::InstanceStatus {
+            id: "2".to_string(),
+            ..Default::default()
+        };
+        connections.report("a", status).await;
+
+        let snapshot = connections.snapshot();
+        let nodes: Vec<(&str, bool)> = snapshot
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.connected))
+            .collect();
+        assert_eq!(nodes, vec![("a", true), ("b", false)]);
+        assert_eq!(snapshot.placements.len(), 1);
+        assert_eq!(snapshot.placements[0].instance_id, "2");
+        assert_eq!(snapshot.placements[0].node_id, "a");
+        assert_eq!(snapshot.pending.len(), 1);
+        assert_eq!(snapshot.pending[0].instance_id, "1");
+    }
+}
@@ -0,0 +1,56 @@
+use std::{collections::HashSet, time::Duration};
+
+use proto::scheduler::{Feature, NodeCapabilities, NodeStatus, Platform};
+
+use crate::breaker::Breaker;
+
+use super::CommandSender;
+
+/// `NodeState` is what the scheduler knows of a node. The node is known once it registered,
+/// connected its lifecycle stream or sent a status, and it stays known once disconnected.
+///
+/// Properties:
+///
+/// * `sender`: The lifecycle stream of the node, `None` while it is disconnected.
+/// * `status`: The last status sent by the node.
+/// * `capabilities`: The capabilities reported by a restricted node, e.g. a rootless one. A node
+///   without can run any instance.
+/// * `features`: The optional operations the agent of the node performs, e.g. the checkpoints.
+///   An agent without predates the negotiation and is assumed to perform all of them.
+/// * `platform`: The OS and architecture of the node. The architecture of a node without is the
+///   one of its capabilities, if any.
+/// * `cordoned`: If true, no new instance is placed on the node, except the ones pinned to it.
+/// * `breaker`: The circuit breaker of the node, pausing its commands while it is suspect.
+/// * `status_interval`: The interval the node was last told to send its statuses at.
+#[derive(Debug, Default)]
+pub(super) struct NodeState {
+    pub sender: Option<CommandSender>,
+    pub status: Option<NodeStatus>,
+    pub capabilities: Option<NodeCapabilities>,
+    pub features: Option<HashSet<Feature>>,
+    pub platform: Option<Platform>,
+    pub cordoned: bool,
+    pub breaker: Breaker,
+    pub status_interval: Option<Duration>,
+}
+
+impl NodeState {
+    /// Returns `true` while the lifecycle stream of the node is connected.
+    pub fn is_connected(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Returns `true` if the lifecycle stream of the node was closed since its last command.
+    pub fn is_closed(&self) -> bool {
+        self.sender
+            .as_ref()
+            .is_some_and(|sender| sender.is_closed())
+    }
+
+    /// Returns `true` if the agent of the node performs an operation.
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features
+            .as_ref()
+            .is_none_or(|features| features.contains(&feature))
+    }
+}
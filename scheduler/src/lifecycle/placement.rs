@@ -0,0 +1,582 @@
+use proto::{
+    agent,
+    scheduler::{
+        Eviction, Feature, Instance, InstanceStatus, NodeCapabilities, NodeStatus, Platform,
+        ResourceSummary, Spread, Status, Type,
+    },
+};
+
+use crate::config::WeightsConfig;
+use crate::parser::{self, Constraint, NodeAttributes};
+use crate::NodeIdentifier;
+
+use super::NodeConnections;
+
+/// An instance placed on a node, `request` being the instance as sent by the controller and
+/// `instance` as sent to the node. `eviction` is set once the controller evicted it, and
+/// `migrating` while its node checkpoints it for its live migration.
+#[derive(Debug)]
+pub(super) struct Placement {
+    pub node_id: NodeIdentifier,
+    pub workload_id: String,
+    pub request: Instance,
+    pub instance: agent::Instance,
+    pub status: Option<InstanceStatus>,
+    pub eviction: Option<Eviction>,
+    pub migrating: bool,
+}
+
+impl NodeConnections {
+    /// Returns the last status sent by a node, if any.
+    fn node_status(&self, node_id: &str) -> Option<&NodeStatus> {
+        self.nodes.get(node_id)?.status.as_ref()
+    }
+
+    /// Returns the capabilities reported by a node, `None` if it can run any instance.
+    fn node_capabilities(&self, node_id: &str) -> Option<&NodeCapabilities> {
+        self.nodes.get(node_id)?.capabilities.as_ref()
+    }
+
+    /// Returns the number of instances of a workload placed on a node.
+    pub(super) fn siblings(&self, node_id: &str, workload_id: &str) -> usize {
+        self.placements
+            .values()
+            .filter(|placement| {
+                placement.node_id == node_id && placement.workload_id == workload_id
+            })
+            .count()
+    }
+
+    /// Returns the load of a node weighted by a profile: the number of instances placed on it and
+    /// the percentages of its cpu and memory in use according to its last status.
+    pub(super) fn load(&self, node_id: &str, weights: &WeightsConfig) -> u64 {
+        let count = self
+            .placements
+            .values()
+            .filter(|placement| placement.node_id == node_id)
+            .count() as u64;
+        let (cpu, memory) = self
+            .node_status(node_id)
+            .and_then(|status| status.resource.as_ref())
+            .map_or((0, 0), |resource| {
+                let capacity = resource.limit.clone().unwrap_or_default();
+                let usage = resource.usage.clone().unwrap_or_default();
+                (
+                    percentage(usage.cpu, capacity.cpu),
+                    percentage(usage.memory, capacity.memory),
+                )
+            });
+        weights.instances * count + weights.cpu * cpu + weights.memory * memory
+    }
+
+    /// Returns which kind of device a node lacks to run an instance, `None` if it has enough: the
+    /// devices advertised in its last status, less the ones requested by the instances placed on
+    /// it, are free.
+    fn missing_device(&self, node_id: &str, instance: &Instance) -> Option<String> {
+        let advertised = self
+            .node_status(node_id)
+            .map_or(&[][..], |status| &status.devices);
+        let mut kinds: Vec<(&String, &u32)> = instance.devices.iter().collect();
+        kinds.sort();
+        kinds.into_iter().find_map(|(kind, requested)| {
+            let total = advertised
+                .iter()
+                .filter(|device| device.kind == *kind)
+                .count() as u64;
+            let used: u64 = self
+                .placements
+                .values()
+                .filter(|placement| placement.node_id == node_id)
+                .filter_map(|placement| placement.request.devices.get(kind))
+                .map(|count| *count as u64)
+                .sum();
+            let free = total.saturating_sub(used);
+            (*requested as u64 > free).then(|| {
+                format!(
+                    "{} {} device(s) requested, {} free on the node",
+                    requested, kind, free
+                )
+            })
+        })
+    }
+
+    /// Returns why a node can't pin the exclusive CPUs of an instance on a single NUMA node,
+    /// `None` if it can or if the instance has none. The CPUs free on each NUMA node are the ones
+    /// of the last status of the node, less the ones of the instances placed on it which didn't
+    /// report yet, given the NUMA nodes the node would give them.
+    fn missing_cpus(&self, node_id: &str, instance: &Instance) -> Option<String> {
+        if instance.exclusive_cpus == 0 {
+            return None;
+        }
+        let Some(status) = self
+            .node_status(node_id)
+            .filter(|status| !status.numa_nodes.is_empty())
+        else {
+            return Some("the node doesn't report its CPU topology".to_string());
+        };
+
+        let mut free: Vec<u32> = status
+            .numa_nodes
+            .iter()
+            .map(|numa_node| (numa_node.cpus.len() as u32).saturating_sub(numa_node.pinned))
+            .collect();
+        let pending = self
+            .placements
+            .values()
+            .filter(|placement| placement.node_id == node_id && placement.status.is_none())
+            .map(|placement| placement.request.exclusive_cpus);
+        for cpus in pending.filter(|cpus| *cpus > 0) {
+            if let Some(numa_node) = best_fit(&free, cpus) {
+                free[numa_node] -= cpus;
+            }
+        }
+
+        best_fit(&free, instance.exclusive_cpus).is_none().then(|| {
+            format!(
+                "{} exclusive CPU(s) requested, at most {} free on a NUMA node",
+                instance.exclusive_cpus,
+                free.iter().max().unwrap_or(&0)
+            )
+        })
+    }
+
+    /// Returns which size of huge pages a node lacks to run an instance, `None` if it has enough:
+    /// the pages of its last status not reserved yet, less the ones of the instances placed on it
+    /// which didn't report yet, are free.
+    fn missing_huge_pages(&self, node_id: &str, instance: &Instance) -> Option<String> {
+        let reported = self
+            .node_status(node_id)
+            .map_or(&[][..], |status| &status.huge_pages);
+        let mut sizes: Vec<(&String, &u64)> = instance.huge_pages.iter().collect();
+        sizes.sort();
+        sizes.into_iter().find_map(|(size, requested)| {
+            let free = reported
+                .iter()
+                .find(|pages| pages.size == *size)
+                .map_or(0, |pages| pages.total.saturating_sub(pages.reserved));
+            let pending: u64 = self
+                .placements
+                .values()
+                .filter(|placement| placement.node_id == node_id && placement.status.is_none())
+                .filter_map(|placement| placement.request.huge_pages.get(size))
+                .sum();
+            let free = free.saturating_sub(pending);
+            (*requested > free).then(|| {
+                format!(
+                    "{} huge page(s) of {} requested, {} free on the node",
+                    requested, size, free
+                )
+            })
+        })
+    }
+
+    /// Returns why the platform of a node doesn't match the ones of the images of an instance,
+    /// `None` if it matches one of them or the instance declares none. A node which didn't
+    /// report its platform only has the architecture of its capabilities, if any.
+    fn missing_platform(&self, node_id: &str, instance: &Instance) -> Option<String> {
+        if instance.platforms.is_empty() {
+            return None;
+        }
+        let platform = match self
+            .nodes
+            .get(node_id)
+            .and_then(|node| node.platform.as_ref())
+        {
+            Some(platform) => platform.clone(),
+            None => Platform {
+                arch: self
+                    .node_capabilities(node_id)
+                    .map(|capabilities| capabilities.arch.clone())
+                    .unwrap_or_default(),
+                ..Default::default()
+            },
+        };
+        if instance
+            .platforms
+            .iter()
+            .any(|image| runs_on(image, &platform))
+        {
+            return None;
+        }
+
+        let images: Vec<String> = instance.platforms.iter().map(platform_name).collect();
+        Some(format!(
+            "the node is {}, the images are built for {}",
+            platform_name(&platform),
+            images.join(", ")
+        ))
+    }
+
+    /// Returns which feature the agent of a node lacks to run an instance, `None` if it has them:
+    /// the WASM instances need the `WASM` feature and the restored ones the `CHECKPOINT` one.
+    fn missing_feature(&self, node_id: &str, instance: &Instance) -> Option<String> {
+        if instance.r#type() == Type::Wasm && !self.supports(node_id, Feature::Wasm) {
+            return Some("the node doesn't run WASM instances".to_string());
+        }
+        if instance.restore.is_some() && !self.supports(node_id, Feature::Checkpoint) {
+            return Some("the node doesn't restore checkpoints".to_string());
+        }
+        None
+    }
+
+    /// Returns why a node can't run an instance, `None` if it can: the node lacks a capability,
+    /// a feature, resources, devices, CPUs to pin or huge pages, its platform doesn't match the
+    /// images of the instance, it is outside of the pool of the instance, it doesn't match its
+    /// constraint or it already runs an instance of its strictly spread workload.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node.
+    /// * `instance`: The instance to place.
+    /// * `constraint`: The constraint of the instance, parsed.
+    /// * `spread`: The spread of the instance, `Any` if it is pinned to a node.
+    pub(super) fn unmet(
+        &self,
+        node_id: &str,
+        instance: &Instance,
+        constraint: Option<&Constraint>,
+        spread: Spread,
+    ) -> Option<String> {
+        // the nodes which didn't report their capabilities have no architecture nor label, they
+        // are in the default pool
+        let unknown = NodeCapabilities::default();
+        let profile = self.profiles.get(&instance.namespace);
+        let limit = total_limit(instance);
+        self.node_capabilities(node_id)
+            .and_then(|capabilities| unmet_requirement(capabilities, instance, &limit))
+            .or_else(|| self.missing_feature(node_id, instance))
+            .or_else(|| self.missing_platform(node_id, instance))
+            .or_else(|| {
+                self.node_status(node_id)
+                    .and_then(|status| missing_resource(status, &limit, profile.overcommit))
+            })
+            .or_else(|| self.missing_device(node_id, instance))
+            .or_else(|| self.missing_cpus(node_id, instance))
+            .or_else(|| self.missing_huge_pages(node_id, instance))
+            .or_else(|| {
+                let capabilities = self.node_capabilities(node_id).unwrap_or(&unknown);
+                if !instance.pool.is_empty() && capabilities.pool != instance.pool {
+                    return Some(format!("the node is not in pool {}", instance.pool));
+                }
+                let attributes = NodeAttributes {
+                    id: node_id,
+                    arch: &capabilities.arch,
+                    labels: &capabilities.labels,
+                };
+                let unmatched =
+                    constraint.is_some_and(|constraint| !constraint.matches(&attributes));
+                unmatched.then(|| {
+                    format!(
+                        "the node doesn't match the constraint {}",
+                        instance.constraint
+                    )
+                })
+            })
+            .or_else(|| {
+                let conflict =
+                    spread == Spread::Strict && self.siblings(node_id, &instance.workload_id) > 0;
+                conflict.then(|| {
+                    format!(
+                        "the node already runs an instance of workload {}",
+                        instance.workload_id
+                    )
+                })
+            })
+    }
+
+    /// Returns `true` if an instance may be stopped to be moved: its workload has no disruption
+    /// budget, or enough of its other instances run without it.
+    pub(super) fn keeps_budget(&self, id: &str, placement: &Placement) -> bool {
+        let min_available = placement.request.min_available as usize;
+        min_available == 0
+            || self
+                .placements
+                .iter()
+                .filter(|(other_id, other)| {
+                    *other_id != id
+                        && other.workload_id == placement.workload_id
+                        && other
+                            .status
+                            .as_ref()
+                            .is_some_and(|status| status.status() == Status::Running)
+                })
+                .count()
+                >= min_available
+    }
+}
+
+/// Returns why a node with restricted capabilities can't run an instance, `None` if it can.
+///
+/// Arguments:
+///
+/// * `capabilities`: The capabilities reported by the node.
+/// * `instance`: The instance to place.
+/// * `limit`: The resources limit of all the containers of the instance.
+fn unmet_requirement(
+    capabilities: &NodeCapabilities,
+    instance: &Instance,
+    limit: &ResourceSummary,
+) -> Option<String> {
+    if let Some(port) = instance
+        .ports
+        .iter()
+        .find(|port| (port.source as u32) < capabilities.unprivileged_port_start)
+    {
+        return Some(format!(
+            "port {} is below the unprivileged ports of the node",
+            port.source
+        ));
+    }
+
+    let limited = limit.cpu > 0 || limit.memory > 0 || limit.disk > 0;
+    if limited && !capabilities.cgroup_limits {
+        return Some("the node can't enforce resource limits".to_string());
+    }
+
+    None
+}
+
+/// Returns `true` if an image built for a platform runs on a node, the fields missing on either
+/// side matching any value, e.g. the images built for `linux/arm` run on the `linux/arm/v7`
+/// nodes and the nodes of unknown OS match on their architecture only.
+fn runs_on(image: &Platform, node: &Platform) -> bool {
+    let matches = |image: &str, node: &str| image.is_empty() || node.is_empty() || image == node;
+    matches(&image.os, &node.os)
+        && matches(&image.arch, &node.arch)
+        && matches(&image.variant, &node.variant)
+}
+
+/// Returns the name of a platform, e.g. `linux/arm64` or `linux/arm/v7`, `unknown` if it is
+/// empty.
+pub(super) fn platform_name(platform: &Platform) -> String {
+    let name: Vec<&str> = [&platform.os, &platform.arch, &platform.variant]
+        .into_iter()
+        .map(String::as_str)
+        .filter(|part| !part.is_empty())
+        .collect();
+    if name.is_empty() {
+        "unknown".to_string()
+    } else {
+        name.join("/")
+    }
+}
+
+/// Parses the constraint of an instance, `None` if it has none.
+#[allow(clippy::result_large_err)]
+pub(super) fn parse_constraint(instance: &Instance) -> Result<Option<Constraint>, tonic::Status> {
+    match instance.constraint.as_str() {
+        "" => Ok(None),
+        constraint => parser::parse(constraint).map(Some).map_err(|err| {
+            tonic::Status::invalid_argument(format!(
+                "invalid constraint of instance {}: {}",
+                instance.id, err
+            ))
+        }),
+    }
+}
+
+/// Returns the spread of an instance, the instances pinned to a node ignore the spread of their
+/// workload.
+pub(super) fn effective_spread(instance: &Instance) -> Spread {
+    if instance.node_id.is_empty() && !instance.workload_id.is_empty() {
+        instance.spread()
+    } else {
+        Spread::Any
+    }
+}
+
+/// Returns the resources limit of an instance: the sum of the limits of its main container and of
+/// its sidecars, which all run on the same node.
+fn total_limit(instance: &Instance) -> ResourceSummary {
+    let main = instance
+        .resource
+        .as_ref()
+        .and_then(|resource| resource.limit.clone())
+        .unwrap_or_default();
+    instance
+        .sidecars
+        .iter()
+        .filter_map(|sidecar| sidecar.resource.as_ref()?.limit.as_ref())
+        .fold(main, |total, limit| ResourceSummary {
+            cpu: total.cpu + limit.cpu,
+            memory: total.memory + limit.memory,
+            disk: total.disk + limit.disk,
+        })
+}
+
+/// Returns `used` as a percentage of `capacity`, 0 if the capacity is unknown.
+fn percentage(used: u64, capacity: u64) -> u64 {
+    if capacity == 0 {
+        return 0;
+    }
+    used.saturating_mul(100) / capacity
+}
+
+/// Returns which resource a node lacks to run an instance, according to the last status it sent,
+/// `None` if the instance fits or if the node didn't report its resources.
+///
+/// Arguments:
+///
+/// * `status`: The last status sent by the node.
+/// * `limit`: The resources limit of all the containers of the instance.
+/// * `overcommit`: The percentage of the capacity of the node its instances may use.
+fn missing_resource(
+    status: &NodeStatus,
+    limit: &ResourceSummary,
+    overcommit: u64,
+) -> Option<String> {
+    let resource = status.resource.as_ref()?;
+    let capacity = resource.limit.clone()?;
+    let capacity = ResourceSummary {
+        cpu: capacity.cpu.saturating_mul(overcommit) / 100,
+        memory: capacity.memory.saturating_mul(overcommit) / 100,
+        disk: capacity.disk.saturating_mul(overcommit) / 100,
+    };
+    let usage = resource.usage.clone().unwrap_or_default();
+    [
+        ("cpu", limit.cpu, capacity.cpu, usage.cpu),
+        ("memory", limit.memory, capacity.memory, usage.memory),
+        ("disk", limit.disk, capacity.disk, usage.disk),
+    ]
+    .into_iter()
+    .find(|(_, requested, capacity, usage)| {
+        *capacity > 0 && *requested > capacity.saturating_sub(*usage)
+    })
+    .map(|(name, requested, capacity, usage)| {
+        format!(
+            "{} {} requested, {} left on the node",
+            name,
+            requested,
+            capacity.saturating_sub(usage)
+        )
+    })
+}
+
+/// Returns the NUMA node with the fewest free CPUs fitting `cpus`, the one the nodes pin them on.
+fn best_fit(free: &[u32], cpus: u32) -> Option<usize> {
+    free.iter()
+        .enumerate()
+        .filter(|(_, free)| **free >= cpus)
+        .min_by_key(|(_, free)| **free)
+        .map(|(numa_node, _)| numa_node)
+}
+
+#[cfg(test)]
+mod tests {
+    use proto::scheduler::Resource;
+
+    use crate::lifecycle::tests::instance;
+
+    use super::*;
+
+    #[test]
+    fn test_runs_on() {
+        let platform = |os: &str, arch: &str, variant: &str| Platform {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            variant: variant.to_string(),
+        };
+        let node = platform("linux", "arm", "v7");
+        assert!(runs_on(&platform("linux", "arm", "v7"), &node));
+        assert!(runs_on(&platform("linux", "arm", ""), &node));
+        assert!(!runs_on(&platform("linux", "arm", "v6"), &node));
+        assert!(!runs_on(&platform("linux", "amd64", ""), &node));
+        assert!(!runs_on(&platform("windows", "arm", ""), &node));
+        assert!(runs_on(
+            &platform("linux", "arm64", ""),
+            &platform("", "arm64", "")
+        ));
+        assert_eq!(platform_name(&node), "linux/arm/v7");
+        assert_eq!(platform_name(&Platform::default()), "unknown");
+    }
+
+    #[test]
+    fn test_unmet_requirement() {
+        let rootless = NodeCapabilities {
+            rootless: true,
+            cgroup_limits: false,
+            unprivileged_port_start: 1024,
+            ..Default::default()
+        };
+        let unlimited = instance("1");
+        assert!(unmet_requirement(&rootless, &unlimited, &total_limit(&unlimited)).is_none());
+
+        let mut limited = instance("1");
+        limited.resource = Some(Resource {
+            limit: Some(ResourceSummary {
+                memory: 512,
+                ..Default::default()
+            }),
+            usage: None,
+        });
+        let limit = total_limit(&limited);
+        assert!(unmet_requirement(&rootless, &limited, &limit).is_some());
+
+        let delegated = NodeCapabilities {
+            cgroup_limits: true,
+            ..rootless
+        };
+        assert!(unmet_requirement(&delegated, &limited, &limit).is_none());
+    }
+
+    #[test]
+    fn test_total_limit_and_missing_resource() {
+        let mut pod = instance("1");
+        pod.resource = Some(Resource {
+            limit: Some(ResourceSummary {
+                cpu: 500,
+                memory: 512,
+                disk: 0,
+            }),
+            usage: None,
+        });
+        pod.sidecars = vec![agent::Container {
+            name: "proxy".to_string(),
+            resource: Some(agent::Resource {
+                limit: Some(agent::ResourceSummary {
+                    cpu: 100,
+                    memory: 128,
+                    disk: 0,
+                }),
+                usage: None,
+            }),
+            ..Default::default()
+        }];
+        let limit = total_limit(&pod);
+        assert_eq!(
+            limit,
+            ResourceSummary {
+                cpu: 600,
+                memory: 640,
+                disk: 0,
+            }
+        );
+
+        let node = |memory_used| NodeStatus {
+            id: "a".to_string(),
+            resource: Some(Resource {
+                limit: Some(ResourceSummary {
+                    cpu: 4000,
+                    memory: 1024,
+                    disk: 0,
+                }),
+                usage: Some(ResourceSummary {
+                    cpu: 1000,
+                    memory: memory_used,
+                    disk: 0,
+                }),
+            }),
+            ..Default::default()
+        };
+        assert!(missing_resource(&node(256), &limit, 100).is_none());
+        // the main container alone would fit, not with its sidecar
+        assert_eq!(
+            missing_resource(&node(512), &limit, 100),
+            Some("memory 640 requested, 512 left on the node".to_string())
+        );
+        // unless the node is overcommitted
+        assert!(missing_resource(&node(512), &limit, 150).is_none());
+        // a node which didn't report its resources can run anything
+        assert!(missing_resource(&NodeStatus::default(), &limit, 100).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use log::{debug, info, warn};
+use proto::{
+    agent::{self, instance_command::Command, ImagePullState},
+    scheduler::{ImagePullRequest, NodeImagePullStatus},
+};
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::NodeIdentifier;
+
+use super::NodeConnections;
+
+/// The sending half of the stream of the image pull statuses returned to the controller.
+pub type PullSender = mpsc::Sender<Result<NodeImagePullStatus, tonic::Status>>;
+
+impl NodeConnections {
+    /// Pulls an image on the given nodes, or on every connected node, ahead of the instances using
+    /// it. The statuses of the pulls are forwarded to `watcher`, which is dropped once every node
+    /// pulled the image or failed to, a node which isn't connected failing right away. A node
+    /// already pulling the image isn't sent the command again, its statuses are forwarded to
+    /// every watcher.
+    ///
+    /// Arguments:
+    ///
+    /// * `request`: The image to pull and the nodes pulling it.
+    /// * `watcher`: The stream of the pull statuses.
+    pub async fn pull(
+        &mut self,
+        request: ImagePullRequest,
+        watcher: PullSender,
+    ) -> Result<(), tonic::Status> {
+        if request.uri.is_empty() {
+            return Err(tonic::Status::invalid_argument(
+                "the image to pull has no uri",
+            ));
+        }
+        self.disconnect_closed().await;
+
+        let node_ids = if request.node_ids.is_empty() {
+            let mut node_ids: Vec<NodeIdentifier> = self.connected().cloned().collect();
+            node_ids.sort();
+            node_ids
+        } else {
+            request.node_ids
+        };
+        if node_ids.is_empty() {
+            return Err(tonic::Status::unavailable(
+                "no node is connected to the scheduler",
+            ));
+        }
+
+        for node_id in node_ids {
+            let key = (node_id, request.uri.clone());
+            if let Some(watchers) = self.pulls.get_mut(&key) {
+                watchers.push(watcher.clone());
+                continue;
+            }
+
+            let command = Command::Pull(agent::ImagePull {
+                uri: request.uri.clone(),
+            });
+            match self.send(&key.0, command).await {
+                Ok(()) => {
+                    info!("node {} is pulling image {}", key.0, key.1);
+                    self.pulls.insert(key, vec![watcher.clone()]);
+                }
+                Err(err) => {
+                    let status = pull_status(
+                        &key.0,
+                        &key.1,
+                        ImagePullState::ImagePullFailed,
+                        err.message().to_string(),
+                    );
+                    _ = timeout(self.timeout, watcher.send(Ok(status))).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Forwards the progress of the pull of an image sent by a node to the watchers of the pull,
+    /// the pull is forgotten once it is over or nobody watches it anymore.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node which sent the status.
+    /// * `status`: The progress of the pull.
+    pub async fn report_pull(&mut self, node_id: &str, status: agent::ImagePullStatus) {
+        let key = (node_id.to_string(), status.uri.clone());
+        let Some(watchers) = self.pulls.remove(&key) else {
+            debug!(
+                "ignored the status of the pull of image {} by node {}, nobody watches it",
+                status.uri, node_id
+            );
+            return;
+        };
+        let finished = status.state() != ImagePullState::ImagePulling;
+        let status = NodeImagePullStatus {
+            node_id: node_id.to_string(),
+            status: Some(status),
+        };
+
+        let mut kept = Vec::with_capacity(watchers.len());
+        for watcher in watchers {
+            match timeout(self.timeout, watcher.send(Ok(status.clone()))).await {
+                Ok(Ok(())) => kept.push(watcher),
+                Ok(Err(_)) => debug!("watcher of the pull of image {} is gone", key.1),
+                Err(_) => warn!(
+                    "watcher of the pull of image {} is hung, it is dropped",
+                    key.1
+                ),
+            }
+        }
+        if !finished && !kept.is_empty() {
+            self.pulls.insert(key, kept);
+        }
+    }
+
+    /// Reports the image pulls of a disconnected node as failed to their watchers.
+    pub(super) async fn fail_pulls(&mut self, node_id: &str) {
+        let pulls: Vec<(NodeIdentifier, String)> = self
+            .pulls
+            .keys()
+            .filter(|(pull_node_id, _)| pull_node_id == node_id)
+            .cloned()
+            .collect();
+        for key in pulls {
+            let Some(watchers) = self.pulls.remove(&key) else {
+                continue;
+            };
+            let status = pull_status(
+                node_id,
+                &key.1,
+                ImagePullState::ImagePullFailed,
+                "the node disconnected".to_string(),
+            );
+            for watcher in watchers {
+                _ = timeout(self.timeout, watcher.send(Ok(status.clone()))).await;
+            }
+        }
+    }
+}
+
+/// Returns the status of the pull of an image on a node.
+fn pull_status(
+    node_id: &str,
+    uri: &str,
+    state: ImagePullState,
+    description: String,
+) -> NodeImagePullStatus {
+    NodeImagePullStatus {
+        node_id: node_id.to_string(),
+        status: Some(agent::ImagePullStatus {
+            uri: uri.to_string(),
+            state: state.into(),
+            description,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use crate::lifecycle::tests::TIMEOUT;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pull() {
+        let mut connections = NodeConnections::new(TIMEOUT);
+        let (node, mut commands) = mpsc::channel(4);
+        connections.connect("a".to_string(), node);
+
+        let request = ImagePullRequest {
+            uri: "nginx".to_string(),
+            node_ids: vec!["a".to_string(), "b".to_string()],
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        connections.pull(request.clone(), tx).await.unwrap();
+        let command = commands.recv().await.unwrap().unwrap().command;
+        assert!(matches!(command, Some(Command::Pull(pull)) if pull.uri == "nginx"));
+
+        // the node which isn't connected fails right away
+        let status = rx.recv().await.unwrap().unwrap();
+        assert_eq!(status.node_id, "b");
+        assert_eq!(
+            status.status.unwrap().state(),
+            ImagePullState::ImagePullFailed
+        );
+
+        // a second pull of the image watches the first one
+        let (tx, mut other) = mpsc::channel(4);
+        let request = ImagePullRequest {
+            node_ids: vec!["a".to_string()],
+            ..request
+        };
+        connections.pull(request, tx).await.unwrap();
+        assert!(commands.try_recv().is_err());
+
+        let status = |state: ImagePullState| agent::ImagePullStatus {
+            uri: "nginx".to_string(),
+            state: state.into(),
+            ..Default::default()
+        };
+        connections
+            .report_pull("a", status(ImagePullState::ImagePulling))
+            .await;
+        connections
+            .report_pull("a", status(ImagePullState::ImagePulled))
+            .await;
+        for rx in [&mut rx, &mut other] {
+            let status = rx.recv().await.unwrap().unwrap();
+            assert_eq!(status.status.unwrap().state(), ImagePullState::ImagePulling);
+            let status = rx.recv().await.unwrap().unwrap();
+            assert_eq!(status.node_id, "a");
+            assert_eq!(status.status.unwrap().state(), ImagePullState::ImagePulled);
+            // the streams close once every node is done
+            assert!(rx.recv().await.is_none());
+        }
+
+        let err = connections
+            .pull(ImagePullRequest::default(), mpsc::channel(1).0)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    }
+}
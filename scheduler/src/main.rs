@@ -17,11 +17,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         confy::load_path(dir.as_path()).map_err(SchedulerError::ConfigReadError)?;
     debug!("config: {:?}", config);
 
+    telemetry::init("scheduler", config.otlp_endpoint.as_deref())?;
+
     let manager = Manager::new(config);
     debug!("initialized manager struct with data : {:?}", manager);
 
     manager.run().await?;
 
     info!("shutting down");
+    telemetry::shutdown();
     Ok(())
 }
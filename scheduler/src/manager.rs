@@ -1,19 +1,46 @@
-use std::sync::Arc;
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::Result;
-use log::{debug, info};
+use log::{debug, info, warn};
+use opentelemetry::{trace::FutureExt, Context};
 use proto::scheduler::{
+    bootstrap_service_server::BootstrapServiceServer,
     instance_service_server::InstanceServiceServer, node_service_server::NodeServiceServer,
-    Instance, InstanceStatus, NodeRegisterResponse, NodeUnregisterResponse,
+    Instance,
 };
-use tokio::sync::mpsc;
+use tokio::{net::TcpListener, sync::mpsc};
 use tokio::{sync::oneshot, task::JoinHandle};
-use tonic::{transport::Server, Response};
+use tonic::{service::interceptor::InterceptedService, transport::Server};
+use tonic_health::proto::health_server::{Health, HealthServer};
+use tonic_health::server::HealthReporter;
 
 use crate::SchedulerError;
 use crate::{
-    config::Config, instance_listener::InstanceListener, node_listener::NodeListener,
-    storage::Storage, Event, Node,
+    auth::NodeAuthenticator,
+    breaker::BreakerPolicy,
+    broadcast,
+    config::{Config, PkiConfig},
+    debug::{self, EventHistory},
+    handler::{
+        middleware::{HealthMiddleware, LoggingMiddleware, TimingMiddleware},
+        EventRegistry, HandlerContext,
+    },
+    instance_listener::{check_protocol, InstanceListener},
+    lifecycle::NodeConnections,
+    node_listener::NodeListener,
+    pki::{self, BootstrapListener, CertificateAuthority},
+    queue::EventQueue,
+    rebalance,
+    retry::RetryPolicy,
+    skew::VersionPolicy,
+    storage::Storage,
+    throttle::StatusThrottle,
+    tls::{self, ServerCredentials},
+    Event, Node,
 };
 
 #[derive(Debug)]
@@ -60,19 +87,41 @@ impl Manager {
     ///
     /// Arguments:
     ///
-    /// * `tx`: mpsc::Sender<Event>
+    /// * `tx`: The event queue
+    /// * `health_service`: The `grpc.health.v1.Health` service reporting the health of the others
     ///
     /// Returns:
     ///
     /// A JoinHandle<()>
-    fn create_grpc_server(&self, tx: mpsc::Sender<Event>) -> Result<JoinHandle<()>> {
+    fn create_grpc_server(
+        &self,
+        tx: EventQueue,
+        health_service: HealthServer<impl Health>,
+    ) -> Result<JoinHandle<()>> {
         info!("creating grpc server ...");
         let addr = format!("{}:{}", self.config.host, self.config.port)
             .parse()
             .map_err(|_| SchedulerError::InvalidGrpcAddress)?;
 
-        let node_listener = NodeListener::new(tx.clone());
-        debug!("create node listener with data : {:?}", node_listener);
+        // with a CA, the node service is only served by the mutual TLS server
+        let node_service = match self.config.pki {
+            Some(_) => None,
+            None => {
+                let node_listener = NodeListener::new(tx.clone());
+                debug!("create node listener with data : {:?}", node_listener);
+
+                let authenticator = NodeAuthenticator::new(self.config.node_secrets.clone());
+                if !authenticator.is_enabled() {
+                    warn!("no node secret configured, the nodes are not authenticated");
+                }
+                Some(InterceptedService::new(
+                    NodeServiceServer::new(node_listener)
+                        .send_gzip()
+                        .accept_gzip(),
+                    authenticator,
+                ))
+            }
+        };
 
         let instance_listener = InstanceListener::new(tx);
         debug!(
@@ -80,18 +129,163 @@ impl Manager {
             instance_listener
         );
 
+        // let grpcurl and the other debugging tools discover the services
+        let reflection_service = tonic_reflection::server::Builder::configure()
+            .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+            .register_encoded_file_descriptor_set(
+                tonic_health::proto::GRPC_HEALTH_V1_FILE_DESCRIPTOR_SET,
+            )
+            .build()?;
+
+        let grpc = self.config.grpc.clone();
+
         Ok(tokio::spawn(async move {
             info!("started grpc server at {}", addr);
 
             Server::builder()
-                .add_service(NodeServiceServer::new(node_listener))
-                .add_service(InstanceServiceServer::new(instance_listener))
+                .timeout(grpc.request_timeout())
+                .tcp_keepalive(Some(grpc.tcp_keepalive()))
+                .http2_keepalive_interval(Some(grpc.keepalive_interval()))
+                .http2_keepalive_timeout(Some(grpc.keepalive_timeout()))
+                .add_service(health_service)
+                .add_service(reflection_service)
+                .add_service(InterceptedService::new(
+                    InstanceServiceServer::new(instance_listener)
+                        .send_gzip()
+                        .accept_gzip(),
+                    check_protocol,
+                ))
+                .add_optional_service(node_service)
                 .serve(addr)
                 .await
                 .unwrap();
         }))
     }
 
+    /// It serves the node service with mutual TLS, the nodes being authenticated by their client
+    /// certificate, and the bootstrap service the nodes get this certificate from. Both present
+    /// a certificate of the scheduler issued by the CA of the cluster, renewed before it expires.
+    ///
+    /// Arguments:
+    ///
+    /// * `tx`: The event queue
+    /// * `pki`: The settings of the CA
+    ///
+    /// Returns:
+    ///
+    /// The JoinHandle<()> of the two servers and of the renewal of the certificate
+    fn create_secure_grpc_servers(
+        &self,
+        tx: EventQueue,
+        pki: &PkiConfig,
+    ) -> Result<Vec<JoinHandle<()>>> {
+        info!("creating secure grpc servers ...");
+        let address = |port: u16| {
+            format!("{}:{}", self.config.host, port)
+                .parse::<SocketAddr>()
+                .map_err(|_| SchedulerError::InvalidGrpcAddress)
+        };
+        let node_addr = address(pki.node_port)?;
+        let join_addr = address(pki.join_port)?;
+
+        let ca = Arc::new(CertificateAuthority::load_or_create(pki)?);
+        let (certificate, key) =
+            ca.issue_server(&pki.server_names, pki.node_certificate_validity_days)?;
+        let credentials = ServerCredentials::new(&certificate, &key)?;
+        if pki.join_tokens.is_empty() {
+            warn!("no join token configured, the nodes can't join the cluster");
+        }
+
+        let node_acceptor = tls::acceptor(credentials.clone(), Some(ca.pem()))?;
+        let join_acceptor = tls::acceptor(credentials.clone(), None)?;
+
+        let node_listener =
+            NodeListener::new(tx).with_ca(ca.clone(), pki.node_certificate_validity_days);
+        let bootstrap_listener = BootstrapListener::new(ca.clone(), pki);
+        let grpc = self.config.grpc.clone();
+
+        let mut node_server = Server::builder()
+            .timeout(grpc.request_timeout())
+            .http2_keepalive_interval(Some(grpc.keepalive_interval()))
+            .http2_keepalive_timeout(Some(grpc.keepalive_timeout()));
+        let mut join_server = Server::builder().timeout(grpc.request_timeout());
+
+        Ok(vec![
+            tokio::spawn(async move {
+                let listener = TcpListener::bind(node_addr).await.unwrap();
+                info!("started node grpc server at {}", node_addr);
+                node_server
+                    .add_service(InterceptedService::new(
+                        NodeServiceServer::new(node_listener)
+                            .send_gzip()
+                            .accept_gzip(),
+                        NodeAuthenticator::with_certificates(),
+                    ))
+                    .serve_with_incoming(tls::incoming(listener, node_acceptor))
+                    .await
+                    .unwrap();
+            }),
+            tokio::spawn(async move {
+                let listener = TcpListener::bind(join_addr).await.unwrap();
+                info!("started bootstrap grpc server at {}", join_addr);
+                join_server
+                    .add_service(BootstrapServiceServer::new(bootstrap_listener))
+                    .serve_with_incoming(tls::incoming(listener, join_acceptor))
+                    .await
+                    .unwrap();
+            }),
+            Self::renew_server_certificate(ca, credentials, pki.clone(), certificate),
+        ])
+    }
+
+    /// It issues a new certificate to the scheduler once two thirds of the validity of the
+    /// current one have elapsed. The servers present it to the next connections, the open ones
+    /// are kept, so the renewal doesn't interrupt the nodes.
+    ///
+    /// Arguments:
+    ///
+    /// * `ca`: The CA of the cluster
+    /// * `credentials`: The certificate presented by the servers
+    /// * `pki`: The settings of the CA
+    /// * `certificate`: The PEM certificate currently presented
+    ///
+    /// Returns:
+    ///
+    /// A JoinHandle<()>
+    fn renew_server_certificate(
+        ca: Arc<CertificateAuthority>,
+        credentials: ServerCredentials,
+        pki: PkiConfig,
+        mut certificate: String,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs() as i64;
+                // a failed renewal is retried after a minute
+                let renewal_time = pki::renewal_time(&certificate).unwrap_or(now);
+                let delay = (renewal_time - now).max(60) as u64;
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+
+                let renewed = ca
+                    .issue_server(&pki.server_names, pki.node_certificate_validity_days)
+                    .and_then(|(renewed, key)| {
+                        credentials.replace(&renewed, &key)?;
+                        Ok(renewed)
+                    });
+                match renewed {
+                    Ok(renewed) => {
+                        info!("renewed the certificate of the scheduler");
+                        certificate = renewed;
+                    }
+                    Err(err) => warn!("unable to renew the certificate of the scheduler: {}", err),
+                }
+            }
+        })
+    }
+
     /// Create a multi-producer, single-consumer channel with a buffer size of 32
     pub fn create_mpsc_channel<T>() -> (mpsc::Sender<T>, mpsc::Receiver<T>) {
         debug!("creating mpsc channel ...");
@@ -108,54 +302,55 @@ impl Manager {
         (tx, rx)
     }
 
-    /// This function listens for incoming events from the event bus and dispatches them to the
-    /// orchestrator
+    /// This function listens for incoming events from the event bus and dispatches them to their
+    /// handlers, through the logging, timing and health middlewares
     ///
     /// Arguments:
     ///
-    /// * `rx`: mpsc::Receiver<Event>
+    /// * `tx`: The event queue, used by the handlers to queue the retries
+    /// * `rx`: The receiving half of the event bus, the events and their trace contexts
+    /// * `reporter`: The reporter updated with the health of the services after each event
+    /// * `history`: The middleware keeping the last events for the debug server
+    /// * `version_policy`: The range of agent versions accepted at registration
     ///
     /// Returns:
     ///
     /// A JoinHandle<()>
-    fn listen_events(&self, mut rx: mpsc::Receiver<Event>) -> JoinHandle<()> {
+    fn listen_events(
+        &self,
+        tx: EventQueue,
+        mut rx: mpsc::Receiver<(Event, Context)>,
+        reporter: HealthReporter,
+        history: EventHistory,
+        version_policy: VersionPolicy,
+    ) -> JoinHandle<()> {
         info!("listening for incoming events ...");
+        let request_timeout = self.config.grpc.request_timeout();
+        let retry_policy = RetryPolicy::from(&self.config.retry);
+        let status_throttle = StatusThrottle::from(&self.config.status);
+        let connections = NodeConnections::new(request_timeout)
+            .with_profiles(self.config.profiles.clone())
+            .with_breakers(BreakerPolicy::from(&self.config.breaker));
 
         tokio::spawn(async move {
-            while let Some(event) = rx.recv().await {
-                debug!("received event : {:?}", event);
-                match event {
-                    Event::InstanceCreate(instance, tx) => {
-                        info!("received instance create event : {:?}", instance);
-                        tx.send(Ok(InstanceStatus::default())).await.unwrap();
-                    }
-                    Event::InstanceStart(id, tx) => {
-                        info!("received instance start event : {:?}", id);
-                        tx.send(Ok(Response::new(()))).unwrap();
-                    }
-                    Event::InstanceStop(id, tx) => {
-                        info!("received instance stop event : {:?}", id);
-                        tx.send(Ok(Response::new(()))).unwrap();
-                    }
-                    Event::InstanceDestroy(id, tx) => {
-                        info!("received instance destroy event : {:?}", id);
-                        tx.send(Ok(Response::new(()))).unwrap();
-                    }
-                    Event::NodeRegister(request, tx) => {
-                        info!("received node register event : {:?}", request);
-                        tx.send(Ok(Response::new(NodeRegisterResponse::default())))
-                            .unwrap();
-                    }
-                    Event::NodeUnregister(request, tx) => {
-                        info!("received node unregister event : {:?}", request);
-                        tx.send(Ok(Response::new(NodeUnregisterResponse::default())))
-                            .unwrap();
-                    }
-                    Event::NodeStatus(status, tx) => {
-                        info!("received node status event : {:?}", status);
-                        tx.send(Ok(())).await.unwrap();
-                    }
-                }
+            let mut context = HandlerContext::new(connections, tx, retry_policy)
+                .with_status_throttle(status_throttle)
+                .with_version_policy(version_policy);
+
+            let mut registry = EventRegistry::new();
+            registry
+                .with_middleware(LoggingMiddleware)
+                .with_middleware(TimingMiddleware::new(request_timeout))
+                .with_middleware(HealthMiddleware::new(reporter))
+                .with_middleware(history);
+
+            // each event is handled in the trace context it was queued in, which the commands
+            // sent to the nodes forward
+            while let Some((event, cx)) = rx.recv().await {
+                registry
+                    .dispatch(event, &mut context)
+                    .with_context(cx)
+                    .await;
             }
         })
     }
@@ -168,13 +363,41 @@ impl Manager {
     /// A Result<(), Box<dyn std::error::Error>>
     pub async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
         let mut handlers = vec![];
-        let (tx, rx) = Self::create_mpsc_channel();
+        let (tx, rx) = EventQueue::channel(self.config.queue.events);
+        let version_policy = VersionPolicy::try_from(&self.config.agent_versions)?;
+
+        // no node is connected yet, the services are reported as such until the first event
+        let (mut reporter, health_service) = tonic_health::server::health_reporter();
+        let connections = NodeConnections::new(self.config.grpc.request_timeout());
+        HealthMiddleware::report(&mut reporter, &connections).await;
 
         // create listeners and serve the grpc server
-        handlers.push(self.create_grpc_server(tx)?);
+        handlers.push(self.create_grpc_server(tx.clone(), health_service)?);
+        if let Some(pki) = &self.config.pki {
+            handlers.extend(self.create_secure_grpc_servers(tx.clone(), pki)?);
+        }
+
+        // serve the debug server if configured
+        let history = EventHistory::default();
+        if let Some(address) = &self.config.debug_address {
+            let address = address
+                .parse()
+                .map_err(|_| SchedulerError::InvalidDebugAddress)?;
+            handlers.push(debug::serve(address, tx.clone(), history.clone()));
+        }
+
+        // announce the scheduler to the node agents of the local network if configured
+        if let Some(config) = &self.config.broadcast {
+            handlers.push(broadcast::announce(&self.config, config));
+        }
+
+        // move the instances off the most loaded nodes if configured
+        if let Some(config) = &self.config.rebalance {
+            handlers.push(rebalance::schedule(config, tx.clone()));
+        }
 
         // listen for incoming events and pass them to the orchestrator
-        handlers.push(self.listen_events(rx));
+        handlers.push(self.listen_events(tx, rx, reporter, history, version_policy));
 
         info!("scheduler running and ready to receive incoming requests ...");
 
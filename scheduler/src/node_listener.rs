@@ -1,22 +1,45 @@
-use log::debug;
+use std::sync::Arc;
+
+use log::{debug, info};
+use proto::agent::{node_message::Message, InstanceCommand, NodeMessage};
 use proto::scheduler::{
-    node_service_server::NodeService, NodeRegisterRequest, NodeRegisterResponse, NodeStatus,
-    NodeUnregisterRequest, NodeUnregisterResponse,
+    node_service_server::NodeService, NodeJoinResponse, NodeRegisterRequest, NodeRegisterResponse,
+    NodeRenewRequest, NodeStatus, NodeUnregisterRequest, NodeUnregisterResponse,
 };
-use tokio::sync::mpsc;
-use tonic::{Request, Response, Status, Streaming};
+use telemetry::grpc::server_context;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Code, Request, Response, Status, Streaming};
 
-use crate::{manager::Manager, Event};
+use crate::{
+    auth::{authenticated_node, check_identity},
+    manager::Manager,
+    pki::CertificateAuthority,
+    queue::EventQueue,
+    Event,
+};
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct NodeListener {
-    sender: mpsc::Sender<Event>,
+    sender: EventQueue,
+    ca: Option<Arc<CertificateAuthority>>,
+    validity_days: i64,
 }
 
 impl NodeListener {
-    pub fn new(sender: mpsc::Sender<Event>) -> Self {
-        NodeListener { sender }
+    pub fn new(sender: EventQueue) -> Self {
+        NodeListener {
+            sender,
+            ca: None,
+            validity_days: 0,
+        }
+    }
+
+    /// Lets the nodes renew their client certificate, issued by `ca` for `validity_days`.
+    pub fn with_ca(mut self, ca: Arc<CertificateAuthority>, validity_days: i64) -> Self {
+        self.ca = Some(ca);
+        self.validity_days = validity_days;
+        self
     }
 }
 
@@ -26,6 +49,7 @@ impl NodeService for NodeListener {
         &self,
         request: Request<Streaming<NodeStatus>>,
     ) -> Result<Response<()>, Status> {
+        let node = authenticated_node(&request);
         let mut stream = request.into_inner();
         let (tx, mut rx) = Manager::create_mpsc_channel();
 
@@ -34,10 +58,17 @@ impl NodeService for NodeListener {
             match message {
                 Some(node_status) => {
                     debug!("Node status: {:?}", node_status);
-                    self.sender
-                        .send(Event::NodeStatus(node_status, tx.clone()))
-                        .await
-                        .unwrap();
+                    check_identity(node.as_deref(), &node_status.id)?;
+                    // the node sends its next status later, an overloaded scheduler skips this one
+                    if let Err(status) = self
+                        .sender
+                        .try_send(Event::NodeStatus(node_status, tx.clone()))
+                    {
+                        if status.code() != Code::ResourceExhausted {
+                            return Err(status);
+                        }
+                        continue;
+                    }
 
                     if let Some(res) = rx.recv().await {
                         match res {
@@ -63,20 +94,18 @@ impl NodeService for NodeListener {
         request: Request<NodeRegisterRequest>,
     ) -> Result<Response<NodeRegisterResponse>, Status> {
         debug!("{:?}", request);
+        let cx = server_context(&request, "NodeService/Register");
+        if !request.get_ref().id.is_empty() {
+            check_identity(
+                authenticated_node(&request).as_deref(),
+                &request.get_ref().id,
+            )?;
+        }
         let (tx, rx) = Manager::create_oneshot_channel();
 
-        match self
-            .sender
-            .send(Event::NodeRegister(request.into_inner(), tx))
-            .await
-        {
-            Ok(_) => {
-                return rx.await.unwrap();
-            }
-            Err(_) => {
-                return Err(Status::internal("could not send event to manager"));
-            }
-        }
+        self.sender
+            .try_send_with_context(Event::NodeRegister(request.into_inner(), tx), &cx)?;
+        rx.await.unwrap()
     }
 
     async fn unregister(
@@ -84,19 +113,143 @@ impl NodeService for NodeListener {
         request: Request<NodeUnregisterRequest>,
     ) -> Result<Response<NodeUnregisterResponse>, Status> {
         debug!("{:?}", request);
+        let cx = server_context(&request, "NodeService/Unregister");
+        check_identity(
+            authenticated_node(&request).as_deref(),
+            &request.get_ref().id,
+        )?;
         let (tx, rx) = Manager::create_oneshot_channel();
 
-        match self
-            .sender
-            .send(Event::NodeUnregister(request.into_inner(), tx))
-            .await
-        {
-            Ok(_) => {
-                return rx.await.unwrap();
+        self.sender
+            .try_send_with_context(Event::NodeUnregister(request.into_inner(), tx), &cx)?;
+        rx.await.unwrap()
+    }
+
+    async fn renew(
+        &self,
+        request: Request<NodeRenewRequest>,
+    ) -> Result<Response<NodeJoinResponse>, Status> {
+        // the renewal queues no event, its span only covers the signature of the certificate
+        let _cx = server_context(&request, "NodeService/Renew");
+        let ca = self
+            .ca
+            .as_ref()
+            .ok_or_else(|| Status::failed_precondition("the scheduler has no CA"))?;
+        // the certificate is issued to the node identified by its current one
+        let node_id = authenticated_node(&request)
+            .ok_or_else(|| Status::unauthenticated("missing client certificate"))?;
+
+        let certificate = ca
+            .sign_node(&node_id, &request.get_ref().csr, self.validity_days)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        info!("renewed the certificate of node {}", node_id);
+
+        Ok(Response::new(NodeJoinResponse {
+            certificate,
+            ca_certificate: ca.pem().to_string(),
+        }))
+    }
+
+    type LifecycleStream = ReceiverStream<Result<InstanceCommand, Status>>;
+
+    async fn lifecycle(
+        &self,
+        request: Request<Streaming<NodeMessage>>,
+    ) -> Result<Response<Self::LifecycleStream>, Status> {
+        let cx = server_context(&request, "NodeService/Lifecycle");
+        let node = authenticated_node(&request);
+        let mut stream = request.into_inner();
+
+        // the node identifies itself with the first message of the stream
+        let node_id = match stream.message().await?.and_then(|message| message.message) {
+            Some(Message::NodeId(node_id)) => node_id,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "the first message of the stream must be the id of the node",
+                ))
             }
-            Err(_) => {
-                return Err(Status::internal("could not send event to manager"));
+        };
+        check_identity(node.as_deref(), &node_id)?;
+        debug!("Node {} opened its lifecycle stream", node_id);
+
+        let (tx, rx) = Manager::create_mpsc_channel();
+        self.sender
+            .send_with_context(Event::NodeConnected(node_id.clone(), tx), &cx)
+            .await?;
+
+        // forward the statuses sent by the node until it closes the stream, the ones of its
+        // instances, of its image pulls and of its checkpoints, and the output of its instances
+        // and of its exec sessions
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            loop {
+                match stream.message().await {
+                    Ok(Some(NodeMessage {
+                        message: Some(Message::Status(status)),
+                    })) => {
+                        if sender
+                            .send(Event::NodeInstanceStatus(node_id.clone(), status))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Ok(Some(NodeMessage {
+                        message: Some(Message::Pull(status)),
+                    })) => {
+                        if sender
+                            .send(Event::NodeImagePullStatus(node_id.clone(), status))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Ok(Some(NodeMessage {
+                        message: Some(Message::Checkpoint(status)),
+                    })) => {
+                        if sender
+                            .send(Event::NodeCheckpointStatus(node_id.clone(), status))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Ok(Some(NodeMessage {
+                        message: Some(Message::Logs(chunk)),
+                    })) => {
+                        if sender
+                            .send(Event::NodeLogs(node_id.clone(), chunk))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Ok(Some(NodeMessage {
+                        message: Some(Message::Exec(output)),
+                    })) => {
+                        if sender
+                            .send(Event::NodeExecOutput(node_id.clone(), output))
+                            .await
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Ok(Some(message)) => debug!("Ignoring lifecycle message: {:?}", message),
+                    Ok(None) => break,
+                    Err(err) => {
+                        debug!("Lifecycle stream of node {} failed: {:?}", node_id, err);
+                        break;
+                    }
+                }
             }
-        }
+            _ = sender.send(Event::NodeDisconnected(node_id)).await;
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 }
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use thiserror::Error;
+
+/// An error found while parsing a placement constraint, `position` is the offset in bytes of the
+/// faulty token.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("{message} at position {position}")]
+pub struct ParseError {
+    pub message: String,
+    pub position: usize,
+}
+
+/// An attribute of a node a constraint can compare.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribute {
+    /// `node.id`
+    Id,
+    /// `node.arch`, e.g. `amd64` or `arm64`
+    Arch,
+    /// `node.labels.<key>`, the empty string if the node doesn't have the label
+    Label(String),
+}
+
+/// A side of a comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Attribute(Attribute),
+    Literal(String),
+}
+
+/// A placement constraint, matched against the attributes of each node when placing an
+/// instance.
+///
+/// The constraints compare the attributes of a node with `==` and `!=`, and combine the
+/// comparisons with `&&`, `||`, `!` and parentheses:
+///
+/// ```text
+/// node.labels.disk == "ssd" && (node.arch == "arm64" || !(node.labels.zone != "eu-1"))
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Constraint {
+    Equal(Operand, Operand),
+    NotEqual(Operand, Operand),
+    And(Box<Constraint>, Box<Constraint>),
+    Or(Box<Constraint>, Box<Constraint>),
+    Not(Box<Constraint>),
+}
+
+/// The attributes of a node a constraint is matched against.
+///
+/// Properties:
+///
+/// * `id`: The id of the node.
+/// * `arch`: The architecture reported by the node, empty if unknown.
+/// * `labels`: The labels set on the node by its operator.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeAttributes<'a> {
+    pub id: &'a str,
+    pub arch: &'a str,
+    pub labels: &'a HashMap<String, String>,
+}
+
+impl Constraint {
+    /// Returns `true` if a node satisfies the constraint.
+    pub fn matches(&self, node: &NodeAttributes) -> bool {
+        match self {
+            Constraint::Equal(left, right) => left.value(node) == right.value(node),
+            Constraint::NotEqual(left, right) => left.value(node) != right.value(node),
+            Constraint::And(left, right) => left.matches(node) && right.matches(node),
+            Constraint::Or(left, right) => left.matches(node) || right.matches(node),
+            Constraint::Not(constraint) => !constraint.matches(node),
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, node: &NodeAttributes<'a>) -> &'a str {
+        match self {
+            Operand::Attribute(Attribute::Id) => node.id,
+            Operand::Attribute(Attribute::Arch) => node.arch,
+            Operand::Attribute(Attribute::Label(key)) => {
+                node.labels.get(key).map(String::as_str).unwrap_or_default()
+            }
+            Operand::Literal(literal) => literal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Path(String),
+    Literal(String),
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Path(path) => write!(f, "{}", path),
+            Token::Literal(literal) => write!(f, "{:?}", literal),
+            Token::Equal => write!(f, "=="),
+            Token::NotEqual => write!(f, "!="),
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn error(message: impl Into<String>, position: usize) -> ParseError {
+    ParseError {
+        message: message.into(),
+        position,
+    }
+}
+
+/// Returns `true` if a character can be part of a path, the label keys may contain dots,
+/// dashes and slashes, e.g. `node.labels.topology.kudo/zone`.
+fn is_path_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/')
+}
+
+/// Splits a constraint into its tokens, each with its position.
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = vec![];
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((position, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' | '&' | '|' => match chars.next() {
+                Some((_, next)) if next == c => match c {
+                    '=' => Token::Equal,
+                    '&' => Token::And,
+                    _ => Token::Or,
+                },
+                _ => return Err(error(format!("expected {}{}", c, c), position)),
+            },
+            '!' => match chars.peek() {
+                Some((_, '=')) => {
+                    chars.next();
+                    Token::NotEqual
+                }
+                _ => Token::Not,
+            },
+            '"' => {
+                let mut literal = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => literal.push(escaped),
+                            None => return Err(error("unterminated string", position)),
+                        },
+                        Some((_, c)) => literal.push(c),
+                        None => return Err(error("unterminated string", position)),
+                    }
+                }
+                Token::Literal(literal)
+            }
+            c if is_path_char(c) => {
+                let mut path = c.to_string();
+                while let Some((_, c)) = chars.peek().filter(|(_, c)| is_path_char(*c)) {
+                    path.push(*c);
+                    chars.next();
+                }
+                Token::Path(path)
+            }
+            c => return Err(error(format!("unexpected character {:?}", c), position)),
+        };
+        tokens.push((token, position));
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser of the constraints, from the lowest precedence to the highest:
+///
+/// ```text
+/// or         := and ("||" and)*
+/// and        := unary ("&&" unary)*
+/// unary      := "!" unary | "(" or ")" | comparison
+/// comparison := operand ("==" | "!=") operand
+/// operand    := path | string
+/// ```
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    /// Returns the position of the next token, the end of the input if there is none.
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |(_, position)| *position)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).map(|(token, _)| token.clone());
+        self.next += 1;
+        token
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => error(format!("unexpected {}", token), self.position()),
+            None => error("unexpected end of the constraint", self.position()),
+        }
+    }
+
+    fn or(&mut self) -> Result<Constraint, ParseError> {
+        let mut constraint = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.advance();
+            constraint = Constraint::Or(Box::new(constraint), Box::new(self.and()?));
+        }
+        Ok(constraint)
+    }
+
+    fn and(&mut self) -> Result<Constraint, ParseError> {
+        let mut constraint = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.advance();
+            constraint = Constraint::And(Box::new(constraint), Box::new(self.unary()?));
+        }
+        Ok(constraint)
+    }
+
+    fn unary(&mut self) -> Result<Constraint, ParseError> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.advance();
+                Ok(Constraint::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Open) => {
+                self.advance();
+                let constraint = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(self.unexpected());
+                }
+                self.advance();
+                Ok(constraint)
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Constraint, ParseError> {
+        let left = self.operand()?;
+        let equal = match self.peek() {
+            Some(Token::Equal) => true,
+            Some(Token::NotEqual) => false,
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+        let right = self.operand()?;
+        if equal {
+            Ok(Constraint::Equal(left, right))
+        } else {
+            Ok(Constraint::NotEqual(left, right))
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        let position = self.position();
+        let unexpected = self.unexpected();
+        match self.advance() {
+            Some(Token::Literal(literal)) => Ok(Operand::Literal(literal)),
+            Some(Token::Path(path)) => match path.as_str() {
+                "node.id" => Ok(Operand::Attribute(Attribute::Id)),
+                "node.arch" => Ok(Operand::Attribute(Attribute::Arch)),
+                path => match path.strip_prefix("node.labels.") {
+                    Some(key) if !key.is_empty() => {
+                        Ok(Operand::Attribute(Attribute::Label(key.to_string())))
+                    }
+                    _ => Err(error(format!("unknown attribute {}", path), position)),
+                },
+            },
+            _ => Err(unexpected),
+        }
+    }
+}
+
+/// Parses a placement constraint.
+///
+/// Arguments:
+///
+/// * `input`: The constraint, e.g. `node.labels.disk == "ssd" && node.arch == "arm64"`.
+pub fn parse(input: &str) -> Result<Constraint, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        next: 0,
+        end: input.len(),
+    };
+    let constraint = parser.or()?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+    Ok(constraint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node<'a>(arch: &'a str, labels: &'a HashMap<String, String>) -> NodeAttributes<'a> {
+        NodeAttributes {
+            id: "node-1",
+            arch,
+            labels,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(r#"node.labels.disk == "ssd" && node.arch != "arm64""#),
+            Ok(Constraint::And(
+                Box::new(Constraint::Equal(
+                    Operand::Attribute(Attribute::Label("disk".to_string())),
+                    Operand::Literal("ssd".to_string()),
+                )),
+                Box::new(Constraint::NotEqual(
+                    Operand::Attribute(Attribute::Arch),
+                    Operand::Literal("arm64".to_string()),
+                )),
+            ))
+        );
+        // && binds tighter than ||
+        assert_eq!(
+            parse(r#"node.id == "a" || node.id == "b" && node.id == "c""#),
+            parse(r#"node.id == "a" || (node.id == "b" && node.id == "c")"#),
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse(r#"node.disk == "ssd""#).unwrap_err().to_string(),
+            "unknown attribute node.disk at position 0"
+        );
+        assert_eq!(
+            parse(r#"node.arch = "arm64""#).unwrap_err().to_string(),
+            "expected == at position 10"
+        );
+        assert_eq!(
+            parse(r#"node.arch == "arm64"#).unwrap_err().to_string(),
+            "unterminated string at position 13"
+        );
+        assert_eq!(
+            parse(r#"(node.arch == "arm64""#).unwrap_err().to_string(),
+            "unexpected end of the constraint at position 21"
+        );
+        assert_eq!(
+            parse(r#"node.arch == "arm64" node.id"#)
+                .unwrap_err()
+                .to_string(),
+            "unexpected node.id at position 21"
+        );
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_matches() {
+        let labels = HashMap::from([
+            ("disk".to_string(), "ssd".to_string()),
+            ("topology.kudo/zone".to_string(), "eu-1".to_string()),
+        ]);
+        let constraint = parse(
+            r#"node.labels.disk == "ssd" && (node.arch == "arm64" || node.labels.topology.kudo/zone == "eu-1")"#,
+        )
+        .unwrap();
+        assert!(constraint.matches(&node("amd64", &labels)));
+        assert!(constraint.matches(&node(
+            "arm64",
+            &HashMap::from([("disk".to_string(), "ssd".to_string())])
+        )));
+        assert!(!constraint.matches(&node("arm64", &HashMap::new())));
+
+        // a missing label compares as the empty string
+        let constraint = parse(r#"!(node.labels.gpu != "") && node.id == "node-1""#).unwrap();
+        assert!(constraint.matches(&node("amd64", &labels)));
+    }
+}
@@ -0,0 +1,323 @@
+use std::{fmt::Debug, fs, path::Path, sync::Arc};
+
+use log::info;
+use proto::scheduler::{
+    bootstrap_service_server::BootstrapService, NodeJoinRequest, NodeJoinResponse,
+};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CertificateSigningRequest, DistinguishedName,
+    DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, RcgenError,
+};
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+use tonic::{Request, Response, Status};
+
+use crate::{auth::constant_time_eq, config::PkiConfig};
+
+/// The common name of the certificate of the cluster CA.
+const CA_COMMON_NAME: &str = "kudo-ca";
+
+#[derive(Error, Debug)]
+pub enum PkiError {
+    #[error("unable to read or write the CA files")]
+    Io(#[from] std::io::Error),
+    #[error("invalid certificate or key: {0}")]
+    Certificate(#[from] RcgenError),
+    #[error("invalid certificate")]
+    InvalidCertificate,
+    #[error("invalid private key")]
+    InvalidKey,
+}
+
+/// `CertificateAuthority` is the CA of the cluster. It signs the client certificates of the
+/// nodes, whose common name is the node id, and the certificate of the scheduler.
+///
+/// Properties:
+///
+/// * `certificate`: The certificate of the CA, with its key.
+/// * `pem`: The PEM certificate of the CA, trusted by the nodes and the scheduler.
+pub struct CertificateAuthority {
+    certificate: Certificate,
+    pem: String,
+}
+
+impl CertificateAuthority {
+    /// Loads the CA from the files of the configuration, they are created with a new CA if they
+    /// don't exist yet.
+    pub fn load_or_create(config: &PkiConfig) -> Result<Self, PkiError> {
+        if config.ca_certificate.exists() && config.ca_key.exists() {
+            let key_pair = KeyPair::from_pem(&fs::read_to_string(&config.ca_key)?)?;
+            let pem = fs::read_to_string(&config.ca_certificate)?;
+            let params = CertificateParams::from_ca_cert_pem(&pem, key_pair)?;
+            return Ok(CertificateAuthority {
+                certificate: Certificate::from_params(params)?,
+                pem,
+            });
+        }
+
+        info!(
+            "creating a new CA in {}",
+            config.ca_certificate.to_string_lossy()
+        );
+        let ca = Self::generate(config.ca_validity_days)?;
+        fs::write(&config.ca_certificate, &ca.pem)?;
+        write_private(&config.ca_key, &ca.certificate.serialize_private_key_pem())?;
+        Ok(ca)
+    }
+
+    /// Generates a new self-signed CA.
+    pub fn generate(validity_days: i64) -> Result<Self, PkiError> {
+        let mut params = CertificateParams::default();
+        params.distinguished_name = distinguished_name(CA_COMMON_NAME);
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+        set_validity(&mut params, validity_days);
+
+        let certificate = Certificate::from_params(params)?;
+        let pem = certificate.serialize_pem()?;
+        Ok(CertificateAuthority { certificate, pem })
+    }
+
+    /// Returns the PEM certificate of the CA.
+    pub fn pem(&self) -> &str {
+        &self.pem
+    }
+
+    /// Signs the certificate signing request of a node. The subject of the request is replaced by
+    /// the node id, so a node can't get a certificate for another identity.
+    ///
+    /// Arguments:
+    ///
+    /// * `node_id`: The id of the node, the common name of its certificate.
+    /// * `csr`: The PEM certificate signing request of the node.
+    /// * `validity_days`: How long the certificate is valid.
+    ///
+    /// Returns:
+    ///
+    /// The PEM client certificate of the node.
+    pub fn sign_node(
+        &self,
+        node_id: &str,
+        csr: &str,
+        validity_days: i64,
+    ) -> Result<String, PkiError> {
+        let mut csr = CertificateSigningRequest::from_pem(csr)?;
+        csr.params.distinguished_name = distinguished_name(node_id);
+        csr.params.subject_alt_names = vec![];
+        csr.params.is_ca = IsCa::NoCa;
+        csr.params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        csr.params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        set_validity(&mut csr.params, validity_days);
+
+        Ok(csr.serialize_pem_with_signer(&self.certificate)?)
+    }
+
+    /// Issues the certificate of the scheduler for its gRPC servers.
+    ///
+    /// Arguments:
+    ///
+    /// * `server_names`: The DNS names and IP addresses the nodes reach the scheduler at.
+    /// * `validity_days`: How long the certificate is valid.
+    ///
+    /// Returns:
+    ///
+    /// The PEM certificate and the PEM private key of the scheduler.
+    pub fn issue_server(
+        &self,
+        server_names: &[String],
+        validity_days: i64,
+    ) -> Result<(String, String), PkiError> {
+        let mut params = CertificateParams::new(server_names.to_vec());
+        params.distinguished_name = distinguished_name("kudo-scheduler");
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        set_validity(&mut params, validity_days);
+
+        let certificate = Certificate::from_params(params)?;
+        Ok((
+            certificate.serialize_pem_with_signer(&self.certificate)?,
+            certificate.serialize_private_key_pem(),
+        ))
+    }
+}
+
+impl Debug for CertificateAuthority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertificateAuthority")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns when a certificate should be renewed, in seconds since the unix epoch: once two
+/// thirds of its validity have elapsed, so a failed renewal is retried long before it expires.
+///
+/// Arguments:
+///
+/// * `pem`: The PEM certificate.
+pub fn renewal_time(pem: &str) -> Option<i64> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).ok()?;
+    let certificate = pem.parse_x509().ok()?;
+    let not_before = certificate.validity().not_before.timestamp();
+    let not_after = certificate.validity().not_after.timestamp();
+    Some(not_before + (not_after - not_before) * 2 / 3)
+}
+
+/// Returns the node id of a client certificate, its common name.
+///
+/// Arguments:
+///
+/// * `der`: The DER client certificate, already verified against the CA by the TLS handshake.
+pub fn node_id(der: &[u8]) -> Option<String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+    let common_name = certificate.subject().iter_common_name().next()?;
+    common_name.as_str().ok().map(String::from)
+}
+
+fn distinguished_name(common_name: &str) -> DistinguishedName {
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, common_name);
+    name
+}
+
+fn set_validity(params: &mut CertificateParams, validity_days: i64) {
+    let now = OffsetDateTime::now_utc();
+    // tolerate the clocks of the nodes being slightly behind
+    params.not_before = now - Duration::hours(1);
+    params.not_after = now + Duration::days(validity_days);
+}
+
+/// Writes a file only readable by its owner.
+fn write_private(path: &Path, content: &str) -> std::io::Result<()> {
+    fs::write(path, content)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// `BootstrapListener` serves the `Join` calls of the nodes: a node presenting a valid join
+/// token gets its certificate signing request signed by the CA.
+///
+/// Properties:
+///
+/// * `ca`: The CA of the cluster.
+/// * `join_tokens`: The tokens allowing the nodes to join.
+/// * `validity_days`: How long the certificates of the nodes are valid.
+pub struct BootstrapListener {
+    ca: Arc<CertificateAuthority>,
+    join_tokens: Vec<String>,
+    validity_days: i64,
+}
+
+impl BootstrapListener {
+    pub fn new(ca: Arc<CertificateAuthority>, config: &PkiConfig) -> Self {
+        BootstrapListener {
+            ca,
+            join_tokens: config.join_tokens.clone(),
+            validity_days: config.node_certificate_validity_days,
+        }
+    }
+
+    /// Returns `true` if `token` is one of the join tokens.
+    fn is_valid_token(&self, token: &str) -> bool {
+        self.join_tokens
+            .iter()
+            .any(|expected| constant_time_eq(expected.as_bytes(), token.as_bytes()))
+    }
+}
+
+#[tonic::async_trait]
+impl BootstrapService for BootstrapListener {
+    async fn join(
+        &self,
+        request: Request<NodeJoinRequest>,
+    ) -> Result<Response<NodeJoinResponse>, Status> {
+        let request = request.into_inner();
+        if !self.is_valid_token(&request.token) {
+            return Err(Status::unauthenticated("invalid join token"));
+        }
+        if request.node_id.is_empty() {
+            return Err(Status::invalid_argument("missing node id"));
+        }
+
+        let certificate = self
+            .ca
+            .sign_node(&request.node_id, &request.csr, self.validity_days)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        info!("issued a certificate to node {}", request.node_id);
+
+        Ok(Response::new(NodeJoinResponse {
+            certificate,
+            ca_certificate: self.ca.pem().to_string(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_csr(common_name: &str) -> String {
+        let mut params = CertificateParams::default();
+        params.distinguished_name = distinguished_name(common_name);
+        Certificate::from_params(params)
+            .unwrap()
+            .serialize_request_pem()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sign_node() {
+        let ca = CertificateAuthority::generate(1).unwrap();
+        // the node asks for another identity, the node id is used instead
+        let pem = ca.sign_node("node-1", &node_csr("node-2"), 1).unwrap();
+
+        let (_, der) = x509_parser::pem::parse_x509_pem(pem.as_bytes()).unwrap();
+        assert_eq!(node_id(&der.contents), Some("node-1".to_string()));
+    }
+
+    #[test]
+    fn test_renewal_time() {
+        let ca = CertificateAuthority::generate(1).unwrap();
+        let pem = ca.sign_node("node-1", &node_csr("node-1"), 2).unwrap();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+
+        // valid from an hour ago for two days
+        let renewal_time = renewal_time(&pem).unwrap();
+        let expected = now - 3600 + (2 * 86400 + 3600) * 2 / 3;
+        assert!((renewal_time - expected).abs() <= 2);
+        assert_eq!(super::renewal_time("invalid"), None);
+    }
+
+    #[test]
+    fn test_sign_invalid_csr() {
+        let ca = CertificateAuthority::generate(1).unwrap();
+        assert!(ca.sign_node("node-1", "not a csr", 1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_join_token() {
+        let config = PkiConfig {
+            join_tokens: vec!["token".to_string()],
+            ..Default::default()
+        };
+        let listener = BootstrapListener::new(
+            Arc::new(CertificateAuthority::generate(1).unwrap()),
+            &config,
+        );
+
+        let request = |token: &str| {
+            Request::new(NodeJoinRequest {
+                node_id: "node-1".to_string(),
+                token: token.to_string(),
+                csr: node_csr("node-1"),
+            })
+        };
+        let err = listener.join(request("wrong")).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+
+        let response = listener.join(request("token")).await.unwrap().into_inner();
+        assert_eq!(response.ca_certificate, listener.ca.pem());
+    }
+}
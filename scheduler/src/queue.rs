@@ -0,0 +1,169 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use log::warn;
+use opentelemetry::Context;
+use serde_derive::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tonic::Status;
+
+use crate::Event;
+
+/// The depth of the event queue and what it rejected, served by the debug server.
+///
+/// Properties:
+///
+/// * `depth`: The number of events waiting to be handled.
+/// * `capacity`: The number of events the queue holds.
+/// * `rejected`: The number of events rejected because the queue was full.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueueMetrics {
+    pub depth: usize,
+    pub capacity: usize,
+    pub rejected: u64,
+}
+
+/// `EventQueue` is the sending half of the bounded event bus of the scheduler. The requests of
+/// the controller and of the nodes are rejected with `RESOURCE_EXHAUSTED` when it is full, so an
+/// overloaded scheduler answers at once and the callers retry later. The events of the lifecycle
+/// streams can't be dropped, they wait for room instead, slowing down the stream of their node.
+///
+/// Each event is queued with the trace context it was sent in, the span of the gRPC procedure
+/// which sent it, so that it is handled in the same trace.
+#[derive(Debug, Clone)]
+pub struct EventQueue {
+    sender: mpsc::Sender<(Event, Context)>,
+    capacity: usize,
+    rejected: Arc<AtomicU64>,
+}
+
+impl EventQueue {
+    /// `channel` creates the queue of `capacity` events and the receiving half of the bus.
+    pub fn channel(capacity: usize) -> (EventQueue, mpsc::Receiver<(Event, Context)>) {
+        let capacity = capacity.max(1);
+        let (sender, receiver) = mpsc::channel(capacity);
+        let queue = EventQueue {
+            sender,
+            capacity,
+            rejected: Arc::default(),
+        };
+        (queue, receiver)
+    }
+
+    /// Queues an event in the current trace context if there is room for it, or rejects it with
+    /// `RESOURCE_EXHAUSTED`.
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&self, event: Event) -> Result<(), Status> {
+        self.try_send_with_context(event, &Context::current())
+    }
+
+    /// Queues an event in the trace context `cx` if there is room for it, or rejects it with
+    /// `RESOURCE_EXHAUSTED`.
+    #[allow(clippy::result_large_err)]
+    pub fn try_send_with_context(&self, event: Event, cx: &Context) -> Result<(), Status> {
+        match self.sender.try_send((event, cx.clone())) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full((event, _))) => {
+                let rejected = self.rejected.fetch_add(1, Ordering::Relaxed) + 1;
+                warn!(
+                    "event queue full, {:?} rejected ({} rejected so far)",
+                    event.kind(),
+                    rejected
+                );
+                Err(Status::resource_exhausted(
+                    "the scheduler is overloaded, retry later",
+                ))
+            }
+            Err(TrySendError::Closed(_)) => {
+                Err(Status::internal("could not send event to manager"))
+            }
+        }
+    }
+
+    /// Queues an event in the current trace context, waiting for room if the queue is full.
+    /// Used for the events which can't be dropped.
+    #[allow(clippy::result_large_err)]
+    pub async fn send(&self, event: Event) -> Result<(), Status> {
+        self.send_with_context(event, &Context::current()).await
+    }
+
+    /// Queues an event in the trace context `cx`, waiting for room if the queue is full.
+    #[allow(clippy::result_large_err)]
+    pub async fn send_with_context(&self, event: Event, cx: &Context) -> Result<(), Status> {
+        self.sender
+            .send((event, cx.clone()))
+            .await
+            .map_err(|_| Status::internal("could not send event to manager"))
+    }
+
+    /// Returns the depth of the queue and the number of events rejected.
+    pub fn metrics(&self) -> QueueMetrics {
+        QueueMetrics {
+            depth: self.capacity - self.sender.capacity(),
+            capacity: self.capacity,
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manager::Manager;
+
+    fn snapshot_event() -> Event {
+        let (tx, _) = Manager::create_oneshot_channel();
+        Event::ClusterSnapshot(tx)
+    }
+
+    #[tokio::test]
+    async fn test_overflow() {
+        let (queue, mut receiver) = EventQueue::channel(2);
+        queue.try_send(snapshot_event()).unwrap();
+        queue.send(snapshot_event()).await.unwrap();
+
+        let err = queue.try_send(snapshot_event()).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(
+            queue.metrics(),
+            QueueMetrics {
+                depth: 2,
+                capacity: 2,
+                rejected: 1,
+            }
+        );
+
+        receiver.recv().await.unwrap();
+        queue.try_send(snapshot_event()).unwrap();
+
+        drop(receiver);
+        let err = queue.try_send(snapshot_event()).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Internal);
+    }
+
+    #[tokio::test]
+    async fn test_trace_context() {
+        use opentelemetry::trace::{
+            SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+        };
+
+        let (queue, mut receiver) = EventQueue::channel(2);
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let cx = Context::new().with_remote_span_context(span_context.clone());
+        queue.try_send_with_context(snapshot_event(), &cx).unwrap();
+        queue.try_send(snapshot_event()).unwrap();
+
+        let (_, queued) = receiver.recv().await.unwrap();
+        assert_eq!(queued.span().span_context(), &span_context);
+        let (_, queued) = receiver.recv().await.unwrap();
+        assert!(!queued.has_active_span());
+    }
+}
@@ -0,0 +1,36 @@
+use log::{debug, info};
+use tokio::task::JoinHandle;
+
+use crate::{config::RebalanceConfig, queue::EventQueue, Event};
+
+/// It queues a rebalance event at each interval, so an instance is moved off the most loaded
+/// node while the loads of the nodes are too far apart. The event is skipped if the queue is
+/// full, the scheduler having more urgent events to handle.
+///
+/// Arguments:
+///
+/// * `config`: The settings of the rebalancing
+/// * `events`: The event queue
+///
+/// Returns:
+///
+/// A JoinHandle<()>
+pub fn schedule(config: &RebalanceConfig, events: EventQueue) -> JoinHandle<()> {
+    let mut interval = tokio::time::interval(config.interval());
+    let threshold = config.threshold;
+
+    tokio::spawn(async move {
+        info!(
+            "rebalancing the nodes every {:?} from a difference of load of {}",
+            interval.period(),
+            threshold
+        );
+
+        loop {
+            interval.tick().await;
+            if let Err(status) = events.try_send(Event::Rebalance(threshold)) {
+                debug!("rebalancing skipped: {}", status.message());
+            }
+        }
+    })
+}
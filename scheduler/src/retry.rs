@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use rand::Rng;
+use tonic::Code;
+
+use crate::config::RetryConfig;
+
+/// Returns `true` if a failed call may succeed when retried, e.g. when the node dropped its
+/// stream for a moment or didn't take the command in time.
+pub fn is_transient(status: &tonic::Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+}
+
+/// `RetryPolicy` computes the delay before each retry, growing exponentially with a full jitter.
+///
+/// Properties:
+///
+/// * `max_attempts`: The number of attempts, the first one included.
+/// * `initial_backoff`: The maximum delay before the first retry.
+/// * `max_backoff`: The cap of the delays.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns `true` if another attempt is allowed after `attempts` failed ones.
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    /// Returns the delay before the retry following `attempts` failed attempts, a random duration
+    /// up to `initial_backoff * 2^(attempts - 1)`, capped by `max_backoff`.
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(16);
+        let ceiling = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+impl From<&RetryConfig> for RetryPolicy {
+    fn from(config: &RetryConfig) -> Self {
+        RetryPolicy {
+            max_attempts: config.max_attempts,
+            initial_backoff: Duration::from_millis(config.initial_backoff),
+            max_backoff: Duration::from_millis(config.max_backoff),
+        }
+    }
+}
+
+/// `RetryBudget` stops the retries when most calls fail, so the nodes aren't flooded with retries
+/// while the cluster is unhealthy. Every failure takes a token and every success gives back a
+/// fraction of one, the retries are allowed while more than half of the tokens are left.
+///
+/// Properties:
+///
+/// * `tokens`: The tokens left.
+/// * `max_tokens`: The size of the budget.
+/// * `refill`: The fraction of a token given back by a success.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    tokens: f64,
+    max_tokens: f64,
+    refill: f64,
+}
+
+impl RetryBudget {
+    pub fn new(max_tokens: f64, refill: f64) -> Self {
+        RetryBudget {
+            tokens: max_tokens,
+            max_tokens,
+            refill,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.tokens = (self.tokens + self.refill).min(self.max_tokens);
+    }
+
+    pub fn record_failure(&mut self) {
+        self.tokens = (self.tokens - 1.0).max(0.0);
+    }
+
+    /// Returns `true` if the budget still allows retries.
+    pub fn can_retry(&self) -> bool {
+        self.tokens > self.max_tokens / 2.0
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        RetryBudget::new(10.0, 0.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = policy();
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(100));
+            assert!(policy.backoff(2) <= Duration::from_millis(200));
+            assert!(policy.backoff(10) <= Duration::from_millis(300));
+        }
+        assert!(policy.should_retry(2));
+        assert!(!policy.should_retry(3));
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut budget = RetryBudget::new(4.0, 0.5);
+        budget.record_failure();
+        assert!(budget.can_retry());
+        budget.record_failure();
+        assert!(!budget.can_retry());
+        budget.record_success();
+        assert!(budget.can_retry());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&tonic::Status::unavailable("")));
+        assert!(is_transient(&tonic::Status::deadline_exceeded("")));
+        assert!(!is_transient(&tonic::Status::not_found("")));
+    }
+}
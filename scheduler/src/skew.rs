@@ -0,0 +1,163 @@
+use crate::{
+    config::{AgentVersionConfig, SkewAction},
+    SchedulerError,
+};
+
+/// The verdict of the version policy on a registering node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Accept,
+    /// The node is registered cordoned, for the given reason
+    Quarantine(String),
+    /// The registration is refused, for the given reason
+    Reject(String),
+}
+
+/// A version of a component, `major.minor.patch`.
+type Version = (u64, u64, u64);
+
+/// Parses a component version, its pre-release and build suffixes are ignored, e.g. `0.3.1-rc.1`
+/// is `0.3.1`. A missing minor or patch number is 0.
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    let core = version.split(['-', '+']).next()?;
+    let mut numbers = core.split('.').map(|number| number.parse::<u64>());
+    let major = numbers.next()?.ok()?;
+    let minor = numbers.next().unwrap_or(Ok(0)).ok()?;
+    let patch = numbers.next().unwrap_or(Ok(0)).ok()?;
+    match numbers.next() {
+        Some(_) => None,
+        None => Some((major, minor, patch)),
+    }
+}
+
+/// `VersionPolicy` checks the version of the agent of each node registering against the range
+/// of versions supported by the scheduler. Every version is accepted if no range is configured.
+///
+/// Properties:
+///
+/// * `min`: The oldest version supported, no lower bound if `None`.
+/// * `max`: The newest version supported, no upper bound if `None`.
+/// * `action`: What happens to the nodes outside of the range.
+#[derive(Debug, Clone, Default)]
+pub struct VersionPolicy {
+    min: Option<(String, Version)>,
+    max: Option<(String, Version)>,
+    action: SkewAction,
+}
+
+impl TryFrom<&AgentVersionConfig> for VersionPolicy {
+    type Error = SchedulerError;
+
+    /// Creates the policy of the configuration, failing if one of its bounds isn't a version.
+    fn try_from(config: &AgentVersionConfig) -> Result<Self, Self::Error> {
+        let bound = |bound: &Option<String>| match bound {
+            Some(version) => parse_version(version)
+                .map(|parsed| Some((version.clone(), parsed)))
+                .ok_or(SchedulerError::InvalidAgentVersion),
+            None => Ok(None),
+        };
+        Ok(VersionPolicy {
+            min: bound(&config.min)?,
+            max: bound(&config.max)?,
+            action: config.action,
+        })
+    }
+}
+
+impl VersionPolicy {
+    /// Returns the verdict on the agent of a node, `version` being the version it reported.
+    pub fn check(&self, node_id: &str, version: Option<&str>) -> Verdict {
+        if self.min.is_none() && self.max.is_none() {
+            return Verdict::Accept;
+        }
+
+        let reason = match version.map(|version| (version, parse_version(version))) {
+            Some((version, Some(parsed))) => match (&self.min, &self.max) {
+                (Some((min, bound)), _) if parsed < *bound => format!(
+                    "the agent of node {} is {}, older than {} supported by the scheduler",
+                    node_id, version, min
+                ),
+                (_, Some((max, bound))) if parsed > *bound => format!(
+                    "the agent of node {} is {}, newer than {} supported by the scheduler",
+                    node_id, version, max
+                ),
+                _ => return Verdict::Accept,
+            },
+            Some((version, None)) => format!(
+                "the agent of node {} has an invalid version {}",
+                node_id, version
+            ),
+            None => format!("the agent of node {} didn't send its version", node_id),
+        };
+
+        match self.action {
+            SkewAction::Reject => Verdict::Reject(reason),
+            SkewAction::Quarantine => Verdict::Quarantine(reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version_policy(min: Option<&str>, max: Option<&str>, action: SkewAction) -> VersionPolicy {
+        VersionPolicy::try_from(&AgentVersionConfig {
+            min: min.map(str::to_string),
+            max: max.map(str::to_string),
+            action,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("0.3.1"), Some((0, 3, 1)));
+        assert_eq!(parse_version("v1.2"), Some((1, 2, 0)));
+        assert_eq!(parse_version("0.3.1-rc.1+abc"), Some((0, 3, 1)));
+        assert_eq!(parse_version("0.3.1.4"), None);
+        assert_eq!(parse_version("latest"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn test_invalid_bound() {
+        let config = AgentVersionConfig {
+            min: Some("latest".to_string()),
+            ..Default::default()
+        };
+        assert!(VersionPolicy::try_from(&config).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = version_policy(Some("0.2.0"), Some("0.4"), SkewAction::Reject);
+        assert_eq!(policy.check("a", Some("0.2.0")), Verdict::Accept);
+        assert_eq!(policy.check("a", Some("0.4.0-rc.1")), Verdict::Accept);
+        assert!(matches!(
+            policy.check("a", Some("0.1.9")),
+            Verdict::Reject(reason) if reason.contains("older than 0.2.0")
+        ));
+        assert!(matches!(
+            policy.check("a", Some("0.4.1")),
+            Verdict::Reject(reason) if reason.contains("newer than 0.4")
+        ));
+        assert!(matches!(policy.check("a", None), Verdict::Reject(_)));
+        assert!(matches!(policy.check("a", Some("dev")), Verdict::Reject(_)));
+
+        let policy = version_policy(Some("0.2.0"), None, SkewAction::Quarantine);
+        assert_eq!(policy.check("a", Some("1.0.0")), Verdict::Accept);
+        assert!(matches!(
+            policy.check("a", Some("0.1.0")),
+            Verdict::Quarantine(_)
+        ));
+    }
+
+    #[test]
+    fn test_no_range() {
+        let policy = VersionPolicy::default();
+        assert_eq!(policy.check("a", None), Verdict::Accept);
+        assert_eq!(policy.check("a", Some("dev")), Verdict::Accept);
+    }
+}
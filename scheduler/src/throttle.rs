@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use crate::{config::StatusConfig, queue::QueueMetrics};
+
+/// `StatusThrottle` computes the interval the nodes send their statuses at from the load of the
+/// scheduler. The interval is `interval` while less than a quarter of the event queue is used,
+/// doubled for each next quarter used, up to `max_interval`.
+///
+/// Properties:
+///
+/// * `interval`: The interval of the statuses when the scheduler isn't loaded.
+/// * `max_interval`: The longest interval asked to the nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusThrottle {
+    pub interval: Duration,
+    pub max_interval: Duration,
+}
+
+impl StatusThrottle {
+    /// Returns the interval the nodes send their statuses at for the load of the event queue.
+    pub fn interval(&self, metrics: &QueueMetrics) -> Duration {
+        let quarters = (metrics.depth * 4 / metrics.capacity.max(1)).min(3) as u32;
+        self.interval
+            .saturating_mul(1 << quarters)
+            .min(self.max_interval)
+            .max(self.interval)
+    }
+}
+
+impl From<&StatusConfig> for StatusThrottle {
+    fn from(config: &StatusConfig) -> Self {
+        StatusThrottle {
+            interval: Duration::from_millis(config.interval),
+            max_interval: Duration::from_millis(config.max_interval),
+        }
+    }
+}
+
+impl Default for StatusThrottle {
+    fn default() -> Self {
+        StatusThrottle::from(&StatusConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(depth: usize) -> QueueMetrics {
+        QueueMetrics {
+            depth,
+            capacity: 100,
+            rejected: 0,
+        }
+    }
+
+    #[test]
+    fn test_interval() {
+        let throttle = StatusThrottle {
+            interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(5),
+        };
+        assert_eq!(throttle.interval(&metrics(0)), Duration::from_secs(1));
+        assert_eq!(throttle.interval(&metrics(24)), Duration::from_secs(1));
+        assert_eq!(throttle.interval(&metrics(25)), Duration::from_secs(2));
+        assert_eq!(throttle.interval(&metrics(60)), Duration::from_secs(4));
+        assert_eq!(throttle.interval(&metrics(100)), Duration::from_secs(5));
+    }
+}
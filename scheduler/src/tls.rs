@@ -0,0 +1,152 @@
+use std::{
+    io,
+    sync::{Arc, RwLock},
+};
+
+use log::{debug, warn};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{
+    rustls::{
+        server::{AllowAnyAuthenticatedClient, ClientHello, ResolvesServerCert},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey, RootCertStore, ServerConfig,
+    },
+    server::TlsStream,
+    TlsAcceptor,
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::pki::PkiError;
+
+/// `ServerCredentials` is the certificate presented by a TLS server. It can be replaced while the
+/// server runs: the next connections get the new certificate and the open ones are kept.
+///
+/// Properties:
+///
+/// * `current`: The certificate chain and the key presented to the clients.
+#[derive(Clone)]
+pub struct ServerCredentials {
+    current: Arc<RwLock<Arc<CertifiedKey>>>,
+}
+
+impl ServerCredentials {
+    pub fn new(certificate: &str, key: &str) -> Result<Self, PkiError> {
+        Ok(ServerCredentials {
+            current: Arc::new(RwLock::new(Arc::new(certified_key(certificate, key)?))),
+        })
+    }
+
+    /// Replaces the certificate presented to the next connections.
+    pub fn replace(&self, certificate: &str, key: &str) -> Result<(), PkiError> {
+        let certified_key = certified_key(certificate, key)?;
+        *self.current.write().unwrap() = Arc::new(certified_key);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for ServerCredentials {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+/// Parses a PEM certificate chain and its PEM PKCS#8 key.
+fn certified_key(certificate: &str, key: &str) -> Result<CertifiedKey, PkiError> {
+    let certificates = rustls_pemfile::certs(&mut certificate.as_bytes())?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    let key = rustls_pemfile::pkcs8_private_keys(&mut key.as_bytes())?
+        .into_iter()
+        .next()
+        .ok_or(PkiError::InvalidKey)?;
+    let key = sign::any_supported_type(&PrivateKey(key)).map_err(|_| PkiError::InvalidKey)?;
+
+    Ok(CertifiedKey::new(certificates, key))
+}
+
+/// Creates the acceptor of a TLS server presenting `credentials`.
+///
+/// Arguments:
+///
+/// * `credentials`: The certificate of the server.
+/// * `client_ca`: The PEM certificate of the CA the clients must present a certificate of, the
+///   clients aren't authenticated if `None`.
+pub fn acceptor(
+    credentials: ServerCredentials,
+    client_ca: Option<&str>,
+) -> Result<TlsAcceptor, PkiError> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in rustls_pemfile::certs(&mut client_ca.as_bytes())? {
+                roots
+                    .add(&Certificate(certificate))
+                    .map_err(|_| PkiError::InvalidCertificate)?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder.with_cert_resolver(Arc::new(credentials));
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Accepts the TLS connections of `listener`, to be served by `Server::serve_with_incoming`. The
+/// handshakes run in their own task, so a slow client doesn't delay the others.
+pub fn incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+) -> ReceiverStream<io::Result<TlsStream<TcpStream>>> {
+    let (tx, rx) = mpsc::channel(32);
+
+    tokio::spawn(async move {
+        while !tx.is_closed() {
+            let (stream, address) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(err) => {
+                    warn!("unable to accept a connection: {}", err);
+                    continue;
+                }
+            };
+
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await {
+                    Ok(stream) => _ = tx.send(Ok(stream)).await,
+                    Err(err) => debug!("TLS handshake with {} failed: {}", address, err),
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pki::CertificateAuthority;
+
+    #[test]
+    fn test_replace_credentials() {
+        let ca = CertificateAuthority::generate(1).unwrap();
+        let (certificate, key) = ca.issue_server(&["localhost".to_string()], 1).unwrap();
+        let credentials = ServerCredentials::new(&certificate, &key).unwrap();
+        let before = credentials.current.read().unwrap().cert.clone();
+
+        let (certificate, key) = ca.issue_server(&["localhost".to_string()], 1).unwrap();
+        credentials.replace(&certificate, &key).unwrap();
+        assert_ne!(credentials.current.read().unwrap().cert, before);
+
+        assert!(credentials.replace("invalid", "invalid").is_err());
+        assert!(acceptor(credentials, Some(ca.pem())).is_ok());
+    }
+}
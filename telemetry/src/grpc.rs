@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+use opentelemetry::{global, Context};
+use tonic::metadata::{KeyRef, MetadataKey, MetadataMap, MetadataValue};
+use tonic::Request;
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl<'a> Injector for MetadataInjector<'a> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::try_from(value.as_str()),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl<'a> Extractor for MetadataExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .map(|key| match key {
+                KeyRef::Ascii(key) => key.as_str(),
+                KeyRef::Binary(key) => key.as_str(),
+            })
+            .collect()
+    }
+}
+
+/// Adds the trace context `cx` to the metadata of an outgoing gRPC request.
+pub fn inject_context<T>(cx: &Context, request: &mut Request<T>) {
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(cx, &mut MetadataInjector(request.metadata_mut()))
+    });
+}
+
+/// Returns the trace context sent by the caller of an incoming gRPC request, an empty context
+/// if the caller didn't send one.
+pub fn extract_context<T>(request: &Request<T>) -> Context {
+    global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetadataExtractor(request.metadata()))
+    })
+}
+
+/// Returns the fields of the trace context `cx`, for the messages of a stream which can't carry
+/// it in their metadata, e.g. the commands sent to the nodes on their lifecycle stream.
+pub fn inject_fields(cx: &Context) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(cx, &mut fields));
+    fields
+}
+
+/// Returns the trace context of the fields of a message, an empty context if there is none.
+pub fn extract_fields(fields: &HashMap<String, String>) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(fields))
+}
+
+/// Starts the span of a gRPC procedure as a child of the span of its caller. The span ends when
+/// the returned context is dropped.
+///
+/// Arguments:
+///
+/// * `request`: The incoming request.
+/// * `name`: The name of the procedure, e.g. `InstanceService/Create`.
+pub fn server_context<T>(request: &Request<T>, name: &str) -> Context {
+    let parent = extract_context(request);
+    let tracer = crate::tracer();
+    let span = tracer
+        .span_builder(name.to_string())
+        .with_kind(SpanKind::Server)
+        .start_with_context(&tracer, &parent);
+
+    parent.with_span(span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::sdk::propagation::TraceContextPropagator;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn test_context_round_trip() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let cx = Context::new().with_remote_span_context(span_context.clone());
+
+        let mut request = Request::new(());
+        inject_context(&cx, &mut request);
+
+        assert_eq!(
+            request.metadata().get("traceparent").unwrap(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+
+        let extracted = extract_context(&request);
+        assert_eq!(
+            extracted.span().span_context().trace_id(),
+            span_context.trace_id()
+        );
+        assert_eq!(
+            extracted.span().span_context().span_id(),
+            span_context.span_id()
+        );
+    }
+
+    #[test]
+    fn test_fields_round_trip() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let span_context = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let cx = Context::new().with_remote_span_context(span_context.clone());
+
+        let fields = inject_fields(&cx);
+        assert_eq!(
+            fields.get("traceparent").unwrap(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+
+        let extracted = extract_fields(&fields);
+        assert_eq!(
+            extracted.span().span_context().span_id(),
+            span_context.span_id()
+        );
+        assert!(inject_fields(&Context::new()).is_empty());
+    }
+}
@@ -0,0 +1,54 @@
+use log::info;
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::trace::TraceError;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+
+pub mod grpc;
+
+/// Name of the tracer used by every component of kudo.
+pub const TRACER_NAME: &str = "kudo";
+
+/// `init` sets up the propagation of the W3C trace context and, when an OTLP endpoint is
+/// configured, the export of the spans of the component to this endpoint. Without endpoint, the
+/// trace context is still forwarded to the other components but no span is recorded.
+///
+/// Arguments:
+///
+/// * `service_name`: The name of the component, as it will appear in the traces.
+/// * `otlp_endpoint`: The gRPC endpoint of the OTLP collector (e.g. `http://127.0.0.1:4317`).
+pub fn init(service_name: &str, otlp_endpoint: Option<&str>) -> Result<(), TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    if let Some(endpoint) = otlp_endpoint {
+        info!("exporting the spans of {} to {}", service_name, endpoint);
+
+        opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(
+                trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    service_name.to_string(),
+                )])),
+            )
+            .install_batch(opentelemetry::runtime::Tokio)?;
+    }
+
+    Ok(())
+}
+
+/// `shutdown` flushes the spans not exported yet, it has to be called before exiting.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Returns the tracer of the component.
+pub fn tracer() -> global::BoxedTracer {
+    global::tracer(TRACER_NAME)
+}